@@ -0,0 +1,179 @@
+//! Per-dataset access control lists.
+//!
+//! Every authenticated key may query a dataset, and anyone may read its shards, until its owner
+//! or an admin grants access to a specific API key (by `Caller::key_id`) or role. From then on the
+//! dataset is restricted: only the owner, admins and the grantees may query it, list or export its
+//! shards, read its per-shard aggregates, summary, quality report, anomalies, manifest or progress
+//! events, or fetch its aggregate proof. A role grant admits every
+//! key whose role satisfies it. Revoking every grant leaves the dataset to its owner and admins.
+
+use crate::auth::{Caller, Role};
+use crate::db::{AclEntryRow, DatasetAclRow};
+use crate::errors::ApiError;
+use crate::models::{DatasetAccessGrant, DatasetAclEntry, DatasetAclResponse};
+use crate::retention;
+use crate::share::ShareClaims;
+use crate::state::AppState;
+use uuid::Uuid;
+
+const KIND_KEY: &str = "key";
+const KIND_ROLE: &str = "role";
+
+/// The `(grantee_kind, grantee)` a grant or revoke names.
+fn grantee(req: &DatasetAccessGrant) -> Result<(&'static str, String), ApiError> {
+    match (&req.key_id, req.role) {
+        (Some(key_id), None) => {
+            let key_id = key_id.trim().to_ascii_lowercase();
+            if key_id.len() != 16 || !key_id.bytes().all(|b| b.is_ascii_hexdigit()) {
+                return Err(ApiError::BadRequest("key_id must be an API key fingerprint (16 hex characters)".to_string()));
+            }
+            Ok((KIND_KEY, key_id))
+        }
+        (None, Some(role)) => Ok((KIND_ROLE, role.name().to_string())),
+        _ => Err(ApiError::BadRequest("exactly one of key_id or role is required".to_string())),
+    }
+}
+
+fn entry_grant(entry: &AclEntryRow) -> DatasetAccessGrant {
+    match entry.grantee_kind.as_str() {
+        KIND_ROLE => DatasetAccessGrant {
+            key_id: None,
+            role: Role::parse(&entry.grantee),
+        },
+        _ => DatasetAccessGrant {
+            key_id: Some(entry.grantee.clone()),
+            role: None,
+        },
+    }
+}
+
+fn admits(acl: &DatasetAclRow, owner: Option<&str>, caller: &Caller) -> bool {
+    if !acl.restricted || caller.role == Role::Admin || owner == Some(caller.key_id.as_str()) {
+        return true;
+    }
+    acl.entries.iter().any(|entry| {
+        let grant = entry_grant(entry);
+        grant.key_id.as_deref() == Some(caller.key_id.as_str()) || grant.role.is_some_and(|role| caller.role.satisfies(role))
+    })
+}
+
+/// Reject (403) `caller` if `dataset_id` is restricted and they are not on its access list.
+///
+/// `caller` is `None` on public routes called without an API key.
+pub async fn check_access(state: &AppState, caller: Option<&Caller>, dataset_id: Uuid) -> Result<(), ApiError> {
+    let acl = state.store.dataset_acl(dataset_id).await?;
+    if !acl.restricted {
+        return Ok(());
+    }
+    let Some(caller) = caller else {
+        return Err(ApiError::Forbidden("dataset access is restricted; send an X-API-KEY on its access list".to_string()));
+    };
+    let owner = state.store.dataset_owner(dataset_id).await?;
+    if admits(&acl, owner.as_deref(), caller) {
+        Ok(())
+    } else {
+        Err(ApiError::Forbidden("this API key is not on the dataset's access list".to_string()))
+    }
+}
+
+/// Like `check_access`, but a share link for the dataset (see `share`) also grants read access.
+pub async fn check_read_access(
+    state: &AppState,
+    caller: Option<&Caller>,
+    share: Option<&ShareClaims>,
+    dataset_id: Uuid,
+) -> Result<(), ApiError> {
+    if caller.is_none() && share.is_some_and(|claims| claims.dataset_id == dataset_id) {
+        return Ok(());
+    }
+    check_access(state, caller, dataset_id).await
+}
+
+/// The dataset's owner, once `caller` is shown to be it or an admin.
+pub async fn managed_dataset(state: &AppState, caller: &Caller, dataset_id: Uuid) -> Result<Option<String>, ApiError> {
+    if state.store.get_dataset(dataset_id).await?.is_none() {
+        return Err(retention::missing_dataset(state, dataset_id).await);
+    }
+    let owner = state.store.dataset_owner(dataset_id).await?;
+    if caller.role != Role::Admin && owner.as_deref() != Some(caller.key_id.as_str()) {
+        return Err(ApiError::Forbidden("only the dataset's owner or an admin can manage access to it".to_string()));
+    }
+    Ok(owner)
+}
+
+async fn response(
+    state: &AppState,
+    dataset_id: Uuid,
+    owner_key_id: Option<String>,
+    audit_entry_hash: Option<String>,
+) -> Result<DatasetAclResponse, ApiError> {
+    let acl = state.store.dataset_acl(dataset_id).await?;
+    Ok(DatasetAclResponse {
+        dataset_id,
+        owner_key_id,
+        restricted: acl.restricted,
+        entries: acl
+            .entries
+            .iter()
+            .map(|entry| DatasetAclEntry {
+                grantee: entry_grant(entry),
+                granted_by: entry.granted_by.clone(),
+                granted_at: entry.granted_at,
+            })
+            .collect(),
+        audit_entry_hash,
+    })
+}
+
+pub async fn get(state: &AppState, caller: &Caller, dataset_id: Uuid) -> Result<DatasetAclResponse, ApiError> {
+    let owner = managed_dataset(state, caller, dataset_id).await?;
+    response(state, dataset_id, owner, None).await
+}
+
+/// Add a grant, restricting the dataset if it was open. Granting an existing entry again is a no-op.
+pub async fn grant(
+    state: &AppState,
+    caller: &Caller,
+    dataset_id: Uuid,
+    req: &DatasetAccessGrant,
+) -> Result<DatasetAclResponse, ApiError> {
+    let owner = managed_dataset(state, caller, dataset_id).await?;
+    let (kind, grantee) = grantee(req)?;
+
+    let added = state.store.grant_dataset_access(dataset_id, kind, &grantee, &caller.key_id).await?;
+    let audit_entry_hash = if added {
+        Some(
+            state.store.append_audit(
+                Some(dataset_id),
+                "dataset_access_granted",
+                &serde_json::json!({ "grantee_kind": kind, "grantee": grantee, "granted_by": caller.key_id }),
+            )
+            .await?,
+        )
+    } else {
+        None
+    };
+    response(state, dataset_id, owner, audit_entry_hash).await
+}
+
+/// Remove a grant (404 if there is none). The dataset stays restricted.
+pub async fn revoke(
+    state: &AppState,
+    caller: &Caller,
+    dataset_id: Uuid,
+    req: &DatasetAccessGrant,
+) -> Result<DatasetAclResponse, ApiError> {
+    let owner = managed_dataset(state, caller, dataset_id).await?;
+    let (kind, grantee) = grantee(req)?;
+
+    if !state.store.revoke_dataset_access(dataset_id, kind, &grantee).await? {
+        return Err(ApiError::NotFound("no such grant on the dataset's access list".to_string()));
+    }
+    let audit_entry_hash = state.store.append_audit(
+        Some(dataset_id),
+        "dataset_access_revoked",
+        &serde_json::json!({ "grantee_kind": kind, "grantee": grantee, "revoked_by": caller.key_id }),
+    )
+    .await?;
+    response(state, dataset_id, owner, Some(audit_entry_hash)).await
+}
@@ -0,0 +1,129 @@
+//! Memory-aware admission control for proving.
+//!
+//! Every shard proof reserves an estimate of its peak memory before it starts. The estimate comes
+//! from the circuit's size (`registry::circuit_metrics`): `PROVING_BYTES_PER_DOMAIN_ELEMENT`
+//! (default 1 KiB) per evaluation-domain point plus 128 bytes per variable, which covers
+//! constraint synthesis, the QAP vectors and MSM scalars. A proof is admitted if nothing else is
+//! proving, or if the host's available memory (`MemAvailable`, capped by the cgroup limit) covers
+//! the estimates of all in-flight proofs plus this one plus `PROVING_MEMORY_RESERVE_MB` (default
+//! 512). Counting in-flight estimates against memory they may already use is deliberately
+//! conservative: throttling is cheap, an OOM kill is not.
+
+use serde::Serialize;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::Notify;
+use zk_proofs::registry::CircuitMetrics;
+
+const DEFAULT_BYTES_PER_DOMAIN_ELEMENT: u64 = 1024;
+const BYTES_PER_VARIABLE: u64 = 128;
+const DEFAULT_RESERVE_MB: u64 = 512;
+
+/// How often a waiting proof re-reads available memory (other processes may free some).
+const RECHECK: Duration = Duration::from_secs(1);
+
+fn env_u64(name: &str) -> Option<u64> {
+    std::env::var(name).ok().and_then(|v| v.parse().ok())
+}
+
+/// Estimated peak memory of one proof for a circuit of this size.
+pub fn estimate_proof_bytes(metrics: CircuitMetrics) -> u64 {
+    let per_domain = env_u64("PROVING_BYTES_PER_DOMAIN_ELEMENT").unwrap_or(DEFAULT_BYTES_PER_DOMAIN_ELEMENT);
+    metrics.domain_size as u64 * per_domain + metrics.num_variables as u64 * BYTES_PER_VARIABLE
+}
+
+fn reserve_bytes() -> u64 {
+    env_u64("PROVING_MEMORY_RESERVE_MB").unwrap_or(DEFAULT_RESERVE_MB) * 1024 * 1024
+}
+
+fn read_u64(path: &str) -> Option<u64> {
+    std::fs::read_to_string(path).ok()?.trim().parse().ok()
+}
+
+/// Memory available to this process, or `None` where it can't be determined (non-Linux).
+pub fn available_memory() -> Option<u64> {
+    let meminfo = std::fs::read_to_string("/proc/meminfo").ok()?;
+    let host = meminfo
+        .lines()
+        .find_map(|l| l.strip_prefix("MemAvailable:"))
+        .and_then(|v| v.trim().trim_end_matches("kB").trim().parse::<u64>().ok())
+        .map(|kb| kb * 1024)?;
+
+    // cgroup v2 limit ("max" when unlimited, which fails to parse).
+    let cgroup = read_u64("/sys/fs/cgroup/memory.max")
+        .zip(read_u64("/sys/fs/cgroup/memory.current"))
+        .map(|(max, current)| max.saturating_sub(current));
+
+    Some(cgroup.map_or(host, |c| c.min(host)))
+}
+
+#[derive(Debug, Default, Clone, Copy, Serialize)]
+pub struct AdmissionSnapshot {
+    pub in_flight: u64,
+    pub reserved_bytes: u64,
+    pub waiting: u64,
+}
+
+#[derive(Default)]
+pub struct ProvingAdmission {
+    state: Mutex<AdmissionSnapshot>,
+    released: Notify,
+}
+
+/// Held for the duration of one proof; releases its reservation on drop.
+pub struct AdmissionPermit {
+    admission: Arc<ProvingAdmission>,
+    bytes: u64,
+}
+
+impl Drop for AdmissionPermit {
+    fn drop(&mut self) {
+        if let Ok(mut s) = self.admission.state.lock() {
+            s.in_flight -= 1;
+            s.reserved_bytes -= self.bytes;
+        }
+        self.admission.released.notify_waiters();
+    }
+}
+
+impl ProvingAdmission {
+    pub fn snapshot(&self) -> AdmissionSnapshot {
+        self.state.lock().map(|s| *s).unwrap_or_default()
+    }
+
+    fn try_admit(&self, bytes: u64) -> bool {
+        let Ok(mut s) = self.state.lock() else { return true };
+        let fits = s.in_flight == 0
+            || available_memory().is_none_or(|avail| avail >= s.reserved_bytes + bytes + reserve_bytes());
+        if fits {
+            s.in_flight += 1;
+            s.reserved_bytes += bytes;
+        }
+        fits
+    }
+
+    fn set_waiting(&self, delta: i64) {
+        if let Ok(mut s) = self.state.lock() {
+            s.waiting = s.waiting.saturating_add_signed(delta);
+        }
+    }
+
+    /// Wait until a proof needing `bytes` fits in memory.
+    pub async fn acquire(self: &Arc<Self>, bytes: u64) -> AdmissionPermit {
+        if !self.try_admit(bytes) {
+            tracing::info!(bytes, "proving throttled: waiting for memory");
+            self.set_waiting(1);
+            loop {
+                let _ = tokio::time::timeout(RECHECK, self.released.notified()).await;
+                if self.try_admit(bytes) {
+                    break;
+                }
+            }
+            self.set_waiting(-1);
+        }
+        AdmissionPermit {
+            admission: self.clone(),
+            bytes,
+        }
+    }
+}
@@ -0,0 +1,180 @@
+//! Dataset aggregate proofs (`GET /api/v1/datasets/:id/aggregate-proof`).
+//!
+//! One Groth16 proof (`zk_proofs::aggregate`) that a dataset's commitment chains exactly its shard
+//! commitments and that the dataset totals are the sums of the shards' proven aggregates, bound to
+//! a Merkle root over every shard's public inputs. A background job (`jobs::KIND_PROVE_AGGREGATE`)
+//! batch-verifies the stored shard proofs, proves the aggregate and stores it per dataset; a proof
+//! made for an older dataset commitment is stale and gets replaced. The circuit recomputes the
+//! commitment chain, so only `poseidon`-chained datasets qualify.
+
+use crate::chain::ChainHash;
+use crate::dataset::{field_hex, parse_field_hex};
+use crate::db;
+use crate::errors::ApiError;
+use crate::export;
+use crate::key_usage;
+use crate::models::AggregateShardPath;
+use crate::state::AppState;
+use base64::Engine;
+use chrono::Utc;
+use rand::rngs::OsRng;
+use uuid::Uuid;
+use zk_proofs::aggregate::{prove_aggregate, shard_inputs_leaf, shard_inputs_path, verify_aggregate_proof, AggregateShape};
+use zk_proofs::groth16::{
+    deserialize_proof, deserialize_vk, invalid_shard_proofs, serialize_proof, serialize_vk, shard_public_inputs_to_field_elems,
+    verify_shard_proofs_batch, ShardProofInstance,
+};
+use zk_proofs::types::ShardStats;
+
+use ark_bn254::Fr;
+
+/// Reject datasets the aggregate circuit can't cover.
+pub fn check_eligible(dataset: &db::DatasetRow) -> Result<(), ApiError> {
+    if dataset.status != "ready" || dataset.commitment_hex.is_none() {
+        return Err(ApiError::Conflict("dataset is not ready".to_string()));
+    }
+    if dataset.chain_hash != ChainHash::Poseidon {
+        return Err(ApiError::BadRequest(format!(
+            "aggregate proofs need a poseidon-chained dataset (this one uses {})",
+            dataset.chain_hash.name()
+        )));
+    }
+    Ok(())
+}
+
+/// The stored aggregate proof of a dataset, unless it is stale.
+pub async fn current_proof(state: &AppState, dataset_id: Uuid, dataset: &db::DatasetRow) -> Result<Option<db::AggregateProofRow>, ApiError> {
+    Ok(state.store.get_aggregate_proof(dataset_id)
+        .await?
+        .filter(|row| dataset.commitment_hex.as_ref() == Some(&row.dataset_commitment_hex)))
+}
+
+/// Every shard's commitment and stats (and proof, with `include_proof`), in shard order.
+pub async fn load_shards(
+    state: &AppState,
+    dataset_id: Uuid,
+    dataset: &db::DatasetRow,
+    include_proof: bool,
+) -> Result<Vec<(Fr, ShardStats, Option<String>)>, ApiError> {
+    let shards_total = dataset.shards_total();
+    let rows = state.store.list_shards(dataset_id, 0..shards_total, 0, shards_total, include_proof).await?;
+    if rows.len() as u64 != shards_total {
+        return Err(ApiError::Conflict(format!("{} of {shards_total} shards are stored", rows.len())));
+    }
+    rows.into_iter()
+        .map(|(_, commitment_hex, stats, _, proof_b64)| {
+            let commitment = parse_field_hex(&commitment_hex).ok_or(ApiError::Internal)?;
+            Ok((commitment, stats, proof_b64))
+        })
+        .collect()
+}
+
+/// Merkle path of shard `shard_index` in the dataset's aggregate proof.
+pub async fn shard_path(state: &AppState, dataset_id: Uuid, dataset: &db::DatasetRow, shard_index: u64) -> Result<AggregateShardPath, ApiError> {
+    if shard_index >= dataset.shards_total() {
+        return Err(ApiError::BadRequest(format!("shard_index must be below {}", dataset.shards_total())));
+    }
+    let shards = load_shards(state, dataset_id, dataset, false).await?;
+
+    let (leaf, siblings) = tokio::task::spawn_blocking(move || {
+        let leaves: Vec<Fr> = shards
+            .iter()
+            .map(|(commitment, stats, _)| shard_inputs_leaf(&shard_public_inputs_to_field_elems(*commitment, stats)))
+            .collect();
+        let siblings = shard_inputs_path(&leaves, shard_index as usize).unwrap_or_default();
+        (leaves[shard_index as usize], siblings)
+    })
+    .await
+    .map_err(|_| ApiError::Internal)?;
+
+    Ok(AggregateShardPath {
+        shard_index,
+        leaf_hex: field_hex(leaf)?,
+        siblings_hex: siblings.into_iter().map(field_hex).collect::<Result<_, _>>()?,
+    })
+}
+
+/// Job body for `jobs::KIND_PROVE_AGGREGATE`: re-verify the dataset's shard proofs, prove their
+/// aggregate and store it.
+pub async fn run_prove_job(state: &AppState, dataset_id: Uuid) -> Result<(), ApiError> {
+    let Some(dataset) = state.store.get_dataset(dataset_id).await? else {
+        return Err(ApiError::NotFound("dataset not found".to_string()));
+    };
+    check_eligible(&dataset)?;
+    if current_proof(state, dataset_id, &dataset).await?.is_some() {
+        // Another job got there first.
+        return Ok(());
+    }
+    let commitment_hex = dataset.commitment_hex.clone().ok_or(ApiError::Internal)?;
+
+    let vk_b64 = export::dataset_vk_b64(
+        state,
+        dataset_id,
+        dataset.shard_size,
+        dataset.field_set,
+        &dataset.age_buckets,
+        dataset.sha256_commitment,
+    )
+    .await?;
+    let vk_bytes = base64::engine::general_purpose::STANDARD
+        .decode(vk_b64)
+        .map_err(|_| ApiError::Internal)?;
+    let shard_vk = deserialize_vk(&vk_bytes).map_err(|_| ApiError::Internal)?;
+
+    let shards = load_shards(state, dataset_id, &dataset, true)
+        .await?
+        .into_iter()
+        .map(|(commitment, stats, proof_b64)| {
+            let proof_bytes = base64::engine::general_purpose::STANDARD
+                .decode(proof_b64.unwrap_or_default())
+                .map_err(|_| ApiError::Internal)?;
+            let proof = deserialize_proof(&proof_bytes).map_err(|_| ApiError::Internal)?;
+            Ok(ShardProofInstance { proof, commitment, stats })
+        })
+        .collect::<Result<Vec<_>, ApiError>>()?;
+    let Some(first) = shards.first() else {
+        return Err(ApiError::Conflict("dataset has no shards".to_string()));
+    };
+
+    let shape = AggregateShape::of(shards.len(), &first.stats);
+    let keys = state.ensure_aggregate_keys(shape).await?;
+    let _permit = state.proving_admission.acquire(keys.proof_bytes).await;
+
+    let pk = keys.pk.clone();
+    let vk = keys.vk.clone();
+    let (proof, aggregate) = tokio::task::spawn_blocking(move || {
+        // The aggregate only covers the shards' public inputs, so their proofs must hold.
+        if verify_shard_proofs_batch(&shard_vk, &shards).is_err() {
+            let invalid = invalid_shard_proofs(&shard_vk, &shards);
+            return Err(ApiError::Conflict(format!("shard proofs {invalid:?} don't verify")));
+        }
+        let inputs: Vec<(Fr, ShardStats)> = shards.into_iter().map(|s| (s.commitment, s.stats)).collect();
+        let (proof, aggregate) =
+            prove_aggregate(&mut OsRng, pk.as_ref(), &inputs).map_err(|e| ApiError::BadRequest(format!("{e}")))?;
+        verify_aggregate_proof(vk.as_ref(), &proof, &aggregate).map_err(|_| ApiError::Internal)?;
+        Ok((proof, aggregate))
+    })
+    .await
+    .map_err(|_| ApiError::Internal)??;
+
+    if field_hex(aggregate.dataset_commitment)? != commitment_hex {
+        return Err(ApiError::Conflict("stored shards don't chain to the dataset commitment".to_string()));
+    }
+
+    let b64 = |bytes: Vec<u8>| base64::engine::general_purpose::STANDARD.encode(bytes);
+    let row = db::AggregateProofRow {
+        created_at: Utc::now(),
+        dataset_commitment_hex: commitment_hex,
+        shard_inputs_root_hex: field_hex(aggregate.shard_inputs_root)?,
+        shards_total: shape.num_shards as u64,
+        totals: aggregate.totals,
+        proof_b64: b64(serialize_proof(&proof).map_err(|_| ApiError::Internal)?),
+        vk_b64: b64(serialize_vk(keys.vk.as_ref()).map_err(|_| ApiError::Internal)?),
+        key_id: keys.key_id,
+    };
+    state.store.put_aggregate_proof(dataset_id, &row).await?;
+    key_usage::record_proof(state, &row.key_id, key_usage::CIRCUIT_AGGREGATE, dataset_id).await?;
+
+    tracing::info!(%dataset_id, shards = shape.num_shards, "aggregate proof stored");
+    Ok(())
+}
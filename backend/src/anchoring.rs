@@ -0,0 +1,206 @@
+//! External anchoring of the transparency log.
+//!
+//! A signed tree head only shows what this instance claims; anchoring publishes the log's root
+//! somewhere it can't rewrite, so an auditor can show that every commitment in the tree existed
+//! by the anchor's time. Every `ANCHOR_INTERVAL_SECS` (default 3600), if the log grew since the
+//! last anchor, the current root hash is published with `ANCHOR_METHOD`:
+//! - `opentimestamps`: the 32-byte root is submitted to the calendar at `ANCHOR_URL` (default
+//!   `https://a.pool.opentimestamps.org`); its pending timestamp is kept as the receipt, to be
+//!   upgraded with the usual OpenTimestamps tooling once the calendar's Bitcoin transaction
+//!   confirms;
+//! - `ethereum`: an `eth_sendTransaction` to the JSON-RPC node at `ANCHOR_URL`, from the node's
+//!   unlocked account `ANCHOR_ETH_FROM` to itself with the root as calldata; the txid is kept;
+//! - `webhook`: the signed tree head is POSTed as JSON to `ANCHOR_URL`; a `txid` in the reply is
+//!   kept, and the whole reply as the receipt.
+//!
+//! Unset `ANCHOR_METHOD` disables anchoring. Anchors are kept in the store (`log_anchors`) and recorded
+//! in the audit chain (`log_anchored`). A dataset's `anchor` is the first one whose tree contains
+//! the log leaf of its current commitment; `GET /api/v1/log/proof?leaf_index=&tree_size=` proves
+//! that leaf into the anchored root.
+
+use crate::db::LogAnchorRow;
+use crate::errors::ApiError;
+use crate::models::{DatasetAnchor, SignedTreeHead};
+use crate::state::AppState;
+use crate::transparency;
+use chrono::Utc;
+use std::time::Duration;
+use uuid::Uuid;
+
+const DEFAULT_INTERVAL_SECS: u64 = 3600;
+const DEFAULT_OTS_CALENDAR: &str = "https://a.pool.opentimestamps.org";
+const PUBLISH_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Method {
+    OpenTimestamps,
+    Ethereum,
+    Webhook,
+}
+
+impl Method {
+    fn name(self) -> &'static str {
+        match self {
+            Method::OpenTimestamps => "opentimestamps",
+            Method::Ethereum => "ethereum",
+            Method::Webhook => "webhook",
+        }
+    }
+}
+
+fn method() -> Option<Method> {
+    match std::env::var("ANCHOR_METHOD").ok()?.trim() {
+        "opentimestamps" => Some(Method::OpenTimestamps),
+        "ethereum" => Some(Method::Ethereum),
+        "webhook" => Some(Method::Webhook),
+        other => {
+            tracing::warn!(method = other, "unknown ANCHOR_METHOD; anchoring disabled");
+            None
+        }
+    }
+}
+
+fn anchor_interval() -> Duration {
+    let secs = std::env::var("ANCHOR_INTERVAL_SECS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .filter(|secs| *secs > 0)
+        .unwrap_or(DEFAULT_INTERVAL_SECS);
+    Duration::from_secs(secs)
+}
+
+/// What a publication left to keep: a transaction id and/or a receipt.
+struct Published {
+    txid: Option<String>,
+    receipt: Option<Vec<u8>>,
+}
+
+fn publish_error(method: Method, e: impl std::fmt::Display) -> ApiError {
+    ApiError::Upstream(format!("{} anchor failed: {e}", method.name()))
+}
+
+async fn publish(client: &reqwest::Client, method: Method, head: &SignedTreeHead) -> Result<Published, ApiError> {
+    let url = std::env::var("ANCHOR_URL").ok();
+    let root = hex::decode(&head.root_hash_hex).map_err(|_| ApiError::Internal)?;
+    match method {
+        Method::OpenTimestamps => {
+            let calendar = url.as_deref().unwrap_or(DEFAULT_OTS_CALENDAR).trim_end_matches('/');
+            let res = client
+                .post(format!("{calendar}/digest"))
+                .header("Accept", "application/vnd.opentimestamps.v1")
+                .body(root)
+                .send()
+                .await
+                .and_then(|r| r.error_for_status())
+                .map_err(|e| publish_error(method, e))?;
+            let receipt = res.bytes().await.map_err(|e| publish_error(method, e))?;
+            Ok(Published { txid: None, receipt: Some(receipt.to_vec()) })
+        }
+        Method::Ethereum => {
+            let url = url.ok_or_else(|| publish_error(method, "ANCHOR_URL is not set"))?;
+            let from = std::env::var("ANCHOR_ETH_FROM").map_err(|_| publish_error(method, "ANCHOR_ETH_FROM is not set"))?;
+            let body = serde_json::json!({
+                "jsonrpc": "2.0",
+                "id": 1,
+                "method": "eth_sendTransaction",
+                "params": [{ "from": from, "to": from, "data": format!("0x{}", head.root_hash_hex) }],
+            });
+            let reply: serde_json::Value = client
+                .post(url)
+                .json(&body)
+                .send()
+                .await
+                .and_then(|r| r.error_for_status())
+                .map_err(|e| publish_error(method, e))?
+                .json()
+                .await
+                .map_err(|e| publish_error(method, e))?;
+            let txid = reply["result"]
+                .as_str()
+                .ok_or_else(|| publish_error(method, format!("no transaction id in {}", reply["error"])))?;
+            Ok(Published { txid: Some(txid.to_string()), receipt: None })
+        }
+        Method::Webhook => {
+            let url = url.ok_or_else(|| publish_error(method, "ANCHOR_URL is not set"))?;
+            let res = client
+                .post(url)
+                .json(head)
+                .send()
+                .await
+                .and_then(|r| r.error_for_status())
+                .map_err(|e| publish_error(method, e))?;
+            let receipt = res.bytes().await.map_err(|e| publish_error(method, e))?;
+            let txid = serde_json::from_slice::<serde_json::Value>(&receipt)
+                .ok()
+                .and_then(|reply| reply["txid"].as_str().map(str::to_string));
+            Ok(Published { txid, receipt: Some(receipt.to_vec()) })
+        }
+    }
+}
+
+/// Anchor the current tree head if the log grew since the last anchor. Returns the anchored size.
+async fn anchor_once(state: &AppState, client: &reqwest::Client, method: Method) -> Result<Option<u64>, ApiError> {
+    let head = transparency::tree_head(state).await?;
+    if head.tree_size <= state.store.anchored_tree_size().await? {
+        return Ok(None);
+    }
+
+    let published = publish(client, method, &head).await?;
+    let row = LogAnchorRow {
+        anchored_at: Utc::now(),
+        method: method.name().to_string(),
+        tree_size: head.tree_size,
+        root_hash_hex: head.root_hash_hex,
+        txid: published.txid,
+        receipt_hex: published.receipt.map(hex::encode),
+    };
+    state.store.insert_log_anchor(&row).await?;
+    state
+        .store
+        .append_audit(
+            None,
+            "log_anchored",
+            &serde_json::json!({
+                "method": row.method,
+                "tree_size": row.tree_size,
+                "root_hash_hex": row.root_hash_hex,
+                "txid": row.txid,
+            }),
+        )
+        .await?;
+    Ok(Some(row.tree_size))
+}
+
+pub async fn run(state: AppState) {
+    let Some(method) = method() else {
+        return;
+    };
+    let Ok(client) = reqwest::Client::builder().timeout(PUBLISH_TIMEOUT).build() else {
+        return;
+    };
+    let mut interval = tokio::time::interval(anchor_interval());
+    loop {
+        interval.tick().await;
+        match anchor_once(&state, &client, method).await {
+            Ok(Some(tree_size)) => tracing::info!(tree_size, method = method.name(), "anchored transparency log"),
+            Ok(None) => {}
+            Err(e) => tracing::warn!(error = %e, "anchoring failed"),
+        }
+    }
+}
+
+/// The first anchor covering `dataset_id`'s commitment `commitment_hex`, if it was anchored yet.
+pub async fn dataset_anchor(state: &AppState, dataset_id: Uuid, commitment_hex: &str) -> Result<Option<DatasetAnchor>, ApiError> {
+    let Some(leaf_index) = state.store.find_dataset_log_leaf(dataset_id, commitment_hex).await? else {
+        return Ok(None);
+    };
+    Ok(state.store.anchor_covering(leaf_index).await?.map(|anchor| DatasetAnchor {
+        method: anchor.method,
+        anchored_at: anchor.anchored_at,
+        tree_size: anchor.tree_size,
+        root_hash_hex: anchor.root_hash_hex,
+        leaf_index,
+        txid: anchor.txid,
+        receipt_hex: anchor.receipt_hex,
+    }))
+}
@@ -0,0 +1,254 @@
+//! Anomaly detection on verified shard aggregates.
+//!
+//! A valid proof shows a shard's aggregates were computed correctly over its committed records,
+//! not that the records are plausible. The analyzer reads the proven stats of every verified
+//! shard and flags:
+//! - a bucket whose mean of some measurement lies outside `quality::plausible_range`;
+//! - an empty bucket where the dataset-wide share of that bucket predicts at least
+//!   `MIN_EXPECTED_COUNT` records;
+//! - a bucket of at least `MIN_VARIANCE_SAMPLE` records whose glucose values are all equal (from
+//!   the proven sum of squares);
+//! - bucket counts that don't add up to the shard size.
+//!
+//! These catch generator bugs and federated submissions that are validly proven but made up.
+//! Warnings are advisory; queries are unaffected. With `MIN_CELL_COUNT` set, warnings on a
+//! bucket `policy::suppressed_cells` withholds leave out its count and observed value.
+//!
+//! The latest analysis of each dataset is kept in the store (`dataset_anomalies`) and redone when the
+//! dataset commitment or its number of stored shards changes. A background pass runs every
+//! `ANOMALY_SCAN_INTERVAL_SECS` (default 600, `0` disables), and `GET /api/v1/datasets/:id/anomalies`
+//! analyzes a stale dataset on demand. Analyses with warnings are recorded in the audit log
+//! (`anomalies_detected`).
+
+use crate::db::{AnomalyAnalysisRow, DatasetRow};
+use crate::errors::ApiError;
+use crate::models::{AnomalyKind, AnomalyWarning};
+use crate::policy;
+use crate::quality::plausible_range;
+use crate::state::AppState;
+use chrono::Utc;
+use std::time::Duration;
+use uuid::Uuid;
+use zk_proofs::types::{Measurement, ShardStats};
+
+const DEFAULT_INTERVAL_SECS: u64 = 600;
+
+/// Shards read per page.
+const PAGE: u64 = 1000;
+
+/// An empty bucket is flagged when at least this many records were expected in it.
+const MIN_EXPECTED_COUNT: f64 = 10.0;
+
+/// Buckets with fewer records than this may plausibly hold one repeated glucose value.
+const MIN_VARIANCE_SAMPLE: u64 = 10;
+
+/// Warnings kept per analysis; `warnings_total` counts them all.
+pub const MAX_STORED_WARNINGS: usize = 1000;
+
+fn scan_interval() -> Option<Duration> {
+    let secs = std::env::var("ANOMALY_SCAN_INTERVAL_SECS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(DEFAULT_INTERVAL_SECS);
+    (secs > 0).then(|| Duration::from_secs(secs))
+}
+
+fn warning(kind: AnomalyKind, shard_index: u64, bucket_index: Option<usize>, field: Option<Measurement>, observed: Option<f64>, message: String) -> AnomalyWarning {
+    AnomalyWarning {
+        kind,
+        shard_index,
+        bucket_index,
+        field,
+        observed,
+        message,
+    }
+}
+
+/// Checks that need only the shard itself.
+fn check_shard(dataset: &DatasetRow, shard_index: u64, stats: &ShardStats, out: &mut Vec<AnomalyWarning>) {
+    let records: u64 = stats.count_by_bucket.iter().sum();
+    if records != dataset.shard_size {
+        out.push(warning(
+            AnomalyKind::CountMismatch,
+            shard_index,
+            None,
+            None,
+            Some(records as f64),
+            format!("bucket counts add up to {records} records, shard size is {}", dataset.shard_size),
+        ));
+    }
+
+    let withheld = policy::suppressed_cells(&stats.count_by_bucket, policy::min_cell_count());
+    for (bucket_index, &count) in stats.count_by_bucket.iter().enumerate() {
+        if count == 0 {
+            continue;
+        }
+        let withheld = withheld[bucket_index];
+        for (field_index, &field) in dataset.field_set.measurements().iter().enumerate() {
+            let Some(&sum) = stats.sums_by_bucket(field_index).and_then(|sums| sums.get(bucket_index)) else {
+                continue;
+            };
+            let mean = sum as f64 / count as f64;
+            let (lo, hi) = plausible_range(field);
+            if mean < lo as f64 || mean > hi as f64 {
+                out.push(warning(
+                    AnomalyKind::MeanOutOfRange,
+                    shard_index,
+                    Some(bucket_index),
+                    Some(field),
+                    (!withheld).then_some(mean),
+                    if withheld {
+                        format!("mean {} is outside {lo}..={hi} (too few records to show it)", field.name())
+                    } else {
+                        format!("mean {} of {mean:.1} over {count} records is outside {lo}..={hi}", field.name())
+                    },
+                ));
+            }
+        }
+
+        let sum_sq = stats.sum_glucose_sq_by_bucket.as_ref().and_then(|s| s.get(bucket_index));
+        let sum = stats.sum_glucose_by_bucket.get(bucket_index);
+        if let (Some(&sum_sq), Some(&sum)) = (sum_sq, sum)
+            && count >= MIN_VARIANCE_SAMPLE
+            && count as u128 * sum_sq as u128 == sum as u128 * sum as u128
+        {
+            out.push(warning(
+                AnomalyKind::ConstantGlucose,
+                shard_index,
+                Some(bucket_index),
+                Some(Measurement::BloodGlucose),
+                (!withheld).then(|| sum as f64 / count as f64),
+                if withheld {
+                    "the bucket's glucose values are all identical".to_string()
+                } else {
+                    format!("all {count} glucose values are identical")
+                },
+            ));
+        }
+    }
+}
+
+/// Flag buckets a shard left empty although the dataset-wide distribution predicts records there.
+fn check_empty_buckets(shard_index: u64, counts: &[u64], dataset_counts: &[u64], out: &mut Vec<AnomalyWarning>) {
+    let dataset_total: u64 = dataset_counts.iter().sum();
+    let shard_total: u64 = counts.iter().sum();
+    if dataset_total == 0 {
+        return;
+    }
+    for (bucket_index, (&count, &dataset_count)) in counts.iter().zip(dataset_counts).enumerate() {
+        let expected = shard_total as f64 * dataset_count as f64 / dataset_total as f64;
+        if count == 0 && expected >= MIN_EXPECTED_COUNT {
+            out.push(warning(
+                AnomalyKind::EmptyBucket,
+                shard_index,
+                Some(bucket_index),
+                None,
+                Some(expected),
+                format!("bucket is empty; the dataset's distribution predicts {expected:.1} records"),
+            ));
+        }
+    }
+}
+
+/// Analyze every verified shard of a ready dataset.
+async fn analyze(state: &AppState, dataset_id: Uuid, dataset: &DatasetRow) -> Result<(Vec<AnomalyWarning>, u64), ApiError> {
+    let shards_total = dataset.shards_total();
+    let mut warnings = Vec::new();
+    let mut shard_counts = Vec::new();
+    let mut dataset_counts = vec![0u64; dataset.age_buckets.num_buckets()];
+
+    let mut offset = 0;
+    loop {
+        let page = state.store.list_shards(dataset_id, 0..shards_total, offset, PAGE, false).await?;
+        offset += page.len() as u64;
+        for (shard_index, _, stats, verified, _) in &page {
+            if !*verified {
+                continue;
+            }
+            check_shard(dataset, *shard_index, stats, &mut warnings);
+            for (total, count) in dataset_counts.iter_mut().zip(&stats.count_by_bucket) {
+                *total += count;
+            }
+            shard_counts.push((*shard_index, stats.count_by_bucket.clone()));
+        }
+        if (page.len() as u64) < PAGE {
+            break;
+        }
+    }
+
+    for (shard_index, counts) in &shard_counts {
+        check_empty_buckets(*shard_index, counts, &dataset_counts, &mut warnings);
+    }
+    warnings.sort_by_key(|w| w.shard_index);
+
+    Ok((warnings, shard_counts.len() as u64))
+}
+
+/// The current anomaly analysis of a dataset, analyzing it first if the stored one is missing or
+/// stale. `None` while the dataset isn't ready.
+pub async fn analysis(state: &AppState, dataset_id: Uuid, dataset: &DatasetRow) -> Result<Option<AnomalyAnalysisRow>, ApiError> {
+    let (Some(commitment_hex), "ready") = (&dataset.commitment_hex, dataset.status.as_str()) else {
+        return Ok(None);
+    };
+    let shards_stored = state.store.count_shards_done(dataset_id).await?;
+    if let Some(stored) = state.store.get_anomaly_analysis(dataset_id).await?
+        && stored.dataset_commitment_hex == *commitment_hex
+        && stored.shards_stored == shards_stored
+    {
+        return Ok(Some(stored));
+    }
+
+    let (mut warnings, shards_analyzed) = analyze(state, dataset_id, dataset).await?;
+    let warnings_total = warnings.len() as u64;
+    warnings.truncate(MAX_STORED_WARNINGS);
+    let row = AnomalyAnalysisRow {
+        analyzed_at: Utc::now(),
+        dataset_commitment_hex: commitment_hex.clone(),
+        shards_stored,
+        shards_analyzed,
+        warnings_total,
+        warnings,
+    };
+    state.store.put_anomaly_analysis(dataset_id, &row).await?;
+
+    if warnings_total > 0 {
+        let mut shard_indices: Vec<u64> = row.warnings.iter().map(|w| w.shard_index).collect();
+        shard_indices.dedup();
+        state.store.append_audit(
+            Some(dataset_id),
+            "anomalies_detected",
+            &serde_json::json!({
+                "dataset_commitment_hex": commitment_hex,
+                "warnings_total": warnings_total,
+                "shard_indices": shard_indices,
+            }),
+        )
+        .await?;
+        tracing::warn!(%dataset_id, warnings_total, "anomalies detected in verified shards");
+    }
+    Ok(Some(row))
+}
+
+/// Analyze every dataset whose stored analysis is missing or stale.
+pub async fn scan(state: &AppState) -> Result<(), ApiError> {
+    for dataset_id in state.store.list_dataset_ids().await? {
+        // Deleted since listing.
+        let Some(dataset) = state.store.get_dataset(dataset_id).await? else {
+            continue;
+        };
+        analysis(state, dataset_id, &dataset).await?;
+    }
+    Ok(())
+}
+
+/// Background loop: periodically analyze new and changed datasets.
+pub async fn run(state: AppState) {
+    let Some(every) = scan_interval() else { return };
+    let mut interval = tokio::time::interval(every);
+    loop {
+        interval.tick().await;
+        if let Err(e) = scan(&state).await {
+            tracing::warn!(error = %e, "anomaly scan failed");
+        }
+    }
+}
@@ -1,262 +1,883 @@
-use crate::db;
+use crate::auth::{self, Caller};
+use crate::deadline::{self, Cancellation};
 use crate::errors::ApiError;
+use crate::export;
+use crate::key_usage;
 use crate::models::*;
+use crate::oidc;
+use crate::pk_download;
+use crate::progress;
+use crate::rate_limit;
+use crate::reverify;
+use crate::service::{self, AggregateProofOutcome, DatasetReverificationOutcome, QueryOutcome};
+use crate::share::{self, ShareClaims};
 use crate::state::AppState;
+use crate::telemetry;
+use crate::upload;
 use axum::{
-    extract::{Path, Query, Request, State},
-    http::{HeaderMap, StatusCode},
+    body::Bytes,
+    extract::{DefaultBodyLimit, Path, Query, Request, State},
+    http::{HeaderMap, HeaderValue, StatusCode},
     middleware::{self, Next},
-    response::Response,
-    routing::{get, post},
-    Json, Router,
+    response::{IntoResponse, Response},
+    routing::{delete, get, post},
+    Extension, Json, Router,
 };
-use base64::Engine;
-use tower_http::cors::{Any, CorsLayer};
+use tower_http::cors::{AllowOrigin, Any, CorsLayer};
 use uuid::Uuid;
-use zk_proofs::constants::{AGE_BUCKETS, DEFAULT_SHARD_SIZE, NUM_BUCKETS};
-use zk_proofs::groth16::{deserialize_proof, deserialize_vk, verify_shard_proof};
-use zk_proofs::types::ShardStats;
 
-use ark_bn254::Fr;
-use ark_serialize::CanonicalDeserialize;
+// Handlers only map HTTP onto `service`; all dataset, query and verification logic lives there.
 
-#[derive(Debug, serde::Deserialize)]
-pub struct ListShardsParams {
-    pub offset: Option<u64>,
-    pub limit: Option<u64>,
-    pub include_proof: Option<bool>,
+/// CORS for `cors_origins` (see `config`).
+fn cors_layer(state: &AppState) -> CorsLayer {
+    let origins = if state.config.any_cors_origin() {
+        AllowOrigin::any()
+    } else {
+        AllowOrigin::list(state.config.cors_origins.iter().filter_map(|origin| HeaderValue::from_str(origin).ok()))
+    };
+    CorsLayer::new().allow_origin(origins).allow_methods(Any).allow_headers(Any)
 }
 
 pub fn router(state: AppState) -> Router {
+    let cors = cors_layer(&state);
+    let proving = || middleware::from_fn_with_state(state.clone(), rate_limit::proving_middleware);
     let protected_routes = Router::new()
-        .route("/api/v1/datasets", post(create_dataset))
-        .route("/api/v1/queries", post(create_query))
+        .route("/api/v1/datasets", post(create_dataset).layer(proving()))
+        .route(
+            "/api/v1/queries",
+            post(create_query).layer(middleware::from_fn_with_state(state.clone(), rate_limit::query_quota)),
+        )
+        .route(
+            "/api/v1/queries/cohort",
+            post(create_cohort_query).layer(middleware::from_fn_with_state(state.clone(), rate_limit::query_quota)),
+        )
+        .route("/api/v1/queries/:id", get(get_query))
+        .route("/api/v1/queries/:id/status", get(get_query_status))
+        .route("/api/v1/queries/:id/approve", post(approve_query))
+        .route("/api/v1/queries/:id/reject", post(reject_query))
         .route("/api/v1/verify/shard", post(verify_shard))
-        .layer(middleware::from_fn(auth_middleware));
+        .route("/api/v1/verify/dataset/:id", post(verify_dataset).get(get_dataset_reverification))
+        .route("/api/v1/verify/dataset/:id/events", get(get_dataset_reverification_events))
+        .route(
+            "/api/v1/verify/shards",
+            post(verify_shards).layer(DefaultBodyLimit::max(upload::max_upload_bytes() as usize)),
+        )
+        .route("/api/v1/datasets/:id/audit", get(list_audit).post(verify_dataset))
+        .route("/api/v1/datasets/:id/queries", get(list_dataset_queries))
+        .route("/api/v1/datasets/:id/disclosure", get(get_disclosure))
+        .route("/api/v1/datasets/:id/privacy-budget", get(get_privacy_budget))
+        .route("/api/v1/datasets/:id/failures", get(list_shard_failures))
+        .route("/api/v1/datasets/:id/aggregate-proof", get(get_aggregate_proof))
+        .route("/api/v1/usage", get(get_usage))
+        .route("/api/v1/datasets/:id", delete(delete_dataset))
+        .route("/api/v1/datasets/:id/archive", get(get_dataset_archive))
+        .route("/api/v1/datasets/:id/share", post(create_share_link))
+        .route("/api/v1/datasets/:id/freeze", post(freeze_dataset))
+        .route("/api/v1/datasets/:id/cancel", post(cancel_dataset))
+        .route("/api/v1/datasets/:id/unfreeze", post(unfreeze_dataset))
+        .route(
+            "/api/v1/datasets/:id/acl",
+            get(get_dataset_acl).post(grant_dataset_access).delete(revoke_dataset_access),
+        )
+        .route("/api/v1/admin/keys", post(create_api_key).get(list_api_keys))
+        .route("/api/v1/admin/keys/:key_id", delete(revoke_api_key))
+        .route("/api/v1/admin/backups", post(create_backup))
+        .route("/api/v1/admin/curve-migrations", post(plan_curve_migration).layer(proving()).get(list_curve_migrations))
+        .route("/api/v1/admin/circuit-migrations", post(plan_circuit_migration).layer(proving()))
+        .route("/api/v1/export", get(export_ledger))
+        .route("/api/v1/admin/zk/self-test", post(run_zk_self_test))
+        .route("/api/v1/admin/proving", get(proving_status))
+        .route("/api/v1/zk/keys", get(list_zk_keys))
+        .route("/api/v1/zk/pk", get(get_pk))
+        .route("/api/v1/admin/proof-blobs", get(proof_blobs_status))
+        .route("/api/v1/admin/proof-blobs/audit", post(run_proof_blob_audit))
+        .route(
+            "/api/v1/datasets/import",
+            post(import_csv_dataset)
+                .layer(DefaultBodyLimit::max(upload::max_upload_bytes() as usize))
+                .layer(proving()),
+        )
+        .route(
+            "/api/v1/imports",
+            post(import_ledger).layer(DefaultBodyLimit::max(upload::max_upload_bytes() as usize)),
+        )
+        .route("/api/v1/uploads", post(init_upload))
+        .route("/api/v1/uploads/:id", get(get_upload))
+        .route("/api/v1/uploads/:id/chunks", post(put_upload_chunk))
+        .route("/api/v1/uploads/:id/commit", post(commit_upload).layer(proving()))
+        .route("/api/v1/streams", post(open_stream).layer(proving()))
+        .route("/api/v1/streams/:id", get(get_stream))
+        .route(
+            "/api/v1/streams/:id/records",
+            post(push_stream_records).layer(DefaultBodyLimit::max(upload::max_upload_bytes() as usize)),
+        )
+        .route("/api/v1/streams/:id/close", post(close_stream))
+        .route("/api/v1/federated", post(create_federated_dataset))
+        .route("/api/v1/federated/:id/shards", post(push_federated_shard))
+        .layer(middleware::from_fn_with_state(state.clone(), rate_limit::middleware))
+        .layer(middleware::from_fn_with_state(state.clone(), auth_middleware));
+
+    // Public unless the dataset's access list restricts it, so the key is optional here; share
+    // links open the shard and verification-report routes.
+    let access_listed_routes = Router::new()
+        .route("/api/v1/datasets/:id/manifest", get(get_manifest))
+        .route("/api/v1/datasets/:id/events", get(get_dataset_events))
+        .route("/api/v1/datasets/:id/quality", get(get_quality))
+        .route("/api/v1/datasets/:id/anomalies", get(get_anomalies))
+        .route("/api/v1/datasets/:id/shards", get(list_shards))
+        .route("/api/v1/datasets/:id/shards/export", get(export_shards))
+        .route("/api/v1/datasets/:id/aggregates", get(get_aggregates))
+        .route("/api/v1/datasets/:id/summary", get(get_dataset_summary))
+        .route("/api/v1/datasets/:id/verification-report", get(get_verification_report))
+        .layer(middleware::from_fn_with_state(state.clone(), rate_limit::middleware))
+        .layer(middleware::from_fn_with_state(state.clone(), optional_auth_middleware));
 
     Router::new()
-        .route("/health", get(|| async { "ok" }))
+        .route("/health", get(healthz))
+        .route("/healthz", get(healthz))
+        .route("/readyz", get(readyz))
+        .route("/metrics", get(metrics))
         .route("/api/v1/datasets/:id", get(get_dataset))
-        .route("/api/v1/datasets/:id/shards", get(list_shards))
         .route("/api/v1/zk/vk", get(get_vk))
+        .route("/api/v1/zk/vk/:version", get(get_vk_version))
+        .route("/api/v1/zk/schema", get(get_zk_schema))
+        .route("/api/v1/zk/verifier.sol", get(get_solidity_verifier))
+        .route("/api/v1/generators", get(list_generators))
+        .route("/api/v1/keys/signing", get(get_signing_key))
+        .route("/api/v1/receipts/verify", post(verify_receipt))
+        .route("/api/v1/log/sth", get(get_log_tree_head))
+        .route("/api/v1/log/proof", get(get_log_proof))
+        .merge(access_listed_routes)
         .merge(protected_routes)
         .with_state(state)
-        .layer(
-            CorsLayer::new()
-                .allow_origin(Any)
-                .allow_methods(Any)
-                .allow_headers(Any),
-        )
+        .layer(middleware::from_fn(deadline::middleware))
+        .layer(middleware::from_fn(telemetry::middleware))
+        .layer(cors)
+}
+
+/// The caller a presented key resolves to; a store failure is a `500`.
+async fn resolved_caller(state: &AppState, provided_key: &str) -> Result<Option<Caller>, StatusCode> {
+    auth::resolve_caller(state, provided_key).await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
+}
+
+/// The caller of a request presenting `X-API-KEY` or an OIDC bearer token (see `oidc`): `None` if
+/// it presents neither, `Some(None)` if what it presents doesn't authenticate or `auth.mode`
+/// doesn't accept it. An unreachable identity provider is a `502`.
+async fn presented_caller(state: &AppState, headers: &HeaderMap) -> Result<Option<Option<Caller>>, StatusCode> {
+    let mode = state.config.auth.mode;
+    if let Some(provided_key) = headers.get("X-API-KEY").and_then(|v| v.to_str().ok()) {
+        if !mode.allows_api_keys() {
+            return Ok(Some(None));
+        }
+        return resolved_caller(state, provided_key).await.map(Some);
+    }
+    let bearer = headers
+        .get(axum::http::header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "));
+    let Some(token) = bearer else {
+        return Ok(None);
+    };
+    if !mode.allows_bearer_tokens() {
+        return Ok(Some(None));
+    }
+    match oidc::resolve_bearer(state, token.trim()).await {
+        Ok(caller) => Ok(Some(caller)),
+        Err(ApiError::Upstream(reason)) => {
+            tracing::warn!(%reason, "OIDC provider unavailable");
+            Err(StatusCode::BAD_GATEWAY)
+        }
+        Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR),
+    }
 }
 
 async fn auth_middleware(
+    State(state): State<AppState>,
     headers: HeaderMap,
-    request: Request,
+    mut request: Request,
     next: Next,
 ) -> Result<Response, StatusCode> {
-    // In production, this should be a strong secret from environment.
-    let expected_key = std::env::var("API_KEY").unwrap_or_else(|_| "dev-secret-key".to_string());
-
-    if let Some(provided_key) = headers.get("X-API-KEY") {
-        if provided_key == expected_key.as_str() {
-            return Ok(next.run(request).await);
-        }
+    if let Some(Some(caller)) = presented_caller(&state, &headers).await? {
+        request.extensions_mut().insert(caller);
+        return Ok(next.run(request).await);
     }
 
     tracing::warn!("unauthorized access attempt");
     Err(StatusCode::UNAUTHORIZED)
 }
 
-async fn create_dataset(State(state): State<AppState>, Json(req): Json<DatasetCreateRequest>) -> Result<Json<DatasetCreateResponse>, ApiError> {
-    let dataset_size = req.dataset_size.unwrap_or(1_000_000);
+#[derive(serde::Deserialize)]
+struct ShareTokenParam {
+    share_token: Option<String>,
+}
 
-    if dataset_size % (DEFAULT_SHARD_SIZE as u64) != 0 {
-        return Err(ApiError::BadRequest(format!(
-            "dataset_size must be a multiple of shard_size ({DEFAULT_SHARD_SIZE})"
-        )));
+/// Like `auth_middleware`, but requests without a key or bearer token pass through with no
+/// `Caller`. A share token (`X-SHARE-TOKEN` or `?share_token=`) in place of the key is checked and
+/// its claims passed on.
+async fn optional_auth_middleware(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    mut request: Request,
+    next: Next,
+) -> Result<Response, StatusCode> {
+    if let Some(presented) = presented_caller(&state, &headers).await? {
+        let Some(caller) = presented else {
+            tracing::warn!("unauthorized access attempt");
+            return Err(StatusCode::UNAUTHORIZED);
+        };
+        request.extensions_mut().insert(caller);
+        return Ok(next.run(request).await);
+    }
+
+    let token = match headers.get("X-SHARE-TOKEN").and_then(|v| v.to_str().ok()) {
+        Some(token) => Some(token.to_string()),
+        None => Query::<ShareTokenParam>::try_from_uri(request.uri()).ok().and_then(|q| q.0.share_token),
+    };
+    if let Some(token) = token {
+        match share::verify(&state.data_dir, &token) {
+            Ok(claims) => {
+                request.extensions_mut().insert(claims);
+            }
+            Err(reason) => {
+                tracing::warn!(%reason, "rejected share token");
+                return Err(StatusCode::UNAUTHORIZED);
+            }
+        }
     }
+    Ok(next.run(request).await)
+}
+
+async fn create_dataset(
+    State(state): State<AppState>,
+    Extension(caller): Extension<Caller>,
+    Json(req): Json<DatasetCreateRequest>,
+) -> Result<Json<DatasetCreateResponse>, ApiError> {
+    Ok(Json(service::create_dataset(&state, &caller, &req).await?))
+}
+
+async fn get_usage(State(state): State<AppState>, Extension(caller): Extension<Caller>) -> Result<Json<UsageResponse>, ApiError> {
+    Ok(Json(service::get_usage(&state, &caller).await?))
+}
+
+async fn list_generators() -> Json<GeneratorListResponse> {
+    Json(service::list_generators())
+}
+
+async fn get_signing_key(State(state): State<AppState>) -> Json<SigningKeyResponse> {
+    Json(service::get_signing_key(&state))
+}
+
+async fn verify_receipt(
+    State(state): State<AppState>,
+    Json(req): Json<ReceiptVerifyRequest>,
+) -> Result<Json<ReceiptVerifyResponse>, ApiError> {
+    Ok(Json(service::verify_receipt(&state, &req)?))
+}
+
+async fn get_log_tree_head(State(state): State<AppState>) -> Result<Json<SignedTreeHead>, ApiError> {
+    Ok(Json(service::get_log_tree_head(&state).await?))
+}
+
+async fn get_log_proof(
+    State(state): State<AppState>,
+    Query(params): Query<LogProofParams>,
+) -> Result<Json<LogProofResponse>, ApiError> {
+    Ok(Json(service::get_log_proof(&state, &params).await?))
+}
+
+async fn init_upload(State(state): State<AppState>, Extension(caller): Extension<Caller>) -> Result<Json<UploadInitResponse>, ApiError> {
+    Ok(Json(service::init_upload(&state, &caller).await?))
+}
+
+async fn get_upload(
+    State(state): State<AppState>,
+    Extension(caller): Extension<Caller>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<UploadStatusResponse>, ApiError> {
+    Ok(Json(service::get_upload(&state, &caller, id).await?))
+}
+
+async fn put_upload_chunk(
+    State(state): State<AppState>,
+    Extension(caller): Extension<Caller>,
+    Path(id): Path<Uuid>,
+    Json(req): Json<UploadChunkRequest>,
+) -> Result<Json<UploadStatusResponse>, ApiError> {
+    Ok(Json(service::put_upload_chunk(&state, &caller, id, &req).await?))
+}
+
+async fn commit_upload(
+    State(state): State<AppState>,
+    Extension(caller): Extension<Caller>,
+    Path(id): Path<Uuid>,
+    Json(req): Json<UploadCommitRequest>,
+) -> Result<Json<DatasetCreateResponse>, ApiError> {
+    Ok(Json(service::commit_upload(&state, &caller, id, &req).await?))
+}
+
+async fn open_stream(
+    State(state): State<AppState>,
+    Extension(caller): Extension<Caller>,
+    Json(req): Json<StreamOpenRequest>,
+) -> Result<Json<StreamStatusResponse>, ApiError> {
+    Ok(Json(service::open_stream(&state, &caller, &req).await?))
+}
 
-    let dataset_id = Uuid::new_v4();
-    db::insert_dataset(&state.db, dataset_id, dataset_size).await?;
+async fn get_stream(
+    State(state): State<AppState>,
+    Extension(caller): Extension<Caller>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<StreamStatusResponse>, ApiError> {
+    Ok(Json(service::get_stream(&state, &caller, id).await?))
+}
+
+/// The CSV batch is the request body.
+async fn push_stream_records(
+    State(state): State<AppState>,
+    Extension(caller): Extension<Caller>,
+    Path(id): Path<Uuid>,
+    Query(params): Query<StreamRecordsParams>,
+    body: Bytes,
+) -> Result<Json<StreamStatusResponse>, ApiError> {
+    Ok(Json(service::push_stream_records(&state, &caller, id, &params, &body).await?))
+}
 
-    // Start background generation.
-    tokio::spawn(crate::dataset::generate_dataset_and_proofs(
-        state.clone(),
-        dataset_id,
-        dataset_size,
-    ));
+async fn close_stream(
+    State(state): State<AppState>,
+    Extension(caller): Extension<Caller>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<StreamStatusResponse>, ApiError> {
+    Ok(Json(service::close_stream(&state, &caller, id).await?))
+}
+
+async fn create_federated_dataset(
+    State(state): State<AppState>,
+    Extension(caller): Extension<Caller>,
+    Json(req): Json<FederatedDatasetCreateRequest>,
+) -> Result<Json<DatasetGetResponse>, ApiError> {
+    Ok(Json(service::create_federated_dataset(&state, &caller, &req).await?))
+}
+
+async fn push_federated_shard(
+    State(state): State<AppState>,
+    Extension(caller): Extension<Caller>,
+    Path(id): Path<Uuid>,
+    Json(req): Json<FederatedShardRequest>,
+) -> Result<Json<FederatedShardResponse>, ApiError> {
+    Ok(Json(service::push_federated_shard(&state, &caller, id, req).await?))
+}
 
-    Ok(Json(DatasetCreateResponse { dataset_id }))
+/// The CSV is the request body.
+async fn import_csv_dataset(
+    State(state): State<AppState>,
+    Extension(caller): Extension<Caller>,
+    Query(params): Query<CsvImportParams>,
+    body: Bytes,
+) -> Result<Json<DatasetCreateResponse>, ApiError> {
+    Ok(Json(service::import_csv_dataset(&state, &caller, &params, &body).await?))
 }
 
 async fn get_dataset(State(state): State<AppState>, Path(id): Path<Uuid>) -> Result<Json<DatasetGetResponse>, ApiError> {
-    let Some((created_at, dataset_size, status_str, commitment, error)) = db::get_dataset(&state.db, id).await? else {
-        return Err(ApiError::NotFound("dataset not found".to_string()));
-    };
+    Ok(Json(service::get_dataset(&state, id).await?))
+}
 
-    let status = match status_str.as_str() {
-        "generating" => DatasetStatus::Generating,
-        "ready" => DatasetStatus::Ready,
-        "failed" => DatasetStatus::Failed,
-        _ => DatasetStatus::Failed,
-    };
+async fn get_dataset_events(
+    State(state): State<AppState>,
+    caller: Option<Extension<Caller>>,
+    Path(id): Path<Uuid>,
+) -> Result<Response, ApiError> {
+    Ok(progress::events(&state, caller.as_deref(), id).await?.into_response())
+}
 
-    let shards_total = dataset_size / (DEFAULT_SHARD_SIZE as u64);
-    let shards_done = db::count_shards_done(&state.db, id).await?;
+async fn export_ledger(
+    State(state): State<AppState>,
+    Extension(caller): Extension<Caller>,
+    Query(params): Query<ExportParams>,
+) -> Result<Response, ApiError> {
+    let body = service::export_ledger(&state, &caller, &params).await?;
+    Ok(([(axum::http::header::CONTENT_TYPE, "application/x-ndjson")], body).into_response())
+}
 
-    Ok(Json(DatasetGetResponse {
-        dataset_id: id,
-        created_at,
-        dataset_size,
-        shard_size: DEFAULT_SHARD_SIZE as u64,
-        num_buckets: NUM_BUCKETS as u64,
-        status,
-        shards_total,
-        shards_done,
-        dataset_commitment_hex: commitment,
-        error,
-    }))
+async fn import_ledger(
+    State(state): State<AppState>,
+    Extension(caller): Extension<Caller>,
+    Extension(cancel): Extension<Cancellation>,
+    body: Bytes,
+) -> Result<Json<export::ImportReport>, ApiError> {
+    Ok(Json(service::import_ledger(&state, &caller, &cancel, &body).await?))
+}
+
+/// `503` until the ZK self-test has passed, or after a failure.
+async fn healthz() -> Json<HealthzResponse> {
+    Json(service::liveness())
+}
+
+async fn readyz(State(state): State<AppState>) -> (StatusCode, Json<ReadyzResponse>) {
+    let readiness = service::readiness(&state).await;
+    let status = if readiness.ready { StatusCode::OK } else { StatusCode::SERVICE_UNAVAILABLE };
+    (status, Json(readiness))
+}
+
+async fn run_zk_self_test(State(state): State<AppState>, Extension(caller): Extension<Caller>) -> Result<Json<ZkSelfTestReport>, ApiError> {
+    Ok(Json(service::run_zk_self_test(&state, &caller).await?))
+}
+
+async fn metrics(State(state): State<AppState>) -> Result<Response, ApiError> {
+    let body = service::metrics(&state).await?;
+    Ok(([(axum::http::header::CONTENT_TYPE, "text/plain; version=0.0.4")], body).into_response())
+}
+
+/// `response` with an `X-Key-Rotation-Due` header naming the `due` keys, if any.
+fn with_rotation_header(due: Vec<String>, response: impl IntoResponse) -> Response {
+    let mut response = response.into_response();
+    if !due.is_empty()
+        && let Ok(value) = due.join(",").parse()
+    {
+        response.headers_mut().insert(key_usage::ROTATION_HEADER, value);
+    }
+    response
+}
+
+async fn list_zk_keys(State(state): State<AppState>, Extension(caller): Extension<Caller>) -> Result<Response, ApiError> {
+    let response = service::list_zk_keys(&state, &caller).await?;
+    let due = response.keys.iter().filter(|k| k.rotation_due).map(|k| k.key_id.clone()).collect();
+    Ok(with_rotation_header(due, Json(response)))
+}
+
+async fn proving_status(State(state): State<AppState>, Extension(caller): Extension<Caller>) -> Result<Json<ProvingStatusResponse>, ApiError> {
+    Ok(Json(service::proving_status(&state, &caller)?))
+}
+
+async fn proof_blobs_status(State(state): State<AppState>, Extension(caller): Extension<Caller>) -> Result<Json<ProofBlobsResponse>, ApiError> {
+    Ok(Json(service::proof_blobs_status(&state, &caller).await?))
+}
+
+async fn run_proof_blob_audit(State(state): State<AppState>, Extension(caller): Extension<Caller>) -> Result<Json<ProofBlobAuditReport>, ApiError> {
+    Ok(Json(service::run_proof_blob_audit(&state, &caller).await?))
+}
+
+async fn create_backup(State(state): State<AppState>, Extension(caller): Extension<Caller>) -> Result<Json<BackupResponse>, ApiError> {
+    Ok(Json(service::create_backup(&state, &caller).await?))
+}
+
+async fn plan_curve_migration(
+    State(state): State<AppState>,
+    Extension(caller): Extension<Caller>,
+    Json(req): Json<CurveMigrationRequest>,
+) -> Result<Json<CurveMigrationPlanResponse>, ApiError> {
+    Ok(Json(service::plan_curve_migration(&state, &caller, req).await?))
+}
+
+async fn plan_circuit_migration(
+    State(state): State<AppState>,
+    Extension(caller): Extension<Caller>,
+    Json(req): Json<CircuitMigrationRequest>,
+) -> Result<Json<CircuitMigrationPlanResponse>, ApiError> {
+    Ok(Json(service::plan_circuit_migration(&state, &caller, req).await?))
+}
+
+async fn list_curve_migrations(
+    State(state): State<AppState>,
+    Extension(caller): Extension<Caller>,
+) -> Result<Json<CurveMigrationListResponse>, ApiError> {
+    Ok(Json(service::list_curve_migrations(&state, &caller).await?))
+}
+
+async fn cancel_dataset(
+    State(state): State<AppState>,
+    Extension(caller): Extension<Caller>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<DatasetCancelResponse>, ApiError> {
+    Ok(Json(service::cancel_dataset(&state, &caller, id).await?))
+}
+
+async fn freeze_dataset(
+    State(state): State<AppState>,
+    Extension(caller): Extension<Caller>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<DatasetFreezeResponse>, ApiError> {
+    Ok(Json(service::freeze_dataset(&state, &caller, id).await?))
+}
+
+async fn unfreeze_dataset(
+    State(state): State<AppState>,
+    Extension(caller): Extension<Caller>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<DatasetFreezeResponse>, ApiError> {
+    Ok(Json(service::unfreeze_dataset(&state, &caller, id).await?))
+}
+
+async fn delete_dataset(
+    State(state): State<AppState>,
+    Extension(caller): Extension<Caller>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<DatasetDeleteResponse>, ApiError> {
+    Ok(Json(service::delete_dataset(&state, &caller, id).await?))
+}
+
+async fn get_dataset_archive(
+    State(state): State<AppState>,
+    Extension(caller): Extension<Caller>,
+    Extension(cancel): Extension<Cancellation>,
+    Path(id): Path<Uuid>,
+) -> Result<Response, ApiError> {
+    let body = service::get_dataset_archive(&state, &caller, &cancel, id).await?;
+    Ok(([(axum::http::header::CONTENT_TYPE, "application/x-ndjson")], body).into_response())
+}
+
+async fn create_share_link(
+    State(state): State<AppState>,
+    Extension(caller): Extension<Caller>,
+    Path(id): Path<Uuid>,
+    body: Option<Json<ShareLinkRequest>>,
+) -> Result<Json<ShareLinkResponse>, ApiError> {
+    let req = body.map(|Json(req)| req).unwrap_or_default();
+    Ok(Json(service::create_share_link(&state, &caller, id, &req).await?))
+}
+
+async fn get_verification_report(
+    State(state): State<AppState>,
+    caller: Option<Extension<Caller>>,
+    share: Option<Extension<ShareClaims>>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<DatasetVerificationReport>, ApiError> {
+    Ok(Json(service::get_verification_report(&state, caller.as_deref(), share.as_deref(), id).await?))
+}
+
+/// `201` with the new key, shown only here.
+async fn create_api_key(
+    State(state): State<AppState>,
+    Extension(caller): Extension<Caller>,
+    Json(req): Json<ApiKeyCreateRequest>,
+) -> Result<(StatusCode, Json<ApiKeyCreateResponse>), ApiError> {
+    Ok((StatusCode::CREATED, Json(service::create_api_key(&state, &caller, &req).await?)))
+}
+
+async fn list_api_keys(
+    State(state): State<AppState>,
+    Extension(caller): Extension<Caller>,
+) -> Result<Json<ApiKeyListResponse>, ApiError> {
+    Ok(Json(service::list_api_keys(&state, &caller).await?))
+}
+
+async fn revoke_api_key(
+    State(state): State<AppState>,
+    Extension(caller): Extension<Caller>,
+    Path(key_id): Path<String>,
+) -> Result<Json<ApiKeyRevokeResponse>, ApiError> {
+    Ok(Json(service::revoke_api_key(&state, &caller, &key_id).await?))
+}
+
+async fn get_dataset_acl(
+    State(state): State<AppState>,
+    Extension(caller): Extension<Caller>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<DatasetAclResponse>, ApiError> {
+    Ok(Json(service::get_dataset_acl(&state, &caller, id).await?))
+}
+
+async fn grant_dataset_access(
+    State(state): State<AppState>,
+    Extension(caller): Extension<Caller>,
+    Path(id): Path<Uuid>,
+    Json(req): Json<DatasetAccessGrant>,
+) -> Result<Json<DatasetAclResponse>, ApiError> {
+    Ok(Json(service::grant_dataset_access(&state, &caller, id, &req).await?))
+}
+
+async fn revoke_dataset_access(
+    State(state): State<AppState>,
+    Extension(caller): Extension<Caller>,
+    Path(id): Path<Uuid>,
+    Query(req): Query<DatasetAccessGrant>,
+) -> Result<Json<DatasetAclResponse>, ApiError> {
+    Ok(Json(service::revoke_dataset_access(&state, &caller, id, &req).await?))
+}
+
+async fn get_manifest(
+    State(state): State<AppState>,
+    caller: Option<Extension<Caller>>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    Ok(Json(service::get_manifest(&state, caller.as_deref(), id).await?))
+}
+
+async fn get_anomalies(
+    State(state): State<AppState>,
+    caller: Option<Extension<Caller>>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<DatasetAnomaliesResponse>, ApiError> {
+    Ok(Json(service::get_anomalies(&state, caller.as_deref(), id).await?))
+}
+
+async fn get_quality(
+    State(state): State<AppState>,
+    caller: Option<Extension<Caller>>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<DatasetQualityResponse>, ApiError> {
+    Ok(Json(service::get_quality(&state, caller.as_deref(), id).await?))
+}
+
+async fn get_aggregates(
+    State(state): State<AppState>,
+    caller: Option<Extension<Caller>>,
+    Path(id): Path<Uuid>,
+    Query(params): Query<PageParams>,
+) -> Result<Json<DatasetAggregatesResponse>, ApiError> {
+    Ok(Json(service::get_aggregates(&state, caller.as_deref(), id, &params).await?))
+}
+
+async fn get_dataset_summary(
+    State(state): State<AppState>,
+    caller: Option<Extension<Caller>>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<DatasetSummaryResponse>, ApiError> {
+    Ok(Json(service::get_dataset_summary(&state, caller.as_deref(), id).await?))
+}
+
+/// `200` with the proof, or `202` while it is being made.
+async fn get_aggregate_proof(
+    State(state): State<AppState>,
+    Extension(caller): Extension<Caller>,
+    Path(id): Path<Uuid>,
+    Query(params): Query<AggregateProofParams>,
+) -> Result<Response, ApiError> {
+    Ok(match service::get_aggregate_proof(&state, &caller, id, &params).await? {
+        AggregateProofOutcome::Ready(response) => Json(response).into_response(),
+        AggregateProofOutcome::Pending(pending) => (StatusCode::ACCEPTED, Json(pending)).into_response(),
+    })
 }
 
 async fn list_shards(
     State(state): State<AppState>,
+    caller: Option<Extension<Caller>>,
+    share: Option<Extension<ShareClaims>>,
     Path(id): Path<Uuid>,
     Query(params): Query<ListShardsParams>,
 ) -> Result<Json<ShardListResponse>, ApiError> {
-    let offset = params.offset.unwrap_or(0);
-    let limit = params.limit.unwrap_or(50).min(500);
-    let include_proof = params.include_proof.unwrap_or(false);
+    Ok(Json(service::list_shards(&state, caller.as_deref(), share.as_deref(), id, &params).await?))
+}
 
-    let Some((_created_at, dataset_size, _status, _commitment, _error)) = db::get_dataset(&state.db, id).await? else {
-        return Err(ApiError::NotFound("dataset not found".to_string()));
-    };
-    let shards_total = dataset_size / (DEFAULT_SHARD_SIZE as u64);
-
-    let rows = db::list_shards(&state.db, id, offset, limit, include_proof).await?;
-
-    let mut shards = Vec::with_capacity(rows.len());
-    for (shard_index, commitment_hex, stats, verified, proof_b64) in rows {
-        shards.push(ShardListItem {
-            shard_index,
-            shard_commitment_hex: commitment_hex,
-            sum_glucose_by_bucket: stats.sum_glucose_by_bucket,
-            count_by_bucket: stats.count_by_bucket,
-            verified,
-            proof_b64,
-        });
-    }
+async fn export_shards(
+    State(state): State<AppState>,
+    caller: Option<Extension<Caller>>,
+    share: Option<Extension<ShareClaims>>,
+    Path(id): Path<Uuid>,
+    Query(params): Query<ExportShardsParams>,
+) -> Result<Response, ApiError> {
+    let export = service::export_shards(&state, caller.as_deref(), share.as_deref(), id, &params).await?;
+    Ok((
+        [
+            (axum::http::header::CONTENT_TYPE, "application/x-ndjson".to_string()),
+            (axum::http::HeaderName::from_static("x-shards-total"), export.shards_total.to_string()),
+        ],
+        axum::body::Body::from_stream(export.lines),
+    )
+        .into_response())
+}
 
-    Ok(Json(ShardListResponse {
-        dataset_id: id,
-        offset,
-        limit,
-        shards_total,
-        shards,
-    }))
+async fn list_audit(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+    Query(params): Query<PageParams>,
+) -> Result<Json<AuditListResponse>, ApiError> {
+    Ok(Json(service::list_audit(&state, id, &params).await?))
 }
 
-async fn create_query(State(state): State<AppState>, Json(req): Json<QueryRequest>) -> Result<Json<QueryResponse>, ApiError> {
-    if req.field != "blood_glucose" && req.field != "blood_glucose_mg_dl" {
-        return Err(ApiError::BadRequest("only field 'blood_glucose' is supported".to_string()));
-    }
+async fn list_shard_failures(State(state): State<AppState>, Path(id): Path<Uuid>) -> Result<Json<ShardFailuresResponse>, ApiError> {
+    Ok(Json(service::list_shard_failures(&state, id).await?))
+}
 
-    let bucket_index = bucket_for_age_range(&req.age_range)
-        .ok_or_else(|| ApiError::BadRequest("age_range must match one of the configured buckets".to_string()))?;
+async fn get_disclosure(State(state): State<AppState>, Path(id): Path<Uuid>) -> Result<Json<DisclosureResponse>, ApiError> {
+    Ok(Json(service::get_disclosure(&state, id).await?))
+}
 
-    // Ensure dataset exists.
-    let Some((_created_at, dataset_size, status, _commitment, _error)) = db::get_dataset(&state.db, req.dataset_id).await? else {
-        return Err(ApiError::NotFound("dataset not found".to_string()));
-    };
+async fn get_privacy_budget(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<PrivacyBudgetResponse>, ApiError> {
+    Ok(Json(service::get_privacy_budget(&state, id).await?))
+}
 
-    if status != "ready" {
-        return Err(ApiError::Conflict("dataset not ready".to_string()));
-    }
+/// `200` with the answer, or `202` when the query awaits approval or runs as a job.
+async fn create_query(
+    State(state): State<AppState>,
+    Extension(caller): Extension<Caller>,
+    Json(req): Json<QueryRequest>,
+) -> Result<Response, ApiError> {
+    Ok(match service::create_query(&state, &caller, &req).await? {
+        QueryOutcome::Released(response) => Json(response).into_response(),
+        QueryOutcome::Breakdown(breakdown) => Json(breakdown).into_response(),
+        QueryOutcome::Deferred(pending) => (StatusCode::ACCEPTED, Json(pending)).into_response(),
+    })
+}
 
-    let (sum, count) = db::aggregate_for_bucket(&state.db, req.dataset_id, bucket_index).await?;
+async fn create_cohort_query(
+    State(state): State<AppState>,
+    Extension(caller): Extension<Caller>,
+    Json(req): Json<CohortQueryRequest>,
+) -> Result<Json<CohortQueryResponse>, ApiError> {
+    Ok(Json(service::create_cohort_query(&state, &caller, &req).await?))
+}
 
-    let mean = match req.metric {
-        Metric::Mean => {
-            if count == 0 {
-                None
-            } else {
-                Some(sum as f64 / count as f64)
-            }
-        }
-        _ => None,
-    };
+async fn approve_query(
+    State(state): State<AppState>,
+    Extension(caller): Extension<Caller>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<QueryResponse>, ApiError> {
+    Ok(Json(service::approve_query(&state, &caller, id).await?))
+}
 
-    // Server-side verification: all shards must be verified.
-    let shards_total = dataset_size / (DEFAULT_SHARD_SIZE as u64);
-    let shards_verified = db::count_shards_verified(&state.db, req.dataset_id).await?;
-    let server_verified = shards_verified == shards_total;
-
-    let query_id = Uuid::new_v4();
-    db::insert_query(
-        &state.db,
-        query_id,
-        req.dataset_id,
-        &req.metric,
-        bucket_index,
-        sum,
-        count,
-        mean,
-        server_verified,
-    )
-    .await?;
+async fn reject_query(
+    State(state): State<AppState>,
+    Extension(caller): Extension<Caller>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<QueryRejectResponse>, ApiError> {
+    Ok(Json(service::reject_query(&state, &caller, id).await?))
+}
 
-    let (min_age, max_age) = AGE_BUCKETS[bucket_index];
+async fn get_query_status(
+    State(state): State<AppState>,
+    Extension(caller): Extension<Caller>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<QueryStatusResponse>, ApiError> {
+    Ok(Json(service::get_query_status(&state, &caller, id).await?))
+}
 
-    Ok(Json(QueryResponse {
-        query_id,
-        dataset_id: req.dataset_id,
-        bucket_index,
-        bucket_range: (min_age, max_age),
-        sum_glucose: sum,
-        count,
-        mean_glucose: match req.metric {
-            Metric::Mean => mean,
-            Metric::Sum => None,
-            Metric::Count => None,
-        },
-        server_verified,
-        shard_proofs_endpoint: format!("/api/v1/datasets/{}/shards?include_proof=true", req.dataset_id),
-    }))
+async fn get_query(
+    State(state): State<AppState>,
+    Extension(caller): Extension<Caller>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<QueryRecord>, ApiError> {
+    Ok(Json(service::get_query(&state, &caller, id).await?))
 }
 
-async fn get_vk(State(state): State<AppState>) -> Result<Json<ZkVkResponse>, ApiError> {
-    let keys = state.ensure_keys().await?;
-    let vk_bytes = zk_proofs::groth16::serialize_vk(keys.vk.as_ref()).map_err(|_| ApiError::Internal)?;
+async fn list_dataset_queries(
+    State(state): State<AppState>,
+    Extension(caller): Extension<Caller>,
+    Path(id): Path<Uuid>,
+    Query(params): Query<PageParams>,
+) -> Result<Json<QueryListResponse>, ApiError> {
+    Ok(Json(service::list_dataset_queries(&state, &caller, id, &params).await?))
+}
 
-    let b64 = base64::engine::general_purpose::STANDARD.encode(vk_bytes);
+/// The proving key file, streamed: `206` with `Content-Range` when resuming a `Range`.
+async fn get_pk(
+    State(state): State<AppState>,
+    Extension(caller): Extension<Caller>,
+    Query(params): Query<PkParams>,
+    headers: HeaderMap,
+) -> Result<Response, ApiError> {
+    let header = |name: axum::http::HeaderName| headers.get(name).and_then(|v| v.to_str().ok());
+    let download = service::get_pk(
+        &state,
+        &caller,
+        &params,
+        header(axum::http::header::RANGE),
+        header(axum::http::header::IF_RANGE),
+    )
+    .await?;
 
-    Ok(Json(ZkVkResponse {
-        curve: "bn254".to_string(),
-        proof_system: "groth16".to_string(),
-        vk_b64: b64,
-    }))
+    let (status, content_range, content_len) = match download.range {
+        Some((start, end)) => (StatusCode::PARTIAL_CONTENT, Some(format!("bytes {start}-{end}/{}", download.len)), end + 1 - start),
+        None => (StatusCode::OK, None, download.len),
+    };
+    let etag = format!("\"{}\"", download.key_id);
+    let mut response = (
+        status,
+        [
+            (axum::http::header::CONTENT_TYPE, "application/octet-stream".to_string()),
+            (axum::http::header::CONTENT_LENGTH, content_len.to_string()),
+            (axum::http::header::ACCEPT_RANGES, "bytes".to_string()),
+            (axum::http::header::ETAG, etag),
+        ],
+        axum::body::Body::from_stream(pk_download::stream(download).await?),
+    )
+        .into_response();
+    if let Some(content_range) = content_range {
+        response
+            .headers_mut()
+            .insert(axum::http::header::CONTENT_RANGE, content_range.parse().map_err(|_| ApiError::Internal)?);
+    }
+    Ok(response)
 }
 
-async fn verify_shard(State(_state): State<AppState>, Json(req): Json<VerifyShardRequest>) -> Result<Json<VerifyShardResponse>, ApiError> {
-    let b64 = base64::engine::general_purpose::STANDARD;
+async fn get_vk(State(state): State<AppState>, Query(params): Query<VkParams>) -> Result<Response, ApiError> {
+    let response = service::get_vk(&state, &params).await?;
+    let due = key_usage::due_keys(&state, std::slice::from_ref(&response.key_id)).await?;
+    if params.format == Some(ZkFormat::Snarkjs) {
+        return Ok(with_rotation_header(due, Json(service::snarkjs_vk(&response)?)));
+    }
+    Ok(with_rotation_header(due, Json(response)))
+}
 
-    let vk_bytes = b64.decode(req.vk_b64).map_err(|_| ApiError::BadRequest("invalid vk_b64".to_string()))?;
-    let proof_bytes = b64.decode(req.proof_b64).map_err(|_| ApiError::BadRequest("invalid proof_b64".to_string()))?;
+async fn get_vk_version(
+    State(state): State<AppState>,
+    Path(version): Path<String>,
+    Query(params): Query<VkVersionParams>,
+) -> Result<Response, ApiError> {
+    let response = service::get_vk_version(&state, &version)?;
+    let due = key_usage::due_keys(&state, std::slice::from_ref(&response.key_id)).await?;
+    if params.format == Some(ZkFormat::Snarkjs) {
+        return Ok(with_rotation_header(due, Json(service::snarkjs_vk(&response)?)));
+    }
+    Ok(with_rotation_header(due, Json(response)))
+}
 
-    let vk = deserialize_vk(&vk_bytes).map_err(|_| ApiError::BadRequest("invalid vk".to_string()))?;
-    let proof = deserialize_proof(&proof_bytes).map_err(|_| ApiError::BadRequest("invalid proof".to_string()))?;
+async fn get_solidity_verifier(State(state): State<AppState>, Query(params): Query<VkParams>) -> Result<Response, ApiError> {
+    let source = service::get_solidity_verifier(&state, &params).await?;
+    Ok(([(axum::http::header::CONTENT_TYPE, "text/plain; charset=utf-8")], source).into_response())
+}
 
-    // Commitment is stored as hex-encoded compressed field element bytes.
-    let commitment_bytes = hex::decode(req.public_shard_commitment_hex)
-        .map_err(|_| ApiError::BadRequest("invalid commitment hex".to_string()))?;
-    let commitment = Fr::deserialize_compressed(&commitment_bytes[..])
-        .map_err(|_| ApiError::BadRequest("invalid commitment bytes".to_string()))?;
+async fn get_zk_schema(
+    State(state): State<AppState>,
+    Query(params): Query<SchemaParams>,
+) -> Result<Json<ZkSchemaResponse>, ApiError> {
+    Ok(Json(service::get_zk_schema(&state, &params).await?))
+}
 
-    let stats = ShardStats {
-        sum_glucose_by_bucket: req.public_sum_glucose_by_bucket,
-        count_by_bucket: req.public_count_by_bucket,
-    };
+/// `202` with the job's endpoints; the report is read back with `GET`.
+async fn verify_dataset(
+    State(state): State<AppState>,
+    Extension(caller): Extension<Caller>,
+    Path(id): Path<Uuid>,
+) -> Result<Response, ApiError> {
+    let pending = service::verify_dataset(&state, &caller, id).await?;
+    Ok((StatusCode::ACCEPTED, Json(pending)).into_response())
+}
+
+/// `200` with the latest report, or `202` while a re-verification is queued or running.
+async fn get_dataset_reverification(
+    State(state): State<AppState>,
+    Extension(caller): Extension<Caller>,
+    Path(id): Path<Uuid>,
+) -> Result<Response, ApiError> {
+    Ok(match service::get_dataset_reverification(&state, &caller, id).await? {
+        DatasetReverificationOutcome::Finished(report) => Json(report).into_response(),
+        DatasetReverificationOutcome::Pending(pending) => (StatusCode::ACCEPTED, Json(pending)).into_response(),
+    })
+}
+
+async fn get_dataset_reverification_events(
+    State(state): State<AppState>,
+    Extension(caller): Extension<Caller>,
+    Path(id): Path<Uuid>,
+) -> Result<Response, ApiError> {
+    Ok(reverify::events(&state, &caller, id).await?.into_response())
+}
 
-    let ok = verify_shard_proof(&vk, &proof, commitment, &stats).is_ok();
+async fn verify_shard(
+    Extension(caller): Extension<Caller>,
+    Json(req): Json<VerifyShardRequest>,
+) -> Result<Json<VerifyShardResponse>, ApiError> {
+    Ok(Json(service::verify_shard(&caller, req)?))
+}
 
-    Ok(Json(VerifyShardResponse { ok }))
+async fn verify_shards(
+    State(state): State<AppState>,
+    Extension(caller): Extension<Caller>,
+    Extension(cancel): Extension<Cancellation>,
+    Json(req): Json<VerifyShardsRequest>,
+) -> Result<Json<VerifyShardsResponse>, ApiError> {
+    Ok(Json(service::verify_shards(&state, &caller, &cancel, req).await?))
 }
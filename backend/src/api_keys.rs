@@ -0,0 +1,127 @@
+//! Issuing, listing and revoking API keys (admin only).
+//!
+//! An issued key is returned once, in the response that creates it; the ledger keeps its SHA-256
+//! and its fingerprint (`key_id`, as in the audit chain). Issue and revocation are recorded in
+//! the audit chain. See `auth` for how presented keys resolve.
+
+use crate::auth::{self, Caller, Role, Scope};
+use crate::db::{ApiKeyRow, NewApiKey};
+use crate::errors::ApiError;
+use crate::models::{ApiKeyCreateRequest, ApiKeyCreateResponse, ApiKeyInfo, ApiKeyListResponse, ApiKeyRevokeResponse};
+use crate::state::AppState;
+
+const MAX_NAME_LEN: usize = 100;
+
+fn info(row: ApiKeyRow) -> Result<ApiKeyInfo, ApiError> {
+    Ok(ApiKeyInfo {
+        key_id: row.key_id,
+        name: row.name,
+        role: Role::parse(&row.role).ok_or(ApiError::Internal)?,
+        scopes: row
+            .scopes
+            .map(|scopes| scopes.iter().map(|s| Scope::parse(s).ok_or(ApiError::Internal)).collect())
+            .transpose()?,
+        created_by: row.created_by,
+        created_at: row.created_at,
+        revoked_at: row.revoked_at,
+    })
+}
+
+fn check_request(req: &ApiKeyCreateRequest) -> Result<(), ApiError> {
+    let name = req.name.trim();
+    if name.is_empty() || name.len() > MAX_NAME_LEN {
+        return Err(ApiError::BadRequest(format!("name must be 1 to {MAX_NAME_LEN} characters")));
+    }
+    match &req.scopes {
+        Some(scopes) if scopes.is_empty() => Err(ApiError::BadRequest(
+            "scopes must not be empty; omit it for a key with every scope of its role".to_string(),
+        )),
+        Some(scopes) if scopes.contains(&Scope::QueriesApprove) && !req.role.satisfies(Role::Approver) => Err(
+            ApiError::BadRequest("scope queries:approve needs the approver or admin role".to_string()),
+        ),
+        _ => Ok(()),
+    }
+}
+
+/// Issue a new key with `req`'s role and scopes.
+pub async fn issue(state: &AppState, caller: &Caller, req: &ApiKeyCreateRequest) -> Result<ApiKeyCreateResponse, ApiError> {
+    caller.require(Role::Admin)?;
+    check_request(req)?;
+
+    let key = auth::generate_key();
+    let key_id = auth::key_id(&key);
+    let scopes: Option<Vec<String>> = req.scopes.as_ref().map(|s| s.iter().map(|s| s.name().to_string()).collect());
+    let created_at = state
+        .store
+        .insert_api_key(&NewApiKey {
+            key_id: &key_id,
+            key_hash: &auth::key_hash(&key),
+            name: req.name.trim(),
+            role: req.role.name(),
+            scopes: scopes.as_deref(),
+            created_by: &caller.key_id,
+        })
+        .await?;
+    let audit_entry_hash = state
+        .store
+        .append_audit(
+            None,
+            "api_key_issued",
+            &serde_json::json!({
+                "key_id": key_id,
+                "name": req.name.trim(),
+                "role": req.role,
+                "scopes": req.scopes,
+                "issued_by": caller.key_id,
+            }),
+        )
+        .await?;
+
+    Ok(ApiKeyCreateResponse {
+        key,
+        api_key: ApiKeyInfo {
+            key_id,
+            name: req.name.trim().to_string(),
+            role: req.role,
+            scopes: req.scopes.clone(),
+            created_by: caller.key_id.clone(),
+            created_at,
+            revoked_at: None,
+        },
+        audit_entry_hash,
+    })
+}
+
+pub async fn list(state: &AppState, caller: &Caller) -> Result<ApiKeyListResponse, ApiError> {
+    caller.require(Role::Admin)?;
+
+    let keys = state.store.list_api_keys().await?.into_iter().map(info).collect::<Result<_, _>>()?;
+    Ok(ApiKeyListResponse { keys })
+}
+
+/// Revoke an issued key (404 if there is no such unrevoked key). Environment keys can't be revoked
+/// here.
+pub async fn revoke(state: &AppState, caller: &Caller, key_id: &str) -> Result<ApiKeyRevokeResponse, ApiError> {
+    caller.require(Role::Admin)?;
+
+    if !state.store.revoke_api_key(key_id).await? {
+        return Err(ApiError::NotFound("no such unrevoked issued key".to_string()));
+    }
+    let audit_entry_hash = state
+        .store
+        .append_audit(None, "api_key_revoked", &serde_json::json!({ "key_id": key_id, "revoked_by": caller.key_id }))
+        .await?;
+
+    let api_key = state
+        .store
+        .list_api_keys()
+        .await?
+        .into_iter()
+        .find(|row| row.key_id == key_id)
+        .ok_or(ApiError::Internal)
+        .and_then(info)?;
+    Ok(ApiKeyRevokeResponse {
+        api_key,
+        audit_entry_hash,
+    })
+}
@@ -0,0 +1,308 @@
+//! Long-term archives of retired datasets.
+//!
+//! An archive is everything needed to re-verify one dataset with no ledger to ask: JSONL with a
+//! `header` line, a `dataset` line (parameters, manifest, dataset commitment and the BN254
+//! verifying key), one `shard` line per shard (commitment, public inputs and proof, in the shape of
+//! `GET /api/v1/datasets/:id/shards/export`), the dataset's `audit` entries, an `attestation` by
+//! the archiving instance, and a `signature` line: Ed25519 over every byte before it, with the
+//! export signing key (see `export`).
+//!
+//! Audit entries carry `created_at` and `details_json` exactly as hashed, so each `entry_hash` can
+//! be recomputed from its line; the excerpt does not chain on its own, as other datasets' entries
+//! sit between them. The attestation states that every shard proof verified and the dataset
+//! commitment recomputed when the archive was written. `ledger-verify --archive` checks all of it.
+//!
+//! When a ready dataset is deleted its archive is written to `ARCHIVE_DIR` (default
+//! `data/archive`) first, and its SHA-256 recorded in the `dataset_deleted` audit entry; set
+//! `ARCHIVE_RETIRED_DATASETS=false` to delete without archiving. Admins can fetch the archive of a
+//! live dataset, or the stored one of a deleted dataset, at `GET /api/v1/datasets/:id/archive`.
+
+use crate::db::AuditRow;
+use crate::deadline::Cancellation;
+use crate::errors::ApiError;
+use crate::export::{self, ImportCandidate};
+use crate::state::AppState;
+use base64::Engine;
+use chrono::{DateTime, Utc};
+use ring::signature::KeyPair;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::path::PathBuf;
+use uuid::Uuid;
+use zk_proofs::types::ShardStats;
+
+pub const ARCHIVE_FORMAT: &str = "phl-dataset-archive";
+const ARCHIVE_VERSION: u32 = 1;
+
+/// Audit entries read per page while collecting the excerpt.
+const AUDIT_PAGE: u64 = 1000;
+
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ArchiveLine<'a> {
+    Header {
+        format: &'a str,
+        version: u32,
+        archived_at: DateTime<Utc>,
+        backend_version: &'a str,
+    },
+    Dataset {
+        dataset_id: Uuid,
+        created_at: DateTime<Utc>,
+        dataset_size: u64,
+        shard_size: u64,
+        field_set: &'a str,
+        chain_hash: &'a str,
+        sha256_commitment: bool,
+        num_buckets: usize,
+        age_buckets: &'a [(u8, u8)],
+        #[serde(skip_serializing_if = "Option::is_none")]
+        window_shards: Option<u64>,
+        dataset_commitment_hex: &'a str,
+        manifest: Option<&'a serde_json::Value>,
+        /// Signer public key of the export the dataset was imported from; `None` if proven here.
+        imported_from: Option<&'a str>,
+        curve: &'a str,
+        /// Hex SHA-256 of the verifying key.
+        key_id: &'a str,
+        vk_b64: &'a str,
+    },
+    Shard {
+        shard_index: u64,
+        shard_commitment_hex: &'a str,
+        proof_b64: &'a str,
+        #[serde(flatten)]
+        stats: &'a ShardStats,
+    },
+    Audit {
+        seq: u64,
+        /// RFC 3339, as hashed.
+        created_at: String,
+        event: &'a str,
+        /// The details as hashed.
+        details_json: String,
+        prev_hash: &'a str,
+        entry_hash: &'a str,
+    },
+    Attestation {
+        dataset_id: Uuid,
+        dataset_commitment_hex: &'a str,
+        key_id: &'a str,
+        shards_verified: u64,
+        audit_entries: u64,
+        archived_by: &'a str,
+        reason: &'a str,
+    },
+    Signature {
+        alg: &'a str,
+        public_key_hex: String,
+        signature_hex: String,
+    },
+}
+
+fn push_line(out: &mut Vec<u8>, line: &ArchiveLine) -> Result<(), ApiError> {
+    serde_json::to_writer(&mut *out, line).map_err(|_| ApiError::Internal)?;
+    out.push(b'\n');
+    Ok(())
+}
+
+fn archive_dir(state: &AppState) -> PathBuf {
+    std::env::var("ARCHIVE_DIR")
+        .ok()
+        .filter(|v| !v.trim().is_empty())
+        .map(PathBuf::from)
+        .unwrap_or_else(|| state.data_dir.join("archive"))
+}
+
+fn archive_path(state: &AppState, dataset_id: Uuid) -> PathBuf {
+    archive_dir(state).join(format!("{dataset_id}.jsonl"))
+}
+
+/// Whether ready datasets are archived before they are deleted (`ARCHIVE_RETIRED_DATASETS`,
+/// default true).
+pub fn archive_on_delete() -> bool {
+    std::env::var("ARCHIVE_RETIRED_DATASETS")
+        .map(|v| !matches!(v.trim(), "0" | "false" | "no"))
+        .unwrap_or(true)
+}
+
+async fn audit_excerpt(state: &AppState, dataset_id: Uuid) -> Result<Vec<AuditRow>, ApiError> {
+    let mut entries = Vec::new();
+    loop {
+        let page = state.store.list_audit(dataset_id, entries.len() as u64, AUDIT_PAGE).await?;
+        let last = (page.len() as u64) < AUDIT_PAGE;
+        entries.extend(page);
+        if last {
+            return Ok(entries);
+        }
+    }
+}
+
+/// Build the signed archive of a ready dataset, re-verifying every shard proof and the dataset
+/// commitment first (`409` if the dataset isn't ready, `500` if it no longer verifies).
+pub async fn build(
+    state: &AppState,
+    dataset_id: Uuid,
+    archived_by: &str,
+    reason: &str,
+    cancel: &Cancellation,
+) -> Result<Vec<u8>, ApiError> {
+    let Some(dataset) = state.store.get_dataset(dataset_id).await? else {
+        return Err(ApiError::NotFound("dataset not found".to_string()));
+    };
+    let Some(commitment_hex) = dataset.commitment_hex.clone().filter(|_| dataset.status == "ready") else {
+        return Err(ApiError::Conflict("only ready datasets can be archived".to_string()));
+    };
+
+    let manifest = state.store.get_dataset_manifest(dataset_id).await?;
+    let vk_b64 = export::dataset_vk_b64(
+        state,
+        dataset_id,
+        dataset.shard_size,
+        dataset.field_set,
+        &dataset.age_buckets,
+        dataset.sha256_commitment,
+    )
+    .await?;
+    let vk_bytes = base64::engine::general_purpose::STANDARD.decode(&vk_b64).map_err(|_| ApiError::Internal)?;
+    let key_id = hex::encode(Sha256::digest(&vk_bytes));
+    let shards = state
+        .store
+        .list_shards(dataset_id, 0..dataset.shards_total(), 0, dataset.shards_total(), true)
+        .await?
+        .into_iter()
+        .map(|(shard_index, commitment_hex, stats, _, proof_b64)| (shard_index, commitment_hex, stats, proof_b64.unwrap_or_default()))
+        .collect();
+
+    let candidate = ImportCandidate {
+        dataset_id,
+        dataset_size: dataset.dataset_size,
+        shard_size: dataset.shard_size,
+        field_set: dataset.field_set,
+        chain_hash: dataset.chain_hash,
+        sha256_commitment: dataset.sha256_commitment,
+        num_buckets: dataset.age_buckets.num_buckets() as u64,
+        age_buckets: dataset.age_buckets.clone(),
+        window_shards: dataset.window_shards,
+        consent_scope: dataset.consent_scope.clone(),
+        requires_approval: dataset.requires_approval,
+        release_limit: dataset.release_limit,
+        dataset_commitment_hex: commitment_hex,
+        manifest,
+        vk_b64,
+        shards,
+    };
+    let task_cancel = cancel.clone();
+    let (candidate, verified) = tokio::task::spawn_blocking(move || {
+        let verified = export::verify_dataset(&candidate, &task_cancel);
+        (candidate, verified)
+    })
+    .await
+    .map_err(|_| ApiError::Internal)?;
+    cancel.check()?;
+    let shards_verified = verified.map_err(|e| {
+        tracing::error!(%dataset_id, error = %e, "dataset does not verify; not archiving it");
+        ApiError::Internal
+    })?;
+    let audit = audit_excerpt(state, dataset_id).await?;
+
+    let mut out = Vec::new();
+    push_line(
+        &mut out,
+        &ArchiveLine::Header {
+            format: ARCHIVE_FORMAT,
+            version: ARCHIVE_VERSION,
+            archived_at: Utc::now(),
+            backend_version: env!("CARGO_PKG_VERSION"),
+        },
+    )?;
+    push_line(
+        &mut out,
+        &ArchiveLine::Dataset {
+            dataset_id,
+            created_at: dataset.created_at,
+            dataset_size: candidate.dataset_size,
+            shard_size: candidate.shard_size,
+            field_set: candidate.field_set.name(),
+            chain_hash: candidate.chain_hash.name(),
+            sha256_commitment: candidate.sha256_commitment,
+            num_buckets: candidate.age_buckets.num_buckets(),
+            age_buckets: candidate.age_buckets.bounds(),
+            window_shards: candidate.window_shards,
+            dataset_commitment_hex: &candidate.dataset_commitment_hex,
+            manifest: candidate.manifest.as_ref(),
+            imported_from: dataset.imported_from.as_deref(),
+            curve: "bn254",
+            key_id: &key_id,
+            vk_b64: &candidate.vk_b64,
+        },
+    )?;
+    for (shard_index, shard_commitment_hex, stats, proof_b64) in &candidate.shards {
+        push_line(
+            &mut out,
+            &ArchiveLine::Shard {
+                shard_index: *shard_index,
+                shard_commitment_hex,
+                proof_b64,
+                stats,
+            },
+        )?;
+    }
+    for entry in &audit {
+        push_line(
+            &mut out,
+            &ArchiveLine::Audit {
+                seq: entry.seq,
+                created_at: entry.created_at.to_rfc3339(),
+                event: &entry.event,
+                details_json: entry.details.to_string(),
+                prev_hash: &entry.prev_hash,
+                entry_hash: &entry.entry_hash,
+            },
+        )?;
+    }
+    push_line(
+        &mut out,
+        &ArchiveLine::Attestation {
+            dataset_id,
+            dataset_commitment_hex: &candidate.dataset_commitment_hex,
+            key_id: &key_id,
+            shards_verified,
+            audit_entries: audit.len() as u64,
+            archived_by,
+            reason,
+        },
+    )?;
+
+    let key = &state.signing_key;
+    let signature = key.sign(&out);
+    push_line(
+        &mut out,
+        &ArchiveLine::Signature {
+            alg: "ed25519",
+            public_key_hex: hex::encode(key.public_key().as_ref()),
+            signature_hex: hex::encode(signature.as_ref()),
+        },
+    )?;
+
+    Ok(out)
+}
+
+/// Build a dataset's archive and store it under `ARCHIVE_DIR`. Returns its hex SHA-256.
+pub async fn write(state: &AppState, dataset_id: Uuid, archived_by: &str, reason: &str) -> Result<String, ApiError> {
+    let archive = build(state, dataset_id, archived_by, reason, &Cancellation::none()).await?;
+    let path = archive_path(state, dataset_id);
+    let tmp = path.with_extension("jsonl.tmp");
+    std::fs::create_dir_all(archive_dir(state)).map_err(|_| ApiError::Internal)?;
+    std::fs::write(&tmp, &archive).map_err(|_| ApiError::Internal)?;
+    std::fs::rename(&tmp, &path).map_err(|_| ApiError::Internal)?;
+
+    let sha256 = hex::encode(Sha256::digest(&archive));
+    tracing::info!(%dataset_id, path = %path.display(), sha256, "dataset archived");
+    Ok(sha256)
+}
+
+/// The stored archive of a deleted dataset, if one was written.
+pub fn read_stored(state: &AppState, dataset_id: Uuid) -> Option<Vec<u8>> {
+    std::fs::read(archive_path(state, dataset_id)).ok()
+}
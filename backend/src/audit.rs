@@ -0,0 +1,90 @@
+//! Background integrity audit of stored proofs.
+//!
+//! Proofs are stored content-addressed (`proof_blobs`, keyed by the SHA-256 of the proof bytes),
+//! so identical proofs written by re-proving, imports or mirroring share one blob (a file under
+//! `data/proofs/` with the SQLite ledger, see `proof_files`). The audit recomputes every blob's
+//! hash, reports blobs whose bytes no longer match their address (bit rot, tampering or a missing
+//! file; each is recorded in the audit log with the shards it backs), and removes blobs no shard
+//! refers to. It runs every `PROOF_AUDIT_INTERVAL_SECS` (default 3600, `0` disables) and on
+//! demand via `POST /api/v1/admin/proof-blobs/audit`.
+
+use crate::db;
+use crate::errors::ApiError;
+use crate::models::{CorruptProofBlob, ProofBlobAuditReport};
+use crate::state::AppState;
+use chrono::Utc;
+use std::time::Duration;
+
+const DEFAULT_INTERVAL_SECS: u64 = 3600;
+
+/// Blobs read per page.
+const PAGE: u64 = 200;
+
+fn audit_interval() -> Option<Duration> {
+    let secs = std::env::var("PROOF_AUDIT_INTERVAL_SECS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(DEFAULT_INTERVAL_SECS);
+    (secs > 0).then(|| Duration::from_secs(secs))
+}
+
+/// Check every proof blob against its hash and drop unreferenced blobs.
+pub async fn audit_proof_blobs(state: &AppState) -> Result<ProofBlobAuditReport, ApiError> {
+    let mut report = ProofBlobAuditReport {
+        ran_at: Utc::now(),
+        blobs_checked: 0,
+        corrupt: Vec::new(),
+        orphans_removed: state.store.delete_orphan_proof_blobs().await?,
+    };
+
+    let mut after = String::new();
+    loop {
+        let page = state.store.list_proof_blobs(&after, PAGE).await?;
+        let Some((last, _)) = page.last() else { break };
+        after = last.clone();
+
+        for (hash, proof_b64) in page {
+            report.blobs_checked += 1;
+            if db::proof_blob_hash(&proof_b64).is_ok_and(|actual| actual == hash) {
+                continue;
+            }
+
+            let shards = state.store.shards_with_proof(&hash).await?;
+            tracing::error!(%hash, shards = shards.len(), "proof blob does not match its hash");
+            for group in shards.chunk_by(|a, b| a.0 == b.0) {
+                let indices: Vec<u64> = group.iter().map(|(_, i)| *i).collect();
+                state.store.append_audit(
+                    Some(group[0].0),
+                    "proof_blob_corrupt",
+                    &serde_json::json!({ "proof_hash": hash, "shard_indices": indices }),
+                )
+                .await?;
+            }
+            report.corrupt.push(CorruptProofBlob {
+                hash,
+                shards: shards.len() as u64,
+            });
+        }
+    }
+
+    tracing::info!(
+        checked = report.blobs_checked,
+        corrupt = report.corrupt.len(),
+        orphans_removed = report.orphans_removed,
+        "proof blob audit finished"
+    );
+    state.set_proof_blob_audit(report.clone());
+    Ok(report)
+}
+
+/// Background loop: periodically audit proof blobs.
+pub async fn run(state: AppState) {
+    let Some(every) = audit_interval() else { return };
+    let mut interval = tokio::time::interval(every);
+    loop {
+        interval.tick().await;
+        if let Err(e) = audit_proof_blobs(&state).await {
+            tracing::warn!(error = %e, "proof blob audit failed");
+        }
+    }
+}
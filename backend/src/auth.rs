@@ -0,0 +1,191 @@
+//! Caller identity, roles and scopes.
+//!
+//! Keys come from two places:
+//! - The environment: `API_KEY`, the instance admin key (defaults to `dev-secret-key` for local
+//!   development), and optional extra keys in `API_KEYS` as `key=role` pairs, comma separated
+//!   (roles: `researcher`, `approver`, `admin`). These bootstrap a deployment.
+//! - The `api_keys` table, filled by admins through `POST /api/v1/admin/keys`. Only each key's
+//!   SHA-256 is stored; the key itself is shown once, when issued. Issued keys may be limited to
+//!   `scopes` within their role, and are revoked rather than deleted so the audit chain's key
+//!   fingerprints stay resolvable.
+//!
+//! Callers may present an OpenID Connect bearer token instead of a key; see `oidc`.
+
+use crate::errors::ApiError;
+use crate::state::AppState;
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+
+/// Prefix of issued keys, so leaked ones are easy to scan for.
+const ISSUED_KEY_PREFIX: &str = "phl_";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+    /// Can create datasets and run queries.
+    Researcher,
+    /// Researcher rights plus approving queries against flagged datasets.
+    Approver,
+    /// Everything.
+    Admin,
+}
+
+impl Role {
+    pub fn name(self) -> &'static str {
+        match self {
+            Role::Researcher => "researcher",
+            Role::Approver => "approver",
+            Role::Admin => "admin",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.trim() {
+            "researcher" => Some(Role::Researcher),
+            "approver" => Some(Role::Approver),
+            "admin" => Some(Role::Admin),
+            _ => None,
+        }
+    }
+
+    pub fn satisfies(self, required: Role) -> bool {
+        match required {
+            Role::Researcher => true,
+            Role::Approver => matches!(self, Role::Approver | Role::Admin),
+            Role::Admin => self == Role::Admin,
+        }
+    }
+}
+
+/// An operation an issued key can be limited to. Scopes narrow a role; they never widen it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum Scope {
+    /// Create datasets (generated, imported, uploaded, streamed or federated).
+    #[serde(rename = "datasets:create")]
+    DatasetsCreate,
+    #[serde(rename = "queries:create")]
+    QueriesCreate,
+    /// Approve or reject held queries (approvers and admins).
+    #[serde(rename = "queries:approve")]
+    QueriesApprove,
+    /// Check proofs with `POST /api/v1/verify/shard(s)` and `POST /api/v1/verify/dataset/:id`.
+    #[serde(rename = "verify")]
+    Verify,
+}
+
+impl Scope {
+    pub const ALL: [Scope; 4] = [Scope::DatasetsCreate, Scope::QueriesCreate, Scope::QueriesApprove, Scope::Verify];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Scope::DatasetsCreate => "datasets:create",
+            Scope::QueriesCreate => "queries:create",
+            Scope::QueriesApprove => "queries:approve",
+            Scope::Verify => "verify",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|scope| scope.name() == s)
+    }
+}
+
+/// Authenticated caller, inserted into request extensions by the auth middleware.
+#[derive(Debug, Clone)]
+pub struct Caller {
+    /// Non-secret key fingerprint, safe to log and store in the audit chain.
+    pub key_id: String,
+    pub role: Role,
+    /// Scopes an issued key is limited to; `None` allows everything the role does.
+    pub scopes: Option<Vec<Scope>>,
+}
+
+impl Caller {
+    pub fn require(&self, role: Role) -> Result<(), ApiError> {
+        if self.role.satisfies(role) {
+            Ok(())
+        } else {
+            Err(ApiError::Forbidden(format!("requires role {role:?}")))
+        }
+    }
+
+    pub fn require_scope(&self, scope: Scope) -> Result<(), ApiError> {
+        match &self.scopes {
+            Some(scopes) if !scopes.contains(&scope) => {
+                Err(ApiError::Forbidden(format!("key lacks scope {}", scope.name())))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Fingerprint of a key: first 8 bytes of its SHA-256, hex encoded.
+pub fn key_id(key: &str) -> String {
+    hex::encode(&Sha256::digest(key.as_bytes())[..8])
+}
+
+/// Hex SHA-256 of a key, as stored for issued keys.
+pub fn key_hash(key: &str) -> String {
+    hex::encode(Sha256::digest(key.as_bytes()))
+}
+
+/// A new random key to issue: the prefix and 32 bytes from the OS RNG, hex encoded.
+pub fn generate_key() -> String {
+    let mut bytes = [0u8; 32];
+    rand::rngs::OsRng.fill_bytes(&mut bytes);
+    format!("{ISSUED_KEY_PREFIX}{}", hex::encode(bytes))
+}
+
+/// Resolve a presented API key to a caller: an environment key, else an unrevoked issued key.
+pub async fn resolve_caller(state: &AppState, provided_key: &str) -> Result<Option<Caller>, ApiError> {
+    if let Some(caller) = env_caller(provided_key) {
+        return Ok(Some(caller));
+    }
+
+    let Some(row) = state.store.api_key_by_hash(&key_hash(provided_key)).await? else {
+        return Ok(None);
+    };
+    if row.revoked_at.is_some() {
+        return Ok(None);
+    }
+    let role = Role::parse(&row.role).ok_or(ApiError::Internal)?;
+    let scopes = row
+        .scopes
+        .map(|scopes| scopes.iter().map(|s| Scope::parse(s).ok_or(ApiError::Internal)).collect())
+        .transpose()?;
+    Ok(Some(Caller {
+        key_id: row.key_id,
+        role,
+        scopes,
+    }))
+}
+
+fn env_caller(provided_key: &str) -> Option<Caller> {
+    // In production, this should be a strong secret from environment.
+    let admin_key = std::env::var("API_KEY").unwrap_or_else(|_| "dev-secret-key".to_string());
+    if provided_key == admin_key {
+        return Some(Caller {
+            key_id: key_id(provided_key),
+            role: Role::Admin,
+            scopes: None,
+        });
+    }
+
+    let extra = std::env::var("API_KEYS").unwrap_or_default();
+    for entry in extra.split(',').filter(|e| !e.trim().is_empty()) {
+        let Some((key, role)) = entry.split_once('=') else {
+            continue;
+        };
+        if key.trim() == provided_key
+            && let Some(role) = Role::parse(role)
+        {
+            return Some(Caller {
+                key_id: key_id(provided_key),
+                role,
+                scopes: None,
+            });
+        }
+    }
+
+    None
+}
@@ -0,0 +1,295 @@
+//! Backup and restore of the ledger.
+//!
+//! A backup is a directory holding a consistent snapshot of the SQLite DB (`VACUUM INTO`, so it
+//! can be taken while the server runs), the proof files it refers to (`proofs/`), the Groth16 key
+//! files, and `manifest.json` listing every file's SHA-256. Backups from before proof files hold
+//! the proofs in the DB; startup moves them out after a restore.
+//!
+//! Restore never trusts the backup: it checks every file hash, stages a copy, re-verifies a random
+//! sample of shard proofs per dataset, recomputes every dataset commitment from its shard
+//! commitments and walks the audit hash chain. Only a healthy copy replaces the live DB (the old
+//! files are moved aside, not deleted). Restore runs from the CLI with the server stopped.
+//!
+//! Both cover the SQLite ledger only; a Postgres ledger (`DATABASE_URL`) is backed up with its own
+//! tools.
+
+use crate::chain::dataset_commitment_hex;
+use crate::dataset::parse_field_hex;
+use crate::db;
+use crate::errors::ApiError;
+use crate::proof_files::{ProofFiles, PROOFS_DIR};
+use crate::state::key_paths;
+use crate::store::{LedgerStore, SqliteStore};
+use base64::Engine;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use zk_proofs::groth16::{deserialize_proof, deserialize_vk, verify_shard_proof};
+
+pub const MANIFEST_FILE: &str = "manifest.json";
+const BACKUP_VERSION: u32 = 1;
+const DB_FILE: &str = "ledger.sqlite";
+const KEYS_DIR: &str = "keys";
+
+/// Shard proofs re-verified per dataset on restore unless overridden.
+pub const DEFAULT_VERIFY_SAMPLE: usize = 16;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BackupFile {
+    /// Relative to the backup directory, `/`-separated.
+    pub path: String,
+    pub sha256_hex: String,
+    pub bytes: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BackupManifest {
+    pub backup_version: u32,
+    pub created_at: DateTime<Utc>,
+    pub backend_version: String,
+    pub files: Vec<BackupFile>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct RestoreReport {
+    pub healthy: bool,
+    /// `false` for a verify-only run, or when problems were found.
+    pub restored: bool,
+    pub files_checked: u64,
+    pub datasets_checked: u64,
+    pub proofs_checked: u64,
+    pub audit_entries_checked: u64,
+    pub problems: Vec<String>,
+}
+
+/// Default location for backups taken through the API.
+pub fn backups_dir(data_dir: &Path) -> PathBuf {
+    data_dir.join("backups")
+}
+
+fn sha256_file(path: &Path) -> Result<(String, u64), ApiError> {
+    let mut file = File::open(path).map_err(|_| ApiError::Internal)?;
+    let mut hasher = Sha256::new();
+    let bytes = std::io::copy(&mut file, &mut hasher).map_err(|_| ApiError::Internal)?;
+    Ok((hex::encode(hasher.finalize()), bytes))
+}
+
+/// Snapshot the DB, proof files and key files from `data_dir` into the new directory `dest`.
+pub async fn create_backup(db: &db::Db, proofs: &ProofFiles, data_dir: &Path, dest: &Path) -> Result<BackupManifest, ApiError> {
+    if dest.exists() {
+        return Err(ApiError::Conflict(format!("backup destination {} already exists", dest.display())));
+    }
+    std::fs::create_dir_all(dest.join(KEYS_DIR)).map_err(|_| ApiError::Internal)?;
+
+    // No proof file is added or removed until they are all copied, so the snapshot's blobs are
+    // exactly the files copied (plus possibly unreferenced ones).
+    let guard = proofs.lock().await;
+
+    sqlx::query("VACUUM INTO ?")
+        .bind(dest.join(DB_FILE).to_string_lossy().to_string())
+        .execute(db)
+        .await
+        .map_err(|_| ApiError::Internal)?;
+
+    let data_dir = data_dir.to_path_buf();
+    let dest = dest.to_path_buf();
+    let proofs = proofs.clone();
+    tokio::task::spawn_blocking(move || {
+        let mut rel_paths = vec![DB_FILE.to_string()];
+
+        for path in proofs.list_rel_paths() {
+            let target = dest.join(&path);
+            std::fs::create_dir_all(target.parent().ok_or(ApiError::Internal)?).map_err(|_| ApiError::Internal)?;
+            std::fs::copy(data_dir.join(&path), target).map_err(|_| ApiError::Internal)?;
+            rel_paths.push(path);
+        }
+        drop(guard);
+
+        if let Ok(entries) = std::fs::read_dir(data_dir.join(KEYS_DIR)) {
+            let mut names: Vec<String> = entries
+                .flatten()
+                .filter(|e| e.path().is_file())
+                .map(|e| e.file_name().to_string_lossy().to_string())
+                .collect();
+            names.sort();
+            for name in names {
+                std::fs::copy(data_dir.join(KEYS_DIR).join(&name), dest.join(KEYS_DIR).join(&name))
+                    .map_err(|_| ApiError::Internal)?;
+                rel_paths.push(format!("{KEYS_DIR}/{name}"));
+            }
+        }
+
+        let mut files = Vec::with_capacity(rel_paths.len());
+        for path in rel_paths {
+            let (sha256_hex, bytes) = sha256_file(&dest.join(&path))?;
+            files.push(BackupFile { path, sha256_hex, bytes });
+        }
+
+        let manifest = BackupManifest {
+            backup_version: BACKUP_VERSION,
+            created_at: Utc::now(),
+            backend_version: env!("CARGO_PKG_VERSION").to_string(),
+            files,
+        };
+        let json = serde_json::to_vec_pretty(&manifest).map_err(|_| ApiError::Internal)?;
+        std::fs::write(dest.join(MANIFEST_FILE), json).map_err(|_| ApiError::Internal)?;
+
+        Ok(manifest)
+    })
+    .await
+    .map_err(|_| ApiError::Internal)?
+}
+
+/// Check the backup at `src` and, unless `verify_only`, swap it in as the live state of
+/// `data_dir`. `sample` shard proofs per dataset are re-verified.
+pub async fn restore_backup(src: &Path, data_dir: &Path, sample: usize, verify_only: bool) -> Result<RestoreReport, ApiError> {
+    let manifest_bytes = std::fs::read(src.join(MANIFEST_FILE))
+        .map_err(|_| ApiError::BadRequest(format!("no {MANIFEST_FILE} in {}", src.display())))?;
+    let manifest: BackupManifest = serde_json::from_slice(&manifest_bytes)
+        .map_err(|e| ApiError::BadRequest(format!("invalid backup manifest: {e}")))?;
+    if manifest.backup_version != BACKUP_VERSION {
+        return Err(ApiError::BadRequest(format!("unsupported backup_version {}", manifest.backup_version)));
+    }
+
+    let mut report = RestoreReport::default();
+
+    // 1) File integrity.
+    for file in &manifest.files {
+        if file.path.split('/').any(|part| part == ".." || part.is_empty()) {
+            return Err(ApiError::BadRequest(format!("invalid path in manifest: {}", file.path)));
+        }
+        report.files_checked += 1;
+        match sha256_file(&src.join(&file.path)) {
+            Ok((hash, _)) if hash == file.sha256_hex => {}
+            Ok(_) => report.problems.push(format!("{}: sha256 mismatch", file.path)),
+            Err(_) => report.problems.push(format!("{}: missing or unreadable", file.path)),
+        }
+    }
+    if !manifest.files.iter().any(|f| f.path == DB_FILE) {
+        report.problems.push(format!("{DB_FILE} not listed in manifest"));
+    }
+    if !report.problems.is_empty() {
+        return Ok(report);
+    }
+
+    // 2) Stage a copy so checks and the final swap use exactly the verified bytes.
+    let staging = data_dir.join("restore-staging");
+    let _ = std::fs::remove_dir_all(&staging);
+    std::fs::create_dir_all(staging.join(KEYS_DIR)).map_err(|_| ApiError::Internal)?;
+    for file in &manifest.files {
+        let target = staging.join(&file.path);
+        std::fs::create_dir_all(target.parent().ok_or(ApiError::Internal)?).map_err(|_| ApiError::Internal)?;
+        std::fs::copy(src.join(&file.path), target).map_err(|_| ApiError::Internal)?;
+    }
+
+    let staged_db = db::connect(&format!("sqlite:{}", staging.join(DB_FILE).to_string_lossy())).await?;
+    // Bring an older backup's schema up to date, as startup would.
+    db::init_schema(&staged_db).await?;
+    let staged_store = SqliteStore::new(staged_db.clone(), ProofFiles::new(&staging));
+    let checked = verify_ledger(&staged_store, &staging.join(KEYS_DIR), sample, &mut report).await;
+    staged_db.close().await;
+    checked?;
+
+    report.healthy = report.problems.is_empty();
+    if !report.healthy || verify_only {
+        let _ = std::fs::remove_dir_all(&staging);
+        return Ok(report);
+    }
+
+    // 3) Swap in. The previous files are kept under `pre-restore-<timestamp>/`.
+    let aside = data_dir.join(format!("pre-restore-{}", Utc::now().format("%Y%m%dT%H%M%SZ")));
+    std::fs::create_dir_all(&aside).map_err(|_| ApiError::Internal)?;
+    for name in [DB_FILE.to_string(), format!("{DB_FILE}-wal"), format!("{DB_FILE}-shm")] {
+        let live = data_dir.join(&name);
+        if live.exists() {
+            std::fs::rename(&live, aside.join(&name)).map_err(|_| ApiError::Internal)?;
+        }
+    }
+    for dir in [KEYS_DIR, PROOFS_DIR] {
+        if data_dir.join(dir).exists() {
+            std::fs::rename(data_dir.join(dir), aside.join(dir)).map_err(|_| ApiError::Internal)?;
+        }
+    }
+    std::fs::rename(staging.join(DB_FILE), data_dir.join(DB_FILE)).map_err(|_| ApiError::Internal)?;
+    std::fs::rename(staging.join(KEYS_DIR), data_dir.join(KEYS_DIR)).map_err(|_| ApiError::Internal)?;
+    if staging.join(PROOFS_DIR).exists() {
+        std::fs::rename(staging.join(PROOFS_DIR), data_dir.join(PROOFS_DIR)).map_err(|_| ApiError::Internal)?;
+    }
+    let _ = std::fs::remove_dir_all(&staging);
+
+    report.restored = true;
+    Ok(report)
+}
+
+/// Re-verify commitment chains, a sample of proofs and the audit chain of a staged ledger.
+async fn verify_ledger(store: &dyn LedgerStore, keys_dir: &Path, sample: usize, report: &mut RestoreReport) -> Result<(), ApiError> {
+    use rand::seq::index;
+
+    for dataset_id in store.list_dataset_ids().await? {
+        let Some(dataset) = store.get_dataset(dataset_id).await? else { continue };
+        if dataset.status != "ready" {
+            continue;
+        }
+        report.datasets_checked += 1;
+
+        let shards = store.list_shards(dataset_id, 0..dataset.shards_total(), 0, dataset.shards_total(), true).await?;
+        if shards.len() as u64 != dataset.shards_total() {
+            report.problems.push(format!(
+                "dataset {dataset_id}: {} of {} shards present",
+                shards.len(),
+                dataset.shards_total()
+            ));
+            continue;
+        }
+
+        let Some(commitments) = shards.iter().map(|s| parse_field_hex(&s.1)).collect::<Option<Vec<_>>>() else {
+            report.problems.push(format!("dataset {dataset_id}: unparsable shard commitment"));
+            continue;
+        };
+        if Some(dataset_commitment_hex(dataset.chain_hash, &commitments)?) != dataset.commitment_hex {
+            report.problems.push(format!("dataset {dataset_id}: commitment chain mismatch"));
+        }
+
+        let b64 = base64::engine::general_purpose::STANDARD;
+        let vk_bytes = match store.get_dataset_external_vk(dataset_id).await? {
+            Some(vk_b64) => b64.decode(vk_b64).ok(),
+            None => {
+                let paths = key_paths(
+                    keys_dir,
+                    dataset.shard_size as usize,
+                    dataset.field_set,
+                    &dataset.age_buckets,
+                    dataset.sha256_commitment,
+                );
+                std::fs::read(paths.1).ok()
+            }
+        };
+        let Some(vk) = vk_bytes.and_then(|b| deserialize_vk(&b).ok()) else {
+            report.problems.push(format!("dataset {dataset_id}: verifying key for shard_size {} missing", dataset.shard_size));
+            continue;
+        };
+
+        let picked = index::sample(&mut rand::thread_rng(), shards.len(), sample.min(shards.len()));
+        for i in picked.iter() {
+            let (shard_index, _, stats, _, proof_b64) = &shards[i];
+            report.proofs_checked += 1;
+            let ok = proof_b64
+                .as_deref()
+                .and_then(|p| b64.decode(p).ok())
+                .and_then(|bytes| deserialize_proof(&bytes).ok())
+                .is_some_and(|proof| verify_shard_proof(&vk, &proof, commitments[i], stats).is_ok());
+            if !ok {
+                report.problems.push(format!("dataset {dataset_id}: shard {shard_index} proof does not verify"));
+            }
+        }
+    }
+
+    match store.verify_audit_chain().await? {
+        Ok(entries) => report.audit_entries_checked = entries,
+        Err(seq) => report.problems.push(format!("audit chain broken at seq {seq}")),
+    }
+
+    Ok(())
+}
@@ -0,0 +1,100 @@
+//! Signed per-bucket record counts in the dataset manifest.
+//!
+//! When a dataset becomes ready its records per age bucket, summed over the public inputs of its
+//! proven shards (every shard proof is verified before it is stored), are added to its manifest
+//! as `bucket_counts`: the counts, the dataset commitment they were summed under, and an Ed25519
+//! signature with the export signing key (see `export`). Consumers can check a query's `count`
+//! against this frozen reference instead of re-summing shards; the counts cover every shard, so
+//! on rolling-window datasets they bound rather than equal a query's count.
+//!
+//! With `MIN_CELL_COUNT` set, the buckets `policy::suppressed_cells` withholds are committed as
+//! `null` and left out of `total`, and the threshold is signed with them as `min_count`.
+//!
+//! The signature is over the compact JSON of `counts`, its keys in the order they are listed.
+
+use crate::db;
+use crate::errors::ApiError;
+use crate::policy;
+use crate::state::AppState;
+use chrono::{DateTime, Utc};
+use ring::signature::KeyPair;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SignedBucketCounts {
+    pub counts: BucketCounts,
+    pub signature: CountsSignature,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BucketCounts {
+    pub dataset_id: Uuid,
+    pub dataset_commitment_hex: String,
+    pub shards_total: u64,
+    pub committed_at: DateTime<Utc>,
+    /// `MIN_CELL_COUNT` when the counts were committed, if set.
+    pub min_count: Option<u64>,
+    pub buckets: Vec<BucketCount>,
+    /// Records in the released buckets.
+    pub total: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BucketCount {
+    pub bucket_index: usize,
+    pub bucket_range: (u8, u8),
+    /// `null` when the bucket is withheld.
+    pub count: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CountsSignature {
+    pub alg: String,
+    pub public_key_hex: String,
+    pub signature_hex: String,
+}
+
+/// Sum a ready dataset's proven bucket counts, sign them and add them to its manifest.
+pub async fn commit(state: &AppState, dataset_id: Uuid, dataset: &db::DatasetRow, dataset_commitment_hex: &str) -> Result<(), ApiError> {
+    let Some(mut manifest) = state.store.get_dataset_manifest(dataset_id).await? else {
+        return Err(ApiError::Internal);
+    };
+
+    let shards_total = dataset.shards_total();
+    let (totals, _) = state.store.dataset_totals(dataset_id, 0..shards_total, dataset.field_set, &dataset.age_buckets).await?;
+    // The manifest is read like `/aggregates`, so it releases no more than that does.
+    let min_count = policy::min_cell_count();
+    let withheld = policy::suppressed_cells(&totals.count_by_bucket, min_count);
+    let buckets: Vec<BucketCount> = (0..dataset.age_buckets.num_buckets())
+        .map(|b| BucketCount {
+            bucket_index: b,
+            bucket_range: dataset.age_buckets.bounds()[b],
+            count: (!withheld[b]).then_some(totals.count_by_bucket[b]),
+        })
+        .collect();
+    let counts = BucketCounts {
+        dataset_id,
+        dataset_commitment_hex: dataset_commitment_hex.to_string(),
+        shards_total,
+        committed_at: Utc::now(),
+        min_count,
+        total: buckets.iter().filter_map(|b| b.count).sum(),
+        buckets,
+    };
+
+    let key = &state.signing_key;
+    let signature = key.sign(&serde_json::to_vec(&counts).map_err(|_| ApiError::Internal)?);
+    let signed = SignedBucketCounts {
+        counts,
+        signature: CountsSignature {
+            alg: "ed25519".to_string(),
+            public_key_hex: hex::encode(key.public_key().as_ref()),
+            signature_hex: hex::encode(signature.as_ref()),
+        },
+    };
+
+    let manifest_fields = manifest.as_object_mut().ok_or(ApiError::Internal)?;
+    manifest_fields.insert("bucket_counts".to_string(), serde_json::to_value(&signed).map_err(|_| ApiError::Internal)?);
+    state.store.set_dataset_manifest(dataset_id, &manifest).await
+}
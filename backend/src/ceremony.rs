@@ -0,0 +1,187 @@
+//! `ceremony` admin commands: a phase-2 trusted setup ceremony for shard keys.
+//!
+//! The coordinator exports the initial params for a shard circuit, each participant in turn runs
+//! `contribute` on the latest params file (with this binary, offline) and passes the output on,
+//! anyone can `verify` the chain against the initial file, and the coordinator `finalize`s it into
+//! the key files `ensure_keys_for` loads (see `zk_proofs::ceremony` for the protocol):
+//!
+//! ```text
+//! backend ceremony export --out phase2_0.bin [--shard-size N] [--field-set F] [--sha256-commitment]
+//! backend ceremony contribute --in phase2_0.bin --out phase2_1.bin [--name NAME]
+//! backend ceremony verify --initial phase2_0.bin --params phase2_2.bin
+//! backend ceremony finalize --initial phase2_0.bin --params phase2_2.bin [--replace]
+//! ```
+//!
+//! Finalizing over existing key files (`--replace`) rotates the key: restart the backend and plan
+//! a circuit migration for the datasets proven with the old one.
+
+use crate::errors::ApiError;
+use crate::state::key_paths;
+use rand::rngs::OsRng;
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+use zk_proofs::ceremony::{self, CeremonyCircuit, Phase2Params};
+use zk_proofs::constants::DEFAULT_SHARD_SIZE;
+use zk_proofs::groth16::{serialize_pk, serialize_vk};
+use zk_proofs::registry;
+use zk_proofs::types::{AgeBuckets, FieldSet};
+
+const USAGE: &str = "usage: ceremony export --out FILE [--shard-size N] [--field-set F] [--sha256-commitment] \
+    | ceremony contribute --in FILE --out FILE [--name NAME] \
+    | ceremony verify --initial FILE --params FILE \
+    | ceremony finalize --initial FILE --params FILE [--replace]";
+
+fn usage() -> ApiError {
+    ApiError::BadRequest(USAGE.to_string())
+}
+
+fn ceremony_error(e: impl std::fmt::Display) -> ApiError {
+    ApiError::BadRequest(e.to_string())
+}
+
+#[derive(Default)]
+struct Options {
+    input: Option<PathBuf>,
+    output: Option<PathBuf>,
+    initial: Option<PathBuf>,
+    params: Option<PathBuf>,
+    name: Option<String>,
+    shard_size: Option<usize>,
+    field_set: Option<FieldSet>,
+    sha256_commitment: bool,
+    replace: bool,
+}
+
+fn parse_options(args: &[String]) -> Result<Options, ApiError> {
+    let mut options = Options::default();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().cloned().ok_or_else(usage);
+        match arg.as_str() {
+            "--in" => options.input = Some(PathBuf::from(value()?)),
+            "--out" => options.output = Some(PathBuf::from(value()?)),
+            "--initial" => options.initial = Some(PathBuf::from(value()?)),
+            "--params" => options.params = Some(PathBuf::from(value()?)),
+            "--name" => options.name = Some(value()?),
+            "--shard-size" => options.shard_size = Some(value()?.parse().map_err(|_| usage())?),
+            "--field-set" => options.field_set = Some(FieldSet::parse(&value()?).ok_or_else(usage)?),
+            "--sha256-commitment" => options.sha256_commitment = true,
+            "--replace" => options.replace = true,
+            _ => return Err(usage()),
+        }
+    }
+    Ok(options)
+}
+
+fn read_params(path: &Path) -> Result<Phase2Params, ApiError> {
+    let bytes = std::fs::read(path).map_err(|e| ceremony_error(format!("{}: {e}", path.display())))?;
+    Phase2Params::from_bytes(&bytes).map_err(|e| ceremony_error(format!("{}: {e}", path.display())))
+}
+
+fn write_params(path: &Path, params: &Phase2Params) -> Result<(), ApiError> {
+    let bytes = params.to_bytes().map_err(ceremony_error)?;
+    std::fs::write(path, bytes).map_err(|e| ceremony_error(format!("{}: {e}", path.display())))
+}
+
+fn print(value: &serde_json::Value) -> Result<(), ApiError> {
+    println!("{}", serde_json::to_string_pretty(value).map_err(|_| ApiError::Internal)?);
+    Ok(())
+}
+
+fn circuit_json(circuit: &CeremonyCircuit) -> serde_json::Value {
+    serde_json::json!({
+        "shard_size": circuit.shard_size,
+        "field_set": circuit.field_set.name(),
+        "sha256_commitment": circuit.sha256_commitment,
+    })
+}
+
+/// The circuit and contribution chain of `params`.
+fn chain_json(params: &Phase2Params) -> Result<serde_json::Value, ApiError> {
+    let contributions = params
+        .contributions
+        .iter()
+        .map(|contribution| {
+            let hash = ceremony::contribution_hash(contribution).map_err(ceremony_error)?;
+            Ok(serde_json::json!({ "name": contribution.name, "hash": hex::encode(hash) }))
+        })
+        .collect::<Result<Vec<_>, ApiError>>()?;
+    Ok(serde_json::json!({
+        "circuit": circuit_json(&params.circuit),
+        "initial_hash": hex::encode(params.initial_hash),
+        "contributions": contributions,
+    }))
+}
+
+pub fn run_cli(args: &[String], data_dir: &Path) -> Result<(), ApiError> {
+    let (command, rest) = args.split_first().ok_or_else(usage)?;
+    let options = parse_options(rest)?;
+    match command.as_str() {
+        "export" => {
+            let output = options.output.ok_or_else(usage)?;
+            let circuit = CeremonyCircuit {
+                shard_size: options.shard_size.unwrap_or(DEFAULT_SHARD_SIZE),
+                field_set: options.field_set.unwrap_or_default(),
+                sha256_commitment: options.sha256_commitment,
+            };
+            if !registry::is_supported(circuit.shard_size) {
+                return Err(ceremony_error(format!("shard size must be one of {:?}", registry::SUPPORTED_SHARD_SIZES)));
+            }
+            let params = ceremony::initial_params(circuit, &mut OsRng).map_err(ceremony_error)?;
+            write_params(&output, &params)?;
+            print(&serde_json::json!({
+                "circuit": circuit_json(&circuit),
+                "initial_hash": hex::encode(params.initial_hash),
+            }))
+        }
+        "contribute" => {
+            let (input, output) = (options.input.ok_or_else(usage)?, options.output.ok_or_else(usage)?);
+            let mut params = read_params(&input)?;
+            let name = options.name.unwrap_or_else(|| format!("contributor {}", params.contributions.len() + 1));
+            let hash = ceremony::contribute(&mut params, &name, &mut OsRng).map_err(ceremony_error)?;
+            write_params(&output, &params)?;
+            print(&serde_json::json!({
+                "name": name,
+                "index": params.contributions.len() - 1,
+                "hash": hex::encode(hash),
+            }))
+        }
+        "verify" => {
+            let initial = read_params(&options.initial.ok_or_else(usage)?)?;
+            let params = read_params(&options.params.ok_or_else(usage)?)?;
+            ceremony::verify(&initial, &params, &mut OsRng).map_err(ceremony_error)?;
+            print(&chain_json(&params)?)
+        }
+        "finalize" => {
+            let initial = read_params(&options.initial.ok_or_else(usage)?)?;
+            let params = read_params(&options.params.ok_or_else(usage)?)?;
+            let (pk, vk) = ceremony::finalize(&initial, &params, &mut OsRng).map_err(ceremony_error)?;
+
+            let circuit = params.circuit;
+            let keys_dir = data_dir.join("keys");
+            std::fs::create_dir_all(&keys_dir).map_err(|_| ApiError::Internal)?;
+            let (pk_path, vk_path) = key_paths(
+                &keys_dir,
+                circuit.shard_size,
+                circuit.field_set,
+                &AgeBuckets::default(),
+                circuit.sha256_commitment,
+            );
+            if (pk_path.exists() || vk_path.exists()) && !options.replace {
+                return Err(ceremony_error(format!(
+                    "{} already exists; pass --replace to rotate to the ceremony's key",
+                    pk_path.display()
+                )));
+            }
+            let pk_bytes = serialize_pk(&pk).map_err(ceremony_error)?;
+            let vk_bytes = serialize_vk(&vk).map_err(ceremony_error)?;
+            std::fs::write(&pk_path, pk_bytes).map_err(|_| ApiError::Internal)?;
+            std::fs::write(&vk_path, &vk_bytes).map_err(|_| ApiError::Internal)?;
+            tracing::info!(pk = %pk_path.display(), vk = %vk_path.display(), "ceremony keys written");
+            let mut chain = chain_json(&params)?;
+            chain["key_id"] = hex::encode(Sha256::digest(&vk_bytes)).into();
+            print(&chain)
+        }
+        _ => Err(usage()),
+    }
+}
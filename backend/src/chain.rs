@@ -0,0 +1,160 @@
+//! Dataset-level commitment chain.
+//!
+//! A dataset commitment folds its shard commitments (compressed BN254 field elements, or those of
+//! another curve's scalar field for shards re-proven by a curve migration) in shard order. Poseidon, the original chain, is SNARK-friendly and is required when the chain has to be
+//! proven in-circuit (dataset aggregate proofs, `aggregate`); datasets that don't need one can use
+//! SHA-256 or BLAKE3, far cheaper to recompute host-side for datasets with many shards. New datasets use
+//! `DATASET_CHAIN_HASH` (default `poseidon`) unless the request names one. The algorithm is stored
+//! per dataset and travels in its manifest and exports, so verifiers recompute the chain with the
+//! right hash.
+//!
+//! The byte-oriented chains hash a domain tag followed by each 32-byte compressed commitment.
+
+use crate::dataset::field_hex;
+use crate::errors::ApiError;
+use ark_bn254::Fr;
+use ark_crypto_primitives::sponge::poseidon::PoseidonSponge;
+use ark_crypto_primitives::sponge::{Absorb, CryptographicSponge, DuplexSpongeMode};
+use ark_ff::PrimeField;
+use ark_serialize::CanonicalSerialize;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use zk_proofs::constants::{poseidon_config_for, DATASET_CHAIN_DOMAIN};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChainHash {
+    /// Poseidon sponge over the field elements (SNARK-friendly).
+    #[default]
+    Poseidon,
+    Sha256,
+    Blake3,
+}
+
+impl ChainHash {
+    pub const ALL: [ChainHash; 3] = [ChainHash::Poseidon, ChainHash::Sha256, ChainHash::Blake3];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ChainHash::Poseidon => "poseidon",
+            ChainHash::Sha256 => "sha256",
+            ChainHash::Blake3 => "blake3",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|h| h.name() == name)
+    }
+}
+
+/// Chain hash for new datasets that don't name one (`DATASET_CHAIN_HASH`).
+pub fn default_chain_hash() -> ChainHash {
+    std::env::var("DATASET_CHAIN_HASH")
+        .ok()
+        .and_then(|v| ChainHash::parse(v.trim()))
+        .unwrap_or_default()
+}
+
+/// Incremental dataset commitment: absorb shard commitments (elements of `F`) in shard order, then
+/// finish. Finishing a clone gives the commitment so far, for datasets that keep growing.
+#[derive(Clone)]
+pub enum DatasetChain<F: PrimeField + Absorb = Fr> {
+    Poseidon(PoseidonSponge<F>),
+    Sha256(Sha256),
+    Blake3(Box<blake3::Hasher>),
+}
+
+impl<F: PrimeField + Absorb> DatasetChain<F> {
+    pub fn new(hash: ChainHash) -> Self {
+        match hash {
+            ChainHash::Poseidon => DatasetChain::Poseidon(PoseidonSponge::<F>::new(&poseidon_config_for::<F>())),
+            ChainHash::Sha256 => DatasetChain::Sha256(Sha256::new_with_prefix(DATASET_CHAIN_DOMAIN)),
+            ChainHash::Blake3 => {
+                let mut hasher = blake3::Hasher::new();
+                hasher.update(DATASET_CHAIN_DOMAIN);
+                DatasetChain::Blake3(Box::new(hasher))
+            }
+        }
+    }
+
+    pub fn absorb(&mut self, shard_commitment: &F) -> Result<(), ApiError> {
+        match self {
+            DatasetChain::Poseidon(sponge) => sponge.absorb(shard_commitment),
+            DatasetChain::Sha256(hasher) => hasher.update(compressed(shard_commitment)?),
+            DatasetChain::Blake3(hasher) => {
+                hasher.update(&compressed(shard_commitment)?);
+            }
+        }
+        Ok(())
+    }
+
+    /// Hex of the dataset commitment.
+    pub fn finish_hex(self) -> Result<String, ApiError> {
+        match self {
+            DatasetChain::Poseidon(mut sponge) => field_hex(sponge.squeeze_field_elements::<F>(1)[0]),
+            DatasetChain::Sha256(hasher) => Ok(hex::encode(hasher.finalize())),
+            DatasetChain::Blake3(hasher) => Ok(hasher.finalize().to_hex().to_string()),
+        }
+    }
+}
+
+/// Saved state of a `DatasetChain`, for proving checkpoints (`checkpoint`).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ChainState {
+    /// The Poseidon sponge's state elements (compressed hex) and absorb position.
+    Poseidon { state_hex: Vec<String>, next_absorb_index: usize },
+    /// SHA-256 and BLAKE3 don't expose their state; the chain is rebuilt by re-absorbing the stored
+    /// shard commitments.
+    Replay,
+}
+
+impl<F: PrimeField + Absorb> DatasetChain<F> {
+    pub fn state(&self) -> Result<ChainState, ApiError> {
+        match self {
+            DatasetChain::Poseidon(sponge) => match sponge.mode {
+                DuplexSpongeMode::Absorbing { next_absorb_index } => Ok(ChainState::Poseidon {
+                    state_hex: sponge.state.iter().map(|e| field_hex(*e)).collect::<Result<_, _>>()?,
+                    next_absorb_index,
+                }),
+                DuplexSpongeMode::Squeezing { .. } => Ok(ChainState::Replay),
+            },
+            DatasetChain::Sha256(_) | DatasetChain::Blake3(_) => Ok(ChainState::Replay),
+        }
+    }
+
+    /// The chain `state` was saved from; `None` for `Replay` or a state that doesn't fit `hash`.
+    pub fn from_state(hash: ChainHash, state: &ChainState) -> Option<Self> {
+        let (ChainHash::Poseidon, ChainState::Poseidon { state_hex, next_absorb_index }) = (hash, state) else {
+            return None;
+        };
+        let mut sponge = PoseidonSponge::<F>::new(&poseidon_config_for::<F>());
+        let elements = state_hex
+            .iter()
+            .map(|h| F::deserialize_compressed(&hex::decode(h).ok()?[..]).ok())
+            .collect::<Option<Vec<F>>>()?;
+        if elements.len() != sponge.state.len() || *next_absorb_index > sponge.parameters.rate {
+            return None;
+        }
+        sponge.state = elements;
+        sponge.mode = DuplexSpongeMode::Absorbing {
+            next_absorb_index: *next_absorb_index,
+        };
+        Some(DatasetChain::Poseidon(sponge))
+    }
+}
+
+fn compressed(f: &impl CanonicalSerialize) -> Result<Vec<u8>, ApiError> {
+    let mut bytes = Vec::with_capacity(32);
+    f.serialize_compressed(&mut bytes).map_err(|_| ApiError::Internal)?;
+    Ok(bytes)
+}
+
+/// Recompute a dataset commitment from its shard commitments, in shard order.
+pub fn dataset_commitment_hex<F: PrimeField + Absorb>(hash: ChainHash, shard_commitments: &[F]) -> Result<String, ApiError> {
+    let mut chain = DatasetChain::<F>::new(hash);
+    for c in shard_commitments {
+        chain.absorb(c)?;
+    }
+    chain.finish_hex()
+}
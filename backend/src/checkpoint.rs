@@ -0,0 +1,219 @@
+//! Proving checkpoints for long proving runs.
+//!
+//! A restarted proving job is requeued (`jobs::start`) and would otherwise prove every shard
+//! again. While a synthetic dataset is proven, a checkpoint is written every
+//! `PROVING_CHECKPOINT_SECS` (default 60, `0` disables) to `data/checkpoints/<dataset_id>.json`.
+//! It holds the next shard to prove, the dataset commitment chain's state after the shards before
+//! it, and the job's keys. It is written to a temporary file, synced and renamed, so a crash leaves
+//! either the previous checkpoint or the new one. Every shard a checkpoint counts was stored in
+//! the ledger before the checkpoint was written.
+//!
+//! On restart the job resumes after the last checkpointed shard. The checkpoint is used only if
+//! it matches the dataset's size, shard size and chain hash, the current keys, and the commitment
+//! of the last checkpointed shard as stored in the ledger. Otherwise proving starts over. Uploaded
+//! datasets aren't checkpointed: their records live in a spool whose key is lost on restart.
+//! Checkpoints are removed once the dataset is ready, has failed or is deleted. A graceful shutdown
+//! writes one after the last stored shard whatever the interval (see `shutdown`).
+
+use crate::chain::{ChainState, DatasetChain};
+use crate::dataset::field_hex;
+use crate::db::DatasetRow;
+use crate::errors::ApiError;
+use crate::state::{AppState, ZkKeys};
+use ark_bn254::Fr;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+const CHECKPOINT_VERSION: u32 = 1;
+
+const CHECKPOINTS_DIR: &str = "checkpoints";
+
+const DEFAULT_INTERVAL_SECS: u64 = 60;
+
+/// Stored shards read per page when a replayed chain is rebuilt.
+const PAGE: u64 = 1000;
+
+fn checkpoint_interval() -> Option<Duration> {
+    let secs = std::env::var("PROVING_CHECKPOINT_SECS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(DEFAULT_INTERVAL_SECS);
+    (secs > 0).then(|| Duration::from_secs(secs))
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ProvingCheckpoint {
+    pub version: u32,
+    pub dataset_id: Uuid,
+    pub written_at: DateTime<Utc>,
+    pub dataset_size: u64,
+    pub shard_size: u64,
+    /// Verifying key of the job's proving keys.
+    pub key_id: String,
+    /// Shards `0..next_shard` are proven and stored.
+    pub next_shard: u64,
+    /// Commitment of shard `next_shard - 1`, checked against the ledger on resume.
+    pub last_shard_commitment_hex: String,
+    pub chain: ChainState,
+}
+
+fn path(data_dir: &Path, dataset_id: Uuid) -> PathBuf {
+    data_dir.join(CHECKPOINTS_DIR).join(format!("{dataset_id}.json"))
+}
+
+fn write(data_dir: &Path, checkpoint: &ProvingCheckpoint) -> Result<(), ApiError> {
+    let dir = data_dir.join(CHECKPOINTS_DIR);
+    std::fs::create_dir_all(&dir).map_err(|_| ApiError::Internal)?;
+    let path = path(data_dir, checkpoint.dataset_id);
+    let tmp = path.with_extension("tmp");
+
+    let json = serde_json::to_vec(checkpoint).map_err(|_| ApiError::Internal)?;
+    let mut file = std::fs::File::create(&tmp).map_err(|_| ApiError::Internal)?;
+    file.write_all(&json).map_err(|_| ApiError::Internal)?;
+    file.sync_all().map_err(|_| ApiError::Internal)?;
+    std::fs::rename(&tmp, &path).map_err(|_| ApiError::Internal)?;
+    // Make the rename itself durable.
+    if let Ok(dir) = std::fs::File::open(&dir) {
+        let _ = dir.sync_all();
+    }
+    Ok(())
+}
+
+fn load(data_dir: &Path, dataset_id: Uuid) -> Option<ProvingCheckpoint> {
+    let bytes = std::fs::read(path(data_dir, dataset_id)).ok()?;
+    match serde_json::from_slice(&bytes) {
+        Ok(checkpoint) => Some(checkpoint),
+        Err(e) => {
+            tracing::warn!(%dataset_id, error = %e, "ignoring unreadable proving checkpoint");
+            None
+        }
+    }
+}
+
+/// Delete a dataset's checkpoint, if any.
+pub fn remove(data_dir: &Path, dataset_id: Uuid) {
+    let _ = std::fs::remove_file(path(data_dir, dataset_id));
+}
+
+/// Where proving of `dataset` resumes: the first shard to prove and the chain over the shards
+/// before it. `(0, empty chain)` without a usable checkpoint.
+pub async fn resume_point(
+    state: &AppState,
+    dataset_id: Uuid,
+    dataset: &DatasetRow,
+    keys: &ZkKeys,
+) -> Result<(u64, DatasetChain), ApiError> {
+    let fresh = (0, DatasetChain::new(dataset.chain_hash));
+    let Some(checkpoint) = load(&state.data_dir, dataset_id) else {
+        return Ok(fresh);
+    };
+
+    let matches = checkpoint.version == CHECKPOINT_VERSION
+        && checkpoint.dataset_id == dataset_id
+        && checkpoint.dataset_size == dataset.dataset_size
+        && checkpoint.shard_size == dataset.shard_size
+        && checkpoint.key_id == keys.key_id
+        && checkpoint.next_shard > 0
+        && checkpoint.next_shard <= dataset.shards_total();
+    if !matches {
+        tracing::warn!(%dataset_id, "proving checkpoint does not match the dataset or keys; starting over");
+        return Ok(fresh);
+    }
+
+    let last = checkpoint.next_shard - 1;
+    let stored = state.store.list_shards(dataset_id, last..last + 1, 0, 1, false).await?;
+    if stored.first().map(|(_, commitment_hex, ..)| commitment_hex) != Some(&checkpoint.last_shard_commitment_hex) {
+        tracing::warn!(%dataset_id, shard_index = last, "checkpointed shard is not in the ledger; starting over");
+        return Ok(fresh);
+    }
+
+    let chain = match DatasetChain::from_state(dataset.chain_hash, &checkpoint.chain) {
+        Some(chain) => chain,
+        None => {
+            let mut chain = DatasetChain::new(dataset.chain_hash);
+            let mut offset = 0;
+            while offset < checkpoint.next_shard {
+                let page = state.store.list_shards(dataset_id, 0..checkpoint.next_shard, offset, PAGE, false).await?;
+                if page.is_empty() {
+                    tracing::warn!(%dataset_id, "checkpointed shards are missing from the ledger; starting over");
+                    return Ok(fresh);
+                }
+                offset += page.len() as u64;
+                for (_, commitment_hex, ..) in &page {
+                    chain.absorb(&crate::dataset::parse_field_hex(commitment_hex).ok_or(ApiError::Internal)?)?;
+                }
+            }
+            chain
+        }
+    };
+
+    tracing::info!(%dataset_id, next_shard = checkpoint.next_shard, "resuming proving from checkpoint");
+    Ok((checkpoint.next_shard, chain))
+}
+
+/// Writes a proving run's checkpoints at most once per interval.
+pub struct Checkpointer {
+    dataset_id: Uuid,
+    dataset_size: u64,
+    shard_size: u64,
+    key_id: String,
+    interval: Option<Duration>,
+    last_written: Instant,
+}
+
+impl Checkpointer {
+    pub fn new(dataset_id: Uuid, dataset: &DatasetRow, keys: &ZkKeys) -> Self {
+        Self {
+            dataset_id,
+            dataset_size: dataset.dataset_size,
+            shard_size: dataset.shard_size,
+            key_id: keys.key_id.clone(),
+            interval: checkpoint_interval(),
+            last_written: Instant::now(),
+        }
+    }
+
+    /// Record that shard `shard_index` (with `commitment`) is stored and absorbed into `chain`.
+    /// A failed write is logged; proving goes on.
+    pub fn shard_done(&mut self, data_dir: &Path, shard_index: u64, commitment: &Fr, chain: &DatasetChain) {
+        let Some(interval) = self.interval else { return };
+        if self.last_written.elapsed() < interval {
+            return;
+        }
+        self.save(data_dir, shard_index, commitment, chain);
+    }
+
+    /// Like `shard_done`, but write the checkpoint whatever the interval (on shutdown).
+    pub fn save_now(&mut self, data_dir: &Path, shard_index: u64, commitment: &Fr, chain: &DatasetChain) {
+        if self.interval.is_some() {
+            self.save(data_dir, shard_index, commitment, chain);
+        }
+    }
+
+    fn save(&mut self, data_dir: &Path, shard_index: u64, commitment: &Fr, chain: &DatasetChain) {
+        self.last_written = Instant::now();
+
+        let written = field_hex(*commitment)
+            .and_then(|last_shard_commitment_hex| {
+                Ok(ProvingCheckpoint {
+                    version: CHECKPOINT_VERSION,
+                    dataset_id: self.dataset_id,
+                    written_at: Utc::now(),
+                    dataset_size: self.dataset_size,
+                    shard_size: self.shard_size,
+                    key_id: self.key_id.clone(),
+                    next_shard: shard_index + 1,
+                    last_shard_commitment_hex,
+                    chain: chain.state()?,
+                })
+            })
+            .and_then(|checkpoint| write(data_dir, &checkpoint));
+        if let Err(e) = written {
+            tracing::warn!(dataset_id = %self.dataset_id, shard_index, error = %e, "failed to write proving checkpoint");
+        }
+    }
+}
@@ -0,0 +1,215 @@
+//! Circuit upgrades: moving datasets proven with an older shard circuit revision to a newer one.
+//!
+//! Keys are set up for the latest `CircuitRevision` and keep proving the revision they were set up
+//! for, so a new revision only takes effect once an operator replaces a key set's files. Every
+//! verifying key is archived by id when loaded (`state::archived_vk_path`), so proofs made with a
+//! replaced key stay verifiable, and `GET /api/v1/zk/vk?dataset_id=` serves the key a dataset's
+//! manifest names.
+//!
+//! `POST /api/v1/admin/circuit-migrations` reports, per dataset, the revision and key its proofs
+//! were made with, whether that key is still available, and the plan: synthetic datasets whose
+//! current keys are of the target revision are re-proven by a background job
+//! (`jobs::KIND_MIGRATE_CIRCUIT`); uploads, streams, imports and federated datasets, whose records
+//! the ledger can't reproduce, are flagged with the reason, as are datasets whose keys haven't
+//! been replaced yet.
+//!
+//! The job regenerates every shard's records, checks them against the proven aggregates, proves
+//! them with the current keys and only then replaces the stored shards, so the dataset keeps
+//! serving its old proofs until the new ones are complete. Its commitment changes (salted
+//! revisions commit to a fresh master salt per shard); `circuit_migrated` in the audit chain
+//! records the old and new commitments and keys.
+
+use crate::bucket_counts;
+use crate::chain::DatasetChain;
+use crate::curve_migration::same_aggregates;
+use crate::dataset::{self, RecordSource};
+use crate::db;
+use crate::errors::ApiError;
+use crate::models::CircuitMigrationPlanItem;
+use crate::state::{archived_vk, key_paths, AppState};
+use crate::transparency;
+use base64::Engine;
+use sha2::{Digest, Sha256};
+use tracing::info;
+use uuid::Uuid;
+use zk_proofs::groth16::{deserialize_vk, vk_revision};
+use zk_proofs::types::CircuitRevision;
+
+/// The circuit a dataset's proofs were made with.
+pub struct ProvenWith {
+    pub revision: Option<CircuitRevision>,
+    pub key_id: Option<String>,
+    /// Whether the verifying key is available (stored with the dataset, current or archived).
+    pub key_available: bool,
+    /// Proven by another ledger or a federated site, with their key.
+    pub external: bool,
+}
+
+/// The circuit `dataset`'s proofs were made with: its external key's, else its manifest's.
+pub async fn proven_with(state: &AppState, dataset_id: Uuid, dataset: &db::DatasetRow) -> Result<ProvenWith, ApiError> {
+    let num_buckets = dataset.age_buckets.num_buckets();
+    if let Some(vk_b64) = state.store.get_dataset_external_vk(dataset_id).await? {
+        let vk_bytes = base64::engine::general_purpose::STANDARD.decode(vk_b64).unwrap_or_default();
+        let vk = deserialize_vk(&vk_bytes).ok();
+        return Ok(ProvenWith {
+            revision: vk.as_ref().map(|vk| vk_revision(vk, dataset.field_set, num_buckets)),
+            key_id: Some(hex::encode(Sha256::digest(&vk_bytes))),
+            key_available: vk.is_some(),
+            external: true,
+        });
+    }
+
+    let manifest = state.store.get_dataset_manifest(dataset_id).await?;
+    let field = |name: &str| manifest.as_ref().and_then(|m| m.get(name)).and_then(|v| v.as_str()).map(str::to_string);
+    let key_id = field("key_id");
+    let key_available = match &key_id {
+        Some(key_id) => {
+            archived_vk(&state.data_dir.join("keys"), key_id).is_some()
+                || current_vk(state, dataset).is_some_and(|bytes| hex::encode(Sha256::digest(bytes)) == *key_id)
+        }
+        None => false,
+    };
+    Ok(ProvenWith {
+        revision: field("circuit_id").as_deref().and_then(CircuitRevision::of_circuit_id),
+        key_id,
+        key_available,
+        external: false,
+    })
+}
+
+/// The verifying key file currently in place for `dataset`'s shard size, field set and layout.
+fn current_vk(state: &AppState, dataset: &db::DatasetRow) -> Option<Vec<u8>> {
+    let (_, vk_path) = key_paths(
+        &state.data_dir.join("keys"),
+        dataset.shard_size as usize,
+        dataset.field_set,
+        &dataset.age_buckets,
+        dataset.sha256_commitment,
+    );
+    std::fs::read(vk_path).ok()
+}
+
+/// Revision new proofs for `dataset` would be made with: that of the keys in place, or the latest
+/// (keys not set up yet are set up for it).
+pub fn current_revision(state: &AppState, dataset: &db::DatasetRow) -> CircuitRevision {
+    current_vk(state, dataset)
+        .and_then(|bytes| deserialize_vk(&bytes).ok())
+        .map(|vk| vk_revision(&vk, dataset.field_set, dataset.age_buckets.num_buckets()))
+        .unwrap_or(CircuitRevision::LATEST)
+}
+
+/// What an upgrade from `from` (every older revision if `None`) to `to` does with `dataset`.
+pub fn plan_item(
+    dataset_id: Uuid,
+    dataset: &db::DatasetRow,
+    proven: &ProvenWith,
+    current: CircuitRevision,
+    from: Option<CircuitRevision>,
+    to: CircuitRevision,
+) -> CircuitMigrationPlanItem {
+    let affected = proven.revision.is_some_and(|r| r < to && from.is_none_or(|f| f == r));
+    let (action, reason) = if dataset.status != "ready" {
+        ("skip", Some("dataset is not ready".to_string()))
+    } else if proven.revision.is_none() {
+        ("flag", Some("no manifest or key records the circuit revision it was proven with".to_string()))
+    } else if !affected {
+        ("skip", None)
+    } else if proven.external {
+        ("flag", Some("proven externally; it must be re-proven where its records are".to_string()))
+    } else if dataset.generator.is_none() {
+        ("flag", Some("uploaded records are not retained; the custodian must re-upload them".to_string()))
+    } else if dataset.frozen_at.is_some() {
+        ("flag", Some("dataset is frozen".to_string()))
+    } else if current != to {
+        (
+            "flag",
+            Some(format!(
+                "the keys in place are {}; replace their files to set up {} keys first",
+                current.version(),
+                to.version()
+            )),
+        )
+    } else {
+        ("reprove", None)
+    };
+    CircuitMigrationPlanItem {
+        dataset_id,
+        circuit_revision: proven.revision.map(|r| r.version().to_string()),
+        key_id: proven.key_id.clone(),
+        affected,
+        proofs_verifiable: proven.key_available,
+        action: action.to_string(),
+        reason,
+    }
+}
+
+/// Job body for `jobs::KIND_MIGRATE_CIRCUIT`: re-prove a synthetic dataset with the keys in place,
+/// if they are of a newer revision than its proofs.
+pub async fn run_migrate_job(state: &AppState, dataset_id: Uuid) -> Result<(), ApiError> {
+    let Some(dataset) = state.store.get_dataset(dataset_id).await? else {
+        return Err(ApiError::NotFound("dataset not found".to_string()));
+    };
+    let proven = proven_with(state, dataset_id, &dataset).await?;
+    let keys = state.ensure_keys_for(dataset.shard_size as usize, dataset.field_set, &dataset.age_buckets, dataset.sha256_commitment).await?;
+    let item = plan_item(dataset_id, &dataset, &proven, keys.revision, None, keys.revision);
+    match item.action.as_str() {
+        "reprove" => {}
+        "skip" => return Ok(()),
+        _ => return Err(ApiError::Conflict(item.reason.unwrap_or_default())),
+    }
+
+    let name = dataset.generator.as_deref().ok_or(ApiError::Internal)?;
+    let source = RecordSource::Synthetic(dataset::synthetic_generator(name, &dataset)?, dataset.field_set);
+    let shards_total = dataset.shards_total();
+    let stored = state.store.list_shards(dataset_id, 0..shards_total, 0, shards_total, false).await?;
+    if stored.len() as u64 != shards_total {
+        return Err(ApiError::Conflict(format!("{} of {shards_total} shards are stored", stored.len())));
+    }
+
+    // Prove everything before replacing anything, so the dataset stays consistent meanwhile.
+    let mut reproven = Vec::with_capacity(stored.len());
+    for (shard_index, _, old_stats, _, _) in stored {
+        let shard = dataset::prove_shard(state, dataset_id, &dataset, &keys, &source, shard_index).await?;
+        if !same_aggregates(&shard.1, &old_stats) {
+            return Err(ApiError::Conflict(format!(
+                "shard {shard_index} regenerates with different aggregates than were proven"
+            )));
+        }
+        if shard_index % 10 == 0 {
+            info!(%dataset_id, shard_index, revision = keys.revision.version(), "re-proved shard");
+        }
+        reproven.push((shard_index, shard));
+    }
+
+    let dataset = state.store.get_dataset(dataset_id).await?.ok_or(ApiError::Internal)?;
+    if dataset.frozen_at.is_some() {
+        return Err(ApiError::Conflict("dataset was frozen while re-proving".to_string()));
+    }
+    let mut chain = DatasetChain::new(dataset.chain_hash);
+    for (shard_index, shard) in &reproven {
+        dataset::store_proven_shard(state, dataset_id, *shard_index, shard, &keys.key_id).await?;
+        chain.absorb(&shard.0)?;
+    }
+    let dataset_commitment_hex = chain.finish_hex()?;
+    state.store.set_dataset_ready(dataset_id, &dataset_commitment_hex).await?;
+    transparency::log_dataset(state, dataset_id, &dataset_commitment_hex).await?;
+    let manifest = dataset::build_manifest(dataset_id, &dataset, &source, &keys);
+    state.store.set_dataset_manifest(dataset_id, &serde_json::to_value(&manifest).map_err(|_| ApiError::Internal)?).await?;
+    bucket_counts::commit(state, dataset_id, &dataset, &dataset_commitment_hex).await?;
+
+    state.store.append_audit(
+        Some(dataset_id),
+        "circuit_migrated",
+        &serde_json::json!({
+            "from": item.circuit_revision,
+            "to": keys.revision.version(),
+            "previous_key_id": proven.key_id,
+            "key_id": keys.key_id,
+            "previous_dataset_commitment_hex": dataset.commitment_hex,
+            "dataset_commitment_hex": dataset_commitment_hex,
+        }),
+    )
+    .await?;
+    info!(%dataset_id, revision = keys.revision.version(), "circuit migration done");
+    Ok(())
+}
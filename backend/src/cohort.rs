@@ -0,0 +1,179 @@
+//! Cohort queries: one measurement's per-bucket totals pooled over several datasets
+//! (`POST /api/v1/queries/cohort`), e.g. the same study run at several sites.
+//!
+//! Each dataset's share of a bucket is summed from its live proven shards exactly as a single
+//! query would (`query::compute_answer`, without query proofs), and is recorded as a query of
+//! that dataset, so pooled answers count toward every dataset's release limit and show up in its
+//! disclosure tracking. The cohort is `server_verified` only if every live shard of every dataset
+//! is. A pooled bucket is suppressed when any dataset's share of it is below `MIN_CELL_COUNT`,
+//! like a complement's buckets: that dataset's share would otherwise follow from the pooled
+//! answer and the other datasets' own answers. Enough other buckets are withheld with it that
+//! the dataset sizes minus the released counts don't give it away either.
+
+use crate::db::{self, QueryResult};
+use crate::errors::ApiError;
+use crate::models::{CohortBucket, CohortDataset, CohortQueryId, CohortQueryResponse, Metric, QueryPurpose, QuerySuppression};
+use crate::policy;
+use crate::query;
+use crate::state::AppState;
+use uuid::Uuid;
+use zk_proofs::types::Measurement;
+
+/// Most datasets one cohort query pools.
+pub const MAX_COHORT_DATASETS: usize = 16;
+
+/// Reject a cohort whose datasets can't be pooled: they must share one age bucket layout.
+pub fn check_poolable(datasets: &[(Uuid, db::DatasetRow)]) -> Result<(), ApiError> {
+    let Some((_, first)) = datasets.first() else {
+        return Err(ApiError::BadRequest("a cohort needs at least 2 datasets".to_string()));
+    };
+    for (id, dataset) in datasets {
+        if dataset.age_buckets != first.age_buckets {
+            return Err(ApiError::BadRequest(format!(
+                "dataset {id} has different age buckets ({:?}) from the cohort's first dataset ({:?})",
+                dataset.age_buckets.bounds(),
+                first.age_buckets.bounds()
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Reject (429) a cohort that would take any dataset past its distinct-release budget: the
+/// cohort releases every bucket of `field` at once.
+async fn enforce_release_limits(state: &AppState, datasets: &[(Uuid, db::DatasetRow)], field: Measurement) -> Result<(), ApiError> {
+    for (dataset_id, dataset) in datasets {
+        let keys: Vec<String> = (0..dataset.age_buckets.num_buckets()).map(|b| db::release_key(db::QueryBuckets::single(b), field)).collect();
+        query::enforce_release_limit_for(state, *dataset_id, dataset, &keys).await?;
+    }
+    Ok(())
+}
+
+/// Pool `field` over `datasets` (checked ready, accessible and consented by the caller), record
+/// each dataset's released shares, and answer.
+pub async fn answer(
+    state: &AppState,
+    datasets: &[(Uuid, db::DatasetRow)],
+    field: Measurement,
+    purpose: Option<&QueryPurpose>,
+) -> Result<CohortQueryResponse, ApiError> {
+    check_poolable(datasets)?;
+    enforce_release_limits(state, datasets, field).await?;
+
+    let cohort_id = Uuid::new_v4();
+    let buckets = datasets[0].1.age_buckets.clone();
+    let spec = |bucket_index| db::QuerySpec {
+        metric: &Metric::Mean,
+        purpose,
+        buckets: db::QueryBuckets::single(bucket_index),
+        field,
+        dp: None,
+        cohort_id: Some(cohort_id),
+        requested_by: None,
+    };
+
+    // Per dataset, its answer for every bucket.
+    let mut shares: Vec<Vec<QueryResult>> = Vec::with_capacity(datasets.len());
+    for (dataset_id, dataset) in datasets {
+        let mut results = Vec::with_capacity(buckets.num_buckets());
+        for bucket_index in 0..buckets.num_buckets() {
+            results.push(query::compute_answer_unproven(state, *dataset_id, dataset, &spec(bucket_index)).await?);
+        }
+        shares.push(results);
+    }
+
+    let min_count = policy::min_cell_count();
+    let pooled: Vec<u64> =
+        (0..buckets.num_buckets()).map(|b| shares.iter().map(|results| results[b].count).sum()).collect();
+    let mut withheld: Vec<bool> = (0..buckets.num_buckets())
+        .map(|b| {
+            policy::below_min_count(pooled[b], min_count)
+                || shares.iter().any(|results| policy::below_min_count(results[b].count, min_count))
+        })
+        .collect();
+    // Dataset sizes are public, so the withheld buckets must hide enough records in the pool and
+    // in each dataset's share.
+    loop {
+        let mut added = policy::suppress_complement(&pooled, &mut withheld, min_count);
+        for results in &shares {
+            let counts: Vec<u64> = results.iter().map(|result| result.count).collect();
+            added |= policy::suppress_complement(&counts, &mut withheld, min_count);
+        }
+        if !added {
+            break;
+        }
+    }
+
+    let mut cohort_buckets = Vec::with_capacity(buckets.num_buckets());
+    for bucket_index in 0..buckets.num_buckets() {
+        let sum: u64 = shares.iter().map(|results| results[bucket_index].sum).sum();
+        let count = pooled[bucket_index];
+        let suppressed = if policy::below_min_count(count, min_count) {
+            Some("the pooled bucket has too few records to release its aggregates")
+        } else if shares.iter().any(|results| policy::below_min_count(results[bucket_index].count, min_count)) {
+            Some("a dataset's share of the bucket has too few records; the other datasets' answers would reveal it")
+        } else if withheld[bucket_index] {
+            Some("another bucket has too few records; with this one released, the dataset sizes would reveal it")
+        } else {
+            None
+        }
+        .map(|reason| QuerySuppression {
+            min_count: min_count.unwrap_or_default(),
+            reason: reason.to_string(),
+        });
+        let released = suppressed.is_none();
+        cohort_buckets.push(CohortBucket {
+            bucket_index,
+            bucket_range: buckets.bounds()[bucket_index],
+            sum: released.then_some(sum),
+            count: released.then_some(count),
+            mean: (released && count > 0).then(|| sum as f64 / count as f64),
+            suppressed,
+        });
+    }
+
+    // Only released buckets are disclosed, so only they are recorded.
+    let mut cohort_datasets = Vec::with_capacity(datasets.len());
+    for ((dataset_id, _), results) in datasets.iter().zip(&shares) {
+        let mut query_ids = Vec::new();
+        for (bucket_index, result) in results.iter().enumerate() {
+            if cohort_buckets[bucket_index].suppressed.is_some() {
+                continue;
+            }
+            let query_id = Uuid::new_v4();
+            state.store.insert_query(query_id, *dataset_id, &spec(bucket_index), result).await?;
+            state.store.insert_released_cells(query_id, *dataset_id, &query::released_cells(bucket_index, result)).await?;
+            query_ids.push(CohortQueryId { bucket_index, query_id });
+        }
+        state.store.append_audit(
+            Some(*dataset_id),
+            "cohort_query",
+            &serde_json::json!({
+                "cohort_id": cohort_id,
+                "dataset_ids": datasets.iter().map(|(id, _)| id).collect::<Vec<_>>(),
+                "field": field.name(),
+                "purpose": purpose,
+                "query_ids": query_ids,
+            }),
+        )
+        .await?;
+
+        // Every bucket is summed over the same shards, so any bucket's shard set is the dataset's.
+        let shard_set = results[0].shard_set.clone().ok_or(ApiError::Internal)?;
+        cohort_datasets.push(CohortDataset {
+            dataset_id: *dataset_id,
+            server_verified: results.iter().all(|r| r.verified),
+            shard_set,
+            shard_proofs_endpoint: format!("/api/v1/datasets/{dataset_id}/shards?include_proof=true"),
+            query_ids,
+        });
+    }
+
+    Ok(CohortQueryResponse {
+        cohort_id,
+        field,
+        buckets: cohort_buckets,
+        server_verified: cohort_datasets.iter().all(|d| d.server_verified),
+        datasets: cohort_datasets,
+    })
+}
@@ -0,0 +1,239 @@
+//! Startup configuration.
+//!
+//! `BACKEND_CONFIG` names an optional TOML (`.toml`) or YAML (`.yaml`/`.yml`) file; every setting
+//! has a default, and the environment variable named next to it overrides the file:
+//!
+//! ```toml
+//! bind_addr = "0.0.0.0:8080"             # BACKEND_ADDR
+//! data_dir = "/var/lib/ledger"           # DATA_DIR
+//! database_url = "postgres://ledger@db/ledger"   # DATABASE_URL
+//! default_shard_size = 1000              # DEFAULT_SHARD_SIZE
+//! cors_origins = ["https://ledger.example.org"]  # CORS_ORIGINS, comma separated
+//!
+//! [workers]
+//! jobs = 2        # JOB_WORKERS
+//! proving = 2     # PROVING_WORKERS
+//! aggregate = 1   # AGGREGATE_WORKERS
+//! migration = 1   # MIGRATION_WORKERS
+//! verify = 1      # VERIFY_WORKERS
+//!
+//! [auth]
+//! mode = "both"   # AUTH_MODE: api_key, oidc or both
+//! ```
+//!
+//! The result is validated before anything starts: unknown keys, unparsable values, unsupported
+//! shard sizes, zero workers, database URLs of another kind and malformed origins are refused
+//! with the setting that is wrong. Settings not listed here (quotas, OIDC, anchoring, ...) stay
+//! environment variables.
+
+use crate::errors::ApiError;
+use crate::pg;
+use serde::Deserialize;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use zk_proofs::constants::DEFAULT_SHARD_SIZE;
+use zk_proofs::registry;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub bind_addr: SocketAddr,
+    pub data_dir: PathBuf,
+    /// `None` keeps the ledger in `<data_dir>/ledger.sqlite` (see `main`).
+    pub database_url: Option<String>,
+    /// Shard size of datasets and keys requested without one.
+    pub default_shard_size: usize,
+    pub workers: Workers,
+    pub auth: AuthConfig,
+    /// Origins browsers may call the API from; empty (or `*`) allows any.
+    pub cors_origins: Vec<String>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            bind_addr: SocketAddr::from(([127, 0, 0, 1], 8080)),
+            data_dir: PathBuf::from("data"),
+            database_url: None,
+            default_shard_size: DEFAULT_SHARD_SIZE,
+            workers: Workers::default(),
+            auth: AuthConfig::default(),
+            cors_origins: Vec::new(),
+        }
+    }
+}
+
+/// Worker pool sizes (see `jobs`).
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Workers {
+    pub jobs: usize,
+    pub proving: usize,
+    pub aggregate: usize,
+    pub migration: usize,
+    pub verify: usize,
+}
+
+impl Default for Workers {
+    fn default() -> Self {
+        Self {
+            jobs: 2,
+            proving: 2,
+            aggregate: 1,
+            migration: 1,
+            verify: 1,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AuthConfig {
+    pub mode: AuthMode,
+}
+
+/// Which credentials authenticate a caller.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuthMode {
+    /// `X-API-KEY` only; bearer tokens are refused even with `OIDC_ISSUER` set.
+    ApiKey,
+    /// OIDC bearer tokens only (see `oidc`); needs `OIDC_ISSUER`.
+    Oidc,
+    /// Either.
+    #[default]
+    Both,
+}
+
+impl AuthMode {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.trim() {
+            "api_key" => Some(AuthMode::ApiKey),
+            "oidc" => Some(AuthMode::Oidc),
+            "both" => Some(AuthMode::Both),
+            _ => None,
+        }
+    }
+
+    pub fn allows_api_keys(self) -> bool {
+        self != AuthMode::Oidc
+    }
+
+    pub fn allows_bearer_tokens(self) -> bool {
+        self != AuthMode::ApiKey
+    }
+}
+
+fn config_error(message: impl std::fmt::Display) -> ApiError {
+    ApiError::BadRequest(format!("configuration: {message}"))
+}
+
+fn env(name: &str) -> Option<String> {
+    std::env::var(name).ok().map(|v| v.trim().to_string()).filter(|v| !v.is_empty())
+}
+
+/// Replace `target` with environment variable `name`, if set.
+fn override_from_env<T: FromStr>(name: &str, target: &mut T) -> Result<(), ApiError> {
+    if let Some(value) = env(name) {
+        *target = value.parse().map_err(|_| config_error(format!("{name}={value:?} is not a valid value")))?;
+    }
+    Ok(())
+}
+
+fn from_file(path: &Path) -> Result<Config, ApiError> {
+    let text = std::fs::read_to_string(path).map_err(|e| config_error(format!("{}: {e}", path.display())))?;
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some("toml") => toml::from_str(&text).map_err(|e| config_error(format!("{}: {e}", path.display()))),
+        Some("yaml" | "yml") => serde_yaml::from_str(&text).map_err(|e| config_error(format!("{}: {e}", path.display()))),
+        _ => Err(config_error(format!("{}: expected a .toml, .yaml or .yml file", path.display()))),
+    }
+}
+
+impl Config {
+    fn apply_env(&mut self) -> Result<(), ApiError> {
+        override_from_env("BACKEND_ADDR", &mut self.bind_addr)?;
+        override_from_env("DATA_DIR", &mut self.data_dir)?;
+        if let Some(url) = env("DATABASE_URL") {
+            self.database_url = Some(url);
+        }
+        override_from_env("DEFAULT_SHARD_SIZE", &mut self.default_shard_size)?;
+        override_from_env("JOB_WORKERS", &mut self.workers.jobs)?;
+        override_from_env("PROVING_WORKERS", &mut self.workers.proving)?;
+        override_from_env("AGGREGATE_WORKERS", &mut self.workers.aggregate)?;
+        override_from_env("MIGRATION_WORKERS", &mut self.workers.migration)?;
+        override_from_env("VERIFY_WORKERS", &mut self.workers.verify)?;
+        if let Some(mode) = env("AUTH_MODE") {
+            self.auth.mode = AuthMode::parse(&mode)
+                .ok_or_else(|| config_error(format!("AUTH_MODE={mode:?} must be api_key, oidc or both")))?;
+        }
+        if let Some(origins) = env("CORS_ORIGINS") {
+            self.cors_origins = origins.split(',').map(|o| o.trim().to_string()).filter(|o| !o.is_empty()).collect();
+        }
+        Ok(())
+    }
+
+    fn validate(&self) -> Result<(), ApiError> {
+        if !registry::is_supported(self.default_shard_size) {
+            return Err(config_error(format!(
+                "default_shard_size {} must be one of {:?}",
+                self.default_shard_size,
+                registry::SUPPORTED_SHARD_SIZES
+            )));
+        }
+        let workers = self.workers;
+        for (name, count) in [
+            ("jobs", workers.jobs),
+            ("proving", workers.proving),
+            ("aggregate", workers.aggregate),
+            ("migration", workers.migration),
+            ("verify", workers.verify),
+        ] {
+            if count == 0 {
+                return Err(config_error(format!("workers.{name} must be at least 1")));
+            }
+        }
+        if let Some(url) = &self.database_url
+            && !url.starts_with("sqlite:")
+            && !pg::is_postgres_url(url)
+        {
+            return Err(config_error("database_url must be a sqlite: or postgres:// URL"));
+        }
+        if self.auth.mode == AuthMode::Oidc && env("OIDC_ISSUER").is_none() {
+            return Err(config_error("auth.mode = oidc needs OIDC_ISSUER"));
+        }
+        if env("OIDC_ISSUER").is_some() && env("OIDC_AUDIENCE").is_none() {
+            // Without it, any token the issuer signed for another client would be accepted.
+            return Err(config_error("OIDC_ISSUER needs OIDC_AUDIENCE"));
+        }
+        for origin in &self.cors_origins {
+            // Browsers send the bare origin, so a path (even `/`) would never match.
+            let valid = origin == "*"
+                || (origin.split_once("://").is_some_and(|(scheme, host)| {
+                    matches!(scheme, "http" | "https") && !host.is_empty() && !host.contains('/')
+                }) && axum::http::HeaderValue::from_str(origin).is_ok());
+            if !valid {
+                return Err(config_error(format!(
+                    "cors_origins: {origin:?} must be * or an origin like https://ledger.example.org"
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether any origin may call the API.
+    pub fn any_cors_origin(&self) -> bool {
+        self.cors_origins.is_empty() || self.cors_origins.iter().any(|origin| origin == "*")
+    }
+}
+
+/// The configuration: `BACKEND_CONFIG`'s file (or the defaults), then the environment, validated.
+pub fn load() -> Result<Config, ApiError> {
+    let mut config = match env("BACKEND_CONFIG") {
+        Some(path) => from_file(Path::new(&path))?,
+        None => Config::default(),
+    };
+    config.apply_env()?;
+    config.validate()?;
+    Ok(config)
+}
@@ -0,0 +1,260 @@
+//! Curve migrations: moving a ledger's shard proofs from BN254 to BLS12-381.
+//!
+//! `POST /api/v1/admin/curve-migrations` plans a migration of every dataset (or the listed ones).
+//! Synthetic datasets are re-proven on BLS12-381 by a background job (`jobs::KIND_MIGRATE_CURVE`):
+//! their records are regenerated from the generator and shard seeds, checked against the proven
+//! BN254 aggregates, and proven with the salted circuit revision v4 under a fresh master salt per
+//! shard (sealed like BN254 ones, bound to the curve). Datasets whose records the ledger can't
+//! reproduce (uploads, imports) or whose circuit has no BLS12-381 keys (dual-commitment) are
+//! flagged with the reason instead, for their custodian to re-upload. BLS12-381 keys sit in the
+//! same key registry as the BN254 ones, one pair per shard size and field set.
+//!
+//! Re-proven shards are stored next to the BN254 ones (`curve_shards`) with their own dataset
+//! commitment (the dataset's chain hash over the BLS12-381 shard commitments), and both proof sets
+//! are served: `?curve=` picks one on `/shards` and `/zk/vk`, and verification requests name
+//! theirs. BN254 stays the default until `CURVE_TRANSITION_DAYS` (default 30) after a dataset's
+//! migration finished, the migrated curve afterwards; BN254 proofs remain available on request.
+
+use crate::chain::dataset_commitment_hex;
+use crate::dataset::{self, field_hex, RecordSource};
+use crate::db;
+use crate::errors::ApiError;
+use crate::key_usage;
+use crate::models::{CurveCommitment, CurveMigrationItem, CurveMigrationPlanItem};
+use crate::state::AppState;
+use base64::Engine;
+use chrono::{DateTime, Duration, Utc};
+use std::collections::HashSet;
+use tracing::info;
+use uuid::Uuid;
+use zk_proofs::groth16::{salt_commitment_on, serialize_proof, verify_shard_proof_on};
+use zk_proofs::registry::prove_shard_on_for;
+use zk_proofs::types::{Curve, ShardStats};
+
+use ark_bls12_381::{Bls12_381, Fr as BlsFr};
+use ark_serialize::CanonicalDeserialize;
+
+/// The curve migrations move to.
+pub const TARGET_CURVE: Curve = Curve::Bls12_381;
+
+const DEFAULT_TRANSITION_DAYS: u64 = 30;
+const MAX_TRANSITION_DAYS: u64 = 36_500;
+
+/// Days after a migration finishes during which BN254 stays the default (`CURVE_TRANSITION_DAYS`).
+pub fn transition_days() -> u64 {
+    std::env::var("CURVE_TRANSITION_DAYS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(DEFAULT_TRANSITION_DAYS)
+}
+
+/// End of the transition window of a migration finished at `finished_at`.
+fn dual_serve_until(finished_at: DateTime<Utc>) -> DateTime<Utc> {
+    finished_at + Duration::days(transition_days().min(MAX_TRANSITION_DAYS) as i64)
+}
+
+/// Why `dataset` can't be re-proven on the target curve here, if it can't.
+pub fn flag_reason(dataset: &db::DatasetRow) -> Option<String> {
+    if dataset.imported_from.is_some() {
+        Some("imported from another ledger; migrate it at its source and re-import".to_string())
+    } else if dataset.generator.is_none() {
+        Some(format!(
+            "uploaded records are not retained; the custodian must re-upload them to prove on {}",
+            TARGET_CURVE.name()
+        ))
+    } else if dataset.sha256_commitment {
+        Some(format!("dual-commitment circuits have no {} keys", TARGET_CURVE.name()))
+    } else if dataset.frozen_at.is_some() {
+        Some("dataset is frozen".to_string())
+    } else {
+        None
+    }
+}
+
+/// What a migration does with `dataset`, given its current migration (if any).
+pub fn plan_item(dataset_id: Uuid, dataset: &db::DatasetRow, current: Option<&db::CurveMigrationRow>) -> CurveMigrationPlanItem {
+    let (action, reason) = if dataset.status != "ready" {
+        ("skip", Some("dataset is not ready".to_string()))
+    } else if let Some(current) = current.filter(|m| matches!(m.status.as_str(), "queued" | "running" | "done")) {
+        ("skip", Some(format!("migration is {}", current.status)))
+    } else if let Some(reason) = flag_reason(dataset) {
+        ("flag", Some(reason))
+    } else {
+        ("reprove", None)
+    };
+    CurveMigrationPlanItem {
+        dataset_id,
+        action: action.to_string(),
+        reason,
+    }
+}
+
+pub fn migration_item(row: db::CurveMigrationRow) -> CurveMigrationItem {
+    let dual_serve_until = row.finished_at.filter(|_| row.status == "done").map(dual_serve_until);
+    CurveMigrationItem {
+        dataset_id: row.dataset_id,
+        status: row.status,
+        reason: row.reason,
+        shards_done: row.shards_done,
+        shards_total: row.shards_total,
+        dataset_commitment_hex: row.dataset_commitment_hex,
+        key_id: row.key_id,
+        created_at: row.created_at,
+        finished_at: row.finished_at,
+        dual_serve_until,
+    }
+}
+
+/// The dataset's commitment on the target curve, once its migration is done.
+pub async fn curve_commitment(state: &AppState, dataset_id: Uuid) -> Result<Option<CurveCommitment>, ApiError> {
+    let Some(row) = state.store.get_curve_migration(dataset_id, TARGET_CURVE).await? else {
+        return Ok(None);
+    };
+    Ok(match (row.status.as_str(), row.dataset_commitment_hex, row.key_id, row.finished_at) {
+        ("done", Some(dataset_commitment_hex), Some(key_id), Some(migrated_at)) => Some(CurveCommitment {
+            curve: row.curve,
+            dataset_commitment_hex,
+            key_id,
+            migrated_at,
+            dual_serve_until: dual_serve_until(migrated_at),
+        }),
+        _ => None,
+    })
+}
+
+/// Curve a dataset's proofs are served on when the request doesn't name one.
+pub fn default_curve(migrated: Option<&CurveCommitment>) -> Curve {
+    match migrated {
+        Some(m) if Utc::now() >= m.dual_serve_until => m.curve,
+        _ => Curve::Bn254,
+    }
+}
+
+/// Resolve the curve to serve a dataset's proofs on: `requested`, or the default. A curve the
+/// dataset hasn't been migrated to is a conflict.
+pub async fn serving_curve(state: &AppState, dataset_id: Uuid, requested: Option<Curve>) -> Result<(Curve, Option<CurveCommitment>), ApiError> {
+    let migrated = curve_commitment(state, dataset_id).await?;
+    let curve = requested.unwrap_or_else(|| default_curve(migrated.as_ref()));
+    if curve != Curve::Bn254 && migrated.as_ref().map(|m| m.curve) != Some(curve) {
+        return Err(ApiError::Conflict(format!("dataset has no {} proofs (not migrated)", curve.name())));
+    }
+    Ok((curve, migrated))
+}
+
+/// Job body for `jobs::KIND_MIGRATE_CURVE`: re-prove a synthetic dataset on the target curve (or
+/// flag it, if it stopped qualifying since it was planned).
+pub async fn run_migrate_job(state: &AppState, dataset_id: Uuid) -> Result<(), ApiError> {
+    let Some(dataset) = state.store.get_dataset(dataset_id).await? else {
+        return Err(ApiError::NotFound("dataset not found".to_string()));
+    };
+    match state.store.get_curve_migration(dataset_id, TARGET_CURVE).await? {
+        Some(m) if m.status == "done" => return Ok(()),
+        Some(_) => {}
+        None => return Err(ApiError::Conflict("no curve migration planned".to_string())),
+    }
+    if let Some(reason) = flag_reason(&dataset) {
+        return state.store.set_curve_migration_status(dataset_id, TARGET_CURVE, "flagged", Some(&reason)).await;
+    }
+
+    state.store.set_curve_migration_status(dataset_id, TARGET_CURVE, "running", None).await?;
+    match reprove(state, dataset_id, &dataset).await {
+        Ok((commitment_hex, key_id)) => {
+            state.store.set_curve_migration_done(dataset_id, TARGET_CURVE, &commitment_hex, &key_id).await?;
+            info!(%dataset_id, curve = TARGET_CURVE.name(), "curve migration done");
+            Ok(())
+        }
+        Err(e) => {
+            let _ = state.store.set_curve_migration_status(dataset_id, TARGET_CURVE, "failed", Some(&format!("{e}"))).await;
+            Err(e)
+        }
+    }
+}
+
+/// Re-prove every shard not yet re-proven with the current keys; returns the dataset commitment
+/// on the target curve and the key id.
+async fn reprove(state: &AppState, dataset_id: Uuid, dataset: &db::DatasetRow) -> Result<(String, String), ApiError> {
+    let name = dataset.generator.as_deref().ok_or(ApiError::Internal)?;
+    let source = RecordSource::Synthetic(dataset::synthetic_generator(name, dataset)?, dataset.field_set);
+    let (shard_size, field_set, shards_total) = (dataset.shard_size as usize, dataset.field_set, dataset.shards_total());
+
+    let keys = state.ensure_bls_keys(shard_size, field_set, &dataset.age_buckets).await?;
+    // Shards proven with keys that have since been replaced are redone.
+    state.store.delete_curve_shards_except(dataset_id, TARGET_CURVE, &keys.key_id).await?;
+    // Resume after a restart: shards already re-proven with these keys are kept.
+    let done: HashSet<u64> = state.store.list_curve_shards(dataset_id, TARGET_CURVE, 0..shards_total, 0, shards_total, false)
+        .await?
+        .into_iter()
+        .map(|s| s.shard_index)
+        .collect();
+
+    // The regenerated records must be the ones whose aggregates were proven on BN254.
+    let proven = state.store.list_shards(dataset_id, 0..shards_total, 0, shards_total, false).await?;
+    if proven.len() as u64 != shards_total {
+        return Err(ApiError::Conflict(format!("{} of {shards_total} shards are stored", proven.len())));
+    }
+
+    for (shard_index, _, bn254_stats, _, _) in proven.into_iter().filter(|s| !done.contains(&s.0)) {
+        let source = source.clone();
+        let buckets = dataset.age_buckets.clone();
+        let pk = keys.pk.clone();
+        let vk = keys.vk.clone();
+        let permit = state.proving_admission.acquire(keys.proof_bytes).await;
+        let (shard, master_salt) = tokio::task::spawn_blocking(move || {
+            let records = source.shard_records(shard_index, shard_size)?;
+            let (proof, commitment, stats, master_salt) =
+                prove_shard_on_for::<Bls12_381>(shard_size, field_set, &buckets, &mut rand::rngs::OsRng, &pk, records, None)
+                    .map_err(|e| ApiError::BadRequest(format!("shard {shard_index}: {e}")))?;
+            if !same_aggregates(&stats, &bn254_stats) {
+                return Err(ApiError::Conflict(format!(
+                    "shard {shard_index} regenerates with different aggregates than were proven"
+                )));
+            }
+
+            // Fail closed if the proof doesn't verify.
+            let salt_commitment = salt_commitment_on(master_salt);
+            verify_shard_proof_on(&vk, &proof, commitment, &stats, Some(salt_commitment)).map_err(|_| ApiError::Internal)?;
+            let proof_bytes = serialize_proof(&proof).map_err(|_| ApiError::Internal)?;
+            let shard = db::CurveShardRow {
+                shard_index,
+                shard_commitment_hex: field_hex(commitment)?,
+                salt_commitment_hex: field_hex(salt_commitment)?,
+                stats,
+                proof_b64: base64::engine::general_purpose::STANDARD.encode(proof_bytes),
+            };
+            Ok::<_, ApiError>((shard, master_salt))
+        })
+        .await
+        .map_err(|_| ApiError::Internal)??;
+        drop(permit);
+
+        let sealed = state.salt_sealer.seal_on(TARGET_CURVE, dataset_id, shard_index, &master_salt)?;
+        state.store.insert_curve_shard(dataset_id, TARGET_CURVE, &shard, &sealed, &keys.key_id).await?;
+        key_usage::record_proof(state, &keys.key_id, key_usage::CIRCUIT_SHARD_BLS, dataset_id).await?;
+        if shard_index % 10 == 0 {
+            info!(%dataset_id, shard_index, curve = TARGET_CURVE.name(), "re-proved shard");
+        }
+    }
+
+    let shards = state.store.list_curve_shards(dataset_id, TARGET_CURVE, 0..shards_total, 0, shards_total, false).await?;
+    let commitments = shards
+        .iter()
+        .map(|s| parse_curve_field_hex(&s.shard_commitment_hex).ok_or(ApiError::Internal))
+        .collect::<Result<Vec<BlsFr>, _>>()?;
+    if commitments.len() as u64 != shards_total {
+        return Err(ApiError::Internal);
+    }
+    Ok((dataset_commitment_hex(dataset.chain_hash, &commitments)?, keys.key_id))
+}
+
+/// Whether two shards' proven sums and counts agree (the outputs every revision proves).
+pub fn same_aggregates(a: &ShardStats, b: &ShardStats) -> bool {
+    a.sum_glucose_by_bucket == b.sum_glucose_by_bucket
+        && a.count_by_bucket == b.count_by_bucket
+        && a.extra_sums_by_bucket == b.extra_sums_by_bucket
+}
+
+/// Parse a stored commitment hex on the target curve.
+pub fn parse_curve_field_hex(hex_str: &str) -> Option<BlsFr> {
+    let bytes = hex::decode(hex_str).ok()?;
+    BlsFr::deserialize_compressed(&bytes[..]).ok()
+}
@@ -1,34 +1,40 @@
+use crate::bucket_counts;
+use crate::chain::{ChainHash, DatasetChain};
+use crate::checkpoint;
+use crate::deadline::Cancellation;
 use crate::{db, errors::ApiError};
-use crate::state::AppState;
+use crate::generator::{self, SyntheticGenerator};
+use crate::jobs::{self, JobEnd};
+use crate::key_usage;
+use crate::quota;
+use crate::models::{CodeVersions, DatasetManifest, GeneratorSpec};
+use crate::quality::{IngestQuality, ShardQuality, MAX_AGE, PLAUSIBLE_GLUCOSE_MG_DL};
+use crate::state::{AppState, ZkKeys};
+use crate::transparency;
 use base64::Engine;
+use chacha20poly1305::aead::{Aead, KeyInit};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
 use rand::{RngCore, SeedableRng};
 use rand_chacha::ChaCha20Rng;
-use tracing::info;
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use zeroize::Zeroizing;
+use tracing::{info, Instrument};
 use uuid::Uuid;
-use zk_proofs::constants::DEFAULT_SHARD_SIZE;
-use zk_proofs::groth16::{prove_shard, verify_shard_proof};
-use zk_proofs::types::{Record, ShardStats};
+use zk_proofs::constants::circuit_id;
+use zk_proofs::groth16::verify_shard_proof;
+use zk_proofs::registry::prove_shard_for;
+use zk_proofs::types::{AgeBuckets, FieldSet, Record, ShardStats};
 
-use ark_bn254::Fr;
-use ark_crypto_primitives::sponge::poseidon::PoseidonSponge;
-use ark_crypto_primitives::sponge::CryptographicSponge;
-use ark_serialize::CanonicalSerialize;
-use zk_proofs::constants::poseidon_config;
+use ark_bn254::{Bn254, Fr};
+use ark_groth16::{ProvingKey, VerifyingKey};
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize};
 
-/// Generate one synthetic record.
-///
-/// The generator is intentionally simple and deterministic.
-fn gen_record(rng: &mut ChaCha20Rng) -> Record {
-    let age = (rng.next_u32() % 121) as u8; // [0, 120]
-
-    // Blood glucose: roughly [70, 180], uniform for the prototype.
-    let glucose = 70u16 + (rng.next_u32() % 111) as u16;
-
-    Record {
-        age,
-        blood_glucose_mg_dl: glucose,
-    }
-}
+/// Human-readable description of `shard_seed`, recorded in manifests.
+const SEED_SCHEME: &str =
+    "chacha20-per-shard-v1: ChaCha20Rng::from_seed(u64le(0x485F4C4544474552) || u64le(shard_index) || [0x07; 16])";
 
 /// Derive a deterministic per-shard RNG seed.
 ///
@@ -43,87 +49,606 @@ fn shard_seed(shard_index: u64) -> [u8; 32] {
     seed
 }
 
-/// Background job: generate the synthetic dataset, prove each shard, store in the ledger.
+/// Bytes per record in the spool encoding: age (u8) + glucose, systolic BP, heart rate and BMI
+/// (u16 LE each).
+const SPOOL_RECORD_BYTES: usize = 9;
+
+/// Encrypted on-disk spool of raw records for one dataset.
 ///
-/// This NEVER writes raw records to disk and never exposes them via the API.
-pub async fn generate_dataset_and_proofs(state: AppState, dataset_id: Uuid, dataset_size: u64) {
-    let res = generate_dataset_and_proofs_inner(state.clone(), dataset_id, dataset_size).await;
-    if let Err(e) = res {
-        let _ = db::set_dataset_failed(&state.db, dataset_id, &format!("{e}"))
-            .await;
+/// Records are written as one ChaCha20-Poly1305 segment per shard under a random per-dataset key
+/// that only ever lives in memory. The file is deleted (and the key zeroized) when the spool is
+/// dropped, so plaintext records never touch disk and a leftover file from a crash is unreadable.
+pub struct EncryptedSpool {
+    path: PathBuf,
+    key: Zeroizing<[u8; 32]>,
+    /// (offset, ciphertext length) per segment.
+    segments: Vec<(u64, u32)>,
+}
+
+impl EncryptedSpool {
+    pub fn create(data_dir: &Path, dataset_id: Uuid) -> Result<Self, ApiError> {
+        let dir = spool_dir(data_dir);
+        std::fs::create_dir_all(&dir).map_err(|_| ApiError::Internal)?;
+
+        let mut key = Zeroizing::new([0u8; 32]);
+        rand::rngs::OsRng.fill_bytes(key.as_mut());
+
+        let path = dir.join(format!("{dataset_id}.spool"));
+        File::create(&path).map_err(|_| ApiError::Internal)?;
+
+        Ok(Self {
+            path,
+            key,
+            segments: Vec::new(),
+        })
+    }
+
+    fn cipher(&self) -> ChaCha20Poly1305 {
+        ChaCha20Poly1305::new(Key::from_slice(self.key.as_ref()))
+    }
+
+    /// Segment index doubles as the nonce; it is unique because each key encrypts one file.
+    fn nonce(index: u64) -> [u8; 12] {
+        let mut nonce = [0u8; 12];
+        nonce[..8].copy_from_slice(&index.to_le_bytes());
+        nonce
+    }
+
+    /// Encrypt and append one segment of records.
+    pub fn append_segment(&mut self, records: &[Record]) -> Result<(), ApiError> {
+        let mut plain = Zeroizing::new(Vec::with_capacity(records.len() * SPOOL_RECORD_BYTES));
+        for r in records {
+            plain.push(r.age);
+            for value in [r.blood_glucose_mg_dl, r.systolic_bp_mm_hg, r.heart_rate_bpm, r.bmi_x10] {
+                plain.extend_from_slice(&value.to_le_bytes());
+            }
+        }
+
+        let index = self.segments.len() as u64;
+        let ciphertext = self
+            .cipher()
+            .encrypt(Nonce::from_slice(&Self::nonce(index)), plain.as_slice())
+            .map_err(|_| ApiError::Internal)?;
+
+        let mut file = OpenOptions::new()
+            .append(true)
+            .open(&self.path)
+            .map_err(|_| ApiError::Internal)?;
+        let offset = file.seek(SeekFrom::End(0)).map_err(|_| ApiError::Internal)?;
+        file.write_all(&ciphertext).map_err(|_| ApiError::Internal)?;
+
+        self.segments.push((offset, ciphertext.len() as u32));
+        Ok(())
+    }
+
+    /// Read and decrypt one segment.
+    pub fn read_segment(&self, index: u64) -> Result<Vec<Record>, ApiError> {
+        let (offset, len) = *self.segments.get(index as usize).ok_or(ApiError::Internal)?;
+
+        let mut file = File::open(&self.path).map_err(|_| ApiError::Internal)?;
+        file.seek(SeekFrom::Start(offset)).map_err(|_| ApiError::Internal)?;
+        let mut ciphertext = vec![0u8; len as usize];
+        file.read_exact(&mut ciphertext).map_err(|_| ApiError::Internal)?;
+
+        let plain = Zeroizing::new(
+            self.cipher()
+                .decrypt(Nonce::from_slice(&Self::nonce(index)), ciphertext.as_slice())
+                .map_err(|_| ApiError::Internal)?,
+        );
+
+        Ok(plain
+            .chunks_exact(SPOOL_RECORD_BYTES)
+            .map(|c| Record {
+                age: c[0],
+                blood_glucose_mg_dl: u16::from_le_bytes([c[1], c[2]]),
+                systolic_bp_mm_hg: u16::from_le_bytes([c[3], c[4]]),
+                heart_rate_bpm: u16::from_le_bytes([c[5], c[6]]),
+                bmi_x10: u16::from_le_bytes([c[7], c[8]]),
+            })
+            .collect())
     }
 }
 
-async fn generate_dataset_and_proofs_inner(
-    state: AppState,
-    dataset_id: Uuid,
-    dataset_size: u64,
-) -> Result<(), ApiError> {
-    if dataset_size % (DEFAULT_SHARD_SIZE as u64) != 0 {
-        return Err(ApiError::BadRequest(format!(
-            "dataset_size must be a multiple of shard_size ({DEFAULT_SHARD_SIZE})"
-        )));
+impl Drop for EncryptedSpool {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
     }
+}
 
-    let num_shards = dataset_size / (DEFAULT_SHARD_SIZE as u64);
+fn spool_dir(data_dir: &Path) -> PathBuf {
+    data_dir.join("spool")
+}
 
-    let keys = state.ensure_keys().await?;
+/// Remove spool files left behind by a crash.
+///
+/// Their keys died with the process, so they are unreadable; this only reclaims disk space.
+pub fn wipe_orphaned_spools(data_dir: &Path) {
+    let _ = std::fs::remove_dir_all(spool_dir(data_dir));
+}
 
-    info!(%dataset_id, dataset_size, num_shards, "starting dataset generation");
+/// Where shard records come from.
+#[derive(Clone)]
+pub enum RecordSource {
+    /// Deterministic synthetic generator (seeded per shard), generating the field set's
+    /// measurements.
+    Synthetic(Arc<dyn SyntheticGenerator>, FieldSet),
+    /// Records supplied by a data custodian, spooled encrypted (one segment per shard).
+    Spooled(Arc<EncryptedSpool>),
+    /// One shard of records received on an ingestion stream (`stream`), held in memory until it
+    /// is proven; it serves every shard index.
+    Streamed(Arc<Vec<Record>>),
+}
 
-    let poseidon_cfg = poseidon_config();
-    let mut dataset_sponge = PoseidonSponge::<Fr>::new(&poseidon_cfg);
+impl RecordSource {
+    pub fn shard_records(&self, shard_index: u64, shard_size: usize) -> Result<Vec<Record>, ApiError> {
+        match self {
+            RecordSource::Synthetic(generator, field_set) => {
+                let mut record_rng = ChaCha20Rng::from_seed(shard_seed(shard_index));
+                let mut records = Vec::with_capacity(shard_size);
+                for _ in 0..shard_size {
+                    let mut record = generator.gen_record(&mut record_rng);
+                    // Glucose-only datasets draw nothing more, so they regenerate as before.
+                    if *field_set == FieldSet::Vitals {
+                        generator.gen_vitals(&mut record_rng, &mut record);
+                    }
+                    records.push(record);
+                }
+                Ok(records)
+            }
+            RecordSource::Spooled(spool) => spool.read_segment(shard_index),
+            RecordSource::Streamed(records) => Ok(records.as_ref().clone()),
+        }
+    }
+}
 
-    for shard_index in 0..num_shards {
-        let pk = keys.pk.clone();
-        let vk = keys.vk.clone();
+/// Parse uploaded CSV bytes into records.
+///
+/// Expected header: `age,blood_glucose` (or `blood_glucose_mg_dl`); `vitals` uploads also need
+/// `systolic_bp`, `heart_rate` and `bmi` (kg/m², up to one decimal). Column order is taken from
+/// the header; extra columns are ignored. Parsing happens in memory only.
+///
+/// Rows with a missing or invalid value, including glucose outside `PLAUSIBLE_GLUCOSE_MG_DL` (which
+/// the shard circuit can't prove), are dropped and counted in the returned [`IngestQuality`]
+/// rather than failing the whole upload.
+pub fn parse_csv_records(bytes: &[u8], field_set: FieldSet) -> Result<(Vec<Record>, IngestQuality), ApiError> {
+    let text = std::str::from_utf8(bytes).map_err(|_| ApiError::BadRequest("csv must be utf-8".to_string()))?;
+    let mut lines = text.lines().filter(|l| !l.trim().is_empty());
 
-        // Generate + prove shard on a blocking thread.
-        let (shard_commitment, stats, proof_b64, shard_commitment_hex) = tokio::task::spawn_blocking(move || {
-            let mut record_rng = ChaCha20Rng::from_seed(shard_seed(shard_index));
+    let header = lines
+        .next()
+        .ok_or_else(|| ApiError::BadRequest("csv is empty".to_string()))?;
+    let columns: Vec<&str> = header.split(',').map(|c| c.trim()).collect();
+    let age_col = columns
+        .iter()
+        .position(|c| *c == "age")
+        .ok_or_else(|| ApiError::BadRequest("csv header must contain 'age'".to_string()))?;
+    let glucose_col = columns
+        .iter()
+        .position(|c| *c == "blood_glucose" || *c == "blood_glucose_mg_dl")
+        .ok_or_else(|| ApiError::BadRequest("csv header must contain 'blood_glucose'".to_string()))?;
+    let vitals_cols = match field_set {
+        FieldSet::Glucose => None,
+        FieldSet::Vitals => {
+            let mut cols = [0usize; 3];
+            for (col, name) in cols.iter_mut().zip(["systolic_bp", "heart_rate", "bmi"]) {
+                *col = columns
+                    .iter()
+                    .position(|c| *c == name)
+                    .ok_or_else(|| ApiError::BadRequest(format!("csv header must contain '{name}' for vitals datasets")))?;
+            }
+            Some(cols)
+        }
+    };
 
-            let mut records = Vec::with_capacity(DEFAULT_SHARD_SIZE);
-            for _ in 0..DEFAULT_SHARD_SIZE {
-                records.push(gen_record(&mut record_rng));
+    let mut records = Vec::new();
+    let mut quality = IngestQuality::default();
+    for line in lines {
+        quality.rows_read += 1;
+        let fields: Vec<&str> = line.split(',').map(|f| f.trim()).collect();
+
+        let age = match fields.get(age_col).filter(|f| !f.is_empty()) {
+            None => {
+                quality.missing_age += 1;
+                continue;
+            }
+            Some(f) => match f.parse::<u8>() {
+                Ok(age) if age <= MAX_AGE => age,
+                _ => {
+                    quality.invalid_age += 1;
+                    continue;
+                }
+            },
+        };
+        let glucose = match fields.get(glucose_col).filter(|f| !f.is_empty()) {
+            None => {
+                quality.missing_glucose += 1;
+                continue;
             }
+            Some(f) => match f.parse::<u16>() {
+                Ok(glucose) if (PLAUSIBLE_GLUCOSE_MG_DL.0..=PLAUSIBLE_GLUCOSE_MG_DL.1).contains(&glucose) => glucose,
+                _ => {
+                    quality.invalid_glucose += 1;
+                    continue;
+                }
+            },
+        };
 
-            // Use OS randomness for the proof to avoid deterministic proofs.
-            let mut proof_rng = rand::rngs::OsRng;
-            let (proof, shard_commitment, stats) = prove_shard::<DEFAULT_SHARD_SIZE>(&mut proof_rng, pk.as_ref(), records)
-                .map_err(|_| ApiError::Internal)?;
+        let mut record = Record {
+            age,
+            blood_glucose_mg_dl: glucose,
+            ..Record::default()
+        };
+        if let Some([bp_col, hr_col, bmi_col]) = vitals_cols {
+            let [bp, hr, bmi] = [bp_col, hr_col, bmi_col].map(|col| fields.get(col).copied().filter(|f| !f.is_empty()));
+            let (Some(bp), Some(hr), Some(bmi)) = (bp, hr, bmi) else {
+                quality.missing_measurement += 1;
+                continue;
+            };
+            match (bp.parse::<u16>(), hr.parse::<u16>(), parse_bmi_x10(bmi)) {
+                (Ok(bp), Ok(hr), Some(bmi)) => {
+                    record.systolic_bp_mm_hg = bp;
+                    record.heart_rate_bpm = hr;
+                    record.bmi_x10 = bmi;
+                }
+                _ => {
+                    quality.invalid_measurement += 1;
+                    continue;
+                }
+            }
+        }
 
-            // Fail closed if proof doesn't verify.
-            verify_shard_proof(vk.as_ref(), &proof, shard_commitment, &stats).map_err(|_| ApiError::Internal)?;
+        quality.rows_accepted += 1;
+        records.push(record);
+    }
 
-            let b64 = base64::engine::general_purpose::STANDARD;
-            let proof_bytes = zk_proofs::groth16::serialize_proof(&proof).map_err(|_| ApiError::Internal)?;
-            let proof_b64 = b64.encode(proof_bytes);
+    Ok((records, quality))
+}
 
-            let mut commitment_bytes = Vec::new();
-            shard_commitment
-                .serialize_compressed(&mut commitment_bytes)
-                .map_err(|_| ApiError::Internal)?;
-            let shard_commitment_hex = hex::encode(commitment_bytes);
+/// BMI in tenths of kg/m² from a decimal like `23.1`; more than one decimal is rejected.
+fn parse_bmi_x10(s: &str) -> Option<u16> {
+    let (whole, tenths) = s.split_once('.').unwrap_or((s, "0"));
+    if tenths.len() != 1 {
+        return None;
+    }
+    let whole: u16 = whole.parse().ok()?;
+    let tenths: u16 = tenths.parse().ok()?;
+    whole.checked_mul(10)?.checked_add(tenths)
+}
 
-            Ok::<(Fr, ShardStats, String, String), ApiError>((shard_commitment, stats, proof_b64, shard_commitment_hex))
-        })
-        .await
-        .map_err(|_| ApiError::Internal)??;
+/// Settings for a dataset created from uploaded records.
+pub struct CsvIngestOptions<'a> {
+    pub shard_size: usize,
+    pub field_set: FieldSet,
+    pub age_buckets: &'a AgeBuckets,
+    pub chain_hash: ChainHash,
+    pub sha256_commitment: bool,
+    pub consent_scope: Option<&'a [String]>,
+    pub requires_approval: bool,
+    pub release_limit: Option<u64>,
+}
 
-        // Update dataset commitment.
-        dataset_sponge.absorb(&[shard_commitment]);
+/// Ingest a CSV of real records: parse it in memory, check that the accepted records fill whole
+/// shards and fit `owner`'s quota, register the dataset and queue it for proving.
+///
+/// Only the parse report and, once proven, commitments, proofs and aggregates are stored; the
+/// records stay in memory until [`ingest_records`] moves them into the encrypted spool.
+pub async fn ingest_csv(state: &AppState, owner: &str, bytes: &[u8], options: &CsvIngestOptions<'_>) -> Result<Uuid, ApiError> {
+    let shard_size = options.shard_size;
+    let (records, ingest_quality) = parse_csv_records(bytes, options.field_set)?;
+    if records.is_empty() || records.len() % shard_size != 0 {
+        return Err(ApiError::BadRequest(format!(
+            "accepted record count must be a non-zero multiple of shard_size ({shard_size}), got {} ({} rows rejected)",
+            records.len(),
+            ingest_quality.rows_rejected()
+        )));
+    }
+
+    quota::enforce_new_dataset(state, owner, records.len() as u64).await?;
 
-        // Persist shard.
-        db::insert_shard(
-            &state.db,
+    let dataset_id = Uuid::new_v4();
+    state.store.insert_dataset(
+        &db::NewDataset {
             dataset_id,
-            shard_index,
-            &shard_commitment_hex,
-            &stats,
-            &proof_b64,
-            true,
-        )
-        .await?;
+            dataset_size: records.len() as u64,
+            shard_size: shard_size as u64,
+            field_set: options.field_set,
+            age_buckets: options.age_buckets,
+            chain_hash: options.chain_hash,
+            sha256_commitment: options.sha256_commitment,
+            consent_scope: options.consent_scope,
+            requires_approval: options.requires_approval,
+            release_limit: options.release_limit,
+            generator: None,
+            generator_params: None,
+            ingest_quality: &ingest_quality,
+            window_shards: None,
+            owner,
+        },
+    )
+    .await?;
+
+    tokio::spawn(ingest_records(state.clone(), dataset_id, records, shard_size, owner.to_string()));
+
+    Ok(dataset_id)
+}
+
+/// The synthetic generator `name` of `dataset`, configured with its parameters.
+pub fn synthetic_generator(name: &str, dataset: &db::DatasetRow) -> Result<Arc<dyn SyntheticGenerator>, ApiError> {
+    generator::resolve(name, dataset.generator_params.as_ref()).map_err(ApiError::BadRequest)
+}
+
+/// Job body for `jobs::KIND_PROVE_DATASET`: generate (or read back) the records, prove each
+/// shard, store in the ledger.
+///
+/// Synthetic records are regenerated from the dataset's generator; uploaded records come from the
+/// encrypted spool registered by [`ingest_records`]. Raw records are NEVER written to disk in
+/// plaintext and never exposed via the API.
+///
+/// A run stops before its next shard once the dataset is cancelled (`POST
+/// /api/v1/datasets/:id/cancel`): at once through its token on this instance, else when it next
+/// reads the dataset's status. Its proven shards are kept. A run of synthetic records also stops
+/// there on shutdown, checkpointed to resume on the next start (see `shutdown`).
+pub async fn run_prove_job(state: &AppState, dataset_id: Uuid) -> Result<JobEnd, ApiError> {
+    let cancel = state.start_proving_run(dataset_id);
+    let res = prove_dataset(state, dataset_id, &cancel).await;
+    state.end_proving_run(dataset_id);
+    if let Err(e) = &res {
+        checkpoint::remove(&state.data_dir, dataset_id);
+        // A no-op for cancelled datasets, which stop with an error too.
+        let _ = state.store.set_dataset_failed(dataset_id, &format!("{e}")).await;
+        if let Ok(Some(dataset)) = state.store.get_dataset(dataset_id).await {
+            let shards_done = state.store.count_shards_done(dataset_id).await.unwrap_or_default();
+            state.progress.run_finished(dataset_id, &dataset.status, shards_done, dataset.shards_total());
+            if dataset.status == "cancelled" {
+                return Ok(JobEnd::Finished);
+            }
+        }
+    }
+    res
+}
+
+/// Whether `dataset_id`'s generation has been cancelled, by `cancel` or in the ledger.
+async fn generation_cancelled(state: &AppState, dataset_id: Uuid, cancel: &Cancellation) -> Result<bool, ApiError> {
+    if cancel.is_cancelled() {
+        return Ok(true);
+    }
+    Ok(state.store.get_dataset(dataset_id).await?.is_some_and(|d| d.status == "cancelled"))
+}
+
+#[tracing::instrument(skip(state, cancel))]
+async fn prove_dataset(state: &AppState, dataset_id: Uuid, cancel: &Cancellation) -> Result<JobEnd, ApiError> {
+    let Some(dataset) = state.store.get_dataset(dataset_id).await? else {
+        return Err(ApiError::NotFound("dataset not found".to_string()));
+    };
+    if dataset.frozen_at.is_some() {
+        return Err(ApiError::Conflict("dataset is frozen".to_string()));
+    }
+    if dataset.status == "cancelled" {
+        return Err(ApiError::Conflict("dataset generation was cancelled".to_string()));
+    }
+
+    let source = match dataset.generator.as_deref() {
+        Some(name) => RecordSource::Synthetic(synthetic_generator(name, &dataset)?, dataset.field_set),
+        None => {
+            let spool = state.spools.lock().await.remove(&dataset_id).ok_or_else(|| {
+                ApiError::Conflict("upload spool lost (server restarted before proving); re-upload".to_string())
+            })?;
+            RecordSource::Spooled(spool)
+        }
+    };
+
+    prove_dataset_inner(state.clone(), dataset_id, &dataset, source, cancel).await
+}
+
+/// Spool an uploaded record set and queue it for proving.
+///
+/// Records are moved into an encrypted spool (one segment per shard) so the plaintext can be
+/// dropped from memory before the long proving run; the spool is wiped once proving ends.
+pub async fn ingest_records(state: AppState, dataset_id: Uuid, records: Vec<Record>, shard_size: usize, tenant: String) {
+    let data_dir = state.data_dir.clone();
+
+    let spool = tokio::task::spawn_blocking(move || {
+        let mut spool = EncryptedSpool::create(&data_dir, dataset_id)?;
+        for shard in records.chunks(shard_size) {
+            spool.append_segment(shard)?;
+        }
+        Ok::<EncryptedSpool, ApiError>(spool)
+    })
+    .await
+    .map_err(|_| ApiError::Internal)
+    .and_then(|r| r);
+
+    let queued = match spool {
+        Ok(spool) => {
+            state.spools.lock().await.insert(dataset_id, Arc::new(spool));
+            // The spool (and its key) only exist on this instance.
+            jobs::enqueue_here(&state, jobs::KIND_PROVE_DATASET, dataset_id, &tenant).await
+        }
+        Err(e) => Err(e),
+    };
+    if let Err(e) = queued {
+        state.spools.lock().await.remove(&dataset_id);
+        let _ = state.store.set_dataset_failed(dataset_id, &format!("{e}")).await;
+    }
+}
+
+pub fn build_manifest(dataset_id: Uuid, dataset: &db::DatasetRow, source: &RecordSource, keys: &ZkKeys) -> DatasetManifest {
+    let shard_size = dataset.shard_size as usize;
+    let (source_name, generator, seed_scheme) = match source {
+        RecordSource::Synthetic(generator, _) => (
+            "synthetic",
+            Some(GeneratorSpec {
+                name: generator.name().to_string(),
+                params: generator.params(),
+            }),
+            Some(SEED_SCHEME.to_string()),
+        ),
+        RecordSource::Spooled(_) => ("upload", None, None),
+        RecordSource::Streamed(_) => ("stream", None, None),
+    };
+
+    DatasetManifest {
+        manifest_version: 1,
+        dataset_id,
+        dataset_size: dataset.dataset_size,
+        shard_size: dataset.shard_size,
+        field_set: dataset.field_set,
+        num_buckets: dataset.age_buckets.num_buckets() as u64,
+        age_buckets: dataset.age_buckets.bounds().to_vec(),
+        source: source_name.to_string(),
+        window_shards: dataset.window_shards,
+        generator,
+        seed_scheme,
+        circuit_id: circuit_id(shard_size, dataset.field_set, &dataset.age_buckets, keys.revision, keys.sha256_commitment),
+        chain_hash: dataset.chain_hash,
+        sha256_commitment: keys.sha256_commitment,
+        proof_system: "groth16".to_string(),
+        curve: "bn254".to_string(),
+        key_id: keys.key_id.clone(),
+        code_versions: CodeVersions {
+            backend: env!("CARGO_PKG_VERSION").to_string(),
+            zk_proofs: zk_proofs::VERSION.to_string(),
+        },
+        bucket_counts: None,
+    }
+}
+
+/// Hex of a compressed field element, as stored for shard and (Poseidon) dataset commitments.
+pub fn field_hex(f: impl CanonicalSerialize) -> Result<String, ApiError> {
+    let mut bytes = Vec::new();
+    f.serialize_compressed(&mut bytes).map_err(|_| ApiError::Internal)?;
+    Ok(hex::encode(bytes))
+}
+
+/// Parse a stored commitment hex back into a field element.
+pub fn parse_field_hex(hex_str: &str) -> Option<Fr> {
+    let bytes = hex::decode(hex_str).ok()?;
+    Fr::deserialize_compressed(&bytes[..]).ok()
+}
+
+/// Failure classes recorded per shard.
+const FAILURE_RECORDS: &str = "records";
+const FAILURE_PROVE: &str = "prove";
+const FAILURE_VERIFY: &str = "verify";
+const FAILURE_SERIALIZE: &str = "serialize";
+const FAILURE_PANIC: &str = "panic";
+
+const DEFAULT_SHARD_PROVE_ATTEMPTS: u32 = 2;
+
+/// Attempts per shard before the dataset fails (`SHARD_PROVE_ATTEMPTS`, default 2).
+fn shard_prove_attempts() -> u32 {
+    std::env::var("SHARD_PROVE_ATTEMPTS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .filter(|n| *n > 0)
+        .unwrap_or(DEFAULT_SHARD_PROVE_ATTEMPTS)
+}
+
+struct ShardFailure {
+    class: &'static str,
+    message: String,
+}
+
+impl ShardFailure {
+    fn new(class: &'static str, error: impl std::fmt::Display) -> Self {
+        Self {
+            class,
+            message: error.to_string(),
+        }
+    }
+}
+
+/// Commitment, stats, quality, proof (b64), commitment hex and master salt (salted circuits only)
+/// of one proven shard.
+pub type ProvenShard = (Fr, ShardStats, ShardQuality, String, String, Option<Fr>);
+
+fn prove_one_shard(
+    source: &RecordSource,
+    shard_index: u64,
+    shard_size: usize,
+    field_set: FieldSet,
+    buckets: &AgeBuckets,
+    pk: &ProvingKey<Bn254>,
+    vk: &VerifyingKey<Bn254>,
+) -> Result<ProvenShard, ShardFailure> {
+    let records = source
+        .shard_records(shard_index, shard_size)
+        .map_err(|e| ShardFailure::new(FAILURE_RECORDS, e))?;
+    let quality = ShardQuality::compute(&records, buckets);
+
+    // Use OS randomness for the proof (and the master salt) to avoid deterministic proofs.
+    let mut proof_rng = rand::rngs::OsRng;
+    let (proof, shard_commitment, stats, master_salt) = prove_shard_for(shard_size, field_set, buckets, &mut proof_rng, pk, records, None)
+        .map_err(|e| ShardFailure::new(FAILURE_PROVE, e))?;
+
+    // Fail closed if proof doesn't verify.
+    verify_shard_proof(vk, &proof, shard_commitment, &stats).map_err(|e| ShardFailure::new(FAILURE_VERIFY, e))?;
+
+    let b64 = base64::engine::general_purpose::STANDARD;
+    let proof_bytes = zk_proofs::groth16::serialize_proof(&proof).map_err(|e| ShardFailure::new(FAILURE_SERIALIZE, e))?;
+    let proof_b64 = b64.encode(proof_bytes);
+
+    let shard_commitment_hex = field_hex(shard_commitment).map_err(|e| ShardFailure::new(FAILURE_SERIALIZE, e))?;
+
+    Ok((shard_commitment, stats, quality, proof_b64, shard_commitment_hex, master_salt))
+}
+
+async fn prove_dataset_inner(
+    state: AppState,
+    dataset_id: Uuid,
+    dataset: &db::DatasetRow,
+    source: RecordSource,
+    cancel: &Cancellation,
+) -> Result<JobEnd, ApiError> {
+    let (dataset_size, shard_size, field_set, chain_hash) =
+        (dataset.dataset_size, dataset.shard_size as usize, dataset.field_set, dataset.chain_hash);
+    if dataset_size % (shard_size as u64) != 0 {
+        return Err(ApiError::BadRequest(format!(
+            "dataset_size must be a multiple of shard_size ({shard_size})"
+        )));
+    }
+
+    let num_shards = dataset_size / (shard_size as u64);
+
+    let keys = state.ensure_keys_for(shard_size, field_set, &dataset.age_buckets, dataset.sha256_commitment).await?;
+
+    let manifest = build_manifest(dataset_id, dataset, &source, &keys);
+    state.store.set_dataset_manifest(dataset_id, &serde_json::to_value(&manifest).map_err(|_| ApiError::Internal)?).await?;
+
+    // Synthetic records can be regenerated after a restart, so their runs are checkpointed.
+    let resumable = matches!(source, RecordSource::Synthetic(..));
+    let (first_shard, mut dataset_chain) = if resumable {
+        checkpoint::resume_point(&state, dataset_id, dataset, &keys).await?
+    } else {
+        (0, DatasetChain::new(chain_hash))
+    };
+    let mut checkpointer = checkpoint::Checkpointer::new(dataset_id, dataset, &keys);
+
+    info!(%dataset_id, dataset_size, num_shards, first_shard, "starting dataset generation");
+    state.progress.run_started(dataset_id, first_shard);
+
+    let mut last_stored = None;
+    for shard_index in first_shard..num_shards {
+        if resumable && state.shutdown.is_cancelled() {
+            if let Some((last, commitment)) = last_stored {
+                checkpointer.save_now(&state.data_dir, last, &commitment, &dataset_chain);
+            }
+            info!(%dataset_id, shard_index, "dataset generation interrupted by shutdown");
+            return Ok(JobEnd::Interrupted);
+        }
+        if generation_cancelled(&state, dataset_id, cancel).await? {
+            info!(%dataset_id, shard_index, "dataset generation cancelled");
+            return Err(ApiError::Conflict("dataset generation was cancelled".to_string()));
+        }
+        let shard_commitment = prove_and_store_shard(&state, dataset_id, dataset, &keys, &source, shard_index).await?;
+
+        // Update dataset commitment.
+        dataset_chain.absorb(&shard_commitment)?;
+        if resumable {
+            checkpointer.shard_done(&state.data_dir, shard_index, &shard_commitment, &dataset_chain);
+        }
+        last_stored = Some((shard_index, shard_commitment));
+        state.progress.shard_done(dataset_id, shard_index + 1, num_shards);
 
         if shard_index % 10 == 0 {
             info!(%dataset_id, shard_index, "generated shard");
@@ -131,15 +656,137 @@ async fn generate_dataset_and_proofs_inner(
     }
 
     // Derive dataset commitment.
-    let dataset_commitment = dataset_sponge.squeeze_field_elements(1)[0];
-    let mut bytes = Vec::new();
-    dataset_commitment
-        .serialize_compressed(&mut bytes)
-        .map_err(|_| ApiError::Internal)?;
-    let dataset_commitment_hex = hex::encode(bytes);
+    let dataset_commitment_hex = dataset_chain.finish_hex()?;
 
-    db::set_dataset_ready(&state.db, dataset_id, &dataset_commitment_hex).await?;
+    state.store.set_dataset_ready(dataset_id, &dataset_commitment_hex).await?;
+    transparency::log_dataset(&state, dataset_id, &dataset_commitment_hex).await?;
+    bucket_counts::commit(&state, dataset_id, dataset, &dataset_commitment_hex).await?;
+    state.progress.run_finished(dataset_id, "ready", num_shards, num_shards);
+    checkpoint::remove(&state.data_dir, dataset_id);
+    audit_expired_shards(&state, dataset_id, dataset, 0).await?;
 
     info!(%dataset_id, "dataset ready");
+    Ok(JobEnd::Finished)
+}
+
+/// Prove shard `shard_index` of `source` and store it with its quality counts and sealed master
+/// salt. Returns the shard commitment.
+pub async fn prove_and_store_shard(
+    state: &AppState,
+    dataset_id: Uuid,
+    dataset: &db::DatasetRow,
+    keys: &ZkKeys,
+    source: &RecordSource,
+    shard_index: u64,
+) -> Result<Fr, ApiError> {
+    let proven = prove_shard(state, dataset_id, dataset, keys, source, shard_index).await?;
+    store_proven_shard(state, dataset_id, shard_index, &proven, &keys.key_id).await?;
+    Ok(proven.0)
+}
+
+/// Prove shard `shard_index` of `source` on a blocking thread, retrying transient failures.
+///
+/// Every failed attempt is recorded in `shard_failures`; after `SHARD_PROVE_ATTEMPTS` the last
+/// failure is returned.
+#[tracing::instrument(skip(state, dataset, keys, source))]
+pub async fn prove_shard(
+    state: &AppState,
+    dataset_id: Uuid,
+    dataset: &db::DatasetRow,
+    keys: &ZkKeys,
+    source: &RecordSource,
+    shard_index: u64,
+) -> Result<ProvenShard, ApiError> {
+    let (shard_size, field_set) = (dataset.shard_size as usize, dataset.field_set);
+    let max_attempts = shard_prove_attempts();
+
+    let mut attempt = 0;
+    loop {
+        attempt += 1;
+        let pk = keys.pk.clone();
+        let vk = keys.vk.clone();
+        let source = source.clone();
+        let buckets = dataset.age_buckets.clone();
+
+        let permit = state
+            .proving_admission
+            .acquire(keys.proof_bytes)
+            .instrument(tracing::info_span!("proving_admission", bytes = keys.proof_bytes))
+            .await;
+        let span = tracing::Span::current();
+        let res = tokio::task::spawn_blocking(move || {
+            span.in_scope(|| prove_one_shard(&source, shard_index, shard_size, field_set, &buckets, &pk, &vk))
+        })
+        .await
+        .unwrap_or_else(|e| Err(ShardFailure::new(FAILURE_PANIC, e)));
+        drop(permit);
+
+        match res {
+            Ok(proven) => {
+                key_usage::record_proof(state, &keys.key_id, key_usage::CIRCUIT_SHARD, dataset_id).await?;
+                return Ok(proven);
+            }
+            Err(failure) => {
+                state.store.record_shard_failure(dataset_id, shard_index, failure.class, &failure.message).await?;
+                tracing::warn!(%dataset_id, shard_index, attempt, class = failure.class, error = %failure.message, "shard failed");
+                if attempt >= max_attempts {
+                    return Err(ApiError::Conflict(format!(
+                        "shard {shard_index} failed after {attempt} attempt(s): {}: {}",
+                        failure.class, failure.message
+                    )));
+                }
+            }
+        }
+    }
+}
+
+/// Store a proven shard (replacing any stored one) with its quality counts, sealed master salt and
+/// the id of the key it was proven and verified with (`vk_version`), and log its commitment.
+#[tracing::instrument(name = "store_shard", skip(state, proven, vk_version))]
+pub async fn store_proven_shard(
+    state: &AppState,
+    dataset_id: Uuid,
+    shard_index: u64,
+    proven: &ProvenShard,
+    vk_version: &str,
+) -> Result<(), ApiError> {
+    let (_, stats, quality, proof_b64, shard_commitment_hex, master_salt) = proven;
+    state.store.insert_shard(
+        dataset_id,
+        shard_index,
+        shard_commitment_hex,
+        stats,
+        proof_b64,
+        true,
+    )
+    .await?;
+    transparency::log_shard(state, dataset_id, shard_index, shard_commitment_hex).await?;
+    state.store.set_shard_quality(dataset_id, shard_index, quality).await?;
+    state.store.set_shard_vk_version(dataset_id, shard_index, vk_version).await?;
+    state.store.set_shards_verified(dataset_id, &[shard_index], vk_version).await?;
+    if let Some(master_salt) = master_salt {
+        let sealed = state.salt_sealer.seal(dataset_id, shard_index, *master_salt)?;
+        state.store.set_shard_sealed_master_salt(dataset_id, shard_index, &sealed).await?;
+    }
+    Ok(())
+}
+
+/// Record in the audit chain the shards of `dataset` (at its current size) that left its rolling
+/// window since its live shards started at `live_from`.
+pub async fn audit_expired_shards(state: &AppState, dataset_id: Uuid, dataset: &db::DatasetRow, live_from: u64) -> Result<(), ApiError> {
+    let expired = live_from..dataset.live_shards().start;
+    if expired.is_empty() {
+        return Ok(());
+    }
+    state.store.append_audit(
+        Some(dataset_id),
+        "shards_expired",
+        &serde_json::json!({
+            "shard_index_from": expired.start,
+            "shard_index_to": expired.end,
+            "window_shards": dataset.window_shards,
+        }),
+    )
+    .await?;
     Ok(())
 }
@@ -1,22 +1,75 @@
+use crate::chain::ChainHash;
 use crate::errors::ApiError;
-use crate::models::Metric;
+use crate::proof_files::ProofFiles;
+use crate::models::{AnomalyWarning, DpParams, DpRelease, Metric, QueryProofStatement, QueryPurpose, QueryShardSet};
+use crate::quality::{IngestQuality, ShardQuality};
 use chrono::{DateTime, Utc};
 use serde_json::json;
-use sqlx::{sqlite::SqlitePoolOptions, Pool, Row, Sqlite};
+use sha2::{Digest, Sha256};
+use sqlx::{sqlite::{SqlitePoolOptions, SqliteRow}, Executor, Pool, Row, Sqlite};
+use tokio::sync::Mutex;
 use uuid::Uuid;
-use zk_proofs::constants::{DEFAULT_SHARD_SIZE, NUM_BUCKETS};
-use zk_proofs::types::ShardStats;
+use zk_proofs::constants::NUM_GLUCOSE_RANGES;
+use zk_proofs::types::{AgeBuckets, Curve, FieldSet, Measurement, ShardStats};
 
 pub type Db = Pool<Sqlite>;
 
+/// Column access shared by SQLite rows and those of the Postgres ledger (`pg`), whose tables use
+/// the same column types: text as `String`, integers as `i64`.
+pub trait LedgerRow {
+    fn text(&self, i: usize) -> String;
+    fn opt_text(&self, i: usize) -> Option<String>;
+    fn int(&self, i: usize) -> i64;
+    fn opt_int(&self, i: usize) -> Option<i64>;
+}
+
+impl LedgerRow for SqliteRow {
+    fn text(&self, i: usize) -> String {
+        self.get(i)
+    }
+
+    fn opt_text(&self, i: usize) -> Option<String> {
+        self.get(i)
+    }
+
+    fn int(&self, i: usize) -> i64 {
+        self.get(i)
+    }
+
+    fn opt_int(&self, i: usize) -> Option<i64> {
+        self.get(i)
+    }
+}
+
+fn parse_time(t: &str) -> Result<DateTime<Utc>, ApiError> {
+    DateTime::parse_from_rfc3339(t)
+        .map(|t| t.with_timezone(&Utc))
+        .map_err(|_| ApiError::Internal)
+}
+
 pub async fn connect(db_url: &str) -> Result<Db, ApiError> {
-    SqlitePoolOptions::new()
-        .max_connections(5)
+    // Every connection to `sqlite::memory:` opens its own empty database, so an in-memory ledger
+    // lives on a single connection that is never closed.
+    let options = if db_url.contains(":memory:") {
+        SqlitePoolOptions::new()
+            .max_connections(1)
+            .min_connections(1)
+            .idle_timeout(None)
+            .max_lifetime(None)
+    } else {
+        SqlitePoolOptions::new().max_connections(5)
+    };
+    options
         .connect(db_url)
         .await
         .map_err(|_| ApiError::Internal)
 }
 
+pub async fn ping(db: &Db) -> Result<(), ApiError> {
+    sqlx::query("SELECT 1").execute(db).await.map_err(|_| ApiError::Internal)?;
+    Ok(())
+}
+
 pub async fn init_schema(db: &Db) -> Result<(), ApiError> {
     // NOTE: Keep schema minimal and explicit. This is an append-only-ish ledger prototype.
     sqlx::query(
@@ -50,29 +103,499 @@ CREATE TABLE IF NOT EXISTS queries (
   result_json TEXT NOT NULL,
   verified INTEGER NOT NULL
 );
+
+CREATE TABLE IF NOT EXISTS audit_log (
+  seq INTEGER PRIMARY KEY AUTOINCREMENT,
+  created_at TEXT NOT NULL,
+  dataset_id TEXT,
+  event TEXT NOT NULL,
+  details_json TEXT NOT NULL,
+  prev_hash TEXT NOT NULL,
+  entry_hash TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS transparency_log (
+  seq INTEGER PRIMARY KEY,
+  created_at TEXT NOT NULL,
+  kind TEXT NOT NULL,
+  dataset_id TEXT NOT NULL,
+  shard_index INTEGER,
+  commitment_hex TEXT NOT NULL,
+  leaf_hash TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS jobs (
+  id TEXT PRIMARY KEY,
+  kind TEXT NOT NULL,
+  subject_id TEXT NOT NULL,
+  status TEXT NOT NULL,
+  created_at TEXT NOT NULL,
+  started_at TEXT,
+  finished_at TEXT,
+  error TEXT
+);
+
+CREATE TABLE IF NOT EXISTS shard_failures (
+  dataset_id TEXT NOT NULL,
+  shard_index INTEGER NOT NULL,
+  error_class TEXT NOT NULL,
+  attempts INTEGER NOT NULL,
+  last_error TEXT NOT NULL,
+  first_failed_at TEXT NOT NULL,
+  last_failed_at TEXT NOT NULL,
+  PRIMARY KEY(dataset_id, shard_index)
+);
+
+CREATE TABLE IF NOT EXISTS proof_blobs (
+  hash TEXT PRIMARY KEY,
+  proof_b64 TEXT NOT NULL,
+  created_at TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS aggregates (
+  dataset_id TEXT NOT NULL,
+  bucket_index INTEGER NOT NULL,
+  shards INTEGER NOT NULL,
+  count INTEGER NOT NULL,
+  sums_json TEXT NOT NULL,
+  sum_sq INTEGER NOT NULL,
+  sum_sq_shards INTEGER NOT NULL,
+  histogram_json TEXT NOT NULL,
+  histogram_shards INTEGER NOT NULL,
+  PRIMARY KEY(dataset_id, bucket_index)
+);
+
+CREATE TABLE IF NOT EXISTS dataset_acl (
+  dataset_id TEXT NOT NULL,
+  grantee_kind TEXT NOT NULL,
+  grantee TEXT NOT NULL,
+  granted_by TEXT NOT NULL,
+  granted_at TEXT NOT NULL,
+  PRIMARY KEY(dataset_id, grantee_kind, grantee)
+);
+
+CREATE TABLE IF NOT EXISTS api_keys (
+  key_id TEXT PRIMARY KEY,
+  key_hash TEXT NOT NULL UNIQUE,
+  name TEXT NOT NULL,
+  role TEXT NOT NULL,
+  scopes_json TEXT,
+  created_by TEXT NOT NULL,
+  created_at TEXT NOT NULL,
+  revoked_at TEXT
+);
+
+CREATE TABLE IF NOT EXISTS query_quota_usage (
+  key_id TEXT NOT NULL,
+  day TEXT NOT NULL,
+  queries INTEGER NOT NULL,
+  PRIMARY KEY(key_id, day)
+);
+
+CREATE TABLE IF NOT EXISTS privacy_budget (
+  dataset_id TEXT PRIMARY KEY,
+  epsilon_spent REAL NOT NULL,
+  delta_spent REAL NOT NULL,
+  releases INTEGER NOT NULL,
+  updated_at TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS dataset_tombstones (
+  dataset_id TEXT PRIMARY KEY,
+  deleted_at TEXT NOT NULL,
+  deleted_by TEXT NOT NULL,
+  reason TEXT NOT NULL,
+  dataset_commitment_hex TEXT
+);
+
+CREATE TABLE IF NOT EXISTS released_cells (
+  query_id TEXT NOT NULL,
+  dataset_id TEXT NOT NULL,
+  bucket_index INTEGER NOT NULL,
+  filter_key TEXT NOT NULL,
+  released_at TEXT NOT NULL,
+  PRIMARY KEY(query_id, bucket_index, filter_key)
+);
+
+CREATE TABLE IF NOT EXISTS aggregate_proofs (
+  dataset_id TEXT PRIMARY KEY,
+  created_at TEXT NOT NULL,
+  dataset_commitment_hex TEXT NOT NULL,
+  shard_inputs_root_hex TEXT NOT NULL,
+  shards_total INTEGER NOT NULL,
+  totals_json TEXT NOT NULL,
+  proof_b64 TEXT NOT NULL,
+  vk_b64 TEXT NOT NULL,
+  key_id TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS dataset_reverifications (
+  dataset_id TEXT PRIMARY KEY,
+  finished_at TEXT NOT NULL,
+  dataset_commitment_hex TEXT NOT NULL,
+  shards_total INTEGER NOT NULL,
+  shards_checked INTEGER NOT NULL,
+  failed_shards_json TEXT NOT NULL,
+  commitment_matches INTEGER NOT NULL,
+  duration_ms INTEGER NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS dataset_anomalies (
+  dataset_id TEXT PRIMARY KEY,
+  analyzed_at TEXT NOT NULL,
+  dataset_commitment_hex TEXT NOT NULL,
+  shards_stored INTEGER NOT NULL,
+  shards_analyzed INTEGER NOT NULL,
+  warnings_total INTEGER NOT NULL,
+  warnings_json TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS curve_migrations (
+  dataset_id TEXT NOT NULL,
+  curve TEXT NOT NULL,
+  status TEXT NOT NULL,
+  reason TEXT,
+  dataset_commitment_hex TEXT,
+  key_id TEXT,
+  created_at TEXT NOT NULL,
+  finished_at TEXT,
+  PRIMARY KEY(dataset_id, curve)
+);
+
+CREATE TABLE IF NOT EXISTS curve_shards (
+  dataset_id TEXT NOT NULL,
+  curve TEXT NOT NULL,
+  shard_index INTEGER NOT NULL,
+  shard_commitment_hex TEXT NOT NULL,
+  salt_commitment_hex TEXT NOT NULL,
+  stats_json TEXT NOT NULL,
+  proof_b64 TEXT NOT NULL,
+  sealed_master_salt_b64 TEXT NOT NULL,
+  key_id TEXT NOT NULL,
+  PRIMARY KEY(dataset_id, curve, shard_index)
+);
+
+CREATE TABLE IF NOT EXISTS zk_key_usage (
+  key_id TEXT PRIMARY KEY,
+  circuit TEXT NOT NULL,
+  first_seen_at TEXT NOT NULL,
+  last_proof_at TEXT NOT NULL,
+  proofs_created INTEGER NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS zk_key_datasets (
+  key_id TEXT NOT NULL,
+  dataset_id TEXT NOT NULL,
+  PRIMARY KEY(key_id, dataset_id)
+);
+
+CREATE TABLE IF NOT EXISTS log_anchors (
+  seq INTEGER PRIMARY KEY AUTOINCREMENT,
+  anchored_at TEXT NOT NULL,
+  method TEXT NOT NULL,
+  tree_size INTEGER NOT NULL,
+  root_hash_hex TEXT NOT NULL,
+  txid TEXT,
+  receipt_hex TEXT
+);
 "#,
     )
     .execute(db)
     .await
     .map_err(|_| ApiError::Internal)?;
 
+    // Columns added after the initial schema. `CREATE TABLE IF NOT EXISTS` won't touch existing
+    // databases, so add them explicitly.
+    add_column_if_missing(db, "datasets", "consent_scope_json", "TEXT").await?;
+    add_column_if_missing(db, "datasets", "requires_approval", "INTEGER NOT NULL DEFAULT 0").await?;
+    add_column_if_missing(db, "queries", "status", "TEXT NOT NULL DEFAULT 'released'").await?;
+    add_column_if_missing(db, "queries", "decided_by", "TEXT").await?;
+    add_column_if_missing(db, "datasets", "release_limit", "INTEGER").await?;
+    add_column_if_missing(db, "queries", "release_key", "TEXT").await?;
+    add_column_if_missing(db, "queries", "released_at", "TEXT").await?;
+    add_column_if_missing(db, "datasets", "generator", "TEXT").await?;
+    add_column_if_missing(db, "datasets", "manifest_json", "TEXT").await?;
+    add_column_if_missing(db, "datasets", "ingest_quality_json", "TEXT").await?;
+    add_column_if_missing(db, "shards", "quality_json", "TEXT").await?;
+    add_column_if_missing(db, "queries", "error", "TEXT").await?;
+    add_column_if_missing(db, "datasets", "owner_key_id", "TEXT").await?;
+    add_column_if_missing(db, "jobs", "tenant", "TEXT").await?;
+    add_column_if_missing(db, "datasets", "frozen_at", "TEXT").await?;
+    add_column_if_missing(db, "datasets", "frozen_by", "TEXT").await?;
+    add_column_if_missing(db, "datasets", "imported_from", "TEXT").await?;
+    add_column_if_missing(db, "datasets", "external_vk_b64", "TEXT").await?;
+    add_column_if_missing(db, "queries", "dataset_commitment_hex", "TEXT").await?;
+    add_column_if_missing(db, "queries", "shards_total", "INTEGER").await?;
+    add_column_if_missing(db, "queries", "verified_bitmap_hex", "TEXT").await?;
+    add_column_if_missing(db, "shards", "proof_hash", "TEXT").await?;
+    add_column_if_missing(db, "datasets", "field_set", "TEXT").await?;
+    add_column_if_missing(db, "datasets", "chain_hash", "TEXT").await?;
+    add_column_if_missing(db, "shards", "sealed_master_salt_b64", "TEXT").await?;
+    add_column_if_missing(db, "datasets", "sha256_commitment", "INTEGER NOT NULL DEFAULT 0").await?;
+    add_column_if_missing(db, "datasets", "age_buckets_json", "TEXT").await?;
+    add_column_if_missing(db, "datasets", "window_shards", "INTEGER").await?;
+    add_column_if_missing(db, "datasets", "generator_params_json", "TEXT").await?;
+    add_column_if_missing(db, "queries", "first_shard_index", "INTEGER").await?;
+    add_column_if_missing(db, "datasets", "access_restricted", "INTEGER NOT NULL DEFAULT 0").await?;
+    add_column_if_missing(db, "proof_blobs", "size_bytes", "INTEGER").await?;
+    add_column_if_missing(db, "shards", "vk_version", "TEXT").await?;
+    add_column_if_missing(db, "shards", "verified_at", "TEXT").await?;
+    add_column_if_missing(db, "shards", "verifier_vk_hash", "TEXT").await?;
+    add_column_if_missing(db, "queries", "requested_by", "TEXT").await?;
+    add_column_if_missing(db, "jobs", "instance", "TEXT").await?;
+    add_column_if_missing(db, "jobs", "claimed_by", "TEXT").await?;
+    add_column_if_missing(db, "jobs", "heartbeat_at", "TEXT").await?;
+
+    migrate_inline_proofs(db).await?;
+    backfill_aggregates(db).await?;
+
+    Ok(())
+}
+
+/// Content address of a proof: hex SHA-256 of the serialized proof bytes.
+pub fn proof_blob_hash(proof_b64: &str) -> Result<String, ApiError> {
+    use base64::Engine;
+    let bytes = base64::engine::general_purpose::STANDARD
+        .decode(proof_b64)
+        .map_err(|_| ApiError::BadRequest("proof is not valid base64".to_string()))?;
+    Ok(hex::encode(Sha256::digest(bytes)))
+}
+
+/// Store a proof blob inline in `proof_blobs` (no-op if an identical blob exists) and return the
+/// hash. Only for proofs about to be moved to files (`move_proof_blobs_to_files`).
+async fn put_proof_blob<'e>(db: impl Executor<'e, Database = Sqlite>, proof_b64: &str) -> Result<String, ApiError> {
+    let hash = proof_blob_hash(proof_b64)?;
+    sqlx::query(r#"INSERT OR IGNORE INTO proof_blobs (hash, proof_b64, created_at) VALUES (?, ?, ?)"#)
+        .bind(&hash)
+        .bind(proof_b64)
+        .bind(Utc::now().to_rfc3339())
+        .execute(db)
+        .await
+        .map_err(|_| ApiError::Internal)?;
+    Ok(hash)
+}
+
+/// A proof written to its file, not yet recorded in `proof_blobs`.
+pub struct ProofFile {
+    pub hash: String,
+    pub size_bytes: u64,
+}
+
+/// Write a proof to its file (no-op if an identical blob exists). Hold `ProofFiles::lock` until
+/// the row referring to it is committed.
+pub async fn write_proof_file(files: &ProofFiles, proof_b64: &str) -> Result<ProofFile, ApiError> {
+    use base64::Engine;
+    let bytes = base64::engine::general_purpose::STANDARD
+        .decode(proof_b64)
+        .map_err(|_| ApiError::BadRequest("proof is not valid base64".to_string()))?;
+    let hash = hex::encode(Sha256::digest(&bytes));
+    files.write(&hash, &bytes).await?;
+    Ok(ProofFile {
+        hash,
+        size_bytes: bytes.len() as u64,
+    })
+}
+
+/// Base64 of a proof blob: `proof_b64` when stored inline, otherwise read from its file. `None`
+/// if the file is missing.
+async fn proof_blob_b64(files: &ProofFiles, hash: &str, proof_b64: String) -> Result<Option<String>, ApiError> {
+    use base64::Engine;
+    if !proof_b64.is_empty() {
+        return Ok(Some(proof_b64));
+    }
+    Ok(files.read(hash).await?.map(|bytes| base64::engine::general_purpose::STANDARD.encode(bytes)))
+}
+
+/// Move proof blobs stored inline in `proof_blobs` (databases from before proof files) to files,
+/// then reclaim the space they took. Returns how many were moved.
+pub async fn move_proof_blobs_to_files(db: &Db, files: &ProofFiles) -> Result<u64, ApiError> {
+    use base64::Engine;
+    let _guard = files.lock().await;
+    let mut moved = 0;
+    loop {
+        let rows = sqlx::query(r#"SELECT hash, proof_b64 FROM proof_blobs WHERE proof_b64 != '' LIMIT 500"#)
+            .fetch_all(db)
+            .await
+            .map_err(|_| ApiError::Internal)?;
+        if rows.is_empty() {
+            break;
+        }
+
+        for row in rows {
+            let hash: String = row.get(0);
+            let proof_b64: String = row.get(1);
+            let bytes = base64::engine::general_purpose::STANDARD
+                .decode(&proof_b64)
+                .map_err(|_| ApiError::Internal)?;
+            // Write the stored bytes as they are; the proof blob audit reports any that no longer
+            // match their hash.
+            files.write(&hash, &bytes).await?;
+            sqlx::query(r#"UPDATE proof_blobs SET proof_b64 = '', size_bytes = ? WHERE hash = ?"#)
+                .bind(bytes.len() as i64)
+                .bind(&hash)
+                .execute(db)
+                .await
+                .map_err(|_| ApiError::Internal)?;
+            moved += 1;
+        }
+    }
+
+    if moved > 0 {
+        sqlx::query("VACUUM").execute(db).await.map_err(|_| ApiError::Internal)?;
+        tracing::info!(moved, "moved proof blobs from the database to files");
+    }
+    Ok(moved)
+}
+
+/// Move proofs stored inline in `shards.proof_b64` (databases from before `proof_blobs`) into the
+/// blob table.
+async fn migrate_inline_proofs(db: &Db) -> Result<(), ApiError> {
+    loop {
+        let rows = sqlx::query(
+            r#"SELECT dataset_id, shard_index, proof_b64 FROM shards
+               WHERE proof_hash IS NULL AND proof_b64 != '' LIMIT 500"#,
+        )
+        .fetch_all(db)
+        .await
+        .map_err(|_| ApiError::Internal)?;
+        if rows.is_empty() {
+            return Ok(());
+        }
+
+        for row in rows {
+            let dataset_id: String = row.get(0);
+            let shard_index: i64 = row.get(1);
+            let proof_b64: String = row.get(2);
+            let hash = put_proof_blob(db, &proof_b64).await?;
+            sqlx::query(r#"UPDATE shards SET proof_hash = ?, proof_b64 = '' WHERE dataset_id = ? AND shard_index = ?"#)
+                .bind(hash)
+                .bind(dataset_id)
+                .bind(shard_index)
+                .execute(db)
+                .await
+                .map_err(|_| ApiError::Internal)?;
+        }
+    }
+}
+
+/// Build the `aggregates` rows of datasets whose shards were stored before the table existed.
+async fn backfill_aggregates(db: &Db) -> Result<(), ApiError> {
+    let rows = sqlx::query(
+        r#"SELECT DISTINCT dataset_id FROM shards s
+           WHERE NOT EXISTS (SELECT 1 FROM aggregates a WHERE a.dataset_id = s.dataset_id)"#,
+    )
+    .fetch_all(db)
+    .await
+    .map_err(|_| ApiError::Internal)?;
+
+    for row in rows {
+        let dataset_id: String = row.get(0);
+        let mut tx = db.begin().await.map_err(|_| ApiError::Internal)?;
+        let shards = sqlx::query(r#"SELECT stats_json FROM shards WHERE dataset_id = ?"#)
+            .bind(&dataset_id)
+            .fetch_all(&mut *tx)
+            .await
+            .map_err(|_| ApiError::Internal)?;
+        let aggregates = sum_bucket_aggregates(&shards)?;
+        put_aggregates(&mut tx, &dataset_id, &aggregates).await?;
+        tx.commit().await.map_err(|_| ApiError::Internal)?;
+        tracing::info!(%dataset_id, shards = shards.len(), "backfilled dataset aggregates");
+    }
+    Ok(())
+}
+
+async fn add_column_if_missing(db: &Db, table: &str, column: &str, decl: &str) -> Result<(), ApiError> {
+    let rows = sqlx::query(&format!("PRAGMA table_info({table})"))
+        .fetch_all(db)
+        .await
+        .map_err(|_| ApiError::Internal)?;
+
+    if rows.iter().any(|r| r.get::<String, _>("name") == column) {
+        return Ok(());
+    }
+
+    sqlx::query(&format!("ALTER TABLE {table} ADD COLUMN {column} {decl}"))
+        .execute(db)
+        .await
+        .map_err(|_| ApiError::Internal)?;
     Ok(())
 }
 
-pub async fn insert_dataset(db: &Db, dataset_id: Uuid, dataset_size: u64) -> Result<(), ApiError> {
+/// Settings of a dataset being registered.
+pub struct NewDataset<'a> {
+    pub dataset_id: Uuid,
+    pub dataset_size: u64,
+    pub shard_size: u64,
+    pub field_set: FieldSet,
+    pub age_buckets: &'a AgeBuckets,
+    pub chain_hash: ChainHash,
+    /// Dual-commitment dataset: shards also carry a circuit-bound SHA-256 commitment.
+    pub sha256_commitment: bool,
+    pub consent_scope: Option<&'a [String]>,
+    pub requires_approval: bool,
+    pub release_limit: Option<u64>,
+    /// Synthetic generator name; `None` for uploaded datasets.
+    pub generator: Option<&'a str>,
+    /// Parameters the generator was configured with; `None` for its defaults.
+    pub generator_params: Option<&'a serde_json::Value>,
+    pub ingest_quality: &'a IngestQuality,
+    /// Rolling window: only the last this many shards are live.
+    pub window_shards: Option<u64>,
+    /// `Caller::key_id` of the creating tenant, for quota accounting.
+    pub owner: &'a str,
+}
+
+/// JSON columns of a new dataset: consent scope, age buckets, ingest quality and generator
+/// parameters.
+pub type NewDatasetJson = (Option<String>, Option<String>, String, Option<String>);
+
+pub fn new_dataset_json(dataset: &NewDataset<'_>) -> Result<NewDatasetJson, ApiError> {
+    let consent_scope_json = dataset
+        .consent_scope
+        .map(|s| serde_json::to_string(s).map_err(|_| ApiError::Internal))
+        .transpose()?;
+    // NULL keeps the default layout, as for datasets created before layouts were configurable.
+    let age_buckets_json = (!dataset.age_buckets.is_default())
+        .then(|| serde_json::to_string(dataset.age_buckets).map_err(|_| ApiError::Internal))
+        .transpose()?;
+    let ingest_quality_json = serde_json::to_string(dataset.ingest_quality).map_err(|_| ApiError::Internal)?;
+    let generator_params_json = dataset
+        .generator_params
+        .map(|p| serde_json::to_string(p).map_err(|_| ApiError::Internal))
+        .transpose()?;
+    Ok((consent_scope_json, age_buckets_json, ingest_quality_json, generator_params_json))
+}
+
+pub async fn insert_dataset(db: &Db, dataset: &NewDataset<'_>) -> Result<(), ApiError> {
     let created_at = Utc::now().to_rfc3339();
     let status = "generating";
+    let (consent_scope_json, age_buckets_json, ingest_quality_json, generator_params_json) = new_dataset_json(dataset)?;
 
     sqlx::query(
-        r#"INSERT INTO datasets (id, created_at, dataset_size, shard_size, num_buckets, status)
-           VALUES (?, ?, ?, ?, ?, ?)"#,
+        r#"INSERT INTO datasets
+           (id, created_at, dataset_size, shard_size, num_buckets, status, consent_scope_json, requires_approval,
+            release_limit, generator, ingest_quality_json, owner_key_id, field_set, chain_hash, sha256_commitment,
+            age_buckets_json, window_shards, generator_params_json)
+           VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)"#,
     )
-    .bind(dataset_id.to_string())
+    .bind(dataset.dataset_id.to_string())
     .bind(created_at)
-    .bind(dataset_size as i64)
-    .bind(DEFAULT_SHARD_SIZE as i64)
-    .bind(NUM_BUCKETS as i64)
+    .bind(dataset.dataset_size as i64)
+    .bind(dataset.shard_size as i64)
+    .bind(dataset.age_buckets.num_buckets() as i64)
     .bind(status)
+    .bind(consent_scope_json)
+    .bind(if dataset.requires_approval { 1i64 } else { 0i64 })
+    .bind(dataset.release_limit.map(|l| l as i64))
+    .bind(dataset.generator)
+    .bind(ingest_quality_json)
+    .bind(dataset.owner)
+    .bind(dataset.field_set.name())
+    .bind(dataset.chain_hash.name())
+    .bind(if dataset.sha256_commitment { 1i64 } else { 0i64 })
+    .bind(age_buckets_json)
+    .bind(dataset.window_shards.map(|w| w as i64))
+    .bind(generator_params_json)
     .execute(db)
     .await
     .map_err(|_| ApiError::Internal)?;
@@ -80,8 +603,9 @@ pub async fn insert_dataset(db: &Db, dataset_id: Uuid, dataset_size: u64) -> Res
     Ok(())
 }
 
+/// Mark a dataset ready, unless its generation was cancelled meanwhile.
 pub async fn set_dataset_ready(db: &Db, dataset_id: Uuid, commitment_hex: &str) -> Result<(), ApiError> {
-    sqlx::query(r#"UPDATE datasets SET status = 'ready', dataset_commitment_hex = ?, error = NULL WHERE id = ?"#)
+    sqlx::query(r#"UPDATE datasets SET status = 'ready', dataset_commitment_hex = ?, error = NULL WHERE id = ? AND status != 'cancelled'"#)
         .bind(commitment_hex)
         .bind(dataset_id.to_string())
         .execute(db)
@@ -90,8 +614,68 @@ pub async fn set_dataset_ready(db: &Db, dataset_id: Uuid, commitment_hex: &str)
     Ok(())
 }
 
+/// Grow a streamed dataset to `dataset_size` records under its new commitment (its shards must
+/// already be stored). Returns `false` if the dataset was frozen meanwhile.
+pub async fn extend_dataset(db: &Db, dataset_id: Uuid, dataset_size: u64, commitment_hex: &str) -> Result<bool, ApiError> {
+    let result = sqlx::query(
+        r#"UPDATE datasets SET dataset_size = ?, status = 'ready', dataset_commitment_hex = ?, error = NULL
+           WHERE id = ? AND frozen_at IS NULL"#,
+    )
+    .bind(dataset_size as i64)
+    .bind(commitment_hex)
+    .bind(dataset_id.to_string())
+    .execute(db)
+    .await
+    .map_err(|_| ApiError::Internal)?;
+    Ok(result.rows_affected() == 1)
+}
+
+pub async fn set_dataset_ingest_quality(db: &Db, dataset_id: Uuid, quality: &IngestQuality) -> Result<(), ApiError> {
+    sqlx::query(r#"UPDATE datasets SET ingest_quality_json = ? WHERE id = ?"#)
+        .bind(serde_json::to_string(quality).map_err(|_| ApiError::Internal)?)
+        .bind(dataset_id.to_string())
+        .execute(db)
+        .await
+        .map_err(|_| ApiError::Internal)?;
+    Ok(())
+}
+
+/// API key that created the dataset; `None` for datasets that predate ownership.
+pub async fn dataset_owner(db: &Db, dataset_id: Uuid) -> Result<Option<String>, ApiError> {
+    let row = sqlx::query(r#"SELECT owner_key_id FROM datasets WHERE id = ?"#)
+        .bind(dataset_id.to_string())
+        .fetch_optional(db)
+        .await
+        .map_err(|_| ApiError::Internal)?;
+    Ok(row.and_then(|r| r.get::<Option<String>, _>(0)))
+}
+
+pub async fn set_dataset_manifest(db: &Db, dataset_id: Uuid, manifest: &serde_json::Value) -> Result<(), ApiError> {
+    sqlx::query(r#"UPDATE datasets SET manifest_json = ? WHERE id = ?"#)
+        .bind(manifest.to_string())
+        .bind(dataset_id.to_string())
+        .execute(db)
+        .await
+        .map_err(|_| ApiError::Internal)?;
+    Ok(())
+}
+
+/// Stored manifest, or `None` if the dataset does not exist or proving has not started.
+pub async fn get_dataset_manifest(db: &Db, dataset_id: Uuid) -> Result<Option<serde_json::Value>, ApiError> {
+    let row = sqlx::query(r#"SELECT manifest_json FROM datasets WHERE id = ?"#)
+        .bind(dataset_id.to_string())
+        .fetch_optional(db)
+        .await
+        .map_err(|_| ApiError::Internal)?;
+
+    let Some(manifest_json) = row.and_then(|r| r.get::<Option<String>, _>(0)) else {
+        return Ok(None);
+    };
+    serde_json::from_str(&manifest_json).map(Some).map_err(|_| ApiError::Internal)
+}
+
 pub async fn set_dataset_failed(db: &Db, dataset_id: Uuid, error: &str) -> Result<(), ApiError> {
-    sqlx::query(r#"UPDATE datasets SET status = 'failed', error = ? WHERE id = ?"#)
+    sqlx::query(r#"UPDATE datasets SET status = 'failed', error = ? WHERE id = ? AND status != 'cancelled'"#)
         .bind(error)
         .bind(dataset_id.to_string())
         .execute(db)
@@ -100,188 +684,2789 @@ pub async fn set_dataset_failed(db: &Db, dataset_id: Uuid, error: &str) -> Resul
     Ok(())
 }
 
-pub async fn insert_shard(
+/// Cancel a dataset's generation. Returns `false` if it is not generating.
+pub async fn cancel_dataset(db: &Db, dataset_id: Uuid) -> Result<bool, ApiError> {
+    let res = sqlx::query(r#"UPDATE datasets SET status = 'cancelled' WHERE id = ? AND status = 'generating'"#)
+        .bind(dataset_id.to_string())
+        .execute(db)
+        .await
+        .map_err(|_| ApiError::Internal)?;
+    Ok(res.rows_affected() == 1)
+}
+
+/// Mark an imported dataset ready, keeping the verifying key its proofs were checked against.
+pub async fn set_dataset_imported(
     db: &Db,
     dataset_id: Uuid,
-    shard_index: u64,
-    shard_commitment_hex: &str,
-    stats: &ShardStats,
-    proof_b64: &str,
-    verified: bool,
+    commitment_hex: &str,
+    vk_b64: &str,
+    imported_from: &str,
 ) -> Result<(), ApiError> {
-    let stats_json = serde_json::to_string(stats).map_err(|_| ApiError::Internal)?;
-
     sqlx::query(
-        r#"INSERT OR REPLACE INTO shards
-           (dataset_id, shard_index, shard_commitment_hex, stats_json, proof_b64, verified)
-           VALUES (?, ?, ?, ?, ?, ?)"#,
+        r#"UPDATE datasets
+           SET status = 'ready', dataset_commitment_hex = ?, external_vk_b64 = ?, imported_from = ?, error = NULL
+           WHERE id = ?"#,
     )
+    .bind(commitment_hex)
+    .bind(vk_b64)
+    .bind(imported_from)
     .bind(dataset_id.to_string())
-    .bind(shard_index as i64)
-    .bind(shard_commitment_hex)
-    .bind(stats_json)
-    .bind(proof_b64)
-    .bind(if verified { 1i64 } else { 0i64 })
     .execute(db)
     .await
     .map_err(|_| ApiError::Internal)?;
+    Ok(())
+}
 
+/// Attach a federated site's verifying key to an empty dataset; its shards are pushed later.
+pub async fn set_dataset_federated(db: &Db, dataset_id: Uuid, vk_b64: &str, imported_from: &str) -> Result<(), ApiError> {
+    sqlx::query(r#"UPDATE datasets SET external_vk_b64 = ?, imported_from = ? WHERE id = ?"#)
+        .bind(vk_b64)
+        .bind(imported_from)
+        .bind(dataset_id.to_string())
+        .execute(db)
+        .await
+        .map_err(|_| ApiError::Internal)?;
     Ok(())
 }
 
-pub async fn get_dataset(db: &Db, dataset_id: Uuid) -> Result<Option<(DateTime<Utc>, u64, String, Option<String>, Option<String>)>, ApiError> {
-    let row = sqlx::query(
-        r#"SELECT created_at, dataset_size, status, dataset_commitment_hex, error
-           FROM datasets WHERE id = ?"#,
+/// Verifying key of an imported dataset; `None` for datasets proven with this instance's keys.
+pub async fn get_dataset_external_vk(db: &Db, dataset_id: Uuid) -> Result<Option<String>, ApiError> {
+    let row = sqlx::query(r#"SELECT external_vk_b64 FROM datasets WHERE id = ?"#)
+        .bind(dataset_id.to_string())
+        .fetch_optional(db)
+        .await
+        .map_err(|_| ApiError::Internal)?;
+    Ok(row.and_then(|r| r.get(0)))
+}
+
+/// Freeze a ready, unfrozen dataset. Returns `false` if it is not ready or already frozen.
+pub async fn freeze_dataset(db: &Db, dataset_id: Uuid, frozen_by: &str) -> Result<bool, ApiError> {
+    let res = sqlx::query(
+        r#"UPDATE datasets SET frozen_at = ?, frozen_by = ?
+           WHERE id = ? AND status = 'ready' AND frozen_at IS NULL"#,
     )
+    .bind(Utc::now().to_rfc3339())
+    .bind(frozen_by)
     .bind(dataset_id.to_string())
-    .fetch_optional(db)
+    .execute(db)
     .await
     .map_err(|_| ApiError::Internal)?;
+    Ok(res.rows_affected() == 1)
+}
 
-    let Some(row) = row else { return Ok(None); };
-
-    let created_at: String = row.get(0);
-    let created_at = DateTime::parse_from_rfc3339(&created_at)
-        .map_err(|_| ApiError::Internal)?
-        .with_timezone(&Utc);
+/// Lift a freeze. Returns `false` if the dataset was not frozen.
+pub async fn unfreeze_dataset(db: &Db, dataset_id: Uuid) -> Result<bool, ApiError> {
+    let res = sqlx::query(
+        r#"UPDATE datasets SET frozen_at = NULL, frozen_by = NULL
+           WHERE id = ? AND frozen_at IS NOT NULL"#,
+    )
+    .bind(dataset_id.to_string())
+    .execute(db)
+    .await
+    .map_err(|_| ApiError::Internal)?;
+    Ok(res.rows_affected() == 1)
+}
 
-    let dataset_size: i64 = row.get(1);
-    let status: String = row.get(2);
-    let commitment_hex: Option<String> = row.get(3);
-    let error: Option<String> = row.get(4);
+/// One grant on a dataset's access list.
+#[derive(Debug, Clone)]
+pub struct AclEntryRow {
+    /// `key` (an API key fingerprint) or `role`.
+    pub grantee_kind: String,
+    pub grantee: String,
+    pub granted_by: String,
+    pub granted_at: DateTime<Utc>,
+}
 
-    Ok(Some((created_at, dataset_size as u64, status, commitment_hex, error)))
+/// A dataset's access list. `restricted` is set by the first grant and stays set when every
+/// grant is revoked, leaving the dataset to its owner and admins.
+#[derive(Debug, Clone)]
+pub struct DatasetAclRow {
+    pub restricted: bool,
+    pub entries: Vec<AclEntryRow>,
 }
 
-pub async fn count_shards_done(db: &Db, dataset_id: Uuid) -> Result<u64, ApiError> {
-    let row = sqlx::query(r#"SELECT COUNT(*) AS c FROM shards WHERE dataset_id = ?"#)
-        .bind(dataset_id.to_string())
-        .fetch_one(db)
-        .await
-        .map_err(|_| ApiError::Internal)?;
-    let c: i64 = row.get("c");
-    Ok(c as u64)
+pub const ACL_ENTRY_COLUMNS: &str = "grantee_kind, grantee, granted_by, granted_at";
+
+/// Decode `ACL_ENTRY_COLUMNS`.
+pub fn acl_entry_row(row: &impl LedgerRow) -> Result<AclEntryRow, ApiError> {
+    Ok(AclEntryRow {
+        grantee_kind: row.text(0),
+        grantee: row.text(1),
+        granted_by: row.text(2),
+        granted_at: parse_time(&row.text(3))?,
+    })
 }
 
-pub async fn count_shards_verified(db: &Db, dataset_id: Uuid) -> Result<u64, ApiError> {
-    let row = sqlx::query(r#"SELECT COUNT(*) AS c FROM shards WHERE dataset_id = ? AND verified = 1"#)
+pub async fn dataset_acl(db: &Db, dataset_id: Uuid) -> Result<DatasetAclRow, ApiError> {
+    let restricted = sqlx::query(r#"SELECT access_restricted FROM datasets WHERE id = ?"#)
         .bind(dataset_id.to_string())
-        .fetch_one(db)
+        .fetch_optional(db)
         .await
-        .map_err(|_| ApiError::Internal)?;
-    let c: i64 = row.get("c");
-    Ok(c as u64)
+        .map_err(|_| ApiError::Internal)?
+        .is_some_and(|row| row.int(0) != 0);
+    let rows = sqlx::query(&format!(
+        "SELECT {ACL_ENTRY_COLUMNS} FROM dataset_acl WHERE dataset_id = ? ORDER BY granted_at, grantee_kind, grantee"
+    ))
+    .bind(dataset_id.to_string())
+    .fetch_all(db)
+    .await
+    .map_err(|_| ApiError::Internal)?;
+    Ok(DatasetAclRow {
+        restricted,
+        entries: rows.iter().map(acl_entry_row).collect::<Result<_, _>>()?,
+    })
 }
 
-pub async fn list_shards(
+/// Add a grant and restrict the dataset. `false` if the grant already existed.
+pub async fn grant_dataset_access(
     db: &Db,
     dataset_id: Uuid,
-    offset: u64,
-    limit: u64,
-    include_proof: bool,
-) -> Result<Vec<(u64, String, ShardStats, bool, Option<String>)>, ApiError> {
-    let rows = sqlx::query(
-        r#"SELECT shard_index, shard_commitment_hex, stats_json, verified, proof_b64
-           FROM shards
-           WHERE dataset_id = ?
-           ORDER BY shard_index
-           LIMIT ? OFFSET ?"#,
+    grantee_kind: &str,
+    grantee: &str,
+    granted_by: &str,
+) -> Result<bool, ApiError> {
+    let mut tx = db.begin().await.map_err(|_| ApiError::Internal)?;
+    let res = sqlx::query(
+        r#"INSERT OR IGNORE INTO dataset_acl (dataset_id, grantee_kind, grantee, granted_by, granted_at)
+           VALUES (?, ?, ?, ?, ?)"#,
     )
     .bind(dataset_id.to_string())
-    .bind(limit as i64)
-    .bind(offset as i64)
-    .fetch_all(db)
+    .bind(grantee_kind)
+    .bind(grantee)
+    .bind(granted_by)
+    .bind(Utc::now().to_rfc3339())
+    .execute(&mut *tx)
     .await
     .map_err(|_| ApiError::Internal)?;
+    sqlx::query(r#"UPDATE datasets SET access_restricted = 1 WHERE id = ?"#)
+        .bind(dataset_id.to_string())
+        .execute(&mut *tx)
+        .await
+        .map_err(|_| ApiError::Internal)?;
+    tx.commit().await.map_err(|_| ApiError::Internal)?;
+    Ok(res.rows_affected() == 1)
+}
 
-    let mut out = Vec::with_capacity(rows.len());
-    for row in rows {
-        let shard_index: i64 = row.get(0);
-        let commitment: String = row.get(1);
-        let stats_json: String = row.get(2);
-        let verified: i64 = row.get(3);
-        let proof_b64: String = row.get(4);
+/// Ledger tables keyed by `dataset_id`, cleared when a dataset is deleted (the audit and transparency logs are kept).
+pub const DATASET_LEDGER_TABLES: [&str; 12] = [
+    "shards",
+    "shard_failures",
+    "aggregates",
+    "released_cells",
+    "queries",
+    "dataset_acl",
+    "privacy_budget",
+    "aggregate_proofs",
+    "dataset_anomalies",
+    "dataset_reverifications",
+    "curve_migrations",
+    "curve_shards",
+];
 
-        let stats: ShardStats = serde_json::from_str(&stats_json).map_err(|_| ApiError::Internal)?;
+/// What is left of a deleted dataset.
+#[derive(Debug, Clone)]
+pub struct TombstoneRow {
+    pub deleted_at: DateTime<Utc>,
+    /// `Caller::key_id` of whoever deleted it, or `retention`.
+    pub deleted_by: String,
+    pub reason: String,
+    pub dataset_commitment_hex: Option<String>,
+}
 
-        out.push((
-            shard_index as u64,
-            commitment,
-            stats,
-            verified == 1,
-            if include_proof { Some(proof_b64) } else { None },
-        ));
-    }
+pub const TOMBSTONE_COLUMNS: &str = "deleted_at, deleted_by, reason, dataset_commitment_hex";
 
-    Ok(out)
+/// Decode `TOMBSTONE_COLUMNS`.
+pub fn tombstone_row(row: &impl LedgerRow) -> Result<TombstoneRow, ApiError> {
+    Ok(TombstoneRow {
+        deleted_at: parse_time(&row.text(0))?,
+        deleted_by: row.text(1),
+        reason: row.text(2),
+        dataset_commitment_hex: row.opt_text(3),
+    })
 }
 
-pub async fn aggregate_for_bucket(
+/// Delete a dataset's shards, their proof blobs no other dataset shares, its queries and the rest
+/// of its ledger rows, and leave a tombstone. `None` if there is no such dataset.
+pub async fn delete_dataset(
     db: &Db,
+    files: &ProofFiles,
     dataset_id: Uuid,
-    bucket_index: usize,
-) -> Result<(u64, u64), ApiError> {
-    if bucket_index >= NUM_BUCKETS {
-        return Err(ApiError::BadRequest("invalid bucket".to_string()));
+    deleted_by: &str,
+    reason: &str,
+) -> Result<Option<TombstoneRow>, ApiError> {
+    let id = dataset_id.to_string();
+    let _guard = files.lock().await;
+    let mut tx = db.begin().await.map_err(|_| ApiError::Internal)?;
+    // A write first, so the transaction holds the write lock before it reads anything.
+    let deleted_blobs = sqlx::query(
+        r#"DELETE FROM proof_blobs
+           WHERE hash IN (SELECT proof_hash FROM shards WHERE dataset_id = ?1)
+             AND NOT EXISTS (SELECT 1 FROM shards s WHERE s.proof_hash = proof_blobs.hash AND s.dataset_id <> ?1)
+           RETURNING hash"#,
+    )
+    .bind(&id)
+    .fetch_all(&mut *tx)
+    .await
+    .map_err(|_| ApiError::Internal)?;
+    let Some(row) = sqlx::query(r#"SELECT dataset_commitment_hex FROM datasets WHERE id = ?"#)
+        .bind(&id)
+        .fetch_optional(&mut *tx)
+        .await
+        .map_err(|_| ApiError::Internal)?
+    else {
+        return Ok(None);
+    };
+    let commitment_hex = row.opt_text(0);
+
+    for table in DATASET_LEDGER_TABLES {
+        sqlx::query(&format!("DELETE FROM {table} WHERE dataset_id = ?"))
+            .bind(&id)
+            .execute(&mut *tx)
+            .await
+            .map_err(|_| ApiError::Internal)?;
+    }
+    sqlx::query(r#"DELETE FROM datasets WHERE id = ?"#)
+        .bind(&id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|_| ApiError::Internal)?;
+
+    let tombstone = TombstoneRow {
+        deleted_at: Utc::now(),
+        deleted_by: deleted_by.to_string(),
+        reason: reason.to_string(),
+        dataset_commitment_hex: commitment_hex,
+    };
+    sqlx::query(
+        r#"INSERT OR REPLACE INTO dataset_tombstones (dataset_id, deleted_at, deleted_by, reason, dataset_commitment_hex)
+           VALUES (?, ?, ?, ?, ?)"#,
+    )
+    .bind(&id)
+    .bind(tombstone.deleted_at.to_rfc3339())
+    .bind(&tombstone.deleted_by)
+    .bind(&tombstone.reason)
+    .bind(&tombstone.dataset_commitment_hex)
+    .execute(&mut *tx)
+    .await
+    .map_err(|_| ApiError::Internal)?;
+    tx.commit().await.map_err(|_| ApiError::Internal)?;
+
+    for row in deleted_blobs {
+        files.remove(&row.text(0)).await?;
     }
+    Ok(Some(tombstone))
+}
 
-    let rows = sqlx::query(r#"SELECT stats_json FROM shards WHERE dataset_id = ?"#)
+pub async fn dataset_tombstone(db: &Db, dataset_id: Uuid) -> Result<Option<TombstoneRow>, ApiError> {
+    sqlx::query(&format!("SELECT {TOMBSTONE_COLUMNS} FROM dataset_tombstones WHERE dataset_id = ?"))
         .bind(dataset_id.to_string())
+        .fetch_optional(db)
+        .await
+        .map_err(|_| ApiError::Internal)?
+        .map(|row| tombstone_row(&row))
+        .transpose()
+}
+
+/// Ready or failed datasets created before `cutoff` that aren't frozen, oldest first.
+pub async fn expired_datasets(db: &Db, cutoff: DateTime<Utc>) -> Result<Vec<Uuid>, ApiError> {
+    let rows = sqlx::query(
+        r#"SELECT id FROM datasets
+           WHERE created_at < ? AND frozen_at IS NULL AND status IN ('ready', 'failed')
+           ORDER BY created_at"#,
+    )
+    .bind(cutoff.to_rfc3339())
+    .fetch_all(db)
+    .await
+    .map_err(|_| ApiError::Internal)?;
+    rows.iter().map(|row| Uuid::parse_str(&row.text(0)).map_err(|_| ApiError::Internal)).collect()
+}
+
+/// An API key issued through `POST /api/v1/admin/keys`. Only the key's SHA-256 is stored.
+#[derive(Debug, Clone)]
+pub struct ApiKeyRow {
+    pub key_id: String,
+    pub name: String,
+    pub role: String,
+    /// Scopes the key is limited to; `None` allows everything its role does.
+    pub scopes: Option<Vec<String>>,
+    pub created_by: String,
+    pub created_at: DateTime<Utc>,
+    pub revoked_at: Option<DateTime<Utc>>,
+}
+
+pub struct NewApiKey<'a> {
+    pub key_id: &'a str,
+    pub key_hash: &'a str,
+    pub name: &'a str,
+    pub role: &'a str,
+    pub scopes: Option<&'a [String]>,
+    pub created_by: &'a str,
+}
+
+pub const API_KEY_COLUMNS: &str = "key_id, name, role, scopes_json, created_by, created_at, revoked_at";
+
+/// Decode `API_KEY_COLUMNS`.
+pub fn api_key_row(row: &impl LedgerRow) -> Result<ApiKeyRow, ApiError> {
+    Ok(ApiKeyRow {
+        key_id: row.text(0),
+        name: row.text(1),
+        role: row.text(2),
+        scopes: row.opt_text(3).map(|s| serde_json::from_str(&s)).transpose().map_err(|_| ApiError::Internal)?,
+        created_by: row.text(4),
+        created_at: parse_time(&row.text(5))?,
+        revoked_at: row.opt_text(6).map(|t| parse_time(&t)).transpose()?,
+    })
+}
+
+/// JSON of a new key's scopes, if limited.
+pub fn api_key_scopes_json(key: &NewApiKey<'_>) -> Option<String> {
+    key.scopes.map(|s| serde_json::to_string(s).unwrap_or_default())
+}
+
+pub async fn insert_api_key(db: &Db, key: &NewApiKey<'_>) -> Result<DateTime<Utc>, ApiError> {
+    let now = Utc::now();
+    sqlx::query(
+        r#"INSERT INTO api_keys (key_id, key_hash, name, role, scopes_json, created_by, created_at)
+           VALUES (?, ?, ?, ?, ?, ?, ?)"#,
+    )
+    .bind(key.key_id)
+    .bind(key.key_hash)
+    .bind(key.name)
+    .bind(key.role)
+    .bind(api_key_scopes_json(key))
+    .bind(key.created_by)
+    .bind(now.to_rfc3339())
+    .execute(db)
+    .await
+    .map_err(|_| ApiError::Internal)?;
+    Ok(now)
+}
+
+/// The key whose SHA-256 is `key_hash`, revoked or not.
+pub async fn api_key_by_hash(db: &Db, key_hash: &str) -> Result<Option<ApiKeyRow>, ApiError> {
+    sqlx::query(&format!("SELECT {API_KEY_COLUMNS} FROM api_keys WHERE key_hash = ?"))
+        .bind(key_hash)
+        .fetch_optional(db)
+        .await
+        .map_err(|_| ApiError::Internal)?
+        .map(|row| api_key_row(&row))
+        .transpose()
+}
+
+pub async fn list_api_keys(db: &Db) -> Result<Vec<ApiKeyRow>, ApiError> {
+    let rows = sqlx::query(&format!("SELECT {API_KEY_COLUMNS} FROM api_keys ORDER BY created_at, key_id"))
         .fetch_all(db)
         .await
         .map_err(|_| ApiError::Internal)?;
+    rows.iter().map(api_key_row).collect()
+}
 
-    let mut sum = 0u64;
-    let mut count = 0u64;
+/// Revoke a key. `false` if there is no such key or it was already revoked.
+pub async fn revoke_api_key(db: &Db, key_id: &str) -> Result<bool, ApiError> {
+    let res = sqlx::query(r#"UPDATE api_keys SET revoked_at = ? WHERE key_id = ? AND revoked_at IS NULL"#)
+        .bind(Utc::now().to_rfc3339())
+        .bind(key_id)
+        .execute(db)
+        .await
+        .map_err(|_| ApiError::Internal)?;
+    Ok(res.rows_affected() == 1)
+}
 
-    for row in rows {
-        let stats_json: String = row.get(0);
-        let stats: ShardStats = serde_json::from_str(&stats_json).map_err(|_| ApiError::Internal)?;
-        sum += stats.sum_glucose_by_bucket[bucket_index];
-        count += stats.count_by_bucket[bucket_index];
+/// Count one more query for `key_id` on `day` (UTC, `YYYY-MM-DD`) unless it already has `max`.
+/// `false` if the quota is used up.
+pub async fn take_daily_query(db: &Db, key_id: &str, day: &str, max: u64) -> Result<bool, ApiError> {
+    if max == 0 {
+        return Ok(false);
     }
+    let res = sqlx::query(
+        r#"INSERT INTO query_quota_usage (key_id, day, queries) VALUES (?, ?, 1)
+           ON CONFLICT(key_id, day) DO UPDATE SET queries = queries + 1 WHERE queries < ?"#,
+    )
+    .bind(key_id)
+    .bind(day)
+    .bind(max as i64)
+    .execute(db)
+    .await
+    .map_err(|_| ApiError::Internal)?;
+    Ok(res.rows_affected() == 1)
+}
 
-    Ok((sum, count))
+/// Give back a query counted by `take_daily_query` that wasn't created.
+pub async fn refund_daily_query(db: &Db, key_id: &str, day: &str) -> Result<(), ApiError> {
+    sqlx::query(r#"UPDATE query_quota_usage SET queries = queries - 1 WHERE key_id = ? AND day = ? AND queries > 0"#)
+        .bind(key_id)
+        .bind(day)
+        .execute(db)
+        .await
+        .map_err(|_| ApiError::Internal)?;
+    Ok(())
 }
 
-pub async fn insert_query(
+pub async fn daily_queries(db: &Db, key_id: &str, day: &str) -> Result<u64, ApiError> {
+    let row = sqlx::query(r#"SELECT queries FROM query_quota_usage WHERE key_id = ? AND day = ?"#)
+        .bind(key_id)
+        .bind(day)
+        .fetch_optional(db)
+        .await
+        .map_err(|_| ApiError::Internal)?;
+    Ok(row.map(|row| row.get::<i64, _>(0) as u64).unwrap_or(0))
+}
+
+/// Remove a grant. `false` if there was none; the dataset stays restricted either way.
+pub async fn revoke_dataset_access(db: &Db, dataset_id: Uuid, grantee_kind: &str, grantee: &str) -> Result<bool, ApiError> {
+    let res = sqlx::query(r#"DELETE FROM dataset_acl WHERE dataset_id = ? AND grantee_kind = ? AND grantee = ?"#)
+        .bind(dataset_id.to_string())
+        .bind(grantee_kind)
+        .bind(grantee)
+        .execute(db)
+        .await
+        .map_err(|_| ApiError::Internal)?;
+    Ok(res.rows_affected() == 1)
+}
+
+/// Store a shard whose proof was written with `write_proof_file`.
+pub async fn insert_shard(
     db: &Db,
-    query_id: Uuid,
     dataset_id: Uuid,
-    metric: &Metric,
-    bucket_index: usize,
-    sum: u64,
-    count: u64,
-    mean: Option<f64>,
+    shard_index: u64,
+    shard_commitment_hex: &str,
+    stats: &ShardStats,
+    proof: &ProofFile,
     verified: bool,
 ) -> Result<(), ApiError> {
-    let created_at = Utc::now().to_rfc3339();
+    let stats_json = serde_json::to_string(stats).map_err(|_| ApiError::Internal)?;
+    let dataset_id = dataset_id.to_string();
+
+    // The blob write comes first so the transaction holds the write lock before it reads the
+    // aggregates it is about to update.
+    let mut tx = db.begin().await.map_err(|_| ApiError::Internal)?;
+    sqlx::query(r#"INSERT OR IGNORE INTO proof_blobs (hash, proof_b64, size_bytes, created_at) VALUES (?, '', ?, ?)"#)
+        .bind(&proof.hash)
+        .bind(proof.size_bytes as i64)
+        .bind(Utc::now().to_rfc3339())
+        .execute(&mut *tx)
+        .await
+        .map_err(|_| ApiError::Internal)?;
 
-    let query_json = json!({
-        "metric": metric,
-        "bucket_index": bucket_index,
-        "field": "blood_glucose_mg_dl"
-    });
-    let result_json = json!({
-        "sum_glucose": sum,
-        "count": count,
-        "mean_glucose": mean
-    });
+    let replaced = sqlx::query(r#"SELECT stats_json FROM shards WHERE dataset_id = ? AND shard_index = ?"#)
+        .bind(&dataset_id)
+        .bind(shard_index as i64)
+        .fetch_optional(&mut *tx)
+        .await
+        .map_err(|_| ApiError::Internal)?
+        .map(|r| serde_json::from_str::<ShardStats>(&r.get::<String, _>(0)).map_err(|_| ApiError::Internal))
+        .transpose()?;
 
     sqlx::query(
-        r#"INSERT INTO queries (id, dataset_id, created_at, query_json, result_json, verified)
-           VALUES (?, ?, ?, ?, ?, ?)"#,
+        r#"INSERT OR REPLACE INTO shards
+           (dataset_id, shard_index, shard_commitment_hex, stats_json, proof_b64, proof_hash, verified)
+           VALUES (?, ?, ?, ?, '', ?, ?)"#,
     )
-    .bind(query_id.to_string())
-    .bind(dataset_id.to_string())
-    .bind(created_at)
-    .bind(query_json.to_string())
-    .bind(result_json.to_string())
+    .bind(&dataset_id)
+    .bind(shard_index as i64)
+    .bind(shard_commitment_hex)
+    .bind(stats_json)
+    .bind(&proof.hash)
     .bind(if verified { 1i64 } else { 0i64 })
-    .execute(db)
+    .execute(&mut *tx)
     .await
     .map_err(|_| ApiError::Internal)?;
 
+    let current = sqlx::query(&format!("SELECT {AGGREGATE_COLUMNS} FROM aggregates WHERE dataset_id = ? ORDER BY bucket_index"))
+        .bind(&dataset_id)
+        .fetch_all(&mut *tx)
+        .await
+        .map_err(|_| ApiError::Internal)?;
+    let mut aggregates = current.iter().map(bucket_aggregate_row).collect::<Result<Vec<_>, _>>()?;
+    if let Some(replaced) = &replaced {
+        apply_shard(&mut aggregates, replaced, false)?;
+    }
+    apply_shard(&mut aggregates, stats, true)?;
+    put_aggregates(&mut tx, &dataset_id, &aggregates).await?;
+
+    tx.commit().await.map_err(|_| ApiError::Internal)?;
+    Ok(())
+}
+
+async fn put_aggregates(tx: &mut sqlx::Transaction<'_, Sqlite>, dataset_id: &str, aggregates: &[BucketAggregate]) -> Result<(), ApiError> {
+    for (bucket_index, a) in aggregates.iter().enumerate() {
+        sqlx::query(
+            r#"INSERT OR REPLACE INTO aggregates
+               (dataset_id, bucket_index, shards, count, sums_json, sum_sq, sum_sq_shards, histogram_json, histogram_shards)
+               VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)"#,
+        )
+        .bind(dataset_id)
+        .bind(bucket_index as i64)
+        .bind(a.shards as i64)
+        .bind(a.count as i64)
+        .bind(serde_json::to_string(&a.sums).map_err(|_| ApiError::Internal)?)
+        .bind(a.sum_sq as i64)
+        .bind(a.sum_sq_shards as i64)
+        .bind(serde_json::to_string(&a.glucose_histogram).map_err(|_| ApiError::Internal)?)
+        .bind(a.histogram_shards as i64)
+        .execute(&mut **tx)
+        .await
+        .map_err(|_| ApiError::Internal)?;
+    }
+    Ok(())
+}
+
+pub async fn set_shard_quality(
+    db: &Db,
+    dataset_id: Uuid,
+    shard_index: u64,
+    quality: &ShardQuality,
+) -> Result<(), ApiError> {
+    let quality_json = serde_json::to_string(quality).map_err(|_| ApiError::Internal)?;
+
+    sqlx::query(r#"UPDATE shards SET quality_json = ? WHERE dataset_id = ? AND shard_index = ?"#)
+        .bind(quality_json)
+        .bind(dataset_id.to_string())
+        .bind(shard_index as i64)
+        .execute(db)
+        .await
+        .map_err(|_| ApiError::Internal)?;
+    Ok(())
+}
+
+/// Store the sealed master salt of a salted shard (see `salt::SaltSealer`).
+pub async fn set_shard_sealed_master_salt(db: &Db, dataset_id: Uuid, shard_index: u64, sealed: &[u8]) -> Result<(), ApiError> {
+    use base64::Engine;
+    sqlx::query(r#"UPDATE shards SET sealed_master_salt_b64 = ? WHERE dataset_id = ? AND shard_index = ?"#)
+        .bind(base64::engine::general_purpose::STANDARD.encode(sealed))
+        .bind(dataset_id.to_string())
+        .bind(shard_index as i64)
+        .execute(db)
+        .await
+        .map_err(|_| ApiError::Internal)?;
+    Ok(())
+}
+
+/// Record the id of the verifying key a stored shard's proof was checked with.
+pub async fn set_shard_vk_version(db: &Db, dataset_id: Uuid, shard_index: u64, vk_version: &str) -> Result<(), ApiError> {
+    sqlx::query(r#"UPDATE shards SET vk_version = ? WHERE dataset_id = ? AND shard_index = ?"#)
+        .bind(vk_version)
+        .bind(dataset_id.to_string())
+        .bind(shard_index as i64)
+        .execute(db)
+        .await
+        .map_err(|_| ApiError::Internal)?;
+    Ok(())
+}
+
+/// Record that the proofs of `shard_indices` verified just now against the key `vk_hash`.
+pub async fn set_shards_verified(db: &Db, dataset_id: Uuid, shard_indices: &[u64], vk_hash: &str) -> Result<(), ApiError> {
+    let verified_at = Utc::now().to_rfc3339();
+    let mut tx = db.begin().await.map_err(|_| ApiError::Internal)?;
+    for shard_index in shard_indices {
+        sqlx::query(r#"UPDATE shards SET verified_at = ?, verifier_vk_hash = ? WHERE dataset_id = ? AND shard_index = ?"#)
+            .bind(&verified_at)
+            .bind(vk_hash)
+            .bind(dataset_id.to_string())
+            .bind(*shard_index as i64)
+            .execute(&mut *tx)
+            .await
+            .map_err(|_| ApiError::Internal)?;
+    }
+    tx.commit().await.map_err(|_| ApiError::Internal)?;
+    Ok(())
+}
+
+/// Dataset-wide quality: ingestion counts plus per-bucket totals summed over shards.
+pub struct DatasetQualityRow {
+    /// `None` for datasets created before quality tracking.
+    pub ingest: Option<IngestQuality>,
+    pub count_by_bucket: Vec<u64>,
+    pub out_of_range_glucose_by_bucket: Vec<u64>,
+    /// Shards that carry quality counts (older shards may not).
+    pub shards_reporting: u64,
+}
+
+pub async fn dataset_quality(db: &Db, dataset_id: Uuid, buckets: &AgeBuckets) -> Result<DatasetQualityRow, ApiError> {
+    let ingest_json: Option<String> = sqlx::query(r#"SELECT ingest_quality_json FROM datasets WHERE id = ?"#)
+        .bind(dataset_id.to_string())
+        .fetch_one(db)
+        .await
+        .map_err(|_| ApiError::Internal)?
+        .get(0);
+
+    let rows = sqlx::query(r#"SELECT stats_json, quality_json FROM shards WHERE dataset_id = ?"#)
+        .bind(dataset_id.to_string())
+        .fetch_all(db)
+        .await
+        .map_err(|_| ApiError::Internal)?;
+
+    dataset_quality_row(ingest_json, &rows, buckets)
+}
+
+/// Sum the `(stats_json, quality_json)` rows of a dataset's shards into its quality.
+pub fn dataset_quality_row(
+    ingest_json: Option<String>,
+    rows: &[impl LedgerRow],
+    buckets: &AgeBuckets,
+) -> Result<DatasetQualityRow, ApiError> {
+    let ingest = ingest_json
+        .map(|j| serde_json::from_str(&j).map_err(|_| ApiError::Internal))
+        .transpose()?;
+
+    let mut out = DatasetQualityRow {
+        ingest,
+        count_by_bucket: vec![0; buckets.num_buckets()],
+        out_of_range_glucose_by_bucket: vec![0; buckets.num_buckets()],
+        shards_reporting: 0,
+    };
+
+    for row in rows {
+        let stats: ShardStats = serde_json::from_str(&row.text(0)).map_err(|_| ApiError::Internal)?;
+        for (total, count) in out.count_by_bucket.iter_mut().zip(&stats.count_by_bucket) {
+            *total += count;
+        }
+
+        if let Some(quality_json) = row.opt_text(1) {
+            let quality: ShardQuality = serde_json::from_str(&quality_json).map_err(|_| ApiError::Internal)?;
+            for (total, count) in out.out_of_range_glucose_by_bucket.iter_mut().zip(&quality.out_of_range_glucose_by_bucket) {
+                *total += count;
+            }
+            out.shards_reporting += 1;
+        }
+    }
+
+    Ok(out)
+}
+
+/// One row of the `datasets` table.
+pub struct DatasetRow {
+    pub created_at: DateTime<Utc>,
+    pub dataset_size: u64,
+    pub shard_size: u64,
+    /// Measurements recorded and proven per record.
+    pub field_set: FieldSet,
+    /// Age buckets the shard aggregates are split into.
+    pub age_buckets: AgeBuckets,
+    /// Hash folding the shard commitments into the dataset commitment.
+    pub chain_hash: ChainHash,
+    /// Shards also carry a circuit-bound SHA-256 commitment (dual-commitment keys).
+    pub sha256_commitment: bool,
+    pub status: String,
+    pub commitment_hex: Option<String>,
+    pub error: Option<String>,
+    /// Purposes the data subjects consented to. `None` means unrestricted.
+    pub consent_scope: Option<Vec<String>>,
+    /// Queries must be approved by an approver before any aggregate is released.
+    pub requires_approval: bool,
+    /// Max distinct aggregate releases per window; `None` uses the server default.
+    pub release_limit: Option<u64>,
+    /// Synthetic generator name; `None` for uploaded datasets.
+    pub generator: Option<String>,
+    /// Parameters the generator was configured with; `None` for its defaults.
+    pub generator_params: Option<serde_json::Value>,
+    /// Set while an admin has declared the commitment final; no further proving is allowed.
+    pub frozen_at: Option<DateTime<Utc>>,
+    /// Signer public key of the export this dataset was imported from; `None` if proven here.
+    pub imported_from: Option<String>,
+    /// Rolling window: only the last this many shards are live; `None` keeps every shard live.
+    pub window_shards: Option<u64>,
+}
+
+impl DatasetRow {
+    pub fn shards_total(&self) -> u64 {
+        self.dataset_size / self.shard_size
+    }
+
+    /// Indices of the shards queries and aggregates read: the last `window_shards` of them for
+    /// rolling-window datasets, all of them otherwise. Earlier shards are expired.
+    pub fn live_shards(&self) -> std::ops::Range<u64> {
+        let shards_total = self.shards_total();
+        let window = self.window_shards.unwrap_or(shards_total);
+        shards_total.saturating_sub(window)..shards_total
+    }
+}
+
+/// Columns `dataset_row` decodes, in order.
+pub const DATASET_COLUMNS: &str = "created_at, dataset_size, status, dataset_commitment_hex, error, consent_scope_json,
+    requires_approval, release_limit, generator, shard_size, frozen_at, imported_from, field_set, chain_hash,
+    sha256_commitment, age_buckets_json, window_shards, generator_params_json";
+
+pub async fn get_dataset(db: &Db, dataset_id: Uuid) -> Result<Option<DatasetRow>, ApiError> {
+    let row = sqlx::query(&format!("SELECT {DATASET_COLUMNS} FROM datasets WHERE id = ?"))
+        .bind(dataset_id.to_string())
+        .fetch_optional(db)
+        .await
+        .map_err(|_| ApiError::Internal)?;
+
+    row.map(|row| dataset_row(&row)).transpose()
+}
+
+/// Decode a row of `DATASET_COLUMNS`.
+pub fn dataset_row(row: &impl LedgerRow) -> Result<DatasetRow, ApiError> {
+    let consent_scope = row
+        .opt_text(5)
+        .map(|j| serde_json::from_str(&j).map_err(|_| ApiError::Internal))
+        .transpose()?;
+    let frozen_at = row.opt_text(10).map(|t| parse_time(&t)).transpose()?;
+    // NULL for datasets created before field sets existed, which are glucose-only.
+    let field_set = row
+        .opt_text(12)
+        .map(|f| FieldSet::parse(&f).ok_or(ApiError::Internal))
+        .transpose()?
+        .unwrap_or_default();
+    // NULL for datasets chained before the hash was selectable, which used Poseidon.
+    let chain_hash = row
+        .opt_text(13)
+        .map(|h| ChainHash::parse(&h).ok_or(ApiError::Internal))
+        .transpose()?
+        .unwrap_or_default();
+    // NULL for the default layout.
+    let age_buckets = row
+        .opt_text(15)
+        .map(|b| serde_json::from_str(&b).map_err(|_| ApiError::Internal))
+        .transpose()?
+        .unwrap_or_default();
+    let generator_params = row
+        .opt_text(17)
+        .map(|j| serde_json::from_str(&j).map_err(|_| ApiError::Internal))
+        .transpose()?;
+
+    Ok(DatasetRow {
+        created_at: parse_time(&row.text(0))?,
+        dataset_size: row.int(1) as u64,
+        status: row.text(2),
+        commitment_hex: row.opt_text(3),
+        error: row.opt_text(4),
+        consent_scope,
+        requires_approval: row.int(6) == 1,
+        release_limit: row.opt_int(7).map(|l| l as u64),
+        generator: row.opt_text(8),
+        generator_params,
+        shard_size: row.int(9) as u64,
+        field_set,
+        age_buckets,
+        chain_hash,
+        sha256_commitment: row.int(14) == 1,
+        frozen_at,
+        imported_from: row.opt_text(11),
+        window_shards: row.opt_int(16).map(|w| w as u64),
+    })
+}
+
+/// Count a failed proving attempt for a shard.
+pub async fn record_shard_failure(
+    db: &Db,
+    dataset_id: Uuid,
+    shard_index: u64,
+    error_class: &str,
+    error: &str,
+) -> Result<(), ApiError> {
+    let now = Utc::now().to_rfc3339();
+    sqlx::query(
+        r#"INSERT INTO shard_failures
+           (dataset_id, shard_index, error_class, attempts, last_error, first_failed_at, last_failed_at)
+           VALUES (?, ?, ?, 1, ?, ?, ?)
+           ON CONFLICT(dataset_id, shard_index) DO UPDATE SET
+             error_class = excluded.error_class,
+             attempts = attempts + 1,
+             last_error = excluded.last_error,
+             last_failed_at = excluded.last_failed_at"#,
+    )
+    .bind(dataset_id.to_string())
+    .bind(shard_index as i64)
+    .bind(error_class)
+    .bind(error)
+    .bind(&now)
+    .bind(&now)
+    .execute(db)
+    .await
+    .map_err(|_| ApiError::Internal)?;
     Ok(())
 }
+
+/// One row of the `shard_failures` table.
+pub struct ShardFailureRow {
+    pub shard_index: u64,
+    pub error_class: String,
+    pub attempts: u64,
+    pub last_error: String,
+    pub first_failed_at: DateTime<Utc>,
+    pub last_failed_at: DateTime<Utc>,
+}
+
+pub async fn list_shard_failures(db: &Db, dataset_id: Uuid) -> Result<Vec<ShardFailureRow>, ApiError> {
+    let rows = sqlx::query(
+        r#"SELECT shard_index, error_class, attempts, last_error, first_failed_at, last_failed_at
+           FROM shard_failures WHERE dataset_id = ? ORDER BY shard_index"#,
+    )
+    .bind(dataset_id.to_string())
+    .fetch_all(db)
+    .await
+    .map_err(|_| ApiError::Internal)?;
+
+    rows.iter().map(shard_failure_row).collect()
+}
+
+/// Decode `(shard_index, error_class, attempts, last_error, first_failed_at, last_failed_at)`.
+pub fn shard_failure_row(row: &impl LedgerRow) -> Result<ShardFailureRow, ApiError> {
+    Ok(ShardFailureRow {
+        shard_index: row.int(0) as u64,
+        error_class: row.text(1),
+        attempts: row.int(2) as u64,
+        last_error: row.text(3),
+        first_failed_at: parse_time(&row.text(4))?,
+        last_failed_at: parse_time(&row.text(5))?,
+    })
+}
+
+/// Ids of all datasets, oldest first.
+pub async fn list_dataset_ids(db: &Db) -> Result<Vec<Uuid>, ApiError> {
+    let rows = sqlx::query(r#"SELECT id FROM datasets ORDER BY created_at"#)
+        .fetch_all(db)
+        .await
+        .map_err(|_| ApiError::Internal)?;
+
+    rows.iter()
+        .map(|r| Uuid::parse_str(&r.get::<String, _>(0)).map_err(|_| ApiError::Internal))
+        .collect()
+}
+
+pub async fn count_shards_done(db: &Db, dataset_id: Uuid) -> Result<u64, ApiError> {
+    let row = sqlx::query(r#"SELECT COUNT(*) AS c FROM shards WHERE dataset_id = ?"#)
+        .bind(dataset_id.to_string())
+        .fetch_one(db)
+        .await
+        .map_err(|_| ApiError::Internal)?;
+    let c: i64 = row.get("c");
+    Ok(c as u64)
+}
+
+/// One shard as listed by `list_shards`: index, commitment (hex), public stats, whether its proof
+/// verified, and the proof (base64) when requested.
+pub type ShardListRow = (u64, String, ShardStats, bool, Option<String>);
+
+/// Shards with `shard_index` in `index_range`, paged by `offset`/`limit` within that range.
+pub async fn list_shards(
+    db: &Db,
+    files: &ProofFiles,
+    dataset_id: Uuid,
+    index_range: std::ops::Range<u64>,
+    offset: u64,
+    limit: u64,
+    include_proof: bool,
+) -> Result<Vec<ShardListRow>, ApiError> {
+    let rows = sqlx::query(
+        r#"SELECT s.shard_index, s.shard_commitment_hex, s.stats_json, s.verified,
+                  CASE WHEN ? THEN COALESCE(NULLIF(b.proof_b64, ''), s.proof_b64) ELSE '' END, s.proof_hash
+           FROM shards s
+           LEFT JOIN proof_blobs b ON b.hash = s.proof_hash
+           WHERE s.dataset_id = ? AND s.shard_index >= ? AND s.shard_index < ?
+           ORDER BY s.shard_index
+           LIMIT ? OFFSET ?"#,
+    )
+    .bind(include_proof)
+    .bind(dataset_id.to_string())
+    .bind(index_range.start.min(i64::MAX as u64) as i64)
+    .bind(index_range.end.min(i64::MAX as u64) as i64)
+    .bind(limit as i64)
+    .bind(offset as i64)
+    .fetch_all(db)
+    .await
+    .map_err(|_| ApiError::Internal)?;
+
+    let mut shards = Vec::with_capacity(rows.len());
+    for row in &rows {
+        let mut shard = shard_list_row(row, include_proof)?;
+        if let (Some(proof_b64), Some(hash)) = (&mut shard.4, row.opt_text(5)) {
+            *proof_b64 = proof_blob_b64(files, &hash, std::mem::take(proof_b64)).await?.ok_or_else(|| {
+                tracing::error!(%hash, "proof file missing");
+                ApiError::Internal
+            })?;
+        }
+        shards.push(shard);
+    }
+    Ok(shards)
+}
+
+/// `(shard_index, vk_version)` of the shards in `index_range` stored with a verifying key id.
+pub async fn shard_vk_versions(db: &Db, dataset_id: Uuid, index_range: std::ops::Range<u64>) -> Result<Vec<(u64, String)>, ApiError> {
+    let rows = sqlx::query(
+        r#"SELECT shard_index, vk_version FROM shards
+           WHERE dataset_id = ? AND shard_index >= ? AND shard_index < ? AND vk_version IS NOT NULL
+           ORDER BY shard_index"#,
+    )
+    .bind(dataset_id.to_string())
+    .bind(index_range.start.min(i64::MAX as u64) as i64)
+    .bind(index_range.end.min(i64::MAX as u64) as i64)
+    .fetch_all(db)
+    .await
+    .map_err(|_| ApiError::Internal)?;
+    Ok(rows.iter().map(|row| (row.int(0) as u64, row.text(1))).collect())
+}
+
+/// `(shard_index, verified_at, verifier_vk_hash)` of the shards in `index_range` whose proofs have
+/// been verified since they were stored.
+pub async fn shard_verifications(
+    db: &Db,
+    dataset_id: Uuid,
+    index_range: std::ops::Range<u64>,
+) -> Result<Vec<(u64, DateTime<Utc>, String)>, ApiError> {
+    let rows = sqlx::query(
+        r#"SELECT shard_index, verified_at, verifier_vk_hash FROM shards
+           WHERE dataset_id = ? AND shard_index >= ? AND shard_index < ? AND verified_at IS NOT NULL
+           ORDER BY shard_index"#,
+    )
+    .bind(dataset_id.to_string())
+    .bind(index_range.start.min(i64::MAX as u64) as i64)
+    .bind(index_range.end.min(i64::MAX as u64) as i64)
+    .fetch_all(db)
+    .await
+    .map_err(|_| ApiError::Internal)?;
+    rows.iter().map(shard_verification_row).collect()
+}
+
+/// Decode `(shard_index, verified_at, verifier_vk_hash)`.
+pub fn shard_verification_row(row: &impl LedgerRow) -> Result<(u64, DateTime<Utc>, String), ApiError> {
+    Ok((row.int(0) as u64, parse_time(&row.text(1))?, row.opt_text(2).unwrap_or_default()))
+}
+
+/// `(dataset_id, shard_index)` of up to `limit` shards of ready datasets, picked at random.
+pub async fn sample_shards(db: &Db, limit: u64) -> Result<Vec<(Uuid, u64)>, ApiError> {
+    let rows = sqlx::query(
+        r#"SELECT s.dataset_id, s.shard_index FROM shards s
+           JOIN datasets d ON d.id = s.dataset_id
+           WHERE d.status = 'ready'
+           ORDER BY RANDOM()
+           LIMIT ?"#,
+    )
+    .bind(limit.min(i64::MAX as u64) as i64)
+    .fetch_all(db)
+    .await
+    .map_err(|_| ApiError::Internal)?;
+    rows.iter().map(sampled_shard_row).collect()
+}
+
+/// Decode `(dataset_id, shard_index)`.
+pub fn sampled_shard_row(row: &impl LedgerRow) -> Result<(Uuid, u64), ApiError> {
+    let dataset_id = Uuid::parse_str(&row.text(0)).map_err(|_| ApiError::Internal)?;
+    Ok((dataset_id, row.int(1) as u64))
+}
+
+/// Decode `(shard_index, shard_commitment_hex, stats_json, verified, proof_b64)`.
+pub fn shard_list_row(row: &impl LedgerRow, include_proof: bool) -> Result<ShardListRow, ApiError> {
+    let stats: ShardStats = serde_json::from_str(&row.text(2)).map_err(|_| ApiError::Internal)?;
+    Ok((
+        row.int(0) as u64,
+        row.text(1),
+        stats,
+        row.int(3) == 1,
+        include_proof.then(|| row.text(4)),
+    ))
+}
+
+/// Per-bucket sums (of every measurement in `field_set`) and counts over the stored shards with
+/// an index in `shards` (split into `buckets`), and the number of shards summed.
+pub async fn dataset_totals(
+    db: &Db,
+    dataset_id: Uuid,
+    shards: std::ops::Range<u64>,
+    field_set: FieldSet,
+    buckets: &AgeBuckets,
+) -> Result<(ShardStats, u64), ApiError> {
+    let rows = sqlx::query(r#"SELECT stats_json FROM shards WHERE dataset_id = ? AND shard_index >= ? AND shard_index < ?"#)
+        .bind(dataset_id.to_string())
+        .bind(shards.start.min(i64::MAX as u64) as i64)
+        .bind(shards.end.min(i64::MAX as u64) as i64)
+        .fetch_all(db)
+        .await
+        .map_err(|_| ApiError::Internal)?;
+
+    sum_shard_stats(&rows, field_set, buckets)
+}
+
+/// Sum the `stats_json` rows of a dataset's shards (see `dataset_totals`).
+pub fn sum_shard_stats(rows: &[impl LedgerRow], field_set: FieldSet, buckets: &AgeBuckets) -> Result<(ShardStats, u64), ApiError> {
+    let mut totals = ShardStats {
+        glucose_bounds: None,
+        ..ShardStats::zero_for(field_set, buckets)
+    };
+    for row in rows {
+        let stats: ShardStats = serde_json::from_str(&row.text(0)).map_err(|_| ApiError::Internal)?;
+        if stats.count_by_bucket.len() != buckets.num_buckets() {
+            return Err(ApiError::Internal);
+        }
+        for b in 0..buckets.num_buckets() {
+            for f in 0..field_set.measurements().len() {
+                let sum = stats.sums_by_bucket(f).ok_or(ApiError::Internal)?[b];
+                if let Some(total) = totals.sums_by_bucket_mut(f) {
+                    total[b] += sum;
+                }
+            }
+            totals.count_by_bucket[b] += stats.count_by_bucket[b];
+        }
+        // Sums of squares are only meaningful if every shard proved them.
+        totals.sum_glucose_sq_by_bucket = match (totals.sum_glucose_sq_by_bucket, stats.sum_glucose_sq_by_bucket) {
+            (Some(total), Some(sums_sq)) => Some(total.iter().zip(&sums_sq).map(|(t, s)| t + s).collect()),
+            _ => None,
+        };
+        // Likewise the glucose histogram.
+        totals.glucose_histogram_by_bucket = match (totals.glucose_histogram_by_bucket, stats.glucose_histogram_by_bucket) {
+            (Some(total), Some(histogram)) => Some(
+                total
+                    .iter()
+                    .zip(&histogram)
+                    .map(|(t, h)| std::array::from_fn(|r| t[r] + h[r]))
+                    .collect(),
+            ),
+            _ => None,
+        };
+    }
+
+    Ok((totals, rows.len() as u64))
+}
+
+/// One bucket's aggregates over the live shards of a dataset (see `aggregate_for_bucket`).
+pub struct BucketTotals {
+    /// Sum of the measurement asked for.
+    pub sum: u64,
+    pub count: u64,
+    /// Sum of squared glucose, if every shard proved one.
+    pub sum_sq: Option<u64>,
+    /// Counts per `GLUCOSE_RANGES` range, if every shard proved them.
+    pub glucose_histogram: Option<[u64; NUM_GLUCOSE_RANGES]>,
+    /// Number of shards summed.
+    pub shards_total: u64,
+    /// Which shards were verified: bit `i % 8` of byte `i / 8` for shard `i` (shards before the
+    /// window are never set).
+    pub verified_bitmap: Vec<u8>,
+}
+
+/// Sum (of the measurement at `field_index` in the dataset's field set) and count of one bucket
+/// over the shards with an index in `shards` (`DatasetRow::live_shards`, so expired shards of a
+/// rolling window never count), plus the shard set they were read from.
+///
+/// Read from `aggregates` when `shards` covers every stored shard; only rolling windows with
+/// expired shards (and appends still in flight) sum the shard stats.
+pub async fn aggregate_for_bucket(
+    db: &Db,
+    dataset_id: Uuid,
+    shards: std::ops::Range<u64>,
+    bucket_index: usize,
+    field_index: usize,
+    buckets: &AgeBuckets,
+) -> Result<BucketTotals, ApiError> {
+    if bucket_index >= buckets.num_buckets() {
+        return Err(ApiError::BadRequest("invalid bucket".to_string()));
+    }
+
+    let verified = sqlx::query(
+        r#"SELECT shard_index, verified FROM shards
+           WHERE dataset_id = ? AND shard_index >= ? AND shard_index < ?
+           ORDER BY shard_index"#,
+    )
+    .bind(dataset_id.to_string())
+    .bind(shards.start.min(i64::MAX as u64) as i64)
+    .bind(shards.end.min(i64::MAX as u64) as i64)
+    .fetch_all(db)
+    .await
+    .map_err(|_| ApiError::Internal)?;
+    let aggregate = sqlx::query(&format!("SELECT {AGGREGATE_COLUMNS} FROM aggregates WHERE dataset_id = ? AND bucket_index = ?"))
+        .bind(dataset_id.to_string())
+        .bind(bucket_index as i64)
+        .fetch_optional(db)
+        .await
+        .map_err(|_| ApiError::Internal)?
+        .map(|row| bucket_aggregate_row(&row))
+        .transpose()?;
+    if let Some(aggregate) = aggregate
+        && aggregate.shards == verified.len() as u64
+    {
+        return aggregate.totals(field_index, &verified);
+    }
+
+    let rows = sqlx::query(
+        r#"SELECT shard_index, stats_json, verified FROM shards
+           WHERE dataset_id = ? AND shard_index >= ? AND shard_index < ?
+           ORDER BY shard_index"#,
+    )
+    .bind(dataset_id.to_string())
+    .bind(shards.start.min(i64::MAX as u64) as i64)
+    .bind(shards.end.min(i64::MAX as u64) as i64)
+    .fetch_all(db)
+    .await
+    .map_err(|_| ApiError::Internal)?;
+
+    bucket_totals(&rows, bucket_index, field_index, buckets)
+}
+
+/// Sum one bucket of the `(shard_index, stats_json, verified)` rows of a dataset's shards, in index
+/// order (see `aggregate_for_bucket`).
+pub fn bucket_totals(rows: &[impl LedgerRow], bucket_index: usize, field_index: usize, buckets: &AgeBuckets) -> Result<BucketTotals, ApiError> {
+    let mut sum = 0u64;
+    let mut count = 0u64;
+    let mut sum_sq = Some(0u64);
+    let mut glucose_histogram = Some([0u64; NUM_GLUCOSE_RANGES]);
+    let mut verified_bitmap = Vec::new();
+
+    for row in rows {
+        let shard_index = row.int(0);
+        let verified = row.int(2);
+        let stats: ShardStats = serde_json::from_str(&row.text(1)).map_err(|_| ApiError::Internal)?;
+        if stats.count_by_bucket.len() != buckets.num_buckets() {
+            return Err(ApiError::Internal);
+        }
+        sum += stats.sums_by_bucket(field_index).ok_or(ApiError::Internal)?[bucket_index];
+        count += stats.count_by_bucket[bucket_index];
+        sum_sq = sum_sq.zip(stats.sum_glucose_sq_by_bucket.as_ref()).map(|(total, sums_sq)| total + sums_sq[bucket_index]);
+        glucose_histogram = glucose_histogram
+            .zip(stats.glucose_histogram_by_bucket.as_ref())
+            .map(|(total, histogram)| std::array::from_fn(|r| total[r] + histogram[bucket_index][r]));
+
+        set_verified_bit(&mut verified_bitmap, shard_index, verified);
+    }
+
+    Ok(BucketTotals {
+        sum,
+        count,
+        sum_sq,
+        glucose_histogram,
+        shards_total: rows.len() as u64,
+        verified_bitmap,
+    })
+}
+
+fn set_verified_bit(bitmap: &mut Vec<u8>, shard_index: i64, verified: i64) {
+    let i = shard_index as usize;
+    if bitmap.len() <= i / 8 {
+        bitmap.resize(i / 8 + 1, 0u8);
+    }
+    if verified == 1 {
+        bitmap[i / 8] |= 1 << (i % 8);
+    }
+}
+
+/// Running totals of one age bucket over every stored shard of a dataset: a row of the
+/// `aggregates` table, updated as shards are stored so queries need not re-read shard stats.
+#[derive(Clone, Debug, Default)]
+pub struct BucketAggregate {
+    pub shards: u64,
+    pub count: u64,
+    /// Per measurement, in the dataset's field set order.
+    pub sums: Vec<u64>,
+    /// Sum of squared glucose over the `sum_sq_shards` shards that proved one.
+    pub sum_sq: u64,
+    pub sum_sq_shards: u64,
+    /// Glucose range counts over the `histogram_shards` shards that proved them.
+    pub glucose_histogram: [u64; NUM_GLUCOSE_RANGES],
+    pub histogram_shards: u64,
+}
+
+/// Columns `bucket_aggregate_row` decodes, in order.
+pub const AGGREGATE_COLUMNS: &str = "shards, count, sums_json, sum_sq, sum_sq_shards, histogram_json, histogram_shards";
+
+/// Decode a row of `AGGREGATE_COLUMNS`.
+pub fn bucket_aggregate_row(row: &impl LedgerRow) -> Result<BucketAggregate, ApiError> {
+    Ok(BucketAggregate {
+        shards: row.int(0) as u64,
+        count: row.int(1) as u64,
+        sums: serde_json::from_str(&row.text(2)).map_err(|_| ApiError::Internal)?,
+        sum_sq: row.int(3) as u64,
+        sum_sq_shards: row.int(4) as u64,
+        glucose_histogram: serde_json::from_str(&row.text(5)).map_err(|_| ApiError::Internal)?,
+        histogram_shards: row.int(6) as u64,
+    })
+}
+
+impl BucketAggregate {
+    /// Totals of the measurement at `field_index`, with the verified bitmap of the
+    /// `(shard_index, verified)` rows of every shard aggregated.
+    pub fn totals(&self, field_index: usize, verified: &[impl LedgerRow]) -> Result<BucketTotals, ApiError> {
+        let mut verified_bitmap = Vec::new();
+        for row in verified {
+            set_verified_bit(&mut verified_bitmap, row.int(0), row.int(1));
+        }
+        Ok(BucketTotals {
+            sum: *self.sums.get(field_index).ok_or(ApiError::Internal)?,
+            count: self.count,
+            sum_sq: (self.sum_sq_shards == self.shards).then_some(self.sum_sq),
+            glucose_histogram: (self.histogram_shards == self.shards).then_some(self.glucose_histogram),
+            shards_total: self.shards,
+            verified_bitmap,
+        })
+    }
+}
+
+/// Add (or with `add == false`, take back) one shard's stats to the per-bucket aggregates of its
+/// dataset; an empty `aggregates` starts from zero.
+pub fn apply_shard(aggregates: &mut Vec<BucketAggregate>, stats: &ShardStats, add: bool) -> Result<(), ApiError> {
+    let num_buckets = stats.count_by_bucket.len();
+    let num_fields = 1 + stats.extra_sums_by_bucket.len();
+    if aggregates.is_empty() {
+        *aggregates = vec![
+            BucketAggregate {
+                sums: vec![0; num_fields],
+                ..BucketAggregate::default()
+            };
+            num_buckets
+        ];
+    }
+    if aggregates.len() != num_buckets {
+        return Err(ApiError::Internal);
+    }
+
+    let step = |total: &mut u64, value: u64| {
+        *total = if add { total.saturating_add(value) } else { total.saturating_sub(value) };
+    };
+    for (b, a) in aggregates.iter_mut().enumerate() {
+        step(&mut a.shards, 1);
+        step(&mut a.count, stats.count_by_bucket[b]);
+        if a.sums.len() != num_fields {
+            return Err(ApiError::Internal);
+        }
+        for (f, total) in a.sums.iter_mut().enumerate() {
+            step(total, stats.sums_by_bucket(f).ok_or(ApiError::Internal)?[b]);
+        }
+        if let Some(sums_sq) = &stats.sum_glucose_sq_by_bucket {
+            step(&mut a.sum_sq, sums_sq[b]);
+            step(&mut a.sum_sq_shards, 1);
+        }
+        if let Some(histogram) = &stats.glucose_histogram_by_bucket {
+            for (total, count) in a.glucose_histogram.iter_mut().zip(&histogram[b]) {
+                step(total, *count);
+            }
+            step(&mut a.histogram_shards, 1);
+        }
+    }
+    Ok(())
+}
+
+/// Per-bucket aggregates of the `stats_json` rows of a dataset's shards.
+pub fn sum_bucket_aggregates(rows: &[impl LedgerRow]) -> Result<Vec<BucketAggregate>, ApiError> {
+    let mut aggregates = Vec::new();
+    for row in rows {
+        let stats: ShardStats = serde_json::from_str(&row.text(0)).map_err(|_| ApiError::Internal)?;
+        apply_shard(&mut aggregates, &stats, true)?;
+    }
+    Ok(aggregates)
+}
+
+/// The age buckets a query aggregates: `first..=last` (one bucket, or several consecutive ones
+/// for an age range spanning them), or with `complement` everyone outside them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QueryBuckets {
+    pub first: usize,
+    pub last: usize,
+    pub complement: bool,
+}
+
+impl QueryBuckets {
+    pub fn single(bucket_index: usize) -> Self {
+        Self { first: bucket_index, last: bucket_index, complement: false }
+    }
+
+    /// Whether the range covers more than one bucket.
+    pub fn spans(&self) -> bool {
+        self.last > self.first
+    }
+
+    /// The buckets added up, of a dataset with `num_buckets`.
+    pub fn indices(&self, num_buckets: usize) -> Vec<usize> {
+        (0..num_buckets).filter(|b| (self.first..=self.last).contains(b) != self.complement).collect()
+    }
+}
+
+/// What a query asks for, as stored with it.
+pub struct QuerySpec<'a> {
+    pub metric: &'a Metric,
+    pub purpose: Option<&'a QueryPurpose>,
+    pub buckets: QueryBuckets,
+    pub field: Measurement,
+    /// Differential privacy to apply on release.
+    pub dp: Option<DpParams>,
+    /// The cohort query this is one dataset's share of a bucket of.
+    pub cohort_id: Option<Uuid>,
+    /// Key that asked for the query (`Caller::key_id`), so it can't approve it itself.
+    pub requested_by: Option<&'a str>,
+}
+
+pub async fn insert_query(
+    db: &Db,
+    query_id: Uuid,
+    dataset_id: Uuid,
+    spec: &QuerySpec<'_>,
+    result: &QueryResult,
+) -> Result<(), ApiError> {
+    let created_at = Utc::now().to_rfc3339();
+
+    let query_json = query_json(spec);
+    let result_json = result_json(result);
+    let shard_set = result.shard_set.as_ref();
+
+    sqlx::query(
+        r#"INSERT INTO queries (id, dataset_id, created_at, query_json, result_json, verified, release_key, released_at,
+                                dataset_commitment_hex, shards_total, verified_bitmap_hex, first_shard_index, requested_by)
+           VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)"#,
+    )
+    .bind(query_id.to_string())
+    .bind(dataset_id.to_string())
+    .bind(&created_at)
+    .bind(query_json.to_string())
+    .bind(result_json.to_string())
+    .bind(if result.verified { 1i64 } else { 0i64 })
+    .bind(release_key(spec.buckets, spec.field))
+    .bind(&created_at)
+    .bind(shard_set.and_then(|s| s.dataset_commitment_hex.as_deref()))
+    .bind(shard_set.map(|s| s.shards_total as i64))
+    .bind(shard_set.map(|s| s.verified_bitmap_hex.as_str()))
+    .bind(shard_set.map(|s| s.first_shard_index as i64))
+    .bind(spec.requested_by)
+    .execute(db)
+    .await
+    .map_err(|_| ApiError::Internal)?;
+
+    Ok(())
+}
+
+/// Identifies which aggregate a query releases. Queries with the same key disclose the same
+/// numbers, so repeating one does not count against the release limit. Glucose keeps the key it
+/// had before other measurements could be queried; ranges of several buckets are keyed
+/// `buckets:<first>-<last>`, and complement queries prefix the range's key.
+pub fn release_key(buckets: QueryBuckets, field: Measurement) -> String {
+    let range = if buckets.spans() {
+        format!("buckets:{}-{}", buckets.first, buckets.last)
+    } else {
+        format!("bucket:{}", buckets.first)
+    };
+    let key = match field {
+        Measurement::BloodGlucose => range,
+        other => format!("{range}:{}", other.name()),
+    };
+    if buckets.complement { format!("complement:{key}") } else { key }
+}
+
+/// Distinct release keys disclosed for a dataset since `since`.
+pub async fn release_keys_since(db: &Db, dataset_id: Uuid, since: DateTime<Utc>) -> Result<Vec<String>, ApiError> {
+    let rows = sqlx::query(
+        r#"SELECT DISTINCT release_key FROM queries
+           WHERE dataset_id = ? AND released_at IS NOT NULL AND released_at >= ? AND release_key IS NOT NULL"#,
+    )
+    .bind(dataset_id.to_string())
+    .bind(since.to_rfc3339())
+    .fetch_all(db)
+    .await
+    .map_err(|_| ApiError::Internal)?;
+
+    Ok(rows.into_iter().map(|r| r.get(0)).collect())
+}
+
+pub fn query_json(spec: &QuerySpec<'_>) -> serde_json::Value {
+    json!({
+        "metric": spec.metric,
+        "bucket_index": spec.buckets.first,
+        "last_bucket_index": spec.buckets.last,
+        "field": spec.field.name(),
+        "complement": spec.buckets.complement,
+        "purpose": spec.purpose,
+        "dp": spec.dp,
+        "cohort_id": spec.cohort_id
+    })
+}
+
+pub fn result_json(result: &QueryResult) -> serde_json::Value {
+    json!({
+        "sum": result.sum,
+        "count": result.count,
+        "mean": result.mean,
+        "sum_sq": result.sum_sq,
+        "variance": result.variance,
+        "glucose_histogram": result.glucose_histogram,
+        "query_proof_b64": result.query_proof.as_ref().map(|(proof_b64, _)| proof_b64),
+        "query_proof": result.query_proof.as_ref().map(|(_, statement)| statement),
+        "dp": result.dp,
+        "combined": result.combined,
+        "excluded": result.excluded
+    })
+}
+
+/// One row of the `queries` table.
+pub struct QueryRow {
+    pub id: Uuid,
+    pub dataset_id: Uuid,
+    /// `pending_approval`, `queued`, `running`, `released`, `rejected` or `failed`.
+    pub status: String,
+    pub query_json: serde_json::Value,
+    /// `None` until released.
+    pub result: Option<QueryResult>,
+    /// The stored result as it was written, once released.
+    pub result_json: Option<serde_json::Value>,
+    pub error: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub released_at: Option<DateTime<Utc>>,
+    /// Approver who released or rejected a held query.
+    pub decided_by: Option<String>,
+    /// Key that asked for the query; `None` for cohort and `group_by` shares and queries stored
+    /// before it was recorded.
+    pub requested_by: Option<String>,
+}
+
+/// Released aggregate of a query.
+pub struct QueryResult {
+    pub sum: u64,
+    pub count: u64,
+    pub mean: Option<f64>,
+    /// Sum of squares and population variance, for `variance` / `stddev` queries.
+    pub sum_sq: Option<u64>,
+    pub variance: Option<f64>,
+    /// Counts per glucose range, for `histogram` queries.
+    pub glucose_histogram: Option<[u64; NUM_GLUCOSE_RANGES]>,
+    pub verified: bool,
+    /// Shards the aggregate was computed over; `None` for queries released before it was recorded.
+    pub shard_set: Option<QueryShardSet>,
+    /// Query proof of `sum` and `count`, with its statement; `None` when not proven.
+    pub query_proof: Option<(String, QueryProofStatement)>,
+    /// Set when `sum`, `count` and `mean` are differentially private (noisy).
+    pub dp: Option<DpRelease>,
+    /// For complement answers and ranges of several buckets, the buckets added up with their
+    /// counts (kept to decide suppression; only the buckets are released).
+    pub combined: Option<Vec<(usize, u64)>>,
+    /// For complement answers, the buckets left out with their counts: the dataset size is public,
+    /// so the complement's count gives away theirs, and they decide suppression too.
+    pub excluded: Option<Vec<(usize, u64)>>,
+}
+
+/// Record a query that is not answered immediately (held for approval, or queued as a job).
+pub async fn insert_unreleased_query(
+    db: &Db,
+    query_id: Uuid,
+    dataset_id: Uuid,
+    spec: &QuerySpec<'_>,
+    status: &str,
+) -> Result<(), ApiError> {
+    let created_at = Utc::now().to_rfc3339();
+
+    sqlx::query(
+        r#"INSERT INTO queries (id, dataset_id, created_at, query_json, result_json, verified, status, release_key, requested_by)
+           VALUES (?, ?, ?, ?, 'null', 0, ?, ?, ?)"#,
+    )
+    .bind(query_id.to_string())
+    .bind(dataset_id.to_string())
+    .bind(created_at)
+    .bind(query_json(spec).to_string())
+    .bind(status)
+    .bind(release_key(spec.buckets, spec.field))
+    .bind(spec.requested_by)
+    .execute(db)
+    .await
+    .map_err(|_| ApiError::Internal)?;
+
+    Ok(())
+}
+
+/// Columns `query_row` decodes, in order.
+pub const QUERY_COLUMNS: &str = "dataset_id, status, query_json, result_json, verified, error, dataset_commitment_hex,
+    shards_total, verified_bitmap_hex, first_shard_index, id, created_at, released_at, decided_by, requested_by";
+
+pub async fn get_query(db: &Db, query_id: Uuid) -> Result<Option<QueryRow>, ApiError> {
+    let row = sqlx::query(&format!("SELECT {QUERY_COLUMNS} FROM queries WHERE id = ?"))
+        .bind(query_id.to_string())
+        .fetch_optional(db)
+        .await
+        .map_err(|_| ApiError::Internal)?;
+
+    row.map(|row| query_row(&row)).transpose()
+}
+
+/// A dataset's queries in the order they were made, whatever their status.
+pub async fn list_dataset_queries(db: &Db, dataset_id: Uuid, offset: u64, limit: u64) -> Result<Vec<QueryRow>, ApiError> {
+    let rows = sqlx::query(&format!(
+        "SELECT {QUERY_COLUMNS} FROM queries WHERE dataset_id = ? ORDER BY created_at, id LIMIT ? OFFSET ?"
+    ))
+    .bind(dataset_id.to_string())
+    .bind(limit as i64)
+    .bind(offset as i64)
+    .fetch_all(db)
+    .await
+    .map_err(|_| ApiError::Internal)?;
+
+    rows.iter().map(query_row).collect()
+}
+
+/// Decode a row of `QUERY_COLUMNS`.
+pub fn query_row(row: &impl LedgerRow) -> Result<QueryRow, ApiError> {
+    let dataset_id = row.text(0);
+    let status = row.text(1);
+    let query_json = row.text(2);
+    let result_json = row.text(3);
+    let verified = row.int(4);
+
+    let result_json = if status == "released" {
+        Some(serde_json::from_str::<serde_json::Value>(&result_json).map_err(|_| ApiError::Internal)?)
+    } else {
+        None
+    };
+    let result = if let Some(r) = &result_json {
+        // Results stored before other measurements could be queried use the glucose names.
+        Some(QueryResult {
+            sum: r["sum"].as_u64().or(r["sum_glucose"].as_u64()).ok_or(ApiError::Internal)?,
+            count: r["count"].as_u64().ok_or(ApiError::Internal)?,
+            mean: r["mean"].as_f64().or(r["mean_glucose"].as_f64()),
+            sum_sq: r["sum_sq"].as_u64(),
+            variance: r["variance"].as_f64(),
+            glucose_histogram: serde_json::from_value(r["glucose_histogram"].clone()).map_err(|_| ApiError::Internal)?,
+            verified: verified == 1,
+            shard_set: match (row.opt_int(7), row.opt_text(8)) {
+                (Some(shards_total), Some(verified_bitmap_hex)) => Some(QueryShardSet {
+                    dataset_commitment_hex: row.opt_text(6),
+                    shards_total: shards_total as u64,
+                    verified_bitmap_hex,
+                    // NULL for queries answered before rolling windows, which summed every shard.
+                    first_shard_index: row.opt_int(9).unwrap_or(0) as u64,
+                }),
+                _ => None,
+            },
+            query_proof: r["query_proof_b64"]
+                .as_str()
+                .zip(serde_json::from_value::<QueryProofStatement>(r["query_proof"].clone()).ok())
+                .map(|(proof_b64, statement)| (proof_b64.to_string(), statement)),
+            dp: serde_json::from_value(r["dp"].clone()).map_err(|_| ApiError::Internal)?,
+            combined: serde_json::from_value(r["combined"].clone()).map_err(|_| ApiError::Internal)?,
+            excluded: serde_json::from_value(r["excluded"].clone()).map_err(|_| ApiError::Internal)?,
+        })
+    } else {
+        None
+    };
+
+    Ok(QueryRow {
+        id: Uuid::parse_str(&row.text(10)).map_err(|_| ApiError::Internal)?,
+        dataset_id: Uuid::parse_str(&dataset_id).map_err(|_| ApiError::Internal)?,
+        status,
+        query_json: serde_json::from_str(&query_json).map_err(|_| ApiError::Internal)?,
+        result,
+        result_json,
+        error: row.opt_text(5),
+        created_at: parse_time(&row.text(11))?,
+        released_at: row.opt_text(12).as_deref().map(parse_time).transpose()?,
+        decided_by: row.opt_text(13),
+        requested_by: row.opt_text(14),
+    })
+}
+
+/// Store the result of a deferred query. Returns false if it was no longer in `from_status`.
+pub async fn release_query(
+    db: &Db,
+    query_id: Uuid,
+    from_status: &str,
+    result: &QueryResult,
+    decided_by: Option<&str>,
+) -> Result<bool, ApiError> {
+    let res = sqlx::query(
+        r#"UPDATE queries SET result_json = ?, verified = ?, status = 'released', decided_by = ?, released_at = ?,
+                              dataset_commitment_hex = ?, shards_total = ?, verified_bitmap_hex = ?, first_shard_index = ?
+           WHERE id = ? AND status = ?"#,
+    )
+    .bind(result_json(result).to_string())
+    .bind(if result.verified { 1i64 } else { 0i64 })
+    .bind(decided_by)
+    .bind(Utc::now().to_rfc3339())
+    .bind(result.shard_set.as_ref().and_then(|s| s.dataset_commitment_hex.as_deref()))
+    .bind(result.shard_set.as_ref().map(|s| s.shards_total as i64))
+    .bind(result.shard_set.as_ref().map(|s| s.verified_bitmap_hex.as_str()))
+    .bind(result.shard_set.as_ref().map(|s| s.first_shard_index as i64))
+    .bind(query_id.to_string())
+    .bind(from_status)
+    .execute(db)
+    .await
+    .map_err(|_| ApiError::Internal)?;
+
+    Ok(res.rows_affected() == 1)
+}
+
+/// Move a query between non-released states. Returns false if it was no longer in `from`.
+pub async fn set_query_status(db: &Db, query_id: Uuid, from: &str, to: &str) -> Result<bool, ApiError> {
+    let res = sqlx::query(r#"UPDATE queries SET status = ? WHERE id = ? AND status = ?"#)
+        .bind(to)
+        .bind(query_id.to_string())
+        .bind(from)
+        .execute(db)
+        .await
+        .map_err(|_| ApiError::Internal)?;
+
+    Ok(res.rows_affected() == 1)
+}
+
+pub async fn fail_query(db: &Db, query_id: Uuid, error: &str) -> Result<(), ApiError> {
+    sqlx::query(r#"UPDATE queries SET status = 'failed', error = ? WHERE id = ? AND status != 'released'"#)
+        .bind(error)
+        .bind(query_id.to_string())
+        .execute(db)
+        .await
+        .map_err(|_| ApiError::Internal)?;
+    Ok(())
+}
+
+/// Reject a pending query. Returns false if it was no longer pending.
+pub async fn reject_pending_query(db: &Db, query_id: Uuid, decided_by: &str) -> Result<bool, ApiError> {
+    let res = sqlx::query(
+        r#"UPDATE queries SET status = 'rejected', decided_by = ?
+           WHERE id = ? AND status = 'pending_approval'"#,
+    )
+    .bind(decided_by)
+    .bind(query_id.to_string())
+    .execute(db)
+    .await
+    .map_err(|_| ApiError::Internal)?;
+
+    Ok(res.rows_affected() == 1)
+}
+
+/// A claimed row of the `jobs` table.
+pub struct JobRow {
+    pub id: Uuid,
+    pub kind: String,
+    pub subject_id: Uuid,
+}
+
+/// Queue a job; with `instance`, only that instance may claim it.
+pub async fn insert_job(db: &Db, job_id: Uuid, kind: &str, subject_id: Uuid, tenant: &str, instance: Option<&str>) -> Result<(), ApiError> {
+    sqlx::query(
+        r#"INSERT INTO jobs (id, kind, subject_id, status, created_at, tenant, instance)
+           VALUES (?, ?, ?, 'queued', ?, ?, ?)"#,
+    )
+    .bind(job_id.to_string())
+    .bind(kind)
+    .bind(subject_id.to_string())
+    .bind(Utc::now().to_rfc3339())
+    .bind(tenant)
+    .bind(instance)
+    .execute(db)
+    .await
+    .map_err(|_| ApiError::Internal)?;
+    Ok(())
+}
+
+/// Atomically take the oldest queued job of `kind` that `instance` may run, marking it running
+/// on `instance`.
+///
+/// With `per_tenant_limit`, jobs of tenants that already have that many `kind` jobs running are
+/// skipped, so one tenant's backlog can't occupy every worker.
+pub async fn claim_next_job(db: &Db, kind: &str, instance: &str, per_tenant_limit: Option<u64>) -> Result<Option<JobRow>, ApiError> {
+    let now = Utc::now().to_rfc3339();
+    let row = sqlx::query(
+        r#"UPDATE jobs SET status = 'running', started_at = ?, claimed_by = ?, heartbeat_at = ?
+           WHERE id = (
+             SELECT j.id FROM jobs j
+             WHERE j.status = 'queued' AND j.kind = ? AND (j.instance IS NULL OR j.instance = ?)
+               AND (? IS NULL OR (SELECT COUNT(*) FROM jobs r
+                                  WHERE r.status = 'running' AND r.kind = j.kind AND r.tenant IS j.tenant) < ?)
+             ORDER BY j.created_at LIMIT 1)
+           RETURNING id, kind, subject_id"#,
+    )
+    .bind(&now)
+    .bind(instance)
+    .bind(&now)
+    .bind(kind)
+    .bind(instance)
+    .bind(per_tenant_limit.map(|l| l as i64))
+    .bind(per_tenant_limit.map(|l| l as i64))
+    .fetch_optional(db)
+    .await
+    .map_err(|_| ApiError::Internal)?;
+
+    let Some(row) = row else { return Ok(None); };
+
+    let id: String = row.get(0);
+    let subject_id: String = row.get(2);
+    Ok(Some(JobRow {
+        id: Uuid::parse_str(&id).map_err(|_| ApiError::Internal)?,
+        kind: row.get(1),
+        subject_id: Uuid::parse_str(&subject_id).map_err(|_| ApiError::Internal)?,
+    }))
+}
+
+pub async fn finish_job(db: &Db, job_id: Uuid, error: Option<&str>) -> Result<(), ApiError> {
+    sqlx::query(r#"UPDATE jobs SET status = ?, error = ?, finished_at = ? WHERE id = ?"#)
+        .bind(if error.is_some() { "failed" } else { "done" })
+        .bind(error)
+        .bind(Utc::now().to_rfc3339())
+        .bind(job_id.to_string())
+        .execute(db)
+        .await
+        .map_err(|_| ApiError::Internal)?;
+    Ok(())
+}
+
+/// Resources held by one tenant, for quota checks and `/api/v1/usage`.
+pub struct TenantUsageRow {
+    pub datasets: u64,
+    /// Records across the tenant's datasets that have not failed.
+    pub records: u64,
+    pub proving_running: u64,
+    pub proving_queued: u64,
+    /// Queries submitted today (UTC).
+    pub queries_today: u64,
+}
+
+/// Datasets a tenant owns and the records across those that have not failed or been cancelled.
+pub async fn tenant_datasets(db: &Db, key_id: &str) -> Result<(u64, u64), ApiError> {
+    let row = sqlx::query(
+        r#"SELECT COUNT(*), COALESCE(SUM(CASE WHEN status NOT IN ('failed', 'cancelled') THEN dataset_size ELSE 0 END), 0)
+           FROM datasets WHERE owner_key_id = ?"#,
+    )
+    .bind(key_id)
+    .fetch_one(db)
+    .await
+    .map_err(|_| ApiError::Internal)?;
+
+    Ok((row.get::<i64, _>(0) as u64, row.get::<i64, _>(1) as u64))
+}
+
+/// Dataset proving jobs of a tenant running and queued.
+pub async fn tenant_proving_jobs(db: &Db, key_id: &str) -> Result<(u64, u64), ApiError> {
+    let row = sqlx::query(
+        r#"SELECT
+             (SELECT COUNT(*) FROM jobs WHERE tenant = ?1 AND kind = ?2 AND status = 'running'),
+             (SELECT COUNT(*) FROM jobs WHERE tenant = ?1 AND kind = ?2 AND status = 'queued')"#,
+    )
+    .bind(key_id)
+    .bind(crate::jobs::KIND_PROVE_DATASET)
+    .fetch_one(db)
+    .await
+    .map_err(|_| ApiError::Internal)?;
+
+    Ok((row.get::<i64, _>(0) as u64, row.get::<i64, _>(1) as u64))
+}
+
+/// Dataset proving jobs running and queued, across tenants.
+pub async fn proving_backlog(db: &Db) -> Result<(u64, u64), ApiError> {
+    let row = sqlx::query(
+        r#"SELECT
+             (SELECT COUNT(*) FROM jobs WHERE kind = ?1 AND status = 'running'),
+             (SELECT COUNT(*) FROM jobs WHERE kind = ?1 AND status = 'queued')"#,
+    )
+    .bind(crate::jobs::KIND_PROVE_DATASET)
+    .fetch_one(db)
+    .await
+    .map_err(|_| ApiError::Internal)?;
+
+    Ok((row.get::<i64, _>(0) as u64, row.get::<i64, _>(1) as u64))
+}
+
+/// Jobs for `subject_id` queued or running.
+pub async fn active_jobs(db: &Db, subject_id: Uuid) -> Result<u64, ApiError> {
+    let row = sqlx::query(r#"SELECT COUNT(*) FROM jobs WHERE subject_id = ? AND status IN ('queued', 'running')"#)
+        .bind(subject_id.to_string())
+        .fetch_one(db)
+        .await
+        .map_err(|_| ApiError::Internal)?;
+    Ok(row.get::<i64, _>(0) as u64)
+}
+
+/// Status and error of the most recent job of `kind` for `subject_id`.
+pub async fn latest_job(db: &Db, kind: &str, subject_id: Uuid) -> Result<Option<(String, Option<String>)>, ApiError> {
+    let row = sqlx::query(
+        r#"SELECT status, error FROM jobs WHERE kind = ? AND subject_id = ?
+           ORDER BY created_at DESC LIMIT 1"#,
+    )
+    .bind(kind)
+    .bind(subject_id.to_string())
+    .fetch_optional(db)
+    .await
+    .map_err(|_| ApiError::Internal)?;
+    Ok(row.map(|row| (row.get(0), row.get(1))))
+}
+
+/// Put a running job back on the queue (interrupted by a shutdown).
+pub async fn requeue_job(db: &Db, job_id: Uuid) -> Result<(), ApiError> {
+    sqlx::query(
+        r#"UPDATE jobs SET status = 'queued', started_at = NULL, claimed_by = NULL, heartbeat_at = NULL
+           WHERE id = ? AND status = 'running'"#,
+    )
+    .bind(job_id.to_string())
+    .execute(db)
+    .await
+    .map_err(|_| ApiError::Internal)?;
+    Ok(())
+}
+
+/// Mark a running job as still being worked on.
+pub async fn heartbeat_job(db: &Db, job_id: Uuid) -> Result<(), ApiError> {
+    sqlx::query(r#"UPDATE jobs SET heartbeat_at = ? WHERE id = ? AND status = 'running'"#)
+        .bind(Utc::now().to_rfc3339())
+        .bind(job_id.to_string())
+        .execute(db)
+        .await
+        .map_err(|_| ApiError::Internal)?;
+    Ok(())
+}
+
+/// Put running jobs back on the queue that `instance` was running before it restarted (and
+/// unclaimed ones from before jobs recorded their instance), or whose last heartbeat is before
+/// `stale_before`. Returns how many were requeued.
+pub async fn requeue_running_jobs(db: &Db, instance: Option<&str>, stale_before: DateTime<Utc>) -> Result<u64, ApiError> {
+    let res = sqlx::query(
+        r#"UPDATE jobs SET status = 'queued', started_at = NULL, claimed_by = NULL, heartbeat_at = NULL
+           WHERE status = 'running'
+             AND ((? IS NOT NULL AND (claimed_by = ? OR claimed_by IS NULL))
+                  OR COALESCE(heartbeat_at, started_at) < ?)"#,
+    )
+    .bind(instance)
+    .bind(instance)
+    .bind(stale_before.to_rfc3339())
+    .execute(db)
+    .await
+    .map_err(|_| ApiError::Internal)?;
+    Ok(res.rows_affected())
+}
+
+/// Drop every job of `subject_id` (its dataset was deleted).
+pub async fn delete_jobs(db: &Db, subject_id: Uuid) -> Result<(), ApiError> {
+    sqlx::query(r#"DELETE FROM jobs WHERE subject_id = ?"#)
+        .bind(subject_id.to_string())
+        .execute(db)
+        .await
+        .map_err(|_| ApiError::Internal)?;
+    Ok(())
+}
+
+/// Serializes audit appends so each entry chains onto the true previous head.
+static AUDIT_LOCK: Mutex<()> = Mutex::const_new(());
+
+/// `prev_hash` of the first entry.
+pub const AUDIT_GENESIS_HASH: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+/// One row of the hash-chained `audit_log` table.
+pub struct AuditRow {
+    pub seq: u64,
+    pub created_at: DateTime<Utc>,
+    pub event: String,
+    pub details: serde_json::Value,
+    pub prev_hash: String,
+    pub entry_hash: String,
+}
+
+pub fn audit_entry_hash(prev_hash: &str, created_at: &str, dataset_id: &str, event: &str, details_json: &str) -> String {
+    let mut h = Sha256::new();
+    for part in [prev_hash, created_at, dataset_id, event, details_json] {
+        // Length-prefix each part so field boundaries are unambiguous.
+        h.update((part.len() as u64).to_le_bytes());
+        h.update(part.as_bytes());
+    }
+    hex::encode(h.finalize())
+}
+
+/// Append an entry to the audit chain, returning its entry hash.
+///
+/// Each entry commits to the previous entry's hash, so rewriting history requires rewriting
+/// every later entry.
+pub async fn append_audit(
+    db: &Db,
+    dataset_id: Option<Uuid>,
+    event: &str,
+    details: &serde_json::Value,
+) -> Result<String, ApiError> {
+    let _guard = AUDIT_LOCK.lock().await;
+
+    let prev = sqlx::query(r#"SELECT entry_hash FROM audit_log ORDER BY seq DESC LIMIT 1"#)
+        .fetch_optional(db)
+        .await
+        .map_err(|_| ApiError::Internal)?;
+    let prev_hash: String = prev
+        .map(|r| r.get(0))
+        .unwrap_or_else(|| AUDIT_GENESIS_HASH.to_string());
+
+    let created_at = Utc::now().to_rfc3339();
+    let dataset_id = dataset_id.map(|id| id.to_string()).unwrap_or_default();
+    let details_json = details.to_string();
+    let entry_hash = audit_entry_hash(&prev_hash, &created_at, &dataset_id, event, &details_json);
+
+    sqlx::query(
+        r#"INSERT INTO audit_log (created_at, dataset_id, event, details_json, prev_hash, entry_hash)
+           VALUES (?, ?, ?, ?, ?, ?)"#,
+    )
+    .bind(created_at)
+    .bind(dataset_id)
+    .bind(event)
+    .bind(details_json)
+    .bind(prev_hash)
+    .bind(&entry_hash)
+    .execute(db)
+    .await
+    .map_err(|_| ApiError::Internal)?;
+
+    Ok(entry_hash)
+}
+
+pub async fn list_audit(db: &Db, dataset_id: Uuid, offset: u64, limit: u64) -> Result<Vec<AuditRow>, ApiError> {
+    let rows = sqlx::query(
+        r#"SELECT seq, created_at, event, details_json, prev_hash, entry_hash
+           FROM audit_log
+           WHERE dataset_id = ?
+           ORDER BY seq
+           LIMIT ? OFFSET ?"#,
+    )
+    .bind(dataset_id.to_string())
+    .bind(limit as i64)
+    .bind(offset as i64)
+    .fetch_all(db)
+    .await
+    .map_err(|_| ApiError::Internal)?;
+
+    rows.iter().map(audit_row).collect()
+}
+
+/// Decode `(seq, created_at, event, details_json, prev_hash, entry_hash)`.
+pub fn audit_row(row: &impl LedgerRow) -> Result<AuditRow, ApiError> {
+    Ok(AuditRow {
+        seq: row.int(0) as u64,
+        created_at: parse_time(&row.text(1))?,
+        event: row.text(2),
+        details: serde_json::from_str(&row.text(3)).map_err(|_| ApiError::Internal)?,
+        prev_hash: row.text(4),
+        entry_hash: row.text(5),
+    })
+}
+
+/// Walk the whole audit chain, recomputing every entry hash. Returns the number of entries
+/// checked, or the `seq` of the first entry that does not chain onto its predecessor.
+pub async fn verify_audit_chain(db: &Db) -> Result<Result<u64, u64>, ApiError> {
+    let rows = sqlx::query(
+        r#"SELECT seq, created_at, dataset_id, event, details_json, prev_hash, entry_hash
+           FROM audit_log ORDER BY seq"#,
+    )
+    .fetch_all(db)
+    .await
+    .map_err(|_| ApiError::Internal)?;
+
+    Ok(check_audit_chain(&rows))
+}
+
+/// Recompute the hashes of `(seq, created_at, dataset_id, event, details_json, prev_hash,
+/// entry_hash)` rows, the whole chain in `seq` order (see `verify_audit_chain`).
+pub fn check_audit_chain(rows: &[impl LedgerRow]) -> Result<u64, u64> {
+    let mut expected_prev = AUDIT_GENESIS_HASH.to_string();
+    for row in rows {
+        let seq = row.int(0);
+        let prev_hash = row.text(5);
+        let entry_hash = row.text(6);
+        let recomputed = audit_entry_hash(&prev_hash, &row.text(1), &row.opt_text(2).unwrap_or_default(), &row.text(3), &row.text(4));
+        if prev_hash != expected_prev || recomputed != entry_hash {
+            return Err(seq as u64);
+        }
+        expected_prev = entry_hash;
+    }
+
+    Ok(rows.len() as u64)
+}
+
+/// Serializes transparency log appends so leaf indices are dense.
+static LOG_LOCK: Mutex<()> = Mutex::const_new(());
+
+/// One leaf of the append-only `transparency_log` (see `transparency`).
+pub struct LogLeafRow {
+    pub created_at: DateTime<Utc>,
+    pub kind: String,
+    pub dataset_id: Uuid,
+    pub shard_index: Option<u64>,
+    pub commitment_hex: String,
+    pub leaf_hash: String,
+}
+
+/// Append a leaf to the transparency log, returning its index.
+pub async fn append_log_leaf(
+    db: &Db,
+    kind: &str,
+    dataset_id: Uuid,
+    shard_index: Option<u64>,
+    commitment_hex: &str,
+    leaf_hash: &str,
+) -> Result<u64, ApiError> {
+    let _guard = LOG_LOCK.lock().await;
+
+    let seq = log_size(db).await?;
+    sqlx::query(
+        r#"INSERT INTO transparency_log (seq, created_at, kind, dataset_id, shard_index, commitment_hex, leaf_hash)
+           VALUES (?, ?, ?, ?, ?, ?, ?)"#,
+    )
+    .bind(seq as i64)
+    .bind(Utc::now().to_rfc3339())
+    .bind(kind)
+    .bind(dataset_id.to_string())
+    .bind(shard_index.map(|i| i as i64))
+    .bind(commitment_hex)
+    .bind(leaf_hash)
+    .execute(db)
+    .await
+    .map_err(|_| ApiError::Internal)?;
+
+    Ok(seq)
+}
+
+pub async fn log_size(db: &Db) -> Result<u64, ApiError> {
+    let row = sqlx::query(r#"SELECT COUNT(*) FROM transparency_log"#)
+        .fetch_one(db)
+        .await
+        .map_err(|_| ApiError::Internal)?;
+    Ok(row.int(0) as u64)
+}
+
+/// Leaf hashes of the first `tree_size` leaves, in order.
+pub async fn log_leaf_hashes(db: &Db, tree_size: u64) -> Result<Vec<String>, ApiError> {
+    let rows = sqlx::query(r#"SELECT leaf_hash FROM transparency_log WHERE seq < ? ORDER BY seq"#)
+        .bind(tree_size as i64)
+        .fetch_all(db)
+        .await
+        .map_err(|_| ApiError::Internal)?;
+    Ok(rows.iter().map(|r| r.text(0)).collect())
+}
+
+pub async fn get_log_leaf(db: &Db, seq: u64) -> Result<Option<LogLeafRow>, ApiError> {
+    let row = sqlx::query(
+        r#"SELECT seq, created_at, kind, dataset_id, shard_index, commitment_hex, leaf_hash
+           FROM transparency_log WHERE seq = ?"#,
+    )
+    .bind(seq as i64)
+    .fetch_optional(db)
+    .await
+    .map_err(|_| ApiError::Internal)?;
+    row.as_ref().map(log_leaf_row).transpose()
+}
+
+/// Index of the first leaf logging `commitment_hex` as the commitment of `dataset_id`.
+pub async fn find_dataset_log_leaf(db: &Db, dataset_id: Uuid, commitment_hex: &str) -> Result<Option<u64>, ApiError> {
+    let row = sqlx::query(
+        r#"SELECT seq FROM transparency_log
+           WHERE kind = 'dataset' AND dataset_id = ? AND commitment_hex = ?
+           ORDER BY seq LIMIT 1"#,
+    )
+    .bind(dataset_id.to_string())
+    .bind(commitment_hex)
+    .fetch_optional(db)
+    .await
+    .map_err(|_| ApiError::Internal)?;
+    Ok(row.map(|r| r.int(0) as u64))
+}
+
+/// Decode `(seq, created_at, kind, dataset_id, shard_index, commitment_hex, leaf_hash)`; the leaf
+/// is looked up by `seq`, so it isn't kept.
+pub fn log_leaf_row(row: &impl LedgerRow) -> Result<LogLeafRow, ApiError> {
+    Ok(LogLeafRow {
+        created_at: parse_time(&row.text(1))?,
+        kind: row.text(2),
+        dataset_id: row.text(3).parse().map_err(|_| ApiError::Internal)?,
+        shard_index: row.opt_int(4).map(|i| i as u64),
+        commitment_hex: row.text(5),
+        leaf_hash: row.text(6),
+    })
+}
+
+/// Record the (bucket, filter) cells a released query disclosed.
+pub async fn insert_released_cells(
+    db: &Db,
+    query_id: Uuid,
+    dataset_id: Uuid,
+    cells: &[(usize, &str)],
+) -> Result<(), ApiError> {
+    let released_at = Utc::now().to_rfc3339();
+
+    for (bucket_index, filter_key) in cells {
+        sqlx::query(
+            r#"INSERT OR IGNORE INTO released_cells (query_id, dataset_id, bucket_index, filter_key, released_at)
+               VALUES (?, ?, ?, ?, ?)"#,
+        )
+        .bind(query_id.to_string())
+        .bind(dataset_id.to_string())
+        .bind(*bucket_index as i64)
+        .bind(*filter_key)
+        .bind(&released_at)
+        .execute(db)
+        .await
+        .map_err(|_| ApiError::Internal)?;
+    }
+
+    Ok(())
+}
+
+/// Differential privacy spent on a dataset's noisy query releases.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PrivacyBudgetRow {
+    pub epsilon_spent: f64,
+    pub delta_spent: f64,
+    pub releases: u64,
+}
+
+/// Slack for float rounding when comparing spent epsilon against the budget.
+pub const BUDGET_SLACK: f64 = 1e-9;
+
+/// Spend `epsilon` and `delta` of a dataset's privacy budget, unless the epsilon spent would then
+/// exceed `budget` (`None` = no cap). Returns the budget as spent, or `None` if refused.
+pub async fn spend_privacy_budget(
+    db: &Db,
+    dataset_id: Uuid,
+    epsilon: f64,
+    delta: f64,
+    budget: Option<f64>,
+) -> Result<Option<PrivacyBudgetRow>, ApiError> {
+    let cap = budget.map(|b| b + BUDGET_SLACK);
+    if cap.is_some_and(|cap| epsilon > cap) {
+        return Ok(None);
+    }
+    let res = sqlx::query(
+        r#"INSERT INTO privacy_budget (dataset_id, epsilon_spent, delta_spent, releases, updated_at)
+           VALUES (?, ?, ?, 1, ?)
+           ON CONFLICT(dataset_id) DO UPDATE SET
+             epsilon_spent = privacy_budget.epsilon_spent + excluded.epsilon_spent,
+             delta_spent = privacy_budget.delta_spent + excluded.delta_spent,
+             releases = privacy_budget.releases + 1,
+             updated_at = excluded.updated_at
+           WHERE ? IS NULL OR privacy_budget.epsilon_spent + excluded.epsilon_spent <= ?"#,
+    )
+    .bind(dataset_id.to_string())
+    .bind(epsilon)
+    .bind(delta)
+    .bind(Utc::now().to_rfc3339())
+    .bind(cap)
+    .bind(cap)
+    .execute(db)
+    .await
+    .map_err(|_| ApiError::Internal)?;
+    if res.rows_affected() == 0 {
+        return Ok(None);
+    }
+    privacy_budget(db, dataset_id).await.map(Some)
+}
+
+pub async fn privacy_budget(db: &Db, dataset_id: Uuid) -> Result<PrivacyBudgetRow, ApiError> {
+    let row = sqlx::query(r#"SELECT epsilon_spent, delta_spent, releases FROM privacy_budget WHERE dataset_id = ?"#)
+        .bind(dataset_id.to_string())
+        .fetch_optional(db)
+        .await
+        .map_err(|_| ApiError::Internal)?;
+    Ok(row.map_or_else(PrivacyBudgetRow::default, |row| PrivacyBudgetRow {
+        epsilon_spent: row.get(0),
+        delta_spent: row.get(1),
+        releases: row.get::<i64, _>(2) as u64,
+    }))
+}
+
+/// Cumulative disclosure of one (bucket, filter) cell.
+pub struct CellDisclosureRow {
+    pub bucket_index: usize,
+    pub filter_key: String,
+    pub release_count: u64,
+    pub first_released_at: DateTime<Utc>,
+    pub last_released_at: DateTime<Utc>,
+}
+
+pub async fn cell_disclosure(db: &Db, dataset_id: Uuid) -> Result<Vec<CellDisclosureRow>, ApiError> {
+    let rows = sqlx::query(
+        r#"SELECT bucket_index, filter_key, COUNT(*), MIN(released_at), MAX(released_at)
+           FROM released_cells
+           WHERE dataset_id = ?
+           GROUP BY bucket_index, filter_key
+           ORDER BY bucket_index, filter_key"#,
+    )
+    .bind(dataset_id.to_string())
+    .fetch_all(db)
+    .await
+    .map_err(|_| ApiError::Internal)?;
+
+    rows.iter().map(cell_disclosure_row).collect()
+}
+
+/// Decode `(bucket_index, filter_key, release count, first and last released_at)`.
+pub fn cell_disclosure_row(row: &impl LedgerRow) -> Result<CellDisclosureRow, ApiError> {
+    Ok(CellDisclosureRow {
+        bucket_index: row.int(0) as usize,
+        filter_key: row.text(1),
+        release_count: row.int(2) as u64,
+        first_released_at: parse_time(&row.text(3))?,
+        last_released_at: parse_time(&row.text(4))?,
+    })
+}
+
+/// Up to `limit` proof blobs with a hash greater than `after`, in hash order. A blob whose file is
+/// missing is listed with an empty proof.
+pub async fn list_proof_blobs(db: &Db, files: &ProofFiles, after: &str, limit: u64) -> Result<Vec<(String, String)>, ApiError> {
+    let rows = sqlx::query(r#"SELECT hash, proof_b64 FROM proof_blobs WHERE hash > ? ORDER BY hash LIMIT ?"#)
+        .bind(after)
+        .bind(limit as i64)
+        .fetch_all(db)
+        .await
+        .map_err(|_| ApiError::Internal)?;
+
+    let mut blobs = Vec::with_capacity(rows.len());
+    for row in rows {
+        let hash: String = row.get(0);
+        let proof_b64 = proof_blob_b64(files, &hash, row.get(1)).await?.unwrap_or_default();
+        blobs.push((hash, proof_b64));
+    }
+    Ok(blobs)
+}
+
+/// Shards whose proof is the blob `hash`.
+pub async fn shards_with_proof(db: &Db, hash: &str) -> Result<Vec<(Uuid, u64)>, ApiError> {
+    let rows = sqlx::query(r#"SELECT dataset_id, shard_index FROM shards WHERE proof_hash = ? ORDER BY dataset_id, shard_index"#)
+        .bind(hash)
+        .fetch_all(db)
+        .await
+        .map_err(|_| ApiError::Internal)?;
+
+    rows.into_iter()
+        .map(|r| {
+            let dataset_id = Uuid::parse_str(&r.get::<String, _>(0)).map_err(|_| ApiError::Internal)?;
+            Ok((dataset_id, r.get::<i64, _>(1) as u64))
+        })
+        .collect()
+}
+
+/// Delete blobs no shard refers to any more (e.g. after a shard was re-proven), with their files.
+/// Returns how many.
+pub async fn delete_orphan_proof_blobs(db: &Db, files: &ProofFiles) -> Result<u64, ApiError> {
+    let _guard = files.lock().await;
+    let rows = sqlx::query(
+        r#"DELETE FROM proof_blobs
+           WHERE NOT EXISTS (SELECT 1 FROM shards WHERE shards.proof_hash = proof_blobs.hash)
+           RETURNING hash"#,
+    )
+    .fetch_all(db)
+    .await
+    .map_err(|_| ApiError::Internal)?;
+
+    for row in &rows {
+        files.remove(&row.text(0)).await?;
+    }
+    Ok(rows.len() as u64)
+}
+
+/// Stored blobs, their total size in bytes (base64 for blobs still stored inline), and how many
+/// shards refer to a blob.
+pub async fn proof_blob_stats(db: &Db) -> Result<(u64, u64, u64), ApiError> {
+    let row = sqlx::query(
+        r#"SELECT (SELECT COUNT(*) FROM proof_blobs),
+                  (SELECT COALESCE(SUM(COALESCE(size_bytes, LENGTH(proof_b64))), 0) FROM proof_blobs),
+                  (SELECT COUNT(*) FROM shards WHERE proof_hash IS NOT NULL)"#,
+    )
+    .fetch_one(db)
+    .await
+    .map_err(|_| ApiError::Internal)?;
+
+    Ok((row.get::<i64, _>(0) as u64, row.get::<i64, _>(1) as u64, row.get::<i64, _>(2) as u64))
+}
+
+/// One row of the `aggregate_proofs` table: the latest dataset aggregate proof of a dataset.
+pub struct AggregateProofRow {
+    pub created_at: DateTime<Utc>,
+    /// Dataset commitment the proof was made for; a proof for an older commitment is stale.
+    pub dataset_commitment_hex: String,
+    pub shard_inputs_root_hex: String,
+    pub shards_total: u64,
+    pub totals: ShardStats,
+    pub proof_b64: String,
+    pub vk_b64: String,
+    pub key_id: String,
+}
+
+pub const AGGREGATE_PROOF_COLUMNS: &str =
+    "created_at, dataset_commitment_hex, shard_inputs_root_hex, shards_total, totals_json, proof_b64, vk_b64, key_id";
+
+pub fn aggregate_proof_row(row: &impl LedgerRow) -> Result<AggregateProofRow, ApiError> {
+    Ok(AggregateProofRow {
+        created_at: parse_time(&row.text(0))?,
+        dataset_commitment_hex: row.text(1),
+        shard_inputs_root_hex: row.text(2),
+        shards_total: row.int(3) as u64,
+        totals: serde_json::from_str(&row.text(4)).map_err(|_| ApiError::Internal)?,
+        proof_b64: row.text(5),
+        vk_b64: row.text(6),
+        key_id: row.text(7),
+    })
+}
+
+/// Store a dataset's aggregate proof, replacing any earlier one.
+pub async fn put_aggregate_proof(db: &Db, dataset_id: Uuid, row: &AggregateProofRow) -> Result<(), ApiError> {
+    let totals_json = serde_json::to_string(&row.totals).map_err(|_| ApiError::Internal)?;
+    sqlx::query(&format!(
+        r#"INSERT OR REPLACE INTO aggregate_proofs (dataset_id, {AGGREGATE_PROOF_COLUMNS})
+           VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)"#
+    ))
+    .bind(dataset_id.to_string())
+    .bind(row.created_at.to_rfc3339())
+    .bind(&row.dataset_commitment_hex)
+    .bind(&row.shard_inputs_root_hex)
+    .bind(row.shards_total as i64)
+    .bind(totals_json)
+    .bind(&row.proof_b64)
+    .bind(&row.vk_b64)
+    .bind(&row.key_id)
+    .execute(db)
+    .await
+    .map_err(|_| ApiError::Internal)?;
+    Ok(())
+}
+
+pub async fn get_aggregate_proof(db: &Db, dataset_id: Uuid) -> Result<Option<AggregateProofRow>, ApiError> {
+    let row = sqlx::query(&format!("SELECT {AGGREGATE_PROOF_COLUMNS} FROM aggregate_proofs WHERE dataset_id = ?"))
+        .bind(dataset_id.to_string())
+        .fetch_optional(db)
+        .await
+        .map_err(|_| ApiError::Internal)?;
+    row.as_ref().map(aggregate_proof_row).transpose()
+}
+
+/// One row of the `dataset_anomalies` table: the latest anomaly analysis of a dataset.
+pub struct AnomalyAnalysisRow {
+    pub analyzed_at: DateTime<Utc>,
+    /// Commitment and stored shard count the analysis saw; either changing makes it stale.
+    pub dataset_commitment_hex: String,
+    pub shards_stored: u64,
+    pub shards_analyzed: u64,
+    pub warnings_total: u64,
+    pub warnings: Vec<AnomalyWarning>,
+}
+
+pub const ANOMALY_ANALYSIS_COLUMNS: &str =
+    "analyzed_at, dataset_commitment_hex, shards_stored, shards_analyzed, warnings_total, warnings_json";
+
+pub fn anomaly_analysis_row(row: &impl LedgerRow) -> Result<AnomalyAnalysisRow, ApiError> {
+    Ok(AnomalyAnalysisRow {
+        analyzed_at: parse_time(&row.text(0))?,
+        dataset_commitment_hex: row.text(1),
+        shards_stored: row.int(2) as u64,
+        shards_analyzed: row.int(3) as u64,
+        warnings_total: row.int(4) as u64,
+        warnings: serde_json::from_str(&row.text(5)).map_err(|_| ApiError::Internal)?,
+    })
+}
+
+/// Store a dataset's anomaly analysis, replacing any earlier one.
+pub async fn put_anomaly_analysis(db: &Db, dataset_id: Uuid, row: &AnomalyAnalysisRow) -> Result<(), ApiError> {
+    let warnings_json = serde_json::to_string(&row.warnings).map_err(|_| ApiError::Internal)?;
+    sqlx::query(&format!(
+        r#"INSERT OR REPLACE INTO dataset_anomalies (dataset_id, {ANOMALY_ANALYSIS_COLUMNS})
+           VALUES (?, ?, ?, ?, ?, ?, ?)"#
+    ))
+    .bind(dataset_id.to_string())
+    .bind(row.analyzed_at.to_rfc3339())
+    .bind(&row.dataset_commitment_hex)
+    .bind(row.shards_stored as i64)
+    .bind(row.shards_analyzed as i64)
+    .bind(row.warnings_total as i64)
+    .bind(warnings_json)
+    .execute(db)
+    .await
+    .map_err(|_| ApiError::Internal)?;
+    Ok(())
+}
+
+pub async fn get_anomaly_analysis(db: &Db, dataset_id: Uuid) -> Result<Option<AnomalyAnalysisRow>, ApiError> {
+    let row = sqlx::query(&format!("SELECT {ANOMALY_ANALYSIS_COLUMNS} FROM dataset_anomalies WHERE dataset_id = ?"))
+        .bind(dataset_id.to_string())
+        .fetch_optional(db)
+        .await
+        .map_err(|_| ApiError::Internal)?;
+    row.as_ref().map(anomaly_analysis_row).transpose()
+}
+
+/// One row of the `log_anchors` table: a transparency log root published externally.
+pub struct LogAnchorRow {
+    pub anchored_at: DateTime<Utc>,
+    pub method: String,
+    pub tree_size: u64,
+    pub root_hash_hex: String,
+    pub txid: Option<String>,
+    pub receipt_hex: Option<String>,
+}
+
+pub const LOG_ANCHOR_COLUMNS: &str = "anchored_at, method, tree_size, root_hash_hex, txid, receipt_hex";
+
+pub fn log_anchor_row(row: &impl LedgerRow) -> Result<LogAnchorRow, ApiError> {
+    Ok(LogAnchorRow {
+        anchored_at: parse_time(&row.text(0))?,
+        method: row.text(1),
+        tree_size: row.int(2) as u64,
+        root_hash_hex: row.text(3),
+        txid: row.opt_text(4),
+        receipt_hex: row.opt_text(5),
+    })
+}
+
+pub async fn insert_log_anchor(db: &Db, row: &LogAnchorRow) -> Result<(), ApiError> {
+    sqlx::query(&format!("INSERT INTO log_anchors ({LOG_ANCHOR_COLUMNS}) VALUES (?, ?, ?, ?, ?, ?)"))
+        .bind(row.anchored_at.to_rfc3339())
+        .bind(&row.method)
+        .bind(row.tree_size as i64)
+        .bind(&row.root_hash_hex)
+        .bind(&row.txid)
+        .bind(&row.receipt_hex)
+        .execute(db)
+        .await
+        .map_err(|_| ApiError::Internal)?;
+    Ok(())
+}
+
+/// The largest tree size anchored so far.
+pub async fn anchored_tree_size(db: &Db) -> Result<u64, ApiError> {
+    let row = sqlx::query(r#"SELECT COALESCE(MAX(tree_size), 0) FROM log_anchors"#)
+        .fetch_one(db)
+        .await
+        .map_err(|_| ApiError::Internal)?;
+    Ok(row.int(0) as u64)
+}
+
+/// The first anchor of a tree containing leaf `leaf_index`.
+pub async fn anchor_covering(db: &Db, leaf_index: u64) -> Result<Option<LogAnchorRow>, ApiError> {
+    let row = sqlx::query(&format!("SELECT {LOG_ANCHOR_COLUMNS} FROM log_anchors WHERE tree_size > ? ORDER BY seq LIMIT 1"))
+        .bind(leaf_index as i64)
+        .fetch_optional(db)
+        .await
+        .map_err(|_| ApiError::Internal)?;
+    row.as_ref().map(log_anchor_row).transpose()
+}
+
+/// One row of the `dataset_reverifications` table: a dataset's latest re-verification report.
+pub struct DatasetReverificationRow {
+    pub finished_at: DateTime<Utc>,
+    /// Commitment the shards were checked against.
+    pub dataset_commitment_hex: String,
+    pub shards_total: u64,
+    pub shards_checked: u64,
+    /// Shards whose proof doesn't verify, in shard order.
+    pub failed_shards: Vec<u64>,
+    /// Whether the stored shard commitments chain to `dataset_commitment_hex`.
+    pub commitment_matches: bool,
+    pub duration_ms: u64,
+}
+
+pub const REVERIFICATION_COLUMNS: &str =
+    "finished_at, dataset_commitment_hex, shards_total, shards_checked, failed_shards_json, commitment_matches, duration_ms";
+
+pub fn dataset_reverification_row(row: &impl LedgerRow) -> Result<DatasetReverificationRow, ApiError> {
+    Ok(DatasetReverificationRow {
+        finished_at: parse_time(&row.text(0))?,
+        dataset_commitment_hex: row.text(1),
+        shards_total: row.int(2) as u64,
+        shards_checked: row.int(3) as u64,
+        failed_shards: serde_json::from_str(&row.text(4)).map_err(|_| ApiError::Internal)?,
+        commitment_matches: row.int(5) != 0,
+        duration_ms: row.int(6) as u64,
+    })
+}
+
+/// Store a dataset's verification report, replacing any earlier one.
+pub async fn put_dataset_reverification(db: &Db, dataset_id: Uuid, row: &DatasetReverificationRow) -> Result<(), ApiError> {
+    let failed_shards_json = serde_json::to_string(&row.failed_shards).map_err(|_| ApiError::Internal)?;
+    sqlx::query(&format!(
+        r#"INSERT OR REPLACE INTO dataset_reverifications (dataset_id, {REVERIFICATION_COLUMNS})
+           VALUES (?, ?, ?, ?, ?, ?, ?, ?)"#
+    ))
+    .bind(dataset_id.to_string())
+    .bind(row.finished_at.to_rfc3339())
+    .bind(&row.dataset_commitment_hex)
+    .bind(row.shards_total as i64)
+    .bind(row.shards_checked as i64)
+    .bind(failed_shards_json)
+    .bind(if row.commitment_matches { 1i64 } else { 0i64 })
+    .bind(row.duration_ms as i64)
+    .execute(db)
+    .await
+    .map_err(|_| ApiError::Internal)?;
+    Ok(())
+}
+
+pub async fn get_dataset_reverification(db: &Db, dataset_id: Uuid) -> Result<Option<DatasetReverificationRow>, ApiError> {
+    let row = sqlx::query(&format!("SELECT {REVERIFICATION_COLUMNS} FROM dataset_reverifications WHERE dataset_id = ?"))
+        .bind(dataset_id.to_string())
+        .fetch_optional(db)
+        .await
+        .map_err(|_| ApiError::Internal)?;
+    row.as_ref().map(dataset_reverification_row).transpose()
+}
+
+/// One row of the `curve_migrations` table: a dataset's migration to another curve.
+pub struct CurveMigrationRow {
+    pub dataset_id: Uuid,
+    pub curve: Curve,
+    /// `queued`, `running`, `done`, `flagged` (can't be re-proven here) or `failed`.
+    pub status: String,
+    /// Why the dataset was flagged or the migration failed.
+    pub reason: Option<String>,
+    /// Shards re-proven so far.
+    pub shards_done: u64,
+    pub shards_total: u64,
+    /// Dataset commitment over the re-proven shards, once done.
+    pub dataset_commitment_hex: Option<String>,
+    /// Id of the verifying key the shards were re-proven with, once done.
+    pub key_id: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub finished_at: Option<DateTime<Utc>>,
+}
+
+/// Start (or restart) a dataset's migration to `curve` with `status`, clearing any earlier outcome.
+/// Shards already re-proven are kept, so a restarted migration resumes.
+pub async fn put_curve_migration(db: &Db, dataset_id: Uuid, curve: Curve, status: &str, reason: Option<&str>) -> Result<(), ApiError> {
+    sqlx::query(
+        r#"INSERT OR REPLACE INTO curve_migrations (dataset_id, curve, status, reason, created_at)
+           VALUES (?, ?, ?, ?, ?)"#,
+    )
+    .bind(dataset_id.to_string())
+    .bind(curve.name())
+    .bind(status)
+    .bind(reason)
+    .bind(Utc::now().to_rfc3339())
+    .execute(db)
+    .await
+    .map_err(|_| ApiError::Internal)?;
+    Ok(())
+}
+
+/// Update a migration's status; `done`, `flagged` and `failed` also set `finished_at`.
+pub async fn set_curve_migration_status(db: &Db, dataset_id: Uuid, curve: Curve, status: &str, reason: Option<&str>) -> Result<(), ApiError> {
+    sqlx::query(r#"UPDATE curve_migrations SET status = ?, reason = ?, finished_at = ? WHERE dataset_id = ? AND curve = ?"#)
+        .bind(status)
+        .bind(reason)
+        .bind(curve_migration_finished_at(status))
+        .bind(dataset_id.to_string())
+        .bind(curve.name())
+        .execute(db)
+        .await
+        .map_err(|_| ApiError::Internal)?;
+    Ok(())
+}
+
+/// `finished_at` for a migration entering `status`.
+pub fn curve_migration_finished_at(status: &str) -> Option<String> {
+    matches!(status, "done" | "flagged" | "failed").then(|| Utc::now().to_rfc3339())
+}
+
+/// Mark a migration done with the dataset commitment and key id of the re-proven shards.
+pub async fn set_curve_migration_done(db: &Db, dataset_id: Uuid, curve: Curve, dataset_commitment_hex: &str, key_id: &str) -> Result<(), ApiError> {
+    sqlx::query(
+        r#"UPDATE curve_migrations SET status = 'done', reason = NULL, dataset_commitment_hex = ?, key_id = ?, finished_at = ?
+           WHERE dataset_id = ? AND curve = ?"#,
+    )
+    .bind(dataset_commitment_hex)
+    .bind(key_id)
+    .bind(Utc::now().to_rfc3339())
+    .bind(dataset_id.to_string())
+    .bind(curve.name())
+    .execute(db)
+    .await
+    .map_err(|_| ApiError::Internal)?;
+    Ok(())
+}
+
+/// Selected from `curve_migrations m LEFT JOIN datasets d ON d.id = m.dataset_id`.
+pub const CURVE_MIGRATION_COLUMNS: &str = r#"m.dataset_id, m.curve, m.status, m.reason,
+    (SELECT COUNT(*) FROM curve_shards s WHERE s.dataset_id = m.dataset_id AND s.curve = m.curve),
+    COALESCE(d.dataset_size / d.shard_size, 0), m.dataset_commitment_hex, m.key_id, m.created_at, m.finished_at"#;
+
+pub fn curve_migration_row(row: &impl LedgerRow) -> Result<CurveMigrationRow, ApiError> {
+    Ok(CurveMigrationRow {
+        dataset_id: Uuid::parse_str(&row.text(0)).map_err(|_| ApiError::Internal)?,
+        curve: Curve::parse(&row.text(1)).ok_or(ApiError::Internal)?,
+        status: row.text(2),
+        reason: row.opt_text(3),
+        shards_done: row.int(4) as u64,
+        shards_total: row.int(5) as u64,
+        dataset_commitment_hex: row.opt_text(6),
+        key_id: row.opt_text(7),
+        created_at: parse_time(&row.text(8))?,
+        finished_at: row.opt_text(9).as_deref().map(parse_time).transpose()?,
+    })
+}
+
+pub async fn get_curve_migration(db: &Db, dataset_id: Uuid, curve: Curve) -> Result<Option<CurveMigrationRow>, ApiError> {
+    let row = sqlx::query(&format!(
+        r#"SELECT {CURVE_MIGRATION_COLUMNS}
+           FROM curve_migrations m LEFT JOIN datasets d ON d.id = m.dataset_id
+           WHERE m.dataset_id = ? AND m.curve = ?"#
+    ))
+    .bind(dataset_id.to_string())
+    .bind(curve.name())
+    .fetch_optional(db)
+    .await
+    .map_err(|_| ApiError::Internal)?;
+    row.as_ref().map(curve_migration_row).transpose()
+}
+
+/// Migrations to `curve`, oldest first.
+pub async fn list_curve_migrations(db: &Db, curve: Curve) -> Result<Vec<CurveMigrationRow>, ApiError> {
+    let rows = sqlx::query(&format!(
+        r#"SELECT {CURVE_MIGRATION_COLUMNS}
+           FROM curve_migrations m LEFT JOIN datasets d ON d.id = m.dataset_id
+           WHERE m.curve = ?
+           ORDER BY m.created_at"#
+    ))
+    .bind(curve.name())
+    .fetch_all(db)
+    .await
+    .map_err(|_| ApiError::Internal)?;
+    rows.iter().map(curve_migration_row).collect()
+}
+
+/// One shard re-proven on another curve. `stats` carry no salt commitment; it is
+/// `salt_commitment_hex`, on the curve's scalar field.
+pub struct CurveShardRow {
+    pub shard_index: u64,
+    pub shard_commitment_hex: String,
+    pub salt_commitment_hex: String,
+    pub stats: ShardStats,
+    /// Empty unless requested.
+    pub proof_b64: String,
+}
+
+/// Decodes `shard_index, shard_commitment_hex, salt_commitment_hex, stats_json, proof_b64`.
+pub fn curve_shard_row(row: &impl LedgerRow) -> Result<CurveShardRow, ApiError> {
+    Ok(CurveShardRow {
+        shard_index: row.int(0) as u64,
+        shard_commitment_hex: row.text(1),
+        salt_commitment_hex: row.text(2),
+        stats: serde_json::from_str(&row.text(3)).map_err(|_| ApiError::Internal)?,
+        proof_b64: row.text(4),
+    })
+}
+
+/// Store a shard re-proven on `curve` with the key `key_id`.
+pub async fn insert_curve_shard(
+    db: &Db,
+    dataset_id: Uuid,
+    curve: Curve,
+    shard: &CurveShardRow,
+    sealed_master_salt: &[u8],
+    key_id: &str,
+) -> Result<(), ApiError> {
+    use base64::Engine;
+    let stats_json = serde_json::to_string(&shard.stats).map_err(|_| ApiError::Internal)?;
+    sqlx::query(
+        r#"INSERT OR REPLACE INTO curve_shards
+             (dataset_id, curve, shard_index, shard_commitment_hex, salt_commitment_hex, stats_json, proof_b64, sealed_master_salt_b64, key_id)
+           VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)"#,
+    )
+    .bind(dataset_id.to_string())
+    .bind(curve.name())
+    .bind(shard.shard_index as i64)
+    .bind(&shard.shard_commitment_hex)
+    .bind(&shard.salt_commitment_hex)
+    .bind(stats_json)
+    .bind(&shard.proof_b64)
+    .bind(base64::engine::general_purpose::STANDARD.encode(sealed_master_salt))
+    .bind(key_id)
+    .execute(db)
+    .await
+    .map_err(|_| ApiError::Internal)?;
+    Ok(())
+}
+
+/// Drop a dataset's shards on `curve` proven with any key but `key_id` (keys replaced since).
+pub async fn delete_curve_shards_except(db: &Db, dataset_id: Uuid, curve: Curve, key_id: &str) -> Result<u64, ApiError> {
+    let res = sqlx::query(r#"DELETE FROM curve_shards WHERE dataset_id = ? AND curve = ? AND key_id <> ?"#)
+        .bind(dataset_id.to_string())
+        .bind(curve.name())
+        .bind(key_id)
+        .execute(db)
+        .await
+        .map_err(|_| ApiError::Internal)?;
+    Ok(res.rows_affected())
+}
+
+/// Re-proven shards on `curve` with `shard_index` in `index_range`, paged like `list_shards`.
+pub async fn list_curve_shards(
+    db: &Db,
+    dataset_id: Uuid,
+    curve: Curve,
+    index_range: std::ops::Range<u64>,
+    offset: u64,
+    limit: u64,
+    include_proof: bool,
+) -> Result<Vec<CurveShardRow>, ApiError> {
+    let rows = sqlx::query(
+        r#"SELECT shard_index, shard_commitment_hex, salt_commitment_hex, stats_json,
+                  CASE WHEN ? THEN proof_b64 ELSE '' END
+           FROM curve_shards
+           WHERE dataset_id = ? AND curve = ? AND shard_index >= ? AND shard_index < ?
+           ORDER BY shard_index
+           LIMIT ? OFFSET ?"#,
+    )
+    .bind(include_proof)
+    .bind(dataset_id.to_string())
+    .bind(curve.name())
+    .bind(index_range.start.min(i64::MAX as u64) as i64)
+    .bind(index_range.end.min(i64::MAX as u64) as i64)
+    .bind(limit.min(i64::MAX as u64) as i64)
+    .bind(offset as i64)
+    .fetch_all(db)
+    .await
+    .map_err(|_| ApiError::Internal)?;
+    rows.iter().map(curve_shard_row).collect()
+}
+
+/// Proofs made with one proving key, from `zk_key_usage` and `zk_key_datasets`.
+pub struct KeyUsageRow {
+    /// Hex SHA-256 of the verifying key.
+    pub key_id: String,
+    /// `shard`, `shard-bls12-381`, `aggregate` or `query`.
+    pub circuit: String,
+    /// First proof made with the key on this ledger.
+    pub first_seen_at: DateTime<Utc>,
+    pub last_proof_at: DateTime<Utc>,
+    pub proofs_created: u64,
+    pub datasets_covered: u64,
+}
+
+/// Selected from `zk_key_usage u`.
+pub const KEY_USAGE_COLUMNS: &str = r#"u.key_id, u.circuit, u.first_seen_at, u.last_proof_at, u.proofs_created,
+    (SELECT COUNT(*) FROM zk_key_datasets d WHERE d.key_id = u.key_id)"#;
+
+pub fn key_usage_row(row: &impl LedgerRow) -> Result<KeyUsageRow, ApiError> {
+    Ok(KeyUsageRow {
+        key_id: row.text(0),
+        circuit: row.text(1),
+        first_seen_at: parse_time(&row.text(2))?,
+        last_proof_at: parse_time(&row.text(3))?,
+        proofs_created: row.int(4) as u64,
+        datasets_covered: row.int(5) as u64,
+    })
+}
+
+/// Count one `circuit` proof made with `key_id` for `dataset_id`. Returns whether the key hadn't
+/// proven anything for that dataset before.
+pub async fn record_key_proof(db: &Db, key_id: &str, circuit: &str, dataset_id: Uuid) -> Result<bool, ApiError> {
+    let now = Utc::now().to_rfc3339();
+    let mut tx = db.begin().await.map_err(|_| ApiError::Internal)?;
+    sqlx::query(
+        r#"INSERT INTO zk_key_usage (key_id, circuit, first_seen_at, last_proof_at, proofs_created)
+           VALUES (?, ?, ?, ?, 1)
+           ON CONFLICT(key_id) DO UPDATE SET
+             last_proof_at = excluded.last_proof_at,
+             proofs_created = zk_key_usage.proofs_created + 1"#,
+    )
+    .bind(key_id)
+    .bind(circuit)
+    .bind(&now)
+    .bind(&now)
+    .execute(&mut *tx)
+    .await
+    .map_err(|_| ApiError::Internal)?;
+    let new_dataset = sqlx::query("INSERT OR IGNORE INTO zk_key_datasets (key_id, dataset_id) VALUES (?, ?)")
+        .bind(key_id)
+        .bind(dataset_id.to_string())
+        .execute(&mut *tx)
+        .await
+        .map_err(|_| ApiError::Internal)?
+        .rows_affected()
+        > 0;
+    tx.commit().await.map_err(|_| ApiError::Internal)?;
+    Ok(new_dataset)
+}
+
+/// Usage of every key that has made a proof here (`key_id` = `None`) or of one key, oldest first.
+pub async fn list_key_usage(db: &Db, key_id: Option<&str>) -> Result<Vec<KeyUsageRow>, ApiError> {
+    let rows = sqlx::query(&format!(
+        r#"SELECT {KEY_USAGE_COLUMNS}
+           FROM zk_key_usage u
+           WHERE ? IS NULL OR u.key_id = ?
+           ORDER BY u.first_seen_at, u.key_id"#
+    ))
+    .bind(key_id)
+    .bind(key_id)
+    .fetch_all(db)
+    .await
+    .map_err(|_| ApiError::Internal)?;
+    rows.iter().map(key_usage_row).collect()
+}
@@ -0,0 +1,136 @@
+//! Per-request deadlines and cooperative cancellation.
+//!
+//! Every request runs under a deadline: `REQUEST_TIMEOUT_SECS` (default 120; `0` disables it), or
+//! sooner if the client sends `X-Request-Timeout-Ms`. A request still running at its deadline is
+//! answered `408`. Either way, once the handler is dropped (deadline passed, or the client
+//! disconnected) its async work stops at the next await, database queries included; with
+//! Postgres the same timeout is also the server-side `statement_timeout`, so an abandoned
+//! statement doesn't keep running there.
+//!
+//! CPU-bound work on the blocking pool doesn't notice the handler going away, so long
+//! verifications take the request's `Cancellation` and check it as they go. Batch endpoints stop
+//! early rather than lose their work where they can:
+//! - `POST /api/v1/verify/shards` verifies in chunks and stops when the deadline is closer than
+//!   its last chunk took; it answers with `complete: false` and the entries it didn't reach in
+//!   `unchecked`, which can be resubmitted.
+//! - `POST /api/v1/imports` registers datasets one at a time; datasets imported before
+//!   cancellation stay imported (re-importing reports them `already_present`).
+
+use crate::errors::ApiError;
+use axum::{extract::Request, middleware::Next, response::IntoResponse, response::Response};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+const DEFAULT_REQUEST_TIMEOUT_SECS: u64 = 120;
+
+/// Headroom left before the deadline for answering with what a batch has so far.
+const ANSWER_MARGIN: Duration = Duration::from_millis(250);
+
+/// The server-wide request timeout (`REQUEST_TIMEOUT_SECS`); `None` if disabled.
+pub fn request_timeout() -> Option<Duration> {
+    let secs = std::env::var("REQUEST_TIMEOUT_SECS")
+        .ok()
+        .and_then(|v| v.trim().parse().ok())
+        .unwrap_or(DEFAULT_REQUEST_TIMEOUT_SECS);
+    (secs > 0).then(|| Duration::from_secs(secs))
+}
+
+/// Cancellation state of one request, cheap to clone into blocking tasks. Background work that
+/// no request waits on uses `Cancellation::none()`.
+#[derive(Clone, Default)]
+pub struct Cancellation {
+    cancelled: Arc<AtomicBool>,
+    deadline: Option<Instant>,
+}
+
+impl Cancellation {
+    pub fn none() -> Self {
+        Self::default()
+    }
+
+    /// Cancel explicitly (background work cancelled on request).
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    /// Whether the request was abandoned or is past its deadline.
+    pub fn is_cancelled(&self) -> bool {
+        self.would_overrun(Duration::ZERO)
+    }
+
+    /// Whether work expected to take `estimate` would end past the deadline (or the request was
+    /// abandoned), leaving time to answer.
+    pub fn would_overrun(&self, estimate: Duration) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+            || self.deadline.is_some_and(|deadline| Instant::now() + estimate + ANSWER_MARGIN >= deadline)
+    }
+
+    /// `408` if the request was abandoned or is past its deadline.
+    pub fn check(&self) -> Result<(), ApiError> {
+        if self.cancelled.load(Ordering::Relaxed) || self.deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            return Err(timed_out());
+        }
+        Ok(())
+    }
+}
+
+fn timed_out() -> ApiError {
+    ApiError::Timeout("request cancelled or past its deadline".to_string())
+}
+
+/// Cancels the request unless disarmed first, so dropping the handler cancels its blocking work.
+struct CancelOnDrop {
+    cancellation: Cancellation,
+    armed: bool,
+}
+
+impl Drop for CancelOnDrop {
+    fn drop(&mut self) {
+        if self.armed {
+            self.cancellation.cancelled.store(true, Ordering::Relaxed);
+        }
+    }
+}
+
+/// The timeout a request asks for (`X-Request-Timeout-Ms`), capped at the server's.
+fn timeout_for(request: &Request) -> Option<Duration> {
+    let requested = request
+        .headers()
+        .get("X-Request-Timeout-Ms")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.trim().parse().ok())
+        .map(Duration::from_millis);
+    match (request_timeout(), requested) {
+        (Some(server), Some(requested)) => Some(server.min(requested)),
+        (server, requested) => server.or(requested),
+    }
+}
+
+/// Give the request a deadline and a `Cancellation` extension, and answer `408` if the handler
+/// hasn't finished by the deadline.
+pub async fn middleware(mut request: Request, next: Next) -> Response {
+    let timeout = timeout_for(&request);
+    let mut guard = CancelOnDrop {
+        cancellation: Cancellation {
+            cancelled: Arc::default(),
+            deadline: timeout.map(|t| Instant::now() + t),
+        },
+        armed: true,
+    };
+    request.extensions_mut().insert(guard.cancellation.clone());
+
+    let response = match timeout {
+        Some(timeout) => match tokio::time::timeout(timeout, next.run(request)).await {
+            Ok(response) => response,
+            Err(_) => {
+                tracing::warn!(timeout_ms = timeout.as_millis() as u64, "request deadline exceeded");
+                return timed_out().into_response();
+            }
+        },
+        None => next.run(request).await,
+    };
+    // Finished in time: a streamed body may still be read after this returns.
+    guard.armed = false;
+    response
+}
@@ -0,0 +1,185 @@
+//! Differential privacy for query releases.
+//!
+//! A query with `epsilon` is released with calibrated noise instead of its exact answer. The sum
+//! and the count each get half of `epsilon` (and of `delta`), and the mean is derived from the
+//! noisy pair. Noise is scaled to each value's sensitivity: one record changes the count by 1 and
+//! the sum by at most `PLAUSIBLE_GLUCOSE_MG_DL`'s upper bound, which ingestion and the shard
+//! circuit enforce for glucose (other measurements aren't range-checked, so their sums can't be
+//! released privately). The query proof of the exact answer would reveal it, so noisy releases
+//! carry none.
+//!
+//! Every noisy release spends its epsilon and delta from the dataset's budget in the
+//! `privacy_budget` table, capped by `DP_EPSILON_BUDGET` (default 10, `0` = uncapped); a release
+//! that would exceed it is refused with `429`. Noise is drawn in floating point from `OsRng`,
+//! which is not hardened against attacks on the low-order bits of the noisy value.
+
+use crate::db::QueryResult;
+use crate::errors::ApiError;
+use crate::models::{DpMechanism, DpParams, DpRelease, Metric};
+use crate::quality::PLAUSIBLE_GLUCOSE_MG_DL;
+use crate::state::AppState;
+use rand::rngs::OsRng;
+use rand::Rng;
+use uuid::Uuid;
+use zk_proofs::types::Measurement;
+
+const DEFAULT_EPSILON_BUDGET: f64 = 10.0;
+const DEFAULT_DELTA: f64 = 1e-6;
+
+/// Largest epsilon one query may spend.
+const MAX_EPSILON: f64 = 10.0;
+
+/// Largest epsilon for the Gaussian mechanism, whose classic calibration needs each released
+/// value's share below 1.
+const MAX_GAUSSIAN_EPSILON: f64 = 2.0;
+
+/// Largest change one record can make to a glucose sum.
+const SUM_SENSITIVITY: u64 = PLAUSIBLE_GLUCOSE_MG_DL.1 as u64;
+const COUNT_SENSITIVITY: u64 = 1;
+
+/// Epsilon each dataset may spend on noisy releases (`DP_EPSILON_BUDGET`); `None` if uncapped.
+pub fn epsilon_budget() -> Option<f64> {
+    match std::env::var("DP_EPSILON_BUDGET").ok().and_then(|v| v.parse::<f64>().ok()) {
+        Some(budget) if budget.is_finite() && budget <= 0.0 => None,
+        Some(budget) if budget.is_finite() => Some(budget),
+        _ => Some(DEFAULT_EPSILON_BUDGET),
+    }
+}
+
+/// Delta of one Gaussian release (`DP_DELTA`, default 1e-6).
+fn gaussian_delta() -> f64 {
+    std::env::var("DP_DELTA")
+        .ok()
+        .and_then(|v| v.parse::<f64>().ok())
+        .filter(|d| *d > 0.0 && *d < 1.0)
+        .unwrap_or(DEFAULT_DELTA)
+}
+
+/// Delta a release with `mechanism` spends.
+fn release_delta(mechanism: DpMechanism) -> f64 {
+    match mechanism {
+        DpMechanism::Laplace => 0.0,
+        DpMechanism::Gaussian => gaussian_delta(),
+    }
+}
+
+/// Validate a query's privacy parameters; `None` for an exact query.
+pub fn check_request(
+    epsilon: Option<f64>,
+    mechanism: Option<DpMechanism>,
+    metric: &Metric,
+    field: Measurement,
+) -> Result<Option<DpParams>, String> {
+    let Some(epsilon) = epsilon else {
+        return match mechanism {
+            Some(_) => Err("dp_mechanism needs epsilon".to_string()),
+            None => Ok(None),
+        };
+    };
+    if !(epsilon > 0.0 && epsilon <= MAX_EPSILON) {
+        return Err(format!("epsilon must be within (0, {MAX_EPSILON}]"));
+    }
+    let mechanism = mechanism.unwrap_or_default();
+    if mechanism == DpMechanism::Gaussian && epsilon > MAX_GAUSSIAN_EPSILON {
+        return Err(format!("the gaussian mechanism needs epsilon <= {MAX_GAUSSIAN_EPSILON}"));
+    }
+    if !matches!(metric, Metric::Sum | Metric::Count | Metric::Mean) {
+        return Err("epsilon is supported for sum, count and mean queries".to_string());
+    }
+    if field != Measurement::BloodGlucose {
+        return Err(format!(
+            "differentially private answers need a bounded measurement: blood_glucose, not '{}'",
+            field.name()
+        ));
+    }
+    Ok(Some(DpParams { epsilon, mechanism }))
+}
+
+/// Spend `dp` from the dataset's budget, refusing (429) a release that would exceed it. Returns
+/// the epsilon left, if capped.
+pub async fn spend_budget(state: &AppState, dataset_id: Uuid, dp: &DpParams) -> Result<Option<f64>, ApiError> {
+    let budget = epsilon_budget();
+    let delta = release_delta(dp.mechanism);
+    if let Some(spent) = state.store.spend_privacy_budget(dataset_id, dp.epsilon, delta, budget).await? {
+        return Ok(budget.map(|b| (b - spent.epsilon_spent).max(0.0)));
+    }
+
+    let spent = state.store.privacy_budget(dataset_id).await?;
+    let reason = format!(
+        "privacy budget exhausted: epsilon {:.3} of {:.3} spent, {} requested",
+        spent.epsilon_spent,
+        budget.unwrap_or_default(),
+        dp.epsilon
+    );
+    state.store.append_audit(
+        Some(dataset_id),
+        "query_dp_budget_exhausted",
+        &serde_json::json!({ "epsilon": dp.epsilon, "epsilon_spent": spent.epsilon_spent, "epsilon_budget": budget }),
+    )
+    .await?;
+    Err(ApiError::TooManyRequests(reason))
+}
+
+/// Noise scale for a value of `sensitivity` released at `epsilon` (and `delta`): Laplace `b`, or
+/// Gaussian `sigma`.
+fn noise_scale(mechanism: DpMechanism, sensitivity: u64, epsilon: f64, delta: f64) -> f64 {
+    let sensitivity = sensitivity as f64;
+    match mechanism {
+        DpMechanism::Laplace => sensitivity / epsilon,
+        DpMechanism::Gaussian => sensitivity * (2.0 * (1.25 / delta).ln()).sqrt() / epsilon,
+    }
+}
+
+fn sample_noise(rng: &mut OsRng, mechanism: DpMechanism, scale: f64) -> f64 {
+    match mechanism {
+        DpMechanism::Laplace => {
+            // Inverse CDF; u = -0.5 would give an infinite sample.
+            let u = rng.r#gen::<f64>() - 0.5;
+            -scale * u.signum() * (1.0 - 2.0 * u.abs()).max(f64::MIN_POSITIVE).ln()
+        }
+        DpMechanism::Gaussian => {
+            // Box–Muller; u1 in (0, 1].
+            let u1 = 1.0 - rng.r#gen::<f64>();
+            let u2 = rng.r#gen::<f64>();
+            scale * (-2.0 * u1.ln()).sqrt() * (std::f64::consts::TAU * u2).cos()
+        }
+    }
+}
+
+/// `value` plus `noise`, rounded and clamped to the non-negative (post-processing).
+fn noisy(value: u64, noise: f64) -> u64 {
+    (value as f64 + noise).round().max(0.0) as u64
+}
+
+/// `result` of a `metric` query with its sum, count and mean replaced by noisy ones for `dp`, and
+/// no query proof.
+pub fn privatize(result: QueryResult, metric: &Metric, dp: &DpParams, epsilon_remaining: Option<f64>) -> QueryResult {
+    let delta = release_delta(dp.mechanism);
+    let (epsilon_each, delta_each) = (dp.epsilon / 2.0, delta / 2.0);
+    let sum_noise_scale = noise_scale(dp.mechanism, SUM_SENSITIVITY, epsilon_each, delta_each);
+    let count_noise_scale = noise_scale(dp.mechanism, COUNT_SENSITIVITY, epsilon_each, delta_each);
+
+    let mut rng = OsRng;
+    let sum = noisy(result.sum, sample_noise(&mut rng, dp.mechanism, sum_noise_scale));
+    let count = noisy(result.count, sample_noise(&mut rng, dp.mechanism, count_noise_scale));
+    // From the noisy pair only: whether the exact count was zero mustn't show.
+    let mean = (matches!(metric, Metric::Mean) && count > 0).then(|| sum as f64 / count as f64);
+
+    QueryResult {
+        sum,
+        count,
+        mean,
+        query_proof: None,
+        dp: Some(DpRelease {
+            mechanism: dp.mechanism,
+            epsilon: dp.epsilon,
+            delta: (dp.mechanism == DpMechanism::Gaussian).then_some(delta),
+            sum_sensitivity: SUM_SENSITIVITY,
+            count_sensitivity: COUNT_SENSITIVITY,
+            sum_noise_scale,
+            count_noise_scale,
+            epsilon_remaining,
+        }),
+        ..result
+    }
+}
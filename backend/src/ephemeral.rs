@@ -0,0 +1,47 @@
+//! Ephemeral mode for tests and demos.
+//!
+//! With `EPHEMERAL=1` (or a build with `--features demo`) the backend keeps its SQLite database
+//! in memory and its key files, spools and backups in a fresh temporary directory that is removed
+//! on shutdown, so nothing survives the process. Groth16 keys are set up from a seed
+//! (`EPHEMERAL_KEY_SEED`, default 0) instead of OS randomness, so every run, and every test that
+//! spins up a server, gets the same keys and verifying key ids. Deterministic keys are only
+//! acceptable because nothing proven in this mode is meant to be trusted.
+
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+
+pub const MEMORY_DB_URL: &str = "sqlite::memory:";
+
+/// Whether ephemeral mode is on; an `EPHEMERAL` value of `0`/`false` turns off the `demo` default.
+pub fn enabled() -> bool {
+    match std::env::var("EPHEMERAL") {
+        Ok(v) => !matches!(v.trim(), "" | "0" | "false"),
+        Err(_) => cfg!(feature = "demo"),
+    }
+}
+
+/// Seed for the deterministic key setup.
+pub fn key_seed() -> u64 {
+    std::env::var("EPHEMERAL_KEY_SEED").ok().and_then(|v| v.parse().ok()).unwrap_or(0)
+}
+
+/// A temporary data directory, deleted when dropped.
+pub struct EphemeralDir(PathBuf);
+
+impl EphemeralDir {
+    pub fn create() -> std::io::Result<Self> {
+        let path = std::env::temp_dir().join(format!("phl-ephemeral-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&path)?;
+        Ok(Self(path))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for EphemeralDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
@@ -1,4 +1,4 @@
-use axum::{http::StatusCode, response::{IntoResponse, Response}, Json};
+use axum::{http::{header, StatusCode}, response::{IntoResponse, Response}, Json};
 use serde::Serialize;
 use thiserror::Error;
 
@@ -7,12 +7,35 @@ pub enum ApiError {
     #[error("bad request: {0}")]
     BadRequest(String),
 
+    #[error("forbidden: {0}")]
+    Forbidden(String),
+
     #[error("not found: {0}")]
     NotFound(String),
 
     #[error("conflict: {0}")]
     Conflict(String),
 
+    #[error("gone: {0}")]
+    Gone(String),
+
+    #[error("too many requests: {0}")]
+    TooManyRequests(String),
+
+    /// A rate limit or quota that frees up after `retry_after_secs` (sent as `Retry-After`).
+    #[error("rate limited: {message}")]
+    RateLimited { message: String, retry_after_secs: u64 },
+
+    /// A `Range` outside a `len`-byte resource (sent with `Content-Range: bytes */len`).
+    #[error("range not satisfiable: {message}")]
+    RangeNotSatisfiable { message: String, len: u64 },
+
+    #[error("request timeout: {0}")]
+    Timeout(String),
+
+    #[error("upstream error: {0}")]
+    Upstream(String),
+
     #[error("internal error")]
     Internal,
 }
@@ -24,10 +47,32 @@ struct ErrorBody {
 
 impl IntoResponse for ApiError {
     fn into_response(self) -> Response {
+        if let ApiError::RateLimited { message, retry_after_secs } = self {
+            return (
+                StatusCode::TOO_MANY_REQUESTS,
+                [(header::RETRY_AFTER, retry_after_secs.to_string())],
+                Json(ErrorBody { error: message }),
+            )
+                .into_response();
+        }
+        if let ApiError::RangeNotSatisfiable { message, len } = self {
+            return (
+                StatusCode::RANGE_NOT_SATISFIABLE,
+                [(header::CONTENT_RANGE, format!("bytes */{len}"))],
+                Json(ErrorBody { error: message }),
+            )
+                .into_response();
+        }
         let (status, msg) = match &self {
             ApiError::BadRequest(m) => (StatusCode::BAD_REQUEST, m.clone()),
+            ApiError::Forbidden(m) => (StatusCode::FORBIDDEN, m.clone()),
             ApiError::NotFound(m) => (StatusCode::NOT_FOUND, m.clone()),
             ApiError::Conflict(m) => (StatusCode::CONFLICT, m.clone()),
+            ApiError::Gone(m) => (StatusCode::GONE, m.clone()),
+            ApiError::TooManyRequests(m) | ApiError::RateLimited { message: m, .. } => (StatusCode::TOO_MANY_REQUESTS, m.clone()),
+            ApiError::RangeNotSatisfiable { message, .. } => (StatusCode::RANGE_NOT_SATISFIABLE, message.clone()),
+            ApiError::Timeout(m) => (StatusCode::REQUEST_TIMEOUT, m.clone()),
+            ApiError::Upstream(m) => (StatusCode::BAD_GATEWAY, m.clone()),
             ApiError::Internal => (StatusCode::INTERNAL_SERVER_ERROR, "internal error".to_string()),
         };
 
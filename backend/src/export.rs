@@ -0,0 +1,570 @@
+//! Signed ledger export and import between deployments.
+//!
+//! An export is JSONL: a `header` line, then for each ready dataset a `dataset` line (carrying the
+//! verifying key its proofs were made with) followed by its `shard` lines, and finally a
+//! `signature` line. The signature is Ed25519 over every byte before the signature line; each
+//! instance signs with its own key, created on first start under `data/keys/`.
+//!
+//! Import trusts nothing but the signature: every shard proof is re-verified against the included
+//! VK, the VK id and the dataset commitment chain are recomputed, and only then is the dataset
+//! registered as externally proven (`imported_from` = signer public key). Imports are refused
+//! unless the signer is listed in `IMPORT_TRUSTED_SIGNERS` (comma-separated hex public keys); the
+//! dataset's consent scope, approval requirement and release limit travel with it.
+
+use crate::chain::{dataset_commitment_hex, ChainHash};
+use crate::dataset::parse_field_hex;
+use crate::deadline::Cancellation;
+use crate::db;
+use crate::errors::ApiError;
+use crate::quality::IngestQuality;
+use crate::state::{archived_vk, AppState};
+use crate::transparency;
+use base64::Engine;
+use chrono::{DateTime, Utc};
+use ring::signature::{Ed25519KeyPair, KeyPair, UnparsedPublicKey, ED25519};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::path::Path;
+use uuid::Uuid;
+use zk_proofs::groth16::{deserialize_proof, deserialize_vk, verify_shard_proof};
+use zk_proofs::types::{AgeBuckets, FieldSet, ShardStats};
+
+pub const EXPORT_FORMAT: &str = "phl-ledger-export";
+const EXPORT_VERSION: u32 = 1;
+const SIGNING_KEY_FILE: &str = "ledger_signing_ed25519.pk8";
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ExportLine {
+    Header {
+        format: String,
+        version: u32,
+        exported_at: DateTime<Utc>,
+        backend_version: String,
+    },
+    Dataset {
+        dataset_id: Uuid,
+        dataset_size: u64,
+        shard_size: u64,
+        /// Absent from exports that predate field sets (glucose-only).
+        #[serde(default)]
+        field_set: FieldSet,
+        /// Absent from exports that predate selectable chain hashes (Poseidon).
+        #[serde(default)]
+        chain_hash: ChainHash,
+        /// Absent from exports that predate dual-commitment datasets.
+        #[serde(default)]
+        sha256_commitment: bool,
+        num_buckets: u64,
+        /// Absent from exports that predate configurable age buckets (default layout).
+        #[serde(default)]
+        age_buckets: AgeBuckets,
+        /// Rolling window in shards; absent if every shard is live.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        window_shards: Option<u64>,
+        /// Release policy; absent from exports that predate carrying it (unrestricted).
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        consent_scope: Option<Vec<String>>,
+        #[serde(default)]
+        requires_approval: bool,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        release_limit: Option<u64>,
+        dataset_commitment_hex: String,
+        manifest: Option<serde_json::Value>,
+        vk_b64: String,
+        /// Set on partial exports: only shards with an index in `[from, to)` follow.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        shard_range: Option<(u64, u64)>,
+    },
+    Shard {
+        dataset_id: Uuid,
+        shard_index: u64,
+        shard_commitment_hex: String,
+        stats: Box<ShardStats>,
+        proof_b64: String,
+    },
+    Signature {
+        alg: String,
+        public_key_hex: String,
+        signature_hex: String,
+    },
+}
+
+/// Outcome of importing one dataset.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ImportedDataset {
+    pub dataset_id: Uuid,
+    /// `imported`, `already_present` or `rejected`.
+    pub outcome: String,
+    pub shards_verified: u64,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ImportReport {
+    pub signer_public_key_hex: String,
+    pub datasets: Vec<ImportedDataset>,
+}
+
+/// This instance's signing key (`AppState::signing_key`), generated on first start.
+pub fn signing_key(data_dir: &Path) -> Result<Ed25519KeyPair, ApiError> {
+    let keys_dir = data_dir.join("keys");
+    let path = keys_dir.join(SIGNING_KEY_FILE);
+
+    let pkcs8 = match std::fs::read(&path) {
+        Ok(bytes) => bytes,
+        Err(_) => {
+            std::fs::create_dir_all(&keys_dir).map_err(|_| ApiError::Internal)?;
+            let doc = Ed25519KeyPair::generate_pkcs8(&ring::rand::SystemRandom::new()).map_err(|_| ApiError::Internal)?;
+            std::fs::write(&path, doc.as_ref()).map_err(|_| ApiError::Internal)?;
+            doc.as_ref().to_vec()
+        }
+    };
+    Ed25519KeyPair::from_pkcs8(&pkcs8).map_err(|_| ApiError::Internal)
+}
+
+fn push_line(out: &mut Vec<u8>, line: &ExportLine) -> Result<(), ApiError> {
+    serde_json::to_writer(&mut *out, line).map_err(|_| ApiError::Internal)?;
+    out.push(b'\n');
+    Ok(())
+}
+
+/// The VK a dataset's proofs verify against: the exporter's for imported datasets, else ours (the
+/// archived key its manifest names, if our keys have been replaced since it was proven).
+pub async fn dataset_vk_b64(
+    state: &AppState,
+    dataset_id: Uuid,
+    shard_size: u64,
+    field_set: FieldSet,
+    buckets: &AgeBuckets,
+    sha256_commitment: bool,
+) -> Result<String, ApiError> {
+    if let Some(vk_b64) = state.store.get_dataset_external_vk(dataset_id).await? {
+        return Ok(vk_b64);
+    }
+    let keys = state.ensure_keys_for(shard_size as usize, field_set, buckets, sha256_commitment).await?;
+    let manifest = state.store.get_dataset_manifest(dataset_id).await?;
+    let proven_with = manifest.as_ref().and_then(|m| m.get("key_id")).and_then(|k| k.as_str());
+    let vk_bytes = match proven_with.filter(|key_id| *key_id != keys.key_id) {
+        Some(key_id) => archived_vk(&state.data_dir.join("keys"), key_id)
+            .ok_or_else(|| ApiError::Conflict("dataset's proofs were made with keys that are no longer available".to_string()))?,
+        None => zk_proofs::groth16::serialize_vk(keys.vk.as_ref()).map_err(|_| ApiError::Internal)?,
+    };
+    Ok(base64::engine::general_purpose::STANDARD.encode(vk_bytes))
+}
+
+/// Export the given ready datasets (all ready datasets if `None`) as signed JSONL. With
+/// `shard_range`, only shards with an index in that range are included; such partial exports are
+/// for distributing verification and cannot be imported.
+pub async fn export_ledger(
+    state: &AppState,
+    dataset_ids: Option<Vec<Uuid>>,
+    shard_range: Option<std::ops::Range<u64>>,
+) -> Result<Vec<u8>, ApiError> {
+    let ids = match dataset_ids {
+        Some(ids) => ids,
+        None => state.store.list_dataset_ids().await?,
+    };
+
+    let mut out = Vec::new();
+    push_line(
+        &mut out,
+        &ExportLine::Header {
+            format: EXPORT_FORMAT.to_string(),
+            version: EXPORT_VERSION,
+            exported_at: Utc::now(),
+            backend_version: env!("CARGO_PKG_VERSION").to_string(),
+        },
+    )?;
+
+    for dataset_id in ids {
+        let Some(dataset) = state.store.get_dataset(dataset_id).await? else {
+            return Err(ApiError::NotFound(format!("dataset {dataset_id} not found")));
+        };
+        let Some(commitment_hex) = dataset.commitment_hex.clone().filter(|_| dataset.status == "ready") else {
+            continue;
+        };
+        let range = match &shard_range {
+            Some(r) => r.start..r.end.min(dataset.shards_total()),
+            None => 0..dataset.shards_total(),
+        };
+
+        push_line(
+            &mut out,
+            &ExportLine::Dataset {
+                dataset_id,
+                dataset_size: dataset.dataset_size,
+                shard_size: dataset.shard_size,
+                field_set: dataset.field_set,
+                chain_hash: dataset.chain_hash,
+                sha256_commitment: dataset.sha256_commitment,
+                num_buckets: dataset.age_buckets.num_buckets() as u64,
+                age_buckets: dataset.age_buckets.clone(),
+                window_shards: dataset.window_shards,
+                consent_scope: dataset.consent_scope.clone(),
+                requires_approval: dataset.requires_approval,
+                release_limit: dataset.release_limit,
+                dataset_commitment_hex: commitment_hex,
+                manifest: state.store.get_dataset_manifest(dataset_id).await?,
+                vk_b64: dataset_vk_b64(
+                    state,
+                    dataset_id,
+                    dataset.shard_size,
+                    dataset.field_set,
+                    &dataset.age_buckets,
+                    dataset.sha256_commitment,
+                )
+                .await?,
+                shard_range: shard_range.is_some().then_some((range.start, range.end)),
+            },
+        )?;
+
+        for (shard_index, shard_commitment_hex, stats, _, proof_b64) in
+            state.store.list_shards(dataset_id, range, 0, dataset.shards_total(), true).await?
+        {
+            push_line(
+                &mut out,
+                &ExportLine::Shard {
+                    dataset_id,
+                    shard_index,
+                    shard_commitment_hex,
+                    stats: Box::new(stats),
+                    proof_b64: proof_b64.unwrap_or_default(),
+                },
+            )?;
+        }
+    }
+
+    let key = &state.signing_key;
+    let signature = key.sign(&out);
+    push_line(
+        &mut out,
+        &ExportLine::Signature {
+            alg: "ed25519".to_string(),
+            public_key_hex: hex::encode(key.public_key().as_ref()),
+            signature_hex: hex::encode(signature.as_ref()),
+        },
+    )?;
+
+    Ok(out)
+}
+
+fn trusted_signers() -> Vec<String> {
+    std::env::var("IMPORT_TRUSTED_SIGNERS")
+        .map(|v| {
+            v.split(',')
+                .map(|s| s.trim().to_ascii_lowercase())
+                .filter(|s| !s.is_empty())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Check the trailing signature line and return (signed body, signer public key hex). The signer
+/// must be in `IMPORT_TRUSTED_SIGNERS`; with no trust list configured every import is refused.
+fn verify_signature(bytes: &[u8]) -> Result<(&[u8], String), ApiError> {
+    let trimmed = bytes.strip_suffix(b"\n").unwrap_or(bytes);
+    let split = trimmed.iter().rposition(|b| *b == b'\n').map(|i| i + 1).unwrap_or(0);
+    let (body, sig_line) = trimmed.split_at(split);
+
+    let Ok(ExportLine::Signature {
+        alg,
+        public_key_hex,
+        signature_hex,
+    }) = serde_json::from_slice(sig_line)
+    else {
+        return Err(ApiError::BadRequest("export must end with a signature line".to_string()));
+    };
+    if alg != "ed25519" {
+        return Err(ApiError::BadRequest(format!("unsupported signature alg '{alg}'")));
+    }
+
+    let public_key = hex::decode(&public_key_hex).map_err(|_| ApiError::BadRequest("invalid public_key_hex".to_string()))?;
+    let signature = hex::decode(&signature_hex).map_err(|_| ApiError::BadRequest("invalid signature_hex".to_string()))?;
+    UnparsedPublicKey::new(&ED25519, &public_key)
+        .verify(body, &signature)
+        .map_err(|_| ApiError::BadRequest("export signature does not verify".to_string()))?;
+
+    let signer = public_key_hex.to_ascii_lowercase();
+    let trusted = trusted_signers();
+    if trusted.is_empty() {
+        return Err(ApiError::Forbidden(
+            "imports are disabled: set IMPORT_TRUSTED_SIGNERS to the public keys of the exporting instances".to_string(),
+        ));
+    }
+    if !trusted.contains(&signer) {
+        return Err(ApiError::Forbidden(format!("signer {signer} is not in IMPORT_TRUSTED_SIGNERS")));
+    }
+
+    Ok((body, signer))
+}
+
+/// A dataset received from another instance, not yet trusted.
+pub struct ImportCandidate {
+    pub dataset_id: Uuid,
+    pub dataset_size: u64,
+    pub shard_size: u64,
+    pub field_set: FieldSet,
+    pub chain_hash: ChainHash,
+    pub sha256_commitment: bool,
+    pub num_buckets: u64,
+    pub age_buckets: AgeBuckets,
+    pub window_shards: Option<u64>,
+    pub consent_scope: Option<Vec<String>>,
+    pub requires_approval: bool,
+    pub release_limit: Option<u64>,
+    pub dataset_commitment_hex: String,
+    pub manifest: Option<serde_json::Value>,
+    pub vk_b64: String,
+    /// (shard index, commitment hex, stats, proof b64), in shard order.
+    pub shards: Vec<(u64, String, ShardStats, String)>,
+}
+
+/// Re-verify a received dataset in full. Returns the number of shard proofs checked, or an error
+/// if `cancel` fires first.
+pub fn verify_dataset(d: &ImportCandidate, cancel: &Cancellation) -> Result<u64, String> {
+    let num_buckets = d.age_buckets.num_buckets();
+    if d.num_buckets != num_buckets as u64 {
+        return Err(format!("num_buckets {} does not match the age buckets ({num_buckets})", d.num_buckets));
+    }
+    if d.shard_size == 0 || !d.dataset_size.is_multiple_of(d.shard_size) || d.shards.len() as u64 != d.dataset_size / d.shard_size {
+        return Err(format!("expected {} shards, got {}", d.dataset_size / d.shard_size.max(1), d.shards.len()));
+    }
+
+    let b64 = base64::engine::general_purpose::STANDARD;
+    let vk_bytes = b64.decode(&d.vk_b64).map_err(|_| "invalid vk_b64".to_string())?;
+    let vk = deserialize_vk(&vk_bytes).map_err(|_| "invalid verifying key".to_string())?;
+    if let Some(key_id) = d.manifest.as_ref().and_then(|m| m.get("key_id")).and_then(|k| k.as_str())
+        && key_id != hex::encode(Sha256::digest(&vk_bytes))
+    {
+        return Err("verifying key does not match manifest key_id".to_string());
+    }
+
+    let mut commitments = Vec::with_capacity(d.shards.len());
+    for (expected_index, (shard_index, commitment_hex, stats, proof_b64)) in d.shards.iter().enumerate() {
+        if cancel.is_cancelled() {
+            return Err("verification cancelled".to_string());
+        }
+        if *shard_index != expected_index as u64 {
+            return Err(format!("shard {expected_index} missing or out of order"));
+        }
+        check_stats_shape(stats, d.field_set, num_buckets).map_err(|e| format!("shard {shard_index}: {e}"))?;
+        let commitment = parse_field_hex(commitment_hex).ok_or_else(|| format!("shard {shard_index}: invalid commitment"))?;
+        let proof = b64
+            .decode(proof_b64)
+            .ok()
+            .and_then(|bytes| deserialize_proof(&bytes).ok())
+            .ok_or_else(|| format!("shard {shard_index}: invalid proof"))?;
+        verify_shard_proof(&vk, &proof, commitment, stats).map_err(|_| format!("shard {shard_index}: proof does not verify"))?;
+        commitments.push(commitment);
+    }
+
+    let recomputed = dataset_commitment_hex(d.chain_hash, &commitments).map_err(|e| e.to_string())?;
+    if recomputed != d.dataset_commitment_hex {
+        return Err("dataset commitment does not match shard commitments".to_string());
+    }
+
+    Ok(d.shards.len() as u64)
+}
+
+/// Check that received shard stats have the dataset's field set and number of age buckets.
+pub fn check_stats_shape(stats: &ShardStats, field_set: FieldSet, num_buckets: usize) -> Result<(), String> {
+    if stats.extra_sums_by_bucket.len() + 1 != field_set.measurements().len() {
+        return Err(format!("stats do not match field set '{}'", field_set.name()));
+    }
+    if stats.count_by_bucket.len() != num_buckets {
+        return Err(format!("stats do not have {num_buckets} age buckets"));
+    }
+    if !stats.glucose_bounds_plausible() {
+        return Err("stats claim glucose bounds wider than the plausible range".to_string());
+    }
+    Ok(())
+}
+
+/// Verify and register every dataset in a signed export. Datasets that fail verification are
+/// reported and skipped; the rest are registered as ready and externally proven. If `cancel` fires,
+/// the datasets registered so far stay (see `deadline`).
+pub async fn import_ledger(
+    state: &AppState,
+    bytes: &[u8],
+    imported_by: &str,
+    cancel: &Cancellation,
+) -> Result<ImportReport, ApiError> {
+    let (body, signer) = verify_signature(bytes)?;
+
+    let mut lines = body.split(|b| *b == b'\n').filter(|l| !l.is_empty());
+    let header = lines.next().map(serde_json::from_slice::<ExportLine>);
+    match header {
+        Some(Ok(ExportLine::Header { format, version, .. })) if format == EXPORT_FORMAT && version == EXPORT_VERSION => {}
+        _ => return Err(ApiError::BadRequest(format!("not a {EXPORT_FORMAT} v{EXPORT_VERSION} export"))),
+    }
+
+    let mut pending: Vec<ImportCandidate> = Vec::new();
+    for (n, line) in lines.enumerate() {
+        let line: ExportLine =
+            serde_json::from_slice(line).map_err(|e| ApiError::BadRequest(format!("line {}: {e}", n + 2)))?;
+        match line {
+            ExportLine::Dataset {
+                dataset_id,
+                dataset_size,
+                shard_size,
+                field_set,
+                chain_hash,
+                sha256_commitment,
+                num_buckets,
+                age_buckets,
+                window_shards,
+                consent_scope,
+                requires_approval,
+                release_limit,
+                dataset_commitment_hex,
+                manifest,
+                vk_b64,
+                shard_range,
+            } => {
+                if let Some((from, to)) = shard_range {
+                    return Err(ApiError::BadRequest(format!(
+                        "line {}: partial export of dataset {dataset_id} (shards {from}..{to}) cannot be imported",
+                        n + 2
+                    )));
+                }
+                pending.push(ImportCandidate {
+                    dataset_id,
+                    dataset_size,
+                    shard_size,
+                    field_set,
+                    chain_hash,
+                    sha256_commitment,
+                    num_buckets,
+                    age_buckets,
+                    window_shards,
+                    consent_scope,
+                    requires_approval,
+                    release_limit,
+                    dataset_commitment_hex,
+                    manifest,
+                    vk_b64,
+                    shards: Vec::new(),
+                })
+            }
+            ExportLine::Shard {
+                dataset_id,
+                shard_index,
+                shard_commitment_hex,
+                stats,
+                proof_b64,
+            } => match pending.last_mut() {
+                Some(d) if d.dataset_id == dataset_id => d.shards.push((shard_index, shard_commitment_hex, *stats, proof_b64)),
+                _ => return Err(ApiError::BadRequest(format!("line {}: shard outside its dataset", n + 2))),
+            },
+            _ => return Err(ApiError::BadRequest(format!("line {}: unexpected record", n + 2))),
+        }
+    }
+
+    let mut datasets = Vec::with_capacity(pending.len());
+    for d in pending {
+        let dataset_id = d.dataset_id;
+
+        if let Some(existing) = state.store.get_dataset(dataset_id).await? {
+            let same = existing.commitment_hex.as_deref() == Some(d.dataset_commitment_hex.as_str());
+            datasets.push(ImportedDataset {
+                dataset_id,
+                outcome: if same { "already_present" } else { "rejected" }.to_string(),
+                shards_verified: 0,
+                error: (!same).then(|| "a different dataset with this id exists".to_string()),
+            });
+            continue;
+        }
+
+        let task_cancel = cancel.clone();
+        let (d, verified) = tokio::task::spawn_blocking(move || {
+            let verified = verify_dataset(&d, &task_cancel);
+            (d, verified)
+        })
+        .await
+        .map_err(|_| ApiError::Internal)?;
+        cancel.check()?;
+
+        let shards_verified = match verified {
+            Ok(n) => n,
+            Err(error) => {
+                datasets.push(ImportedDataset {
+                    dataset_id,
+                    outcome: "rejected".to_string(),
+                    shards_verified: 0,
+                    error: Some(error),
+                });
+                continue;
+            }
+        };
+
+        if let Err(e) = register(state, &d, &signer, imported_by).await {
+            let _ = state.store.set_dataset_failed(dataset_id, &format!("{e}")).await;
+            return Err(e);
+        }
+        datasets.push(ImportedDataset {
+            dataset_id,
+            outcome: "imported".to_string(),
+            shards_verified,
+            error: None,
+        });
+    }
+
+    Ok(ImportReport {
+        signer_public_key_hex: signer,
+        datasets,
+    })
+}
+
+/// Register a verified dataset as ready and externally proven. `imported_from` identifies the
+/// source (export signer key, or mirror upstream).
+pub async fn register(state: &AppState, d: &ImportCandidate, imported_from: &str, imported_by: &str) -> Result<(), ApiError> {
+    state.store.insert_dataset(
+        &db::NewDataset {
+            dataset_id: d.dataset_id,
+            dataset_size: d.dataset_size,
+            shard_size: d.shard_size,
+            field_set: d.field_set,
+            chain_hash: d.chain_hash,
+            sha256_commitment: d.sha256_commitment,
+            age_buckets: &d.age_buckets,
+            consent_scope: d.consent_scope.as_deref(),
+            requires_approval: d.requires_approval,
+            release_limit: d.release_limit,
+            generator: None,
+            generator_params: None,
+            ingest_quality: &IngestQuality::external(d.dataset_size),
+            window_shards: d.window_shards,
+            owner: imported_by,
+        },
+    )
+    .await?;
+
+    let vk_bytes = base64::engine::general_purpose::STANDARD.decode(&d.vk_b64).map_err(|_| ApiError::Internal)?;
+    let key_id = hex::encode(Sha256::digest(&vk_bytes));
+    for (shard_index, commitment_hex, stats, proof_b64) in &d.shards {
+        state.store.insert_shard(d.dataset_id, *shard_index, commitment_hex, stats, proof_b64, true).await?;
+        state.store.set_shard_vk_version(d.dataset_id, *shard_index, &key_id).await?;
+        transparency::log_shard(state, d.dataset_id, *shard_index, commitment_hex).await?;
+    }
+    let shard_indices: Vec<u64> = d.shards.iter().map(|(shard_index, ..)| *shard_index).collect();
+    state.store.set_shards_verified(d.dataset_id, &shard_indices, &key_id).await?;
+    if let Some(manifest) = &d.manifest {
+        state.store.set_dataset_manifest(d.dataset_id, manifest).await?;
+    }
+    state.store.set_dataset_imported(d.dataset_id, &d.dataset_commitment_hex, &d.vk_b64, imported_from).await?;
+    transparency::log_dataset(state, d.dataset_id, &d.dataset_commitment_hex).await?;
+
+    state.store.append_audit(
+        Some(d.dataset_id),
+        "dataset_imported",
+        &serde_json::json!({
+            "dataset_commitment_hex": d.dataset_commitment_hex,
+            "imported_from": imported_from,
+            "imported_by": imported_by,
+        }),
+    )
+    .await?;
+
+    Ok(())
+}
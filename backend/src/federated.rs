@@ -0,0 +1,150 @@
+//! Federated sites: datasets proven off-site, e.g. by a `ledger-agent` at a hospital.
+//!
+//! A site proves shards with its own Groth16 keys and never sends records:
+//! 1) `POST /api/v1/federated` registers an empty dataset with the site's verifying key.
+//! 2) `POST /api/v1/federated/:id/shards` pushes the next shard's commitment, public stats and
+//!    proof. The proof is verified against the registered key before anything is stored; the
+//!    shard is then stored and the dataset grows by one shard under a new commitment (the chain
+//!    over all its shards so far), and `shard_federated` is recorded in the audit chain.
+//!
+//! Re-sending a stored shard with the same commitment is a no-op, so a site can resume after an
+//! outage from the dataset's size. Federated datasets are externally proven like imports
+//! (`imported_from` = `federated:<site>`); their proofs are only as trustworthy as the site's key
+//! setup.
+
+use crate::chain::DatasetChain;
+use crate::dataset::{self, parse_field_hex};
+use crate::errors::ApiError;
+use crate::export;
+use crate::models::{FederatedShardRequest, FederatedShardResponse};
+use crate::quality::IngestQuality;
+use crate::quota;
+use crate::retention;
+use crate::state::AppState;
+use crate::transparency;
+use ark_bn254::Bn254;
+use ark_groth16::VerifyingKey;
+use base64::Engine;
+use sha2::{Digest, Sha256};
+use tokio::sync::Mutex;
+use uuid::Uuid;
+use zk_proofs::groth16::{deserialize_proof, deserialize_vk, verify_shard_proof};
+
+/// `imported_from` prefix of federated datasets.
+pub const SOURCE_PREFIX: &str = "federated:";
+
+/// Serializes pushes, so concurrent pushes can't both extend a dataset from the same size.
+static PUSH_LOCK: Mutex<()> = Mutex::const_new(());
+
+/// Decode a site's verifying key; returns it with its key id (hex SHA-256 of the key bytes).
+pub fn decode_vk(vk_b64: &str) -> Result<(VerifyingKey<Bn254>, String), ApiError> {
+    let vk_bytes = base64::engine::general_purpose::STANDARD
+        .decode(vk_b64)
+        .map_err(|_| ApiError::BadRequest("invalid vk_b64".to_string()))?;
+    let vk = deserialize_vk(&vk_bytes).map_err(|_| ApiError::BadRequest("invalid verifying key".to_string()))?;
+    Ok((vk, hex::encode(Sha256::digest(&vk_bytes))))
+}
+
+/// Verify and append shard `req.shard_index` of federated dataset `dataset_id`, pushed by `owner`.
+pub async fn push_shard(
+    state: &AppState,
+    owner: &str,
+    dataset_id: Uuid,
+    req: FederatedShardRequest,
+) -> Result<FederatedShardResponse, ApiError> {
+    let _guard = PUSH_LOCK.lock().await;
+
+    let Some(mut dataset) = state.store.get_dataset(dataset_id).await? else {
+        return Err(retention::missing_dataset(state, dataset_id).await);
+    };
+    if state.store.dataset_owner(dataset_id).await?.as_deref() != Some(owner) {
+        return Err(ApiError::Forbidden("only the API key that registered the dataset can push shards to it".to_string()));
+    }
+    let Some(site) = dataset.imported_from.as_deref().and_then(|s| s.strip_prefix(SOURCE_PREFIX)).map(str::to_string) else {
+        return Err(ApiError::BadRequest("not a federated dataset".to_string()));
+    };
+    if dataset.frozen_at.is_some() {
+        return Err(ApiError::Conflict("dataset is frozen".to_string()));
+    }
+
+    let shards_total = dataset.shards_total();
+    let response = |outcome: &str, dataset_size: u64, dataset_commitment_hex: Option<String>| FederatedShardResponse {
+        dataset_id,
+        shard_index: req.shard_index,
+        outcome: outcome.to_string(),
+        dataset_size,
+        dataset_commitment_hex,
+    };
+    if req.shard_index < shards_total {
+        let stored = state.store.list_shards(dataset_id, req.shard_index..req.shard_index + 1, 0, 1, false).await?;
+        return match stored.first() {
+            Some((_, commitment_hex, ..)) if *commitment_hex == req.shard_commitment_hex => {
+                Ok(response("already_present", dataset.dataset_size, dataset.commitment_hex.clone()))
+            }
+            _ => Err(ApiError::Conflict(format!(
+                "shard {} is already stored with a different commitment",
+                req.shard_index
+            ))),
+        };
+    }
+    if req.shard_index > shards_total {
+        return Err(ApiError::Conflict(format!("expected shard {shards_total}")));
+    }
+
+    export::check_stats_shape(&req.stats, dataset.field_set, dataset.age_buckets.num_buckets()).map_err(ApiError::BadRequest)?;
+    let commitment = parse_field_hex(&req.shard_commitment_hex)
+        .ok_or_else(|| ApiError::BadRequest("invalid shard_commitment_hex".to_string()))?;
+    let proof = base64::engine::general_purpose::STANDARD
+        .decode(&req.proof_b64)
+        .ok()
+        .and_then(|bytes| deserialize_proof(&bytes).ok())
+        .ok_or_else(|| ApiError::BadRequest("invalid proof_b64".to_string()))?;
+    let vk_b64 = state.store.get_dataset_external_vk(dataset_id).await?.ok_or(ApiError::Internal)?;
+    let (vk, key_id) = decode_vk(&vk_b64)?;
+    let (stats, verified) = tokio::task::spawn_blocking(move || {
+        let verified = verify_shard_proof(&vk, &proof, commitment, &req.stats).is_ok();
+        (req.stats, verified)
+    })
+    .await
+    .map_err(|_| ApiError::Internal)?;
+    if !verified {
+        return Err(ApiError::BadRequest(format!("shard {shards_total}: proof does not verify")));
+    }
+
+    quota::enforce_more_records(state, owner, dataset.shard_size).await?;
+
+    state.store.insert_shard(dataset_id, shards_total, &req.shard_commitment_hex, &stats, &req.proof_b64, true).await?;
+    state.store.set_shard_vk_version(dataset_id, shards_total, &key_id).await?;
+    state.store.set_shards_verified(dataset_id, &[shards_total], &key_id).await?;
+    transparency::log_shard(state, dataset_id, shards_total, &req.shard_commitment_hex).await?;
+
+    let mut chain = DatasetChain::new(dataset.chain_hash);
+    for (_, commitment_hex, _, _, _) in state.store.list_shards(dataset_id, 0..shards_total + 1, 0, shards_total + 1, false).await? {
+        chain.absorb(&parse_field_hex(&commitment_hex).ok_or(ApiError::Internal)?)?;
+    }
+    let dataset_commitment_hex = chain.finish_hex()?;
+    let live_from = dataset.live_shards().start;
+    dataset.dataset_size = (shards_total + 1) * dataset.shard_size;
+    if !state.store.extend_dataset(dataset_id, dataset.dataset_size, &dataset_commitment_hex).await? {
+        return Err(ApiError::Conflict("dataset is frozen".to_string()));
+    }
+    transparency::log_dataset(state, dataset_id, &dataset_commitment_hex).await?;
+    state.store.set_dataset_ingest_quality(dataset_id, &IngestQuality::external(dataset.dataset_size)).await?;
+
+    state.store.append_audit(
+        Some(dataset_id),
+        "shard_federated",
+        &serde_json::json!({
+            "site": site,
+            "shard_index": shards_total,
+            "shard_commitment_hex": req.shard_commitment_hex,
+            "dataset_size": dataset.dataset_size,
+            "dataset_commitment_hex": dataset_commitment_hex,
+        }),
+    )
+    .await?;
+    dataset::audit_expired_shards(state, dataset_id, &dataset, live_from).await?;
+
+    tracing::info!(%dataset_id, shard_index = shards_total, %site, "appended federated shard");
+    Ok(response("appended", dataset.dataset_size, Some(dataset_commitment_hex)))
+}
@@ -0,0 +1,256 @@
+//! Pluggable synthetic record generators.
+//!
+//! Each generator is registered by name and selected via `DatasetCreateRequest::generator`.
+//! Generators must be deterministic given the RNG: the per-shard seed is what makes a synthetic
+//! dataset reproducible. For `vitals` datasets each record's further measurements are drawn right
+//! after it by `gen_vitals`; glucose-only datasets never call it.
+//!
+//! Generators taking parameters (`DatasetCreateRequest::generator_params`) are configured per
+//! dataset by `resolve`; their defaults must keep generating the records they did before the
+//! parameters existed, since migrations regenerate stored datasets.
+
+use rand::{Rng, RngCore};
+use rand_chacha::ChaCha20Rng;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::sync::Arc;
+use zk_proofs::types::Record;
+
+/// Generator used when a dataset request does not name one.
+pub const DEFAULT_GENERATOR: &str = "uniform";
+
+pub trait SyntheticGenerator: Send + Sync {
+    /// Registry name, as accepted in `DatasetCreateRequest::generator`.
+    fn name(&self) -> &'static str;
+
+    /// One-line description for the generator listing.
+    fn description(&self) -> &'static str;
+
+    /// Distribution parameters, recorded in the dataset manifest.
+    fn params(&self) -> serde_json::Value;
+
+    /// This generator configured with a dataset's `params` (`None` = defaults).
+    fn configure(&self, params: Option<&serde_json::Value>) -> Result<Arc<dyn SyntheticGenerator>, String>;
+
+    /// Generate one synthetic record (age and glucose).
+    fn gen_record(&self, rng: &mut ChaCha20Rng) -> Record;
+
+    /// Fill systolic blood pressure, heart rate and BMI of `record`. The default shared by all
+    /// generators: systolic BP rising ~0.5 mmHg per year from 105 (sd 12), heart rate around 72
+    /// (sd 10), BMI around 25.0 (sd 4.5).
+    fn gen_vitals(&self, rng: &mut ChaCha20Rng, record: &mut Record) {
+        let systolic = approx_normal(rng, 105.0 + 0.5 * record.age as f64, 12.0);
+        record.systolic_bp_mm_hg = systolic.round().clamp(70.0, 250.0) as u16;
+        record.heart_rate_bpm = approx_normal(rng, 72.0, 10.0).round().clamp(35.0, 200.0) as u16;
+        record.bmi_x10 = approx_normal(rng, 250.0, 45.0).round().clamp(120.0, 600.0) as u16;
+    }
+}
+
+/// Ages uniform in [0, 120], glucose uniform in [70, 180], independent.
+pub struct Uniform;
+
+impl SyntheticGenerator for Uniform {
+    fn name(&self) -> &'static str {
+        "uniform"
+    }
+
+    fn description(&self) -> &'static str {
+        "age uniform in [0, 120], glucose uniform in [70, 180] mg/dL"
+    }
+
+    fn params(&self) -> serde_json::Value {
+        json!({ "age": [0, 120], "glucose": [70, 180] })
+    }
+
+    fn configure(&self, params: Option<&serde_json::Value>) -> Result<Arc<dyn SyntheticGenerator>, String> {
+        no_params(self, params)?;
+        Ok(Arc::new(Uniform))
+    }
+
+    fn gen_record(&self, rng: &mut ChaCha20Rng) -> Record {
+        let age = (rng.next_u32() % 121) as u8; // [0, 120]
+
+        // Blood glucose: roughly [70, 180], uniform for the prototype.
+        let glucose = 70u16 + (rng.next_u32() % 111) as u16;
+
+        Record {
+            age,
+            blood_glucose_mg_dl: glucose,
+            ..Record::default()
+        }
+    }
+}
+
+/// Parameters of `AgeCorrelated`: glucose ~ normal(`intercept` + `slope_per_year`·age,
+/// `sd` + `sd_per_year`·age), in mg/dL.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AgeCorrelatedParams {
+    pub intercept: f64,
+    pub slope_per_year: f64,
+    pub sd: f64,
+    /// Growth of the spread with age; 0 keeps it constant.
+    pub sd_per_year: f64,
+}
+
+impl Default for AgeCorrelatedParams {
+    fn default() -> Self {
+        AgeCorrelated::DEFAULT.0
+    }
+}
+
+impl AgeCorrelatedParams {
+    fn check(&self) -> Result<(), String> {
+        let within = |name: &str, value: f64, (min, max): (f64, f64)| {
+            if value.is_finite() && (min..=max).contains(&value) {
+                Ok(())
+            } else {
+                Err(format!("{name} must be within [{min}, {max}]"))
+            }
+        };
+        within("intercept", self.intercept, (40.0, 400.0))?;
+        within("slope_per_year", self.slope_per_year, (-2.0, 2.0))?;
+        within("sd", self.sd, (0.0, 100.0))?;
+        within("sd_per_year", self.sd_per_year, (0.0, 1.0))
+    }
+}
+
+/// Fasting glucose drifting upward with age, with roughly normal noise that may widen with age.
+pub struct AgeCorrelated(pub AgeCorrelatedParams);
+
+impl AgeCorrelated {
+    pub const DEFAULT: AgeCorrelated = AgeCorrelated(AgeCorrelatedParams {
+        intercept: 85.0,
+        slope_per_year: 0.3,
+        sd: 12.0,
+        sd_per_year: 0.0,
+    });
+}
+
+impl SyntheticGenerator for AgeCorrelated {
+    fn name(&self) -> &'static str {
+        "age_correlated"
+    }
+
+    fn description(&self) -> &'static str {
+        "glucose mean rises ~0.3 mg/dL per year of age (85 at birth), sd 12; mean, slope and an age-growing sd are configurable"
+    }
+
+    fn params(&self) -> serde_json::Value {
+        let p = &self.0;
+        json!({
+            "age": [0, 120],
+            "intercept": p.intercept,
+            "slope_per_year": p.slope_per_year,
+            "sd": p.sd,
+            "sd_per_year": p.sd_per_year,
+            "clamp": [40, 400]
+        })
+    }
+
+    fn configure(&self, params: Option<&serde_json::Value>) -> Result<Arc<dyn SyntheticGenerator>, String> {
+        let params: AgeCorrelatedParams = match params {
+            Some(p) => serde_json::from_value(p.clone()).map_err(|e| format!("generator_params: {e}"))?,
+            None => AgeCorrelatedParams::default(),
+        };
+        params.check().map_err(|e| format!("generator_params: {e}"))?;
+        Ok(Arc::new(AgeCorrelated(params)))
+    }
+
+    fn gen_record(&self, rng: &mut ChaCha20Rng) -> Record {
+        let p = &self.0;
+        let age = (rng.next_u32() % 121) as u8;
+        let mean = p.intercept + p.slope_per_year * age as f64;
+        let sd = p.sd + p.sd_per_year * age as f64;
+
+        Record {
+            age,
+            blood_glucose_mg_dl: clamp_glucose(approx_normal(rng, mean, sd)),
+            ..Record::default()
+        }
+    }
+}
+
+/// Mixture of a healthy population and a diabetic subpopulation whose prevalence grows with age.
+pub struct DiabeticMixture;
+
+impl SyntheticGenerator for DiabeticMixture {
+    fn name(&self) -> &'static str {
+        "diabetic_mixture"
+    }
+
+    fn description(&self) -> &'static str {
+        "healthy (mean 95, sd 10) mixed with diabetic (mean 170, sd 35); prevalence 2% at 20 to ~25% at 80"
+    }
+
+    fn params(&self) -> serde_json::Value {
+        json!({
+            "age": [0, 120],
+            "healthy": { "mean": 95.0, "sd": 10.0 },
+            "diabetic": { "mean": 170.0, "sd": 35.0 },
+            "prevalence": { "at_20": 0.02, "per_year": 0.0038, "clamp": [0.005, 0.3] },
+            "clamp": [40, 400]
+        })
+    }
+
+    fn configure(&self, params: Option<&serde_json::Value>) -> Result<Arc<dyn SyntheticGenerator>, String> {
+        no_params(self, params)?;
+        Ok(Arc::new(DiabeticMixture))
+    }
+
+    fn gen_record(&self, rng: &mut ChaCha20Rng) -> Record {
+        let age = (rng.next_u32() % 121) as u8;
+        let prevalence = (0.02 + 0.0038 * (age as f64 - 20.0)).clamp(0.005, 0.3);
+
+        let glucose = if rng.r#gen::<f64>() < prevalence {
+            approx_normal(rng, 170.0, 35.0)
+        } else {
+            approx_normal(rng, 95.0, 10.0)
+        };
+
+        Record {
+            age,
+            blood_glucose_mg_dl: clamp_glucose(glucose),
+            ..Record::default()
+        }
+    }
+}
+
+fn no_params(generator: &dyn SyntheticGenerator, params: Option<&serde_json::Value>) -> Result<(), String> {
+    match params {
+        Some(_) => Err(format!("generator '{}' takes no generator_params", generator.name())),
+        None => Ok(()),
+    }
+}
+
+/// Irwin–Hall approximation of a normal sample (sum of 12 uniforms).
+fn approx_normal(rng: &mut ChaCha20Rng, mean: f64, sd: f64) -> f64 {
+    let z: f64 = (0..12).map(|_| rng.r#gen::<f64>()).sum::<f64>() - 6.0;
+    mean + sd * z
+}
+
+/// Keep generated values within a physiologically plausible range.
+fn clamp_glucose(value: f64) -> u16 {
+    value.round().clamp(40.0, 400.0) as u16
+}
+
+static GENERATORS: [&dyn SyntheticGenerator; 3] = [&Uniform, &AgeCorrelated::DEFAULT, &DiabeticMixture];
+
+/// All registered generators.
+pub fn all() -> &'static [&'static dyn SyntheticGenerator] {
+    &GENERATORS
+}
+
+/// Look up a generator by registry name.
+pub fn by_name(name: &str) -> Option<&'static dyn SyntheticGenerator> {
+    GENERATORS.iter().copied().find(|g| g.name() == name)
+}
+
+/// The generator `name` configured with a dataset's `params`.
+pub fn resolve(name: &str, params: Option<&serde_json::Value>) -> Result<Arc<dyn SyntheticGenerator>, String> {
+    let generator = by_name(name).ok_or_else(|| {
+        let known: Vec<&str> = all().iter().map(|g| g.name()).collect();
+        format!("unknown generator '{name}' (known: {known:?})")
+    })?;
+    generator.configure(params)
+}
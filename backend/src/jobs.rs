@@ -0,0 +1,230 @@
+//! Background job queue.
+//!
+//! Jobs live in the ledger store's `jobs` table, so queued work survives restarts and, with the
+//! ledger in Postgres, every instance works off one queue. Workers claim the oldest queued job;
+//! enqueueing wakes this instance's idle workers, and workers also poll periodically so jobs
+//! inserted by another instance are picked up.
+//!
+//! Each instance has a stable id (`data/instance_id`). Jobs whose input or output only exists on
+//! the instance that queued them (an upload's spool, aggregate proofs, migrations and
+//! re-verification reports kept in its SQLite file) are pinned to it; queries and synthetic
+//! dataset proving run anywhere. A running job's heartbeat is refreshed every
+//! `HEARTBEAT_INTERVAL`; on start an instance requeues the jobs it was running, and every instance
+//! requeues jobs whose heartbeat is older than `STALE_AFTER` (their instance died).
+//!
+//! Query and proving jobs run in separate worker pools (`workers.jobs` and `workers.proving` in
+//! the configuration, default 2 each) so long proving runs never hold up queries; dataset
+//! aggregate proofs get a pool of their own (`workers.aggregate`, default 1). Proving workers skip
+//! jobs of a tenant already at `QUOTA_MAX_CONCURRENT_PROVING` running jobs, and proving and
+//! aggregate workers wait for the ZK self-test (`selftest`) to pass. Curve and circuit migrations
+//! re-prove whole datasets and get pools of their own too (`workers.migration` each, default 1),
+//! so they never starve new datasets, and so do dataset re-verifications (`workers.verify`,
+//! default 1).
+
+use crate::errors::ApiError;
+use crate::quota;
+use crate::state::AppState;
+use chrono::Utc;
+use std::path::Path;
+use std::time::Duration;
+use uuid::Uuid;
+
+/// Job kind for `query::run_async_query`.
+pub const KIND_QUERY: &str = "query";
+
+/// Job kind for `dataset::run_prove_job`.
+pub const KIND_PROVE_DATASET: &str = "prove_dataset";
+
+/// Job kind for `aggregate::run_prove_job`.
+pub const KIND_PROVE_AGGREGATE: &str = "prove_aggregate";
+
+/// Job kind for `curve_migration::run_migrate_job`.
+pub const KIND_MIGRATE_CURVE: &str = "migrate_curve";
+
+/// Job kind for `circuit_migration::run_migrate_job`.
+pub const KIND_MIGRATE_CIRCUIT: &str = "migrate_circuit";
+
+/// Job kind for `reverify::run_verify_job`.
+pub const KIND_VERIFY_DATASET: &str = "verify_dataset";
+
+/// How long an idle worker waits before checking the table again.
+const IDLE_POLL: Duration = Duration::from_secs(5);
+
+/// How often a running job's heartbeat is refreshed.
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(30);
+
+/// A running job whose heartbeat is older than this is requeued by any instance.
+const STALE_AFTER: Duration = Duration::from_secs(300);
+
+const INSTANCE_ID_FILE: &str = "instance_id";
+
+/// Kinds any instance may run; other jobs run on the instance that queued them.
+const SHARED_KINDS: [&str; 2] = [KIND_QUERY, KIND_PROVE_DATASET];
+
+/// How a job run ended without error.
+pub enum JobEnd {
+    Finished,
+    /// Stopped by a shutdown before finishing; queued again for the next start.
+    Interrupted,
+}
+
+/// This instance's id, created on first start.
+pub fn instance_id(data_dir: &Path) -> Result<String, ApiError> {
+    let path = data_dir.join(INSTANCE_ID_FILE);
+    if let Ok(id) = std::fs::read_to_string(&path)
+        && !id.trim().is_empty()
+    {
+        return Ok(id.trim().to_string());
+    }
+    let id = Uuid::new_v4().to_string();
+    std::fs::create_dir_all(data_dir).map_err(|_| ApiError::Internal)?;
+    std::fs::write(&path, &id).map_err(|_| ApiError::Internal)?;
+    Ok(id)
+}
+
+/// Queue a job on behalf of `tenant` (a `Caller::key_id`) and wake the workers.
+pub async fn enqueue(state: &AppState, kind: &str, subject_id: Uuid, tenant: &str) -> Result<Uuid, ApiError> {
+    let pinned = !SHARED_KINDS.contains(&kind);
+    insert(state, kind, subject_id, tenant, pinned).await
+}
+
+/// Queue a job only this instance may run, because its input only exists here.
+pub async fn enqueue_here(state: &AppState, kind: &str, subject_id: Uuid, tenant: &str) -> Result<Uuid, ApiError> {
+    insert(state, kind, subject_id, tenant, true).await
+}
+
+async fn insert(state: &AppState, kind: &str, subject_id: Uuid, tenant: &str, pinned: bool) -> Result<Uuid, ApiError> {
+    let job_id = Uuid::new_v4();
+    let instance = pinned.then_some(&*state.instance_id);
+    state.store.insert_job(job_id, kind, subject_id, tenant, instance).await?;
+    // Workers of every pool share the notifier; wake all so the right pool sees the job.
+    state.jobs_notify.notify_waiters();
+    Ok(job_id)
+}
+
+fn stale_before() -> chrono::DateTime<Utc> {
+    Utc::now() - chrono::Duration::from_std(STALE_AFTER).unwrap_or(chrono::Duration::MAX)
+}
+
+/// Requeue interrupted jobs and start the worker pool.
+pub async fn start(state: AppState) -> Result<(), ApiError> {
+    let requeued = state.store.requeue_running_jobs(Some(&*state.instance_id), stale_before()).await?;
+    if requeued > 0 {
+        tracing::info!(requeued, "requeued interrupted jobs");
+    }
+    tokio::spawn(requeue_stale(state.clone()));
+
+    let workers = state.config.workers;
+
+    for worker in 0..workers.jobs {
+        tokio::spawn(run_worker(state.clone(), KIND_QUERY, worker));
+    }
+    for worker in 0..workers.proving {
+        tokio::spawn(run_worker(state.clone(), KIND_PROVE_DATASET, worker));
+    }
+    for worker in 0..workers.aggregate {
+        tokio::spawn(run_worker(state.clone(), KIND_PROVE_AGGREGATE, worker));
+    }
+    for worker in 0..workers.migration {
+        tokio::spawn(run_worker(state.clone(), KIND_MIGRATE_CURVE, worker));
+        tokio::spawn(run_worker(state.clone(), KIND_MIGRATE_CIRCUIT, worker));
+    }
+    for worker in 0..workers.verify {
+        tokio::spawn(run_worker(state.clone(), KIND_VERIFY_DATASET, worker));
+    }
+    Ok(())
+}
+
+async fn run_worker(state: AppState, kind: &'static str, worker: usize) {
+    loop {
+        if state.shutdown.is_cancelled() {
+            return;
+        }
+        // Nothing is proven until the ZK self-test has passed with the loaded keys.
+        if kind != KIND_QUERY && !state.zk_ready() {
+            let _ = tokio::time::timeout(IDLE_POLL, state.jobs_notify.notified()).await;
+            continue;
+        }
+
+        let per_tenant_limit = match kind {
+            KIND_PROVE_DATASET => quota::quotas().max_concurrent_proving,
+            _ => None,
+        };
+        let job = match state.store.claim_next_job(kind, &state.instance_id, per_tenant_limit).await {
+            Ok(Some(job)) => job,
+            Ok(None) => {
+                let _ = tokio::time::timeout(IDLE_POLL, state.jobs_notify.notified()).await;
+                continue;
+            }
+            Err(e) => {
+                tracing::warn!(worker, error = %e, "failed to claim job");
+                tokio::time::sleep(IDLE_POLL).await;
+                continue;
+            }
+        };
+
+        let heartbeat = tokio::spawn(heartbeat(state.clone(), job.id));
+        // Only dataset proving stops early on shutdown; other jobs run on or are requeued on
+        // the next start.
+        let res = match job.kind.as_str() {
+            KIND_PROVE_DATASET => crate::dataset::run_prove_job(&state, job.subject_id).await,
+            KIND_QUERY => crate::query::run_async_query(&state, job.subject_id).await.map(|()| JobEnd::Finished),
+            KIND_PROVE_AGGREGATE => crate::aggregate::run_prove_job(&state, job.subject_id).await.map(|()| JobEnd::Finished),
+            KIND_MIGRATE_CURVE => crate::curve_migration::run_migrate_job(&state, job.subject_id).await.map(|()| JobEnd::Finished),
+            KIND_MIGRATE_CIRCUIT => crate::circuit_migration::run_migrate_job(&state, job.subject_id).await.map(|()| JobEnd::Finished),
+            KIND_VERIFY_DATASET => crate::reverify::run_verify_job(&state, job.subject_id).await.map(|()| JobEnd::Finished),
+            other => Err(ApiError::BadRequest(format!("unknown job kind '{other}'"))),
+        };
+        heartbeat.abort();
+
+        if let Ok(JobEnd::Interrupted) = res {
+            match state.store.requeue_job(job.id).await {
+                Ok(()) => tracing::info!(worker, job_id = %job.id, kind = %job.kind, "job interrupted by shutdown; requeued"),
+                Err(e) => tracing::warn!(worker, job_id = %job.id, error = %e, "failed to requeue interrupted job"),
+            }
+            continue;
+        }
+        let error = res.err().map(|e| format!("{e}"));
+        if let Some(error) = &error {
+            tracing::warn!(worker, job_id = %job.id, kind = %job.kind, error, "job failed");
+        }
+        if let Err(e) = state.store.finish_job(job.id, error.as_deref()).await {
+            tracing::warn!(worker, job_id = %job.id, error = %e, "failed to record job outcome");
+        }
+        if kind == KIND_PROVE_DATASET {
+            // A finished proving job may unblock another job of the same tenant.
+            state.jobs_notify.notify_waiters();
+        }
+    }
+}
+
+/// Keep a running job's heartbeat fresh until aborted.
+async fn heartbeat(state: AppState, job_id: Uuid) {
+    let mut interval = tokio::time::interval(HEARTBEAT_INTERVAL);
+    interval.tick().await;
+    loop {
+        interval.tick().await;
+        if let Err(e) = state.store.heartbeat_job(job_id).await {
+            tracing::warn!(%job_id, error = %e, "failed to refresh job heartbeat");
+        }
+    }
+}
+
+/// Background loop: requeue jobs left running by instances that stopped heartbeating.
+async fn requeue_stale(state: AppState) {
+    let mut interval = tokio::time::interval(HEARTBEAT_INTERVAL);
+    loop {
+        interval.tick().await;
+        if state.shutdown.is_cancelled() {
+            return;
+        }
+        match state.store.requeue_running_jobs(None, stale_before()).await {
+            Ok(0) => {}
+            Ok(requeued) => {
+                tracing::warn!(requeued, "requeued jobs of an instance that stopped heartbeating");
+                state.jobs_notify.notify_waiters();
+            }
+            Err(e) => tracing::warn!(error = %e, "failed to requeue stale jobs"),
+        }
+    }
+}
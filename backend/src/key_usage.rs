@@ -0,0 +1,151 @@
+//! Proving key usage accounting and rotation reminders.
+//!
+//! Every proof this ledger makes is counted against its key (`key_id`, the hex SHA-256 of the
+//! verifying key), along with the datasets the key has proven. A key is due for rotation once it
+//! is older than `ZK_KEY_MAX_AGE_DAYS` (counted from its first proof here) or has made more than
+//! `ZK_KEY_MAX_PROOFS` proofs; an unset threshold never fires. Due keys are logged as they cross
+//! the proof limit and start proving each further dataset, flagged by `phl_zk_key_rotation_due` in `GET /metrics` and named
+//! in the `X-Key-Rotation-Due` header of `GET /api/v1/zk/keys` and `GET /api/v1/zk/vk`. Rotating
+//! means replacing the key files and re-proving with `POST /api/v1/admin/circuit-migrations`.
+
+use crate::db::KeyUsageRow;
+use crate::errors::ApiError;
+use crate::models::ZkKeyUsage;
+use crate::state::AppState;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fmt::Write;
+use uuid::Uuid;
+
+/// Response header naming the (comma-separated) due keys a response involves.
+pub const ROTATION_HEADER: &str = "X-Key-Rotation-Due";
+
+/// Shard circuit on BN254.
+pub const CIRCUIT_SHARD: &str = "shard";
+/// Shard circuit on BLS12-381 (curve migrations).
+pub const CIRCUIT_SHARD_BLS: &str = "shard-bls12-381";
+pub const CIRCUIT_AGGREGATE: &str = "aggregate";
+pub const CIRCUIT_QUERY: &str = "query";
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct RotationThresholds {
+    pub max_age_days: Option<u64>,
+    pub max_proofs: Option<u64>,
+}
+
+fn env_threshold(name: &str) -> Option<u64> {
+    std::env::var(name).ok().and_then(|v| v.parse().ok()).filter(|n| *n > 0)
+}
+
+pub fn thresholds() -> RotationThresholds {
+    RotationThresholds {
+        max_age_days: env_threshold("ZK_KEY_MAX_AGE_DAYS"),
+        max_proofs: env_threshold("ZK_KEY_MAX_PROOFS"),
+    }
+}
+
+/// Whole days since the key's first proof here.
+fn age_days(usage: &KeyUsageRow, now: DateTime<Utc>) -> u64 {
+    (now - usage.first_seen_at).num_days().max(0) as u64
+}
+
+/// Why the key with `usage` is due for rotation under `thresholds`; empty if it isn't.
+pub fn rotation_reasons(thresholds: &RotationThresholds, usage: &KeyUsageRow, now: DateTime<Utc>) -> Vec<String> {
+    let mut reasons = Vec::new();
+    if let Some(max) = thresholds.max_age_days {
+        let age = age_days(usage, now);
+        if age >= max {
+            reasons.push(format!("key is {age} days old (limit {max})"));
+        }
+    }
+    if let Some(max) = thresholds.max_proofs
+        && usage.proofs_created > max
+    {
+        reasons.push(format!("key made {} proofs (limit {max})", usage.proofs_created));
+    }
+    reasons
+}
+
+/// Count a `circuit` proof made with `key_id` for `dataset_id`, warning when the key crosses its
+/// proof limit and whenever a due key starts proving another dataset.
+pub async fn record_proof(state: &AppState, key_id: &str, circuit: &str, dataset_id: Uuid) -> Result<(), ApiError> {
+    let new_dataset = state.store.record_key_proof(key_id, circuit, dataset_id).await?;
+    let thresholds = thresholds();
+    for usage in state.store.list_key_usage(Some(key_id)).await? {
+        let crossed = thresholds.max_proofs.is_some_and(|max| usage.proofs_created == max + 1);
+        let reasons = rotation_reasons(&thresholds, &usage, Utc::now());
+        if (new_dataset || crossed) && !reasons.is_empty() {
+            tracing::warn!(
+                key_id,
+                circuit,
+                %dataset_id,
+                reasons = reasons.join("; "),
+                "proving key is due for rotation; plan a circuit migration"
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Usage of every key that has made a proof on this ledger.
+pub async fn key_usage(state: &AppState) -> Result<Vec<ZkKeyUsage>, ApiError> {
+    let thresholds = thresholds();
+    let now = Utc::now();
+    Ok(state.store.list_key_usage(None)
+        .await?
+        .into_iter()
+        .map(|usage| {
+            let rotation_reasons = rotation_reasons(&thresholds, &usage, now);
+            ZkKeyUsage {
+                age_days: age_days(&usage, now),
+                rotation_due: !rotation_reasons.is_empty(),
+                rotation_reasons,
+                key_id: usage.key_id,
+                circuit: usage.circuit,
+                first_seen_at: usage.first_seen_at,
+                last_proof_at: usage.last_proof_at,
+                proofs_created: usage.proofs_created,
+                datasets_covered: usage.datasets_covered,
+            }
+        })
+        .collect())
+}
+
+/// Those of `key_ids` that are due for rotation.
+pub async fn due_keys(state: &AppState, key_ids: &[String]) -> Result<Vec<String>, ApiError> {
+    let thresholds = thresholds();
+    if thresholds.max_age_days.is_none() && thresholds.max_proofs.is_none() {
+        return Ok(Vec::new());
+    }
+    let mut due = Vec::new();
+    for key_id in key_ids {
+        for usage in state.store.list_key_usage(Some(key_id)).await? {
+            if !rotation_reasons(&thresholds, &usage, Utc::now()).is_empty() {
+                due.push(usage.key_id);
+            }
+        }
+    }
+    Ok(due)
+}
+
+type KeyValue = fn(&ZkKeyUsage) -> u64;
+
+/// Key usage in the Prometheus text exposition format.
+pub async fn metrics_text(state: &AppState) -> Result<String, ApiError> {
+    let keys = key_usage(state).await?;
+    let mut out = String::new();
+    let families: [(&str, &str, &str, KeyValue); 4] = [
+        ("phl_zk_key_proofs_total", "counter", "Proofs made with the key.", |k| k.proofs_created),
+        ("phl_zk_key_datasets", "gauge", "Datasets the key has made proofs for.", |k| k.datasets_covered),
+        ("phl_zk_key_age_days", "gauge", "Days since the key's first proof on this ledger.", |k| k.age_days),
+        ("phl_zk_key_rotation_due", "gauge", "1 if the key exceeds an age or usage threshold.", |k| k.rotation_due as u64),
+    ];
+    for (name, kind, help, value) in families {
+        let _ = writeln!(out, "# HELP {name} {help}");
+        let _ = writeln!(out, "# TYPE {name} {kind}");
+        for key in &keys {
+            let _ = writeln!(out, "{name}{{key_id=\"{}\",circuit=\"{}\"}} {}", key.key_id, key.circuit, value(key));
+        }
+    }
+    Ok(out)
+}
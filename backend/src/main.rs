@@ -1,44 +1,230 @@
+mod acl;
+mod admission;
+mod anchoring;
+mod anomaly;
+mod aggregate;
 mod api;
+mod api_keys;
+mod archive;
+mod audit;
+mod auth;
+mod backup;
+mod bucket_counts;
+mod ceremony;
+mod chain;
+mod checkpoint;
+mod circuit_migration;
+mod cohort;
+mod config;
+mod curve_migration;
 mod dataset;
+mod deadline;
 mod db;
+mod dp;
+mod ephemeral;
 mod errors;
+mod export;
+mod federated;
+mod generator;
+mod jobs;
+mod key_usage;
+mod mirror;
 mod models;
+mod notify;
+mod oidc;
+mod pg;
+mod pk_download;
+mod policy;
+mod progress;
+mod proof_files;
+mod query;
+mod selftest;
+mod service;
+mod share;
+mod shutdown;
+mod quality;
+mod quota;
+mod rate_limit;
+mod receipt;
+mod retention;
+mod reverify;
+mod salt;
 mod state;
+mod store;
+mod stream;
+mod summary;
+mod telemetry;
+mod transparency;
+mod upload;
 
 use crate::errors::ApiError;
 use crate::state::AppState;
+use crate::store::PgStore;
 use std::path::PathBuf;
-use tracing_subscriber::EnvFilter;
+use std::sync::Arc;
 
 #[tokio::main]
 async fn main() -> Result<(), ApiError> {
-    tracing_subscriber::fmt()
-        .with_env_filter(EnvFilter::from_default_env().add_directive("info".parse().unwrap()))
-        .init();
+    let telemetry = telemetry::init();
+    let config = config::load()?;
 
-    // Store local state under backend/data (ignored by git).
-    let data_dir = PathBuf::from("data");
+    // Store local state under `data_dir` (default backend/data, ignored by git), or in memory and
+    // a temporary directory in ephemeral mode.
+    let ephemeral_dir = if ephemeral::enabled() {
+        Some(ephemeral::EphemeralDir::create().map_err(|_| ApiError::Internal)?)
+    } else {
+        None
+    };
+    let data_dir = match &ephemeral_dir {
+        Some(dir) => dir.path().to_path_buf(),
+        None => config.data_dir.clone(),
+    };
     std::fs::create_dir_all(&data_dir).map_err(|_| ApiError::Internal)?;
+    dataset::wipe_orphaned_spools(&data_dir);
 
-    let db_path = data_dir.join("ledger.sqlite");
-    let db_url = format!("sqlite:{}", db_path.to_string_lossy());
+    // `database_url` picks where the ledger lives: a `sqlite:` URL replaces the default file, a
+    // `postgres://` URL moves the ledger to Postgres while jobs and other operational tables stay
+    // in the default file. Ephemeral mode ignores it.
+    let database_url = config.database_url.clone().filter(|_| ephemeral_dir.is_none());
+    let pg_url = database_url.as_deref().filter(|url| pg::is_postgres_url(url));
+    let db_url = match (&ephemeral_dir, database_url.as_deref()) {
+        (Some(_), _) => ephemeral::MEMORY_DB_URL.to_string(),
+        (None, Some(url)) if url.starts_with("sqlite:") => url.to_string(),
+        (None, _) => format!("sqlite:{}", data_dir.join("ledger.sqlite").to_string_lossy()),
+    };
+
+    // Admin commands: `backup [DEST]`, `restore SRC [--sample N] [--verify-only]`, `ceremony ...`.
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if matches!(args.first().map(String::as_str), Some("backup" | "restore")) && pg_url.is_some() {
+        return Err(ApiError::BadRequest(
+            "backup and restore cover the SQLite ledger only; back up a Postgres ledger with pg_dump".to_string(),
+        ));
+    }
+    if args.first().map(String::as_str) == Some("ceremony") {
+        if ephemeral_dir.is_some() {
+            return Err(ApiError::BadRequest("ceremony is not available in ephemeral mode".to_string()));
+        }
+        return ceremony::run_cli(&args[1..], &data_dir);
+    }
+    if args.first().map(String::as_str) == Some("restore") {
+        if ephemeral_dir.is_some() {
+            return Err(ApiError::BadRequest("restore is not available in ephemeral mode".to_string()));
+        }
+        return run_restore(&args[1..], &data_dir).await;
+    }
 
     let db = db::connect(&db_url).await?;
     db::init_schema(&db).await?;
 
-    let state = AppState::new(db, data_dir);
+    if args.first().map(String::as_str) == Some("backup") {
+        let dest = args
+            .get(1)
+            .map(PathBuf::from)
+            .unwrap_or_else(|| backup::backups_dir(&data_dir).join(chrono::Utc::now().format("%Y%m%dT%H%M%SZ").to_string()));
+        let manifest = backup::create_backup(&db, &proof_files::ProofFiles::new(&data_dir), &data_dir, &dest).await?;
+        println!("{}", serde_json::to_string_pretty(&manifest).map_err(|_| ApiError::Internal)?);
+        tracing::info!(dest = %dest.display(), "backup written");
+        return Ok(());
+    }
 
-    let app = api::router(state);
+    let salt_sealer = salt::SaltSealer::load_or_create(&data_dir.join("keys"))?;
+    let signing_key = export::signing_key(&data_dir)?;
+    let addr = config.bind_addr;
+    let instance_id = jobs::instance_id(&data_dir)?;
+    let mut state = AppState::new(db, data_dir, salt_sealer, signing_key)
+        .with_config(config)
+        .with_instance_id(instance_id);
+    db::move_proof_blobs_to_files(&state.db, &state.proof_files).await?;
+    if let Some(url) = pg_url {
+        let pg = pg::connect(url).await?;
+        pg::init_schema(&pg).await?;
+        tracing::info!("ledger stored in Postgres");
+        state = state.with_store(Arc::new(PgStore::new(pg)));
+    }
+    if let Some(dir) = &ephemeral_dir {
+        tracing::warn!(data_dir = %dir.path().display(), "ephemeral mode: in-memory DB, deterministic keys; nothing is kept");
+        state = state.with_key_seed(ephemeral::key_seed());
+    }
+
+    tokio::spawn(upload::run_gc(state.clone()));
+    tokio::spawn(audit::run(state.clone()));
+    tokio::spawn(retention::run(state.clone()));
+    tokio::spawn(anomaly::run(state.clone()));
+    tokio::spawn(anchoring::run(state.clone()));
+    tokio::spawn(reverify::run_sampling(state.clone()));
+    jobs::start(state.clone()).await?;
+
+    let selftest_state = state.clone();
+    tokio::spawn(async move {
+        selftest::run(&selftest_state).await;
+        selftest_state.jobs_notify.notify_waiters();
+    });
 
-    let addr = std::env::var("BACKEND_ADDR").unwrap_or_else(|_| "127.0.0.1:8080".to_string());
+    let shutdown_state = state.clone();
+    let app = api::router(state);
 
-    let listener = tokio::net::TcpListener::bind(&addr)
+    let listener = tokio::net::TcpListener::bind(addr)
         .await
         .map_err(|_| ApiError::Internal)?;
 
     tracing::info!(%addr, "backend listening");
 
-    axum::serve(listener, app).await.map_err(|_| ApiError::Internal)?;
+    // On SIGTERM/SIGINT: stop accepting connections, let requests and proving runs wind down
+    // (runs checkpoint and requeue their jobs), bounded by the grace period (see `shutdown`).
+    let (stop, stopped) = tokio::sync::oneshot::channel::<()>();
+    let mut server = tokio::spawn(async move {
+        axum::serve(listener, app)
+            .with_graceful_shutdown(async move {
+                let _ = stopped.await;
+            })
+            .await
+    });
+    tokio::select! {
+        res = &mut server => {
+            res.map_err(|_| ApiError::Internal)?.map_err(|_| ApiError::Internal)?;
+        }
+        () = shutdown::signal() => {}
+    }
+    let deadline = shutdown::begin(&shutdown_state);
+    let _ = stop.send(());
+    if let Some(dir) = ephemeral_dir {
+        // Nothing is kept, so don't wait for in-flight requests, key setup or proving to finish.
+        telemetry.shutdown();
+        drop(dir);
+        std::process::exit(0);
+    }
+    if tokio::time::timeout_at(deadline, &mut server).await.is_err() {
+        tracing::warn!("connections still open at the shutdown deadline; closing them");
+    }
+    shutdown::wait_for_proving_runs(&shutdown_state, deadline).await;
+    telemetry.shutdown();
+    // Blocking tasks (key setup, proofs) would otherwise hold up the runtime's shutdown.
+    std::process::exit(0)
+}
+
+/// Verify a backup and swap it in. Must run with the server stopped.
+async fn run_restore(args: &[String], data_dir: &std::path::Path) -> Result<(), ApiError> {
+    let usage = || ApiError::BadRequest("usage: restore SRC [--sample N] [--verify-only]".to_string());
+
+    let mut src = None;
+    let mut sample = backup::DEFAULT_VERIFY_SAMPLE;
+    let mut verify_only = false;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--sample" => sample = args.next().and_then(|n| n.parse().ok()).ok_or_else(usage)?,
+            "--verify-only" => verify_only = true,
+            other if src.is_none() => src = Some(PathBuf::from(other)),
+            _ => return Err(usage()),
+        }
+    }
+    let src = src.ok_or_else(usage)?;
 
+    let report = backup::restore_backup(&src, data_dir, sample, verify_only).await?;
+    println!("{}", serde_json::to_string_pretty(&report).map_err(|_| ApiError::Internal)?);
+    if !report.healthy {
+        tracing::error!(problems = report.problems.len(), "backup failed verification; nothing restored");
+        std::process::exit(1);
+    }
     Ok(())
 }
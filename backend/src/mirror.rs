@@ -0,0 +1,308 @@
+//! Read-through mirror of a remote ledger.
+//!
+//! With `MIRROR_UPSTREAM_URL` set, a dataset unknown locally is fetched from the upstream
+//! instance's public endpoints (dataset, manifest, verifying key, shards with proofs) on first
+//! access. Every proof, the key id and the commitment chain are re-verified before the dataset is
+//! cached; afterwards it is served from the local DB like an imported dataset
+//! (`imported_from` = `mirror:<upstream>`), with the upstream's consent scope, approval
+//! requirement and release limit. Only datasets that are `ready` upstream are cached.
+//!
+//! A proof only means something under a verifying key nobody holds the trapdoor of, so the
+//! upstream's key isn't taken on its word: it must be one of `MIRROR_TRUSTED_KEY_IDS`
+//! (comma-separated hex SHA-256 key ids, as in manifests and `/api/v1/zk/keys`). With no key ids
+//! configured, mirroring is refused.
+//!
+//! Lookups are public, so fetching is bounded: concurrent lookups of one id share a fetch, at most
+//! `MIRROR_MAX_CONCURRENT_FETCHES` (default 4) fetches run at once (more are answered `429`), and
+//! an id the upstream doesn't know, or whose proofs fail verification, isn't asked for again for
+//! `MIRROR_MISS_TTL_SECS` (default 300).
+
+use crate::dataset::parse_field_hex;
+use crate::db::DatasetRow;
+use crate::deadline::Cancellation;
+use crate::errors::ApiError;
+use crate::export::{self, ImportCandidate};
+use crate::models::{DatasetGetResponse, DatasetStatus, ShardListResponse, ZkVkResponse};
+use crate::state::AppState;
+use base64::Engine;
+use serde::de::DeserializeOwned;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::sync::{Arc, LazyLock, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::Semaphore;
+use uuid::Uuid;
+use zk_proofs::types::ShardStats;
+
+/// One lock per dataset being fetched, so concurrent requests for it cache it once.
+static FETCH_LOCKS: LazyLock<Mutex<HashMap<Uuid, Arc<tokio::sync::Mutex<()>>>>> = LazyLock::new(Default::default);
+
+/// Upstream fetches in progress, across all datasets.
+static FETCH_SLOTS: LazyLock<Semaphore> = LazyLock::new(|| Semaphore::new(max_concurrent_fetches()));
+
+/// Ids recently not found (or not verifiable) upstream, with when that was learned.
+static MISSES: LazyLock<Mutex<HashMap<Uuid, Instant>>> = LazyLock::new(Default::default);
+
+/// Cap on remembered misses; the oldest is forgotten first.
+const MAX_MISSES: usize = 10_000;
+
+/// Upstream shard pages are capped at this size.
+const SHARD_PAGE: u64 = 500;
+
+const UPSTREAM_TIMEOUT: Duration = Duration::from_secs(30);
+
+pub fn upstream() -> Option<String> {
+    std::env::var("MIRROR_UPSTREAM_URL")
+        .ok()
+        .map(|u| u.trim_end_matches('/').to_string())
+        .filter(|u| !u.is_empty())
+}
+
+fn trusted_key_ids() -> Vec<String> {
+    std::env::var("MIRROR_TRUSTED_KEY_IDS")
+        .map(|v| {
+            v.split(',')
+                .map(|s| s.trim().to_ascii_lowercase())
+                .filter(|s| !s.is_empty())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+fn max_concurrent_fetches() -> usize {
+    std::env::var("MIRROR_MAX_CONCURRENT_FETCHES")
+        .ok()
+        .and_then(|v| v.trim().parse().ok())
+        .filter(|n: &usize| *n > 0)
+        .unwrap_or(4)
+}
+
+fn miss_ttl() -> Duration {
+    Duration::from_secs(
+        std::env::var("MIRROR_MISS_TTL_SECS")
+            .ok()
+            .and_then(|v| v.trim().parse().ok())
+            .unwrap_or(300),
+    )
+}
+
+fn recently_missed(dataset_id: Uuid) -> Result<bool, ApiError> {
+    let mut misses = MISSES.lock().map_err(|_| ApiError::Internal)?;
+    match misses.get(&dataset_id) {
+        Some(at) if at.elapsed() < miss_ttl() => Ok(true),
+        Some(_) => {
+            misses.remove(&dataset_id);
+            Ok(false)
+        }
+        None => Ok(false),
+    }
+}
+
+fn record_miss(dataset_id: Uuid) -> Result<(), ApiError> {
+    let ttl = miss_ttl();
+    let mut misses = MISSES.lock().map_err(|_| ApiError::Internal)?;
+    if misses.len() >= MAX_MISSES {
+        misses.retain(|_, at| at.elapsed() < ttl);
+    }
+    if misses.len() >= MAX_MISSES
+        && let Some(oldest) = misses.iter().min_by_key(|(_, at)| **at).map(|(id, _)| *id)
+    {
+        misses.remove(&oldest);
+    }
+    misses.insert(dataset_id, Instant::now());
+    Ok(())
+}
+
+/// The fetch lock for `dataset_id`, shared by everyone fetching it right now.
+fn fetch_lock(dataset_id: Uuid) -> Result<Arc<tokio::sync::Mutex<()>>, ApiError> {
+    let mut locks = FETCH_LOCKS.lock().map_err(|_| ApiError::Internal)?;
+    Ok(locks.entry(dataset_id).or_default().clone())
+}
+
+/// Look up a dataset, fetching and caching it from the upstream when mirroring is enabled.
+/// `None` if neither this instance nor the upstream knows it.
+pub async fn load_dataset(state: &AppState, dataset_id: Uuid) -> Result<Option<DatasetRow>, ApiError> {
+    if let Some(dataset) = state.store.get_dataset(dataset_id).await? {
+        return Ok(Some(dataset));
+    }
+    let Some(upstream) = upstream() else {
+        return Ok(None);
+    };
+    // Deleted here: don't bring it back from the upstream.
+    if state.store.dataset_tombstone(dataset_id).await?.is_some() {
+        return Ok(None);
+    }
+
+    if recently_missed(dataset_id)? {
+        return Ok(None);
+    }
+
+    let lock = fetch_lock(dataset_id)?;
+    let _guard = lock.lock().await;
+    let result = fetch_and_register(state, &upstream, dataset_id).await;
+    // Whoever got here first fetched (or missed) it; later holders find it in the DB or misses.
+    if let Ok(mut locks) = FETCH_LOCKS.lock() {
+        locks.remove(&dataset_id);
+    }
+    result
+}
+
+async fn fetch_and_register(state: &AppState, upstream: &str, dataset_id: Uuid) -> Result<Option<DatasetRow>, ApiError> {
+    if let Some(dataset) = state.store.get_dataset(dataset_id).await? {
+        return Ok(Some(dataset));
+    }
+    if recently_missed(dataset_id)? {
+        return Ok(None);
+    }
+    let trusted = trusted_key_ids();
+    if trusted.is_empty() {
+        return Err(ApiError::Forbidden(
+            "mirroring is disabled: set MIRROR_TRUSTED_KEY_IDS to the upstream's verifying key ids".to_string(),
+        ));
+    }
+    let Ok(_slot) = FETCH_SLOTS.try_acquire() else {
+        return Err(ApiError::RateLimited {
+            message: "too many upstream mirror fetches in progress".to_string(),
+            retry_after_secs: 1,
+        });
+    };
+
+    let Some(candidate) = fetch(upstream, dataset_id, &trusted).await? else {
+        record_miss(dataset_id)?;
+        return Ok(None);
+    };
+
+    let (candidate, verified) = tokio::task::spawn_blocking(move || {
+        let verified = export::verify_dataset(&candidate, &Cancellation::none());
+        (candidate, verified)
+    })
+    .await
+    .map_err(|_| ApiError::Internal)?;
+    let shards_verified = match verified {
+        Ok(n) => n,
+        Err(e) => {
+            tracing::warn!(%dataset_id, %upstream, error = %e, "upstream dataset failed verification");
+            record_miss(dataset_id)?;
+            return Err(ApiError::Upstream(format!("upstream dataset failed verification: {e}")));
+        }
+    };
+
+    let imported_from = format!("mirror:{upstream}");
+    if let Err(e) = export::register(state, &candidate, &imported_from, "mirror").await {
+        let _ = state.store.set_dataset_failed(dataset_id, &format!("{e}")).await;
+        return Err(e);
+    }
+    tracing::info!(%dataset_id, %upstream, shards_verified, "mirrored dataset");
+
+    state.store.get_dataset(dataset_id).await
+}
+
+async fn get_json<T: DeserializeOwned>(client: &reqwest::Client, url: &str) -> Result<Option<T>, ApiError> {
+    let res = client
+        .get(url)
+        .send()
+        .await
+        .map_err(|e| ApiError::Upstream(format!("{url}: {e}")))?;
+    if res.status() == reqwest::StatusCode::NOT_FOUND {
+        return Ok(None);
+    }
+    if !res.status().is_success() {
+        return Err(ApiError::Upstream(format!("{url}: HTTP {}", res.status())));
+    }
+    res.json()
+        .await
+        .map(Some)
+        .map_err(|e| ApiError::Upstream(format!("{url}: {e}")))
+}
+
+async fn fetch(upstream: &str, dataset_id: Uuid, trusted_key_ids: &[String]) -> Result<Option<ImportCandidate>, ApiError> {
+    let client = reqwest::Client::builder()
+        .timeout(UPSTREAM_TIMEOUT)
+        .build()
+        .map_err(|_| ApiError::Internal)?;
+    let base = format!("{upstream}/api/v1/datasets/{dataset_id}");
+
+    let Some(dataset) = get_json::<DatasetGetResponse>(&client, &base).await? else {
+        return Ok(None);
+    };
+    if !matches!(dataset.status, DatasetStatus::Ready) {
+        return Err(ApiError::Conflict("dataset is not ready on the upstream yet".to_string()));
+    }
+    let dataset_commitment_hex = dataset
+        .dataset_commitment_hex
+        .ok_or_else(|| ApiError::Upstream("upstream dataset has no commitment".to_string()))?;
+
+    let manifest = get_json::<serde_json::Value>(&client, &format!("{base}/manifest")).await?;
+    let vk = get_json::<ZkVkResponse>(&client, &format!("{upstream}/api/v1/zk/vk?dataset_id={dataset_id}"))
+        .await?
+        .ok_or_else(|| ApiError::Upstream("upstream has no verifying key for the dataset".to_string()))?;
+    // The id is hashed here rather than read from the response, which is the upstream's word.
+    let vk_bytes = base64::engine::general_purpose::STANDARD
+        .decode(&vk.vk_b64)
+        .map_err(|_| ApiError::Upstream("upstream verifying key is not valid base64".to_string()))?;
+    let key_id = hex::encode(Sha256::digest(&vk_bytes));
+    if !trusted_key_ids.contains(&key_id) {
+        tracing::warn!(%dataset_id, %upstream, %key_id, "upstream verifying key is not trusted");
+        record_miss(dataset_id)?;
+        return Err(ApiError::Upstream(format!("upstream verifying key {key_id} is not in MIRROR_TRUSTED_KEY_IDS")));
+    }
+
+    // `shards_total` is the upstream's claim, so nothing is reserved for it up front.
+    let mut shards = Vec::new();
+    while (shards.len() as u64) < dataset.shards_total {
+        let url = format!("{base}/shards?include_proof=true&offset={}&limit={SHARD_PAGE}", shards.len());
+        let page = get_json::<ShardListResponse>(&client, &url)
+            .await?
+            .ok_or_else(|| ApiError::Upstream("upstream shards disappeared".to_string()))?;
+        if page.shards.is_empty() {
+            break;
+        }
+        for shard in page.shards {
+            let proof_b64 = shard
+                .proof_b64
+                .ok_or_else(|| ApiError::Upstream(format!("upstream shard {} has no proof", shard.shard_index)))?;
+            let salt_commitment = match shard.salt_commitment_hex.as_deref() {
+                Some(hex) => Some(parse_field_hex(hex).ok_or_else(|| {
+                    ApiError::Upstream(format!("upstream shard {} has an invalid salt commitment", shard.shard_index))
+                })?),
+                None => None,
+            };
+            let sha256_commitment = match shard.sha256_commitment_hex.as_deref() {
+                Some(h) => Some(hex::decode(h).ok().and_then(|b| <[u8; 32]>::try_from(b).ok()).ok_or_else(|| {
+                    ApiError::Upstream(format!("upstream shard {} has an invalid sha256 commitment", shard.shard_index))
+                })?),
+                None => None,
+            };
+            let stats = ShardStats {
+                sum_glucose_by_bucket: shard.sum_glucose_by_bucket,
+                count_by_bucket: shard.count_by_bucket,
+                extra_sums_by_bucket: shard.extra_sums_by_bucket,
+                sum_glucose_sq_by_bucket: shard.sum_glucose_sq_by_bucket,
+                glucose_histogram_by_bucket: shard.glucose_histogram_by_bucket,
+                salt_commitment,
+                sha256_commitment,
+                glucose_bounds: shard.glucose_bounds,
+            };
+            shards.push((shard.shard_index, shard.shard_commitment_hex, stats, proof_b64));
+        }
+    }
+
+    Ok(Some(ImportCandidate {
+        dataset_id,
+        dataset_size: dataset.dataset_size,
+        shard_size: dataset.shard_size,
+        field_set: dataset.field_set,
+        chain_hash: dataset.chain_hash,
+        sha256_commitment: dataset.sha256_commitment,
+        num_buckets: dataset.num_buckets,
+        age_buckets: dataset.age_buckets,
+        window_shards: dataset.window_shards,
+        consent_scope: dataset.consent_scope,
+        requires_approval: dataset.requires_approval,
+        release_limit: dataset.release_limit,
+        dataset_commitment_hex,
+        manifest,
+        vk_b64: vk.vk_b64,
+        shards,
+    }))
+}
@@ -1,7 +1,10 @@
+use crate::chain::ChainHash;
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
-use zk_proofs::constants::{AGE_BUCKETS, NUM_BUCKETS};
+use std::collections::BTreeMap;
+use zk_proofs::constants::NUM_GLUCOSE_RANGES;
+use zk_proofs::types::{AgeBuckets, Curve, FieldSet, Measurement, ShardStats};
 
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
@@ -9,14 +12,63 @@ pub enum DatasetStatus {
     Generating,
     Ready,
     Failed,
+    /// Generation was stopped on request; the shards proven before are kept.
+    Cancelled,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct DatasetCreateRequest {
     /// Total number of synthetic records to commit.
     ///
-    /// Must be a multiple of the shard size (1000 in the default build).
+    /// Must be a multiple of the shard size.
     pub dataset_size: Option<u64>,
+
+    /// Records per shard; one of the sizes with a compiled circuit (100, 1000, 5000).
+    /// Defaults to 1000.
+    pub shard_size: Option<u64>,
+
+    /// Purposes the data subjects consented to (e.g. `["research-diabetes"]`).
+    ///
+    /// When set, queries must declare a matching `purpose`. Omit for unrestricted datasets.
+    pub consent_scope: Option<Vec<String>>,
+
+    /// Sensitive cohort: every query is held until an approver releases it.
+    pub requires_approval: Option<bool>,
+
+    /// Max distinct aggregates released per `RELEASE_WINDOW_SECS`. Defaults to
+    /// `RELEASE_LIMIT_PER_WINDOW` (unlimited if unset).
+    pub release_limit: Option<u64>,
+
+    /// Synthetic generator name (see `GET /api/v1/generators`). Defaults to `uniform`.
+    pub generator: Option<String>,
+
+    /// Parameters for a configurable generator, overriding its defaults (listed by
+    /// `GET /api/v1/generators`), e.g. `{"slope_per_year": 0.6, "sd_per_year": 0.2}` for
+    /// `age_correlated`. Recorded in the manifest.
+    pub generator_params: Option<serde_json::Value>,
+
+    /// Measurements per record: `glucose` (default) or `vitals` (glucose, systolic blood
+    /// pressure, heart rate and BMI). Each field set has its own circuit and keys.
+    pub field_set: Option<FieldSet>,
+
+    /// Hash chaining shard commitments into the dataset commitment: `poseidon` (SNARK-friendly),
+    /// `sha256` or `blake3`. Defaults to `DATASET_CHAIN_HASH` (`poseidon` if unset).
+    pub chain_hash: Option<ChainHash>,
+
+    /// Dual-commitment mode: every shard also gets a SHA-256 commitment over its canonical record
+    /// encoding, bound to the Poseidon commitment by the proof, for systems that anchor plain
+    /// hashes. Uses separate, larger circuits (slower proving). Defaults to false.
+    pub sha256_commitment: Option<bool>,
+
+    /// Age buckets as inclusive `(min_age, max_age)` pairs, in order, covering 0..=120 without
+    /// gaps or overlaps (at most 24). Defaults to the six standard buckets. Each layout has its own
+    /// circuit and keys.
+    pub buckets: Option<Vec<(u8, u8)>>,
+
+    /// Rolling window: only the last this many shards are queried and aggregated (e.g. `12` for
+    /// the last twelve monthly shards of a feed). Earlier shards are expired: kept, listed and
+    /// verifiable, but excluded. Omit to keep every shard live.
+    pub window_shards: Option<u64>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -30,12 +82,285 @@ pub struct DatasetGetResponse {
     pub created_at: DateTime<Utc>,
     pub dataset_size: u64,
     pub shard_size: u64,
+    /// Absent from instances that predate field sets, which are glucose-only.
+    #[serde(default)]
+    pub field_set: FieldSet,
+    /// Absent from instances that predate selectable chain hashes, which use Poseidon.
+    #[serde(default)]
+    pub chain_hash: ChainHash,
+    /// Dual-commitment dataset (see `DatasetCreateRequest::sha256_commitment`).
+    #[serde(default)]
+    pub sha256_commitment: bool,
     pub num_buckets: u64,
+    /// Inclusive age bounds of each bucket. Absent from instances that predate configurable
+    /// layouts, which use the default buckets.
+    #[serde(default)]
+    pub age_buckets: AgeBuckets,
     pub status: DatasetStatus,
     pub shards_total: u64,
     pub shards_done: u64,
     pub dataset_commitment_hex: Option<String>,
     pub error: Option<String>,
+    pub consent_scope: Option<Vec<String>>,
+    pub requires_approval: bool,
+    /// Effective release limit (dataset override or server default).
+    pub release_limit: Option<u64>,
+    /// Synthetic generator used; absent for uploaded datasets.
+    pub generator: Option<String>,
+    /// Parameters the generator was configured with; absent for its defaults.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub generator_params: Option<serde_json::Value>,
+    /// When an admin froze the dataset; a frozen commitment is final.
+    pub frozen_at: Option<DateTime<Utc>>,
+    /// Signer public key of the export this dataset was imported from; absent if proven here.
+    pub imported_from: Option<String>,
+    /// Rolling window in shards (see `DatasetCreateRequest::window_shards`); absent if every shard
+    /// is live.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub window_shards: Option<u64>,
+    /// Shards that expired from the rolling window.
+    #[serde(default)]
+    pub shards_expired: u64,
+    /// Curve `/shards` and `/zk/vk` serve by default: `bn254` until a curve migration's transition
+    /// window has passed.
+    #[serde(default)]
+    pub default_curve: Curve,
+    /// Dataset commitments on the curves the dataset was migrated to.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub curve_commitments: Vec<CurveCommitment>,
+    /// Warnings of the latest anomaly analysis (see `/anomalies`).
+    #[serde(default)]
+    pub anomaly_warnings: u64,
+    /// First external anchor of the transparency log covering the current commitment; absent
+    /// until one is published (see `anchoring`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub anchor: Option<DatasetAnchor>,
+}
+
+/// A published transparency log root whose tree contains a dataset commitment's leaf.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DatasetAnchor {
+    /// `opentimestamps`, `ethereum` or `webhook`.
+    pub method: String,
+    pub anchored_at: DateTime<Utc>,
+    pub tree_size: u64,
+    pub root_hash_hex: String,
+    /// Log leaf of the commitment; prove it into the root with
+    /// `GET /api/v1/log/proof?leaf_index=..&tree_size=..`.
+    pub leaf_index: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub txid: Option<String>,
+    /// What the anchor endpoint returned (an OpenTimestamps pending timestamp, a webhook reply).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub receipt_hex: Option<String>,
+}
+
+/// A dataset's commitment on a curve it was migrated to (over its shards re-proven there).
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CurveCommitment {
+    pub curve: Curve,
+    pub dataset_commitment_hex: String,
+    pub key_id: String,
+    pub migrated_at: DateTime<Utc>,
+    /// End of the transition window, until which `bn254` stays the default.
+    pub dual_serve_until: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DatasetFreezeResponse {
+    pub dataset_id: Uuid,
+    pub frozen: bool,
+    pub dataset_commitment_hex: Option<String>,
+    /// Hash of the audit entry recording the freeze/unfreeze.
+    pub audit_entry_hash: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DatasetCancelResponse {
+    pub dataset_id: Uuid,
+    pub status: DatasetStatus,
+    /// Shards proven when it was cancelled (a shard being proven may still be stored).
+    pub shards_done: u64,
+    pub shards_total: u64,
+    /// Hash of the audit entry recording the cancellation.
+    pub audit_entry_hash: String,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct ShareLinkRequest {
+    /// Lifetime of the link (default 7 days, at most `SHARE_LINK_MAX_TTL_SECS`).
+    #[serde(default)]
+    pub ttl_secs: Option<u64>,
+    /// Who the link is for, recorded in the audit chain.
+    #[serde(default)]
+    pub label: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ShareLinkResponse {
+    pub share_id: Uuid,
+    pub dataset_id: Uuid,
+    /// Bearer token for the `X-SHARE-TOKEN` header or the `share_token` query parameter.
+    pub token: String,
+    pub expires_at: DateTime<Utc>,
+    /// The endpoints the token opens.
+    pub endpoints: Vec<String>,
+    /// Hash of the audit entry recording the link.
+    pub audit_entry_hash: String,
+}
+
+/// What the ledger can vouch for about a dataset's proofs, for auditors.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DatasetVerificationReport {
+    pub dataset_id: Uuid,
+    pub generated_at: DateTime<Utc>,
+    pub status: String,
+    pub frozen_at: Option<DateTime<Utc>>,
+    pub chain_hash: ChainHash,
+    pub dataset_commitment_hex: Option<String>,
+    /// The commitment recomputed from the stored shard commitments.
+    pub recomputed_commitment_hex: String,
+    pub commitment_matches: bool,
+    /// SHA-256 of the verifying key the shard proofs check against (as `ledger-verify` reports it).
+    pub vk_key_id: Option<String>,
+    pub shards_total: u64,
+    pub shards_stored: u64,
+    /// Shards whose proof the ledger verified when storing it.
+    pub shards_verified: u64,
+    /// Bit `i % 8` of byte `i / 8` set if shard `i` is verified.
+    pub verified_bitmap_hex: String,
+    /// Shards that failed proving or verification (see `/failures`).
+    pub shard_failures: u64,
+    /// Whether the whole audit hash chain recomputes.
+    pub audit_chain_intact: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DatasetDeleteResponse {
+    pub dataset_id: Uuid,
+    pub deleted_at: DateTime<Utc>,
+    pub dataset_commitment_hex: Option<String>,
+    /// SHA-256 of the archive written before deletion; `None` if it wasn't archived.
+    pub archive_sha256: Option<String>,
+    /// Hash of the audit entry recording the deletion.
+    pub audit_entry_hash: String,
+}
+
+/// What the anomaly analyzer flagged (see `anomaly`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AnomalyKind {
+    /// A bucket's mean of a measurement lies outside its physiologically plausible range.
+    MeanOutOfRange,
+    /// A bucket is empty although the dataset-wide share of that bucket predicts many records.
+    EmptyBucket,
+    /// Every glucose value in a sizeable bucket is identical (zero variance).
+    ConstantGlucose,
+    /// The shard's bucket counts don't add up to the shard size.
+    CountMismatch,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AnomalyWarning {
+    pub kind: AnomalyKind,
+    pub shard_index: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bucket_index: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub field: Option<Measurement>,
+    /// The offending value: a mean, an expected count or a record count; absent when the bucket
+    /// is too small to release it (see `policy::suppressed_cells`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub observed: Option<f64>,
+    pub message: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DatasetAnomaliesResponse {
+    pub dataset_id: Uuid,
+    /// When the stored analysis ran; absent until the dataset is ready and first analyzed.
+    pub analyzed_at: Option<DateTime<Utc>>,
+    /// Commitment the analysis covers.
+    pub dataset_commitment_hex: Option<String>,
+    /// Verified shards analyzed; unverified shards are skipped.
+    pub shards_analyzed: u64,
+    pub warnings_total: u64,
+    /// The first `anomaly::MAX_STORED_WARNINGS` warnings, in shard order.
+    pub warnings: Vec<AnomalyWarning>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ApiKeyCreateRequest {
+    /// What the key is for, e.g. the person or service holding it.
+    pub name: String,
+    pub role: crate::auth::Role,
+    /// Limit the key to these scopes; omit for every scope of its role.
+    #[serde(default)]
+    pub scopes: Option<Vec<crate::auth::Scope>>,
+}
+
+/// An issued API key, without the key itself.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ApiKeyInfo {
+    /// Fingerprint, as in the audit chain and access lists.
+    pub key_id: String,
+    pub name: String,
+    pub role: crate::auth::Role,
+    /// `null` means every scope of the role.
+    pub scopes: Option<Vec<crate::auth::Scope>>,
+    pub created_by: String,
+    pub created_at: DateTime<Utc>,
+    pub revoked_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ApiKeyCreateResponse {
+    /// The key to present in `X-API-KEY`. It is not stored and can't be shown again.
+    pub key: String,
+    pub api_key: ApiKeyInfo,
+    pub audit_entry_hash: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ApiKeyListResponse {
+    pub keys: Vec<ApiKeyInfo>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ApiKeyRevokeResponse {
+    pub api_key: ApiKeyInfo,
+    pub audit_entry_hash: String,
+}
+
+/// A grantee on a dataset's access list: exactly one of an API key fingerprint (`key_id`, as in
+/// the audit chain) or a role. Body of a grant, query string of a revoke.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DatasetAccessGrant {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub role: Option<crate::auth::Role>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DatasetAclEntry {
+    #[serde(flatten)]
+    pub grantee: DatasetAccessGrant,
+    pub granted_by: String,
+    pub granted_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DatasetAclResponse {
+    pub dataset_id: Uuid,
+    pub owner_key_id: Option<String>,
+    /// Whether access is limited to the owner, admins and `entries`; otherwise every key may query
+    /// the dataset and its shards are public.
+    pub restricted: bool,
+    pub entries: Vec<DatasetAclEntry>,
+    /// Hash of the audit entry recording a grant or revoke.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub audit_entry_hash: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -44,6 +369,12 @@ pub enum Metric {
     Count,
     Sum,
     Mean,
+    /// Population variance, from the proven sum, sum of squares and count (blood glucose only).
+    Variance,
+    /// Square root of `Variance`.
+    Stddev,
+    /// Proven counts per blood glucose range (`GLUCOSE_RANGES`), blood glucose only.
+    Histogram,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -57,11 +388,174 @@ pub struct QueryRequest {
     pub dataset_id: Uuid,
     pub metric: Metric,
 
-    /// The prototype supports a single field: blood glucose.
+    /// Measurement to aggregate (`blood_glucose`, `systolic_bp`, `heart_rate` or `bmi`); it must
+    /// be in the dataset's field set.
+    pub field: String,
+
+    /// Filter: one of the configured buckets, or a range spanning consecutive buckets (from one
+    /// bucket's `min_age` to a later bucket's `max_age`), whose aggregates are added up. Optional
+    /// with `group_by` (every bucket).
+    pub age_range: Option<AgeRange>,
+
+    /// `age_bucket` answers every bucket of `age_range` separately, in one response. Not with
+    /// `complement`, `epsilon` or `async`, nor on datasets that require approval.
+    pub group_by: Option<GroupBy>,
+
+    /// Query everyone outside `age_range` instead: the answer adds up the aggregates of every
+    /// other bucket, listed in the response's `complement`. Not with `epsilon`.
+    #[serde(default)]
+    pub complement: bool,
+
+    /// Declared purpose of use. Its `category` is checked against the dataset's consent scope.
+    ///
+    /// Required when the server runs with `REQUIRE_QUERY_PURPOSE=true`.
+    pub purpose: Option<QueryPurpose>,
+
+    /// `async` queues the aggregation as a background job and returns `202` with a status
+    /// endpoint to poll. Defaults to `sync`.
+    pub mode: Option<QueryMode>,
+
+    /// Release a differentially private answer: `sum` and `count` get noise calibrated to this
+    /// privacy loss (split evenly between them), spent from the dataset's budget. `sum`, `count`
+    /// and `mean` queries only; sums and means of `blood_glucose` only.
+    pub epsilon: Option<f64>,
+
+    /// Noise for `epsilon`: `laplace` (default) or `gaussian` (with `DP_DELTA`).
+    pub dp_mechanism: Option<DpMechanism>,
+}
+
+/// Pooled aggregates of one measurement over several datasets (`POST /api/v1/queries/cohort`).
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CohortQueryRequest {
+    /// Datasets to pool (2 to 16, distinct): all ready, with the same age buckets, and measuring
+    /// `field`.
+    pub dataset_ids: Vec<Uuid>,
+    /// Measurement to aggregate, as in `QueryRequest`.
     pub field: String,
+    /// Declared purpose of use, checked against each dataset's consent scope.
+    pub purpose: Option<QueryPurpose>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CohortQueryResponse {
+    pub cohort_id: Uuid,
+    pub field: Measurement,
+    /// Per age bucket, the totals over every dataset.
+    pub buckets: Vec<CohortBucket>,
+    /// True only if every live shard of every dataset has been verified by the backend.
+    pub server_verified: bool,
+    pub datasets: Vec<CohortDataset>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CohortBucket {
+    pub bucket_index: usize,
+    pub bucket_range: (u8, u8),
+    /// `null` (with `count` and `mean`) when the bucket is `suppressed`.
+    pub sum: Option<u64>,
+    pub count: Option<u64>,
+    pub mean: Option<f64>,
+    /// Set when the pooled bucket, or any dataset's share of it, has fewer records than
+    /// `MIN_CELL_COUNT`: otherwise that dataset's own answer would reveal the other's share.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub suppressed: Option<QuerySuppression>,
+}
+
+/// One dataset of a cohort. Its share of each released bucket is recorded as a query of that
+/// dataset (`query_ids`, by bucket), which counts toward its release limit.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CohortDataset {
+    pub dataset_id: Uuid,
+    pub server_verified: bool,
+    pub shard_set: QueryShardSet,
+    pub shard_proofs_endpoint: String,
+    pub query_ids: Vec<CohortQueryId>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CohortQueryId {
+    pub bucket_index: usize,
+    pub query_id: Uuid,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DpMechanism {
+    #[default]
+    Laplace,
+    Gaussian,
+}
+
+/// Differential privacy requested for a query.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct DpParams {
+    pub epsilon: f64,
+    pub mechanism: DpMechanism,
+}
 
-    /// Filter: age range must match one of the configured buckets.
-    pub age_range: AgeRange,
+/// How a differentially private answer was noised.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DpRelease {
+    pub mechanism: DpMechanism,
+    pub epsilon: f64,
+    /// Gaussian mechanism only.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub delta: Option<f64>,
+    /// Largest change one record can make to the sum, and to the count.
+    pub sum_sensitivity: u64,
+    pub count_sensitivity: u64,
+    /// Noise scale of the sum and of the count: Laplace `b`, or Gaussian `sigma`.
+    pub sum_noise_scale: f64,
+    pub count_noise_scale: f64,
+    /// The dataset's epsilon budget left after this release; absent if uncapped.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub epsilon_remaining: Option<f64>,
+}
+
+/// Differential privacy spent on a dataset's noisy query releases.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PrivacyBudgetResponse {
+    pub dataset_id: Uuid,
+    /// `DP_EPSILON_BUDGET`; absent if uncapped.
+    pub epsilon_budget: Option<f64>,
+    pub epsilon_spent: f64,
+    pub epsilon_remaining: Option<f64>,
+    pub delta_spent: f64,
+    pub releases: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GroupBy {
+    AgeBucket,
+}
+
+/// Answer of a `group_by` query: one answer per bucket, each released, stored and signed as a
+/// query of its own, without a query proof (query a single bucket for one).
+#[derive(Debug, Serialize, Deserialize)]
+pub struct QueryBreakdownResponse {
+    pub dataset_id: Uuid,
+    pub group_by: GroupBy,
+    pub field: Measurement,
+    pub buckets: Vec<QueryResponse>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum QueryMode {
+    Sync,
+    Async,
+}
+
+/// Structured purpose-of-use declaration, stored with every query for traceability.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QueryPurpose {
+    /// Purpose category, e.g. `research-diabetes`.
+    pub category: String,
+    /// Identifier of the approved study this release belongs to.
+    pub study_id: String,
+    /// Ethics approval reference (IRB / REC number).
+    pub irb_reference: String,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -72,8 +566,35 @@ pub struct QueryResponse {
     pub bucket_index: usize,
     pub bucket_range: (u8, u8),
 
-    pub sum_glucose: u64,
-    pub count: u64,
+    /// The aggregated measurement; `sum` and `mean` are in its unit (BMI in tenths).
+    pub field: Measurement,
+    /// `null` (with every other aggregate) when the answer is `suppressed`.
+    pub sum: Option<u64>,
+    pub count: Option<u64>,
+    pub mean: Option<f64>,
+    /// Set when the bucket has fewer records than `MIN_CELL_COUNT`; nothing about it is released.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub suppressed: Option<QuerySuppression>,
+    /// Set for `variance` / `stddev` queries, with the sum of squares they derive from.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sum_sq: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub variance: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stddev: Option<f64>,
+    /// Set for `histogram` queries: the bucket's records per glucose range.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub histogram: Option<Vec<HistogramBin>>,
+
+    /// Set when the answer is differentially private: `sum`, `count` and `mean` are noisy, and
+    /// there is no query proof (it would reveal the exact answer).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dp: Option<DpRelease>,
+
+    /// Same as `sum` / `mean`, kept for glucose queries only.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sum_glucose: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub mean_glucose: Option<f64>,
 
     /// Indicates whether all shard proofs backing this dataset have been verified by the backend.
@@ -81,15 +602,290 @@ pub struct QueryResponse {
 
     /// Where a researcher can fetch shard proofs and public inputs for independent verification.
     pub shard_proofs_endpoint: String,
+
+    /// The shards this answer was computed over, as they were when the query ran.
+    pub shard_set: Option<QueryShardSet>,
+
+    /// Groth16 proof (`zk_proofs::query`) that `sum` and `count` are the totals over exactly the
+    /// shards chained into `shard_set.dataset_commitment_hex`; absent when the answer wasn't
+    /// proven (see `query_proof`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub query_proof_b64: Option<String>,
+    /// The rest of the query proof's public statement, and its verifying key.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub query_proof: Option<QueryProofStatement>,
+
+    /// For an age range spanning several buckets, the buckets added up (`bucket_index` is the
+    /// first, and `bucket_range` covers them all).
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub combined_buckets: Vec<CombinedBucket>,
+
+    /// Set for complement queries, whose `bucket_index` and `bucket_range` name the excluded
+    /// bucket (or the first and the whole range of excluded buckets).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub complement: Option<QueryComplement>,
+
+    /// Detached signature over the rest of this response (see `receipt`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub receipt: Option<QueryReceipt>,
+}
+
+/// Ed25519 signature by the instance's signing key over a `QueryResponse`, as of `signed_at`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QueryReceipt {
+    pub alg: String,
+    pub public_key_hex: String,
+    pub signed_at: DateTime<Utc>,
+    pub signature_hex: String,
+}
+
+/// `POST /api/v1/receipts/verify`: a query response as returned, and its receipt if it was
+/// detached from it.
+#[derive(Debug, Deserialize)]
+pub struct ReceiptVerifyRequest {
+    pub response: serde_json::Value,
+    #[serde(default)]
+    pub receipt: Option<QueryReceipt>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ReceiptVerifyResponse {
+    /// The signature verifies over the response.
+    pub valid: bool,
+    /// It was made with this instance's key; a valid receipt of another key only proves what
+    /// that key's holder signed.
+    pub signed_by_this_instance: bool,
+    pub public_key_hex: String,
+    pub signed_at: DateTime<Utc>,
+    pub query_id: Option<Uuid>,
+}
+
+/// The instance's signing key (exports, archives, manifest bucket counts, query receipts).
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SigningKeyResponse {
+    pub alg: String,
+    pub public_key_hex: String,
+    /// Prefix of every signed receipt message.
+    pub receipt_domain: String,
+}
+
+/// A transparency log leaf: a dataset or shard commitment as it was logged.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TransparencyLeaf {
+    /// `dataset` (a dataset commitment at some size) or `shard`.
+    pub kind: String,
+    pub dataset_id: Uuid,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub shard_index: Option<u64>,
+    pub commitment_hex: String,
+}
+
+/// `GET /api/v1/log/sth`: the signed head of the transparency log.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SignedTreeHead {
+    pub tree_size: u64,
+    pub timestamp: DateTime<Utc>,
+    pub root_hash_hex: String,
+    pub signature: TreeHeadSignature,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TreeHeadSignature {
+    pub alg: String,
+    pub public_key_hex: String,
+    /// Over `sth_domain`, then `tree_size` and `timestamp` (milliseconds) as big-endian `u64`s, then
+    /// the 32-byte root hash.
+    pub signature_hex: String,
+    pub sth_domain: String,
+}
+
+/// `GET /api/v1/log/proof`: a consistency proof between two tree sizes, or (with `leaf_index`) an
+/// inclusion proof of one leaf.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LogProofResponse {
+    /// `consistency` or `inclusion`.
+    pub kind: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub first: Option<u64>,
+    pub tree_size: u64,
+    pub root_hash_hex: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub leaf_index: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub leaf: Option<TransparencyLeaf>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub leaf_hash_hex: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub logged_at: Option<DateTime<Utc>>,
+    /// RFC 6962 `PROOF(first, D[tree_size])` or `PATH(leaf_index, D[tree_size])`, in order.
+    pub proof_hex: Vec<String>,
+}
+
+/// The buckets a complement answer adds up. Each is one of the bucket totals of
+/// `GET /api/v1/datasets/:id/aggregates` over the same shards, so the answer can be checked by
+/// summing those.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QueryComplement {
+    pub excluded_bucket_index: usize,
+    pub excluded_range: (u8, u8),
+    pub combined: Vec<CombinedBucket>,
+    pub aggregates_endpoint: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CombinedBucket {
+    pub bucket_index: usize,
+    pub bucket_range: (u8, u8),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QuerySuppression {
+    pub min_count: u64,
+    pub reason: String,
+}
+
+/// Public inputs of a query proof besides the dataset commitment, `sum` and `count`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QueryProofStatement {
+    /// Merkle root over the shards' public inputs, as in dataset aggregate proofs; a shard's
+    /// inputs can be checked against it with the path from `GET /datasets/:id/aggregate-proof`.
+    pub shard_inputs_root_hex: String,
+    /// Positions of the summed measurement and of the count in a shard's public-input vector.
+    pub sum_index: usize,
+    pub count_index: usize,
+    pub vk_b64: String,
+    pub key_id: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct HistogramBin {
+    /// Inclusive (min, max) blood glucose, mg/dL.
+    pub glucose_range: (u16, u16),
+    pub count: u64,
+}
+
+/// Snapshot of a dataset's shards taken when a query is evaluated, so the answer can later be
+/// re-checked against exactly the shards that existed then.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QueryShardSet {
+    /// Dataset commitment at query time (`None` if the dataset had none yet).
+    pub dataset_commitment_hex: Option<String>,
+    /// Number of shards the aggregate summed over.
+    pub shards_total: u64,
+    /// Bit `i % 8` of byte `i / 8` is set if shard `i` was verified.
+    pub verified_bitmap_hex: String,
+    /// Index of the first shard summed: shards before it had expired from the dataset's rolling
+    /// window, so the shard set is `first_shard_index..first_shard_index + shards_total`.
+    #[serde(default)]
+    pub first_shard_index: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GeneratorInfo {
+    pub name: String,
+    pub description: String,
+    /// Default distribution parameters.
+    pub params: serde_json::Value,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GeneratorListResponse {
+    pub default: String,
+    pub generators: Vec<GeneratorInfo>,
 }
 
-pub fn bucket_for_age_range(range: &AgeRange) -> Option<usize> {
-    for (i, (min, max)) in AGE_BUCKETS.iter().enumerate() {
-        if range.min_age == *min && range.max_age == *max {
-            return Some(i);
+/// Returned (with `202 Accepted`) when a query is held for approval or queued as a job.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct QueryPendingResponse {
+    pub query_id: Uuid,
+    pub dataset_id: Uuid,
+    /// `pending_approval` or `queued`.
+    pub status: String,
+    pub status_endpoint: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct QueryStatusResponse {
+    pub query_id: Uuid,
+    pub dataset_id: Uuid,
+    /// `pending_approval`, `queued`, `running`, `released`, `rejected` or `failed`.
+    pub status: String,
+    /// Present once `released`.
+    pub result: Option<QueryResponse>,
+    pub error: Option<String>,
+}
+
+/// A stored query, for reproducibility audits (`GET /api/v1/queries/:id`).
+#[derive(Debug, Serialize, Deserialize)]
+pub struct QueryRecord {
+    pub query_id: Uuid,
+    pub dataset_id: Uuid,
+    /// `pending_approval`, `queued`, `running`, `released`, `rejected` or `failed`.
+    pub status: String,
+    pub created_at: DateTime<Utc>,
+    pub released_at: Option<DateTime<Utc>>,
+    /// Approver who released or rejected a held query.
+    pub decided_by: Option<String>,
+    /// The request as stored: metric, bucket, field, complement, purpose, dp and cohort id.
+    pub query_json: serde_json::Value,
+    /// The answer as released (suppression applied), once `released`.
+    pub result: Option<QueryResponse>,
+    /// The stored result, exact even when suppressed; admins only.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub result_json: Option<serde_json::Value>,
+    /// Whether every shard summed had been verified when the answer was released.
+    pub server_verified: Option<bool>,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct QueryListResponse {
+    pub dataset_id: Uuid,
+    pub offset: u64,
+    pub limit: u64,
+    /// Oldest first.
+    pub queries: Vec<QueryRecord>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct QueryRejectResponse {
+    pub query_id: Uuid,
+    /// `rejected`.
+    pub status: String,
+}
+
+/// The first and last bucket of `range`: it must start where a bucket starts and end where
+/// the same or a later bucket ends. Otherwise the error lists the boundaries it can use.
+pub fn buckets_for_age_range(buckets: &AgeBuckets, range: &AgeRange) -> Result<(usize, usize), String> {
+    let bounds = buckets.bounds();
+    let first = bounds.iter().position(|(min, _)| *min == range.min_age);
+    let last = bounds.iter().position(|(_, max)| *max == range.max_age);
+    match (first, last) {
+        (Some(first), Some(last)) if first <= last => Ok((first, last)),
+        _ => {
+            let min_ages: Vec<u8> = bounds.iter().map(|(min, _)| *min).collect();
+            let max_ages: Vec<u8> = bounds.iter().map(|(_, max)| *max).collect();
+            Err(format!(
+                "age_range {}-{} cuts through the dataset's buckets {bounds:?}: min_age must be one of {min_ages:?} and max_age one of {max_ages:?}, at or after it",
+                range.min_age, range.max_age
+            ))
         }
     }
-    None
+}
+
+/// One line of a shard export: a shard as listed, with the public inputs its proof verifies
+/// against (hex field elements on the export's curve, in circuit order).
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ShardExportLine {
+    #[serde(flatten)]
+    pub shard: ShardListItem,
+    pub public_inputs_hex: Vec<String>,
+    /// The proof as snarkjs' `proof.json` (`format=snarkjs`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub snarkjs_proof: Option<serde_json::Value>,
+    /// The public inputs as snarkjs' `public.json`: decimal strings (`format=snarkjs`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub snarkjs_public_signals: Option<Vec<String>>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -97,19 +893,218 @@ pub struct ShardListResponse {
     pub dataset_id: Uuid,
     pub offset: u64,
     pub limit: u64,
+    /// Shard index range the page was taken from, `[from, to)`.
+    #[serde(default)]
+    pub shard_index_from: Option<u64>,
+    #[serde(default)]
+    pub shard_index_to: Option<u64>,
     pub shards_total: u64,
+    /// Curve the listed commitments and proofs are on.
+    #[serde(default)]
+    pub curve: Curve,
     pub shards: Vec<ShardListItem>,
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BucketAggregate {
+    pub bucket_index: usize,
+    pub bucket_range: (u8, u8),
+    pub sum_glucose: u64,
+    /// Sums of every measurement in the dataset's field set (glucose included).
+    pub sums: BTreeMap<Measurement, u64>,
+    /// Absent if any shard was proven without sums of squares.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sum_glucose_sq: Option<u64>,
+    /// Counts per glucose range; absent if any shard was proven without them.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub glucose_histogram: Option<[u64; NUM_GLUCOSE_RANGES]>,
+    pub count: u64,
+    /// The bucket is withheld (see `policy::suppressed_cells`): every aggregate above is zeroed.
+    #[serde(default)]
+    pub suppressed: bool,
+}
+
+/// Per-bucket descriptive statistics of a dataset, computed from the live shards' proven aggregates.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DatasetSummaryResponse {
+    pub dataset_id: Uuid,
+    /// Every live shard summed was verified.
+    pub server_verified: bool,
+    /// Level of the `ci_low`..`ci_high` intervals.
+    pub confidence_level: f64,
+    pub shard_set: QueryShardSet,
+    pub buckets: Vec<SummaryBucket>,
+    /// The sums and counts the table is computed from.
+    pub aggregates_endpoint: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SummaryBucket {
+    pub bucket_index: usize,
+    pub bucket_range: (u8, u8),
+    /// `null` (and `measurements` empty) when the bucket is `suppressed`.
+    pub count: Option<u64>,
+    pub measurements: Vec<MeasurementSummary>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub suppressed: Option<QuerySuppression>,
+}
+
+/// One measurement of a bucket, in its recorded units (BMI times 10).
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MeasurementSummary {
+    pub measurement: Measurement,
+    pub mean: Option<f64>,
+    /// Sample standard deviation; only blood glucose has proven sums of squares, and only with
+    /// at least 2 records.
+    pub sd: Option<f64>,
+    /// Normal-approximation confidence interval of the mean, `mean ± z·sd/√count`.
+    pub ci_low: Option<f64>,
+    pub ci_high: Option<f64>,
+}
+
+/// Dataset-wide per-bucket totals plus a page of the per-shard public inputs they sum, so a
+/// verifier can reconcile a query answer shard by shard.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DatasetAggregatesResponse {
+    pub dataset_id: Uuid,
+    pub dataset_commitment_hex: Option<String>,
+    pub shards_total: u64,
+    /// Shards the totals were summed over (the live ones, for rolling-window datasets).
+    pub shards_summed: u64,
+    /// With `MIN_CELL_COUNT` set, buckets below it are `suppressed`.
+    pub buckets: Vec<BucketAggregate>,
+
+    pub offset: u64,
+    pub limit: u64,
+    /// Per-shard contributions (without proofs); with `MIN_CELL_COUNT` set, each shard's buckets
+    /// below it are masked as with `mask_small_counts`.
+    pub shards: Vec<ShardListItem>,
+}
+
+/// A dataset aggregate proof: one Groth16 proof that `dataset_commitment_hex` chains the
+/// commitments of the shards whose public inputs `shard_inputs_root_hex` is the Merkle root of, and
+/// that `totals` are the sums of their aggregates.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AggregateProofResponse {
+    pub dataset_id: Uuid,
+    pub created_at: DateTime<Utc>,
+    pub dataset_commitment_hex: String,
+    pub shard_inputs_root_hex: String,
+    pub shards_total: u64,
+    /// Per-bucket sums (and sums of squares, histogram) over every shard, as proven.
+    pub totals: ShardStats,
+    pub proof_b64: String,
+    /// Verifying key of the aggregate circuit for this dataset's shard count and layout.
+    pub vk_b64: String,
+    pub key_id: String,
+    /// With `shard_index`: that shard's path to `shard_inputs_root_hex`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub shard_path: Option<AggregateShardPath>,
+}
+
+/// Merkle path of one shard's public inputs in an aggregate proof. The leaf is Poseidon over the
+/// shard's public-input vector; `siblings_hex` go from the leaf level up, and bit `d` of
+/// `shard_index` says whether the node at depth `d` is a right child.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AggregateShardPath {
+    pub shard_index: u64,
+    pub leaf_hex: String,
+    pub siblings_hex: Vec<String>,
+}
+
+/// Returned (with `202 Accepted`) while a dataset's aggregate proof is being made.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AggregatePendingResponse {
+    pub dataset_id: Uuid,
+    /// `queued` or `running`.
+    pub status: String,
+    /// Why the previous attempt failed, if it did (a new one has been queued).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// The latest re-verification of a dataset (`GET /api/v1/verify/dataset/:id`).
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DatasetReverificationReport {
+    pub dataset_id: Uuid,
+    pub finished_at: DateTime<Utc>,
+    /// `passed` if every shard proof verifies and the shards chain to the commitment, else `failed`.
+    pub status: String,
+    pub dataset_commitment_hex: String,
+    /// False when the dataset has been extended or migrated since; re-verify to cover it.
+    pub current: bool,
+    pub shards_total: u64,
+    pub shards_checked: u64,
+    /// Shards whose proof doesn't verify.
+    pub failed_shards: Vec<u64>,
+    pub commitment_matches: bool,
+    pub duration_ms: u64,
+}
+
+/// Returned (with `202 Accepted`) while a dataset is being re-verified.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DatasetReverificationPending {
+    pub dataset_id: Uuid,
+    /// `queued` or `running`.
+    pub status: String,
+    /// Server-Sent Events with shards checked and failures so far.
+    pub events_endpoint: String,
+    /// Polled for the report once the job has finished.
+    pub report_endpoint: String,
+    /// Why the previous attempt failed, if it did.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ShardListItem {
     pub shard_index: u64,
     pub shard_commitment_hex: String,
 
-    pub sum_glucose_by_bucket: [u64; NUM_BUCKETS],
-    pub count_by_bucket: [u64; NUM_BUCKETS],
+    pub sum_glucose_by_bucket: Vec<u64>,
+    pub count_by_bucket: Vec<u64>,
+    /// Sums of the field set's further measurements (see `FieldSet::measurements`); absent for
+    /// glucose-only datasets.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub extra_sums_by_bucket: Vec<Vec<u64>>,
+    /// Sums of squared glucose; absent for shards proven with keys that predate them.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sum_glucose_sq_by_bucket: Option<Vec<u64>>,
+    /// Counts per age bucket and glucose range; absent for shards proven with keys that predate
+    /// them.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub glucose_histogram_by_bucket: Option<Vec<[u64; NUM_GLUCOSE_RANGES]>>,
+    /// Commitment to the shard's master salt (hex, like `shard_commitment_hex`); absent for
+    /// shards proven with keys that predate salting. The salt itself is never returned.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub salt_commitment_hex: Option<String>,
+    /// Hex SHA-256 commitment of dual-commitment datasets: the digest of the shard's canonical
+    /// record encoding, bound to `shard_commitment_hex` by the proof. Absent otherwise.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sha256_commitment_hex: Option<String>,
+    /// Inclusive `(min, max)` mg/dL the proof shows every record's glucose lies within; absent for
+    /// shards proven with keys that predate it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub glucose_bounds: Option<(u16, u16)>,
+    /// Age buckets zeroed by `mask_small_counts`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub masked_buckets: Vec<usize>,
 
     pub verified: bool,
+    /// Before the dataset's rolling window: excluded from queries and aggregates, but kept for
+    /// audit and still verifiable against the dataset commitment.
+    #[serde(default)]
+    pub expired: bool,
+    /// Id of the verifying key the proof was checked with (`GET /api/v1/zk/vk/:version`); absent
+    /// for shards stored before key versions were recorded, which use the dataset's key.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub vk_version: Option<String>,
+    /// When the proof last verified (on storing, a dataset re-verification or the periodic
+    /// sample), and against which key id; absent if it hasn't since these were recorded.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub verified_at: Option<DateTime<Utc>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub verifier_vk_hash: Option<String>,
 
     /// Included only if requested (large).
     pub proof_b64: Option<String>,
@@ -120,19 +1115,824 @@ pub struct ZkVkResponse {
     pub curve: String,
     pub proof_system: String,
     pub vk_b64: String,
+    /// Hex SHA-256 of the verifying key.
+    pub key_id: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SchemaParams {
+    /// Describe a specific dataset's circuit instead of the deployment defaults.
+    pub dataset_id: Option<Uuid>,
+}
+
+/// One measurement a shard circuit can prove aggregates for.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SchemaField {
+    pub name: String,
+    pub unit: String,
+    /// Bits each value is range-checked to in the circuit.
+    pub bits: u32,
+    /// Field sets that measure it.
+    pub field_sets: Vec<FieldSet>,
+    /// Inclusive bounds every value must lie within; `None` if only `bits` bounds it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub plausible_range: Option<(u16, u16)>,
+}
+
+/// Width and round counts of the Poseidon sponge the circuits hash with.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PoseidonSchema {
+    pub width: usize,
+    pub full_rounds: usize,
+    pub partial_rounds: usize,
+    pub alpha: u64,
+}
+
+/// The bucket layout, fields and circuit parameters queries are answered under, so clients can
+/// build queries without hard-coding them.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ZkSchemaResponse {
+    /// Set when describing one dataset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dataset_id: Option<Uuid>,
+    /// Inclusive (min_age, max_age) bounds of each bucket; `age_range` in queries names one.
+    pub age_buckets: Vec<(u8, u8)>,
+    pub num_buckets: usize,
+    pub max_buckets: usize,
+    pub max_age: u8,
+    pub age_bits: u32,
+    pub shard_size: usize,
+    pub supported_shard_sizes: Vec<usize>,
+    pub field_set: FieldSet,
+    pub fields: Vec<SchemaField>,
+    /// Inclusive glucose bounds, mg/dL, of the `histogram` ranges.
+    pub glucose_ranges: Vec<(u16, u16)>,
+    /// Revision new shard proofs are made with, and every revision verifiers accept.
+    pub circuit_version: String,
+    pub circuit_versions: Vec<String>,
+    pub circuit_id: String,
+    /// Hash folding shard commitments into the dataset commitment.
+    pub chain_hash: ChainHash,
+    pub poseidon: PoseidonSchema,
+    pub curves: Vec<Curve>,
+}
+
+/// Proofs made with one key on this ledger, and whether it is due for rotation.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ZkKeyUsage {
+    pub key_id: String,
+    /// `shard`, `shard-bls12-381`, `aggregate` or `query`.
+    pub circuit: String,
+    pub first_seen_at: DateTime<Utc>,
+    pub last_proof_at: DateTime<Utc>,
+    pub age_days: u64,
+    pub proofs_created: u64,
+    pub datasets_covered: u64,
+    pub rotation_due: bool,
+    pub rotation_reasons: Vec<String>,
+}
+
+/// Usage of the keys this ledger has proven with, under the deployment's rotation thresholds
+/// (`null` = none).
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ZkKeysResponse {
+    pub thresholds: crate::key_usage::RotationThresholds,
+    pub keys: Vec<ZkKeyUsage>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct VerifyShardRequest {
     pub vk_b64: String,
+    /// Curve of the key, proof and commitments. Defaults to `bn254`.
+    #[serde(default)]
+    pub curve: Curve,
+    #[serde(flatten)]
+    pub shard: ShardProofRequest,
+}
+
+/// A shard proof and the public inputs it is claimed to prove.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ShardProofRequest {
     pub proof_b64: String,
 
     pub public_shard_commitment_hex: String,
-    pub public_sum_glucose_by_bucket: [u64; NUM_BUCKETS],
-    pub public_count_by_bucket: [u64; NUM_BUCKETS],
+    pub public_sum_glucose_by_bucket: Vec<u64>,
+    pub public_count_by_bucket: Vec<u64>,
+    /// Required for proofs of multi-field datasets (see `ShardListItem::extra_sums_by_bucket`).
+    #[serde(default)]
+    pub public_extra_sums_by_bucket: Vec<Vec<u64>>,
+    /// Required for proofs that include sums of squares (see `ShardListItem::sum_glucose_sq_by_bucket`).
+    #[serde(default)]
+    pub public_sum_glucose_sq_by_bucket: Option<Vec<u64>>,
+    /// Required for proofs that include glucose histograms (see
+    /// `ShardListItem::glucose_histogram_by_bucket`).
+    #[serde(default)]
+    pub public_glucose_histogram_by_bucket: Option<Vec<[u64; NUM_GLUCOSE_RANGES]>>,
+    /// Required for proofs of salted commitments (see `ShardListItem::salt_commitment_hex`).
+    #[serde(default)]
+    pub public_salt_commitment_hex: Option<String>,
+    /// Required for proofs made with dual-commitment keys (see
+    /// `ShardListItem::sha256_commitment_hex`).
+    #[serde(default)]
+    pub public_sha256_commitment_hex: Option<String>,
+    /// Required for proofs that bound glucose (see `ShardListItem::glucose_bounds`).
+    #[serde(default)]
+    pub public_glucose_bounds: Option<(u16, u16)>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct VerifyShardResponse {
     pub ok: bool,
 }
+
+/// Many shard proofs checked against one verifying key in a single batch.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct VerifyShardsRequest {
+    /// The verifying key, inline; or name one this ledger has with `key_id`.
+    #[serde(default)]
+    pub vk_b64: Option<String>,
+    /// `key_id` of a BN254 shard verifying key this ledger has used (as in `GET /zk/vk` and
+    /// dataset manifests), including keys since replaced by circuit migrations.
+    #[serde(default)]
+    pub key_id: Option<String>,
+    /// Curve of the key, proofs and commitments. Defaults to `bn254`; `bls12_381` proofs are
+    /// checked one by one rather than batched.
+    #[serde(default)]
+    pub curve: Curve,
+    pub shards: Vec<ShardProofRequest>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct VerifyShardsResponse {
+    /// True when every proof in `shards` was checked and verifies.
+    pub ok: bool,
+    /// False if the request's deadline stopped verification before every entry was checked.
+    pub complete: bool,
+    pub shards_total: usize,
+    /// Positions in `shards` of the proofs that don't verify, ascending.
+    pub invalid: Vec<usize>,
+    /// Positions in `shards` of the entries left unchecked, ascending.
+    pub unchecked: Vec<usize>,
+    /// One result per entry of `shards`, in order.
+    pub results: Vec<VerifyShardResult>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct VerifyShardResult {
+    pub ok: bool,
+    /// Why the entry couldn't be checked (malformed proof or public inputs); absent for entries
+    /// whose proof was checked.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UploadInitResponse {
+    pub upload_id: Uuid,
+    /// Upper bound on the assembled upload size.
+    pub max_upload_bytes: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UploadChunkRequest {
+    /// Zero-based chunk index. Chunks are concatenated in index order at commit time.
+    pub index: u32,
+    pub data_b64: String,
+    /// Hex SHA-256 of the decoded chunk bytes.
+    pub sha256_hex: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UploadStatusResponse {
+    pub upload_id: Uuid,
+    pub created_at: DateTime<Utc>,
+    pub received_chunks: Vec<u32>,
+    pub received_bytes: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UploadCommitRequest {
+    pub total_chunks: u32,
+    /// Optional hex SHA-256 of the whole assembled file.
+    pub sha256_hex: Option<String>,
+    /// Same semantics as `DatasetCreateRequest::consent_scope`.
+    pub consent_scope: Option<Vec<String>>,
+    /// Same semantics as `DatasetCreateRequest::requires_approval`.
+    pub requires_approval: Option<bool>,
+    /// Same semantics as `DatasetCreateRequest::release_limit`.
+    pub release_limit: Option<u64>,
+    /// Same semantics as `DatasetCreateRequest::shard_size`.
+    pub shard_size: Option<u64>,
+    /// Same semantics as `DatasetCreateRequest::field_set`; vitals uploads need the
+    /// `systolic_bp`, `heart_rate` and `bmi` columns.
+    pub field_set: Option<FieldSet>,
+    /// Same semantics as `DatasetCreateRequest::chain_hash`.
+    pub chain_hash: Option<ChainHash>,
+    /// Same semantics as `DatasetCreateRequest::sha256_commitment`.
+    pub sha256_commitment: Option<bool>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct StreamOpenRequest {
+    /// Reopen the stream of this dataset (an uploaded or streamed one created with the caller's
+    /// key) to append to it; the settings below are then taken from the dataset. Omit to start a
+    /// new, empty dataset.
+    pub dataset_id: Option<Uuid>,
+    /// Same semantics as `DatasetCreateRequest::shard_size`; full shards are proven as they fill.
+    pub shard_size: Option<u64>,
+    /// Same semantics as `DatasetCreateRequest::field_set`; vitals batches need the
+    /// `systolic_bp`, `heart_rate` and `bmi` columns.
+    pub field_set: Option<FieldSet>,
+    /// Same semantics as `DatasetCreateRequest::chain_hash`.
+    pub chain_hash: Option<ChainHash>,
+    /// Same semantics as `DatasetCreateRequest::sha256_commitment`.
+    pub sha256_commitment: Option<bool>,
+    /// Same semantics as `DatasetCreateRequest::buckets`.
+    pub buckets: Option<Vec<(u8, u8)>>,
+    /// Same semantics as `DatasetCreateRequest::consent_scope`.
+    pub consent_scope: Option<Vec<String>>,
+    /// Same semantics as `DatasetCreateRequest::requires_approval`.
+    pub requires_approval: Option<bool>,
+    /// Same semantics as `DatasetCreateRequest::release_limit`.
+    pub release_limit: Option<u64>,
+    /// Same semantics as `DatasetCreateRequest::window_shards`; as shards are appended, the oldest
+    /// expire.
+    pub window_shards: Option<u64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct StreamRecordsParams {
+    /// Batch sequence number: 0 for the first batch of a session, then consecutive. Re-sending
+    /// the last batch unchanged is a no-op.
+    pub sequence: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct StreamStatusResponse {
+    pub dataset_id: Uuid,
+    pub opened_at: DateTime<Utc>,
+    pub last_activity: DateTime<Utc>,
+    pub shard_size: u64,
+    /// Sequence number the next batch must carry.
+    pub next_sequence: u64,
+    /// Accepted records waiting for enough to fill a shard.
+    pub records_buffered: u64,
+    /// Full shards waiting to be proven and appended.
+    pub shards_pending: u64,
+    /// Shards appended during this session.
+    pub shards_appended: u64,
+    /// Current size and commitment of the dataset (over every shard appended so far).
+    pub dataset_size: u64,
+    pub dataset_commitment_hex: Option<String>,
+    /// Rows seen across all batches of the dataset, and why any were rejected.
+    pub ingest: crate::quality::IngestQuality,
+    /// Closed streams take no more records; they go away once their pending shards are appended.
+    pub closed: bool,
+    /// Buffered records dropped on close, too few to fill a shard.
+    pub records_discarded: u64,
+    /// Set when a shard could not be proven or appended; the stream then takes no more records.
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FederatedDatasetCreateRequest {
+    /// Name of the site proving the dataset (e.g. a hospital); recorded as
+    /// `imported_from` = `federated:<site>`.
+    pub site: String,
+    /// The site's shard verifying key (base64, as `/zk/vk` returns it); every pushed shard proof
+    /// must verify against it. The key's dual-commitment variant is recognized from it.
+    pub vk_b64: String,
+    /// Shard size the key was set up for; same semantics as `DatasetCreateRequest::shard_size`.
+    pub shard_size: Option<u64>,
+    /// Same semantics as `DatasetCreateRequest::field_set`.
+    pub field_set: Option<FieldSet>,
+    /// Same semantics as `DatasetCreateRequest::chain_hash`.
+    pub chain_hash: Option<ChainHash>,
+    /// Same semantics as `DatasetCreateRequest::buckets`; must match the key.
+    pub buckets: Option<Vec<(u8, u8)>>,
+    /// Same semantics as `DatasetCreateRequest::consent_scope`.
+    pub consent_scope: Option<Vec<String>>,
+    /// Same semantics as `DatasetCreateRequest::requires_approval`.
+    pub requires_approval: Option<bool>,
+    /// Same semantics as `DatasetCreateRequest::release_limit`.
+    pub release_limit: Option<u64>,
+    /// Same semantics as `StreamOpenRequest::window_shards`.
+    pub window_shards: Option<u64>,
+}
+
+/// One shard proven by a federated site: its commitment, public outputs and proof. No records.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FederatedShardRequest {
+    /// Must be the dataset's next shard; re-sending a stored shard unchanged is a no-op.
+    pub shard_index: u64,
+    pub shard_commitment_hex: String,
+    pub stats: ShardStats,
+    pub proof_b64: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FederatedShardResponse {
+    pub dataset_id: Uuid,
+    pub shard_index: u64,
+    /// `appended`, or `already_present` for a re-sent shard.
+    pub outcome: String,
+    /// Current size and commitment of the dataset (over every shard pushed so far).
+    pub dataset_size: u64,
+    pub dataset_commitment_hex: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AuditEntry {
+    pub seq: u64,
+    pub created_at: DateTime<Utc>,
+    pub event: String,
+    pub details: serde_json::Value,
+    pub prev_hash: String,
+    pub entry_hash: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AuditListResponse {
+    pub dataset_id: Uuid,
+    pub offset: u64,
+    pub limit: u64,
+    pub entries: Vec<AuditEntry>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CellDisclosure {
+    pub bucket_index: usize,
+    pub bucket_range: (u8, u8),
+    /// Filter applied on top of the bucket (`none` for plain bucket aggregates).
+    pub filter: String,
+    /// How many released queries disclosed this cell.
+    pub release_count: u64,
+    pub first_released_at: DateTime<Utc>,
+    pub last_released_at: DateTime<Utc>,
+    pub level: crate::policy::DisclosureLevel,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DisclosureResponse {
+    pub dataset_id: Uuid,
+    pub threshold: u64,
+    pub cells: Vec<CellDisclosure>,
+}
+
+/// Resources held by the calling API key, with the deployment's per-tenant limits (`null` =
+/// unlimited).
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UsageResponse {
+    pub key_id: String,
+    pub datasets: u64,
+    pub records: u64,
+    pub proving_running: u64,
+    pub proving_queued: u64,
+    /// Queries submitted today (UTC), counted against `limits.max_queries_per_day`.
+    pub queries_today: u64,
+    pub limits: crate::quota::Quotas,
+    pub rate_limits: crate::rate_limit::RateLimits,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ShardFailure {
+    pub shard_index: u64,
+    /// `records`, `prove`, `verify`, `serialize` or `panic`.
+    pub error_class: String,
+    pub attempts: u64,
+    pub last_error: String,
+    pub first_failed_at: DateTime<Utc>,
+    pub last_failed_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ShardFailuresResponse {
+    pub dataset_id: Uuid,
+    pub failures: Vec<ShardFailure>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ShardSizeSelfTest {
+    pub shard_size: u64,
+    #[serde(default)]
+    pub field_set: FieldSet,
+    /// Dual-commitment key set.
+    #[serde(default)]
+    pub sha256_commitment: bool,
+    pub ok: bool,
+    pub error: Option<String>,
+    pub duration_ms: u64,
+}
+
+/// Result of proving and verifying the fixed self-test shard with each on-disk key set.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ZkSelfTestReport {
+    pub ran_at: DateTime<Utc>,
+    pub ok: bool,
+    /// Shard sizes whose keys were tested; sizes without keys yet are not listed.
+    pub results: Vec<ShardSizeSelfTest>,
+    /// Set if a record no longer encodes to the canonical encoding's golden vectors.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub encoding_error: Option<String>,
+}
+
+/// Liveness: the process is up and serving requests.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct HealthzResponse {
+    pub status: String,
+    pub version: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ReadyzResponse {
+    /// Every check passed and the ZK self-test did.
+    pub ready: bool,
+    pub checks: ReadinessChecks,
+    /// Absent while the startup self-test is still running.
+    pub zk_self_test: Option<ZkSelfTestReport>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ReadinessChecks {
+    pub database: DatabaseCheck,
+    pub keys: KeysCheck,
+    pub proving_backlog: ProvingBacklogCheck,
+}
+
+/// A round trip to the ledger database (and the local SQLite database, if the ledger is elsewhere).
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DatabaseCheck {
+    pub ok: bool,
+    pub latency_ms: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Key files of the shard keys loaded so far: still on disk, and the verifying key unchanged.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct KeysCheck {
+    pub ok: bool,
+    pub loaded: u64,
+    /// Key files missing or replaced since they were loaded.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub problems: Vec<String>,
+}
+
+/// Dataset proving jobs, against `READYZ_MAX_PROVING_BACKLOG`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ProvingBacklogCheck {
+    pub ok: bool,
+    pub running: u64,
+    pub queued: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_queued: Option<u64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ProvingKeyEstimate {
+    pub shard_size: u64,
+    pub field_set: FieldSet,
+    pub age_buckets: AgeBuckets,
+    pub sha256_commitment: bool,
+    pub proof_bytes: u64,
+}
+
+/// Proving admission state: what is running, what is waiting for memory, and why.
+#[derive(Debug, Serialize)]
+pub struct ProvingStatusResponse {
+    #[serde(flatten)]
+    pub admission: crate::admission::AdmissionSnapshot,
+    /// `None` where the platform doesn't report it (no throttling then).
+    pub available_bytes: Option<u64>,
+    /// Per-proof estimates for the key sets loaded so far.
+    pub estimates: Vec<ProvingKeyEstimate>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CorruptProofBlob {
+    pub hash: String,
+    /// Shards whose proof is this blob.
+    pub shards: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ProofBlobAuditReport {
+    pub ran_at: DateTime<Utc>,
+    pub blobs_checked: u64,
+    /// Blobs whose bytes no longer hash to their address.
+    pub corrupt: Vec<CorruptProofBlob>,
+    /// Blobs deleted because no shard refers to them.
+    pub orphans_removed: u64,
+}
+
+/// Content-addressed proof storage: `shard_references - blobs` proofs were deduplicated.
+#[derive(Debug, Serialize)]
+pub struct ProofBlobsResponse {
+    pub blobs: u64,
+    pub stored_bytes: u64,
+    pub shard_references: u64,
+    pub last_audit: Option<ProofBlobAuditReport>,
+}
+
+/// `POST /api/v1/admin/curve-migrations`.
+#[derive(Debug, Deserialize)]
+pub struct CurveMigrationRequest {
+    /// Target curve; only `bls12_381` is supported.
+    #[serde(default)]
+    pub curve: Option<Curve>,
+    /// Datasets to migrate; every dataset when absent.
+    #[serde(default)]
+    pub dataset_ids: Option<Vec<Uuid>>,
+    /// Only return the plan. Defaults to false.
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CurveMigrationPlanResponse {
+    pub curve: Curve,
+    pub dry_run: bool,
+    pub datasets: Vec<CurveMigrationPlanItem>,
+}
+
+/// What a migration does with one dataset.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CurveMigrationPlanItem {
+    pub dataset_id: Uuid,
+    /// `reprove` (synthetic, queued as a job), `flag` (records not reproducible here) or `skip`
+    /// (not ready, or already migrated or migrating).
+    pub action: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CurveMigrationListResponse {
+    pub curve: Curve,
+    /// Days after a migration finishes during which `bn254` stays the default.
+    pub transition_days: u64,
+    pub migrations: Vec<CurveMigrationItem>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CurveMigrationItem {
+    pub dataset_id: Uuid,
+    /// `queued`, `running`, `done`, `flagged` or `failed`.
+    pub status: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+    pub shards_done: u64,
+    pub shards_total: u64,
+    pub dataset_commitment_hex: Option<String>,
+    pub key_id: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub finished_at: Option<DateTime<Utc>>,
+    /// End of the transition window of a finished migration.
+    pub dual_serve_until: Option<DateTime<Utc>>,
+}
+
+/// `POST /api/v1/admin/circuit-migrations`.
+#[derive(Debug, Deserialize)]
+pub struct CircuitMigrationRequest {
+    /// Only plan datasets proven with this circuit revision (e.g. `shard-aggregate-v3`); every
+    /// older revision than `to` when absent.
+    #[serde(default)]
+    pub from: Option<String>,
+    /// Revision to move to; the latest when absent.
+    #[serde(default)]
+    pub to: Option<String>,
+    /// Datasets to plan; every dataset when absent.
+    #[serde(default)]
+    pub dataset_ids: Option<Vec<Uuid>>,
+    /// Only return the plan. Defaults to false.
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CircuitMigrationPlanResponse {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub from: Option<String>,
+    pub to: String,
+    pub dry_run: bool,
+    pub datasets: Vec<CircuitMigrationPlanItem>,
+}
+
+/// How a circuit upgrade affects one dataset.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CircuitMigrationPlanItem {
+    pub dataset_id: Uuid,
+    /// Circuit revision the dataset's proofs were made with; absent if no manifest or key says.
+    pub circuit_revision: Option<String>,
+    /// Id of the verifying key its proofs were made with.
+    pub key_id: Option<String>,
+    /// Whether the upgrade concerns the dataset (proven with an older revision than `to`).
+    pub affected: bool,
+    /// Whether the verifying key its proofs were made with is still available, so they verify
+    /// (`GET /api/v1/zk/vk?dataset_id=` serves it) before and after the upgrade.
+    pub proofs_verifiable: bool,
+    /// `reprove` (synthetic, queued as a job), `flag` (affected, but can't be re-proven here) or
+    /// `skip` (not affected, or not ready).
+    pub action: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BackupResponse {
+    /// Backup directory on the server.
+    pub path: String,
+    pub manifest: crate::backup::BackupManifest,
+}
+
+/// How a dataset was made and what its proofs verify against. Salted shards can't be recomputed
+/// from it: their master salts are sealed server-side.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DatasetManifest {
+    pub manifest_version: u32,
+    pub dataset_id: Uuid,
+    pub dataset_size: u64,
+    pub shard_size: u64,
+    #[serde(default)]
+    pub field_set: FieldSet,
+    pub num_buckets: u64,
+    pub age_buckets: Vec<(u8, u8)>,
+    /// `synthetic`, `upload` or `stream`. Uploaded and streamed datasets cannot be regenerated.
+    pub source: String,
+    /// Rolling window: queries and aggregates read only the last this many shards, the ones
+    /// before are expired (still committed to and verifiable). Absent if every shard is live.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub window_shards: Option<u64>,
+    pub generator: Option<GeneratorSpec>,
+    /// How per-shard RNG seeds are derived (synthetic datasets only).
+    pub seed_scheme: Option<String>,
+    pub circuit_id: String,
+    /// How shard commitments are chained into the dataset commitment.
+    #[serde(default)]
+    pub chain_hash: ChainHash,
+    /// Whether shards also carry a circuit-bound SHA-256 commitment.
+    #[serde(default)]
+    pub sha256_commitment: bool,
+    pub proof_system: String,
+    pub curve: String,
+    /// Hex SHA-256 of the serialized verifying key (see `GET /api/v1/zk/vk`).
+    pub key_id: String,
+    pub code_versions: CodeVersions,
+    /// Signed records per bucket, added once the dataset is ready (see `bucket_counts`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bucket_counts: Option<crate::bucket_counts::SignedBucketCounts>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GeneratorSpec {
+    pub name: String,
+    pub params: serde_json::Value,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CodeVersions {
+    pub backend: String,
+    pub zk_proofs: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BucketQuality {
+    pub bucket_index: usize,
+    pub bucket_range: (u8, u8),
+    /// Records in this bucket (from proven shard stats).
+    pub count: u64,
+    /// Fraction of all records that fall in this bucket.
+    pub share: f64,
+    /// Records with glucose outside the plausible range (host-side check, not proven).
+    pub out_of_range_glucose: u64,
+    /// With `MIN_CELL_COUNT` set, the bucket is withheld as on `/aggregates`: `count`, `share`
+    /// and `out_of_range_glucose` are zeroed.
+    #[serde(default)]
+    pub suppressed: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DatasetQualityResponse {
+    pub dataset_id: Uuid,
+    /// Ingestion counts, including rejected rows; absent for datasets created before tracking.
+    pub ingest: Option<crate::quality::IngestQuality>,
+    pub rows_rejected: Option<u64>,
+    pub plausible_glucose_mg_dl: (u16, u16),
+    pub shards_total: u64,
+    pub shards_done: u64,
+    pub shards_reporting: u64,
+    pub buckets: Vec<BucketQuality>,
+}
+
+// --- Query-string parameters ---
+
+#[derive(Debug, Deserialize)]
+pub struct PageParams {
+    pub offset: Option<u64>,
+    pub limit: Option<u64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AggregateProofParams {
+    /// Include this shard's Merkle path.
+    pub shard_index: Option<u64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct VkParams {
+    pub shard_size: Option<u64>,
+    /// Defaults to `glucose`.
+    pub field_set: Option<FieldSet>,
+    /// The dual-commitment key. Defaults to false.
+    pub sha256_commitment: Option<bool>,
+    /// Return the key a specific dataset was proven with (differs for imported datasets). Keys of
+    /// non-default age bucket layouts are only served this way.
+    pub dataset_id: Option<Uuid>,
+    /// Curve of the key; defaults to the dataset's default curve (`bn254` without a dataset).
+    pub curve: Option<Curve>,
+    /// `snarkjs` returns the key as snarkjs' `verification_key.json` instead (BN254 only).
+    pub format: Option<ZkFormat>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct VkVersionParams {
+    /// As in `VkParams`.
+    pub format: Option<ZkFormat>,
+}
+
+/// How keys and proofs are encoded: base64 arkworks serialization (the default), or the JSON of
+/// snarkjs/circom tooling.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ZkFormat {
+    #[default]
+    Arkworks,
+    Snarkjs,
+}
+
+/// `GET /api/v1/zk/pk`: the proving key of the default age buckets, for client-side proving.
+#[derive(Debug, Deserialize)]
+pub struct PkParams {
+    pub shard_size: Option<u64>,
+    /// Defaults to `glucose`.
+    pub field_set: Option<FieldSet>,
+    /// The dual-commitment key. Defaults to false.
+    pub sha256_commitment: Option<bool>,
+}
+
+/// `GET /api/v1/log/proof`. `tree_size` defaults to the current size.
+#[derive(Debug, Deserialize)]
+pub struct LogProofParams {
+    /// Older tree size of a consistency proof.
+    pub first: Option<u64>,
+    /// Leaf of an inclusion proof.
+    pub leaf_index: Option<u64>,
+    pub tree_size: Option<u64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CsvImportParams {
+    pub shard_size: Option<u64>,
+    pub field_set: Option<FieldSet>,
+    pub chain_hash: Option<ChainHash>,
+    pub sha256_commitment: Option<bool>,
+    /// Comma-separated consent purposes.
+    pub consent_scope: Option<String>,
+    pub requires_approval: Option<bool>,
+    pub release_limit: Option<u64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ExportParams {
+    pub dataset_id: Option<Uuid>,
+    pub shard_index_from: Option<u64>,
+    pub shard_index_to: Option<u64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ListShardsParams {
+    pub offset: Option<u64>,
+    pub limit: Option<u64>,
+    pub include_proof: Option<bool>,
+    /// First shard index to include.
+    pub shard_index_from: Option<u64>,
+    /// One past the last shard index to include.
+    pub shard_index_to: Option<u64>,
+    /// Curve of the shards to list; defaults to the dataset's default curve.
+    pub curve: Option<Curve>,
+    /// Zero the aggregates of age buckets with fewer records than `MIN_CELL_COUNT`, listing them
+    /// in `masked_buckets`. Masked public inputs no longer verify, so this excludes `include_proof`.
+    pub mask_small_counts: Option<bool>,
+}
+
+/// Query of `GET /api/v1/datasets/:id/shards/export`: like `ListShardsParams`, without paging.
+#[derive(Debug, Deserialize)]
+pub struct ExportShardsParams {
+    /// Defaults to true.
+    pub include_proof: Option<bool>,
+    pub shard_index_from: Option<u64>,
+    pub shard_index_to: Option<u64>,
+    pub curve: Option<Curve>,
+    /// `snarkjs` adds each proof and its public inputs in snarkjs' JSON (BN254 only, with proofs).
+    pub format: Option<ZkFormat>,
+}
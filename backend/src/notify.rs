@@ -0,0 +1,30 @@
+//! Outbound notification hooks.
+//!
+//! If `NOTIFY_WEBHOOK_URL` is set, events are POSTed there as JSON (fire-and-forget). Events are
+//! always logged, so deployments without a webhook still see them.
+
+use serde_json::json;
+use uuid::Uuid;
+
+/// Emit a workflow event, e.g. `query_pending_approval`.
+pub fn emit(event: &'static str, dataset_id: Uuid, subject_id: Uuid) {
+    tracing::info!(event, %dataset_id, %subject_id, "notification");
+
+    let Ok(url) = std::env::var("NOTIFY_WEBHOOK_URL") else {
+        return;
+    };
+
+    let body = json!({
+        "event": event,
+        "dataset_id": dataset_id,
+        "subject_id": subject_id,
+        "at": chrono::Utc::now(),
+    });
+
+    tokio::spawn(async move {
+        let res = reqwest::Client::new().post(&url).json(&body).send().await;
+        if let Err(e) = res {
+            tracing::warn!(event, error = %e, "notification webhook failed");
+        }
+    });
+}
@@ -0,0 +1,315 @@
+//! OpenID Connect bearer tokens as an alternative to API keys.
+//!
+//! With `OIDC_ISSUER` set, requests may authenticate with `Authorization: Bearer <id or access
+//! token>` from that issuer instead of `X-API-KEY`, so hospital SSO identities are used directly.
+//! The issuer's discovery document names its JWKS, which is cached for `OIDC_JWKS_TTL_SECS`
+//! (default 3600) and refetched early when a token names an unknown key (at most every
+//! `JWKS_MIN_REFETCH`). A token is accepted when its RS256 or ES256 signature verifies against
+//! that JWKS, `iss` is the issuer, `aud` includes `OIDC_AUDIENCE` (required with the issuer) and it is within
+//! `exp`/`nbf` (60s leeway).
+//!
+//! Claims map onto the key model:
+//! - the tenant is the value of `OIDC_TENANT_CLAIM` (default `sub`); its `key_id` is the
+//!   fingerprint of `oidc:<issuer>:<value>`, so a claim shared by a site's staff (e.g. an
+//!   organization claim) gives them one quota and one dataset ownership;
+//! - the role is the highest that `OIDC_ROLE_MAP` (`value=role` pairs, comma separated) gives
+//!   any value of `OIDC_ROLES_CLAIM` (default `roles`, a string or an array), else
+//!   `OIDC_DEFAULT_ROLE`; tokens that map to no role are rejected.
+//!
+//! The first time an instance sees an identity with a role it appends `oidc_identity` (issuer,
+//! subject, tenant, `key_id`, role) to the audit chain, so the fingerprints recorded by later
+//! entries resolve to an SSO identity.
+
+use crate::auth::{self, Caller, Role};
+use crate::errors::ApiError;
+use crate::state::AppState;
+use base64::Engine;
+use ring::signature::{self, RsaPublicKeyComponents, UnparsedPublicKey};
+use serde::Deserialize;
+use std::collections::BTreeSet;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+
+const DEFAULT_JWKS_TTL_SECS: u64 = 3600;
+
+/// A token naming an unknown key refetches the JWKS at most this often.
+const JWKS_MIN_REFETCH: Duration = Duration::from_secs(30);
+
+const CLOCK_LEEWAY_SECS: i64 = 60;
+
+const FETCH_TIMEOUT: Duration = Duration::from_secs(10);
+
+static JWKS: Mutex<Option<CachedJwks>> = Mutex::const_new(None);
+
+/// `key_id`s and roles already recorded in the audit chain by this instance.
+static RECORDED: std::sync::Mutex<BTreeSet<String>> = std::sync::Mutex::new(BTreeSet::new());
+
+struct CachedJwks {
+    keys: Vec<Jwk>,
+    fetched_at: Instant,
+}
+
+#[derive(Debug, Deserialize)]
+struct Discovery {
+    issuer: String,
+    jwks_uri: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct JwkSet {
+    keys: Vec<Jwk>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct Jwk {
+    kty: String,
+    kid: Option<String>,
+    /// RSA modulus and exponent.
+    n: Option<String>,
+    e: Option<String>,
+    /// EC curve and point.
+    crv: Option<String>,
+    x: Option<String>,
+    y: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Header {
+    alg: String,
+    kid: Option<String>,
+}
+
+#[derive(Debug)]
+struct Config {
+    issuer: String,
+    audience: String,
+    tenant_claim: String,
+    roles_claim: String,
+    role_map: Vec<(String, Role)>,
+    default_role: Option<Role>,
+    jwks_ttl: Duration,
+}
+
+fn env(name: &str) -> Option<String> {
+    std::env::var(name).ok().map(|v| v.trim().to_string()).filter(|v| !v.is_empty())
+}
+
+/// The OIDC configuration; `None` unless `OIDC_ISSUER` and `OIDC_AUDIENCE` are set (startup
+/// configuration checks refuse one without the other).
+fn config() -> Option<Config> {
+    let issuer = env("OIDC_ISSUER")?.trim_end_matches('/').to_string();
+    let role_map = env("OIDC_ROLE_MAP")
+        .unwrap_or_default()
+        .split(',')
+        .filter_map(|entry| {
+            let (value, role) = entry.split_once('=')?;
+            Some((value.trim().to_string(), Role::parse(role)?))
+        })
+        .collect();
+    Some(Config {
+        issuer,
+        audience: env("OIDC_AUDIENCE")?,
+        tenant_claim: env("OIDC_TENANT_CLAIM").unwrap_or_else(|| "sub".to_string()),
+        roles_claim: env("OIDC_ROLES_CLAIM").unwrap_or_else(|| "roles".to_string()),
+        role_map,
+        default_role: env("OIDC_DEFAULT_ROLE").and_then(|r| Role::parse(&r)),
+        jwks_ttl: Duration::from_secs(env("OIDC_JWKS_TTL_SECS").and_then(|v| v.parse().ok()).unwrap_or(DEFAULT_JWKS_TTL_SECS)),
+    })
+}
+
+async fn fetch_jwks(issuer: &str) -> Result<Vec<Jwk>, ApiError> {
+    let client = reqwest::Client::builder().timeout(FETCH_TIMEOUT).build().map_err(|_| ApiError::Internal)?;
+    let get = |url: String| {
+        let client = client.clone();
+        async move {
+            let res = client.get(&url).send().await.map_err(|e| ApiError::Upstream(format!("{url}: {e}")))?;
+            if !res.status().is_success() {
+                return Err(ApiError::Upstream(format!("{url}: HTTP {}", res.status())));
+            }
+            Ok(res)
+        }
+    };
+
+    let discovery: Discovery = get(format!("{issuer}/.well-known/openid-configuration"))
+        .await?
+        .json()
+        .await
+        .map_err(|e| ApiError::Upstream(format!("OIDC discovery: {e}")))?;
+    if discovery.issuer.trim_end_matches('/') != issuer {
+        return Err(ApiError::Upstream(format!("OIDC discovery names issuer {}", discovery.issuer)));
+    }
+    let jwks: JwkSet = get(discovery.jwks_uri)
+        .await?
+        .json()
+        .await
+        .map_err(|e| ApiError::Upstream(format!("OIDC JWKS: {e}")))?;
+    Ok(jwks.keys)
+}
+
+/// The issuer's key `kid` (its only key if the token names none), from the cache or refetched.
+async fn signing_key(config: &Config, kid: Option<&str>) -> Result<Option<Jwk>, ApiError> {
+    let find = |keys: &[Jwk]| match kid {
+        Some(kid) => keys.iter().find(|k| k.kid.as_deref() == Some(kid)).cloned(),
+        None => (keys.len() == 1).then(|| keys[0].clone()),
+    };
+
+    let mut cache = JWKS.lock().await;
+    if let Some(cached) = cache.as_ref() {
+        let age = cached.fetched_at.elapsed();
+        if age < config.jwks_ttl {
+            if let Some(key) = find(&cached.keys) {
+                return Ok(Some(key));
+            }
+            if age < JWKS_MIN_REFETCH {
+                return Ok(None);
+            }
+        }
+    }
+
+    let keys = fetch_jwks(&config.issuer).await?;
+    let key = find(&keys);
+    *cache = Some(CachedJwks {
+        keys,
+        fetched_at: Instant::now(),
+    });
+    Ok(key)
+}
+
+fn b64url(s: &str) -> Result<Vec<u8>, String> {
+    base64::engine::general_purpose::URL_SAFE_NO_PAD
+        .decode(s.trim_end_matches('='))
+        .map_err(|_| "malformed base64url".to_string())
+}
+
+fn verify_signature(alg: &str, key: &Jwk, message: &[u8], sig: &[u8]) -> Result<(), String> {
+    let field = |v: &Option<String>, name: &str| v.as_deref().ok_or(format!("JWK lacks {name}")).and_then(b64url);
+    match (alg, key.kty.as_str()) {
+        ("RS256", "RSA") => RsaPublicKeyComponents {
+            n: field(&key.n, "n")?,
+            e: field(&key.e, "e")?,
+        }
+        .verify(&signature::RSA_PKCS1_2048_8192_SHA256, message, sig)
+        .map_err(|_| "token signature does not verify".to_string()),
+        ("ES256", "EC") if key.crv.as_deref() == Some("P-256") => {
+            let point = [vec![0x04], field(&key.x, "x")?, field(&key.y, "y")?].concat();
+            UnparsedPublicKey::new(&signature::ECDSA_P256_SHA256_FIXED, point)
+                .verify(message, sig)
+                .map_err(|_| "token signature does not verify".to_string())
+        }
+        _ => Err(format!("unsupported token alg {alg} for a {} key", key.kty)),
+    }
+}
+
+/// String values of a claim that is a string or an array of strings.
+fn claim_values(claims: &serde_json::Value, name: &str) -> Vec<String> {
+    match &claims[name] {
+        serde_json::Value::String(s) => vec![s.clone()],
+        serde_json::Value::Array(values) => values.iter().filter_map(|v| v.as_str().map(str::to_string)).collect(),
+        _ => Vec::new(),
+    }
+}
+
+fn check_claims(config: &Config, claims: &serde_json::Value) -> Result<(), String> {
+    if claims["iss"].as_str().map(|i| i.trim_end_matches('/')) != Some(config.issuer.as_str()) {
+        return Err("token issuer mismatch".to_string());
+    }
+    if !claim_values(claims, "aud").contains(&config.audience) {
+        return Err("token audience mismatch".to_string());
+    }
+    let now = chrono::Utc::now().timestamp();
+    match claims["exp"].as_i64() {
+        Some(exp) if exp + CLOCK_LEEWAY_SECS >= now => {}
+        Some(_) => return Err("token expired".to_string()),
+        None => return Err("token lacks exp".to_string()),
+    }
+    if claims["nbf"].as_i64().is_some_and(|nbf| nbf - CLOCK_LEEWAY_SECS > now) {
+        return Err("token not yet valid".to_string());
+    }
+    Ok(())
+}
+
+/// The highest role the token's role claim values map to, else the default.
+fn mapped_role(config: &Config, claims: &serde_json::Value) -> Option<Role> {
+    let values = claim_values(claims, &config.roles_claim);
+    config
+        .role_map
+        .iter()
+        .filter(|(value, _)| values.contains(value))
+        .map(|(_, role)| *role)
+        .reduce(|a, b| if b.satisfies(a) { b } else { a })
+        .or(config.default_role)
+}
+
+/// Resolve a bearer token to a caller. `None` if OIDC is off or the token doesn't authenticate;
+/// an error only if the issuer can't be reached.
+pub async fn resolve_bearer(state: &AppState, token: &str) -> Result<Option<Caller>, ApiError> {
+    let Some(config) = config() else {
+        return Ok(None);
+    };
+    match validate(&config, token).await? {
+        Ok(claims) => identify(state, &config, &claims).await,
+        Err(reason) => {
+            tracing::warn!(%reason, "rejected OIDC token");
+            Ok(None)
+        }
+    }
+}
+
+/// The token's claims if it is valid; the reason if not.
+async fn validate(config: &Config, token: &str) -> Result<Result<serde_json::Value, String>, ApiError> {
+    // A JWS signs `header.payload`.
+    let Some(((header, payload), sig, signed)) = token.rsplit_once('.').and_then(|(signed, sig)| Some((signed.split_once('.')?, sig, signed))) else {
+        return Ok(Err("malformed token".to_string()));
+    };
+    let parsed = (|| {
+        let header: Header = serde_json::from_slice(&b64url(header)?).map_err(|_| "malformed token header".to_string())?;
+        let claims: serde_json::Value = serde_json::from_slice(&b64url(payload)?).map_err(|_| "malformed token claims".to_string())?;
+        Ok::<_, String>((header, claims, b64url(sig)?))
+    })();
+    let (header, claims, sig) = match parsed {
+        Ok(parsed) => parsed,
+        Err(reason) => return Ok(Err(reason)),
+    };
+
+    let Some(key) = signing_key(config, header.kid.as_deref()).await? else {
+        return Ok(Err("token signed with a key the issuer doesn't list".to_string()));
+    };
+    Ok(verify_signature(&header.alg, &key, signed.as_bytes(), &sig)
+        .and_then(|()| check_claims(config, &claims))
+        .map(|()| claims))
+}
+
+async fn identify(state: &AppState, config: &Config, claims: &serde_json::Value) -> Result<Option<Caller>, ApiError> {
+    let Some(tenant) = claim_values(claims, &config.tenant_claim).into_iter().next() else {
+        tracing::warn!(claim = %config.tenant_claim, "OIDC token lacks the tenant claim");
+        return Ok(None);
+    };
+    let Some(role) = mapped_role(config, claims) else {
+        tracing::warn!(%tenant, "OIDC token maps to no role");
+        return Ok(None);
+    };
+    let key_id = auth::key_id(&format!("oidc:{}:{tenant}", config.issuer));
+
+    let first_seen = RECORDED.lock().map(|mut recorded| recorded.insert(format!("{key_id}:{}", role.name()))).unwrap_or(false);
+    if first_seen {
+        state.store.append_audit(
+            None,
+            "oidc_identity",
+            &serde_json::json!({
+                "issuer": config.issuer,
+                "subject": claims["sub"],
+                "tenant_claim": config.tenant_claim,
+                "tenant": tenant,
+                "key_id": key_id,
+                "role": role.name(),
+            }),
+        )
+        .await?;
+    }
+
+    Ok(Some(Caller {
+        key_id,
+        role,
+        scopes: None,
+    }))
+}
@@ -0,0 +1,2173 @@
+//! Postgres persistence for the ledger (`store::PgStore`), used when `DATABASE_URL` is a
+//! `postgres://` URL so several backend instances can share one ledger.
+//!
+//! Tables, columns and column types mirror the SQLite schema in `db` (text ids and RFC 3339
+//! timestamps, `BIGINT` integers), so rows decode through the same `db` helpers and the audit
+//! chain hashes the same way on either database. The ledger, the job queue and the operational
+//! state derived from it (aggregate proofs, verification reports, anomaly analyses, curve
+//! migrations, key usage, log anchors) live here, so any instance can claim a queued job
+//! (`FOR UPDATE SKIP LOCKED`) and serve what another instance produced.
+
+use crate::db::{
+    self, AggregateProofRow, AnomalyAnalysisRow, ApiKeyRow, AuditRow, BucketAggregate, BucketTotals, CellDisclosureRow, CurveMigrationRow, CurveShardRow, DatasetAclRow,
+    DatasetQualityRow, DatasetReverificationRow, DatasetRow, KeyUsageRow, LedgerRow, LogAnchorRow, LogLeafRow, NewDataset, PrivacyBudgetRow, QueryResult, NewApiKey, QueryRow,
+    QuerySpec, ShardFailureRow, ShardListRow, TombstoneRow,
+};
+use crate::deadline;
+use crate::errors::ApiError;
+use crate::quality::{IngestQuality, ShardQuality};
+use chrono::{DateTime, Utc};
+use sqlx::postgres::{PgConnectOptions, PgPoolOptions, PgRow};
+use sqlx::{Executor, Pool, Postgres, Row};
+use std::ops::Range;
+use std::str::FromStr;
+use uuid::Uuid;
+use zk_proofs::types::{AgeBuckets, Curve, FieldSet, ShardStats};
+
+pub type PgDb = Pool<Postgres>;
+
+/// Advisory lock key serializing audit appends across every instance sharing the database.
+const AUDIT_LOCK_KEY: i64 = 0x6c65_6467_6572_6175;
+
+/// Advisory lock key serializing transparency log appends, so leaf indices are dense.
+const LOG_LOCK_KEY: i64 = 0x6c65_6467_6572_6c67;
+
+impl LedgerRow for PgRow {
+    fn text(&self, i: usize) -> String {
+        self.get(i)
+    }
+
+    fn opt_text(&self, i: usize) -> Option<String> {
+        self.get(i)
+    }
+
+    fn int(&self, i: usize) -> i64 {
+        self.get(i)
+    }
+
+    fn opt_int(&self, i: usize) -> Option<i64> {
+        self.get(i)
+    }
+}
+
+/// Whether `url` selects the Postgres ledger.
+pub fn is_postgres_url(url: &str) -> bool {
+    url.starts_with("postgres://") || url.starts_with("postgresql://")
+}
+
+pub async fn connect(url: &str) -> Result<PgDb, ApiError> {
+    let mut options = PgConnectOptions::from_str(url).map_err(|e| {
+        tracing::error!(error = %e, "invalid DATABASE_URL");
+        ApiError::Internal
+    })?;
+    // A statement keeps running server-side after the request that issued it is dropped; bound
+    // it by the request timeout (see `deadline`).
+    if let Some(timeout) = deadline::request_timeout() {
+        options = options.options([("statement_timeout", timeout.as_millis())]);
+    }
+    PgPoolOptions::new().max_connections(10).connect_with(options).await.map_err(|e| {
+        tracing::error!(error = %e, "failed to connect to Postgres");
+        ApiError::Internal
+    })
+}
+
+pub async fn ping(db: &PgDb) -> Result<(), ApiError> {
+    sqlx::query("SELECT 1").execute(db).await.map_err(|e| {
+        tracing::warn!(error = %e, "Postgres ping failed");
+        ApiError::Internal
+    })?;
+    Ok(())
+}
+
+pub async fn init_schema(db: &PgDb) -> Result<(), ApiError> {
+    // A plain string runs as one simple query, so the whole schema goes in a single round trip.
+    db.execute(
+        r#"
+CREATE TABLE IF NOT EXISTS datasets (
+  id TEXT PRIMARY KEY,
+  created_at TEXT NOT NULL,
+  dataset_size BIGINT NOT NULL,
+  shard_size BIGINT NOT NULL,
+  num_buckets BIGINT NOT NULL,
+  status TEXT NOT NULL,
+  dataset_commitment_hex TEXT,
+  error TEXT,
+  consent_scope_json TEXT,
+  requires_approval BIGINT NOT NULL DEFAULT 0,
+  release_limit BIGINT,
+  generator TEXT,
+  manifest_json TEXT,
+  ingest_quality_json TEXT,
+  owner_key_id TEXT,
+  frozen_at TEXT,
+  frozen_by TEXT,
+  imported_from TEXT,
+  external_vk_b64 TEXT,
+  field_set TEXT,
+  chain_hash TEXT,
+  sha256_commitment BIGINT NOT NULL DEFAULT 0,
+  age_buckets_json TEXT,
+  window_shards BIGINT,
+  access_restricted BIGINT NOT NULL DEFAULT 0,
+  generator_params_json TEXT
+);
+
+-- Columns added after the first Postgres schema.
+ALTER TABLE datasets ADD COLUMN IF NOT EXISTS access_restricted BIGINT NOT NULL DEFAULT 0;
+ALTER TABLE datasets ADD COLUMN IF NOT EXISTS generator_params_json TEXT;
+
+CREATE INDEX IF NOT EXISTS datasets_owner ON datasets (owner_key_id);
+
+CREATE TABLE IF NOT EXISTS shards (
+  dataset_id TEXT NOT NULL,
+  shard_index BIGINT NOT NULL,
+  shard_commitment_hex TEXT NOT NULL,
+  stats_json TEXT NOT NULL,
+  proof_hash TEXT NOT NULL,
+  verified BIGINT NOT NULL,
+  quality_json TEXT,
+  sealed_master_salt_b64 TEXT,
+  vk_version TEXT,
+  verified_at TEXT,
+  verifier_vk_hash TEXT,
+  PRIMARY KEY(dataset_id, shard_index)
+);
+
+ALTER TABLE shards ADD COLUMN IF NOT EXISTS vk_version TEXT;
+ALTER TABLE shards ADD COLUMN IF NOT EXISTS verified_at TEXT;
+ALTER TABLE shards ADD COLUMN IF NOT EXISTS verifier_vk_hash TEXT;
+
+CREATE INDEX IF NOT EXISTS shards_proof_hash ON shards (proof_hash);
+
+CREATE TABLE IF NOT EXISTS proof_blobs (
+  hash TEXT PRIMARY KEY,
+  proof_b64 TEXT NOT NULL,
+  created_at TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS shard_failures (
+  dataset_id TEXT NOT NULL,
+  shard_index BIGINT NOT NULL,
+  error_class TEXT NOT NULL,
+  attempts BIGINT NOT NULL,
+  last_error TEXT NOT NULL,
+  first_failed_at TEXT NOT NULL,
+  last_failed_at TEXT NOT NULL,
+  PRIMARY KEY(dataset_id, shard_index)
+);
+
+CREATE TABLE IF NOT EXISTS queries (
+  id TEXT PRIMARY KEY,
+  dataset_id TEXT NOT NULL,
+  created_at TEXT NOT NULL,
+  query_json TEXT NOT NULL,
+  result_json TEXT NOT NULL,
+  verified BIGINT NOT NULL,
+  status TEXT NOT NULL DEFAULT 'released',
+  decided_by TEXT,
+  release_key TEXT,
+  released_at TEXT,
+  error TEXT,
+  dataset_commitment_hex TEXT,
+  shards_total BIGINT,
+  verified_bitmap_hex TEXT,
+  first_shard_index BIGINT,
+  requested_by TEXT
+);
+
+ALTER TABLE queries ADD COLUMN IF NOT EXISTS requested_by TEXT;
+
+CREATE INDEX IF NOT EXISTS queries_dataset ON queries (dataset_id, released_at);
+
+CREATE TABLE IF NOT EXISTS aggregates (
+  dataset_id TEXT NOT NULL,
+  bucket_index BIGINT NOT NULL,
+  shards BIGINT NOT NULL,
+  count BIGINT NOT NULL,
+  sums_json TEXT NOT NULL,
+  sum_sq BIGINT NOT NULL,
+  sum_sq_shards BIGINT NOT NULL,
+  histogram_json TEXT NOT NULL,
+  histogram_shards BIGINT NOT NULL,
+  PRIMARY KEY(dataset_id, bucket_index)
+);
+
+CREATE TABLE IF NOT EXISTS dataset_acl (
+  dataset_id TEXT NOT NULL,
+  grantee_kind TEXT NOT NULL,
+  grantee TEXT NOT NULL,
+  granted_by TEXT NOT NULL,
+  granted_at TEXT NOT NULL,
+  PRIMARY KEY(dataset_id, grantee_kind, grantee)
+);
+
+CREATE TABLE IF NOT EXISTS api_keys (
+  key_id TEXT PRIMARY KEY,
+  key_hash TEXT NOT NULL UNIQUE,
+  name TEXT NOT NULL,
+  role TEXT NOT NULL,
+  scopes_json TEXT,
+  created_by TEXT NOT NULL,
+  created_at TEXT NOT NULL,
+  revoked_at TEXT
+);
+
+CREATE TABLE IF NOT EXISTS query_quota_usage (
+  key_id TEXT NOT NULL,
+  day TEXT NOT NULL,
+  queries BIGINT NOT NULL,
+  PRIMARY KEY(key_id, day)
+);
+
+CREATE TABLE IF NOT EXISTS dataset_tombstones (
+  dataset_id TEXT PRIMARY KEY,
+  deleted_at TEXT NOT NULL,
+  deleted_by TEXT NOT NULL,
+  reason TEXT NOT NULL,
+  dataset_commitment_hex TEXT
+);
+
+CREATE TABLE IF NOT EXISTS privacy_budget (
+  dataset_id TEXT PRIMARY KEY,
+  epsilon_spent DOUBLE PRECISION NOT NULL,
+  delta_spent DOUBLE PRECISION NOT NULL,
+  releases BIGINT NOT NULL,
+  updated_at TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS released_cells (
+  query_id TEXT NOT NULL,
+  dataset_id TEXT NOT NULL,
+  bucket_index BIGINT NOT NULL,
+  filter_key TEXT NOT NULL,
+  released_at TEXT NOT NULL,
+  PRIMARY KEY(query_id, bucket_index, filter_key)
+);
+
+CREATE INDEX IF NOT EXISTS released_cells_dataset ON released_cells (dataset_id);
+
+CREATE TABLE IF NOT EXISTS audit_log (
+  seq BIGSERIAL PRIMARY KEY,
+  created_at TEXT NOT NULL,
+  dataset_id TEXT,
+  event TEXT NOT NULL,
+  details_json TEXT NOT NULL,
+  prev_hash TEXT NOT NULL,
+  entry_hash TEXT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS audit_log_dataset ON audit_log (dataset_id, seq);
+
+CREATE TABLE IF NOT EXISTS transparency_log (
+  seq BIGINT PRIMARY KEY,
+  created_at TEXT NOT NULL,
+  kind TEXT NOT NULL,
+  dataset_id TEXT NOT NULL,
+  shard_index BIGINT,
+  commitment_hex TEXT NOT NULL,
+  leaf_hash TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS jobs (
+  id TEXT PRIMARY KEY,
+  kind TEXT NOT NULL,
+  subject_id TEXT NOT NULL,
+  status TEXT NOT NULL,
+  created_at TEXT NOT NULL,
+  started_at TEXT,
+  finished_at TEXT,
+  error TEXT,
+  tenant TEXT,
+  instance TEXT,
+  claimed_by TEXT,
+  heartbeat_at TEXT
+);
+
+CREATE INDEX IF NOT EXISTS jobs_queue ON jobs (kind, status, created_at);
+CREATE INDEX IF NOT EXISTS jobs_subject ON jobs (subject_id, kind);
+
+CREATE TABLE IF NOT EXISTS aggregate_proofs (
+  dataset_id TEXT PRIMARY KEY,
+  created_at TEXT NOT NULL,
+  dataset_commitment_hex TEXT NOT NULL,
+  shard_inputs_root_hex TEXT NOT NULL,
+  shards_total BIGINT NOT NULL,
+  totals_json TEXT NOT NULL,
+  proof_b64 TEXT NOT NULL,
+  vk_b64 TEXT NOT NULL,
+  key_id TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS dataset_reverifications (
+  dataset_id TEXT PRIMARY KEY,
+  finished_at TEXT NOT NULL,
+  dataset_commitment_hex TEXT NOT NULL,
+  shards_total BIGINT NOT NULL,
+  shards_checked BIGINT NOT NULL,
+  failed_shards_json TEXT NOT NULL,
+  commitment_matches BIGINT NOT NULL,
+  duration_ms BIGINT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS dataset_anomalies (
+  dataset_id TEXT PRIMARY KEY,
+  analyzed_at TEXT NOT NULL,
+  dataset_commitment_hex TEXT NOT NULL,
+  shards_stored BIGINT NOT NULL,
+  shards_analyzed BIGINT NOT NULL,
+  warnings_total BIGINT NOT NULL,
+  warnings_json TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS curve_migrations (
+  dataset_id TEXT NOT NULL,
+  curve TEXT NOT NULL,
+  status TEXT NOT NULL,
+  reason TEXT,
+  dataset_commitment_hex TEXT,
+  key_id TEXT,
+  created_at TEXT NOT NULL,
+  finished_at TEXT,
+  PRIMARY KEY(dataset_id, curve)
+);
+
+CREATE TABLE IF NOT EXISTS curve_shards (
+  dataset_id TEXT NOT NULL,
+  curve TEXT NOT NULL,
+  shard_index BIGINT NOT NULL,
+  shard_commitment_hex TEXT NOT NULL,
+  salt_commitment_hex TEXT NOT NULL,
+  stats_json TEXT NOT NULL,
+  proof_b64 TEXT NOT NULL,
+  sealed_master_salt_b64 TEXT NOT NULL,
+  key_id TEXT NOT NULL,
+  PRIMARY KEY(dataset_id, curve, shard_index)
+);
+
+CREATE TABLE IF NOT EXISTS zk_key_usage (
+  key_id TEXT PRIMARY KEY,
+  circuit TEXT NOT NULL,
+  first_seen_at TEXT NOT NULL,
+  last_proof_at TEXT NOT NULL,
+  proofs_created BIGINT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS zk_key_datasets (
+  key_id TEXT NOT NULL,
+  dataset_id TEXT NOT NULL,
+  PRIMARY KEY(key_id, dataset_id)
+);
+
+CREATE TABLE IF NOT EXISTS log_anchors (
+  seq BIGSERIAL PRIMARY KEY,
+  anchored_at TEXT NOT NULL,
+  method TEXT NOT NULL,
+  tree_size BIGINT NOT NULL,
+  root_hash_hex TEXT NOT NULL,
+  txid TEXT,
+  receipt_hex TEXT
+);
+"#,
+    )
+    .await
+    .map_err(|_| ApiError::Internal)?;
+
+    backfill_aggregates(db).await
+}
+
+/// Build the `aggregates` rows of datasets whose shards were stored before the table existed.
+async fn backfill_aggregates(db: &PgDb) -> Result<(), ApiError> {
+    let rows = sqlx::query(
+        r#"SELECT DISTINCT dataset_id FROM shards s
+           WHERE NOT EXISTS (SELECT 1 FROM aggregates a WHERE a.dataset_id = s.dataset_id)"#,
+    )
+    .fetch_all(db)
+    .await
+    .map_err(|_| ApiError::Internal)?;
+
+    for row in rows {
+        let dataset_id = row.text(0);
+        let mut tx = db.begin().await.map_err(|_| ApiError::Internal)?;
+        lock_dataset(&mut tx, &dataset_id).await?;
+        let shards = sqlx::query(r#"SELECT stats_json FROM shards WHERE dataset_id = $1"#)
+            .bind(&dataset_id)
+            .fetch_all(&mut *tx)
+            .await
+            .map_err(|_| ApiError::Internal)?;
+        let aggregates = db::sum_bucket_aggregates(&shards)?;
+        put_aggregates(&mut tx, &dataset_id, &aggregates).await?;
+        tx.commit().await.map_err(|_| ApiError::Internal)?;
+        tracing::info!(%dataset_id, shards = shards.len(), "backfilled dataset aggregates");
+    }
+    Ok(())
+}
+
+/// Serialize writers of one dataset's shards and aggregates, across instances.
+async fn lock_dataset(tx: &mut sqlx::Transaction<'_, Postgres>, dataset_id: &str) -> Result<(), ApiError> {
+    sqlx::query(r#"SELECT 1 FROM datasets WHERE id = $1 FOR UPDATE"#)
+        .bind(dataset_id)
+        .execute(&mut **tx)
+        .await
+        .map_err(|_| ApiError::Internal)?;
+    Ok(())
+}
+
+async fn put_aggregates(tx: &mut sqlx::Transaction<'_, Postgres>, dataset_id: &str, aggregates: &[BucketAggregate]) -> Result<(), ApiError> {
+    for (bucket_index, a) in aggregates.iter().enumerate() {
+        sqlx::query(
+            r#"INSERT INTO aggregates
+               (dataset_id, bucket_index, shards, count, sums_json, sum_sq, sum_sq_shards, histogram_json, histogram_shards)
+               VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+               ON CONFLICT (dataset_id, bucket_index) DO UPDATE SET
+                 shards = excluded.shards,
+                 count = excluded.count,
+                 sums_json = excluded.sums_json,
+                 sum_sq = excluded.sum_sq,
+                 sum_sq_shards = excluded.sum_sq_shards,
+                 histogram_json = excluded.histogram_json,
+                 histogram_shards = excluded.histogram_shards"#,
+        )
+        .bind(dataset_id)
+        .bind(bucket_index as i64)
+        .bind(a.shards as i64)
+        .bind(a.count as i64)
+        .bind(serde_json::to_string(&a.sums).map_err(|_| ApiError::Internal)?)
+        .bind(a.sum_sq as i64)
+        .bind(a.sum_sq_shards as i64)
+        .bind(serde_json::to_string(&a.glucose_histogram).map_err(|_| ApiError::Internal)?)
+        .bind(a.histogram_shards as i64)
+        .execute(&mut **tx)
+        .await
+        .map_err(|_| ApiError::Internal)?;
+    }
+    Ok(())
+}
+
+// --- Datasets ---
+
+pub async fn insert_dataset(db: &PgDb, dataset: &NewDataset<'_>) -> Result<(), ApiError> {
+    let (consent_scope_json, age_buckets_json, ingest_quality_json, generator_params_json) = db::new_dataset_json(dataset)?;
+
+    sqlx::query(
+        r#"INSERT INTO datasets
+           (id, created_at, dataset_size, shard_size, num_buckets, status, consent_scope_json, requires_approval,
+            release_limit, generator, ingest_quality_json, owner_key_id, field_set, chain_hash, sha256_commitment,
+            age_buckets_json, window_shards, generator_params_json)
+           VALUES ($1, $2, $3, $4, $5, 'generating', $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17)"#,
+    )
+    .bind(dataset.dataset_id.to_string())
+    .bind(Utc::now().to_rfc3339())
+    .bind(dataset.dataset_size as i64)
+    .bind(dataset.shard_size as i64)
+    .bind(dataset.age_buckets.num_buckets() as i64)
+    .bind(consent_scope_json)
+    .bind(if dataset.requires_approval { 1i64 } else { 0i64 })
+    .bind(dataset.release_limit.map(|l| l as i64))
+    .bind(dataset.generator)
+    .bind(ingest_quality_json)
+    .bind(dataset.owner)
+    .bind(dataset.field_set.name())
+    .bind(dataset.chain_hash.name())
+    .bind(if dataset.sha256_commitment { 1i64 } else { 0i64 })
+    .bind(age_buckets_json)
+    .bind(dataset.window_shards.map(|w| w as i64))
+    .bind(generator_params_json)
+    .execute(db)
+    .await
+    .map_err(|_| ApiError::Internal)?;
+
+    Ok(())
+}
+
+pub async fn get_dataset(db: &PgDb, dataset_id: Uuid) -> Result<Option<DatasetRow>, ApiError> {
+    let row = sqlx::query(&format!("SELECT {} FROM datasets WHERE id = $1", db::DATASET_COLUMNS))
+        .bind(dataset_id.to_string())
+        .fetch_optional(db)
+        .await
+        .map_err(|_| ApiError::Internal)?;
+
+    row.map(|row| db::dataset_row(&row)).transpose()
+}
+
+pub async fn list_dataset_ids(db: &PgDb) -> Result<Vec<Uuid>, ApiError> {
+    let rows = sqlx::query(r#"SELECT id FROM datasets ORDER BY created_at"#)
+        .fetch_all(db)
+        .await
+        .map_err(|_| ApiError::Internal)?;
+
+    rows.iter()
+        .map(|r| Uuid::parse_str(&r.text(0)).map_err(|_| ApiError::Internal))
+        .collect()
+}
+
+/// Fetch one optional text column of a dataset.
+async fn dataset_text_column(db: &PgDb, dataset_id: Uuid, column: &str) -> Result<Option<String>, ApiError> {
+    let row = sqlx::query(&format!("SELECT {column} FROM datasets WHERE id = $1"))
+        .bind(dataset_id.to_string())
+        .fetch_optional(db)
+        .await
+        .map_err(|_| ApiError::Internal)?;
+    Ok(row.and_then(|r| r.opt_text(0)))
+}
+
+pub async fn dataset_owner(db: &PgDb, dataset_id: Uuid) -> Result<Option<String>, ApiError> {
+    dataset_text_column(db, dataset_id, "owner_key_id").await
+}
+
+pub async fn get_dataset_external_vk(db: &PgDb, dataset_id: Uuid) -> Result<Option<String>, ApiError> {
+    dataset_text_column(db, dataset_id, "external_vk_b64").await
+}
+
+pub async fn get_dataset_manifest(db: &PgDb, dataset_id: Uuid) -> Result<Option<serde_json::Value>, ApiError> {
+    let Some(manifest_json) = dataset_text_column(db, dataset_id, "manifest_json").await? else {
+        return Ok(None);
+    };
+    serde_json::from_str(&manifest_json).map(Some).map_err(|_| ApiError::Internal)
+}
+
+pub async fn set_dataset_ready(db: &PgDb, dataset_id: Uuid, commitment_hex: &str) -> Result<(), ApiError> {
+    sqlx::query(r#"UPDATE datasets SET status = 'ready', dataset_commitment_hex = $1, error = NULL WHERE id = $2 AND status != 'cancelled'"#)
+        .bind(commitment_hex)
+        .bind(dataset_id.to_string())
+        .execute(db)
+        .await
+        .map_err(|_| ApiError::Internal)?;
+    Ok(())
+}
+
+pub async fn extend_dataset(db: &PgDb, dataset_id: Uuid, dataset_size: u64, commitment_hex: &str) -> Result<bool, ApiError> {
+    let result = sqlx::query(
+        r#"UPDATE datasets SET dataset_size = $1, status = 'ready', dataset_commitment_hex = $2, error = NULL
+           WHERE id = $3 AND frozen_at IS NULL"#,
+    )
+    .bind(dataset_size as i64)
+    .bind(commitment_hex)
+    .bind(dataset_id.to_string())
+    .execute(db)
+    .await
+    .map_err(|_| ApiError::Internal)?;
+    Ok(result.rows_affected() == 1)
+}
+
+pub async fn set_dataset_failed(db: &PgDb, dataset_id: Uuid, error: &str) -> Result<(), ApiError> {
+    sqlx::query(r#"UPDATE datasets SET status = 'failed', error = $1 WHERE id = $2 AND status != 'cancelled'"#)
+        .bind(error)
+        .bind(dataset_id.to_string())
+        .execute(db)
+        .await
+        .map_err(|_| ApiError::Internal)?;
+    Ok(())
+}
+
+pub async fn cancel_dataset(db: &PgDb, dataset_id: Uuid) -> Result<bool, ApiError> {
+    let res = sqlx::query(r#"UPDATE datasets SET status = 'cancelled' WHERE id = $1 AND status = 'generating'"#)
+        .bind(dataset_id.to_string())
+        .execute(db)
+        .await
+        .map_err(|_| ApiError::Internal)?;
+    Ok(res.rows_affected() == 1)
+}
+
+pub async fn set_dataset_imported(
+    db: &PgDb,
+    dataset_id: Uuid,
+    commitment_hex: &str,
+    vk_b64: &str,
+    imported_from: &str,
+) -> Result<(), ApiError> {
+    sqlx::query(
+        r#"UPDATE datasets
+           SET status = 'ready', dataset_commitment_hex = $1, external_vk_b64 = $2, imported_from = $3, error = NULL
+           WHERE id = $4"#,
+    )
+    .bind(commitment_hex)
+    .bind(vk_b64)
+    .bind(imported_from)
+    .bind(dataset_id.to_string())
+    .execute(db)
+    .await
+    .map_err(|_| ApiError::Internal)?;
+    Ok(())
+}
+
+pub async fn set_dataset_federated(db: &PgDb, dataset_id: Uuid, vk_b64: &str, imported_from: &str) -> Result<(), ApiError> {
+    sqlx::query(r#"UPDATE datasets SET external_vk_b64 = $1, imported_from = $2 WHERE id = $3"#)
+        .bind(vk_b64)
+        .bind(imported_from)
+        .bind(dataset_id.to_string())
+        .execute(db)
+        .await
+        .map_err(|_| ApiError::Internal)?;
+    Ok(())
+}
+
+pub async fn set_dataset_ingest_quality(db: &PgDb, dataset_id: Uuid, quality: &IngestQuality) -> Result<(), ApiError> {
+    sqlx::query(r#"UPDATE datasets SET ingest_quality_json = $1 WHERE id = $2"#)
+        .bind(serde_json::to_string(quality).map_err(|_| ApiError::Internal)?)
+        .bind(dataset_id.to_string())
+        .execute(db)
+        .await
+        .map_err(|_| ApiError::Internal)?;
+    Ok(())
+}
+
+pub async fn set_dataset_manifest(db: &PgDb, dataset_id: Uuid, manifest: &serde_json::Value) -> Result<(), ApiError> {
+    sqlx::query(r#"UPDATE datasets SET manifest_json = $1 WHERE id = $2"#)
+        .bind(manifest.to_string())
+        .bind(dataset_id.to_string())
+        .execute(db)
+        .await
+        .map_err(|_| ApiError::Internal)?;
+    Ok(())
+}
+
+pub async fn freeze_dataset(db: &PgDb, dataset_id: Uuid, frozen_by: &str) -> Result<bool, ApiError> {
+    let res = sqlx::query(
+        r#"UPDATE datasets SET frozen_at = $1, frozen_by = $2
+           WHERE id = $3 AND status = 'ready' AND frozen_at IS NULL"#,
+    )
+    .bind(Utc::now().to_rfc3339())
+    .bind(frozen_by)
+    .bind(dataset_id.to_string())
+    .execute(db)
+    .await
+    .map_err(|_| ApiError::Internal)?;
+    Ok(res.rows_affected() == 1)
+}
+
+pub async fn unfreeze_dataset(db: &PgDb, dataset_id: Uuid) -> Result<bool, ApiError> {
+    let res = sqlx::query(r#"UPDATE datasets SET frozen_at = NULL, frozen_by = NULL WHERE id = $1 AND frozen_at IS NOT NULL"#)
+        .bind(dataset_id.to_string())
+        .execute(db)
+        .await
+        .map_err(|_| ApiError::Internal)?;
+    Ok(res.rows_affected() == 1)
+}
+
+pub async fn delete_dataset(db: &PgDb, dataset_id: Uuid, deleted_by: &str, reason: &str) -> Result<Option<TombstoneRow>, ApiError> {
+    let id = dataset_id.to_string();
+    let mut tx = db.begin().await.map_err(|_| ApiError::Internal)?;
+    let Some(row) = sqlx::query(r#"SELECT dataset_commitment_hex FROM datasets WHERE id = $1 FOR UPDATE"#)
+        .bind(&id)
+        .fetch_optional(&mut *tx)
+        .await
+        .map_err(|_| ApiError::Internal)?
+    else {
+        return Ok(None);
+    };
+    let commitment_hex = row.opt_text(0);
+
+    sqlx::query(
+        r#"DELETE FROM proof_blobs
+           WHERE hash IN (SELECT proof_hash FROM shards WHERE dataset_id = $1)
+             AND NOT EXISTS (SELECT 1 FROM shards s WHERE s.proof_hash = proof_blobs.hash AND s.dataset_id <> $1)"#,
+    )
+    .bind(&id)
+    .execute(&mut *tx)
+    .await
+    .map_err(|_| ApiError::Internal)?;
+    for table in db::DATASET_LEDGER_TABLES {
+        sqlx::query(&format!("DELETE FROM {table} WHERE dataset_id = $1"))
+            .bind(&id)
+            .execute(&mut *tx)
+            .await
+            .map_err(|_| ApiError::Internal)?;
+    }
+    sqlx::query(r#"DELETE FROM datasets WHERE id = $1"#)
+        .bind(&id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|_| ApiError::Internal)?;
+
+    let tombstone = TombstoneRow {
+        deleted_at: Utc::now(),
+        deleted_by: deleted_by.to_string(),
+        reason: reason.to_string(),
+        dataset_commitment_hex: commitment_hex,
+    };
+    sqlx::query(
+        r#"INSERT INTO dataset_tombstones (dataset_id, deleted_at, deleted_by, reason, dataset_commitment_hex)
+           VALUES ($1, $2, $3, $4, $5)
+           ON CONFLICT (dataset_id) DO UPDATE SET deleted_at = EXCLUDED.deleted_at, deleted_by = EXCLUDED.deleted_by,
+             reason = EXCLUDED.reason, dataset_commitment_hex = EXCLUDED.dataset_commitment_hex"#,
+    )
+    .bind(&id)
+    .bind(tombstone.deleted_at.to_rfc3339())
+    .bind(&tombstone.deleted_by)
+    .bind(&tombstone.reason)
+    .bind(&tombstone.dataset_commitment_hex)
+    .execute(&mut *tx)
+    .await
+    .map_err(|_| ApiError::Internal)?;
+    tx.commit().await.map_err(|_| ApiError::Internal)?;
+    Ok(Some(tombstone))
+}
+
+pub async fn dataset_tombstone(db: &PgDb, dataset_id: Uuid) -> Result<Option<TombstoneRow>, ApiError> {
+    sqlx::query(&format!("SELECT {} FROM dataset_tombstones WHERE dataset_id = $1", db::TOMBSTONE_COLUMNS))
+        .bind(dataset_id.to_string())
+        .fetch_optional(db)
+        .await
+        .map_err(|_| ApiError::Internal)?
+        .map(|row| db::tombstone_row(&row))
+        .transpose()
+}
+
+pub async fn expired_datasets(db: &PgDb, cutoff: DateTime<Utc>) -> Result<Vec<Uuid>, ApiError> {
+    let rows = sqlx::query(
+        r#"SELECT id FROM datasets
+           WHERE created_at < $1 AND frozen_at IS NULL AND status IN ('ready', 'failed')
+           ORDER BY created_at"#,
+    )
+    .bind(cutoff.to_rfc3339())
+    .fetch_all(db)
+    .await
+    .map_err(|_| ApiError::Internal)?;
+    rows.iter().map(|row| Uuid::parse_str(&row.text(0)).map_err(|_| ApiError::Internal)).collect()
+}
+
+pub async fn dataset_acl(db: &PgDb, dataset_id: Uuid) -> Result<DatasetAclRow, ApiError> {
+    let restricted = sqlx::query(r#"SELECT access_restricted FROM datasets WHERE id = $1"#)
+        .bind(dataset_id.to_string())
+        .fetch_optional(db)
+        .await
+        .map_err(|_| ApiError::Internal)?
+        .is_some_and(|row| row.int(0) != 0);
+    let rows = sqlx::query(&format!(
+        "SELECT {} FROM dataset_acl WHERE dataset_id = $1 ORDER BY granted_at, grantee_kind, grantee",
+        db::ACL_ENTRY_COLUMNS
+    ))
+    .bind(dataset_id.to_string())
+    .fetch_all(db)
+    .await
+    .map_err(|_| ApiError::Internal)?;
+    Ok(DatasetAclRow {
+        restricted,
+        entries: rows.iter().map(db::acl_entry_row).collect::<Result<_, _>>()?,
+    })
+}
+
+pub async fn grant_dataset_access(
+    db: &PgDb,
+    dataset_id: Uuid,
+    grantee_kind: &str,
+    grantee: &str,
+    granted_by: &str,
+) -> Result<bool, ApiError> {
+    let mut tx = db.begin().await.map_err(|_| ApiError::Internal)?;
+    let res = sqlx::query(
+        r#"INSERT INTO dataset_acl (dataset_id, grantee_kind, grantee, granted_by, granted_at)
+           VALUES ($1, $2, $3, $4, $5) ON CONFLICT DO NOTHING"#,
+    )
+    .bind(dataset_id.to_string())
+    .bind(grantee_kind)
+    .bind(grantee)
+    .bind(granted_by)
+    .bind(Utc::now().to_rfc3339())
+    .execute(&mut *tx)
+    .await
+    .map_err(|_| ApiError::Internal)?;
+    sqlx::query(r#"UPDATE datasets SET access_restricted = 1 WHERE id = $1"#)
+        .bind(dataset_id.to_string())
+        .execute(&mut *tx)
+        .await
+        .map_err(|_| ApiError::Internal)?;
+    tx.commit().await.map_err(|_| ApiError::Internal)?;
+    Ok(res.rows_affected() == 1)
+}
+
+pub async fn insert_api_key(db: &PgDb, key: &NewApiKey<'_>) -> Result<DateTime<Utc>, ApiError> {
+    let now = Utc::now();
+    sqlx::query(
+        r#"INSERT INTO api_keys (key_id, key_hash, name, role, scopes_json, created_by, created_at)
+           VALUES ($1, $2, $3, $4, $5, $6, $7)"#,
+    )
+    .bind(key.key_id)
+    .bind(key.key_hash)
+    .bind(key.name)
+    .bind(key.role)
+    .bind(db::api_key_scopes_json(key))
+    .bind(key.created_by)
+    .bind(now.to_rfc3339())
+    .execute(db)
+    .await
+    .map_err(|_| ApiError::Internal)?;
+    Ok(now)
+}
+
+pub async fn api_key_by_hash(db: &PgDb, key_hash: &str) -> Result<Option<ApiKeyRow>, ApiError> {
+    sqlx::query(&format!("SELECT {} FROM api_keys WHERE key_hash = $1", db::API_KEY_COLUMNS))
+        .bind(key_hash)
+        .fetch_optional(db)
+        .await
+        .map_err(|_| ApiError::Internal)?
+        .map(|row| db::api_key_row(&row))
+        .transpose()
+}
+
+pub async fn list_api_keys(db: &PgDb) -> Result<Vec<ApiKeyRow>, ApiError> {
+    let rows = sqlx::query(&format!("SELECT {} FROM api_keys ORDER BY created_at, key_id", db::API_KEY_COLUMNS))
+        .fetch_all(db)
+        .await
+        .map_err(|_| ApiError::Internal)?;
+    rows.iter().map(db::api_key_row).collect()
+}
+
+pub async fn revoke_api_key(db: &PgDb, key_id: &str) -> Result<bool, ApiError> {
+    let res = sqlx::query(r#"UPDATE api_keys SET revoked_at = $1 WHERE key_id = $2 AND revoked_at IS NULL"#)
+        .bind(Utc::now().to_rfc3339())
+        .bind(key_id)
+        .execute(db)
+        .await
+        .map_err(|_| ApiError::Internal)?;
+    Ok(res.rows_affected() == 1)
+}
+
+pub async fn take_daily_query(db: &PgDb, key_id: &str, day: &str, max: u64) -> Result<bool, ApiError> {
+    if max == 0 {
+        return Ok(false);
+    }
+    let res = sqlx::query(
+        r#"INSERT INTO query_quota_usage (key_id, day, queries) VALUES ($1, $2, 1)
+           ON CONFLICT (key_id, day) DO UPDATE SET queries = query_quota_usage.queries + 1
+           WHERE query_quota_usage.queries < $3"#,
+    )
+    .bind(key_id)
+    .bind(day)
+    .bind(max as i64)
+    .execute(db)
+    .await
+    .map_err(|_| ApiError::Internal)?;
+    Ok(res.rows_affected() == 1)
+}
+
+pub async fn refund_daily_query(db: &PgDb, key_id: &str, day: &str) -> Result<(), ApiError> {
+    sqlx::query(r#"UPDATE query_quota_usage SET queries = queries - 1 WHERE key_id = $1 AND day = $2 AND queries > 0"#)
+        .bind(key_id)
+        .bind(day)
+        .execute(db)
+        .await
+        .map_err(|_| ApiError::Internal)?;
+    Ok(())
+}
+
+pub async fn daily_queries(db: &PgDb, key_id: &str, day: &str) -> Result<u64, ApiError> {
+    let row = sqlx::query(r#"SELECT queries FROM query_quota_usage WHERE key_id = $1 AND day = $2"#)
+        .bind(key_id)
+        .bind(day)
+        .fetch_optional(db)
+        .await
+        .map_err(|_| ApiError::Internal)?;
+    Ok(row.map(|row| row.get::<i64, _>(0) as u64).unwrap_or(0))
+}
+
+pub async fn revoke_dataset_access(db: &PgDb, dataset_id: Uuid, grantee_kind: &str, grantee: &str) -> Result<bool, ApiError> {
+    let res = sqlx::query(r#"DELETE FROM dataset_acl WHERE dataset_id = $1 AND grantee_kind = $2 AND grantee = $3"#)
+        .bind(dataset_id.to_string())
+        .bind(grantee_kind)
+        .bind(grantee)
+        .execute(db)
+        .await
+        .map_err(|_| ApiError::Internal)?;
+    Ok(res.rows_affected() == 1)
+}
+
+pub async fn tenant_datasets(db: &PgDb, key_id: &str) -> Result<(u64, u64), ApiError> {
+    let row = sqlx::query(
+        r#"SELECT COUNT(*), COALESCE(SUM(CASE WHEN status NOT IN ('failed', 'cancelled') THEN dataset_size ELSE 0 END), 0)::BIGINT
+           FROM datasets WHERE owner_key_id = $1"#,
+    )
+    .bind(key_id)
+    .fetch_one(db)
+    .await
+    .map_err(|_| ApiError::Internal)?;
+
+    Ok((row.int(0) as u64, row.int(1) as u64))
+}
+
+pub async fn dataset_quality(db: &PgDb, dataset_id: Uuid, buckets: &AgeBuckets) -> Result<DatasetQualityRow, ApiError> {
+    let ingest_json = sqlx::query(r#"SELECT ingest_quality_json FROM datasets WHERE id = $1"#)
+        .bind(dataset_id.to_string())
+        .fetch_one(db)
+        .await
+        .map_err(|_| ApiError::Internal)?
+        .opt_text(0);
+
+    let rows = sqlx::query(r#"SELECT stats_json, quality_json FROM shards WHERE dataset_id = $1"#)
+        .bind(dataset_id.to_string())
+        .fetch_all(db)
+        .await
+        .map_err(|_| ApiError::Internal)?;
+
+    db::dataset_quality_row(ingest_json, &rows, buckets)
+}
+
+pub async fn dataset_totals(
+    db: &PgDb,
+    dataset_id: Uuid,
+    shards: Range<u64>,
+    field_set: FieldSet,
+    buckets: &AgeBuckets,
+) -> Result<(ShardStats, u64), ApiError> {
+    let rows = sqlx::query(r#"SELECT stats_json FROM shards WHERE dataset_id = $1 AND shard_index >= $2 AND shard_index < $3"#)
+        .bind(dataset_id.to_string())
+        .bind(shards.start.min(i64::MAX as u64) as i64)
+        .bind(shards.end.min(i64::MAX as u64) as i64)
+        .fetch_all(db)
+        .await
+        .map_err(|_| ApiError::Internal)?;
+
+    db::sum_shard_stats(&rows, field_set, buckets)
+}
+
+pub async fn aggregate_for_bucket(
+    db: &PgDb,
+    dataset_id: Uuid,
+    shards: Range<u64>,
+    bucket_index: usize,
+    field_index: usize,
+    buckets: &AgeBuckets,
+) -> Result<BucketTotals, ApiError> {
+    if bucket_index >= buckets.num_buckets() {
+        return Err(ApiError::BadRequest("invalid bucket".to_string()));
+    }
+
+    let verified = sqlx::query(
+        r#"SELECT shard_index, verified FROM shards
+           WHERE dataset_id = $1 AND shard_index >= $2 AND shard_index < $3
+           ORDER BY shard_index"#,
+    )
+    .bind(dataset_id.to_string())
+    .bind(shards.start.min(i64::MAX as u64) as i64)
+    .bind(shards.end.min(i64::MAX as u64) as i64)
+    .fetch_all(db)
+    .await
+    .map_err(|_| ApiError::Internal)?;
+    let aggregate = sqlx::query(&format!(
+        "SELECT {} FROM aggregates WHERE dataset_id = $1 AND bucket_index = $2",
+        db::AGGREGATE_COLUMNS
+    ))
+    .bind(dataset_id.to_string())
+    .bind(bucket_index as i64)
+    .fetch_optional(db)
+    .await
+    .map_err(|_| ApiError::Internal)?
+    .map(|row| db::bucket_aggregate_row(&row))
+    .transpose()?;
+    if let Some(aggregate) = aggregate
+        && aggregate.shards == verified.len() as u64
+    {
+        return aggregate.totals(field_index, &verified);
+    }
+
+    let rows = sqlx::query(
+        r#"SELECT shard_index, stats_json, verified FROM shards
+           WHERE dataset_id = $1 AND shard_index >= $2 AND shard_index < $3
+           ORDER BY shard_index"#,
+    )
+    .bind(dataset_id.to_string())
+    .bind(shards.start.min(i64::MAX as u64) as i64)
+    .bind(shards.end.min(i64::MAX as u64) as i64)
+    .fetch_all(db)
+    .await
+    .map_err(|_| ApiError::Internal)?;
+
+    db::bucket_totals(&rows, bucket_index, field_index, buckets)
+}
+
+// --- Shards ---
+
+pub async fn insert_shard(
+    db: &PgDb,
+    dataset_id: Uuid,
+    shard_index: u64,
+    shard_commitment_hex: &str,
+    stats: &ShardStats,
+    proof_b64: &str,
+    verified: bool,
+) -> Result<(), ApiError> {
+    let stats_json = serde_json::to_string(stats).map_err(|_| ApiError::Internal)?;
+    let proof_hash = db::proof_blob_hash(proof_b64)?;
+    let dataset_id = dataset_id.to_string();
+
+    let mut tx = db.begin().await.map_err(|_| ApiError::Internal)?;
+    lock_dataset(&mut tx, &dataset_id).await?;
+
+    sqlx::query(r#"INSERT INTO proof_blobs (hash, proof_b64, created_at) VALUES ($1, $2, $3) ON CONFLICT DO NOTHING"#)
+        .bind(&proof_hash)
+        .bind(proof_b64)
+        .bind(Utc::now().to_rfc3339())
+        .execute(&mut *tx)
+        .await
+        .map_err(|_| ApiError::Internal)?;
+
+    let replaced = sqlx::query(r#"SELECT stats_json FROM shards WHERE dataset_id = $1 AND shard_index = $2"#)
+        .bind(&dataset_id)
+        .bind(shard_index as i64)
+        .fetch_optional(&mut *tx)
+        .await
+        .map_err(|_| ApiError::Internal)?
+        .map(|r| serde_json::from_str::<ShardStats>(&r.text(0)).map_err(|_| ApiError::Internal))
+        .transpose()?;
+
+    // Replaces the whole row, as SQLite's INSERT OR REPLACE does: a re-proven shard starts without
+    // quality counts, a sealed salt, a key version or a verification time until they are stored
+    // again.
+    sqlx::query(
+        r#"INSERT INTO shards (dataset_id, shard_index, shard_commitment_hex, stats_json, proof_hash, verified)
+           VALUES ($1, $2, $3, $4, $5, $6)
+           ON CONFLICT (dataset_id, shard_index) DO UPDATE SET
+             shard_commitment_hex = excluded.shard_commitment_hex,
+             stats_json = excluded.stats_json,
+             proof_hash = excluded.proof_hash,
+             verified = excluded.verified,
+             quality_json = NULL,
+             sealed_master_salt_b64 = NULL,
+             vk_version = NULL,
+             verified_at = NULL,
+             verifier_vk_hash = NULL"#,
+    )
+    .bind(&dataset_id)
+    .bind(shard_index as i64)
+    .bind(shard_commitment_hex)
+    .bind(stats_json)
+    .bind(proof_hash)
+    .bind(if verified { 1i64 } else { 0i64 })
+    .execute(&mut *tx)
+    .await
+    .map_err(|_| ApiError::Internal)?;
+
+    let current = sqlx::query(&format!(
+        "SELECT {} FROM aggregates WHERE dataset_id = $1 ORDER BY bucket_index",
+        db::AGGREGATE_COLUMNS
+    ))
+    .bind(&dataset_id)
+    .fetch_all(&mut *tx)
+    .await
+    .map_err(|_| ApiError::Internal)?;
+    let mut aggregates = current.iter().map(db::bucket_aggregate_row).collect::<Result<Vec<_>, _>>()?;
+    if let Some(replaced) = &replaced {
+        db::apply_shard(&mut aggregates, replaced, false)?;
+    }
+    db::apply_shard(&mut aggregates, stats, true)?;
+    put_aggregates(&mut tx, &dataset_id, &aggregates).await?;
+
+    tx.commit().await.map_err(|_| ApiError::Internal)?;
+    Ok(())
+}
+
+pub async fn set_shard_quality(db: &PgDb, dataset_id: Uuid, shard_index: u64, quality: &ShardQuality) -> Result<(), ApiError> {
+    sqlx::query(r#"UPDATE shards SET quality_json = $1 WHERE dataset_id = $2 AND shard_index = $3"#)
+        .bind(serde_json::to_string(quality).map_err(|_| ApiError::Internal)?)
+        .bind(dataset_id.to_string())
+        .bind(shard_index as i64)
+        .execute(db)
+        .await
+        .map_err(|_| ApiError::Internal)?;
+    Ok(())
+}
+
+pub async fn set_shard_sealed_master_salt(db: &PgDb, dataset_id: Uuid, shard_index: u64, sealed: &[u8]) -> Result<(), ApiError> {
+    use base64::Engine;
+    sqlx::query(r#"UPDATE shards SET sealed_master_salt_b64 = $1 WHERE dataset_id = $2 AND shard_index = $3"#)
+        .bind(base64::engine::general_purpose::STANDARD.encode(sealed))
+        .bind(dataset_id.to_string())
+        .bind(shard_index as i64)
+        .execute(db)
+        .await
+        .map_err(|_| ApiError::Internal)?;
+    Ok(())
+}
+
+pub async fn set_shard_vk_version(db: &PgDb, dataset_id: Uuid, shard_index: u64, vk_version: &str) -> Result<(), ApiError> {
+    sqlx::query(r#"UPDATE shards SET vk_version = $1 WHERE dataset_id = $2 AND shard_index = $3"#)
+        .bind(vk_version)
+        .bind(dataset_id.to_string())
+        .bind(shard_index as i64)
+        .execute(db)
+        .await
+        .map_err(|_| ApiError::Internal)?;
+    Ok(())
+}
+
+pub async fn set_shards_verified(db: &PgDb, dataset_id: Uuid, shard_indices: &[u64], vk_hash: &str) -> Result<(), ApiError> {
+    let indices: Vec<i64> = shard_indices.iter().map(|i| *i as i64).collect();
+    sqlx::query(r#"UPDATE shards SET verified_at = $1, verifier_vk_hash = $2 WHERE dataset_id = $3 AND shard_index = ANY($4)"#)
+        .bind(Utc::now().to_rfc3339())
+        .bind(vk_hash)
+        .bind(dataset_id.to_string())
+        .bind(indices)
+        .execute(db)
+        .await
+        .map_err(|_| ApiError::Internal)?;
+    Ok(())
+}
+
+pub async fn count_shards_done(db: &PgDb, dataset_id: Uuid) -> Result<u64, ApiError> {
+    let row = sqlx::query(r#"SELECT COUNT(*) FROM shards WHERE dataset_id = $1"#)
+        .bind(dataset_id.to_string())
+        .fetch_one(db)
+        .await
+        .map_err(|_| ApiError::Internal)?;
+    Ok(row.int(0) as u64)
+}
+
+pub async fn list_shards(
+    db: &PgDb,
+    dataset_id: Uuid,
+    index_range: Range<u64>,
+    offset: u64,
+    limit: u64,
+    include_proof: bool,
+) -> Result<Vec<ShardListRow>, ApiError> {
+    let rows = sqlx::query(
+        r#"SELECT s.shard_index, s.shard_commitment_hex, s.stats_json, s.verified,
+                  CASE WHEN $1 THEN COALESCE(b.proof_b64, '') ELSE '' END
+           FROM shards s
+           LEFT JOIN proof_blobs b ON b.hash = s.proof_hash
+           WHERE s.dataset_id = $2 AND s.shard_index >= $3 AND s.shard_index < $4
+           ORDER BY s.shard_index
+           LIMIT $5 OFFSET $6"#,
+    )
+    .bind(include_proof)
+    .bind(dataset_id.to_string())
+    .bind(index_range.start.min(i64::MAX as u64) as i64)
+    .bind(index_range.end.min(i64::MAX as u64) as i64)
+    .bind(limit as i64)
+    .bind(offset as i64)
+    .fetch_all(db)
+    .await
+    .map_err(|_| ApiError::Internal)?;
+
+    rows.iter().map(|row| db::shard_list_row(row, include_proof)).collect()
+}
+
+pub async fn shard_vk_versions(db: &PgDb, dataset_id: Uuid, index_range: Range<u64>) -> Result<Vec<(u64, String)>, ApiError> {
+    let rows = sqlx::query(
+        r#"SELECT shard_index, vk_version FROM shards
+           WHERE dataset_id = $1 AND shard_index >= $2 AND shard_index < $3 AND vk_version IS NOT NULL
+           ORDER BY shard_index"#,
+    )
+    .bind(dataset_id.to_string())
+    .bind(index_range.start.min(i64::MAX as u64) as i64)
+    .bind(index_range.end.min(i64::MAX as u64) as i64)
+    .fetch_all(db)
+    .await
+    .map_err(|_| ApiError::Internal)?;
+    Ok(rows.iter().map(|row| (row.int(0) as u64, row.text(1))).collect())
+}
+
+pub async fn shard_verifications(db: &PgDb, dataset_id: Uuid, index_range: Range<u64>) -> Result<Vec<(u64, DateTime<Utc>, String)>, ApiError> {
+    let rows = sqlx::query(
+        r#"SELECT shard_index, verified_at, verifier_vk_hash FROM shards
+           WHERE dataset_id = $1 AND shard_index >= $2 AND shard_index < $3 AND verified_at IS NOT NULL
+           ORDER BY shard_index"#,
+    )
+    .bind(dataset_id.to_string())
+    .bind(index_range.start.min(i64::MAX as u64) as i64)
+    .bind(index_range.end.min(i64::MAX as u64) as i64)
+    .fetch_all(db)
+    .await
+    .map_err(|_| ApiError::Internal)?;
+    rows.iter().map(db::shard_verification_row).collect()
+}
+
+pub async fn sample_shards(db: &PgDb, limit: u64) -> Result<Vec<(Uuid, u64)>, ApiError> {
+    let rows = sqlx::query(
+        r#"SELECT s.dataset_id, s.shard_index FROM shards s
+           JOIN datasets d ON d.id = s.dataset_id
+           WHERE d.status = 'ready'
+           ORDER BY random()
+           LIMIT $1"#,
+    )
+    .bind(limit.min(i64::MAX as u64) as i64)
+    .fetch_all(db)
+    .await
+    .map_err(|_| ApiError::Internal)?;
+    rows.iter().map(db::sampled_shard_row).collect()
+}
+
+pub async fn record_shard_failure(
+    db: &PgDb,
+    dataset_id: Uuid,
+    shard_index: u64,
+    error_class: &str,
+    error: &str,
+) -> Result<(), ApiError> {
+    let now = Utc::now().to_rfc3339();
+    sqlx::query(
+        r#"INSERT INTO shard_failures
+           (dataset_id, shard_index, error_class, attempts, last_error, first_failed_at, last_failed_at)
+           VALUES ($1, $2, $3, 1, $4, $5, $5)
+           ON CONFLICT (dataset_id, shard_index) DO UPDATE SET
+             error_class = excluded.error_class,
+             attempts = shard_failures.attempts + 1,
+             last_error = excluded.last_error,
+             last_failed_at = excluded.last_failed_at"#,
+    )
+    .bind(dataset_id.to_string())
+    .bind(shard_index as i64)
+    .bind(error_class)
+    .bind(error)
+    .bind(&now)
+    .execute(db)
+    .await
+    .map_err(|_| ApiError::Internal)?;
+    Ok(())
+}
+
+pub async fn list_shard_failures(db: &PgDb, dataset_id: Uuid) -> Result<Vec<ShardFailureRow>, ApiError> {
+    let rows = sqlx::query(
+        r#"SELECT shard_index, error_class, attempts, last_error, first_failed_at, last_failed_at
+           FROM shard_failures WHERE dataset_id = $1 ORDER BY shard_index"#,
+    )
+    .bind(dataset_id.to_string())
+    .fetch_all(db)
+    .await
+    .map_err(|_| ApiError::Internal)?;
+
+    rows.iter().map(db::shard_failure_row).collect()
+}
+
+// --- Proof blobs ---
+
+pub async fn list_proof_blobs(db: &PgDb, after: &str, limit: u64) -> Result<Vec<(String, String)>, ApiError> {
+    // Hashes are lowercase hex, so byte order (`COLLATE "C"`) is the order `after` pages by.
+    let rows = sqlx::query(r#"SELECT hash, proof_b64 FROM proof_blobs WHERE hash > $1 COLLATE "C" ORDER BY hash COLLATE "C" LIMIT $2"#)
+        .bind(after)
+        .bind(limit as i64)
+        .fetch_all(db)
+        .await
+        .map_err(|_| ApiError::Internal)?;
+
+    Ok(rows.iter().map(|r| (r.text(0), r.text(1))).collect())
+}
+
+pub async fn shards_with_proof(db: &PgDb, hash: &str) -> Result<Vec<(Uuid, u64)>, ApiError> {
+    let rows = sqlx::query(r#"SELECT dataset_id, shard_index FROM shards WHERE proof_hash = $1 ORDER BY dataset_id, shard_index"#)
+        .bind(hash)
+        .fetch_all(db)
+        .await
+        .map_err(|_| ApiError::Internal)?;
+
+    rows.iter()
+        .map(|r| {
+            let dataset_id = Uuid::parse_str(&r.text(0)).map_err(|_| ApiError::Internal)?;
+            Ok((dataset_id, r.int(1) as u64))
+        })
+        .collect()
+}
+
+pub async fn delete_orphan_proof_blobs(db: &PgDb) -> Result<u64, ApiError> {
+    let res = sqlx::query(r#"DELETE FROM proof_blobs WHERE NOT EXISTS (SELECT 1 FROM shards WHERE shards.proof_hash = proof_blobs.hash)"#)
+        .execute(db)
+        .await
+        .map_err(|_| ApiError::Internal)?;
+
+    Ok(res.rows_affected())
+}
+
+pub async fn proof_blob_stats(db: &PgDb) -> Result<(u64, u64, u64), ApiError> {
+    let row = sqlx::query(
+        r#"SELECT (SELECT COUNT(*) FROM proof_blobs),
+                  (SELECT COALESCE(SUM(LENGTH(proof_b64)), 0)::BIGINT FROM proof_blobs),
+                  (SELECT COUNT(*) FROM shards)"#,
+    )
+    .fetch_one(db)
+    .await
+    .map_err(|_| ApiError::Internal)?;
+
+    Ok((row.int(0) as u64, row.int(1) as u64, row.int(2) as u64))
+}
+
+// --- Queries ---
+
+pub async fn insert_query(db: &PgDb, query_id: Uuid, dataset_id: Uuid, spec: &QuerySpec<'_>, result: &QueryResult) -> Result<(), ApiError> {
+    let created_at = Utc::now().to_rfc3339();
+    let shard_set = result.shard_set.as_ref();
+
+    sqlx::query(
+        r#"INSERT INTO queries (id, dataset_id, created_at, query_json, result_json, verified, release_key, released_at,
+                                dataset_commitment_hex, shards_total, verified_bitmap_hex, first_shard_index, requested_by)
+           VALUES ($1, $2, $3, $4, $5, $6, $7, $3, $8, $9, $10, $11, $12)"#,
+    )
+    .bind(query_id.to_string())
+    .bind(dataset_id.to_string())
+    .bind(&created_at)
+    .bind(db::query_json(spec).to_string())
+    .bind(db::result_json(result).to_string())
+    .bind(if result.verified { 1i64 } else { 0i64 })
+    .bind(db::release_key(spec.buckets, spec.field))
+    .bind(shard_set.and_then(|s| s.dataset_commitment_hex.as_deref()))
+    .bind(shard_set.map(|s| s.shards_total as i64))
+    .bind(shard_set.map(|s| s.verified_bitmap_hex.as_str()))
+    .bind(shard_set.map(|s| s.first_shard_index as i64))
+    .bind(spec.requested_by)
+    .execute(db)
+    .await
+    .map_err(|_| ApiError::Internal)?;
+
+    Ok(())
+}
+
+pub async fn insert_unreleased_query(db: &PgDb, query_id: Uuid, dataset_id: Uuid, spec: &QuerySpec<'_>, status: &str) -> Result<(), ApiError> {
+    sqlx::query(
+        r#"INSERT INTO queries (id, dataset_id, created_at, query_json, result_json, verified, status, release_key, requested_by)
+           VALUES ($1, $2, $3, $4, 'null', 0, $5, $6, $7)"#,
+    )
+    .bind(query_id.to_string())
+    .bind(dataset_id.to_string())
+    .bind(Utc::now().to_rfc3339())
+    .bind(db::query_json(spec).to_string())
+    .bind(status)
+    .bind(db::release_key(spec.buckets, spec.field))
+    .bind(spec.requested_by)
+    .execute(db)
+    .await
+    .map_err(|_| ApiError::Internal)?;
+
+    Ok(())
+}
+
+pub async fn get_query(db: &PgDb, query_id: Uuid) -> Result<Option<QueryRow>, ApiError> {
+    let row = sqlx::query(&format!("SELECT {} FROM queries WHERE id = $1", db::QUERY_COLUMNS))
+        .bind(query_id.to_string())
+        .fetch_optional(db)
+        .await
+        .map_err(|_| ApiError::Internal)?;
+
+    row.map(|row| db::query_row(&row)).transpose()
+}
+
+pub async fn list_dataset_queries(db: &PgDb, dataset_id: Uuid, offset: u64, limit: u64) -> Result<Vec<QueryRow>, ApiError> {
+    let rows = sqlx::query(&format!(
+        "SELECT {} FROM queries WHERE dataset_id = $1 ORDER BY created_at, id LIMIT $2 OFFSET $3",
+        db::QUERY_COLUMNS
+    ))
+    .bind(dataset_id.to_string())
+    .bind(limit as i64)
+    .bind(offset as i64)
+    .fetch_all(db)
+    .await
+    .map_err(|_| ApiError::Internal)?;
+
+    rows.iter().map(db::query_row).collect()
+}
+
+pub async fn release_query(
+    db: &PgDb,
+    query_id: Uuid,
+    from_status: &str,
+    result: &QueryResult,
+    decided_by: Option<&str>,
+) -> Result<bool, ApiError> {
+    let shard_set = result.shard_set.as_ref();
+    let res = sqlx::query(
+        r#"UPDATE queries SET result_json = $1, verified = $2, status = 'released', decided_by = $3, released_at = $4,
+                              dataset_commitment_hex = $5, shards_total = $6, verified_bitmap_hex = $7, first_shard_index = $8
+           WHERE id = $9 AND status = $10"#,
+    )
+    .bind(db::result_json(result).to_string())
+    .bind(if result.verified { 1i64 } else { 0i64 })
+    .bind(decided_by)
+    .bind(Utc::now().to_rfc3339())
+    .bind(shard_set.and_then(|s| s.dataset_commitment_hex.as_deref()))
+    .bind(shard_set.map(|s| s.shards_total as i64))
+    .bind(shard_set.map(|s| s.verified_bitmap_hex.as_str()))
+    .bind(shard_set.map(|s| s.first_shard_index as i64))
+    .bind(query_id.to_string())
+    .bind(from_status)
+    .execute(db)
+    .await
+    .map_err(|_| ApiError::Internal)?;
+
+    Ok(res.rows_affected() == 1)
+}
+
+pub async fn set_query_status(db: &PgDb, query_id: Uuid, from: &str, to: &str) -> Result<bool, ApiError> {
+    let res = sqlx::query(r#"UPDATE queries SET status = $1 WHERE id = $2 AND status = $3"#)
+        .bind(to)
+        .bind(query_id.to_string())
+        .bind(from)
+        .execute(db)
+        .await
+        .map_err(|_| ApiError::Internal)?;
+
+    Ok(res.rows_affected() == 1)
+}
+
+pub async fn fail_query(db: &PgDb, query_id: Uuid, error: &str) -> Result<(), ApiError> {
+    sqlx::query(r#"UPDATE queries SET status = 'failed', error = $1 WHERE id = $2 AND status != 'released'"#)
+        .bind(error)
+        .bind(query_id.to_string())
+        .execute(db)
+        .await
+        .map_err(|_| ApiError::Internal)?;
+    Ok(())
+}
+
+pub async fn reject_pending_query(db: &PgDb, query_id: Uuid, decided_by: &str) -> Result<bool, ApiError> {
+    let res = sqlx::query(r#"UPDATE queries SET status = 'rejected', decided_by = $1 WHERE id = $2 AND status = 'pending_approval'"#)
+        .bind(decided_by)
+        .bind(query_id.to_string())
+        .execute(db)
+        .await
+        .map_err(|_| ApiError::Internal)?;
+
+    Ok(res.rows_affected() == 1)
+}
+
+pub async fn release_keys_since(db: &PgDb, dataset_id: Uuid, since: DateTime<Utc>) -> Result<Vec<String>, ApiError> {
+    let rows = sqlx::query(
+        r#"SELECT DISTINCT release_key FROM queries
+           WHERE dataset_id = $1 AND released_at IS NOT NULL AND released_at >= $2 AND release_key IS NOT NULL"#,
+    )
+    .bind(dataset_id.to_string())
+    .bind(since.to_rfc3339())
+    .fetch_all(db)
+    .await
+    .map_err(|_| ApiError::Internal)?;
+
+    Ok(rows.iter().map(|r| r.text(0)).collect())
+}
+
+pub async fn spend_privacy_budget(
+    db: &PgDb,
+    dataset_id: Uuid,
+    epsilon: f64,
+    delta: f64,
+    budget: Option<f64>,
+) -> Result<Option<PrivacyBudgetRow>, ApiError> {
+    let cap = budget.map(|b| b + db::BUDGET_SLACK);
+    if cap.is_some_and(|cap| epsilon > cap) {
+        return Ok(None);
+    }
+    let res = sqlx::query(
+        r#"INSERT INTO privacy_budget (dataset_id, epsilon_spent, delta_spent, releases, updated_at)
+           VALUES ($1, $2, $3, 1, $4)
+           ON CONFLICT(dataset_id) DO UPDATE SET
+             epsilon_spent = privacy_budget.epsilon_spent + excluded.epsilon_spent,
+             delta_spent = privacy_budget.delta_spent + excluded.delta_spent,
+             releases = privacy_budget.releases + 1,
+             updated_at = excluded.updated_at
+           WHERE $5::DOUBLE PRECISION IS NULL OR privacy_budget.epsilon_spent + excluded.epsilon_spent <= $5"#,
+    )
+    .bind(dataset_id.to_string())
+    .bind(epsilon)
+    .bind(delta)
+    .bind(Utc::now().to_rfc3339())
+    .bind(cap)
+    .execute(db)
+    .await
+    .map_err(|_| ApiError::Internal)?;
+    if res.rows_affected() == 0 {
+        return Ok(None);
+    }
+    privacy_budget(db, dataset_id).await.map(Some)
+}
+
+pub async fn privacy_budget(db: &PgDb, dataset_id: Uuid) -> Result<PrivacyBudgetRow, ApiError> {
+    let row = sqlx::query(r#"SELECT epsilon_spent, delta_spent, releases FROM privacy_budget WHERE dataset_id = $1"#)
+        .bind(dataset_id.to_string())
+        .fetch_optional(db)
+        .await
+        .map_err(|_| ApiError::Internal)?;
+    Ok(row.map_or_else(PrivacyBudgetRow::default, |row| PrivacyBudgetRow {
+        epsilon_spent: row.get(0),
+        delta_spent: row.get(1),
+        releases: row.get::<i64, _>(2) as u64,
+    }))
+}
+
+pub async fn insert_released_cells(db: &PgDb, query_id: Uuid, dataset_id: Uuid, cells: &[(usize, &str)]) -> Result<(), ApiError> {
+    let released_at = Utc::now().to_rfc3339();
+
+    for (bucket_index, filter_key) in cells {
+        sqlx::query(
+            r#"INSERT INTO released_cells (query_id, dataset_id, bucket_index, filter_key, released_at)
+               VALUES ($1, $2, $3, $4, $5) ON CONFLICT DO NOTHING"#,
+        )
+        .bind(query_id.to_string())
+        .bind(dataset_id.to_string())
+        .bind(*bucket_index as i64)
+        .bind(*filter_key)
+        .bind(&released_at)
+        .execute(db)
+        .await
+        .map_err(|_| ApiError::Internal)?;
+    }
+
+    Ok(())
+}
+
+pub async fn cell_disclosure(db: &PgDb, dataset_id: Uuid) -> Result<Vec<CellDisclosureRow>, ApiError> {
+    let rows = sqlx::query(
+        r#"SELECT bucket_index, filter_key, COUNT(*), MIN(released_at), MAX(released_at)
+           FROM released_cells
+           WHERE dataset_id = $1
+           GROUP BY bucket_index, filter_key
+           ORDER BY bucket_index, filter_key"#,
+    )
+    .bind(dataset_id.to_string())
+    .fetch_all(db)
+    .await
+    .map_err(|_| ApiError::Internal)?;
+
+    rows.iter().map(db::cell_disclosure_row).collect()
+}
+
+// --- Jobs ---
+
+pub async fn insert_job(db: &PgDb, job_id: Uuid, kind: &str, subject_id: Uuid, tenant: &str, instance: Option<&str>) -> Result<(), ApiError> {
+    sqlx::query(
+        r#"INSERT INTO jobs (id, kind, subject_id, status, created_at, tenant, instance)
+           VALUES ($1, $2, $3, 'queued', $4, $5, $6)"#,
+    )
+    .bind(job_id.to_string())
+    .bind(kind)
+    .bind(subject_id.to_string())
+    .bind(Utc::now().to_rfc3339())
+    .bind(tenant)
+    .bind(instance)
+    .execute(db)
+    .await
+    .map_err(|_| ApiError::Internal)?;
+    Ok(())
+}
+
+/// Like `db::claim_next_job`. `SKIP LOCKED` lets instances claim concurrently without waiting on
+/// (or taking) the same row; the per-tenant limit is checked against the running jobs visible at
+/// claim time, so concurrent claims on several instances may overshoot it by one each.
+pub async fn claim_next_job(db: &PgDb, kind: &str, instance: &str, per_tenant_limit: Option<u64>) -> Result<Option<db::JobRow>, ApiError> {
+    let now = Utc::now().to_rfc3339();
+    let row = sqlx::query(
+        r#"UPDATE jobs SET status = 'running', started_at = $1, claimed_by = $2, heartbeat_at = $1
+           WHERE id = (
+             SELECT j.id FROM jobs j
+             WHERE j.status = 'queued' AND j.kind = $3 AND (j.instance IS NULL OR j.instance = $2)
+               AND ($4::BIGINT IS NULL OR (SELECT COUNT(*) FROM jobs r
+                                           WHERE r.status = 'running' AND r.kind = j.kind
+                                             AND r.tenant IS NOT DISTINCT FROM j.tenant) < $4::BIGINT)
+             ORDER BY j.created_at LIMIT 1
+             FOR UPDATE SKIP LOCKED)
+           RETURNING id, kind, subject_id"#,
+    )
+    .bind(&now)
+    .bind(instance)
+    .bind(kind)
+    .bind(per_tenant_limit.map(|l| l as i64))
+    .fetch_optional(db)
+    .await
+    .map_err(|_| ApiError::Internal)?;
+
+    let Some(row) = row else { return Ok(None); };
+    Ok(Some(db::JobRow {
+        id: Uuid::parse_str(&row.text(0)).map_err(|_| ApiError::Internal)?,
+        kind: row.text(1),
+        subject_id: Uuid::parse_str(&row.text(2)).map_err(|_| ApiError::Internal)?,
+    }))
+}
+
+pub async fn finish_job(db: &PgDb, job_id: Uuid, error: Option<&str>) -> Result<(), ApiError> {
+    sqlx::query(r#"UPDATE jobs SET status = $1, error = $2, finished_at = $3 WHERE id = $4"#)
+        .bind(if error.is_some() { "failed" } else { "done" })
+        .bind(error)
+        .bind(Utc::now().to_rfc3339())
+        .bind(job_id.to_string())
+        .execute(db)
+        .await
+        .map_err(|_| ApiError::Internal)?;
+    Ok(())
+}
+
+pub async fn requeue_job(db: &PgDb, job_id: Uuid) -> Result<(), ApiError> {
+    sqlx::query(
+        r#"UPDATE jobs SET status = 'queued', started_at = NULL, claimed_by = NULL, heartbeat_at = NULL
+           WHERE id = $1 AND status = 'running'"#,
+    )
+    .bind(job_id.to_string())
+    .execute(db)
+    .await
+    .map_err(|_| ApiError::Internal)?;
+    Ok(())
+}
+
+pub async fn heartbeat_job(db: &PgDb, job_id: Uuid) -> Result<(), ApiError> {
+    sqlx::query(r#"UPDATE jobs SET heartbeat_at = $1 WHERE id = $2 AND status = 'running'"#)
+        .bind(Utc::now().to_rfc3339())
+        .bind(job_id.to_string())
+        .execute(db)
+        .await
+        .map_err(|_| ApiError::Internal)?;
+    Ok(())
+}
+
+pub async fn requeue_running_jobs(db: &PgDb, instance: Option<&str>, stale_before: DateTime<Utc>) -> Result<u64, ApiError> {
+    let res = sqlx::query(
+        r#"UPDATE jobs SET status = 'queued', started_at = NULL, claimed_by = NULL, heartbeat_at = NULL
+           WHERE status = 'running'
+             AND (($1::TEXT IS NOT NULL AND (claimed_by = $1 OR claimed_by IS NULL))
+                  OR COALESCE(heartbeat_at, started_at) < $2)"#,
+    )
+    .bind(instance)
+    .bind(stale_before.to_rfc3339())
+    .execute(db)
+    .await
+    .map_err(|_| ApiError::Internal)?;
+    Ok(res.rows_affected())
+}
+
+pub async fn delete_jobs(db: &PgDb, subject_id: Uuid) -> Result<(), ApiError> {
+    sqlx::query(r#"DELETE FROM jobs WHERE subject_id = $1"#)
+        .bind(subject_id.to_string())
+        .execute(db)
+        .await
+        .map_err(|_| ApiError::Internal)?;
+    Ok(())
+}
+
+pub async fn tenant_proving_jobs(db: &PgDb, key_id: &str) -> Result<(u64, u64), ApiError> {
+    let row = sqlx::query(
+        r#"SELECT
+             (SELECT COUNT(*) FROM jobs WHERE tenant = $1 AND kind = $2 AND status = 'running'),
+             (SELECT COUNT(*) FROM jobs WHERE tenant = $1 AND kind = $2 AND status = 'queued')"#,
+    )
+    .bind(key_id)
+    .bind(crate::jobs::KIND_PROVE_DATASET)
+    .fetch_one(db)
+    .await
+    .map_err(|_| ApiError::Internal)?;
+    Ok((row.int(0) as u64, row.int(1) as u64))
+}
+
+pub async fn proving_backlog(db: &PgDb) -> Result<(u64, u64), ApiError> {
+    let row = sqlx::query(
+        r#"SELECT
+             (SELECT COUNT(*) FROM jobs WHERE kind = $1 AND status = 'running'),
+             (SELECT COUNT(*) FROM jobs WHERE kind = $1 AND status = 'queued')"#,
+    )
+    .bind(crate::jobs::KIND_PROVE_DATASET)
+    .fetch_one(db)
+    .await
+    .map_err(|_| ApiError::Internal)?;
+    Ok((row.int(0) as u64, row.int(1) as u64))
+}
+
+pub async fn active_jobs(db: &PgDb, subject_id: Uuid) -> Result<u64, ApiError> {
+    let row = sqlx::query(r#"SELECT COUNT(*) FROM jobs WHERE subject_id = $1 AND status IN ('queued', 'running')"#)
+        .bind(subject_id.to_string())
+        .fetch_one(db)
+        .await
+        .map_err(|_| ApiError::Internal)?;
+    Ok(row.int(0) as u64)
+}
+
+pub async fn latest_job(db: &PgDb, kind: &str, subject_id: Uuid) -> Result<Option<(String, Option<String>)>, ApiError> {
+    let row = sqlx::query(
+        r#"SELECT status, error FROM jobs WHERE kind = $1 AND subject_id = $2
+           ORDER BY created_at DESC LIMIT 1"#,
+    )
+    .bind(kind)
+    .bind(subject_id.to_string())
+    .fetch_optional(db)
+    .await
+    .map_err(|_| ApiError::Internal)?;
+    Ok(row.map(|row| (row.text(0), row.opt_text(1))))
+}
+
+// --- Audit log ---
+
+/// Append an entry to the audit chain, returning its entry hash. An advisory lock held for the
+/// transaction serializes appends from every instance, so each entry chains onto the true head.
+pub async fn append_audit(
+    db: &PgDb,
+    dataset_id: Option<Uuid>,
+    event: &str,
+    details: &serde_json::Value,
+) -> Result<String, ApiError> {
+    let mut tx = db.begin().await.map_err(|_| ApiError::Internal)?;
+
+    sqlx::query(r#"SELECT pg_advisory_xact_lock($1)"#)
+        .bind(AUDIT_LOCK_KEY)
+        .execute(&mut *tx)
+        .await
+        .map_err(|_| ApiError::Internal)?;
+
+    let prev_hash = sqlx::query(r#"SELECT entry_hash FROM audit_log ORDER BY seq DESC LIMIT 1"#)
+        .fetch_optional(&mut *tx)
+        .await
+        .map_err(|_| ApiError::Internal)?
+        .map(|r| r.text(0))
+        .unwrap_or_else(|| db::AUDIT_GENESIS_HASH.to_string());
+
+    let created_at = Utc::now().to_rfc3339();
+    let dataset_id = dataset_id.map(|id| id.to_string()).unwrap_or_default();
+    let details_json = details.to_string();
+    let entry_hash = db::audit_entry_hash(&prev_hash, &created_at, &dataset_id, event, &details_json);
+
+    sqlx::query(
+        r#"INSERT INTO audit_log (created_at, dataset_id, event, details_json, prev_hash, entry_hash)
+           VALUES ($1, $2, $3, $4, $5, $6)"#,
+    )
+    .bind(created_at)
+    .bind(dataset_id)
+    .bind(event)
+    .bind(details_json)
+    .bind(prev_hash)
+    .bind(&entry_hash)
+    .execute(&mut *tx)
+    .await
+    .map_err(|_| ApiError::Internal)?;
+
+    tx.commit().await.map_err(|_| ApiError::Internal)?;
+    Ok(entry_hash)
+}
+
+pub async fn list_audit(db: &PgDb, dataset_id: Uuid, offset: u64, limit: u64) -> Result<Vec<AuditRow>, ApiError> {
+    let rows = sqlx::query(
+        r#"SELECT seq, created_at, event, details_json, prev_hash, entry_hash
+           FROM audit_log
+           WHERE dataset_id = $1
+           ORDER BY seq
+           LIMIT $2 OFFSET $3"#,
+    )
+    .bind(dataset_id.to_string())
+    .bind(limit as i64)
+    .bind(offset as i64)
+    .fetch_all(db)
+    .await
+    .map_err(|_| ApiError::Internal)?;
+
+    rows.iter().map(db::audit_row).collect()
+}
+
+pub async fn verify_audit_chain(db: &PgDb) -> Result<Result<u64, u64>, ApiError> {
+    let rows = sqlx::query(
+        r#"SELECT seq, created_at, dataset_id, event, details_json, prev_hash, entry_hash
+           FROM audit_log ORDER BY seq"#,
+    )
+    .fetch_all(db)
+    .await
+    .map_err(|_| ApiError::Internal)?;
+
+    Ok(db::check_audit_chain(&rows))
+}
+
+// --- Transparency log ---
+
+/// Append a leaf to the transparency log, returning its index.
+pub async fn append_log_leaf(
+    db: &PgDb,
+    kind: &str,
+    dataset_id: Uuid,
+    shard_index: Option<u64>,
+    commitment_hex: &str,
+    leaf_hash: &str,
+) -> Result<u64, ApiError> {
+    let mut tx = db.begin().await.map_err(|_| ApiError::Internal)?;
+
+    sqlx::query(r#"SELECT pg_advisory_xact_lock($1)"#)
+        .bind(LOG_LOCK_KEY)
+        .execute(&mut *tx)
+        .await
+        .map_err(|_| ApiError::Internal)?;
+
+    let seq = sqlx::query(r#"SELECT COUNT(*) FROM transparency_log"#)
+        .fetch_one(&mut *tx)
+        .await
+        .map_err(|_| ApiError::Internal)?
+        .int(0);
+    sqlx::query(
+        r#"INSERT INTO transparency_log (seq, created_at, kind, dataset_id, shard_index, commitment_hex, leaf_hash)
+           VALUES ($1, $2, $3, $4, $5, $6, $7)"#,
+    )
+    .bind(seq)
+    .bind(Utc::now().to_rfc3339())
+    .bind(kind)
+    .bind(dataset_id.to_string())
+    .bind(shard_index.map(|i| i as i64))
+    .bind(commitment_hex)
+    .bind(leaf_hash)
+    .execute(&mut *tx)
+    .await
+    .map_err(|_| ApiError::Internal)?;
+
+    tx.commit().await.map_err(|_| ApiError::Internal)?;
+    Ok(seq as u64)
+}
+
+pub async fn log_size(db: &PgDb) -> Result<u64, ApiError> {
+    let row = sqlx::query(r#"SELECT COUNT(*) FROM transparency_log"#)
+        .fetch_one(db)
+        .await
+        .map_err(|_| ApiError::Internal)?;
+    Ok(row.int(0) as u64)
+}
+
+pub async fn log_leaf_hashes(db: &PgDb, tree_size: u64) -> Result<Vec<String>, ApiError> {
+    let rows = sqlx::query(r#"SELECT leaf_hash FROM transparency_log WHERE seq < $1 ORDER BY seq"#)
+        .bind(tree_size as i64)
+        .fetch_all(db)
+        .await
+        .map_err(|_| ApiError::Internal)?;
+    Ok(rows.iter().map(|r| r.text(0)).collect())
+}
+
+pub async fn get_log_leaf(db: &PgDb, seq: u64) -> Result<Option<LogLeafRow>, ApiError> {
+    let row = sqlx::query(
+        r#"SELECT seq, created_at, kind, dataset_id, shard_index, commitment_hex, leaf_hash
+           FROM transparency_log WHERE seq = $1"#,
+    )
+    .bind(seq as i64)
+    .fetch_optional(db)
+    .await
+    .map_err(|_| ApiError::Internal)?;
+    row.as_ref().map(db::log_leaf_row).transpose()
+}
+
+pub async fn find_dataset_log_leaf(db: &PgDb, dataset_id: Uuid, commitment_hex: &str) -> Result<Option<u64>, ApiError> {
+    let row = sqlx::query(
+        r#"SELECT seq FROM transparency_log
+           WHERE kind = 'dataset' AND dataset_id = $1 AND commitment_hex = $2
+           ORDER BY seq LIMIT 1"#,
+    )
+    .bind(dataset_id.to_string())
+    .bind(commitment_hex)
+    .fetch_optional(db)
+    .await
+    .map_err(|_| ApiError::Internal)?;
+    Ok(row.map(|r| r.int(0) as u64))
+}
+
+// --- Operational state ---
+
+pub async fn put_aggregate_proof(db: &PgDb, dataset_id: Uuid, row: &AggregateProofRow) -> Result<(), ApiError> {
+    let totals_json = serde_json::to_string(&row.totals).map_err(|_| ApiError::Internal)?;
+    sqlx::query(&format!(
+        r#"INSERT INTO aggregate_proofs (dataset_id, {})
+           VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+           ON CONFLICT (dataset_id) DO UPDATE SET created_at = EXCLUDED.created_at,
+             dataset_commitment_hex = EXCLUDED.dataset_commitment_hex, shard_inputs_root_hex = EXCLUDED.shard_inputs_root_hex,
+             shards_total = EXCLUDED.shards_total, totals_json = EXCLUDED.totals_json, proof_b64 = EXCLUDED.proof_b64,
+             vk_b64 = EXCLUDED.vk_b64, key_id = EXCLUDED.key_id"#,
+        db::AGGREGATE_PROOF_COLUMNS
+    ))
+    .bind(dataset_id.to_string())
+    .bind(row.created_at.to_rfc3339())
+    .bind(&row.dataset_commitment_hex)
+    .bind(&row.shard_inputs_root_hex)
+    .bind(row.shards_total as i64)
+    .bind(totals_json)
+    .bind(&row.proof_b64)
+    .bind(&row.vk_b64)
+    .bind(&row.key_id)
+    .execute(db)
+    .await
+    .map_err(|_| ApiError::Internal)?;
+    Ok(())
+}
+
+pub async fn get_aggregate_proof(db: &PgDb, dataset_id: Uuid) -> Result<Option<AggregateProofRow>, ApiError> {
+    let row = sqlx::query(&format!("SELECT {} FROM aggregate_proofs WHERE dataset_id = $1", db::AGGREGATE_PROOF_COLUMNS))
+        .bind(dataset_id.to_string())
+        .fetch_optional(db)
+        .await
+        .map_err(|_| ApiError::Internal)?;
+    row.as_ref().map(db::aggregate_proof_row).transpose()
+}
+
+pub async fn put_anomaly_analysis(db: &PgDb, dataset_id: Uuid, row: &AnomalyAnalysisRow) -> Result<(), ApiError> {
+    let warnings_json = serde_json::to_string(&row.warnings).map_err(|_| ApiError::Internal)?;
+    sqlx::query(&format!(
+        r#"INSERT INTO dataset_anomalies (dataset_id, {})
+           VALUES ($1, $2, $3, $4, $5, $6, $7)
+           ON CONFLICT (dataset_id) DO UPDATE SET analyzed_at = EXCLUDED.analyzed_at,
+             dataset_commitment_hex = EXCLUDED.dataset_commitment_hex, shards_stored = EXCLUDED.shards_stored,
+             shards_analyzed = EXCLUDED.shards_analyzed, warnings_total = EXCLUDED.warnings_total,
+             warnings_json = EXCLUDED.warnings_json"#,
+        db::ANOMALY_ANALYSIS_COLUMNS
+    ))
+    .bind(dataset_id.to_string())
+    .bind(row.analyzed_at.to_rfc3339())
+    .bind(&row.dataset_commitment_hex)
+    .bind(row.shards_stored as i64)
+    .bind(row.shards_analyzed as i64)
+    .bind(row.warnings_total as i64)
+    .bind(warnings_json)
+    .execute(db)
+    .await
+    .map_err(|_| ApiError::Internal)?;
+    Ok(())
+}
+
+pub async fn get_anomaly_analysis(db: &PgDb, dataset_id: Uuid) -> Result<Option<AnomalyAnalysisRow>, ApiError> {
+    let row = sqlx::query(&format!("SELECT {} FROM dataset_anomalies WHERE dataset_id = $1", db::ANOMALY_ANALYSIS_COLUMNS))
+        .bind(dataset_id.to_string())
+        .fetch_optional(db)
+        .await
+        .map_err(|_| ApiError::Internal)?;
+    row.as_ref().map(db::anomaly_analysis_row).transpose()
+}
+
+pub async fn put_dataset_reverification(db: &PgDb, dataset_id: Uuid, row: &DatasetReverificationRow) -> Result<(), ApiError> {
+    let failed_shards_json = serde_json::to_string(&row.failed_shards).map_err(|_| ApiError::Internal)?;
+    sqlx::query(&format!(
+        r#"INSERT INTO dataset_reverifications (dataset_id, {})
+           VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+           ON CONFLICT (dataset_id) DO UPDATE SET finished_at = EXCLUDED.finished_at,
+             dataset_commitment_hex = EXCLUDED.dataset_commitment_hex, shards_total = EXCLUDED.shards_total,
+             shards_checked = EXCLUDED.shards_checked, failed_shards_json = EXCLUDED.failed_shards_json,
+             commitment_matches = EXCLUDED.commitment_matches, duration_ms = EXCLUDED.duration_ms"#,
+        db::REVERIFICATION_COLUMNS
+    ))
+    .bind(dataset_id.to_string())
+    .bind(row.finished_at.to_rfc3339())
+    .bind(&row.dataset_commitment_hex)
+    .bind(row.shards_total as i64)
+    .bind(row.shards_checked as i64)
+    .bind(failed_shards_json)
+    .bind(if row.commitment_matches { 1i64 } else { 0i64 })
+    .bind(row.duration_ms as i64)
+    .execute(db)
+    .await
+    .map_err(|_| ApiError::Internal)?;
+    Ok(())
+}
+
+pub async fn get_dataset_reverification(db: &PgDb, dataset_id: Uuid) -> Result<Option<DatasetReverificationRow>, ApiError> {
+    let row = sqlx::query(&format!("SELECT {} FROM dataset_reverifications WHERE dataset_id = $1", db::REVERIFICATION_COLUMNS))
+        .bind(dataset_id.to_string())
+        .fetch_optional(db)
+        .await
+        .map_err(|_| ApiError::Internal)?;
+    row.as_ref().map(db::dataset_reverification_row).transpose()
+}
+
+pub async fn put_curve_migration(db: &PgDb, dataset_id: Uuid, curve: Curve, status: &str, reason: Option<&str>) -> Result<(), ApiError> {
+    sqlx::query(
+        r#"INSERT INTO curve_migrations (dataset_id, curve, status, reason, created_at)
+           VALUES ($1, $2, $3, $4, $5)
+           ON CONFLICT (dataset_id, curve) DO UPDATE SET status = EXCLUDED.status, reason = EXCLUDED.reason,
+             created_at = EXCLUDED.created_at, dataset_commitment_hex = NULL, key_id = NULL, finished_at = NULL"#,
+    )
+    .bind(dataset_id.to_string())
+    .bind(curve.name())
+    .bind(status)
+    .bind(reason)
+    .bind(Utc::now().to_rfc3339())
+    .execute(db)
+    .await
+    .map_err(|_| ApiError::Internal)?;
+    Ok(())
+}
+
+pub async fn set_curve_migration_status(db: &PgDb, dataset_id: Uuid, curve: Curve, status: &str, reason: Option<&str>) -> Result<(), ApiError> {
+    sqlx::query(r#"UPDATE curve_migrations SET status = $1, reason = $2, finished_at = $3 WHERE dataset_id = $4 AND curve = $5"#)
+        .bind(status)
+        .bind(reason)
+        .bind(db::curve_migration_finished_at(status))
+        .bind(dataset_id.to_string())
+        .bind(curve.name())
+        .execute(db)
+        .await
+        .map_err(|_| ApiError::Internal)?;
+    Ok(())
+}
+
+pub async fn set_curve_migration_done(db: &PgDb, dataset_id: Uuid, curve: Curve, dataset_commitment_hex: &str, key_id: &str) -> Result<(), ApiError> {
+    sqlx::query(
+        r#"UPDATE curve_migrations SET status = 'done', reason = NULL, dataset_commitment_hex = $1, key_id = $2, finished_at = $3
+           WHERE dataset_id = $4 AND curve = $5"#,
+    )
+    .bind(dataset_commitment_hex)
+    .bind(key_id)
+    .bind(Utc::now().to_rfc3339())
+    .bind(dataset_id.to_string())
+    .bind(curve.name())
+    .execute(db)
+    .await
+    .map_err(|_| ApiError::Internal)?;
+    Ok(())
+}
+
+pub async fn get_curve_migration(db: &PgDb, dataset_id: Uuid, curve: Curve) -> Result<Option<CurveMigrationRow>, ApiError> {
+    let row = sqlx::query(&format!(
+        r#"SELECT {}
+           FROM curve_migrations m LEFT JOIN datasets d ON d.id = m.dataset_id
+           WHERE m.dataset_id = $1 AND m.curve = $2"#,
+        db::CURVE_MIGRATION_COLUMNS
+    ))
+    .bind(dataset_id.to_string())
+    .bind(curve.name())
+    .fetch_optional(db)
+    .await
+    .map_err(|_| ApiError::Internal)?;
+    row.as_ref().map(db::curve_migration_row).transpose()
+}
+
+pub async fn list_curve_migrations(db: &PgDb, curve: Curve) -> Result<Vec<CurveMigrationRow>, ApiError> {
+    let rows = sqlx::query(&format!(
+        r#"SELECT {}
+           FROM curve_migrations m LEFT JOIN datasets d ON d.id = m.dataset_id
+           WHERE m.curve = $1
+           ORDER BY m.created_at"#,
+        db::CURVE_MIGRATION_COLUMNS
+    ))
+    .bind(curve.name())
+    .fetch_all(db)
+    .await
+    .map_err(|_| ApiError::Internal)?;
+    rows.iter().map(db::curve_migration_row).collect()
+}
+
+pub async fn insert_curve_shard(
+    db: &PgDb,
+    dataset_id: Uuid,
+    curve: Curve,
+    shard: &CurveShardRow,
+    sealed_master_salt: &[u8],
+    key_id: &str,
+) -> Result<(), ApiError> {
+    use base64::Engine;
+    let stats_json = serde_json::to_string(&shard.stats).map_err(|_| ApiError::Internal)?;
+    sqlx::query(
+        r#"INSERT INTO curve_shards
+             (dataset_id, curve, shard_index, shard_commitment_hex, salt_commitment_hex, stats_json, proof_b64, sealed_master_salt_b64, key_id)
+           VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+           ON CONFLICT (dataset_id, curve, shard_index) DO UPDATE SET shard_commitment_hex = EXCLUDED.shard_commitment_hex,
+             salt_commitment_hex = EXCLUDED.salt_commitment_hex, stats_json = EXCLUDED.stats_json, proof_b64 = EXCLUDED.proof_b64,
+             sealed_master_salt_b64 = EXCLUDED.sealed_master_salt_b64, key_id = EXCLUDED.key_id"#,
+    )
+    .bind(dataset_id.to_string())
+    .bind(curve.name())
+    .bind(shard.shard_index as i64)
+    .bind(&shard.shard_commitment_hex)
+    .bind(&shard.salt_commitment_hex)
+    .bind(stats_json)
+    .bind(&shard.proof_b64)
+    .bind(base64::engine::general_purpose::STANDARD.encode(sealed_master_salt))
+    .bind(key_id)
+    .execute(db)
+    .await
+    .map_err(|_| ApiError::Internal)?;
+    Ok(())
+}
+
+pub async fn delete_curve_shards_except(db: &PgDb, dataset_id: Uuid, curve: Curve, key_id: &str) -> Result<u64, ApiError> {
+    let res = sqlx::query(r#"DELETE FROM curve_shards WHERE dataset_id = $1 AND curve = $2 AND key_id <> $3"#)
+        .bind(dataset_id.to_string())
+        .bind(curve.name())
+        .bind(key_id)
+        .execute(db)
+        .await
+        .map_err(|_| ApiError::Internal)?;
+    Ok(res.rows_affected())
+}
+
+pub async fn list_curve_shards(
+    db: &PgDb,
+    dataset_id: Uuid,
+    curve: Curve,
+    index_range: Range<u64>,
+    offset: u64,
+    limit: u64,
+    include_proof: bool,
+) -> Result<Vec<CurveShardRow>, ApiError> {
+    let rows = sqlx::query(
+        r#"SELECT shard_index, shard_commitment_hex, salt_commitment_hex, stats_json,
+                  CASE WHEN $1 THEN proof_b64 ELSE '' END
+           FROM curve_shards
+           WHERE dataset_id = $2 AND curve = $3 AND shard_index >= $4 AND shard_index < $5
+           ORDER BY shard_index
+           LIMIT $6 OFFSET $7"#,
+    )
+    .bind(include_proof)
+    .bind(dataset_id.to_string())
+    .bind(curve.name())
+    .bind(index_range.start.min(i64::MAX as u64) as i64)
+    .bind(index_range.end.min(i64::MAX as u64) as i64)
+    .bind(limit.min(i64::MAX as u64) as i64)
+    .bind(offset.min(i64::MAX as u64) as i64)
+    .fetch_all(db)
+    .await
+    .map_err(|_| ApiError::Internal)?;
+    rows.iter().map(db::curve_shard_row).collect()
+}
+
+pub async fn record_key_proof(db: &PgDb, key_id: &str, circuit: &str, dataset_id: Uuid) -> Result<bool, ApiError> {
+    let now = Utc::now().to_rfc3339();
+    let mut tx = db.begin().await.map_err(|_| ApiError::Internal)?;
+    sqlx::query(
+        r#"INSERT INTO zk_key_usage (key_id, circuit, first_seen_at, last_proof_at, proofs_created)
+           VALUES ($1, $2, $3, $4, 1)
+           ON CONFLICT (key_id) DO UPDATE SET
+             last_proof_at = EXCLUDED.last_proof_at,
+             proofs_created = zk_key_usage.proofs_created + 1"#,
+    )
+    .bind(key_id)
+    .bind(circuit)
+    .bind(&now)
+    .bind(&now)
+    .execute(&mut *tx)
+    .await
+    .map_err(|_| ApiError::Internal)?;
+    let new_dataset = sqlx::query(r#"INSERT INTO zk_key_datasets (key_id, dataset_id) VALUES ($1, $2) ON CONFLICT DO NOTHING"#)
+        .bind(key_id)
+        .bind(dataset_id.to_string())
+        .execute(&mut *tx)
+        .await
+        .map_err(|_| ApiError::Internal)?
+        .rows_affected()
+        > 0;
+    tx.commit().await.map_err(|_| ApiError::Internal)?;
+    Ok(new_dataset)
+}
+
+pub async fn list_key_usage(db: &PgDb, key_id: Option<&str>) -> Result<Vec<KeyUsageRow>, ApiError> {
+    let rows = sqlx::query(&format!(
+        r#"SELECT {}
+           FROM zk_key_usage u
+           WHERE $1::TEXT IS NULL OR u.key_id = $1
+           ORDER BY u.first_seen_at, u.key_id"#,
+        db::KEY_USAGE_COLUMNS
+    ))
+    .bind(key_id)
+    .fetch_all(db)
+    .await
+    .map_err(|_| ApiError::Internal)?;
+    rows.iter().map(db::key_usage_row).collect()
+}
+
+pub async fn insert_log_anchor(db: &PgDb, row: &LogAnchorRow) -> Result<(), ApiError> {
+    sqlx::query(&format!("INSERT INTO log_anchors ({}) VALUES ($1, $2, $3, $4, $5, $6)", db::LOG_ANCHOR_COLUMNS))
+        .bind(row.anchored_at.to_rfc3339())
+        .bind(&row.method)
+        .bind(row.tree_size as i64)
+        .bind(&row.root_hash_hex)
+        .bind(&row.txid)
+        .bind(&row.receipt_hex)
+        .execute(db)
+        .await
+        .map_err(|_| ApiError::Internal)?;
+    Ok(())
+}
+
+pub async fn anchored_tree_size(db: &PgDb) -> Result<u64, ApiError> {
+    let row = sqlx::query(r#"SELECT COALESCE(MAX(tree_size), 0) FROM log_anchors"#)
+        .fetch_one(db)
+        .await
+        .map_err(|_| ApiError::Internal)?;
+    Ok(row.int(0) as u64)
+}
+
+pub async fn anchor_covering(db: &PgDb, leaf_index: u64) -> Result<Option<LogAnchorRow>, ApiError> {
+    let row = sqlx::query(&format!(
+        "SELECT {} FROM log_anchors WHERE tree_size > $1 ORDER BY seq LIMIT 1",
+        db::LOG_ANCHOR_COLUMNS
+    ))
+    .bind(leaf_index as i64)
+    .fetch_optional(db)
+    .await
+    .map_err(|_| ApiError::Internal)?;
+    row.as_ref().map(db::log_anchor_row).transpose()
+}
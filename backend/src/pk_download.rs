@@ -0,0 +1,126 @@
+//! Proving key download (`GET /api/v1/zk/pk`) for client-side proving.
+//!
+//! Trusted partner applications can prove small shards themselves (`ledger-client` with its
+//! `prove` feature) and push them to a federated dataset registered with this instance's verifying
+//! key (`GET /api/v1/zk/vk`), without running a backend. Proving keys are large even for small
+//! circuits, so the key file is streamed and served resumably: its `ETag` is the key id, and
+//! `Range: bytes=N-` (with `If-Range: <etag>`, so every piece is of one key) continues an
+//! interrupted download. Only shard sizes up to `ZK_PK_MAX_SHARD_SIZE` (default 100) are served:
+//! larger shards take more memory and time to prove than a client application should need.
+
+use crate::errors::ApiError;
+use crate::state::{key_paths, AppState};
+use axum::body::Bytes;
+use futures_util::Stream;
+use std::io::SeekFrom;
+use std::path::PathBuf;
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+use zk_proofs::types::{AgeBuckets, FieldSet};
+
+/// Bytes read from the key file per streamed chunk.
+const CHUNK_BYTES: usize = 256 * 1024;
+
+/// Largest shard size whose proving key is served.
+pub fn max_shard_size() -> usize {
+    std::env::var("ZK_PK_MAX_SHARD_SIZE").ok().and_then(|v| v.parse().ok()).unwrap_or(100)
+}
+
+/// A proving key file to send: all of it, or `range` (inclusive) of its `len` bytes.
+pub struct PkDownload {
+    pub key_id: String,
+    pub path: PathBuf,
+    pub len: u64,
+    pub range: Option<(u64, u64)>,
+}
+
+/// The default-layout BN254 proving key for `shard_size` and `field_set` (set up if missing),
+/// with the part of it `range` asks for.
+pub async fn pk_download(
+    state: &AppState,
+    shard_size: usize,
+    field_set: FieldSet,
+    sha256_commitment: bool,
+    range: Option<&str>,
+    if_range: Option<&str>,
+) -> Result<PkDownload, ApiError> {
+    if shard_size > max_shard_size() {
+        return Err(ApiError::Forbidden(format!(
+            "proving keys are only served for shards of up to {} records (ZK_PK_MAX_SHARD_SIZE)",
+            max_shard_size()
+        )));
+    }
+    let buckets = AgeBuckets::default();
+    let keys = state.ensure_keys_for(shard_size, field_set, &buckets, sha256_commitment).await?;
+    let (path, _) = key_paths(&state.data_dir.join("keys"), shard_size, field_set, &buckets, sha256_commitment);
+    let len = tokio::fs::metadata(&path).await.map_err(|_| ApiError::Internal)?.len();
+
+    // A range is only honoured for the key it was started on; otherwise the whole key is sent.
+    let same_key = if_range.is_none_or(|tag| tag.trim().trim_matches('"') == keys.key_id);
+    let range = match range {
+        Some(range) if same_key => byte_range(range, len)?,
+        _ => None,
+    };
+    Ok(PkDownload {
+        key_id: keys.key_id,
+        path,
+        len,
+        range,
+    })
+}
+
+/// Parse a single `bytes=` range (`N-`, `N-M` or `-N`) of a `len`-byte file. Anything else (other
+/// units, several ranges) is ignored, which sends the whole file.
+fn byte_range(range: &str, len: u64) -> Result<Option<(u64, u64)>, ApiError> {
+    let Some(spec) = range.trim().strip_prefix("bytes=") else {
+        return Ok(None);
+    };
+    if spec.contains(',') {
+        return Ok(None);
+    }
+    let Some((start, end)) = spec.split_once('-') else {
+        return Ok(None);
+    };
+    let unsatisfiable = || ApiError::RangeNotSatisfiable {
+        message: format!("range {spec} is outside the {len}-byte key"),
+        len,
+    };
+    let parse = |n: &str| n.trim().parse::<u64>().map_err(|_| ApiError::BadRequest(format!("invalid range {spec}")));
+    let (start, end) = match (start.trim(), end.trim()) {
+        ("", suffix) => {
+            let suffix = parse(suffix)?;
+            if suffix == 0 || len == 0 {
+                return Err(unsatisfiable());
+            }
+            (len.saturating_sub(suffix), len - 1)
+        }
+        (start, "") => (parse(start)?, len.saturating_sub(1)),
+        (start, end) => (parse(start)?, parse(end)?.min(len.saturating_sub(1))),
+    };
+    if start >= len || start > end {
+        return Err(unsatisfiable());
+    }
+    Ok(Some((start, end)))
+}
+
+/// The bytes of `download`'s range (or whole file), read a chunk at a time.
+pub async fn stream(download: PkDownload) -> Result<impl Stream<Item = Result<Bytes, std::io::Error>>, ApiError> {
+    let (start, end) = download.range.unwrap_or((0, download.len.saturating_sub(1)));
+    let mut file = tokio::fs::File::open(&download.path).await.map_err(|_| ApiError::Internal)?;
+    file.seek(SeekFrom::Start(start)).await.map_err(|_| ApiError::Internal)?;
+    let remaining = if download.len == 0 { 0 } else { end + 1 - start };
+
+    Ok(futures_util::stream::unfold((file, remaining), |(mut file, remaining)| async move {
+        if remaining == 0 {
+            return None;
+        }
+        let mut chunk = vec![0u8; CHUNK_BYTES.min(remaining as usize)];
+        match file.read(&mut chunk).await {
+            Ok(0) => Some((Err(std::io::ErrorKind::UnexpectedEof.into()), (file, 0))),
+            Ok(n) => {
+                chunk.truncate(n);
+                Some((Ok(Bytes::from(chunk)), (file, remaining - n as u64)))
+            }
+            Err(e) => Some((Err(e), (file, 0))),
+        }
+    }))
+}
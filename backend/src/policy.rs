@@ -0,0 +1,166 @@
+//! Data-use policy checks applied before any aggregate is released.
+
+use crate::models::QueryPurpose;
+use std::time::Duration;
+
+/// Default window for counting aggregate releases.
+const DEFAULT_RELEASE_WINDOW_SECS: u64 = 24 * 60 * 60;
+
+/// Default number of releases of a single cell before it is flagged.
+const DEFAULT_DISCLOSURE_THRESHOLD: u64 = 20;
+
+/// Fraction of the disclosure threshold at which a cell is reported as approaching it.
+const DISCLOSURE_WARN_RATIO: f64 = 0.8;
+
+/// Filter key for cells that are not narrowed beyond their age bucket.
+pub const FILTER_NONE: &str = "none";
+
+/// Whether every query must carry a purpose-of-use declaration (`REQUIRE_QUERY_PURPOSE`).
+pub fn purpose_required() -> bool {
+    std::env::var("REQUIRE_QUERY_PURPOSE")
+        .map(|v| v == "1" || v.eq_ignore_ascii_case("true"))
+        .unwrap_or(false)
+}
+
+/// Validate the purpose-of-use declaration itself.
+pub fn check_purpose(purpose: Option<&QueryPurpose>, required: bool) -> Result<(), String> {
+    let Some(purpose) = purpose else {
+        return if required {
+            Err("purpose (category, study_id, irb_reference) is required".to_string())
+        } else {
+            Ok(())
+        };
+    };
+
+    for (name, value) in [
+        ("category", &purpose.category),
+        ("study_id", &purpose.study_id),
+        ("irb_reference", &purpose.irb_reference),
+    ] {
+        if value.trim().is_empty() {
+            return Err(format!("purpose.{name} must not be empty"));
+        }
+    }
+    Ok(())
+}
+
+/// Check a query's declared purpose against the dataset's consent scope.
+///
+/// A dataset without a consent scope is unrestricted. Otherwise the query must declare a purpose
+/// that matches one of the consented purposes (case-insensitive).
+pub fn check_consent(consent_scope: Option<&[String]>, purpose: Option<&str>) -> Result<(), String> {
+    let Some(scope) = consent_scope else {
+        return Ok(());
+    };
+
+    let Some(purpose) = purpose else {
+        return Err(format!("dataset requires a declared purpose within consent scope {scope:?}"));
+    };
+
+    if scope.iter().any(|s| s.eq_ignore_ascii_case(purpose)) {
+        Ok(())
+    } else {
+        Err(format!("purpose '{purpose}' is outside the dataset consent scope {scope:?}"))
+    }
+}
+
+/// Server-wide cap on distinct releases per dataset per window (`RELEASE_LIMIT_PER_WINDOW`).
+///
+/// Unset means unlimited; datasets can override it with their own `release_limit`.
+pub fn default_release_limit() -> Option<u64> {
+    std::env::var("RELEASE_LIMIT_PER_WINDOW").ok().and_then(|v| v.parse().ok())
+}
+
+/// Length of the release-throttling window (`RELEASE_WINDOW_SECS`).
+pub fn release_window() -> Duration {
+    let secs = std::env::var("RELEASE_WINDOW_SECS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(DEFAULT_RELEASE_WINDOW_SECS);
+    Duration::from_secs(secs)
+}
+
+/// Check whether releasing `key` stays within the dataset's release budget.
+///
+/// Many overlapping aggregates over the same records can be combined to reconstruct individual
+/// values, so the number of *distinct* releases per window is capped. Re-releasing an aggregate
+/// already disclosed in the window reveals nothing new and is always allowed.
+pub fn check_release_budget(released: &[String], key: &str, limit: Option<u64>) -> Result<(), String> {
+    let Some(limit) = limit else {
+        return Ok(());
+    };
+
+    if released.iter().any(|k| k == key) || (released.len() as u64) < limit {
+        Ok(())
+    } else {
+        Err(format!(
+            "release limit reached: {limit} distinct aggregates per {}s window for this dataset",
+            release_window().as_secs()
+        ))
+    }
+}
+
+/// Smallest bucket count whose aggregates are released (`MIN_CELL_COUNT`, the k of
+/// k-anonymity); `None` (unset, 0 or 1) releases every count.
+///
+/// A count of a few records, with its sum or mean, is close to individual-level data.
+pub fn min_cell_count() -> Option<u64> {
+    std::env::var("MIN_CELL_COUNT").ok().and_then(|v| v.parse().ok()).filter(|k| *k > 1)
+}
+
+/// Whether a cell of `count` records falls below `min_count`.
+pub fn below_min_count(count: u64, min_count: Option<u64>) -> bool {
+    min_count.is_some_and(|k| count < k)
+}
+
+/// Which of `counts` to withhold when they partition a known total (a dataset's or a shard's
+/// age buckets): those below `min_count`, then as `suppress_complement` adds.
+pub fn suppressed_cells(counts: &[u64], min_count: Option<u64>) -> Vec<bool> {
+    let mut suppressed: Vec<bool> = counts.iter().map(|&count| below_min_count(count, min_count)).collect();
+    suppress_complement(counts, &mut suppressed, min_count);
+    suppressed
+}
+
+/// Also withhold the smallest released cells until the withheld ones add up to `min_count`, so
+/// the total minus the released cells doesn't give a small cell away. Returns whether any was added.
+pub fn suppress_complement(counts: &[u64], suppressed: &mut [bool], min_count: Option<u64>) -> bool {
+    let mut added = false;
+    loop {
+        let withheld: u64 = counts.iter().zip(suppressed.iter()).filter(|(_, s)| **s).map(|(count, _)| count).sum();
+        if !suppressed.contains(&true) || !below_min_count(withheld, min_count) {
+            return added;
+        }
+        let Some(next) = (0..counts.len()).filter(|&i| !suppressed[i]).min_by_key(|&i| counts[i]) else {
+            return added;
+        };
+        suppressed[next] = true;
+        added = true;
+    }
+}
+
+/// Per-cell disclosure threshold (`DISCLOSURE_THRESHOLD`).
+pub fn disclosure_threshold() -> u64 {
+    std::env::var("DISCLOSURE_THRESHOLD")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(DEFAULT_DISCLOSURE_THRESHOLD)
+}
+
+/// Classify a cell's cumulative release count against the threshold.
+pub fn disclosure_level(release_count: u64, threshold: u64) -> DisclosureLevel {
+    if release_count >= threshold {
+        DisclosureLevel::Exceeded
+    } else if release_count as f64 >= threshold as f64 * DISCLOSURE_WARN_RATIO {
+        DisclosureLevel::Approaching
+    } else {
+        DisclosureLevel::Ok
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DisclosureLevel {
+    Ok,
+    Approaching,
+    Exceeded,
+}
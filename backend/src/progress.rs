@@ -0,0 +1,208 @@
+//! Live progress of dataset proving runs, for `GET /api/v1/datasets/:id/events`.
+//!
+//! Proving jobs publish an event after every shard, and one when the dataset is ready or has
+//! failed, on a broadcast channel in `AppState` (memory only: each instance reports the runs it
+//! executes). The endpoint streams them as Server-Sent Events (`event: progress`), starting with
+//! the dataset's current state and ending after `ready`, `failed` or `cancelled`. A subscriber that falls
+//! behind skips to the latest events rather than slowing proving down.
+//!
+//! Dataset re-verification jobs (`reverify`) publish on a channel of their own, streamed the same
+//! way by `GET /api/v1/verify/dataset/:id/events` (`event: verification`).
+
+use crate::acl;
+use crate::auth::Caller;
+use crate::errors::ApiError;
+use crate::state::AppState;
+use axum::response::sse::{Event, KeepAlive, Sse};
+use futures_util::stream::{self, Stream, StreamExt};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::sync::Mutex;
+use std::time::Instant;
+use tokio::sync::broadcast;
+use uuid::Uuid;
+
+/// Events kept for subscribers that fall behind.
+const CHANNEL_CAPACITY: usize = 256;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ProgressEvent {
+    pub dataset_id: Uuid,
+    /// `generating`, `ready`, `failed` or `cancelled`.
+    pub status: String,
+    pub shards_done: u64,
+    pub shards_total: u64,
+    /// Shards proven per second since this run started; absent before its first shard.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub shards_per_sec: Option<f64>,
+    /// Seconds until the last shard at that rate.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub eta_secs: Option<f64>,
+}
+
+impl ProgressEvent {
+    fn finished(&self) -> bool {
+        self.status != "generating"
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct VerificationEvent {
+    pub dataset_id: Uuid,
+    /// `queued`, `running`, `passed`, `failed` (some shard or the commitment doesn't verify) or
+    /// `error` (the job itself failed).
+    pub status: String,
+    pub shards_checked: u64,
+    pub shards_total: u64,
+    /// Shards found not to verify so far.
+    pub failures: u64,
+}
+
+impl VerificationEvent {
+    fn finished(&self) -> bool {
+        self.status != "queued" && self.status != "running"
+    }
+}
+
+pub struct ProgressHub {
+    sender: broadcast::Sender<ProgressEvent>,
+    /// Per running dataset: when this run started and the shards already done then.
+    runs: Mutex<HashMap<Uuid, (Instant, u64)>>,
+    verifications: broadcast::Sender<VerificationEvent>,
+}
+
+impl Default for ProgressHub {
+    fn default() -> Self {
+        Self {
+            sender: broadcast::channel(CHANNEL_CAPACITY).0,
+            runs: Mutex::new(HashMap::new()),
+            verifications: broadcast::channel(CHANNEL_CAPACITY).0,
+        }
+    }
+}
+
+impl ProgressHub {
+    /// A proving run of `dataset_id` starts with `shards_done` shards already proven.
+    pub fn run_started(&self, dataset_id: Uuid, shards_done: u64) {
+        if let Ok(mut runs) = self.runs.lock() {
+            runs.insert(dataset_id, (Instant::now(), shards_done));
+        }
+    }
+
+    /// `shards_done` of `shards_total` shards of `dataset_id` are proven.
+    pub fn shard_done(&self, dataset_id: Uuid, shards_done: u64, shards_total: u64) {
+        let run = self.runs.lock().ok().and_then(|runs| runs.get(&dataset_id).copied());
+        let shards_per_sec = run.and_then(|(started, done_at_start)| {
+            let elapsed = started.elapsed().as_secs_f64();
+            (shards_done > done_at_start && elapsed > 0.0).then(|| (shards_done - done_at_start) as f64 / elapsed)
+        });
+        // No receivers is not an error: nobody is watching.
+        let _ = self.sender.send(ProgressEvent {
+            dataset_id,
+            status: "generating".to_string(),
+            shards_done,
+            shards_total,
+            shards_per_sec,
+            eta_secs: shards_per_sec.map(|rate| shards_total.saturating_sub(shards_done) as f64 / rate),
+        });
+    }
+
+    /// The run of `dataset_id` ended with `status` (`ready`, `failed` or `cancelled`).
+    pub fn run_finished(&self, dataset_id: Uuid, status: &str, shards_done: u64, shards_total: u64) {
+        if let Ok(mut runs) = self.runs.lock() {
+            runs.remove(&dataset_id);
+        }
+        let _ = self.sender.send(ProgressEvent {
+            dataset_id,
+            status: status.to_string(),
+            shards_done,
+            shards_total,
+            shards_per_sec: None,
+            eta_secs: None,
+        });
+    }
+
+    /// Progress (or the end) of a re-verification of `event.dataset_id`.
+    pub fn verification(&self, event: VerificationEvent) {
+        let _ = self.verifications.send(event);
+    }
+
+    fn subscribe(&self) -> broadcast::Receiver<ProgressEvent> {
+        self.sender.subscribe()
+    }
+
+    pub fn subscribe_verifications(&self) -> broadcast::Receiver<VerificationEvent> {
+        self.verifications.subscribe()
+    }
+}
+
+fn sse_event(name: &'static str, event: &impl Serialize) -> Result<Event, Infallible> {
+    Ok(Event::default().event(name).json_data(event).unwrap_or_else(|_| Event::default().comment("unserializable event")))
+}
+
+/// `current` as `name` events, then the live events `receiver` has for `dataset_id`, until one
+/// is `finished`.
+fn event_stream<T: Clone + Serialize + Send + 'static>(
+    name: &'static str,
+    current: T,
+    receiver: broadcast::Receiver<T>,
+    dataset_id: Uuid,
+    id_of: fn(&T) -> Uuid,
+    finished: fn(&T) -> bool,
+) -> impl Stream<Item = Result<Event, Infallible>> {
+    let first = stream::iter([sse_event(name, &current)]);
+    let live = stream::unfold((receiver, finished(&current)), move |(mut receiver, done)| async move {
+        if done {
+            return None;
+        }
+        loop {
+            match receiver.recv().await {
+                Ok(event) if id_of(&event) == dataset_id => {
+                    let done = finished(&event);
+                    return Some((sse_event(name, &event), (receiver, done)));
+                }
+                Ok(_) | Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(broadcast::error::RecvError::Closed) => return None,
+            }
+        }
+    });
+    first.chain(live)
+}
+
+/// The SSE stream of `dataset_id`'s progress: its current state, then live events until its run
+/// has ended. Subject to the dataset's access list.
+pub async fn events(
+    state: &AppState,
+    caller: Option<&Caller>,
+    dataset_id: Uuid,
+) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>> + use<>>, ApiError> {
+    // Subscribe before reading the current state so no event falls between the two.
+    let receiver = state.progress.subscribe();
+    let Some(dataset) = state.store.get_dataset(dataset_id).await? else {
+        return Err(ApiError::NotFound("dataset not found".to_string()));
+    };
+    acl::check_access(state, caller, dataset_id).await?;
+    let current = ProgressEvent {
+        dataset_id,
+        status: dataset.status.clone(),
+        shards_done: state.store.count_shards_done(dataset_id).await?,
+        shards_total: dataset.shards_total(),
+        shards_per_sec: None,
+        eta_secs: None,
+    };
+
+    let stream = event_stream("progress", current, receiver, dataset_id, |e| e.dataset_id, ProgressEvent::finished);
+    Ok(Sse::new(stream).keep_alive(KeepAlive::default()))
+}
+
+/// The SSE stream of a re-verification of `dataset_id`: its `current` state, then the live
+/// events of `receiver` (subscribed before `current` was read) until it has ended.
+pub fn verification_events(
+    receiver: broadcast::Receiver<VerificationEvent>,
+    dataset_id: Uuid,
+    current: VerificationEvent,
+) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+    let stream = event_stream("verification", current, receiver, dataset_id, |e| e.dataset_id, VerificationEvent::finished);
+    Sse::new(stream).keep_alive(KeepAlive::default())
+}
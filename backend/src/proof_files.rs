@@ -0,0 +1,99 @@
+//! On-disk proof blob store of the SQLite ledger.
+//!
+//! Shard proofs are content-addressed (see `db::proof_blob_hash`). With the SQLite ledger their
+//! bytes live in files under `data/proofs/<first two hex digits>/<hash>.bin`, and `proof_blobs`
+//! keeps only the hash, size and creation time of each; shards refer to a proof by hash. This keeps
+//! the database small and `include_proof=true` listings off it. Databases that stored proofs as
+//! base64 in `proof_blobs` are moved to files on startup (`db::move_proof_blobs_to_files`).
+//!
+//! A Postgres ledger keeps proofs in its `proof_blobs` table, where every instance can reach them.
+//!
+//! Writing a blob and committing the row that references it, and deleting a row and its file, run
+//! under `ProofFiles::lock`, so a blob is never removed between the two.
+
+use crate::errors::ApiError;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::sync::{Mutex, OwnedMutexGuard};
+
+/// Directory under the data directory holding proof files.
+pub const PROOFS_DIR: &str = "proofs";
+
+#[derive(Clone)]
+pub struct ProofFiles {
+    dir: PathBuf,
+    lock: Arc<Mutex<()>>,
+}
+
+impl ProofFiles {
+    pub fn new(data_dir: &Path) -> Self {
+        Self {
+            dir: data_dir.join(PROOFS_DIR),
+            lock: Arc::new(Mutex::new(())),
+        }
+    }
+
+    /// Path of blob `hash` relative to the data directory, `/`-separated.
+    pub fn rel_path(hash: &str) -> String {
+        format!("{PROOFS_DIR}/{}/{hash}.bin", &hash[..2.min(hash.len())])
+    }
+
+    fn path(&self, hash: &str) -> PathBuf {
+        self.dir.join(&hash[..2.min(hash.len())]).join(format!("{hash}.bin"))
+    }
+
+    /// Hold off other writers and deleters of proof files.
+    pub async fn lock(&self) -> OwnedMutexGuard<()> {
+        self.lock.clone().lock_owned().await
+    }
+
+    /// Write blob `hash` unless it exists. The file appears atomically.
+    pub async fn write(&self, hash: &str, bytes: &[u8]) -> Result<(), ApiError> {
+        let path = self.path(hash);
+        if tokio::fs::try_exists(&path).await.unwrap_or(false) {
+            return Ok(());
+        }
+        let parent = path.parent().ok_or(ApiError::Internal)?;
+        tokio::fs::create_dir_all(parent).await.map_err(|_| ApiError::Internal)?;
+        let tmp = path.with_extension("tmp");
+        tokio::fs::write(&tmp, bytes).await.map_err(|_| ApiError::Internal)?;
+        tokio::fs::rename(&tmp, &path).await.map_err(|_| ApiError::Internal)
+    }
+
+    /// The bytes of blob `hash`; `None` if its file is missing.
+    pub async fn read(&self, hash: &str) -> Result<Option<Vec<u8>>, ApiError> {
+        match tokio::fs::read(self.path(hash)).await {
+            Ok(bytes) => Ok(Some(bytes)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(_) => Err(ApiError::Internal),
+        }
+    }
+
+    /// Delete blob `hash`; a missing file is not an error.
+    pub async fn remove(&self, hash: &str) -> Result<(), ApiError> {
+        match tokio::fs::remove_file(self.path(hash)).await {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            Err(_) => Err(ApiError::Internal),
+        }
+    }
+
+    /// Every proof file, as paths relative to the data directory, sorted.
+    pub fn list_rel_paths(&self) -> Vec<String> {
+        let mut paths = Vec::new();
+        let Ok(prefixes) = std::fs::read_dir(&self.dir) else {
+            return paths;
+        };
+        for prefix in prefixes.flatten().filter(|e| e.path().is_dir()) {
+            let Ok(files) = std::fs::read_dir(prefix.path()) else { continue };
+            for file in files.flatten() {
+                let name = file.file_name().to_string_lossy().to_string();
+                if let Some(hash) = name.strip_suffix(".bin") {
+                    paths.push(Self::rel_path(hash));
+                }
+            }
+        }
+        paths.sort();
+        paths
+    }
+}
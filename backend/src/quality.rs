@@ -0,0 +1,98 @@
+//! Data-quality accounting for ingested records.
+//!
+//! Quality metrics are computed host-side and are NOT covered by the shard proofs; only the
+//! per-bucket counts they are reported against come from proven shard stats.
+
+use serde::{Deserialize, Serialize};
+use zk_proofs::types::{AgeBuckets, Measurement, Record};
+
+/// Oldest age accepted at ingestion. The circuit would silently clamp older ages into the last
+/// bucket, so they are rejected instead.
+pub const MAX_AGE: u8 = zk_proofs::constants::MAX_AGE;
+
+/// Physiologically plausible glucose range (mg/dL). The shard circuit bounds glucose to it from v5
+/// on, so values outside it are rejected at ingestion; shards ingested before were only flagged.
+pub const PLAUSIBLE_GLUCOSE_MG_DL: (u16, u16) = zk_proofs::constants::PLAUSIBLE_GLUCOSE_MG_DL;
+
+/// Physiologically plausible range of `measurement`, in its unit (BMI in tenths of kg/m²).
+pub fn plausible_range(measurement: Measurement) -> (u16, u16) {
+    match measurement {
+        Measurement::BloodGlucose => PLAUSIBLE_GLUCOSE_MG_DL,
+        Measurement::SystolicBp => (60, 260),
+        Measurement::HeartRate => (25, 250),
+        Measurement::Bmi => (100, 800),
+    }
+}
+
+/// Rows seen at ingestion and why any were rejected.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct IngestQuality {
+    pub rows_read: u64,
+    pub rows_accepted: u64,
+    pub missing_age: u64,
+    pub missing_glucose: u64,
+    /// Non-numeric or above `MAX_AGE`.
+    pub invalid_age: u64,
+    /// Non-numeric or outside `PLAUSIBLE_GLUCOSE_MG_DL`.
+    pub invalid_glucose: u64,
+    /// Vitals uploads: rows lacking systolic blood pressure, heart rate or BMI.
+    #[serde(default)]
+    pub missing_measurement: u64,
+    /// Vitals uploads: one of those values is non-numeric or out of range.
+    #[serde(default)]
+    pub invalid_measurement: u64,
+}
+
+impl IngestQuality {
+    /// Quality for generator output, which is valid by construction.
+    pub fn synthetic(dataset_size: u64) -> Self {
+        Self {
+            rows_read: dataset_size,
+            rows_accepted: dataset_size,
+            ..Self::default()
+        }
+    }
+
+    /// Quality for records proven by another instance; its rejection counts are not exported.
+    pub fn external(dataset_size: u64) -> Self {
+        Self::synthetic(dataset_size)
+    }
+
+    pub fn rows_rejected(&self) -> u64 {
+        self.rows_read - self.rows_accepted
+    }
+
+    /// Fold in the counts of another batch (streamed datasets ingest many).
+    pub fn add(&mut self, other: &IngestQuality) {
+        self.rows_read += other.rows_read;
+        self.rows_accepted += other.rows_accepted;
+        self.missing_age += other.missing_age;
+        self.missing_glucose += other.missing_glucose;
+        self.invalid_age += other.invalid_age;
+        self.invalid_glucose += other.invalid_glucose;
+        self.missing_measurement += other.missing_measurement;
+        self.invalid_measurement += other.invalid_measurement;
+    }
+}
+
+/// Per-shard quality counts for accepted records.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ShardQuality {
+    /// Records whose glucose lies outside `PLAUSIBLE_GLUCOSE_MG_DL`.
+    pub out_of_range_glucose_by_bucket: Vec<u64>,
+}
+
+impl ShardQuality {
+    pub fn compute(records: &[Record], buckets: &AgeBuckets) -> Self {
+        let (lo, hi) = PLAUSIBLE_GLUCOSE_MG_DL;
+        let mut out_of_range_glucose_by_bucket = vec![0u64; buckets.num_buckets()];
+        for r in records {
+            if r.blood_glucose_mg_dl < lo || r.blood_glucose_mg_dl > hi {
+                out_of_range_glucose_by_bucket[buckets.bucket_for_age(r.age)] += 1;
+            }
+        }
+        Self {
+            out_of_range_glucose_by_bucket,
+        }
+    }
+}
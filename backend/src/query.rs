@@ -0,0 +1,607 @@
+//! Query evaluation shared by the synchronous handler, approvals and async query jobs.
+//!
+//! Answers are summed from the stored shard aggregates in SQL. For `poseidon`-chained datasets of
+//! up to `QUERY_PROOF_MAX_SHARDS` shards (default 64, `0` disables), the released sum and count
+//! are also proven (`zk_proofs::query`) to be the totals over exactly the shards the dataset
+//! commitment chains, so a verifier needn't trust the backend's arithmetic; the proof is made when
+//! the answer is released and stored with it. Differentially private answers (`dp`) are noisy
+//! and unproven.
+//!
+//! An age range spanning several buckets ("18–49") adds up the totals of those buckets, and a
+//! complement query ("everyone not aged 18–29") those of every other bucket: the same totals
+//! `GET /datasets/:id/aggregates` lists, named in the answer so the sum can be checked. Such
+//! answers are not query-proven (the circuit proves one bucket's total), and are suppressed when
+//! any bucket they add up is below `MIN_CELL_COUNT`: otherwise subtracting the other buckets'
+//! answers would reveal it. A complement is also suppressed when the bucket it leaves out is below
+//! it, since the public dataset size minus the complement's count is that bucket's count. A `group_by` query (`breakdown`) answers each bucket of its range on
+//! its own instead, as that many single-bucket queries released together.
+
+use crate::aggregate;
+use crate::chain::ChainHash;
+use crate::dataset::field_hex;
+use crate::db::{self, QueryBuckets, QueryResult};
+use crate::dp;
+use crate::errors::ApiError;
+use crate::key_usage;
+use crate::models::{
+    CombinedBucket, DpParams, GroupBy, HistogramBin, Metric, QueryBreakdownResponse, QueryComplement, QueryProofStatement, QueryPurpose,
+    QueryResponse, QueryShardSet, QuerySuppression,
+};
+use crate::policy;
+use crate::receipt;
+use crate::state::AppState;
+use base64::Engine;
+use rand::rngs::OsRng;
+use uuid::Uuid;
+use zk_proofs::aggregate::AggregateShape;
+use zk_proofs::constants::{GLUCOSE_RANGES, MAX_BUCKETS};
+use zk_proofs::groth16::{serialize_proof, serialize_vk};
+use zk_proofs::query::{prove_query, query_input_indices, verify_query_proof};
+use zk_proofs::types::{AgeBuckets, Measurement, ShardStats};
+
+use ark_bn254::Fr;
+
+const DEFAULT_QUERY_PROOF_MAX_SHARDS: u64 = 64;
+
+/// Largest dataset, in shards, whose answers are proven (`QUERY_PROOF_MAX_SHARDS`). The circuit
+/// grows by about 6k constraints per shard and proving runs before the answer is released.
+pub fn query_proof_max_shards() -> u64 {
+    std::env::var("QUERY_PROOF_MAX_SHARDS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(DEFAULT_QUERY_PROOF_MAX_SHARDS)
+}
+
+/// Reject (429) a release that would exceed the dataset's distinct-release budget.
+pub async fn enforce_release_limit(
+    state: &AppState,
+    dataset_id: Uuid,
+    dataset: &db::DatasetRow,
+    buckets: QueryBuckets,
+    field: Measurement,
+) -> Result<(), ApiError> {
+    enforce_release_limit_for(state, dataset_id, dataset, &[db::release_key(buckets, field)]).await
+}
+
+/// Reject (429) releasing all of `keys` at once if that would exceed the dataset's budget.
+pub async fn enforce_release_limit_for(
+    state: &AppState,
+    dataset_id: Uuid,
+    dataset: &db::DatasetRow,
+    keys: &[String],
+) -> Result<(), ApiError> {
+    let limit = dataset.release_limit.or_else(policy::default_release_limit);
+    if limit.is_none() {
+        return Ok(());
+    }
+
+    let window = chrono::Duration::from_std(policy::release_window()).map_err(|_| ApiError::Internal)?;
+    let mut released = state.store.release_keys_since(dataset_id, chrono::Utc::now() - window).await?;
+
+    for key in keys {
+        if let Err(reason) = policy::check_release_budget(&released, key, limit) {
+            state.store.append_audit(
+                Some(dataset_id),
+                "query_throttled",
+                &serde_json::json!({ "release_key": key, "limit": limit, "reason": reason }),
+            )
+            .await?;
+            return Err(ApiError::TooManyRequests(reason));
+        }
+        if !released.contains(key) {
+            released.push(key.clone());
+        }
+    }
+    Ok(())
+}
+
+/// Reject a measurement the dataset does not record; otherwise its index in the field set.
+pub fn field_index(dataset: &db::DatasetRow, field: Measurement) -> Result<usize, ApiError> {
+    dataset.field_set.position(field).ok_or_else(|| {
+        ApiError::BadRequest(format!(
+            "field '{}' is not measured in this dataset (field set '{}')",
+            field.name(),
+            dataset.field_set.name()
+        ))
+    })
+}
+
+/// Reject a metric that isn't proven for `field`: variance and stddev need sums of squares, and
+/// histograms need range counts, which shards prove for blood glucose only.
+pub fn check_metric(metric: &Metric, field: Measurement) -> Result<(), ApiError> {
+    match metric {
+        Metric::Variance | Metric::Stddev if field != Measurement::BloodGlucose => Err(ApiError::BadRequest(format!(
+            "variance and stddev are only proven for blood_glucose, not '{}'",
+            field.name()
+        ))),
+        Metric::Histogram if field != Measurement::BloodGlucose => Err(ApiError::BadRequest(format!(
+            "histograms are only proven for blood_glucose, not '{}'",
+            field.name()
+        ))),
+        _ => Ok(()),
+    }
+}
+
+/// Population variance from a bucket's sum, sum of squares and count, computed exactly in
+/// integers before the final division.
+fn variance(sum: u64, sum_sq: u64, count: u64) -> Option<f64> {
+    if count == 0 {
+        return None;
+    }
+    let n = count as u128;
+    let numerator = (n * sum_sq as u128).saturating_sub(sum as u128 * sum as u128);
+    Some(numerator as f64 / (n * n) as f64)
+}
+
+/// Totals of `bucket_indices` added up, with the buckets and their counts.
+async fn combined_totals(
+    state: &AppState,
+    dataset_id: Uuid,
+    dataset: &db::DatasetRow,
+    bucket_indices: &[usize],
+    field_index: usize,
+) -> Result<(db::BucketTotals, Vec<(usize, u64)>), ApiError> {
+    let live_shards = dataset.live_shards();
+    let mut combined = Vec::new();
+    let mut totals: Option<db::BucketTotals> = None;
+    for &bucket_index in bucket_indices {
+        let bucket = state.store.aggregate_for_bucket(dataset_id, live_shards.clone(), bucket_index, field_index, &dataset.age_buckets).await?;
+        combined.push((bucket_index, bucket.count));
+        totals = Some(match totals {
+            None => bucket,
+            // Every bucket is summed over the same shards, so the shard set is shared.
+            Some(t) => db::BucketTotals {
+                sum: t.sum + bucket.sum,
+                count: t.count + bucket.count,
+                sum_sq: t.sum_sq.zip(bucket.sum_sq).map(|(a, b)| a + b),
+                glucose_histogram: t
+                    .glucose_histogram
+                    .zip(bucket.glucose_histogram)
+                    .map(|(a, b)| std::array::from_fn(|i| a[i] + b[i])),
+                ..t
+            },
+        });
+    }
+    let totals = totals.ok_or_else(|| ApiError::BadRequest("the age range covers every bucket, which leaves no complement".to_string()))?;
+    Ok((totals, combined))
+}
+
+/// Aggregate one measurement of the query's buckets (of every other bucket, for a complement
+/// query) over the live proven shards (all of them, or the rolling window), recording the exact
+/// shard set used. With `dp` the answer is noisy and its epsilon is spent from the dataset's
+/// budget first.
+pub async fn compute_answer(
+    state: &AppState,
+    dataset_id: Uuid,
+    dataset: &db::DatasetRow,
+    spec: &db::QuerySpec<'_>,
+) -> Result<QueryResult, ApiError> {
+    compute(state, dataset_id, dataset, spec, true).await
+}
+
+/// `compute_answer` without a query proof, for answers that are only released pooled.
+pub async fn compute_answer_unproven(
+    state: &AppState,
+    dataset_id: Uuid,
+    dataset: &db::DatasetRow,
+    spec: &db::QuerySpec<'_>,
+) -> Result<QueryResult, ApiError> {
+    compute(state, dataset_id, dataset, spec, false).await
+}
+
+async fn compute(
+    state: &AppState,
+    dataset_id: Uuid,
+    dataset: &db::DatasetRow,
+    spec: &db::QuerySpec<'_>,
+    prove: bool,
+) -> Result<QueryResult, ApiError> {
+    let (metric, buckets, field, dp) = (spec.metric, spec.buckets, spec.field, spec.dp.as_ref());
+    let field_index = field_index(dataset, field)?;
+    check_metric(metric, field)?;
+    let epsilon_remaining = match dp {
+        Some(dp) => dp::spend_budget(state, dataset_id, dp).await?,
+        None => None,
+    };
+    let live_shards = dataset.live_shards();
+    let (totals, combined) = if buckets.complement || buckets.spans() {
+        let bucket_indices = buckets.indices(dataset.age_buckets.num_buckets());
+        let (totals, combined) = combined_totals(state, dataset_id, dataset, &bucket_indices, field_index).await?;
+        (totals, Some(combined))
+    } else {
+        let totals = state.store.aggregate_for_bucket(dataset_id, live_shards.clone(), buckets.first, field_index, &dataset.age_buckets).await?;
+        (totals, None)
+    };
+    let excluded = if buckets.complement {
+        let mut excluded = Vec::new();
+        for bucket_index in buckets.first..=buckets.last {
+            let bucket = state.store.aggregate_for_bucket(dataset_id, live_shards.clone(), bucket_index, field_index, &dataset.age_buckets).await?;
+            excluded.push((bucket_index, bucket.count));
+        }
+        Some(excluded)
+    } else {
+        None
+    };
+    let db::BucketTotals {
+        sum,
+        count,
+        sum_sq,
+        glucose_histogram,
+        shards_total: shards_used,
+        verified_bitmap,
+    } = totals;
+
+    let mean = match metric {
+        Metric::Mean => {
+            if count == 0 {
+                None
+            } else {
+                Some(sum as f64 / count as f64)
+            }
+        }
+        _ => None,
+    };
+
+    let sum_sq = match metric {
+        Metric::Variance | Metric::Stddev => Some(sum_sq.ok_or_else(|| {
+            ApiError::Conflict(
+                "dataset has shards proven without sums of squares (keys set up before variance support)".to_string(),
+            )
+        })?),
+        _ => None,
+    };
+
+    let glucose_histogram = match metric {
+        Metric::Histogram => Some(glucose_histogram.ok_or_else(|| {
+            ApiError::Conflict(
+                "dataset has shards proven without glucose histograms (keys set up before histogram support)".to_string(),
+            )
+        })?),
+        _ => None,
+    };
+
+    // Server-side verification: all live shards must be verified.
+    let shards_verified: u64 = verified_bitmap.iter().map(|b| b.count_ones() as u64).sum();
+    let verified = shards_verified == live_shards.end - live_shards.start;
+
+    // A proof of the exact answer would undo the noise.
+    let query_proof = if prove && verified && dp.is_none() && combined.is_none() {
+        prove_answer(state, dataset_id, dataset, field_index, buckets.first, (sum, count)).await?
+    } else {
+        None
+    };
+
+    let result = QueryResult {
+        sum,
+        count,
+        mean,
+        sum_sq,
+        variance: sum_sq.and_then(|sum_sq| variance(sum, sum_sq, count)),
+        glucose_histogram,
+        verified,
+        shard_set: Some(QueryShardSet {
+            dataset_commitment_hex: dataset.commitment_hex.clone(),
+            shards_total: shards_used,
+            verified_bitmap_hex: hex::encode(&verified_bitmap),
+            first_shard_index: live_shards.start,
+        }),
+        query_proof,
+        dp: None,
+        combined,
+        excluded,
+    };
+    Ok(match dp {
+        Some(dp) => dp::privatize(result, metric, dp, epsilon_remaining),
+        None => result,
+    })
+}
+
+/// Prove that `answer` (sum, count) is the total over the dataset's shards, if the dataset
+/// qualifies for query proofs. The proof covers every shard chained into the commitment, so
+/// rolling-window datasets with expired shards don't.
+async fn prove_answer(
+    state: &AppState,
+    dataset_id: Uuid,
+    dataset: &db::DatasetRow,
+    field_index: usize,
+    bucket_index: usize,
+    answer: (u64, u64),
+) -> Result<Option<(String, QueryProofStatement)>, ApiError> {
+    let shards_total = dataset.shards_total();
+    let Some(commitment_hex) = dataset.commitment_hex.as_deref() else {
+        return Ok(None);
+    };
+    if dataset.chain_hash != ChainHash::Poseidon
+        || shards_total == 0
+        || shards_total > query_proof_max_shards()
+        || dataset.live_shards().start > 0
+    {
+        return Ok(None);
+    }
+
+    let shards: Vec<(Fr, ShardStats)> = aggregate::load_shards(state, dataset_id, dataset, false)
+        .await?
+        .into_iter()
+        .map(|(commitment, stats, _)| (commitment, stats))
+        .collect();
+    let keys = state.ensure_query_keys(AggregateShape::of(shards.len(), &shards[0].1)).await?;
+    let (sum_index, count_index) = query_input_indices(field_index, bucket_index, dataset.age_buckets.num_buckets());
+
+    let permit = state.proving_admission.acquire(keys.proof_bytes).await;
+    let pk = keys.pk.clone();
+    let vk = keys.vk.clone();
+    let (proof, statement) = tokio::task::spawn_blocking(move || {
+        let (proof, statement) =
+            prove_query(&mut OsRng, pk.as_ref(), &shards, sum_index, count_index).map_err(|e| ApiError::Conflict(format!("{e}")))?;
+        // Fail closed if the proof doesn't verify.
+        verify_query_proof(vk.as_ref(), &proof, &statement).map_err(|_| ApiError::Internal)?;
+        Ok::<_, ApiError>((proof, statement))
+    })
+    .await
+    .map_err(|_| ApiError::Internal)??;
+    drop(permit);
+    key_usage::record_proof(state, &keys.key_id, key_usage::CIRCUIT_QUERY, dataset_id).await?;
+
+    // The proof is over the stored shard inputs; the released answer must be what they sum to.
+    if (statement.sum, statement.count) != answer || field_hex(statement.dataset_commitment)? != commitment_hex {
+        return Err(ApiError::Conflict(
+            "stored aggregates don't match the shards chained into the dataset commitment".to_string(),
+        ));
+    }
+
+    let b64 = |bytes: Vec<u8>| base64::engine::general_purpose::STANDARD.encode(bytes);
+    Ok(Some((
+        b64(serialize_proof(&proof).map_err(|_| ApiError::Internal)?),
+        QueryProofStatement {
+            shard_inputs_root_hex: field_hex(statement.shard_inputs_root)?,
+            sum_index,
+            count_index,
+            vk_b64: b64(serialize_vk(keys.vk.as_ref()).map_err(|_| ApiError::Internal)?),
+            key_id: keys.key_id,
+        },
+    )))
+}
+
+pub fn query_response(
+    query_id: Uuid,
+    dataset_id: Uuid,
+    buckets: &AgeBuckets,
+    metric: &Metric,
+    selected: QueryBuckets,
+    field: Measurement,
+    result: &QueryResult,
+) -> QueryResponse {
+    let bounds = buckets.bounds();
+    let (min_age, max_age) = (bounds[selected.first].0, bounds[selected.last].1);
+    let mean = match metric {
+        Metric::Mean => result.mean,
+        Metric::Sum | Metric::Count | Metric::Variance | Metric::Stddev | Metric::Histogram => None,
+    };
+    let (variance, stddev) = match metric {
+        Metric::Variance => (result.variance, None),
+        Metric::Stddev => (None, result.variance.map(f64::sqrt)),
+        _ => (None, None),
+    };
+    let glucose = field == Measurement::BloodGlucose;
+    // Noisy answers are private already, and suppressing on their noisy count is post-processing.
+    let min_count = policy::min_cell_count();
+    let any_below_min = |buckets: &Option<Vec<(usize, u64)>>| {
+        buckets.as_ref().is_some_and(|buckets| buckets.iter().any(|(_, count)| policy::below_min_count(*count, min_count)))
+    };
+    let combined_below_min = any_below_min(&result.combined);
+    let suppressed = if result.dp.is_some() {
+        None
+    } else if policy::below_min_count(result.count, min_count) {
+        Some("the bucket has too few records to release its aggregates")
+    } else if combined_below_min && selected.complement {
+        Some("a bucket of the complement has too few records; the other buckets' answers would reveal it")
+    } else if any_below_min(&result.excluded) {
+        Some("the excluded bucket has too few records; the dataset size minus the complement would reveal it")
+    } else if combined_below_min {
+        Some("a bucket of the age range has too few records; the other buckets' answers would reveal it")
+    } else {
+        None
+    }
+    .map(|reason| QuerySuppression {
+        min_count: min_count.unwrap_or_default(),
+        reason: reason.to_string(),
+    });
+    let released = suppressed.is_none();
+
+    QueryResponse {
+        query_id,
+        dataset_id,
+        bucket_index: selected.first,
+        bucket_range: (min_age, max_age),
+        field,
+        sum: released.then_some(result.sum),
+        count: released.then_some(result.count),
+        mean: mean.filter(|_| released),
+        suppressed,
+        sum_sq: result.sum_sq.filter(|_| released),
+        variance: variance.filter(|_| released),
+        stddev: stddev.filter(|_| released),
+        histogram: result.glucose_histogram.filter(|_| released).map(|counts| {
+            GLUCOSE_RANGES
+                .iter()
+                .zip(counts)
+                .map(|(glucose_range, count)| HistogramBin { glucose_range: *glucose_range, count })
+                .collect()
+        }),
+        sum_glucose: (glucose && released).then_some(result.sum),
+        mean_glucose: mean.filter(|_| glucose && released),
+        server_verified: result.verified,
+        shard_set: result.shard_set.clone(),
+        shard_proofs_endpoint: format!("/api/v1/datasets/{dataset_id}/shards?include_proof=true"),
+        // The proof's public inputs include the sum and count.
+        query_proof_b64: result.query_proof.as_ref().filter(|_| released).map(|(proof_b64, _)| proof_b64.clone()),
+        query_proof: result.query_proof.as_ref().filter(|_| released).map(|(_, statement)| statement.clone()),
+        dp: result.dp.clone(),
+        combined_buckets: match &result.combined {
+            Some(combined) if !selected.complement => combined_buckets(buckets, combined),
+            _ => Vec::new(),
+        },
+        complement: result.combined.as_ref().filter(|_| selected.complement).map(|combined| QueryComplement {
+            excluded_bucket_index: selected.first,
+            excluded_range: (min_age, max_age),
+            combined: combined_buckets(buckets, combined),
+            aggregates_endpoint: format!("/api/v1/datasets/{dataset_id}/aggregates"),
+        }),
+        receipt: None,
+    }
+}
+
+fn combined_buckets(buckets: &AgeBuckets, combined: &[(usize, u64)]) -> Vec<CombinedBucket> {
+    combined
+        .iter()
+        .map(|(bucket_index, _)| CombinedBucket {
+            bucket_index: *bucket_index,
+            bucket_range: buckets.bounds()[*bucket_index],
+        })
+        .collect()
+}
+
+/// Cells a released answer discloses, for disclosure tracking: its bucket, or every bucket a
+/// range or complement added up.
+pub fn released_cells(bucket_index: usize, result: &QueryResult) -> Vec<(usize, &'static str)> {
+    match &result.combined {
+        Some(combined) => combined.iter().map(|(bucket_index, _)| (*bucket_index, policy::FILTER_NONE)).collect(),
+        None => vec![(bucket_index, policy::FILTER_NONE)],
+    }
+}
+
+/// Metric, buckets and measurement of a stored query. Queries stored before ranges of several
+/// buckets have no `last_bucket_index`.
+pub fn stored_params(query: &db::QueryRow) -> Result<(Metric, QueryBuckets, Measurement), ApiError> {
+    let metric: Metric = serde_json::from_value(query.query_json["metric"].clone()).map_err(|_| ApiError::Internal)?;
+    let bucket = |key: &str| query.query_json[key].as_u64().map(|b| b as usize).filter(|b| *b < MAX_BUCKETS);
+    let first = bucket("bucket_index").ok_or(ApiError::Internal)?;
+    let last = match query.query_json.get("last_bucket_index") {
+        Some(_) => bucket("last_bucket_index").filter(|last| *last >= first).ok_or(ApiError::Internal)?,
+        None => first,
+    };
+    let buckets = QueryBuckets {
+        first,
+        last,
+        complement: query.query_json["complement"].as_bool().unwrap_or(false),
+    };
+    let field = query.query_json["field"]
+        .as_str()
+        .map_or(Some(Measurement::BloodGlucose), Measurement::parse)
+        .ok_or(ApiError::Internal)?;
+    Ok((metric, buckets, field))
+}
+
+/// Differential privacy requested with a stored query.
+pub fn stored_dp(query: &db::QueryRow) -> Result<Option<DpParams>, ApiError> {
+    serde_json::from_value(query.query_json["dp"].clone()).map_err(|_| ApiError::Internal)
+}
+
+/// Answer `metric` of `field` for each bucket of `buckets` (a range, not a complement) at once.
+/// Each bucket's answer is released, stored and signed as a query of its own (the release budget
+/// is checked for all of them first), but without a query proof, which would take a proving run
+/// per bucket.
+pub async fn breakdown(
+    state: &AppState,
+    dataset_id: Uuid,
+    dataset: &db::DatasetRow,
+    metric: &Metric,
+    field: Measurement,
+    purpose: Option<&QueryPurpose>,
+    buckets: QueryBuckets,
+) -> Result<QueryBreakdownResponse, ApiError> {
+    let selected: Vec<QueryBuckets> = (buckets.first..=buckets.last).map(QueryBuckets::single).collect();
+    let keys: Vec<String> = selected.iter().map(|bucket| db::release_key(*bucket, field)).collect();
+    enforce_release_limit_for(state, dataset_id, dataset, &keys).await?;
+
+    let mut answers = Vec::with_capacity(selected.len());
+    for bucket in selected {
+        let spec = db::QuerySpec {
+            metric,
+            purpose,
+            buckets: bucket,
+            field,
+            dp: None,
+            cohort_id: None,
+            requested_by: None,
+        };
+        let answer = compute_answer_unproven(state, dataset_id, dataset, &spec).await?;
+        let query_id = Uuid::new_v4();
+        state.store.insert_query(query_id, dataset_id, &spec, &answer).await?;
+        state.store.insert_released_cells(query_id, dataset_id, &released_cells(bucket.first, &answer)).await?;
+
+        let mut response = query_response(query_id, dataset_id, &dataset.age_buckets, metric, bucket, field, &answer);
+        receipt::sign(&state.signing_key, &mut response)?;
+        answers.push(response);
+    }
+    Ok(QueryBreakdownResponse {
+        dataset_id,
+        group_by: GroupBy::AgeBucket,
+        field,
+        buckets: answers,
+    })
+}
+
+/// Evaluate a stored, not-yet-released query and release its result.
+///
+/// `from_status` guards against double release: if another caller moved the query out of that
+/// state first, this fails with a conflict.
+pub async fn release_stored_query(
+    state: &AppState,
+    query_id: Uuid,
+    query: &db::QueryRow,
+    from_status: &str,
+    decided_by: Option<&str>,
+) -> Result<QueryResponse, ApiError> {
+    let (metric, buckets, field) = stored_params(query)?;
+
+    let Some(dataset) = state.store.get_dataset(query.dataset_id).await? else {
+        return Err(ApiError::NotFound("dataset not found".to_string()));
+    };
+
+    enforce_release_limit(state, query.dataset_id, &dataset, buckets, field).await?;
+
+    let spec = db::QuerySpec {
+        metric: &metric,
+        purpose: None,
+        buckets,
+        field,
+        dp: stored_dp(query)?,
+        cohort_id: None,
+        requested_by: None,
+    };
+    let result = compute_answer(state, query.dataset_id, &dataset, &spec).await?;
+
+    if !state.store.release_query(query_id, from_status, &result, decided_by).await? {
+        return Err(ApiError::Conflict("query already decided".to_string()));
+    }
+    state.store.insert_released_cells(query_id, query.dataset_id, &released_cells(buckets.first, &result)).await?;
+
+    let mut response = query_response(query_id, query.dataset_id, &dataset.age_buckets, &metric, buckets, field, &result);
+    receipt::sign(&state.signing_key, &mut response)?;
+    Ok(response)
+}
+
+/// Job body for an async query: `queued` -> `running` -> `released` (or `failed`).
+///
+/// A query is only ever run by its own job, so one already `running` was interrupted by a
+/// restart or shutdown (its job was requeued, the query's status wasn't) and is evaluated again.
+pub async fn run_async_query(state: &AppState, query_id: Uuid) -> Result<(), ApiError> {
+    let started = state.store.set_query_status(query_id, "queued", "running").await?;
+
+    let Some(query) = state.store.get_query(query_id).await? else {
+        return Err(ApiError::NotFound("query not found".to_string()));
+    };
+    if !started && query.status != "running" {
+        // Already released or failed, by an earlier run of this job.
+        return Ok(());
+    }
+
+    match release_stored_query(state, query_id, &query, "running", None).await {
+        Ok(_) => Ok(()),
+        Err(e) => {
+            state.store.fail_query(query_id, &format!("{e}")).await?;
+            Err(e)
+        }
+    }
+}
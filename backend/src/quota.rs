@@ -0,0 +1,127 @@
+//! Per-tenant quotas.
+//!
+//! A tenant is an API key (`Caller::key_id`). Limits come from the environment and apply to every
+//! tenant; an unset limit is unlimited:
+//! - `QUOTA_MAX_DATASETS`: datasets a tenant may create (failed ones included).
+//! - `QUOTA_MAX_RECORDS`: total records across a tenant's datasets that haven't failed or been
+//!   cancelled.
+//! - `QUOTA_MAX_CONCURRENT_PROVING`: proving jobs of one tenant running at once; further jobs
+//!   wait in the queue.
+//! - `QUOTA_MAX_QUERIES_PER_DAY`: queries a tenant may submit per UTC day, counted in the ledger
+//!   so every instance sharing it enforces one quota. Queries refused before they were created
+//!   don't count.
+//!
+//! Request rates are limited separately, per instance (see `rate_limit`).
+
+use crate::db::TenantUsageRow;
+use crate::errors::ApiError;
+use crate::state::AppState;
+use chrono::{Days, Utc};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct Quotas {
+    pub max_datasets: Option<u64>,
+    pub max_records: Option<u64>,
+    pub max_concurrent_proving: Option<u64>,
+    pub max_queries_per_day: Option<u64>,
+}
+
+fn env_limit(name: &str) -> Option<u64> {
+    std::env::var(name).ok().and_then(|v| v.parse().ok())
+}
+
+pub fn quotas() -> Quotas {
+    Quotas {
+        max_datasets: env_limit("QUOTA_MAX_DATASETS"),
+        max_records: env_limit("QUOTA_MAX_RECORDS"),
+        max_concurrent_proving: env_limit("QUOTA_MAX_CONCURRENT_PROVING").filter(|n| *n > 0),
+        max_queries_per_day: env_limit("QUOTA_MAX_QUERIES_PER_DAY"),
+    }
+}
+
+/// Check whether a tenant with `usage` may register another dataset of `new_records` records.
+pub fn check_new_dataset(quotas: &Quotas, usage: &TenantUsageRow, new_records: u64) -> Result<(), String> {
+    if let Some(max) = quotas.max_datasets
+        && usage.datasets >= max
+    {
+        return Err(format!("dataset quota exhausted ({max} datasets)"));
+    }
+    check_more_records(quotas, usage, new_records)
+}
+
+/// Check whether a tenant with `usage` may add `new_records` records to an existing dataset.
+pub fn check_more_records(quotas: &Quotas, usage: &TenantUsageRow, new_records: u64) -> Result<(), String> {
+    if let Some(max) = quotas.max_records
+        && usage.records.saturating_add(new_records) > max
+    {
+        return Err(format!(
+            "record quota exceeded: {} in use + {new_records} requested > {max}",
+            usage.records
+        ));
+    }
+    Ok(())
+}
+
+/// What `key_id` holds: datasets and records from the ledger, proving jobs from the job queue.
+pub async fn tenant_usage(state: &AppState, key_id: &str) -> Result<TenantUsageRow, ApiError> {
+    let (datasets, records) = state.store.tenant_datasets(key_id).await?;
+    let (proving_running, proving_queued) = state.store.tenant_proving_jobs(key_id).await?;
+    let queries_today = state.store.daily_queries(key_id, &quota_day()).await?;
+    Ok(TenantUsageRow {
+        datasets,
+        records,
+        proving_running,
+        proving_queued,
+        queries_today,
+    })
+}
+
+/// Reject (429) a new dataset of `new_records` records that would exceed `key_id`'s quota.
+pub async fn enforce_new_dataset(state: &AppState, key_id: &str, new_records: u64) -> Result<(), ApiError> {
+    let usage = tenant_usage(state, key_id).await?;
+    check_new_dataset(&quotas(), &usage, new_records).map_err(ApiError::TooManyRequests)
+}
+
+/// Reject (429) `new_records` more records for one of `key_id`'s datasets beyond its quota.
+pub async fn enforce_more_records(state: &AppState, key_id: &str, new_records: u64) -> Result<(), ApiError> {
+    let usage = tenant_usage(state, key_id).await?;
+    check_more_records(&quotas(), &usage, new_records).map_err(ApiError::TooManyRequests)
+}
+
+/// The UTC day queries are counted against, as `YYYY-MM-DD`.
+fn quota_day() -> String {
+    Utc::now().format("%Y-%m-%d").to_string()
+}
+
+/// Seconds until the daily query quota resets (UTC midnight).
+fn secs_until_next_day() -> u64 {
+    let now = Utc::now();
+    now.date_naive()
+        .checked_add_days(Days::new(1))
+        .and_then(|day| day.and_hms_opt(0, 0, 0))
+        .map(|midnight| (midnight.and_utc() - now).num_seconds().max(1) as u64)
+        .unwrap_or(1)
+}
+
+/// Count a query against `key_id`'s daily quota, or reject it (429 with `Retry-After`) if the quota
+/// is used up. Returns the day it was counted on, to refund it if the query isn't created; `None`
+/// without a quota.
+pub async fn take_query(state: &AppState, key_id: &str) -> Result<Option<String>, ApiError> {
+    let Some(max) = quotas().max_queries_per_day else {
+        return Ok(None);
+    };
+    let day = quota_day();
+    if !state.store.take_daily_query(key_id, &day, max).await? {
+        return Err(ApiError::RateLimited {
+            message: format!("daily query quota exhausted ({max} queries per day)"),
+            retry_after_secs: secs_until_next_day(),
+        });
+    }
+    Ok(Some(day))
+}
+
+/// Give back a query counted by `take_query` that wasn't created.
+pub async fn refund_query(state: &AppState, key_id: &str, day: &str) -> Result<(), ApiError> {
+    state.store.refund_daily_query(key_id, day).await
+}
@@ -0,0 +1,142 @@
+//! Per-key request rate limits.
+//!
+//! Every API key has token buckets, kept in memory by each instance:
+//! - each authenticated request takes a token from the key's request bucket, refilled at
+//!   `RATE_LIMIT_PER_SEC` tokens per second up to `RATE_LIMIT_BURST` (default: one second's worth);
+//! - requests that start proving (creating or importing a dataset, committing an upload, opening a
+//!   stream, curve and circuit migrations) also take one from its proving bucket, refilled at
+//!   `RATE_LIMIT_PROVING_PER_MIN` per minute up to `RATE_LIMIT_PROVING_BURST` (default: one
+//!   minute's worth).
+//!
+//! An unset rate is unlimited. A request finding its bucket empty is answered `429` with
+//! `Retry-After`, the seconds until the bucket holds a token again. Requests without a key (public
+//! routes, share links) aren't limited here. The daily query quota (`QUOTA_MAX_QUERIES_PER_DAY`,
+//! see `quota`) is taken by `query_quota` on `POST /api/v1/queries`.
+
+use crate::auth::Caller;
+use crate::errors::ApiError;
+use crate::quota;
+use crate::state::AppState;
+use axum::{
+    extract::{Request, State},
+    middleware::Next,
+    response::Response,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Instant;
+
+/// A token bucket's refill rate and capacity.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct Rate {
+    pub tokens_per_sec: f64,
+    pub burst: f64,
+}
+
+/// The deployment's rate limits; `None` is unlimited.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct RateLimits {
+    pub requests: Option<Rate>,
+    pub proving: Option<Rate>,
+}
+
+fn env_f64(name: &str) -> Option<f64> {
+    std::env::var(name).ok().and_then(|v| v.trim().parse().ok()).filter(|v: &f64| *v > 0.0)
+}
+
+/// A rate of `per_period` tokens per `period_secs`, bursting to `burst_var` (default `per_period`).
+fn rate(per_period_var: &str, period_secs: f64, burst_var: &str) -> Option<Rate> {
+    let per_period = env_f64(per_period_var)?;
+    Some(Rate {
+        tokens_per_sec: per_period / period_secs,
+        burst: env_f64(burst_var).unwrap_or(per_period).max(1.0),
+    })
+}
+
+pub fn limits() -> RateLimits {
+    RateLimits {
+        requests: rate("RATE_LIMIT_PER_SEC", 1.0, "RATE_LIMIT_BURST"),
+        proving: rate("RATE_LIMIT_PROVING_PER_MIN", 60.0, "RATE_LIMIT_PROVING_BURST"),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Class {
+    Requests,
+    Proving,
+}
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+/// Token buckets per API key and class.
+#[derive(Default)]
+pub struct RateLimiter {
+    buckets: Mutex<HashMap<(Class, String), Bucket>>,
+}
+
+impl RateLimiter {
+    /// Take a token from `key_id`'s `class` bucket, or `429` with the wait until one is available.
+    fn take(&self, class: Class, key_id: &str, rate: Rate) -> Result<(), ApiError> {
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().map_err(|_| ApiError::Internal)?;
+        let bucket = buckets.entry((class, key_id.to_string())).or_insert(Bucket {
+            tokens: rate.burst,
+            updated: now,
+        });
+        bucket.tokens = (bucket.tokens + now.duration_since(bucket.updated).as_secs_f64() * rate.tokens_per_sec).min(rate.burst);
+        bucket.updated = now;
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            return Ok(());
+        }
+
+        let wait_secs = ((1.0 - bucket.tokens) / rate.tokens_per_sec).ceil().max(1.0) as u64;
+        tracing::warn!(key_id, class = ?class, wait_secs, "rate limited");
+        Err(ApiError::RateLimited {
+            message: match class {
+                Class::Requests => "request rate limit exceeded".to_string(),
+                Class::Proving => "proving rate limit exceeded".to_string(),
+            },
+            retry_after_secs: wait_secs,
+        })
+    }
+}
+
+async fn limit(class: Class, rate: Option<Rate>, state: &AppState, request: Request, next: Next) -> Result<Response, ApiError> {
+    if let Some(rate) = rate
+        && let Some(caller) = request.extensions().get::<Caller>()
+    {
+        state.rate_limiter.take(class, &caller.key_id, rate)?;
+    }
+    Ok(next.run(request).await)
+}
+
+/// Limit the request rate of each key (after authentication).
+pub async fn middleware(State(state): State<AppState>, request: Request, next: Next) -> Result<Response, ApiError> {
+    limit(Class::Requests, limits().requests, &state, request, next).await
+}
+
+/// Also limit the rate at which each key starts proving.
+pub async fn proving_middleware(State(state): State<AppState>, request: Request, next: Next) -> Result<Response, ApiError> {
+    limit(Class::Proving, limits().proving, &state, request, next).await
+}
+
+/// Count the query against its key's daily quota, giving it back if the query isn't created.
+pub async fn query_quota(State(state): State<AppState>, request: Request, next: Next) -> Result<Response, ApiError> {
+    let Some(key_id) = request.extensions().get::<Caller>().map(|c| c.key_id.clone()) else {
+        return Ok(next.run(request).await);
+    };
+    let day = quota::take_query(&state, &key_id).await?;
+    let response = next.run(request).await;
+    if let Some(day) = day
+        && !response.status().is_success()
+        && let Err(e) = quota::refund_query(&state, &key_id, &day).await
+    {
+        tracing::error!(key_id, error = %e, "failed to refund a refused query to the daily quota");
+    }
+    Ok(response)
+}
@@ -0,0 +1,94 @@
+//! Signed query receipts.
+//!
+//! Every released `QueryResponse` carries a `receipt`: a detached Ed25519 signature with the
+//! instance's signing key (`GET /api/v1/keys/signing`) over the answer, so a researcher can later
+//! prove what result the server returned. Receipts are re-issued (with a new `signed_at`) each
+//! time a stored answer is read back.
+//!
+//! The signed message is `RECEIPT_DOMAIN` followed by the canonical JSON of
+//! `{"response": <the response without its receipt>, "signed_at": <receipt.signed_at>}`: compact,
+//! object keys sorted, numbers as `serde_json` prints them. The domain prefix keeps a receipt from
+//! being passed off as any other signature made with the same key (exports, archives, bucket
+//! counts). `POST /api/v1/receipts/verify` checks one.
+
+use crate::errors::ApiError;
+use crate::models::{QueryReceipt, QueryResponse, ReceiptVerifyRequest, ReceiptVerifyResponse, SigningKeyResponse};
+use crate::state::AppState;
+use chrono::{DateTime, Utc};
+use ring::signature::{Ed25519KeyPair, KeyPair, UnparsedPublicKey, ED25519};
+use serde_json::{Map, Value};
+
+/// Prefix of every signed receipt message.
+pub const RECEIPT_DOMAIN: &[u8] = b"phl-query-receipt-v1\n";
+
+/// `value` with every object's keys in sorted order, whatever map the JSON was parsed into.
+fn canonical(value: &Value) -> Value {
+    match value {
+        Value::Object(fields) => {
+            let mut keys: Vec<&String> = fields.keys().collect();
+            keys.sort();
+            Value::Object(keys.into_iter().map(|k| (k.clone(), canonical(&fields[k]))).collect::<Map<_, _>>())
+        }
+        Value::Array(items) => Value::Array(items.iter().map(canonical).collect()),
+        other => other.clone(),
+    }
+}
+
+/// The message a receipt signs for `response` (as JSON, its `receipt` ignored).
+fn message(response: &Value, signed_at: DateTime<Utc>) -> Result<Vec<u8>, ApiError> {
+    let mut response = response.clone();
+    if let Some(fields) = response.as_object_mut() {
+        fields.remove("receipt");
+    }
+    let signed = serde_json::json!({ "response": response, "signed_at": signed_at });
+    let mut message = RECEIPT_DOMAIN.to_vec();
+    serde_json::to_writer(&mut message, &canonical(&signed)).map_err(|_| ApiError::Internal)?;
+    Ok(message)
+}
+
+/// Sign `response`, replacing any receipt it had.
+pub fn sign(key: &Ed25519KeyPair, response: &mut QueryResponse) -> Result<(), ApiError> {
+    response.receipt = None;
+    let signed_at = Utc::now();
+    let message = message(&serde_json::to_value(&*response).map_err(|_| ApiError::Internal)?, signed_at)?;
+    response.receipt = Some(QueryReceipt {
+        alg: "ed25519".to_string(),
+        public_key_hex: hex::encode(key.public_key().as_ref()),
+        signed_at,
+        signature_hex: hex::encode(key.sign(&message).as_ref()),
+    });
+    Ok(())
+}
+
+pub fn signing_key(state: &AppState) -> SigningKeyResponse {
+    SigningKeyResponse {
+        alg: "ed25519".to_string(),
+        public_key_hex: hex::encode(state.signing_key.public_key().as_ref()),
+        receipt_domain: String::from_utf8_lossy(RECEIPT_DOMAIN).into_owned(),
+    }
+}
+
+/// Check a receipt over a response, as returned: `req.receipt`, or the response's own.
+pub fn verify(state: &AppState, req: &ReceiptVerifyRequest) -> Result<ReceiptVerifyResponse, ApiError> {
+    let receipt = match &req.receipt {
+        Some(receipt) => receipt.clone(),
+        None => serde_json::from_value(req.response.get("receipt").cloned().unwrap_or(Value::Null))
+            .map_err(|_| ApiError::BadRequest("the response has no receipt; pass one as `receipt`".to_string()))?,
+    };
+    if receipt.alg != "ed25519" {
+        return Err(ApiError::BadRequest(format!("unsupported signature alg '{}'", receipt.alg)));
+    }
+    let public_key = hex::decode(&receipt.public_key_hex).map_err(|_| ApiError::BadRequest("invalid public_key_hex".to_string()))?;
+    let signature = hex::decode(&receipt.signature_hex).map_err(|_| ApiError::BadRequest("invalid signature_hex".to_string()))?;
+
+    let valid = UnparsedPublicKey::new(&ED25519, &public_key)
+        .verify(&message(&req.response, receipt.signed_at)?, &signature)
+        .is_ok();
+    Ok(ReceiptVerifyResponse {
+        valid,
+        signed_by_this_instance: public_key.as_slice() == state.signing_key.public_key().as_ref(),
+        public_key_hex: receipt.public_key_hex.to_ascii_lowercase(),
+        signed_at: receipt.signed_at,
+        query_id: req.response.get("query_id").and_then(|id| serde_json::from_value(id.clone()).ok()),
+    })
+}
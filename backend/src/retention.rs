@@ -0,0 +1,151 @@
+//! Dataset deletion and retention.
+//!
+//! Deleting a dataset (`DELETE /api/v1/datasets/:id`, or the retention sweep) removes its shards,
+//! the proof blobs no other dataset shares, its queries and the rest of its ledger rows, plus what
+//! this instance keeps locally about it (aggregate proof, curve migrations, finished jobs, proving
+//! checkpoint). Its audit entries stay, so the hash chain remains verifiable, and a
+//! `dataset_deleted` entry is appended. Ready datasets are archived first (see `archive`), and the
+//! archive's SHA-256 goes in that entry. A tombstone keeps the id: requests for it answer `410 Gone`
+//! instead of `404`, and it is never mirrored from an upstream again.
+//!
+//! Retention is configured via environment:
+//! - `DATASET_RETENTION_SECS`: ready or failed datasets created longer ago than this are deleted
+//!   (unset or `0` keeps them forever). Frozen datasets are exempt.
+//! - `RETENTION_SWEEP_INTERVAL_SECS`: how often the sweep runs (default 3600).
+
+use crate::archive;
+use crate::auth::{Caller, Role};
+use crate::checkpoint;
+use crate::errors::ApiError;
+use crate::models::DatasetDeleteResponse;
+use crate::state::AppState;
+use chrono::Utc;
+use std::time::Duration;
+use uuid::Uuid;
+
+const DEFAULT_SWEEP_INTERVAL_SECS: u64 = 3600;
+
+/// `deleted_by` of datasets removed by the sweep.
+const RETENTION_DELETER: &str = "retention";
+
+fn retention_ttl() -> Option<Duration> {
+    std::env::var("DATASET_RETENTION_SECS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .filter(|secs| *secs > 0)
+        .map(Duration::from_secs)
+}
+
+fn sweep_interval() -> Duration {
+    let secs = std::env::var("RETENTION_SWEEP_INTERVAL_SECS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .filter(|secs| *secs > 0)
+        .unwrap_or(DEFAULT_SWEEP_INTERVAL_SECS);
+    Duration::from_secs(secs)
+}
+
+/// The error for a dataset that isn't in the ledger: `410` if it was deleted, `404` otherwise.
+pub async fn missing_dataset(state: &AppState, dataset_id: Uuid) -> ApiError {
+    match state.store.dataset_tombstone(dataset_id).await {
+        Ok(Some(tombstone)) => ApiError::Gone(format!(
+            "dataset was deleted at {} ({})",
+            tombstone.deleted_at.to_rfc3339(),
+            tombstone.reason
+        )),
+        Ok(None) => ApiError::NotFound("dataset not found".to_string()),
+        Err(e) => e,
+    }
+}
+
+async fn delete(state: &AppState, dataset_id: Uuid, deleted_by: &str, reason: &str) -> Result<DatasetDeleteResponse, ApiError> {
+    let ready = state.store.get_dataset(dataset_id).await?.is_some_and(|d| d.status == "ready");
+    let archive_sha256 = if ready && archive::archive_on_delete() {
+        Some(archive::write(state, dataset_id, deleted_by, reason).await?)
+    } else {
+        None
+    };
+
+    let Some(tombstone) = state.store.delete_dataset(dataset_id, deleted_by, reason).await? else {
+        return Err(missing_dataset(state, dataset_id).await);
+    };
+    state.store.delete_jobs(dataset_id).await?;
+    checkpoint::remove(&state.data_dir, dataset_id);
+
+    let audit_entry_hash = state.store.append_audit(
+        Some(dataset_id),
+        "dataset_deleted",
+        &serde_json::json!({
+            "dataset_commitment_hex": tombstone.dataset_commitment_hex,
+            "deleted_by": tombstone.deleted_by,
+            "reason": tombstone.reason,
+            "archive_sha256": archive_sha256,
+        }),
+    )
+    .await?;
+    tracing::info!(%dataset_id, deleted_by, reason, "dataset deleted");
+
+    Ok(DatasetDeleteResponse {
+        dataset_id,
+        deleted_at: tombstone.deleted_at,
+        dataset_commitment_hex: tombstone.dataset_commitment_hex,
+        archive_sha256,
+        audit_entry_hash,
+    })
+}
+
+/// Delete a dataset on behalf of its owner or an admin.
+///
+/// Frozen datasets, datasets still being proven or streamed into, and datasets with queued or
+/// running jobs are refused (`409`).
+pub async fn delete_dataset(state: &AppState, caller: &Caller, dataset_id: Uuid) -> Result<DatasetDeleteResponse, ApiError> {
+    let Some(dataset) = state.store.get_dataset(dataset_id).await? else {
+        return Err(missing_dataset(state, dataset_id).await);
+    };
+    let owner = state.store.dataset_owner(dataset_id).await?;
+    if caller.role != Role::Admin && owner.as_deref() != Some(caller.key_id.as_str()) {
+        return Err(ApiError::Forbidden("only the dataset's owner or an admin can delete it".to_string()));
+    }
+    if dataset.frozen_at.is_some() {
+        return Err(ApiError::Conflict("dataset is frozen; unfreeze it first".to_string()));
+    }
+    if dataset.status == "generating" || state.streams.lock().await.contains_key(&dataset_id) {
+        return Err(ApiError::Conflict("dataset is still being proven; wait for it or close its stream".to_string()));
+    }
+    if state.store.active_jobs(dataset_id).await? > 0 {
+        return Err(ApiError::Conflict("dataset has queued or running jobs".to_string()));
+    }
+
+    delete(state, dataset_id, &caller.key_id, "deleted on request").await
+}
+
+/// Delete every dataset past the retention TTL. Returns how many were deleted.
+pub async fn sweep(state: &AppState, ttl: Duration) -> Result<u64, ApiError> {
+    let cutoff = Utc::now() - chrono::Duration::from_std(ttl).unwrap_or(chrono::Duration::MAX);
+    let mut deleted = 0;
+    for dataset_id in state.store.expired_datasets(cutoff).await? {
+        if state.streams.lock().await.contains_key(&dataset_id) || state.store.active_jobs(dataset_id).await? > 0 {
+            continue;
+        }
+        let reason = format!("retention: older than {}s", ttl.as_secs());
+        delete(state, dataset_id, RETENTION_DELETER, &reason).await?;
+        deleted += 1;
+    }
+    Ok(deleted)
+}
+
+/// Background loop: periodically delete datasets past the retention TTL.
+pub async fn run(state: AppState) {
+    let Some(ttl) = retention_ttl() else {
+        return;
+    };
+    let mut interval = tokio::time::interval(sweep_interval());
+    loop {
+        interval.tick().await;
+        match sweep(&state, ttl).await {
+            Ok(0) => {}
+            Ok(deleted) => tracing::info!(deleted, "retention sweep deleted datasets"),
+            Err(e) => tracing::warn!(error = %e, "retention sweep failed"),
+        }
+    }
+}
@@ -0,0 +1,393 @@
+//! Dataset re-verification (`POST /api/v1/verify/dataset/:id`, or `/api/v1/datasets/:id/audit`).
+//!
+//! Re-checks every stored shard proof of a ready dataset against the verifying key it was proven
+//! with (its `vk_version`, or the dataset's key), and that the shard commitments chain to the
+//! dataset commitment, as a background job (`jobs::KIND_VERIFY_DATASET`): datasets of thousands of
+//! shards take minutes. Shards are read and batch-verified a page at a time (a failing batch is
+//! bisected to find its bad proofs), and after every page the job publishes the shards checked and
+//! the failures so far (`progress::VerificationEvent`, streamed by
+//! `GET /api/v1/verify/dataset/:id/events`).
+//!
+//! The final report is kept in the store (`dataset_reverifications`, the latest per dataset) and recorded
+//! in the audit chain (`dataset_reverified`). A failed re-verification does not change the
+//! shards' `verified` flags: it means the stored data or keys need investigating.
+//!
+//! Every shard whose proof verifies, when stored or re-verified, gets a `verified_at` time and the
+//! id of the key it verified against (`verifier_vk_hash`), listed with the shard. Besides full
+//! re-verifications, a background loop (`run_sampling`) re-verifies `SHARD_SAMPLE_SIZE` (default
+//! 16) shards of ready datasets picked at random every `SHARD_SAMPLE_INTERVAL_SECS` (default 3600,
+//! `0` disables it), so long-lived data keeps fresh evidence without re-proving whole datasets. A
+//! sampled shard that fails is logged and recorded in the audit chain (`shard_sample_failed`), and
+//! its `verified_at` stays at its last pass.
+
+use crate::chain::DatasetChain;
+use crate::dataset::parse_field_hex;
+use crate::db::{self, DatasetReverificationRow};
+use crate::errors::ApiError;
+use crate::export;
+use crate::jobs;
+use crate::models::{DatasetReverificationPending, DatasetReverificationReport};
+use crate::acl;
+use crate::auth::Caller;
+use crate::progress::{self, VerificationEvent};
+use crate::state::AppState;
+use ark_bn254::{Bn254, Fr};
+use ark_groth16::VerifyingKey;
+use axum::response::sse::{Event, Sse};
+use base64::Engine;
+use chrono::Utc;
+use futures_util::Stream;
+use sha2::{Digest, Sha256};
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+use zk_proofs::groth16::{
+    deserialize_proof, deserialize_vk, invalid_shard_proofs, verify_shard_proof, verify_shard_proofs_batch, ShardProofInstance,
+};
+
+/// Shards read and batch-verified per page.
+const PAGE: u64 = 256;
+
+const DEFAULT_SAMPLE_INTERVAL_SECS: u64 = 3600;
+const DEFAULT_SAMPLE_SIZE: u64 = 16;
+
+/// A page's shards proven with one key: the key, their indices and proofs.
+type ShardGroup = (Arc<VerifyingKey<Bn254>>, Vec<u64>, Vec<ShardProofInstance>);
+
+/// A stored report as the API returns it.
+pub fn to_report(dataset_id: Uuid, dataset: &db::DatasetRow, row: DatasetReverificationRow) -> DatasetReverificationReport {
+    let passed = row.failed_shards.is_empty() && row.commitment_matches && row.shards_checked == row.shards_total;
+    DatasetReverificationReport {
+        dataset_id,
+        finished_at: row.finished_at,
+        status: if passed { "passed" } else { "failed" }.to_string(),
+        current: dataset.commitment_hex.as_ref() == Some(&row.dataset_commitment_hex),
+        dataset_commitment_hex: row.dataset_commitment_hex,
+        shards_total: row.shards_total,
+        shards_checked: row.shards_checked,
+        failed_shards: row.failed_shards,
+        commitment_matches: row.commitment_matches,
+        duration_ms: row.duration_ms,
+    }
+}
+
+/// The `202` response while a re-verification is `status`.
+pub fn pending(dataset_id: Uuid, status: &str, error: Option<String>) -> DatasetReverificationPending {
+    DatasetReverificationPending {
+        dataset_id,
+        status: status.to_string(),
+        events_endpoint: format!("/api/v1/verify/dataset/{dataset_id}/events"),
+        report_endpoint: format!("/api/v1/verify/dataset/{dataset_id}"),
+        error,
+    }
+}
+
+/// Status of the latest re-verification job of `dataset_id` (`queued` or `running` while there
+/// is one), with its error if it failed.
+pub async fn job_status(state: &AppState, dataset_id: Uuid) -> Result<Option<(String, Option<String>)>, ApiError> {
+    state.store.latest_job(jobs::KIND_VERIFY_DATASET, dataset_id).await
+}
+
+/// The state to open an events stream with: the running job, or the stored report's outcome.
+async fn current_event(state: &AppState, dataset_id: Uuid, dataset: &db::DatasetRow) -> Result<VerificationEvent, ApiError> {
+    let shards_total = dataset.shards_total();
+    let job = job_status(state, dataset_id).await?;
+    let report = state.store.get_dataset_reverification(dataset_id).await?.map(|row| to_report(dataset_id, dataset, row));
+    Ok(match (job, report) {
+        (Some((status, _)), _) if status == "queued" || status == "running" => VerificationEvent {
+            dataset_id,
+            status,
+            shards_checked: 0,
+            shards_total,
+            failures: 0,
+        },
+        (Some((status, Some(_))), _) if status == "failed" => VerificationEvent {
+            dataset_id,
+            status: "error".to_string(),
+            shards_checked: 0,
+            shards_total,
+            failures: 0,
+        },
+        (_, Some(report)) => VerificationEvent {
+            dataset_id,
+            status: report.status,
+            shards_checked: report.shards_checked,
+            shards_total: report.shards_total,
+            failures: report.failed_shards.len() as u64,
+        },
+        (_, None) => return Err(ApiError::NotFound("dataset has not been re-verified".to_string())),
+    })
+}
+
+/// The SSE stream of a dataset's re-verification, opening with its current state.
+pub async fn events(state: &AppState, caller: &Caller, dataset_id: Uuid) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>> + use<>>, ApiError> {
+    let Some(dataset) = state.store.get_dataset(dataset_id).await? else {
+        return Err(ApiError::NotFound("dataset not found".to_string()));
+    };
+    acl::check_access(state, Some(caller), dataset_id).await?;
+    // Subscribe before reading the current state so no event falls between the two.
+    let receiver = state.progress.subscribe_verifications();
+    let current = current_event(state, dataset_id, &dataset).await?;
+    Ok(progress::verification_events(receiver, dataset_id, current))
+}
+
+/// The dataset's verifying key and its id.
+async fn dataset_vk(state: &AppState, dataset_id: Uuid, dataset: &db::DatasetRow) -> Result<(Arc<VerifyingKey<Bn254>>, String), ApiError> {
+    let vk_b64 = export::dataset_vk_b64(
+        state,
+        dataset_id,
+        dataset.shard_size,
+        dataset.field_set,
+        &dataset.age_buckets,
+        dataset.sha256_commitment,
+    )
+    .await?;
+    let vk_bytes = base64::engine::general_purpose::STANDARD.decode(vk_b64).map_err(|_| ApiError::Internal)?;
+    let vk = Arc::new(deserialize_vk(&vk_bytes).map_err(|_| ApiError::Internal)?);
+    Ok((vk, hex::encode(Sha256::digest(&vk_bytes))))
+}
+
+/// Job body for `jobs::KIND_VERIFY_DATASET`.
+pub async fn run_verify_job(state: &AppState, dataset_id: Uuid) -> Result<(), ApiError> {
+    let result = verify(state, dataset_id).await;
+    if let Err(e) = &result {
+        tracing::warn!(%dataset_id, error = %e, "dataset re-verification failed to run");
+        state.progress.verification(VerificationEvent {
+            dataset_id,
+            status: "error".to_string(),
+            shards_checked: 0,
+            shards_total: 0,
+            failures: 0,
+        });
+    }
+    result
+}
+
+async fn verify(state: &AppState, dataset_id: Uuid) -> Result<(), ApiError> {
+    let started = Instant::now();
+    let Some(dataset) = state.store.get_dataset(dataset_id).await? else {
+        return Err(ApiError::NotFound("dataset not found".to_string()));
+    };
+    let Some(commitment_hex) = dataset.commitment_hex.clone().filter(|_| dataset.status == "ready") else {
+        return Err(ApiError::Conflict("only ready datasets can be re-verified".to_string()));
+    };
+    let shards_total = dataset.shards_total();
+
+    let (vk, vk_id) = dataset_vk(state, dataset_id, &dataset).await?;
+
+    let event = |shards_checked: u64, failures: u64, status: &str| VerificationEvent {
+        dataset_id,
+        status: status.to_string(),
+        shards_checked,
+        shards_total,
+        failures,
+    };
+    state.progress.verification(event(0, 0, "running"));
+
+    let mut chain = DatasetChain::<Fr>::new(dataset.chain_hash);
+    let mut failed_shards = Vec::new();
+    let mut shards_checked = 0;
+    loop {
+        let page = state.store.list_shards(dataset_id, 0..shards_total, shards_checked, PAGE, true).await?;
+        if page.is_empty() {
+            break;
+        }
+        // Shards are checked against the key generation they were proven with (`vk_version`);
+        // shards stored before versions were recorded use the dataset's key.
+        let page_range = page[0].0..page[page.len() - 1].0 + 1;
+        let versions: HashMap<u64, String> = state.store.shard_vk_versions(dataset_id, page_range).await?.into_iter().collect();
+        let mut groups: HashMap<String, ShardGroup> = HashMap::new();
+        for (shard_index, commitment_hex, stats, _, proof_b64) in page {
+            let commitment = parse_field_hex(&commitment_hex).ok_or(ApiError::Internal)?;
+            chain.absorb(&commitment)?;
+            // A proof that doesn't even decode is as bad as one that doesn't verify.
+            let proof = base64::engine::general_purpose::STANDARD
+                .decode(proof_b64.unwrap_or_default())
+                .ok()
+                .and_then(|bytes| deserialize_proof(&bytes).ok());
+            match proof {
+                Some(proof) => {
+                    let key_id = versions.get(&shard_index).unwrap_or(&vk_id);
+                    let group = match groups.entry(key_id.clone()) {
+                        Entry::Occupied(group) => group.into_mut(),
+                        Entry::Vacant(group) => {
+                            let group_vk = if *key_id == vk_id {
+                                vk.clone()
+                            } else {
+                                state.shard_vk(key_id)?.ok_or_else(|| {
+                                    ApiError::Conflict(format!("shard {shard_index} was proven with key {key_id}, which is no longer available"))
+                                })?
+                            };
+                            group.insert((group_vk, Vec::new(), Vec::new()))
+                        }
+                    };
+                    group.1.push(shard_index);
+                    group.2.push(ShardProofInstance { proof, commitment, stats });
+                }
+                None => failed_shards.push(shard_index),
+            }
+            shards_checked += 1;
+        }
+
+        let (invalid, passed) = tokio::task::spawn_blocking(move || {
+            let mut invalid = Vec::new();
+            let mut passed = Vec::new();
+            for (key_id, (group_vk, mut indices, instances)) in groups {
+                if verify_shard_proofs_batch(&group_vk, &instances).is_err() {
+                    let bad: Vec<u64> = invalid_shard_proofs(&group_vk, &instances).into_iter().map(|i| indices[i]).collect();
+                    indices.retain(|shard_index| !bad.contains(shard_index));
+                    invalid.extend(bad);
+                }
+                passed.push((key_id, indices));
+            }
+            (invalid, passed)
+        })
+        .await
+        .map_err(|_| ApiError::Internal)?;
+        failed_shards.extend(invalid);
+        for (key_id, shard_indices) in passed {
+            state.store.set_shards_verified(dataset_id, &shard_indices, &key_id).await?;
+        }
+
+        state.progress.verification(event(shards_checked, failed_shards.len() as u64, "running"));
+    }
+    failed_shards.sort_unstable();
+
+    let commitment_matches = shards_checked == shards_total && chain.finish_hex()? == commitment_hex;
+    let row = DatasetReverificationRow {
+        finished_at: Utc::now(),
+        dataset_commitment_hex: commitment_hex,
+        shards_total,
+        shards_checked,
+        failed_shards,
+        commitment_matches,
+        duration_ms: started.elapsed().as_millis() as u64,
+    };
+    state.store.put_dataset_reverification(dataset_id, &row).await?;
+
+    let report = to_report(dataset_id, &dataset, row);
+    state.store.append_audit(
+        Some(dataset_id),
+        "dataset_reverified",
+        &serde_json::json!({
+            "status": report.status,
+            "dataset_commitment_hex": report.dataset_commitment_hex,
+            "shards_checked": report.shards_checked,
+            "failed_shards": report.failed_shards,
+            "commitment_matches": report.commitment_matches,
+        }),
+    )
+    .await?;
+    state.progress.verification(event(report.shards_checked, report.failed_shards.len() as u64, &report.status));
+
+    tracing::info!(%dataset_id, status = %report.status, shards = report.shards_checked, "dataset re-verified");
+    Ok(())
+}
+
+fn sample_interval() -> Option<Duration> {
+    let secs = std::env::var("SHARD_SAMPLE_INTERVAL_SECS")
+        .ok()
+        .and_then(|v| v.trim().parse().ok())
+        .unwrap_or(DEFAULT_SAMPLE_INTERVAL_SECS);
+    (secs > 0).then(|| Duration::from_secs(secs))
+}
+
+fn sample_size() -> u64 {
+    std::env::var("SHARD_SAMPLE_SIZE")
+        .ok()
+        .and_then(|v| v.trim().parse().ok())
+        .filter(|n| *n > 0)
+        .unwrap_or(DEFAULT_SAMPLE_SIZE)
+}
+
+/// Re-verify one stored shard against the key it was proven with, recording when it passed.
+/// `None` if the shard is gone (its dataset deleted since it was sampled).
+async fn check_shard(state: &AppState, dataset_id: Uuid, shard_index: u64) -> Result<Option<bool>, ApiError> {
+    let Some(dataset) = state.store.get_dataset(dataset_id).await? else {
+        return Ok(None);
+    };
+    let range = shard_index..shard_index + 1;
+    let Some((_, commitment_hex, stats, _, proof_b64)) = state.store.list_shards(dataset_id, range.clone(), 0, 1, true).await?.pop() else {
+        return Ok(None);
+    };
+    let version = state.store.shard_vk_versions(dataset_id, range).await?.pop().map(|(_, version)| version);
+    let (vk, key_id) = match version {
+        Some(version) => match state.shard_vk(&version)? {
+            Some(vk) => (vk, version),
+            None => {
+                let (vk, vk_id) = dataset_vk(state, dataset_id, &dataset).await?;
+                if vk_id != version {
+                    return Err(ApiError::Conflict(format!(
+                        "shard {shard_index} of {dataset_id} was proven with key {version}, which is no longer available"
+                    )));
+                }
+                (vk, vk_id)
+            }
+        },
+        None => dataset_vk(state, dataset_id, &dataset).await?,
+    };
+
+    let commitment = parse_field_hex(&commitment_hex).ok_or(ApiError::Internal)?;
+    let proof = base64::engine::general_purpose::STANDARD
+        .decode(proof_b64.unwrap_or_default())
+        .ok()
+        .and_then(|bytes| deserialize_proof(&bytes).ok());
+    let valid = tokio::task::spawn_blocking(move || {
+        proof.is_some_and(|proof| verify_shard_proof(&vk, &proof, commitment, &stats).is_ok())
+    })
+    .await
+    .map_err(|_| ApiError::Internal)?;
+
+    if valid {
+        state.store.set_shards_verified(dataset_id, &[shard_index], &key_id).await?;
+    } else {
+        tracing::warn!(%dataset_id, shard_index, vk = %key_id, "sampled shard proof no longer verifies");
+        state.store.append_audit(
+            Some(dataset_id),
+            "shard_sample_failed",
+            &serde_json::json!({ "shard_index": shard_index, "vk_version": key_id }),
+        )
+        .await?;
+    }
+    Ok(Some(valid))
+}
+
+/// Re-verify `size` shards of ready datasets picked at random. Returns how many were checked and
+/// how many failed; shards that can't be checked (their key is gone) are logged and skipped.
+pub async fn sample(state: &AppState, size: u64) -> Result<(u64, u64), ApiError> {
+    let (mut checked, mut failed) = (0, 0);
+    for (dataset_id, shard_index) in state.store.sample_shards(size).await? {
+        match check_shard(state, dataset_id, shard_index).await {
+            Ok(Some(true)) => checked += 1,
+            Ok(Some(false)) => {
+                checked += 1;
+                failed += 1;
+            }
+            Ok(None) => {}
+            Err(e) => tracing::warn!(%dataset_id, shard_index, error = %e, "sampled shard could not be re-verified"),
+        }
+    }
+    Ok((checked, failed))
+}
+
+/// Background loop: periodically re-verify a random sample of stored shards.
+pub async fn run_sampling(state: AppState) {
+    let Some(period) = sample_interval() else {
+        return;
+    };
+    let size = sample_size();
+    let mut interval = tokio::time::interval(period);
+    // The first tick is immediate; leave the first sample for a full period after startup.
+    interval.tick().await;
+    loop {
+        interval.tick().await;
+        match sample(&state, size).await {
+            Ok((_, 0)) => {}
+            Ok((checked, failed)) => tracing::warn!(checked, failed, "shard sample re-verification found failing proofs"),
+            Err(e) => tracing::warn!(error = %e, "shard sample re-verification failed"),
+        }
+    }
+}
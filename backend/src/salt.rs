@@ -0,0 +1,100 @@
+//! Sealed storage of shard master salts.
+//!
+//! Shards proven with circuit v4 or later commit to their records salted from a random master salt
+//! per shard (see `zk_proofs::circuit`). The salt is what keeps a small shard's commitment from
+//! being brute-forced, so it is never stored in the clear: each one is sealed with
+//! ChaCha20-Poly1305 under the instance's salt key (`keys/salt_seal.key`, created on first start
+//! and carried by backups with the other key files), bound to its dataset and shard index (and, for
+//! shards re-proven by a curve migration, its curve).
+
+use crate::errors::ApiError;
+use ark_bn254::Fr;
+use ark_serialize::CanonicalSerialize;
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use rand::RngCore;
+use std::path::Path;
+use uuid::Uuid;
+use zeroize::Zeroizing;
+use zk_proofs::types::Curve;
+
+const SEAL_KEY_FILE: &str = "salt_seal.key";
+const NONCE_BYTES: usize = 12;
+
+pub struct SaltSealer {
+    key: Zeroizing<[u8; 32]>,
+}
+
+impl SaltSealer {
+    /// Load the salt key from `keys_dir`, creating it if missing.
+    pub fn load_or_create(keys_dir: &Path) -> Result<Self, ApiError> {
+        std::fs::create_dir_all(keys_dir).map_err(|_| ApiError::Internal)?;
+        let path = keys_dir.join(SEAL_KEY_FILE);
+
+        let mut key = Zeroizing::new([0u8; 32]);
+        if path.exists() {
+            let bytes = Zeroizing::new(std::fs::read(&path).map_err(|_| ApiError::Internal)?);
+            if bytes.len() != key.len() {
+                return Err(ApiError::Internal);
+            }
+            key.copy_from_slice(&bytes);
+        } else {
+            rand::rngs::OsRng.fill_bytes(key.as_mut());
+            write_private(&path, key.as_ref())?;
+        }
+        Ok(Self { key })
+    }
+
+    /// Seal `master_salt` of shard `shard_index` of `dataset_id`: a random nonce followed by the
+    /// ciphertext of its compressed encoding.
+    pub fn seal(&self, dataset_id: Uuid, shard_index: u64, master_salt: Fr) -> Result<Vec<u8>, ApiError> {
+        self.seal_on(Curve::Bn254, dataset_id, shard_index, &master_salt)
+    }
+
+    /// `seal` for the master salt of a shard proven on `curve`.
+    pub fn seal_on(&self, curve: Curve, dataset_id: Uuid, shard_index: u64, master_salt: &impl CanonicalSerialize) -> Result<Vec<u8>, ApiError> {
+        let mut plain = Zeroizing::new(Vec::new());
+        master_salt.serialize_compressed(&mut *plain).map_err(|_| ApiError::Internal)?;
+
+        let mut nonce = [0u8; NONCE_BYTES];
+        rand::rngs::OsRng.fill_bytes(&mut nonce);
+        let aad = associated_data(curve, dataset_id, shard_index);
+        let ciphertext = ChaCha20Poly1305::new(Key::from_slice(self.key.as_ref()))
+            .encrypt(Nonce::from_slice(&nonce), Payload { msg: &plain, aad: &aad })
+            .map_err(|_| ApiError::Internal)?;
+
+        let mut sealed = nonce.to_vec();
+        sealed.extend(ciphertext);
+        Ok(sealed)
+    }
+}
+
+/// Binds a sealed salt to its shard, so it can't be swapped onto another. BN254 salts keep the
+/// original binding; other curves append their name.
+fn associated_data(curve: Curve, dataset_id: Uuid, shard_index: u64) -> Vec<u8> {
+    let mut aad = dataset_id.as_bytes().to_vec();
+    aad.extend_from_slice(&shard_index.to_le_bytes());
+    if curve != Curve::Bn254 {
+        aad.extend_from_slice(curve.name().as_bytes());
+    }
+    aad
+}
+
+/// Create a key file readable only by its owner (fails if it exists).
+#[cfg(unix)]
+pub fn write_private(path: &Path, bytes: &[u8]) -> Result<(), ApiError> {
+    use std::io::Write;
+    use std::os::unix::fs::OpenOptionsExt;
+    let mut file = std::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(path)
+        .map_err(|_| ApiError::Internal)?;
+    file.write_all(bytes).map_err(|_| ApiError::Internal)
+}
+
+#[cfg(not(unix))]
+pub fn write_private(path: &Path, bytes: &[u8]) -> Result<(), ApiError> {
+    std::fs::write(path, bytes).map_err(|_| ApiError::Internal)
+}
@@ -0,0 +1,156 @@
+//! ZK subsystem self-test.
+//!
+//! Proves a fixed, deterministic shard with each key set (shard size, field set and
+//! dual-commitment variant) of the default age bucket layout present on disk and checks that the
+//! proof verifies, that the proven aggregates match host-computed ones, and that tampered
+//! aggregates are rejected; it also checks the canonical record encoding against its golden
+//! vectors. This catches corrupted key files, circuit/key mismatches and encoding changes before
+//! user data is proven. It runs at startup, proving workers wait for it to pass, and admins can
+//! rerun it via `POST /api/v1/admin/zk/self-test`. Key sets not on disk yet are skipped: they are
+//! created by a fresh setup on first use.
+
+use crate::models::{ShardSizeSelfTest, ZkSelfTestReport};
+use crate::state::{key_paths, AppState};
+use chrono::Utc;
+use std::time::Instant;
+use ark_bn254::Fr;
+use zk_proofs::canonical_encoding;
+use zk_proofs::groth16::{salt_commitment, sha256_commitment, verify_shard_proof};
+use zk_proofs::registry::{prove_shard_for, SUPPORTED_SHARD_SIZES};
+use zk_proofs::types::{glucose_range_for, AgeBuckets, FieldSet, Record, ShardStats};
+
+/// The fixed shard: ages sweep every bucket, glucose cycles through [70, 180]; the other
+/// measurements cycle through fixed ranges too (unused by glucose-only keys).
+fn fixed_records(shard_size: usize) -> Vec<Record> {
+    (0..shard_size)
+        .map(|i| Record {
+            age: (i * 7 % 121) as u8,
+            blood_glucose_mg_dl: 70 + (i % 111) as u16,
+            systolic_bp_mm_hg: 95 + (i % 61) as u16,
+            heart_rate_bpm: 50 + (i % 51) as u16,
+            bmi_x10: 180 + (i % 171) as u16,
+        })
+        .collect()
+}
+
+/// Master salt of the fixed shard (for keys of salted circuit revisions).
+fn fixed_master_salt() -> Fr {
+    Fr::from(0x5a17u64)
+}
+
+fn expected_stats(records: &[Record], field_set: FieldSet, buckets: &AgeBuckets) -> ShardStats {
+    let mut stats = ShardStats::zero_for(field_set, buckets);
+    stats.salt_commitment = Some(salt_commitment(fixed_master_salt()));
+    for r in records {
+        let b = buckets.bucket_for_age(r.age);
+        for (f, m) in field_set.measurements().iter().enumerate() {
+            if let Some(sums) = stats.sums_by_bucket_mut(f) {
+                sums[b] += r.value(*m) as u64;
+            }
+        }
+        if let Some(sums_sq) = stats.sum_glucose_sq_by_bucket.as_mut() {
+            sums_sq[b] += (r.blood_glucose_mg_dl as u64).pow(2);
+        }
+        if let Some(histogram) = stats.glucose_histogram_by_bucket.as_mut() {
+            histogram[b][glucose_range_for(r.blood_glucose_mg_dl)] += 1;
+        }
+        stats.count_by_bucket[b] += 1;
+    }
+    stats
+}
+
+async fn test_shard_size(state: &AppState, shard_size: usize, field_set: FieldSet, sha256: bool) -> ShardSizeSelfTest {
+    let started = Instant::now();
+    let buckets = AgeBuckets::default();
+    let result = async {
+        let keys = state
+            .ensure_keys_for(shard_size, field_set, &buckets, sha256)
+            .await
+            .map_err(|e| format!("loading keys: {e}"))?;
+        let _permit = state.proving_admission.acquire(keys.proof_bytes).await;
+
+        tokio::task::spawn_blocking(move || {
+            let records = fixed_records(shard_size);
+            let mut expected = expected_stats(&records, field_set, &buckets);
+            expected.restrict_to(keys.revision);
+            if keys.sha256_commitment {
+                expected.sha256_commitment =
+                    Some(sha256_commitment(&records, field_set, fixed_master_salt()).map_err(|e| format!("hashing: {e}"))?);
+            }
+
+            let mut rng = rand::rngs::OsRng;
+            let (proof, commitment, stats, _) =
+                prove_shard_for(shard_size, field_set, &buckets, &mut rng, keys.pk.as_ref(), records, Some(fixed_master_salt()))
+                    .map_err(|e| format!("proving: {e}"))?;
+
+            if stats.sum_glucose_by_bucket != expected.sum_glucose_by_bucket
+                || stats.count_by_bucket != expected.count_by_bucket
+                || stats.extra_sums_by_bucket != expected.extra_sums_by_bucket
+                || stats.sum_glucose_sq_by_bucket != expected.sum_glucose_sq_by_bucket
+                || stats.glucose_histogram_by_bucket != expected.glucose_histogram_by_bucket
+                || stats.salt_commitment != expected.salt_commitment
+                || stats.sha256_commitment != expected.sha256_commitment
+            {
+                return Err("proven aggregates differ from host-computed aggregates".to_string());
+            }
+            verify_shard_proof(keys.vk.as_ref(), &proof, commitment, &stats)
+                .map_err(|e| format!("proof does not verify with the loaded verifying key: {e}"))?;
+
+            let mut tampered = stats;
+            tampered.sum_glucose_by_bucket[0] += 1;
+            if verify_shard_proof(keys.vk.as_ref(), &proof, commitment, &tampered).is_ok() {
+                return Err("verifying key accepts tampered aggregates".to_string());
+            }
+            Ok(())
+        })
+        .await
+        .map_err(|_| "self-test task panicked".to_string())?
+    }
+    .await;
+
+    ShardSizeSelfTest {
+        shard_size: shard_size as u64,
+        field_set,
+        sha256_commitment: sha256,
+        ok: result.is_ok(),
+        error: result.err(),
+        duration_ms: started.elapsed().as_millis() as u64,
+    }
+}
+
+/// Self-test every key set that exists on disk, and record the report in `state`.
+pub async fn run(state: &AppState) -> ZkSelfTestReport {
+    let keys_dir = state.data_dir.join("keys");
+
+    let mut results = Vec::new();
+    for shard_size in SUPPORTED_SHARD_SIZES {
+        for field_set in FieldSet::ALL {
+            for sha256 in [false, true] {
+                let (pk_path, vk_path) = key_paths(&keys_dir, shard_size, field_set, &AgeBuckets::default(), sha256);
+                if !(pk_path.exists() && vk_path.exists()) {
+                    continue;
+                }
+                let result = test_shard_size(state, shard_size, field_set, sha256).await;
+                if let Some(error) = &result.error {
+                    tracing::error!(shard_size, field_set = field_set.name(), sha256, error, "ZK self-test failed");
+                }
+                results.push(result);
+            }
+        }
+    }
+
+    let encoding_error = canonical_encoding::check_golden_vectors::<Fr>().err();
+    if let Some(error) = &encoding_error {
+        tracing::error!(error, "ZK self-test: canonical encoding does not match its golden vectors");
+    }
+
+    let report = ZkSelfTestReport {
+        ran_at: Utc::now(),
+        ok: encoding_error.is_none() && results.iter().all(|r| r.ok),
+        results,
+        encoding_error,
+    };
+    tracing::info!(ok = report.ok, tested = report.results.len(), "ZK self-test finished");
+    state.set_zk_self_test(report.clone());
+    report
+}
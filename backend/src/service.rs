@@ -0,0 +1,2401 @@
+//! Transport-agnostic service layer.
+//!
+//! Every dataset, query, verification and admin operation of the ledger is a function of
+//! `AppState`, the authenticated `Caller` where it matters, and a typed request from `models`,
+//! returning a typed response or `ApiError`. `api.rs` only maps HTTP (paths, query strings, bodies,
+//! status codes) onto these; other front ends (an admin CLI, a gRPC server, a binary embedding the
+//! ledger) call them directly and get exactly the same validation, policy checks and audit entries.
+
+use crate::acl;
+use crate::api_keys;
+use crate::archive;
+use crate::admission;
+use crate::anchoring;
+use crate::anomaly;
+use crate::aggregate;
+use crate::audit;
+use crate::auth::{Caller, Role, Scope};
+use crate::backup;
+use crate::chain;
+use crate::circuit_migration;
+use crate::cohort;
+use crate::curve_migration;
+use crate::dataset::{self, CsvIngestOptions};
+use crate::deadline::Cancellation;
+use crate::db;
+use crate::dp;
+use crate::errors::ApiError;
+use crate::export;
+use crate::federated;
+use crate::generator;
+use crate::jobs;
+use crate::key_usage;
+use crate::mirror;
+use crate::models::*;
+use crate::notify;
+use crate::pk_download;
+use crate::policy;
+use crate::progress::VerificationEvent;
+use crate::query;
+use crate::quality::{IngestQuality, PLAUSIBLE_GLUCOSE_MG_DL};
+use crate::quota;
+use crate::receipt;
+use crate::rate_limit;
+use crate::retention;
+use crate::reverify;
+use crate::selftest;
+use crate::share::{self, ShareClaims};
+use crate::state::{archived_vk, key_paths, AppState};
+use crate::stream;
+use crate::summary;
+use crate::transparency;
+use crate::upload::{self, UploadSession};
+use base64::Engine;
+use sha2::{Digest, Sha256};
+use futures_util::stream::{BoxStream, StreamExt};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+use zk_proofs::constants::{
+    circuit_id, AGE_BITS, GLUCOSE_RANGES, MAX_AGE, MAX_BUCKETS, MEASUREMENT_BITS, NUM_GLUCOSE_RANGES,
+    POSEIDON_ALPHA, POSEIDON_CAPACITY, POSEIDON_FULL_ROUNDS, POSEIDON_PARTIAL_ROUNDS, POSEIDON_RATE,
+};
+use zk_proofs::groth16::{
+    invalid_shard_proofs, shard_public_inputs_on, shard_public_inputs_to_field_elems, verify_shard_proofs_batch, vk_revision,
+    vk_sha256_commitment,
+};
+use zk_proofs::registry;
+use zk_proofs::verification::{
+    decode_proof_b64, decode_shard, decode_shard_instance, decode_vk_b64, verify_decoded, verify_encoded_shard, DecodeError,
+    EncodedShard, VerificationOutcome,
+};
+use zk_proofs::types::{AgeBuckets, CircuitRevision, Curve, FieldSet, FrHex, Measurement, ShardStats};
+
+use ark_bls12_381::Bls12_381;
+use ark_bn254::Bn254;
+
+/// Outcome of `create_query`: answered now (per bucket, for `group_by`), or held for approval /
+/// queued as a job.
+pub enum QueryOutcome {
+    Released(Box<QueryResponse>),
+    /// A `group_by` query, answered per bucket.
+    Breakdown(QueryBreakdownResponse),
+    Deferred(QueryPendingResponse),
+}
+
+/// Outcome of `get_aggregate_proof`: the current proof, or the job making it.
+pub enum AggregateProofOutcome {
+    Ready(Box<AggregateProofResponse>),
+    Pending(AggregatePendingResponse),
+}
+
+pub enum DatasetReverificationOutcome {
+    Finished(Box<DatasetReverificationReport>),
+    Pending(DatasetReverificationPending),
+}
+
+/// `[from, to)` with defaults `0` and `shards_total`.
+fn shard_index_range(from: Option<u64>, to: Option<u64>, shards_total: u64) -> Result<std::ops::Range<u64>, ApiError> {
+    let range = from.unwrap_or(0)..to.unwrap_or(shards_total);
+    if range.start > range.end {
+        return Err(ApiError::BadRequest("shard_index_from must not exceed shard_index_to".to_string()));
+    }
+    Ok(range)
+}
+
+/// Resolve a requested shard size (default: `default_shard_size`) against the circuit registry.
+fn checked_shard_size(state: &AppState, requested: Option<u64>) -> Result<usize, ApiError> {
+    let shard_size = requested.map(|s| s as usize).unwrap_or(state.config.default_shard_size);
+    if !registry::is_supported(shard_size) {
+        return Err(ApiError::BadRequest(format!(
+            "shard_size must be one of {:?}",
+            registry::SUPPORTED_SHARD_SIZES
+        )));
+    }
+    Ok(shard_size)
+}
+
+/// Validate requested age bucket bounds; `None` is the default layout.
+fn checked_age_buckets(requested: &Option<Vec<(u8, u8)>>) -> Result<AgeBuckets, ApiError> {
+    match requested {
+        Some(bounds) => AgeBuckets::new(bounds.clone()).map_err(|e| ApiError::BadRequest(format!("buckets: {e}"))),
+        None => Ok(AgeBuckets::default()),
+    }
+}
+
+/// Reject an empty rolling window.
+fn checked_window(requested: Option<u64>) -> Result<Option<u64>, ApiError> {
+    if requested == Some(0) {
+        return Err(ApiError::BadRequest("window_shards must be at least 1".to_string()));
+    }
+    Ok(requested)
+}
+
+/// `offset` and `limit` (default 50, at most 500) of a paged listing.
+fn page(offset: Option<u64>, limit: Option<u64>) -> (u64, u64) {
+    (offset.unwrap_or(0), limit.unwrap_or(50).min(500))
+}
+
+async fn existing_dataset(state: &AppState, id: Uuid) -> Result<db::DatasetRow, ApiError> {
+    match state.store.get_dataset(id).await? {
+        Some(dataset) => Ok(dataset),
+        None => Err(retention::missing_dataset(state, id).await),
+    }
+}
+
+/// Like `existing_dataset`, but datasets mirrored from an upstream are fetched on first access.
+async fn loaded_dataset(state: &AppState, id: Uuid) -> Result<db::DatasetRow, ApiError> {
+    match mirror::load_dataset(state, id).await? {
+        Some(dataset) => Ok(dataset),
+        None => Err(retention::missing_dataset(state, id).await),
+    }
+}
+
+fn shard_list_item(
+    shard_index: u64,
+    shard_commitment_hex: String,
+    stats: ShardStats,
+    verified: bool,
+    live_shards: &std::ops::Range<u64>,
+    proof_b64: Option<String>,
+) -> ShardListItem {
+    ShardListItem {
+        shard_index,
+        shard_commitment_hex,
+        sum_glucose_by_bucket: stats.sum_glucose_by_bucket,
+        count_by_bucket: stats.count_by_bucket,
+        extra_sums_by_bucket: stats.extra_sums_by_bucket,
+        sum_glucose_sq_by_bucket: stats.sum_glucose_sq_by_bucket,
+        glucose_histogram_by_bucket: stats.glucose_histogram_by_bucket,
+        salt_commitment_hex: stats.salt_commitment.as_ref().map(|c| FrHex::from_fr(c).hex),
+        sha256_commitment_hex: stats.sha256_commitment.map(hex::encode),
+        glucose_bounds: stats.glucose_bounds,
+        masked_buckets: Vec::new(),
+        verified,
+        expired: shard_index < live_shards.start,
+        vk_version: None,
+        verified_at: None,
+        verifier_vk_hash: None,
+        proof_b64,
+    }
+}
+
+/// Zero every aggregate of the shard's age buckets with fewer than `min_count` records, and of
+/// those `policy::suppressed_cells` withholds with them.
+fn mask_small_buckets(item: &mut ShardListItem, min_count: Option<u64>) {
+    let suppressed = policy::suppressed_cells(&item.count_by_bucket, min_count);
+    item.masked_buckets = (0..item.count_by_bucket.len()).filter(|b| suppressed[*b]).collect();
+    for &b in &item.masked_buckets {
+        item.count_by_bucket[b] = 0;
+        item.sum_glucose_by_bucket[b] = 0;
+        for sums in &mut item.extra_sums_by_bucket {
+            sums[b] = 0;
+        }
+        if let Some(sums) = &mut item.sum_glucose_sq_by_bucket {
+            sums[b] = 0;
+        }
+        if let Some(histogram) = &mut item.glucose_histogram_by_bucket {
+            histogram[b] = [0; NUM_GLUCOSE_RANGES];
+        }
+    }
+}
+
+// --- Datasets ---
+
+pub async fn create_dataset(state: &AppState, caller: &Caller, req: &DatasetCreateRequest) -> Result<DatasetCreateResponse, ApiError> {
+    caller.require_scope(Scope::DatasetsCreate)?;
+    let dataset_size = req.dataset_size.unwrap_or(1_000_000);
+    let shard_size = checked_shard_size(state, req.shard_size)?;
+
+    if !dataset_size.is_multiple_of(shard_size as u64) {
+        return Err(ApiError::BadRequest(format!(
+            "dataset_size must be a multiple of shard_size ({shard_size})"
+        )));
+    }
+
+    let generator_name = req.generator.as_deref().unwrap_or(generator::DEFAULT_GENERATOR);
+    let generator = generator::resolve(generator_name, req.generator_params.as_ref()).map_err(ApiError::BadRequest)?;
+
+    let age_buckets = checked_age_buckets(&req.buckets)?;
+    let window_shards = checked_window(req.window_shards)?;
+
+    quota::enforce_new_dataset(state, &caller.key_id, dataset_size).await?;
+
+    let dataset_id = Uuid::new_v4();
+    state.store.insert_dataset(
+        &db::NewDataset {
+            dataset_id,
+            dataset_size,
+            shard_size: shard_size as u64,
+            field_set: req.field_set.unwrap_or_default(),
+            chain_hash: req.chain_hash.unwrap_or_else(chain::default_chain_hash),
+            sha256_commitment: req.sha256_commitment.unwrap_or(false),
+            age_buckets: &age_buckets,
+            consent_scope: req.consent_scope.as_deref(),
+            requires_approval: req.requires_approval.unwrap_or(false),
+            release_limit: req.release_limit,
+            generator: Some(generator.name()),
+            generator_params: req.generator_params.as_ref(),
+            ingest_quality: &IngestQuality::synthetic(dataset_size),
+            window_shards,
+            owner: &caller.key_id,
+        },
+    )
+    .await?;
+
+    // Queue background generation.
+    jobs::enqueue(state, jobs::KIND_PROVE_DATASET, dataset_id, &caller.key_id).await?;
+
+    Ok(DatasetCreateResponse { dataset_id })
+}
+
+/// Create a dataset from a CSV of real records held in memory in one piece (the chunked upload
+/// flow is for files too large for one request).
+pub async fn import_csv_dataset(state: &AppState, caller: &Caller, params: &CsvImportParams, csv: &[u8]) -> Result<DatasetCreateResponse, ApiError> {
+    caller.require_scope(Scope::DatasetsCreate)?;
+    let consent_scope: Option<Vec<String>> = params
+        .consent_scope
+        .as_ref()
+        .map(|s| s.split(',').map(|p| p.trim().to_string()).filter(|p| !p.is_empty()).collect());
+    let options = CsvIngestOptions {
+        shard_size: checked_shard_size(state, params.shard_size)?,
+        field_set: params.field_set.unwrap_or_default(),
+        chain_hash: params.chain_hash.unwrap_or_else(chain::default_chain_hash),
+        sha256_commitment: params.sha256_commitment.unwrap_or(false),
+        age_buckets: &AgeBuckets::default(),
+        consent_scope: consent_scope.as_deref(),
+        requires_approval: params.requires_approval.unwrap_or(false),
+        release_limit: params.release_limit,
+    };
+    let dataset_id = dataset::ingest_csv(state, &caller.key_id, csv, &options).await?;
+
+    Ok(DatasetCreateResponse { dataset_id })
+}
+
+pub async fn get_dataset(state: &AppState, id: Uuid) -> Result<DatasetGetResponse, ApiError> {
+    let dataset = loaded_dataset(state, id).await?;
+
+    let status = match dataset.status.as_str() {
+        "generating" => DatasetStatus::Generating,
+        "ready" => DatasetStatus::Ready,
+        "failed" => DatasetStatus::Failed,
+        "cancelled" => DatasetStatus::Cancelled,
+        _ => DatasetStatus::Failed,
+    };
+
+    let shards_total = dataset.shards_total();
+    let shards_expired = dataset.live_shards().start;
+    let shards_done = state.store.count_shards_done(id).await?;
+    let migrated = curve_migration::curve_commitment(state, id).await?;
+    let anomaly_warnings = state.store.get_anomaly_analysis(id)
+        .await?
+        .filter(|a| dataset.commitment_hex.as_ref() == Some(&a.dataset_commitment_hex))
+        .map_or(0, |a| a.warnings_total);
+    let anchor = match &dataset.commitment_hex {
+        Some(commitment_hex) => anchoring::dataset_anchor(state, id, commitment_hex).await?,
+        None => None,
+    };
+
+    Ok(DatasetGetResponse {
+        dataset_id: id,
+        created_at: dataset.created_at,
+        dataset_size: dataset.dataset_size,
+        shard_size: dataset.shard_size,
+        field_set: dataset.field_set,
+        chain_hash: dataset.chain_hash,
+        sha256_commitment: dataset.sha256_commitment,
+        num_buckets: dataset.age_buckets.num_buckets() as u64,
+        age_buckets: dataset.age_buckets,
+        status,
+        shards_total,
+        shards_done,
+        dataset_commitment_hex: dataset.commitment_hex,
+        error: dataset.error,
+        consent_scope: dataset.consent_scope,
+        requires_approval: dataset.requires_approval,
+        release_limit: dataset.release_limit.or_else(policy::default_release_limit),
+        generator: dataset.generator,
+        generator_params: dataset.generator_params,
+        frozen_at: dataset.frozen_at,
+        imported_from: dataset.imported_from,
+        window_shards: dataset.window_shards,
+        shards_expired,
+        default_curve: curve_migration::default_curve(migrated.as_ref()),
+        curve_commitments: migrated.into_iter().collect(),
+        anomaly_warnings,
+        anchor,
+    })
+}
+
+pub async fn get_manifest(state: &AppState, caller: Option<&Caller>, id: Uuid) -> Result<serde_json::Value, ApiError> {
+    loaded_dataset(state, id).await?;
+    acl::check_access(state, caller, id).await?;
+
+    // Written when proving starts (it needs the verifying key id).
+    state.store.get_dataset_manifest(id)
+        .await?
+        .ok_or_else(|| ApiError::Conflict("manifest not yet available".to_string()))
+}
+
+pub async fn get_quality(state: &AppState, caller: Option<&Caller>, id: Uuid) -> Result<DatasetQualityResponse, ApiError> {
+    let dataset = loaded_dataset(state, id).await?;
+    acl::check_access(state, caller, id).await?;
+
+    let quality = state.store.dataset_quality(id, &dataset.age_buckets).await?;
+    let total: u64 = quality.count_by_bucket.iter().sum();
+
+    let suppressed_buckets = policy::suppressed_cells(&quality.count_by_bucket, policy::min_cell_count());
+    let buckets = (0..dataset.age_buckets.num_buckets())
+        .map(|b| {
+            let suppressed = suppressed_buckets[b];
+            let count = if suppressed { 0 } else { quality.count_by_bucket[b] };
+            BucketQuality {
+                bucket_index: b,
+                bucket_range: dataset.age_buckets.bounds()[b],
+                count,
+                share: if total == 0 { 0.0 } else { count as f64 / total as f64 },
+                out_of_range_glucose: if suppressed { 0 } else { quality.out_of_range_glucose_by_bucket[b] },
+                suppressed,
+            }
+        })
+        .collect();
+
+    Ok(DatasetQualityResponse {
+        dataset_id: id,
+        rows_rejected: quality.ingest.as_ref().map(|q| q.rows_rejected()),
+        ingest: quality.ingest,
+        plausible_glucose_mg_dl: PLAUSIBLE_GLUCOSE_MG_DL,
+        shards_total: dataset.shards_total(),
+        shards_done: state.store.count_shards_done(id).await?,
+        shards_reporting: quality.shards_reporting,
+        buckets,
+    })
+}
+
+pub async fn get_anomalies(state: &AppState, caller: Option<&Caller>, id: Uuid) -> Result<DatasetAnomaliesResponse, ApiError> {
+    let dataset = loaded_dataset(state, id).await?;
+    acl::check_access(state, caller, id).await?;
+
+    let analysis = anomaly::analysis(state, id, &dataset).await?;
+    Ok(DatasetAnomaliesResponse {
+        dataset_id: id,
+        analyzed_at: analysis.as_ref().map(|a| a.analyzed_at),
+        dataset_commitment_hex: analysis.as_ref().map(|a| a.dataset_commitment_hex.clone()),
+        shards_analyzed: analysis.as_ref().map_or(0, |a| a.shards_analyzed),
+        warnings_total: analysis.as_ref().map_or(0, |a| a.warnings_total),
+        warnings: analysis.map(|a| a.warnings).unwrap_or_default(),
+    })
+}
+
+pub async fn get_aggregates(
+    state: &AppState,
+    caller: Option<&Caller>,
+    id: Uuid,
+    params: &PageParams,
+) -> Result<DatasetAggregatesResponse, ApiError> {
+    let (offset, limit) = page(params.offset, params.limit);
+
+    let dataset = loaded_dataset(state, id).await?;
+    acl::check_access(state, caller, id).await?;
+    let shards_total = dataset.shards_total();
+    let live_shards = dataset.live_shards();
+
+    let (totals, shards_summed) =
+        state.store.dataset_totals(id, live_shards.clone(), dataset.field_set, &dataset.age_buckets).await?;
+    // This endpoint is public: with k-anonymity on, it releases no more than a query would.
+    let min_count = policy::min_cell_count();
+    let suppressed_buckets = policy::suppressed_cells(&totals.count_by_bucket, min_count);
+    let buckets = (0..dataset.age_buckets.num_buckets())
+        .map(|b| {
+            let suppressed = suppressed_buckets[b];
+            let released = |value: u64| if suppressed { 0 } else { value };
+            BucketAggregate {
+                bucket_index: b,
+                bucket_range: dataset.age_buckets.bounds()[b],
+                sum_glucose: released(totals.sum_glucose_by_bucket[b]),
+                sums: dataset
+                    .field_set
+                    .measurements()
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(f, m)| totals.sums_by_bucket(f).map(|sums| (*m, released(sums[b]))))
+                    .collect(),
+                sum_glucose_sq: totals.sum_glucose_sq_by_bucket.as_ref().map(|sums_sq| released(sums_sq[b])),
+                glucose_histogram: totals
+                    .glucose_histogram_by_bucket
+                    .as_ref()
+                    .map(|histogram| if suppressed { [0; NUM_GLUCOSE_RANGES] } else { histogram[b] }),
+                count: released(totals.count_by_bucket[b]),
+                suppressed,
+            }
+        })
+        .collect();
+
+    let mut shards: Vec<ShardListItem> = state.store.list_shards(id, 0..shards_total, offset, limit, false)
+        .await?
+        .into_iter()
+        .map(|(shard_index, commitment_hex, stats, verified, _)| {
+            shard_list_item(shard_index, commitment_hex, stats, verified, &live_shards, None)
+        })
+        .collect();
+    if min_count.is_some() {
+        shards.iter_mut().for_each(|item| mask_small_buckets(item, min_count));
+    }
+
+    Ok(DatasetAggregatesResponse {
+        dataset_id: id,
+        dataset_commitment_hex: dataset.commitment_hex,
+        shards_total,
+        shards_summed,
+        buckets,
+        offset,
+        limit,
+        shards,
+    })
+}
+
+/// Per-bucket count, means and (for blood glucose) standard deviation and confidence interval.
+pub async fn get_dataset_summary(state: &AppState, caller: Option<&Caller>, id: Uuid) -> Result<DatasetSummaryResponse, ApiError> {
+    let dataset = loaded_dataset(state, id).await?;
+    acl::check_access(state, caller, id).await?;
+    if dataset.status != "ready" {
+        return Err(ApiError::Conflict("dataset not ready".to_string()));
+    }
+    if dataset.requires_approval {
+        return Err(ApiError::Conflict(
+            "the dataset requires approval for every release; query it instead".to_string(),
+        ));
+    }
+    summary::summarize(state, id, &dataset).await
+}
+
+/// The dataset's aggregate proof, or (when there is none for its current commitment) the job
+/// making it, queued on first request.
+pub async fn get_aggregate_proof(
+    state: &AppState,
+    caller: &Caller,
+    id: Uuid,
+    params: &AggregateProofParams,
+) -> Result<AggregateProofOutcome, ApiError> {
+    let dataset = loaded_dataset(state, id).await?;
+    acl::check_access(state, Some(caller), id).await?;
+    aggregate::check_eligible(&dataset)?;
+
+    if let Some(row) = aggregate::current_proof(state, id, &dataset).await? {
+        let shard_path = match params.shard_index {
+            Some(shard_index) => Some(aggregate::shard_path(state, id, &dataset, shard_index).await?),
+            None => None,
+        };
+        return Ok(AggregateProofOutcome::Ready(Box::new(AggregateProofResponse {
+            dataset_id: id,
+            created_at: row.created_at,
+            dataset_commitment_hex: row.dataset_commitment_hex,
+            shard_inputs_root_hex: row.shard_inputs_root_hex,
+            shards_total: row.shards_total,
+            totals: row.totals,
+            proof_b64: row.proof_b64,
+            vk_b64: row.vk_b64,
+            key_id: row.key_id,
+            shard_path,
+        })));
+    }
+
+    let pending = match state.store.latest_job(jobs::KIND_PROVE_AGGREGATE, id).await? {
+        Some((status, _)) if status == "queued" || status == "running" => AggregatePendingResponse {
+            dataset_id: id,
+            status,
+            error: None,
+        },
+        previous => {
+            jobs::enqueue(state, jobs::KIND_PROVE_AGGREGATE, id, &caller.key_id).await?;
+            AggregatePendingResponse {
+                dataset_id: id,
+                status: "queued".to_string(),
+                error: previous.and_then(|(_, error)| error),
+            }
+        }
+    };
+    Ok(AggregateProofOutcome::Pending(pending))
+}
+
+pub async fn list_shards(
+    state: &AppState,
+    caller: Option<&Caller>,
+    share: Option<&ShareClaims>,
+    id: Uuid,
+    params: &ListShardsParams,
+) -> Result<ShardListResponse, ApiError> {
+    let (offset, limit) = page(params.offset, params.limit);
+    let include_proof = params.include_proof.unwrap_or(false);
+    let mask_small_counts = params.mask_small_counts.unwrap_or(false);
+    if mask_small_counts && include_proof {
+        return Err(ApiError::BadRequest("mask_small_counts can't be combined with include_proof".to_string()));
+    }
+
+    let dataset = loaded_dataset(state, id).await?;
+    acl::check_read_access(state, caller, share, id).await?;
+    let shards_total = dataset.shards_total();
+    let live_shards = dataset.live_shards();
+    let index_range = shard_index_range(params.shard_index_from, params.shard_index_to, shards_total)?;
+    let (curve, _) = curve_migration::serving_curve(state, id, params.curve).await?;
+
+    let mut shards: Vec<ShardListItem> = if curve == Curve::Bn254 {
+        let mut shards = state.store.list_shards(id, index_range.clone(), offset, limit, include_proof)
+            .await?
+            .into_iter()
+            .map(|(shard_index, commitment_hex, stats, verified, proof_b64)| {
+                shard_list_item(shard_index, commitment_hex, stats, verified, &live_shards, proof_b64)
+            })
+            .collect::<Vec<_>>();
+        if let (Some(first), Some(last)) = (shards.first(), shards.last()) {
+            let listed = first.shard_index..last.shard_index + 1;
+            let mut versions: HashMap<u64, String> = state.store.shard_vk_versions(id, listed.clone()).await?.into_iter().collect();
+            let mut verifications: HashMap<u64, _> = state
+                .store
+                .shard_verifications(id, listed)
+                .await?
+                .into_iter()
+                .map(|(shard_index, verified_at, vk_hash)| (shard_index, (verified_at, vk_hash)))
+                .collect();
+            for item in &mut shards {
+                item.vk_version = versions.remove(&item.shard_index);
+                if let Some((verified_at, vk_hash)) = verifications.remove(&item.shard_index) {
+                    item.verified_at = Some(verified_at);
+                    item.verifier_vk_hash = Some(vk_hash);
+                }
+            }
+        }
+        shards
+    } else {
+        // Re-proven shards were verified before they were stored.
+        state.store.list_curve_shards(id, curve, index_range.clone(), offset, limit, include_proof)
+            .await?
+            .into_iter()
+            .map(|s| ShardListItem {
+                salt_commitment_hex: Some(s.salt_commitment_hex.clone()),
+                ..shard_list_item(
+                    s.shard_index,
+                    s.shard_commitment_hex,
+                    s.stats,
+                    true,
+                    &live_shards,
+                    include_proof.then_some(s.proof_b64),
+                )
+            })
+            .collect()
+    };
+    if mask_small_counts {
+        let min_count = policy::min_cell_count();
+        shards.iter_mut().for_each(|item| mask_small_buckets(item, min_count));
+    }
+
+    Ok(ShardListResponse {
+        dataset_id: id,
+        offset,
+        limit,
+        shard_index_from: Some(index_range.start),
+        shard_index_to: Some(index_range.end),
+        shards_total,
+        curve,
+        shards,
+    })
+}
+
+/// Shards fetched per store call while exporting.
+const SHARD_EXPORT_BATCH: u64 = 500;
+
+/// A dataset's shards as NDJSON chunks of `ShardExportLine`s, one line per shard.
+pub struct ShardExport {
+    /// Shards in the exported index range (fixed when the export starts).
+    pub shards_total: u64,
+    pub lines: BoxStream<'static, Result<Vec<u8>, ApiError>>,
+}
+
+/// Every shard of a dataset in the requested index range, with proofs (unless excluded) and public
+/// inputs. Shards are read one batch at a time as the client consumes the stream, so a slow reader
+/// holds back the reads rather than the ledger buffering the whole dataset.
+pub async fn export_shards(
+    state: &AppState,
+    caller: Option<&Caller>,
+    share: Option<&ShareClaims>,
+    id: Uuid,
+    params: &ExportShardsParams,
+) -> Result<ShardExport, ApiError> {
+    let include_proof = params.include_proof.unwrap_or(true);
+    let snarkjs = params.format == Some(ZkFormat::Snarkjs);
+    if snarkjs && !include_proof {
+        return Err(ApiError::BadRequest("format=snarkjs exports proofs; drop include_proof=false".to_string()));
+    }
+
+    let dataset = loaded_dataset(state, id).await?;
+    acl::check_read_access(state, caller, share, id).await?;
+    let shards_total = dataset.shards_total();
+    let live_shards = dataset.live_shards();
+    let index_range = shard_index_range(params.shard_index_from, params.shard_index_to, shards_total)?;
+    let end = index_range.end.min(shards_total);
+    let (curve, _) = curve_migration::serving_curve(state, id, params.curve).await?;
+    if snarkjs && curve != Curve::Bn254 {
+        return Err(ApiError::BadRequest(format!("format=snarkjs is only available for bn254 proofs, not {}", curve.name())));
+    }
+
+    let state = state.clone();
+    let lines = futures_util::stream::try_unfold(index_range.start, move |next| {
+        let state = state.clone();
+        let live_shards = live_shards.clone();
+        async move {
+            if next >= end {
+                return Ok(None);
+            }
+            let batch = shard_export_batch(&state, id, curve, next..end, include_proof, snarkjs, &live_shards).await?;
+            let Some(last) = batch.last().map(|l| l.shard.shard_index) else {
+                return Ok(None);
+            };
+            let mut chunk = Vec::new();
+            for line in &batch {
+                serde_json::to_writer(&mut chunk, line).map_err(|_| ApiError::Internal)?;
+                chunk.push(b'\n');
+            }
+            Ok(Some((chunk, last + 1)))
+        }
+    });
+
+    Ok(ShardExport {
+        shards_total: end.saturating_sub(index_range.start),
+        lines: lines.boxed(),
+    })
+}
+
+async fn shard_export_batch(
+    state: &AppState,
+    id: Uuid,
+    curve: Curve,
+    index_range: std::ops::Range<u64>,
+    include_proof: bool,
+    snarkjs: bool,
+    live_shards: &std::ops::Range<u64>,
+) -> Result<Vec<ShardExportLine>, ApiError> {
+    if curve == Curve::Bn254 {
+        state.store.list_shards(id, index_range, 0, SHARD_EXPORT_BATCH, include_proof)
+            .await?
+            .into_iter()
+            .map(|(shard_index, commitment_hex, stats, verified, proof_b64)| {
+                let commitment = dataset::parse_field_hex(&commitment_hex).ok_or(ApiError::Internal)?;
+                let public_inputs = shard_public_inputs_to_field_elems(commitment, &stats);
+                let public_inputs_hex = public_inputs.iter().copied().map(dataset::field_hex).collect::<Result<_, _>>()?;
+                let snarkjs_proof = match proof_b64.as_deref().filter(|_| snarkjs) {
+                    Some(proof_b64) => Some(zk_proofs::groth16::to_snarkjs_proof_json(
+                        &decode_proof_b64::<Bn254>(proof_b64).map_err(|_| ApiError::Internal)?,
+                    )),
+                    None => None,
+                };
+                Ok(ShardExportLine {
+                    shard: shard_list_item(shard_index, commitment_hex, stats, verified, live_shards, proof_b64),
+                    public_inputs_hex,
+                    snarkjs_proof,
+                    snarkjs_public_signals: snarkjs.then(|| zk_proofs::groth16::to_snarkjs_public_signals(&public_inputs)),
+                })
+            })
+            .collect()
+    } else {
+        state.store.list_curve_shards(id, curve, index_range, 0, SHARD_EXPORT_BATCH, include_proof)
+            .await?
+            .into_iter()
+            .map(|s| {
+                let commitment = curve_migration::parse_curve_field_hex(&s.shard_commitment_hex).ok_or(ApiError::Internal)?;
+                let salt = curve_migration::parse_curve_field_hex(&s.salt_commitment_hex).ok_or(ApiError::Internal)?;
+                let public_inputs_hex = shard_public_inputs_on(commitment, &s.stats, Some(salt))
+                    .into_iter()
+                    .map(dataset::field_hex)
+                    .collect::<Result<_, _>>()?;
+                Ok(ShardExportLine {
+                    shard: ShardListItem {
+                        salt_commitment_hex: Some(s.salt_commitment_hex.clone()),
+                        ..shard_list_item(
+                            s.shard_index,
+                            s.shard_commitment_hex,
+                            s.stats,
+                            true,
+                            live_shards,
+                            include_proof.then_some(s.proof_b64),
+                        )
+                    },
+                    public_inputs_hex,
+                    snarkjs_proof: None,
+                    snarkjs_public_signals: None,
+                })
+            })
+            .collect()
+    }
+}
+
+pub async fn list_audit(state: &AppState, id: Uuid, params: &PageParams) -> Result<AuditListResponse, ApiError> {
+    let (offset, limit) = page(params.offset, params.limit);
+    // The audit trail of a deleted dataset stays readable.
+    if state.store.get_dataset(id).await?.is_none() && state.store.dataset_tombstone(id).await?.is_none() {
+        return Err(ApiError::NotFound("dataset not found".to_string()));
+    }
+
+    let entries = state.store.list_audit(id, offset, limit)
+        .await?
+        .into_iter()
+        .map(|r| AuditEntry {
+            seq: r.seq,
+            created_at: r.created_at,
+            event: r.event,
+            details: r.details,
+            prev_hash: r.prev_hash,
+            entry_hash: r.entry_hash,
+        })
+        .collect();
+
+    Ok(AuditListResponse {
+        dataset_id: id,
+        offset,
+        limit,
+        entries,
+    })
+}
+
+/// Shards read per page when building a verification report.
+const REPORT_PAGE: u64 = 1000;
+
+pub async fn get_verification_report(
+    state: &AppState,
+    caller: Option<&Caller>,
+    share: Option<&ShareClaims>,
+    id: Uuid,
+) -> Result<DatasetVerificationReport, ApiError> {
+    let dataset = existing_dataset(state, id).await?;
+    acl::check_read_access(state, caller, share, id).await?;
+    let shards_total = dataset.shards_total();
+
+    let mut verified_bitmap = vec![0u8; shards_total.div_ceil(8) as usize];
+    let mut commitments = Vec::new();
+    let mut offset = 0;
+    loop {
+        let page = state.store.list_shards(id, 0..shards_total, offset, REPORT_PAGE, false).await?;
+        offset += page.len() as u64;
+        for (shard_index, commitment_hex, _, verified, _) in &page {
+            if *verified && let Some(byte) = verified_bitmap.get_mut((*shard_index / 8) as usize) {
+                *byte |= 1 << (shard_index % 8);
+            }
+            commitments.push(dataset::parse_field_hex(commitment_hex).ok_or(ApiError::Internal)?);
+        }
+        if (page.len() as u64) < REPORT_PAGE {
+            break;
+        }
+    }
+    let recomputed_commitment_hex = chain::dataset_commitment_hex(dataset.chain_hash, &commitments)?;
+
+    let vk_key_id = match dataset.status.as_str() {
+        "ready" => {
+            let vk_b64 = export::dataset_vk_b64(
+                state,
+                id,
+                dataset.shard_size,
+                dataset.field_set,
+                &dataset.age_buckets,
+                dataset.sha256_commitment,
+            )
+            .await?;
+            let vk_bytes = base64::engine::general_purpose::STANDARD.decode(vk_b64).map_err(|_| ApiError::Internal)?;
+            Some(hex::encode(Sha256::digest(&vk_bytes)))
+        }
+        _ => None,
+    };
+
+    Ok(DatasetVerificationReport {
+        dataset_id: id,
+        generated_at: chrono::Utc::now(),
+        status: dataset.status.clone(),
+        frozen_at: dataset.frozen_at,
+        chain_hash: dataset.chain_hash,
+        commitment_matches: dataset.commitment_hex.as_deref() == Some(recomputed_commitment_hex.as_str()),
+        dataset_commitment_hex: dataset.commitment_hex,
+        recomputed_commitment_hex,
+        vk_key_id,
+        shards_total,
+        shards_stored: commitments.len() as u64,
+        shards_verified: verified_bitmap.iter().map(|b| b.count_ones() as u64).sum(),
+        verified_bitmap_hex: hex::encode(&verified_bitmap),
+        shard_failures: state.store.list_shard_failures(id).await?.len() as u64,
+        audit_chain_intact: state.store.verify_audit_chain().await?.is_ok(),
+    })
+}
+
+pub async fn create_share_link(
+    state: &AppState,
+    caller: &Caller,
+    id: Uuid,
+    req: &ShareLinkRequest,
+) -> Result<ShareLinkResponse, ApiError> {
+    share::create(state, caller, id, req).await
+}
+
+pub async fn list_shard_failures(state: &AppState, id: Uuid) -> Result<ShardFailuresResponse, ApiError> {
+    existing_dataset(state, id).await?;
+
+    let failures = state.store.list_shard_failures(id)
+        .await?
+        .into_iter()
+        .map(|f| ShardFailure {
+            shard_index: f.shard_index,
+            error_class: f.error_class,
+            attempts: f.attempts,
+            last_error: f.last_error,
+            first_failed_at: f.first_failed_at,
+            last_failed_at: f.last_failed_at,
+        })
+        .collect();
+
+    Ok(ShardFailuresResponse { dataset_id: id, failures })
+}
+
+pub async fn get_disclosure(state: &AppState, id: Uuid) -> Result<DisclosureResponse, ApiError> {
+    let dataset = existing_dataset(state, id).await?;
+
+    let threshold = policy::disclosure_threshold();
+    let cells = state.store.cell_disclosure(id)
+        .await?
+        .into_iter()
+        .map(|c| CellDisclosure {
+            bucket_index: c.bucket_index,
+            bucket_range: dataset.age_buckets.bounds().get(c.bucket_index).copied().unwrap_or((0, 0)),
+            filter: c.filter_key,
+            release_count: c.release_count,
+            first_released_at: c.first_released_at,
+            last_released_at: c.last_released_at,
+            level: policy::disclosure_level(c.release_count, threshold),
+        })
+        .collect();
+
+    Ok(DisclosureResponse {
+        dataset_id: id,
+        threshold,
+        cells,
+    })
+}
+
+pub async fn get_privacy_budget(state: &AppState, id: Uuid) -> Result<PrivacyBudgetResponse, ApiError> {
+    existing_dataset(state, id).await?;
+
+    let spent = state.store.privacy_budget(id).await?;
+    let epsilon_budget = dp::epsilon_budget();
+    Ok(PrivacyBudgetResponse {
+        dataset_id: id,
+        epsilon_budget,
+        epsilon_spent: spent.epsilon_spent,
+        epsilon_remaining: epsilon_budget.map(|b| (b - spent.epsilon_spent).max(0.0)),
+        delta_spent: spent.delta_spent,
+        releases: spent.releases,
+    })
+}
+
+/// Declare a ready dataset's commitment final: no further proving, appends or amendments.
+pub async fn freeze_dataset(state: &AppState, caller: &Caller, id: Uuid) -> Result<DatasetFreezeResponse, ApiError> {
+    caller.require(Role::Admin)?;
+
+    let dataset = existing_dataset(state, id).await?;
+    if !state.store.freeze_dataset(id, &caller.key_id).await? {
+        return Err(ApiError::Conflict(if dataset.frozen_at.is_some() {
+            "dataset already frozen".to_string()
+        } else {
+            "only ready datasets can be frozen".to_string()
+        }));
+    }
+
+    let audit_entry_hash = state.store.append_audit(
+        Some(id),
+        "dataset_frozen",
+        &serde_json::json!({ "dataset_commitment_hex": dataset.commitment_hex, "frozen_by": caller.key_id }),
+    )
+    .await?;
+
+    Ok(DatasetFreezeResponse {
+        dataset_id: id,
+        frozen: true,
+        dataset_commitment_hex: dataset.commitment_hex,
+        audit_entry_hash,
+    })
+}
+
+/// Stop a dataset's generation; its owner or an admin may.
+pub async fn cancel_dataset(state: &AppState, caller: &Caller, id: Uuid) -> Result<DatasetCancelResponse, ApiError> {
+    let dataset = existing_dataset(state, id).await?;
+    let owner = state.store.dataset_owner(id).await?;
+    if caller.role != Role::Admin && owner.as_deref() != Some(caller.key_id.as_str()) {
+        return Err(ApiError::Forbidden("only the dataset's owner or an admin can cancel it".to_string()));
+    }
+    if state.streams.lock().await.contains_key(&id) {
+        return Err(ApiError::Conflict("the dataset is an open stream; close it instead".to_string()));
+    }
+    if !state.store.cancel_dataset(id).await? {
+        return Err(ApiError::Conflict(format!("only generating datasets can be cancelled (it is {})", dataset.status)));
+    }
+    state.cancel_proving_run(id);
+    // An upload still waiting for its proving job won't be proven.
+    state.spools.lock().await.remove(&id);
+
+    let shards_done = state.store.count_shards_done(id).await?;
+    let audit_entry_hash = state.store.append_audit(
+        Some(id),
+        "dataset_cancelled",
+        &serde_json::json!({ "cancelled_by": caller.key_id, "shards_done": shards_done, "shards_total": dataset.shards_total() }),
+    )
+    .await?;
+
+    Ok(DatasetCancelResponse {
+        dataset_id: id,
+        status: DatasetStatus::Cancelled,
+        shards_done,
+        shards_total: dataset.shards_total(),
+        audit_entry_hash,
+    })
+}
+
+pub async fn delete_dataset(state: &AppState, caller: &Caller, id: Uuid) -> Result<DatasetDeleteResponse, ApiError> {
+    retention::delete_dataset(state, caller, id).await
+}
+
+/// The archive of a ready dataset, built now, or the one stored when it was deleted.
+pub async fn get_dataset_archive(state: &AppState, caller: &Caller, cancel: &Cancellation, id: Uuid) -> Result<Vec<u8>, ApiError> {
+    caller.require(Role::Admin)?;
+
+    if state.store.get_dataset(id).await?.is_some() {
+        return archive::build(state, id, &caller.key_id, "archived on request", cancel).await;
+    }
+    match archive::read_stored(state, id) {
+        Some(archive) => Ok(archive),
+        None => Err(retention::missing_dataset(state, id).await),
+    }
+}
+
+// --- API keys ---
+
+pub async fn create_api_key(state: &AppState, caller: &Caller, req: &ApiKeyCreateRequest) -> Result<ApiKeyCreateResponse, ApiError> {
+    api_keys::issue(state, caller, req).await
+}
+
+pub async fn list_api_keys(state: &AppState, caller: &Caller) -> Result<ApiKeyListResponse, ApiError> {
+    api_keys::list(state, caller).await
+}
+
+pub async fn revoke_api_key(state: &AppState, caller: &Caller, key_id: &str) -> Result<ApiKeyRevokeResponse, ApiError> {
+    api_keys::revoke(state, caller, key_id).await
+}
+
+pub async fn get_dataset_acl(state: &AppState, caller: &Caller, id: Uuid) -> Result<DatasetAclResponse, ApiError> {
+    acl::get(state, caller, id).await
+}
+
+pub async fn grant_dataset_access(
+    state: &AppState,
+    caller: &Caller,
+    id: Uuid,
+    req: &DatasetAccessGrant,
+) -> Result<DatasetAclResponse, ApiError> {
+    acl::grant(state, caller, id, req).await
+}
+
+pub async fn revoke_dataset_access(
+    state: &AppState,
+    caller: &Caller,
+    id: Uuid,
+    req: &DatasetAccessGrant,
+) -> Result<DatasetAclResponse, ApiError> {
+    acl::revoke(state, caller, id, req).await
+}
+
+pub async fn unfreeze_dataset(state: &AppState, caller: &Caller, id: Uuid) -> Result<DatasetFreezeResponse, ApiError> {
+    caller.require(Role::Admin)?;
+
+    let dataset = existing_dataset(state, id).await?;
+    if !state.store.unfreeze_dataset(id).await? {
+        return Err(ApiError::Conflict("dataset is not frozen".to_string()));
+    }
+
+    let audit_entry_hash = state.store.append_audit(
+        Some(id),
+        "dataset_unfrozen",
+        &serde_json::json!({ "dataset_commitment_hex": dataset.commitment_hex, "unfrozen_by": caller.key_id }),
+    )
+    .await?;
+
+    Ok(DatasetFreezeResponse {
+        dataset_id: id,
+        frozen: false,
+        dataset_commitment_hex: dataset.commitment_hex,
+        audit_entry_hash,
+    })
+}
+
+pub async fn get_usage(state: &AppState, caller: &Caller) -> Result<UsageResponse, ApiError> {
+    let usage = quota::tenant_usage(state, &caller.key_id).await?;
+    Ok(UsageResponse {
+        key_id: caller.key_id.clone(),
+        datasets: usage.datasets,
+        records: usage.records,
+        proving_running: usage.proving_running,
+        proving_queued: usage.proving_queued,
+        queries_today: usage.queries_today,
+        limits: quota::quotas(),
+        rate_limits: rate_limit::limits(),
+    })
+}
+
+pub fn get_signing_key(state: &AppState) -> SigningKeyResponse {
+    receipt::signing_key(state)
+}
+
+/// Check a query receipt; the signature not verifying is `valid: false`, not an error.
+pub fn verify_receipt(state: &AppState, req: &ReceiptVerifyRequest) -> Result<ReceiptVerifyResponse, ApiError> {
+    receipt::verify(state, req)
+}
+
+pub async fn get_log_tree_head(state: &AppState) -> Result<SignedTreeHead, ApiError> {
+    transparency::tree_head(state).await
+}
+
+pub async fn get_log_proof(state: &AppState, params: &LogProofParams) -> Result<LogProofResponse, ApiError> {
+    transparency::proof(state, params).await
+}
+
+pub fn list_generators() -> GeneratorListResponse {
+    GeneratorListResponse {
+        default: generator::DEFAULT_GENERATOR.to_string(),
+        generators: generator::all()
+            .iter()
+            .map(|g| GeneratorInfo {
+                name: g.name().to_string(),
+                description: g.description().to_string(),
+                params: g.params(),
+            })
+            .collect(),
+    }
+}
+
+// --- Chunked uploads ---
+
+pub async fn init_upload(state: &AppState, caller: &Caller) -> Result<UploadInitResponse, ApiError> {
+    caller.require_scope(Scope::DatasetsCreate)?;
+    let upload_id = upload::open_session(&state.uploads, &caller.key_id).await?;
+
+    Ok(UploadInitResponse {
+        upload_id,
+        max_upload_bytes: upload::max_upload_bytes(),
+    })
+}
+
+pub async fn get_upload(state: &AppState, caller: &Caller, id: Uuid) -> Result<UploadStatusResponse, ApiError> {
+    let uploads = state.uploads.lock().await;
+    let session = uploads
+        .get(&id)
+        .ok_or_else(|| ApiError::NotFound("upload not found".to_string()))?;
+    session.check_owner(&caller.key_id)?;
+
+    Ok(UploadStatusResponse {
+        upload_id: id,
+        created_at: session.created_at,
+        received_chunks: session.received_chunks(),
+        received_bytes: session.received_bytes(),
+    })
+}
+
+pub async fn put_upload_chunk(state: &AppState, caller: &Caller, id: Uuid, req: &UploadChunkRequest) -> Result<UploadStatusResponse, ApiError> {
+    let data = base64::engine::general_purpose::STANDARD
+        .decode(&req.data_b64)
+        .map_err(|_| ApiError::BadRequest("invalid data_b64".to_string()))?;
+
+    let mut uploads = state.uploads.lock().await;
+    let session = uploads
+        .get_mut(&id)
+        .ok_or_else(|| ApiError::NotFound("upload not found".to_string()))?;
+    session.check_owner(&caller.key_id)?;
+    session.put_chunk(req.index, data, &req.sha256_hex)?;
+
+    Ok(UploadStatusResponse {
+        upload_id: id,
+        created_at: session.created_at,
+        received_chunks: session.received_chunks(),
+        received_bytes: session.received_bytes(),
+    })
+}
+
+pub async fn commit_upload(state: &AppState, caller: &Caller, id: Uuid, req: &UploadCommitRequest) -> Result<DatasetCreateResponse, ApiError> {
+    caller.require_scope(Scope::DatasetsCreate)?;
+    // The session is taken out while it is committed, so a concurrent commit of the same upload
+    // finds nothing instead of creating a second dataset. It goes back if the upload turns out
+    // invalid, so a client can fix a bad chunk and retry the commit.
+    let session = {
+        let mut uploads = state.uploads.lock().await;
+        uploads
+            .get(&id)
+            .ok_or_else(|| ApiError::NotFound("upload not found".to_string()))?
+            .check_owner(&caller.key_id)?;
+        uploads.remove(&id).ok_or(ApiError::Internal)?
+    };
+    match commit_session(state, caller, &session, req).await {
+        Ok(dataset_id) => Ok(DatasetCreateResponse { dataset_id }),
+        Err(e) => {
+            state.uploads.lock().await.insert(id, session);
+            Err(e)
+        }
+    }
+}
+
+async fn commit_session(state: &AppState, caller: &Caller, session: &UploadSession, req: &UploadCommitRequest) -> Result<Uuid, ApiError> {
+    let bytes = session.assemble(req.total_chunks)?;
+    if let Some(expected) = req.sha256_hex.as_deref()
+        && upload::sha256_hex_of(&bytes) != expected.to_ascii_lowercase()
+    {
+        return Err(ApiError::BadRequest("upload hash mismatch".to_string()));
+    }
+
+    let options = CsvIngestOptions {
+        shard_size: checked_shard_size(state, req.shard_size)?,
+        field_set: req.field_set.unwrap_or_default(),
+        chain_hash: req.chain_hash.unwrap_or_else(chain::default_chain_hash),
+        sha256_commitment: req.sha256_commitment.unwrap_or(false),
+        age_buckets: &AgeBuckets::default(),
+        consent_scope: req.consent_scope.as_deref(),
+        requires_approval: req.requires_approval.unwrap_or(false),
+        release_limit: req.release_limit,
+    };
+    dataset::ingest_csv(state, &caller.key_id, &bytes, &options).await
+}
+
+// --- Ingestion streams ---
+
+/// Open a stream on a new, empty dataset, or reopen `req.dataset_id` to append to it.
+pub async fn open_stream(state: &AppState, caller: &Caller, req: &StreamOpenRequest) -> Result<StreamStatusResponse, ApiError> {
+    caller.require_scope(Scope::DatasetsCreate)?;
+    if let Some(dataset_id) = req.dataset_id {
+        stream::open(state, &caller.key_id, dataset_id).await?;
+        return get_stream(state, caller, dataset_id).await;
+    }
+
+    let shard_size = checked_shard_size(state, req.shard_size)?;
+    let age_buckets = checked_age_buckets(&req.buckets)?;
+    let window_shards = checked_window(req.window_shards)?;
+    quota::enforce_new_dataset(state, &caller.key_id, 0).await?;
+
+    let dataset_id = Uuid::new_v4();
+    state.store.insert_dataset(
+        &db::NewDataset {
+            dataset_id,
+            dataset_size: 0,
+            shard_size: shard_size as u64,
+            field_set: req.field_set.unwrap_or_default(),
+            chain_hash: req.chain_hash.unwrap_or_else(chain::default_chain_hash),
+            sha256_commitment: req.sha256_commitment.unwrap_or(false),
+            age_buckets: &age_buckets,
+            consent_scope: req.consent_scope.as_deref(),
+            requires_approval: req.requires_approval.unwrap_or(false),
+            release_limit: req.release_limit,
+            generator: None,
+            generator_params: None,
+            ingest_quality: &IngestQuality::default(),
+            window_shards,
+            owner: &caller.key_id,
+        },
+    )
+    .await?;
+
+    if let Err(e) = stream::open(state, &caller.key_id, dataset_id).await {
+        state.store.set_dataset_failed(dataset_id, &e.to_string()).await?;
+        return Err(e);
+    }
+    get_stream(state, caller, dataset_id).await
+}
+
+pub async fn get_stream(state: &AppState, caller: &Caller, id: Uuid) -> Result<StreamStatusResponse, ApiError> {
+    let dataset = existing_dataset(state, id).await?;
+    let streams = state.streams.lock().await;
+    let session = streams
+        .get(&id)
+        .ok_or_else(|| ApiError::NotFound("no open stream for this dataset".to_string()))?;
+    session.check_owner(&caller.key_id)?;
+    Ok(session.status(id, &dataset))
+}
+
+/// Accept batch `params.sequence` of records (CSV, as uploaded) on the stream of dataset `id`.
+pub async fn push_stream_records(
+    state: &AppState,
+    caller: &Caller,
+    id: Uuid,
+    params: &StreamRecordsParams,
+    csv: &[u8],
+) -> Result<StreamStatusResponse, ApiError> {
+    let (field_set, records_unappended) = {
+        let streams = state.streams.lock().await;
+        let session = streams
+            .get(&id)
+            .ok_or_else(|| ApiError::NotFound("no open stream for this dataset".to_string()))?;
+        session.check_owner(&caller.key_id)?;
+        (session.field_set, session.records_unappended())
+    };
+
+    let (records, quality) = dataset::parse_csv_records(csv, field_set)?;
+    // Appended shards already count towards the tenant's usage; records still in the session don't.
+    quota::enforce_more_records(state, &caller.key_id, records_unappended + records.len() as u64).await?;
+
+    let ingest = {
+        let mut streams = state.streams.lock().await;
+        let session = streams
+            .get_mut(&id)
+            .ok_or_else(|| ApiError::NotFound("no open stream for this dataset".to_string()))?;
+        session.accept(params.sequence, &upload::sha256_hex_of(csv), records, &quality)?;
+        session.ingest.clone()
+    };
+    state.store.set_dataset_ingest_quality(id, &ingest).await?;
+
+    get_stream(state, caller, id).await
+}
+
+/// Close the stream of dataset `id`: no more records are taken, the records not filling a shard
+/// are dropped, and shards already full are still appended.
+pub async fn close_stream(state: &AppState, caller: &Caller, id: Uuid) -> Result<StreamStatusResponse, ApiError> {
+    let dataset = existing_dataset(state, id).await?;
+    let status = {
+        let mut streams = state.streams.lock().await;
+        let session = streams
+            .get_mut(&id)
+            .ok_or_else(|| ApiError::NotFound("no open stream for this dataset".to_string()))?;
+        session.check_owner(&caller.key_id)?;
+        let done = session.close();
+        let status = session.status(id, &dataset);
+        if done {
+            streams.remove(&id);
+        }
+        status
+    };
+
+    state.store.append_audit(
+        Some(id),
+        "stream_closed",
+        &serde_json::json!({
+            "closed_by": caller.key_id,
+            "shards_appended": status.shards_appended,
+            "shards_pending": status.shards_pending,
+            "records_discarded": status.records_discarded,
+        }),
+    )
+    .await?;
+
+    Ok(status)
+}
+
+// --- Federated sites ---
+
+/// Register an empty dataset proven off-site by `req.site` with its own verifying key.
+pub async fn create_federated_dataset(
+    state: &AppState,
+    caller: &Caller,
+    req: &FederatedDatasetCreateRequest,
+) -> Result<DatasetGetResponse, ApiError> {
+    caller.require_scope(Scope::DatasetsCreate)?;
+    let site = req.site.trim();
+    if site.is_empty() || site.len() > 64 {
+        return Err(ApiError::BadRequest("site must be 1 to 64 characters".to_string()));
+    }
+    let shard_size = checked_shard_size(state, req.shard_size)?;
+    let age_buckets = checked_age_buckets(&req.buckets)?;
+    let window_shards = checked_window(req.window_shards)?;
+    let field_set = req.field_set.unwrap_or_default();
+    let (vk, key_id) = federated::decode_vk(&req.vk_b64)?;
+    quota::enforce_new_dataset(state, &caller.key_id, 0).await?;
+
+    let dataset_id = Uuid::new_v4();
+    state.store.insert_dataset(
+        &db::NewDataset {
+            dataset_id,
+            dataset_size: 0,
+            shard_size: shard_size as u64,
+            field_set,
+            chain_hash: req.chain_hash.unwrap_or_else(chain::default_chain_hash),
+            sha256_commitment: vk_sha256_commitment(&vk, field_set, age_buckets.num_buckets()),
+            age_buckets: &age_buckets,
+            consent_scope: req.consent_scope.as_deref(),
+            requires_approval: req.requires_approval.unwrap_or(false),
+            release_limit: req.release_limit,
+            generator: None,
+            generator_params: None,
+            ingest_quality: &IngestQuality::external(0),
+            window_shards,
+            owner: &caller.key_id,
+        },
+    )
+    .await?;
+    state.store.set_dataset_federated(dataset_id, &req.vk_b64, &format!("{}{site}", federated::SOURCE_PREFIX)).await?;
+
+    state.store.append_audit(
+        Some(dataset_id),
+        "federated_dataset_registered",
+        &serde_json::json!({ "site": site, "key_id": key_id, "registered_by": caller.key_id }),
+    )
+    .await?;
+
+    get_dataset(state, dataset_id).await
+}
+
+/// Push the next shard of federated dataset `id`, proven off-site.
+pub async fn push_federated_shard(
+    state: &AppState,
+    caller: &Caller,
+    id: Uuid,
+    req: FederatedShardRequest,
+) -> Result<FederatedShardResponse, ApiError> {
+    federated::push_shard(state, &caller.key_id, id, req).await
+}
+
+// --- Queries ---
+
+pub async fn create_query(state: &AppState, caller: &Caller, req: &QueryRequest) -> Result<QueryOutcome, ApiError> {
+    caller.require_scope(Scope::QueriesCreate)?;
+    let field = Measurement::parse(&req.field).ok_or_else(|| {
+        let known: Vec<&str> = Measurement::ALL.iter().map(|m| m.name()).collect();
+        ApiError::BadRequest(format!("unknown field '{}' (known: {known:?})", req.field))
+    })?;
+
+    // Ensure dataset exists and the caller may query it.
+    let dataset = loaded_dataset(state, req.dataset_id).await?;
+    acl::check_access(state, Some(caller), req.dataset_id).await?;
+
+    let (first, last) = match (&req.age_range, req.group_by) {
+        (Some(age_range), _) => buckets_for_age_range(&dataset.age_buckets, age_range).map_err(ApiError::BadRequest)?,
+        (None, Some(GroupBy::AgeBucket)) => (0, dataset.age_buckets.num_buckets() - 1),
+        (None, None) => return Err(ApiError::BadRequest("age_range is required without group_by".to_string())),
+    };
+    let buckets = db::QueryBuckets { first, last, complement: req.complement };
+
+    if dataset.status != "ready" {
+        return Err(ApiError::Conflict("dataset not ready".to_string()));
+    }
+    query::field_index(&dataset, field)?;
+    query::check_metric(&req.metric, field)?;
+    let dp = dp::check_request(req.epsilon, req.dp_mechanism, &req.metric, field).map_err(ApiError::BadRequest)?;
+    if req.complement && dp.is_some() {
+        return Err(ApiError::BadRequest("complement queries can't be differentially private".to_string()));
+    }
+    if req.complement && buckets.indices(dataset.age_buckets.num_buckets()).is_empty() {
+        return Err(ApiError::BadRequest("the age range covers every bucket, which leaves no complement".to_string()));
+    }
+    if req.group_by.is_some() {
+        if req.complement || dp.is_some() || matches!(req.mode, Some(QueryMode::Async)) {
+            return Err(ApiError::BadRequest("group_by can't be combined with complement, epsilon or async mode".to_string()));
+        }
+        // Every bucket is released at once, so there is nothing to hold for an approver.
+        if dataset.requires_approval {
+            return Err(ApiError::Conflict("the dataset requires approval for queries; query its buckets one at a time".to_string()));
+        }
+    }
+
+    policy::check_purpose(req.purpose.as_ref(), policy::purpose_required()).map_err(ApiError::BadRequest)?;
+
+    // Consent policy: every decision is recorded in the audit chain, including denials.
+    let purpose_category = req.purpose.as_ref().map(|p| p.category.as_str());
+    let decision = policy::check_consent(dataset.consent_scope.as_deref(), purpose_category);
+    state.store.append_audit(
+        Some(req.dataset_id),
+        if decision.is_ok() { "query_policy_allowed" } else { "query_policy_denied" },
+        &serde_json::json!({
+            "purpose": req.purpose,
+            "consent_scope": dataset.consent_scope,
+            "reason": decision.as_ref().err(),
+        }),
+    )
+    .await?;
+    decision.map_err(ApiError::Forbidden)?;
+
+    if req.group_by == Some(GroupBy::AgeBucket) {
+        let breakdown = query::breakdown(state, req.dataset_id, &dataset, &req.metric, field, req.purpose.as_ref(), buckets).await?;
+        return Ok(QueryOutcome::Breakdown(breakdown));
+    }
+
+    let query_id = Uuid::new_v4();
+    let spec = db::QuerySpec {
+        metric: &req.metric,
+        purpose: req.purpose.as_ref(),
+        buckets,
+        field,
+        dp,
+        cohort_id: None,
+        requested_by: Some(&caller.key_id),
+    };
+
+    // Sensitive cohorts: nothing is computed until an approver releases the query.
+    if dataset.requires_approval {
+        state.store.insert_unreleased_query(query_id, req.dataset_id, &spec, "pending_approval").await?;
+        state.store.append_audit(
+            Some(req.dataset_id),
+            "query_pending_approval",
+            &serde_json::json!({ "query_id": query_id, "requested_by": caller.key_id }),
+        )
+        .await?;
+        notify::emit("query_pending_approval", req.dataset_id, query_id);
+
+        return Ok(QueryOutcome::Deferred(deferred_response(query_id, req.dataset_id, "pending_approval")));
+    }
+
+    // Async mode: the aggregation runs on the job queue; poll the status endpoint for the result.
+    if matches!(req.mode, Some(QueryMode::Async)) {
+        state.store.insert_unreleased_query(query_id, req.dataset_id, &spec, "queued").await?;
+        jobs::enqueue(state, jobs::KIND_QUERY, query_id, &caller.key_id).await?;
+
+        return Ok(QueryOutcome::Deferred(deferred_response(query_id, req.dataset_id, "queued")));
+    }
+
+    query::enforce_release_limit(state, req.dataset_id, &dataset, buckets, field).await?;
+
+    let answer = query::compute_answer(state, req.dataset_id, &dataset, &spec).await?;
+
+    state.store.insert_query(query_id, req.dataset_id, &spec, &answer).await?;
+    state.store.insert_released_cells(query_id, req.dataset_id, &query::released_cells(buckets.first, &answer)).await?;
+
+    let mut response = query::query_response(query_id, req.dataset_id, &dataset.age_buckets, &req.metric, buckets, field, &answer);
+    receipt::sign(&state.signing_key, &mut response)?;
+    Ok(QueryOutcome::Released(Box::new(response)))
+}
+
+/// Pool one measurement's per-bucket aggregates over several datasets; see `cohort`.
+pub async fn create_cohort_query(state: &AppState, caller: &Caller, req: &CohortQueryRequest) -> Result<CohortQueryResponse, ApiError> {
+    caller.require_scope(Scope::QueriesCreate)?;
+    let field = Measurement::parse(&req.field).ok_or_else(|| {
+        let known: Vec<&str> = Measurement::ALL.iter().map(|m| m.name()).collect();
+        ApiError::BadRequest(format!("unknown field '{}' (known: {known:?})", req.field))
+    })?;
+    let mut ids = req.dataset_ids.clone();
+    ids.sort();
+    ids.dedup();
+    if ids.len() != req.dataset_ids.len() || !(2..=cohort::MAX_COHORT_DATASETS).contains(&ids.len()) {
+        return Err(ApiError::BadRequest(format!(
+            "dataset_ids must list 2 to {} distinct datasets",
+            cohort::MAX_COHORT_DATASETS
+        )));
+    }
+    policy::check_purpose(req.purpose.as_ref(), policy::purpose_required()).map_err(ApiError::BadRequest)?;
+
+    let mut datasets = Vec::with_capacity(req.dataset_ids.len());
+    for id in &req.dataset_ids {
+        let dataset = loaded_dataset(state, *id).await?;
+        acl::check_access(state, Some(caller), *id).await?;
+        if dataset.status != "ready" {
+            return Err(ApiError::Conflict(format!("dataset {id} not ready")));
+        }
+        query::field_index(&dataset, field)?;
+        // Pooled answers are released at once, so there is nothing to hold for an approver.
+        if dataset.requires_approval {
+            return Err(ApiError::Conflict(format!(
+                "dataset {id} requires approval for queries; query it on its own"
+            )));
+        }
+
+        // Consent policy, per dataset, as for single queries.
+        let purpose_category = req.purpose.as_ref().map(|p| p.category.as_str());
+        let decision = policy::check_consent(dataset.consent_scope.as_deref(), purpose_category);
+        state.store.append_audit(
+            Some(*id),
+            if decision.is_ok() { "query_policy_allowed" } else { "query_policy_denied" },
+            &serde_json::json!({
+                "purpose": req.purpose,
+                "consent_scope": dataset.consent_scope,
+                "reason": decision.as_ref().err(),
+            }),
+        )
+        .await?;
+        decision.map_err(ApiError::Forbidden)?;
+
+        datasets.push((*id, dataset));
+    }
+
+    cohort::answer(state, &datasets, field, req.purpose.as_ref()).await
+}
+
+pub async fn approve_query(state: &AppState, caller: &Caller, id: Uuid) -> Result<QueryResponse, ApiError> {
+    caller.require(Role::Approver)?;
+    caller.require_scope(Scope::QueriesApprove)?;
+
+    let query = pending_query(state, id).await?;
+    if query.requested_by.as_deref() == Some(caller.key_id.as_str()) {
+        return Err(ApiError::Forbidden("a query can't be approved by the key that made it".to_string()));
+    }
+    let response = query::release_stored_query(state, id, &query, "pending_approval", Some(&caller.key_id)).await?;
+
+    state.store.append_audit(
+        Some(query.dataset_id),
+        "query_approved",
+        &serde_json::json!({ "query_id": id, "decided_by": caller.key_id }),
+    )
+    .await?;
+    notify::emit("query_approved", query.dataset_id, id);
+
+    Ok(response)
+}
+
+pub async fn reject_query(state: &AppState, caller: &Caller, id: Uuid) -> Result<QueryRejectResponse, ApiError> {
+    caller.require(Role::Approver)?;
+    caller.require_scope(Scope::QueriesApprove)?;
+
+    let query = pending_query(state, id).await?;
+    if !state.store.reject_pending_query(id, &caller.key_id).await? {
+        return Err(ApiError::Conflict("query already decided".to_string()));
+    }
+
+    state.store.append_audit(
+        Some(query.dataset_id),
+        "query_rejected",
+        &serde_json::json!({ "query_id": id, "decided_by": caller.key_id }),
+    )
+    .await?;
+    notify::emit("query_rejected", query.dataset_id, id);
+
+    Ok(QueryRejectResponse {
+        query_id: id,
+        status: "rejected".to_string(),
+    })
+}
+
+pub async fn get_query_status(state: &AppState, caller: &Caller, id: Uuid) -> Result<QueryStatusResponse, ApiError> {
+    let Some(row) = state.store.get_query(id).await? else {
+        return Err(ApiError::NotFound("query not found".to_string()));
+    };
+    acl::check_access(state, Some(caller), row.dataset_id).await?;
+
+    let result = match &row.result {
+        Some(result) => {
+            let (metric, buckets, field) = query::stored_params(&row)?;
+            let dataset = existing_dataset(state, row.dataset_id).await?;
+            let mut response = query::query_response(id, row.dataset_id, &dataset.age_buckets, &metric, buckets, field, result);
+            receipt::sign(&state.signing_key, &mut response)?;
+            Some(response)
+        }
+        None => None,
+    };
+
+    Ok(QueryStatusResponse {
+        query_id: id,
+        dataset_id: row.dataset_id,
+        status: row.status,
+        result,
+        error: row.error,
+    })
+}
+
+/// A stored query as recorded, with its released answer rebuilt as `get_query_status` does.
+fn query_record(state: &AppState, caller: &Caller, dataset: &db::DatasetRow, row: db::QueryRow) -> Result<QueryRecord, ApiError> {
+    let result = match &row.result {
+        Some(result) => {
+            let (metric, buckets, field) = query::stored_params(&row)?;
+            let mut response = query::query_response(row.id, row.dataset_id, &dataset.age_buckets, &metric, buckets, field, result);
+            receipt::sign(&state.signing_key, &mut response)?;
+            Some(response)
+        }
+        None => None,
+    };
+    Ok(QueryRecord {
+        query_id: row.id,
+        dataset_id: row.dataset_id,
+        status: row.status,
+        created_at: row.created_at,
+        released_at: row.released_at,
+        decided_by: row.decided_by,
+        query_json: row.query_json,
+        server_verified: row.result.as_ref().map(|r| r.verified),
+        result,
+        // Suppressed answers are stored exact; only admins see past the suppression.
+        result_json: row.result_json.filter(|_| caller.require(Role::Admin).is_ok()),
+        error: row.error,
+    })
+}
+
+pub async fn get_query(state: &AppState, caller: &Caller, id: Uuid) -> Result<QueryRecord, ApiError> {
+    let Some(row) = state.store.get_query(id).await? else {
+        return Err(ApiError::NotFound("query not found".to_string()));
+    };
+    acl::check_access(state, Some(caller), row.dataset_id).await?;
+    let dataset = existing_dataset(state, row.dataset_id).await?;
+    query_record(state, caller, &dataset, row)
+}
+
+pub async fn list_dataset_queries(state: &AppState, caller: &Caller, id: Uuid, params: &PageParams) -> Result<QueryListResponse, ApiError> {
+    let (offset, limit) = page(params.offset, params.limit);
+    let dataset = existing_dataset(state, id).await?;
+    acl::check_access(state, Some(caller), id).await?;
+
+    let mut queries = Vec::new();
+    for row in state.store.list_dataset_queries(id, offset, limit).await? {
+        queries.push(query_record(state, caller, &dataset, row)?);
+    }
+    Ok(QueryListResponse {
+        dataset_id: id,
+        offset,
+        limit,
+        queries,
+    })
+}
+
+fn deferred_response(query_id: Uuid, dataset_id: Uuid, status: &str) -> QueryPendingResponse {
+    QueryPendingResponse {
+        query_id,
+        dataset_id,
+        status: status.to_string(),
+        status_endpoint: format!("/api/v1/queries/{query_id}/status"),
+    }
+}
+
+async fn pending_query(state: &AppState, id: Uuid) -> Result<db::QueryRow, ApiError> {
+    let Some(query) = state.store.get_query(id).await? else {
+        return Err(ApiError::NotFound("query not found".to_string()));
+    };
+    if query.status != "pending_approval" {
+        return Err(ApiError::Conflict(format!("query is {}", query.status)));
+    }
+    Ok(query)
+}
+
+// --- Verification ---
+
+/// Circuit parameters of `params.dataset_id`, or those a new dataset gets by default.
+pub async fn get_zk_schema(state: &AppState, params: &SchemaParams) -> Result<ZkSchemaResponse, ApiError> {
+    let (shard_size, field_set, age_buckets, chain_hash, sha256_commitment, revision) = match params.dataset_id {
+        Some(dataset_id) => {
+            let dataset = loaded_dataset(state, dataset_id).await?;
+            let revision = circuit_migration::current_revision(state, &dataset);
+            (
+                dataset.shard_size as usize,
+                dataset.field_set,
+                dataset.age_buckets,
+                dataset.chain_hash,
+                dataset.sha256_commitment,
+                revision,
+            )
+        }
+        None => (
+            state.config.default_shard_size,
+            FieldSet::default(),
+            AgeBuckets::default(),
+            chain::default_chain_hash(),
+            false,
+            CircuitRevision::LATEST,
+        ),
+    };
+
+    let fields = Measurement::ALL
+        .into_iter()
+        .map(|m| SchemaField {
+            name: m.name().to_string(),
+            unit: m.unit().to_string(),
+            bits: MEASUREMENT_BITS,
+            field_sets: FieldSet::ALL.into_iter().filter(|s| s.position(m).is_some()).collect(),
+            plausible_range: (m == Measurement::BloodGlucose && revision.proves_glucose_bounds())
+                .then_some(PLAUSIBLE_GLUCOSE_MG_DL),
+        })
+        .collect();
+
+    Ok(ZkSchemaResponse {
+        dataset_id: params.dataset_id,
+        num_buckets: age_buckets.num_buckets(),
+        max_buckets: MAX_BUCKETS,
+        max_age: MAX_AGE,
+        age_bits: AGE_BITS,
+        shard_size,
+        supported_shard_sizes: registry::SUPPORTED_SHARD_SIZES.to_vec(),
+        field_set,
+        fields,
+        glucose_ranges: GLUCOSE_RANGES.to_vec(),
+        circuit_version: revision.version().to_string(),
+        circuit_versions: CircuitRevision::ALL.iter().map(|r| r.version().to_string()).collect(),
+        circuit_id: circuit_id(shard_size, field_set, &age_buckets, revision, sha256_commitment),
+        age_buckets: age_buckets.into(),
+        chain_hash,
+        poseidon: PoseidonSchema {
+            width: POSEIDON_RATE + POSEIDON_CAPACITY,
+            full_rounds: POSEIDON_FULL_ROUNDS,
+            partial_rounds: POSEIDON_PARTIAL_ROUNDS,
+            alpha: POSEIDON_ALPHA,
+        },
+        curves: Curve::ALL.to_vec(),
+    })
+}
+
+/// A proving key (or the `range` of it being resumed) for a partner proving small shards itself;
+/// the keys that can push federated shards are the ones allowed to download it.
+pub async fn get_pk(
+    state: &AppState,
+    caller: &Caller,
+    params: &PkParams,
+    range: Option<&str>,
+    if_range: Option<&str>,
+) -> Result<pk_download::PkDownload, ApiError> {
+    caller.require_scope(Scope::DatasetsCreate)?;
+    let shard_size = checked_shard_size(state, params.shard_size)?;
+    let field_set = params.field_set.unwrap_or_default();
+    let download = pk_download::pk_download(state, shard_size, field_set, params.sha256_commitment.unwrap_or(false), range, if_range).await?;
+    if download.range.is_none_or(|(start, _)| start == 0) {
+        tracing::info!(key_id = %download.key_id, caller = %caller.key_id, shard_size, "proving key downloaded");
+    }
+    Ok(download)
+}
+
+pub async fn get_vk(state: &AppState, params: &VkParams) -> Result<ZkVkResponse, ApiError> {
+    let b64 = base64::engine::general_purpose::STANDARD;
+    let (curve, b64) = match params.dataset_id {
+        Some(dataset_id) => {
+            let dataset = loaded_dataset(state, dataset_id).await?;
+            match curve_migration::serving_curve(state, dataset_id, params.curve).await? {
+                (Curve::Bn254, _) => (
+                    Curve::Bn254,
+                    export::dataset_vk_b64(
+                        state,
+                        dataset_id,
+                        dataset.shard_size,
+                        dataset.field_set,
+                        &dataset.age_buckets,
+                        dataset.sha256_commitment,
+                    )
+                    .await?,
+                ),
+                (curve, migrated) => {
+                    let keys = state
+                        .ensure_bls_keys(dataset.shard_size as usize, dataset.field_set, &dataset.age_buckets)
+                        .await?;
+                    if migrated.map(|m| m.key_id) != Some(keys.key_id.clone()) {
+                        return Err(ApiError::Conflict(format!(
+                            "dataset's {} proofs were made with keys that are no longer loaded",
+                            curve.name()
+                        )));
+                    }
+                    (curve, b64.encode(zk_proofs::groth16::serialize_vk(keys.vk.as_ref()).map_err(|_| ApiError::Internal)?))
+                }
+            }
+        }
+        None => {
+            let shard_size = checked_shard_size(state, params.shard_size)?;
+            let field_set = params.field_set.unwrap_or_default();
+            let buckets = AgeBuckets::default();
+            let sha256_commitment = params.sha256_commitment.unwrap_or(false);
+            let curve = params.curve.unwrap_or_default();
+            let vk_bytes = match curve {
+                Curve::Bn254 => {
+                    let keys = state.ensure_keys_for(shard_size, field_set, &buckets, sha256_commitment).await?;
+                    zk_proofs::groth16::serialize_vk(keys.vk.as_ref())
+                }
+                Curve::Bls12_381 => {
+                    if sha256_commitment {
+                        return Err(ApiError::BadRequest(format!("dual-commitment circuits have no {} keys", curve.name())));
+                    }
+                    let keys = state.ensure_bls_keys(shard_size, field_set, &buckets).await?;
+                    zk_proofs::groth16::serialize_vk(keys.vk.as_ref())
+                }
+            };
+            (curve, b64.encode(vk_bytes.map_err(|_| ApiError::Internal)?))
+        }
+    };
+
+    let vk_bytes = base64::engine::general_purpose::STANDARD.decode(&b64).map_err(|_| ApiError::Internal)?;
+    Ok(ZkVkResponse {
+        curve: curve.name().to_string(),
+        proof_system: "groth16".to_string(),
+        vk_b64: b64,
+        key_id: hex::encode(Sha256::digest(&vk_bytes)),
+    })
+}
+
+/// The BN254 shard verifying key generation `version` (a key id, as in a shard's `vk_version`):
+/// the live key or one its key files replaced.
+pub fn get_vk_version(state: &AppState, version: &str) -> Result<ZkVkResponse, ApiError> {
+    let vk = state
+        .shard_vk(version)?
+        .ok_or_else(|| ApiError::NotFound(format!("no shard verifying key {version} on this ledger")))?;
+    let vk_bytes = zk_proofs::groth16::serialize_vk(vk.as_ref()).map_err(|_| ApiError::Internal)?;
+    Ok(ZkVkResponse {
+        curve: Curve::Bn254.name().to_string(),
+        proof_system: "groth16".to_string(),
+        vk_b64: base64::engine::general_purpose::STANDARD.encode(&vk_bytes),
+        key_id: hex::encode(Sha256::digest(&vk_bytes)),
+    })
+}
+
+/// A `get_vk` key as snarkjs' `verification_key.json`.
+pub fn snarkjs_vk(response: &ZkVkResponse) -> Result<serde_json::Value, ApiError> {
+    if response.curve != Curve::Bn254.name() {
+        return Err(ApiError::BadRequest(format!("format=snarkjs is only available for bn254 keys, not {}", response.curve)));
+    }
+    let vk = decode_vk_b64::<Bn254>(&response.vk_b64).map_err(|_| ApiError::Internal)?;
+    Ok(zk_proofs::groth16::to_snarkjs_vk_json(&vk))
+}
+
+/// A Solidity contract verifying shard proofs made with the `get_vk` key (BN254 only), with the
+/// circuit's public inputs named in its comments.
+pub async fn get_solidity_verifier(state: &AppState, params: &VkParams) -> Result<String, ApiError> {
+    let response = get_vk(state, params).await?;
+    if response.curve != Curve::Bn254.name() {
+        return Err(ApiError::BadRequest(format!("verifier contracts are only generated for bn254 keys, not {}", response.curve)));
+    }
+    let vk = decode_vk_b64::<Bn254>(&response.vk_b64).map_err(|_| ApiError::Internal)?;
+    let (shard_size, field_set, age_buckets) = match params.dataset_id {
+        Some(dataset_id) => {
+            let dataset = loaded_dataset(state, dataset_id).await?;
+            (dataset.shard_size as usize, dataset.field_set, dataset.age_buckets)
+        }
+        None => (checked_shard_size(state, params.shard_size)?, params.field_set.unwrap_or_default(), AgeBuckets::default()),
+    };
+    let num_buckets = age_buckets.num_buckets();
+    let revision = vk_revision(&vk, field_set, num_buckets);
+    let sha256_commitment = vk_sha256_commitment(&vk, field_set, num_buckets);
+    let input_names = zk_proofs::groth16::shard_public_input_names(revision, field_set, num_buckets, sha256_commitment);
+    let circuit_id = circuit_id(shard_size, field_set, &age_buckets, revision, sha256_commitment);
+    Ok(zk_proofs::groth16::solidity_verifier(&vk, &circuit_id, &input_names))
+}
+
+/// Verify one shard proof against caller-supplied public inputs (no ledger state involved).
+pub fn verify_shard(caller: &Caller, req: VerifyShardRequest) -> Result<VerifyShardResponse, ApiError> {
+    caller.require_scope(Scope::Verify)?;
+    let shard = encoded_shard(req.shard);
+    let outcome = match req.curve {
+        Curve::Bn254 => verify_encoded_shard(&decode_vk_b64::<Bn254>(&req.vk_b64).map_err(bad_request)?, &shard),
+        Curve::Bls12_381 => verify_encoded_shard(&decode_vk_b64::<Bls12_381>(&req.vk_b64).map_err(bad_request)?, &shard),
+    };
+    if let VerificationOutcome::Malformed(e) = outcome {
+        return Err(bad_request(e));
+    }
+
+    Ok(VerifyShardResponse { ok: outcome.is_valid() })
+}
+
+/// The verifying key a batch names: inline, or a BN254 shard key this ledger has archived.
+fn batch_vk_b64(state: &AppState, req: &VerifyShardsRequest) -> Result<String, ApiError> {
+    match (&req.vk_b64, &req.key_id) {
+        (Some(vk_b64), None) => Ok(vk_b64.clone()),
+        (None, Some(key_id)) => {
+            if req.curve != Curve::Bn254 {
+                return Err(ApiError::BadRequest("key_id names BN254 shard keys; pass vk_b64 for other curves".to_string()));
+            }
+            archived_vk(&state.data_dir.join("keys"), key_id)
+                .map(|bytes| base64::engine::general_purpose::STANDARD.encode(bytes))
+                .ok_or_else(|| ApiError::NotFound(format!("no shard verifying key {key_id} on this ledger")))
+        }
+        _ => Err(ApiError::BadRequest("exactly one of vk_b64 and key_id is required".to_string())),
+    }
+}
+
+/// Entries checked per chunk of a batch; the request's cancellation is checked between chunks.
+const VERIFY_CHUNK: usize = 256;
+
+/// Check a batch chunk by chunk until done or until the next chunk would overrun the request's
+/// deadline; `check_chunk` gives one result per entry of its chunk. Entries not reached are left
+/// unchecked.
+fn verify_chunked(
+    shards: &[EncodedShard],
+    cancel: &Cancellation,
+    check_chunk: impl Fn(&[EncodedShard]) -> Vec<VerifyShardResult>,
+) -> VerifyShardsResponse {
+    let mut results = Vec::with_capacity(shards.len());
+    let mut last_chunk = Duration::ZERO;
+    for chunk in shards.chunks(VERIFY_CHUNK) {
+        if cancel.would_overrun(last_chunk) {
+            break;
+        }
+        let started = Instant::now();
+        results.extend(check_chunk(chunk));
+        last_chunk = started.elapsed();
+    }
+
+    let checked = results.len();
+    let invalid: Vec<usize> = results.iter().enumerate().filter(|(_, r)| !r.ok).map(|(i, _)| i).collect();
+    let unchecked: Vec<usize> = (checked..shards.len()).collect();
+    results.resize(
+        shards.len(),
+        VerifyShardResult {
+            ok: false,
+            error: Some("not checked: the request's deadline was reached".to_string()),
+        },
+    );
+    VerifyShardsResponse {
+        ok: invalid.is_empty() && unchecked.is_empty(),
+        complete: unchecked.is_empty(),
+        shards_total: shards.len(),
+        invalid,
+        unchecked,
+        results,
+    }
+}
+
+/// Per-entry results of one chunk: entries that `decode` rejects fail with its error, and
+/// `failed_in` names (by position among the decoded ones) those whose proof doesn't verify.
+fn check_chunk<T>(
+    chunk: &[EncodedShard],
+    decode: impl Fn(&EncodedShard) -> Result<T, DecodeError>,
+    failed_in: impl FnOnce(&[T]) -> Vec<usize>,
+) -> Vec<VerifyShardResult> {
+    let mut results = vec![VerifyShardResult::default(); chunk.len()];
+    let (mut decoded, mut instances) = (Vec::new(), Vec::new());
+    for (i, shard) in chunk.iter().enumerate() {
+        match decode(shard) {
+            Ok(instance) => {
+                decoded.push(i);
+                instances.push(instance);
+            }
+            Err(e) => results[i].error = Some(e.to_string()),
+        }
+    }
+    let failed = failed_in(&instances);
+    for (j, &i) in decoded.iter().enumerate() {
+        results[i].ok = !failed.contains(&j);
+    }
+    results
+}
+
+/// Verify many shard proofs against one verifying key with batched pairing checks, naming the
+/// invalid ones when a batch fails. An entry that can't be decoded fails on its own, with its
+/// error, rather than failing the request. Near the request's deadline the rest are left
+/// unchecked (see `deadline`).
+/// Queue a re-verification of every shard proof of a ready dataset (joining one already queued
+/// or running); see `reverify`.
+pub async fn verify_dataset(state: &AppState, caller: &Caller, id: Uuid) -> Result<DatasetReverificationPending, ApiError> {
+    caller.require_scope(Scope::Verify)?;
+    let dataset = loaded_dataset(state, id).await?;
+    acl::check_access(state, Some(caller), id).await?;
+    if dataset.status != "ready" {
+        return Err(ApiError::Conflict("only ready datasets can be re-verified".to_string()));
+    }
+
+    if let Some((status, _)) = reverify::job_status(state, id).await?
+        && (status == "queued" || status == "running")
+    {
+        return Ok(reverify::pending(id, &status, None));
+    }
+    jobs::enqueue(state, jobs::KIND_VERIFY_DATASET, id, &caller.key_id).await?;
+    state.progress.verification(VerificationEvent {
+        dataset_id: id,
+        status: "queued".to_string(),
+        shards_checked: 0,
+        shards_total: dataset.shards_total(),
+        failures: 0,
+    });
+    Ok(reverify::pending(id, "queued", None))
+}
+
+/// The latest re-verification report of a dataset, or the job still making one.
+pub async fn get_dataset_reverification(state: &AppState, caller: &Caller, id: Uuid) -> Result<DatasetReverificationOutcome, ApiError> {
+    let dataset = existing_dataset(state, id).await?;
+    acl::check_access(state, Some(caller), id).await?;
+
+    let job = reverify::job_status(state, id).await?;
+    if let Some((status, _)) = &job
+        && (status == "queued" || status == "running")
+    {
+        return Ok(DatasetReverificationOutcome::Pending(reverify::pending(id, status, None)));
+    }
+    match state.store.get_dataset_reverification(id).await? {
+        Some(row) => Ok(DatasetReverificationOutcome::Finished(Box::new(reverify::to_report(id, &dataset, row)))),
+        None => match job {
+            Some((_, Some(error))) => Err(ApiError::Conflict(format!("re-verification failed: {error}"))),
+            _ => Err(ApiError::NotFound("dataset has not been re-verified".to_string())),
+        },
+    }
+}
+
+pub async fn verify_shards(
+    state: &AppState,
+    caller: &Caller,
+    cancel: &Cancellation,
+    req: VerifyShardsRequest,
+) -> Result<VerifyShardsResponse, ApiError> {
+    caller.require_scope(Scope::Verify)?;
+    let vk_b64 = batch_vk_b64(state, &req)?;
+    let shards: Vec<EncodedShard> = req.shards.into_iter().map(encoded_shard).collect();
+    let cancel = cancel.clone();
+
+    // BLS12-381 proofs of migrated datasets are checked one by one; there is no batched check on
+    // that curve.
+    match req.curve {
+        Curve::Bn254 => {
+            let vk = decode_vk_b64::<Bn254>(&vk_b64).map_err(bad_request)?;
+            tokio::task::spawn_blocking(move || {
+                verify_chunked(&shards, &cancel, |chunk| {
+                    check_chunk(chunk, decode_shard_instance, |instances| match verify_shard_proofs_batch(&vk, instances) {
+                        Ok(()) => Vec::new(),
+                        Err(_) => invalid_shard_proofs(&vk, instances),
+                    })
+                })
+            })
+            .await
+        }
+        Curve::Bls12_381 => {
+            let vk = decode_vk_b64::<Bls12_381>(&vk_b64).map_err(bad_request)?;
+            tokio::task::spawn_blocking(move || {
+                verify_chunked(&shards, &cancel, |chunk| {
+                    check_chunk(chunk, decode_shard::<Bls12_381>, |instances| {
+                        instances
+                            .iter()
+                            .enumerate()
+                            .filter(|(_, (proof, inputs))| !verify_decoded(&vk, proof, inputs))
+                            .map(|(j, _)| j)
+                            .collect()
+                    })
+                })
+            })
+            .await
+        }
+    }
+    .map_err(|_| ApiError::Internal)
+}
+
+fn bad_request(e: DecodeError) -> ApiError {
+    ApiError::BadRequest(e.to_string())
+}
+
+/// A `/verify` request's shard, in the shared wire form.
+fn encoded_shard(req: ShardProofRequest) -> EncodedShard {
+    EncodedShard {
+        proof_b64: Some(req.proof_b64),
+        shard_commitment_hex: req.public_shard_commitment_hex,
+        salt_commitment_hex: req.public_salt_commitment_hex,
+        sha256_commitment_hex: req.public_sha256_commitment_hex,
+        stats: ShardStats {
+            sum_glucose_by_bucket: req.public_sum_glucose_by_bucket,
+            count_by_bucket: req.public_count_by_bucket,
+            extra_sums_by_bucket: req.public_extra_sums_by_bucket,
+            sum_glucose_sq_by_bucket: req.public_sum_glucose_sq_by_bucket,
+            glucose_histogram_by_bucket: req.public_glucose_histogram_by_bucket,
+            salt_commitment: None,
+            sha256_commitment: None,
+            glucose_bounds: req.public_glucose_bounds,
+        },
+    }
+}
+
+// --- Administration ---
+
+/// Signed JSONL export of one or all ready datasets, for `import_ledger` elsewhere.
+pub async fn export_ledger(state: &AppState, caller: &Caller, params: &ExportParams) -> Result<Vec<u8>, ApiError> {
+    caller.require(Role::Admin)?;
+
+    let shard_range = match (params.shard_index_from, params.shard_index_to) {
+        (None, None) => None,
+        (from, to) => {
+            if params.dataset_id.is_none() {
+                return Err(ApiError::BadRequest("shard_index_from/shard_index_to require dataset_id".to_string()));
+            }
+            Some(shard_index_range(from, to, u64::MAX)?)
+        }
+    };
+
+    export::export_ledger(state, params.dataset_id.map(|id| vec![id]), shard_range).await
+}
+
+pub async fn import_ledger(
+    state: &AppState,
+    caller: &Caller,
+    cancel: &Cancellation,
+    export: &[u8],
+) -> Result<export::ImportReport, ApiError> {
+    caller.require(Role::Admin)?;
+
+    export::import_ledger(state, export, &caller.key_id, cancel).await
+}
+
+/// How long a readiness probe waits for a database round trip.
+const READYZ_DB_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Liveness: answering at all is the check.
+pub fn liveness() -> HealthzResponse {
+    HealthzResponse {
+        status: "ok".to_string(),
+        version: env!("CARGO_PKG_VERSION").to_string(),
+    }
+}
+
+async fn database_check(state: &AppState) -> DatabaseCheck {
+    let started = Instant::now();
+    let ping = async {
+        state.store.ping().await?;
+        if !state.store.is_sqlite() {
+            db::ping(&state.db).await?;
+        }
+        Ok::<(), ApiError>(())
+    };
+    let error = match tokio::time::timeout(READYZ_DB_TIMEOUT, ping).await {
+        Ok(Ok(())) => None,
+        Ok(Err(_)) => Some("database query failed".to_string()),
+        Err(_) => Some(format!("no answer within {}s", READYZ_DB_TIMEOUT.as_secs())),
+    };
+    DatabaseCheck {
+        ok: error.is_none(),
+        latency_ms: started.elapsed().as_millis() as u64,
+        error,
+    }
+}
+
+/// Loaded keys are proving from memory; a key file gone or replaced underneath them means the
+/// next restart sets up or loads different keys.
+fn keys_check(state: &AppState) -> KeysCheck {
+    let keys_dir = state.data_dir.join("keys");
+    let loaded = state.loaded_keys();
+    let mut problems = Vec::new();
+    for (shard_size, field_set, buckets, keys) in &loaded {
+        let (pk_path, vk_path) = key_paths(&keys_dir, *shard_size, *field_set, buckets, keys.sha256_commitment);
+        if !pk_path.exists() {
+            problems.push(format!("{} is missing", pk_path.display()));
+        }
+        match std::fs::read(&vk_path) {
+            Ok(vk_bytes) if hex::encode(Sha256::digest(&vk_bytes)) == keys.key_id => {}
+            Ok(_) => problems.push(format!("{} no longer holds key {}", vk_path.display(), keys.key_id)),
+            Err(_) => problems.push(format!("{} is missing", vk_path.display())),
+        }
+    }
+    KeysCheck {
+        ok: problems.is_empty(),
+        loaded: loaded.len() as u64,
+        problems,
+    }
+}
+
+/// `READYZ_MAX_PROVING_BACKLOG`: queued proving jobs above which the instance reports not ready,
+/// so new work goes elsewhere. Unset = no limit.
+fn max_proving_backlog() -> Option<u64> {
+    std::env::var("READYZ_MAX_PROVING_BACKLOG").ok().and_then(|v| v.parse().ok())
+}
+
+async fn proving_backlog_check(state: &AppState) -> ProvingBacklogCheck {
+    let max_queued = max_proving_backlog();
+    match state.store.proving_backlog().await {
+        Ok((running, queued)) => ProvingBacklogCheck {
+            ok: max_queued.is_none_or(|max| queued <= max),
+            running,
+            queued,
+            max_queued,
+        },
+        Err(_) => ProvingBacklogCheck { ok: false, running: 0, queued: 0, max_queued },
+    }
+}
+
+/// Readiness: the database answers, loaded keys are still on disk, the proving backlog is within
+/// bounds and the ZK self-test has passed.
+pub async fn readiness(state: &AppState) -> ReadyzResponse {
+    let checks = ReadinessChecks {
+        database: database_check(state).await,
+        keys: keys_check(state),
+        proving_backlog: proving_backlog_check(state).await,
+    };
+    let zk_self_test = state.zk_self_test();
+    let ready = checks.database.ok
+        && checks.keys.ok
+        && checks.proving_backlog.ok
+        && zk_self_test.as_ref().is_some_and(|r| r.ok);
+    ReadyzResponse { ready, checks, zk_self_test }
+}
+
+/// Rerun the ZK self-test. Keys are cached in memory, so replaced key files need a restart.
+pub async fn run_zk_self_test(state: &AppState, caller: &Caller) -> Result<ZkSelfTestReport, ApiError> {
+    caller.require(Role::Admin)?;
+
+    let report = selftest::run(state).await;
+    if report.ok {
+        state.jobs_notify.notify_waiters();
+    }
+    Ok(report)
+}
+
+pub fn proving_status(state: &AppState, caller: &Caller) -> Result<ProvingStatusResponse, ApiError> {
+    caller.require(Role::Admin)?;
+
+    Ok(ProvingStatusResponse {
+        admission: state.proving_admission.snapshot(),
+        available_bytes: admission::available_memory(),
+        estimates: state
+            .loaded_keys()
+            .into_iter()
+            .map(|(shard_size, field_set, age_buckets, keys)| ProvingKeyEstimate {
+                shard_size: shard_size as u64,
+                field_set,
+                age_buckets,
+                sha256_commitment: keys.sha256_commitment,
+                proof_bytes: keys.proof_bytes,
+            })
+            .collect(),
+    })
+}
+
+pub async fn list_zk_keys(state: &AppState, caller: &Caller) -> Result<ZkKeysResponse, ApiError> {
+    caller.require(Role::Admin)?;
+
+    Ok(ZkKeysResponse {
+        thresholds: key_usage::thresholds(),
+        keys: key_usage::key_usage(state).await?,
+    })
+}
+
+pub async fn metrics(state: &AppState) -> Result<String, ApiError> {
+    key_usage::metrics_text(state).await
+}
+
+pub async fn proof_blobs_status(state: &AppState, caller: &Caller) -> Result<ProofBlobsResponse, ApiError> {
+    caller.require(Role::Admin)?;
+
+    let (blobs, stored_bytes, shard_references) = state.store.proof_blob_stats().await?;
+    Ok(ProofBlobsResponse {
+        blobs,
+        stored_bytes,
+        shard_references,
+        last_audit: state.proof_blob_audit(),
+    })
+}
+
+pub async fn run_proof_blob_audit(state: &AppState, caller: &Caller) -> Result<ProofBlobAuditReport, ApiError> {
+    caller.require(Role::Admin)?;
+
+    audit::audit_proof_blobs(state).await
+}
+
+/// Plan (and unless `dry_run`, start) moving datasets to the target curve: synthetic datasets are
+/// queued for re-proving, the rest flagged with the reason (see `curve_migration`).
+pub async fn plan_curve_migration(state: &AppState, caller: &Caller, req: CurveMigrationRequest) -> Result<CurveMigrationPlanResponse, ApiError> {
+    caller.require(Role::Admin)?;
+
+    let curve = req.curve.unwrap_or(curve_migration::TARGET_CURVE);
+    if curve != curve_migration::TARGET_CURVE {
+        return Err(ApiError::BadRequest(format!(
+            "datasets can only be migrated to {}",
+            curve_migration::TARGET_CURVE.name()
+        )));
+    }
+    let dataset_ids = match req.dataset_ids {
+        Some(ids) => ids,
+        None => state.store.list_dataset_ids().await?,
+    };
+
+    let mut datasets = Vec::with_capacity(dataset_ids.len());
+    for id in dataset_ids {
+        let dataset = existing_dataset(state, id).await?;
+        let current = state.store.get_curve_migration(id, curve).await?;
+        datasets.push(curve_migration::plan_item(id, &dataset, current.as_ref()));
+    }
+
+    if !req.dry_run {
+        for item in &datasets {
+            match item.action.as_str() {
+                "reprove" => {
+                    state.store.put_curve_migration(item.dataset_id, curve, "queued", None).await?;
+                    jobs::enqueue(state, jobs::KIND_MIGRATE_CURVE, item.dataset_id, &caller.key_id).await?;
+                }
+                "flag" => {
+                    state.store.put_curve_migration(item.dataset_id, curve, "flagged", item.reason.as_deref()).await?;
+                    state.store.set_curve_migration_status(item.dataset_id, curve, "flagged", item.reason.as_deref()).await?;
+                }
+                _ => {}
+            }
+        }
+        state.store.append_audit(
+            None,
+            "curve_migration_planned",
+            &serde_json::json!({
+                "curve": curve.name(),
+                "reprove": datasets.iter().filter(|d| d.action == "reprove").map(|d| d.dataset_id).collect::<Vec<_>>(),
+                "flag": datasets.iter().filter(|d| d.action == "flag").map(|d| d.dataset_id).collect::<Vec<_>>(),
+                "planned_by": caller.key_id,
+            }),
+        )
+        .await?;
+    }
+
+    Ok(CurveMigrationPlanResponse {
+        curve,
+        dry_run: req.dry_run,
+        datasets,
+    })
+}
+
+/// Plan (and unless `dry_run`, start) upgrading datasets to a newer shard circuit revision:
+/// synthetic datasets are queued for re-proving, affected ones that can't be are flagged with the
+/// reason (see `circuit_migration`).
+pub async fn plan_circuit_migration(state: &AppState, caller: &Caller, req: CircuitMigrationRequest) -> Result<CircuitMigrationPlanResponse, ApiError> {
+    caller.require(Role::Admin)?;
+
+    let revision = |version: &str| {
+        CircuitRevision::parse(version).ok_or_else(|| {
+            let known: Vec<&str> = CircuitRevision::ALL.iter().map(|r| r.version()).collect();
+            ApiError::BadRequest(format!("unknown circuit revision '{version}' (known: {})", known.join(", ")))
+        })
+    };
+    let to = req.to.as_deref().map(revision).transpose()?.unwrap_or(CircuitRevision::LATEST);
+    let from = req.from.as_deref().map(revision).transpose()?;
+    if from.is_some_and(|from| from >= to) {
+        return Err(ApiError::BadRequest("from must be an older revision than to".to_string()));
+    }
+    let dataset_ids = match req.dataset_ids {
+        Some(ids) => ids,
+        None => state.store.list_dataset_ids().await?,
+    };
+
+    let mut datasets = Vec::with_capacity(dataset_ids.len());
+    for id in dataset_ids {
+        let dataset = existing_dataset(state, id).await?;
+        let proven = circuit_migration::proven_with(state, id, &dataset).await?;
+        let current = circuit_migration::current_revision(state, &dataset);
+        datasets.push(circuit_migration::plan_item(id, &dataset, &proven, current, from, to));
+    }
+
+    if !req.dry_run {
+        for item in datasets.iter().filter(|d| d.action == "reprove") {
+            jobs::enqueue(state, jobs::KIND_MIGRATE_CIRCUIT, item.dataset_id, &caller.key_id).await?;
+        }
+        state.store.append_audit(
+            None,
+            "circuit_migration_planned",
+            &serde_json::json!({
+                "from": from.map(|r| r.version()),
+                "to": to.version(),
+                "reprove": datasets.iter().filter(|d| d.action == "reprove").map(|d| d.dataset_id).collect::<Vec<_>>(),
+                "flag": datasets.iter().filter(|d| d.action == "flag").map(|d| d.dataset_id).collect::<Vec<_>>(),
+                "planned_by": caller.key_id,
+            }),
+        )
+        .await?;
+    }
+
+    Ok(CircuitMigrationPlanResponse {
+        from: from.map(|r| r.version().to_string()),
+        to: to.version().to_string(),
+        dry_run: req.dry_run,
+        datasets,
+    })
+}
+
+pub async fn list_curve_migrations(state: &AppState, caller: &Caller) -> Result<CurveMigrationListResponse, ApiError> {
+    caller.require(Role::Admin)?;
+
+    let curve = curve_migration::TARGET_CURVE;
+    let migrations = state.store.list_curve_migrations(curve).await?;
+    Ok(CurveMigrationListResponse {
+        curve,
+        transition_days: curve_migration::transition_days(),
+        migrations: migrations.into_iter().map(curve_migration::migration_item).collect(),
+    })
+}
+
+/// Snapshot the ledger under `data/backups/<timestamp>`. Restoring is CLI-only (`restore SRC`).
+pub async fn create_backup(state: &AppState, caller: &Caller) -> Result<BackupResponse, ApiError> {
+    caller.require(Role::Admin)?;
+    if !state.store.is_sqlite() {
+        return Err(ApiError::Conflict(
+            "backups cover the SQLite ledger only; back up the Postgres ledger with pg_dump".to_string(),
+        ));
+    }
+
+    let dest = backup::backups_dir(&state.data_dir).join(chrono::Utc::now().format("%Y%m%dT%H%M%S%.3fZ").to_string());
+    let manifest = backup::create_backup(&state.db, &state.proof_files, &state.data_dir, &dest).await?;
+
+    state.store.append_audit(
+        None,
+        "backup_created",
+        &serde_json::json!({ "path": dest.display().to_string(), "created_by": caller.key_id }),
+    )
+    .await?;
+
+    Ok(BackupResponse {
+        path: dest.display().to_string(),
+        manifest,
+    })
+}
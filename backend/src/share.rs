@@ -0,0 +1,150 @@
+//! Time-limited, read-only share links for auditors.
+//!
+//! `POST /api/v1/datasets/:id/share` (the dataset's owner or an admin) issues a token that lets its
+//! bearer read that one dataset's shards with their proofs (listing and NDJSON export) and its
+//! verification report until it expires, even when the dataset's access list restricts it. It is
+//! not an API key: it carries no query rights and every other endpoint refuses it.
+//!
+//! A token is `base64url(claims JSON) "." base64url(HMAC-SHA256(claims JSON))` under the instance's
+//! share key (`keys/share_link.key`, created on first use and carried by backups; instances sharing
+//! a Postgres ledger need the same file). It is presented in the `X-SHARE-TOKEN` header or a
+//! `share_token` query parameter. Issuing one is recorded in the audit chain, without the token.
+//!
+//! - `SHARE_LINK_MAX_TTL_SECS`: longest lifetime a link may be given (default 30 days); links
+//!   last 7 days unless the request asks for less or more.
+
+use crate::acl;
+use crate::auth::Caller;
+use crate::errors::ApiError;
+use crate::models::{ShareLinkRequest, ShareLinkResponse};
+use crate::salt::write_private;
+use crate::state::AppState;
+use base64::Engine;
+use chrono::{DateTime, Utc};
+use ring::hmac;
+use ring::rand::{SecureRandom, SystemRandom};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use uuid::Uuid;
+use zeroize::Zeroizing;
+
+const SHARE_KEY_FILE: &str = "share_link.key";
+const CLAIMS_VERSION: u32 = 1;
+const DEFAULT_TTL_SECS: u64 = 7 * 24 * 60 * 60;
+const DEFAULT_MAX_TTL_SECS: u64 = 30 * 24 * 60 * 60;
+const LABEL_MAX_CHARS: usize = 200;
+
+/// What a share token grants: read access to one dataset until `expires_at`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ShareClaims {
+    pub v: u32,
+    pub share_id: Uuid,
+    pub dataset_id: Uuid,
+    pub issued_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+}
+
+fn max_ttl_secs() -> u64 {
+    std::env::var("SHARE_LINK_MAX_TTL_SECS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .filter(|secs| *secs > 0)
+        .unwrap_or(DEFAULT_MAX_TTL_SECS)
+}
+
+fn share_key(data_dir: &Path) -> Result<hmac::Key, ApiError> {
+    let keys_dir = data_dir.join("keys");
+    let path = keys_dir.join(SHARE_KEY_FILE);
+
+    let bytes = match std::fs::read(&path) {
+        Ok(bytes) => Zeroizing::new(bytes),
+        Err(_) => {
+            std::fs::create_dir_all(&keys_dir).map_err(|_| ApiError::Internal)?;
+            let mut key = Zeroizing::new(vec![0u8; 32]);
+            SystemRandom::new().fill(&mut key).map_err(|_| ApiError::Internal)?;
+            write_private(&path, &key)?;
+            key
+        }
+    };
+    Ok(hmac::Key::new(hmac::HMAC_SHA256, &bytes))
+}
+
+fn sign(data_dir: &Path, claims: &ShareClaims) -> Result<String, ApiError> {
+    let b64 = base64::engine::general_purpose::URL_SAFE_NO_PAD;
+    let payload = serde_json::to_vec(claims).map_err(|_| ApiError::Internal)?;
+    let tag = hmac::sign(&share_key(data_dir)?, &payload);
+    Ok(format!("{}.{}", b64.encode(&payload), b64.encode(tag.as_ref())))
+}
+
+/// The claims of a token signed by this instance that hasn't expired.
+pub fn verify(data_dir: &Path, token: &str) -> Result<ShareClaims, String> {
+    let b64 = base64::engine::general_purpose::URL_SAFE_NO_PAD;
+    let (payload, tag) = token.split_once('.').ok_or("malformed share token")?;
+    let payload = b64.decode(payload).map_err(|_| "malformed share token")?;
+    let tag = b64.decode(tag).map_err(|_| "malformed share token")?;
+
+    let key = share_key(data_dir).map_err(|_| "share key unavailable")?;
+    hmac::verify(&key, &payload, &tag).map_err(|_| "share token signature does not verify")?;
+
+    let claims: ShareClaims = serde_json::from_slice(&payload).map_err(|_| "malformed share token")?;
+    if claims.v != CLAIMS_VERSION {
+        return Err(format!("unsupported share token version {}", claims.v));
+    }
+    if claims.expires_at <= Utc::now() {
+        return Err("share token expired".to_string());
+    }
+    Ok(claims)
+}
+
+/// Issue a share link for `dataset_id` on behalf of its owner or an admin.
+pub async fn create(
+    state: &AppState,
+    caller: &Caller,
+    dataset_id: Uuid,
+    req: &ShareLinkRequest,
+) -> Result<ShareLinkResponse, ApiError> {
+    acl::managed_dataset(state, caller, dataset_id).await?;
+
+    let max_ttl = max_ttl_secs();
+    let ttl = req.ttl_secs.unwrap_or(DEFAULT_TTL_SECS.min(max_ttl));
+    if ttl == 0 || ttl > max_ttl {
+        return Err(ApiError::BadRequest(format!("ttl_secs must be between 1 and {max_ttl}")));
+    }
+    if req.label.as_ref().is_some_and(|l| l.chars().count() > LABEL_MAX_CHARS) {
+        return Err(ApiError::BadRequest(format!("label must be at most {LABEL_MAX_CHARS} characters")));
+    }
+
+    let issued_at = Utc::now();
+    let claims = ShareClaims {
+        v: CLAIMS_VERSION,
+        share_id: Uuid::new_v4(),
+        dataset_id,
+        issued_at,
+        expires_at: issued_at + chrono::Duration::seconds(ttl as i64),
+    };
+    let token = sign(&state.data_dir, &claims)?;
+
+    let audit_entry_hash = state.store.append_audit(
+        Some(dataset_id),
+        "share_link_created",
+        &serde_json::json!({
+            "share_id": claims.share_id,
+            "expires_at": claims.expires_at,
+            "label": req.label,
+            "created_by": caller.key_id,
+        }),
+    )
+    .await?;
+
+    Ok(ShareLinkResponse {
+        share_id: claims.share_id,
+        dataset_id,
+        token,
+        expires_at: claims.expires_at,
+        endpoints: ["shards", "shards/export", "verification-report"]
+            .iter()
+            .map(|path| format!("/api/v1/datasets/{dataset_id}/{path}"))
+            .collect(),
+        audit_entry_hash,
+    })
+}
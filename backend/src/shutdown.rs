@@ -0,0 +1,73 @@
+//! Graceful shutdown.
+//!
+//! On SIGTERM or SIGINT the backend stops accepting connections and lets in-flight requests
+//! finish, while job workers stop claiming jobs and proving runs of synthetic datasets stop before
+//! their next shard: each writes a checkpoint after its last stored shard (see `checkpoint`) and
+//! its job goes back to the queue, so the next start resumes it rather than proving it again.
+//! Uploaded datasets can't resume (their spool dies with the process), so their runs keep
+//! proving in the hope of finishing.
+//!
+//! All of it is bounded by `SHUTDOWN_GRACE_SECS` (default 25, inside Kubernetes' default 30 s
+//! termination grace period); then the process exits whatever is still running. Long-lived
+//! responses (event streams) are cut at that point, and jobs still running are requeued on the
+//! next start as after a crash.
+
+use crate::state::AppState;
+use std::time::Duration;
+use tokio::time::Instant;
+
+const DEFAULT_GRACE_SECS: u64 = 25;
+
+/// How often the proving runs are checked while waiting for them to stop.
+const RUN_POLL: Duration = Duration::from_millis(100);
+
+fn grace_period() -> Duration {
+    let secs = std::env::var("SHUTDOWN_GRACE_SECS")
+        .ok()
+        .and_then(|v| v.trim().parse().ok())
+        .unwrap_or(DEFAULT_GRACE_SECS);
+    Duration::from_secs(secs)
+}
+
+/// Resolves on SIGTERM or SIGINT (Ctrl-C).
+pub async fn signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal as unix_signal, SignalKind};
+        match unix_signal(SignalKind::terminate()) {
+            Ok(mut sigterm) => {
+                tokio::select! {
+                    _ = sigterm.recv() => {}
+                    _ = tokio::signal::ctrl_c() => {}
+                }
+            }
+            Err(_) => {
+                let _ = tokio::signal::ctrl_c().await;
+            }
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = tokio::signal::ctrl_c().await;
+    }
+}
+
+/// Tell job workers and proving runs the instance is stopping; returns the deadline for the
+/// rest of the shutdown.
+pub fn begin(state: &AppState) -> Instant {
+    tracing::info!("shutting down: draining requests and checkpointing proving runs");
+    state.shutdown.cancel();
+    state.jobs_notify.notify_waiters();
+    Instant::now() + grace_period()
+}
+
+/// Wait until no proving run is active on this instance, or `deadline`.
+pub async fn wait_for_proving_runs(state: &AppState, deadline: Instant) {
+    while state.active_proving_runs() > 0 {
+        if Instant::now() >= deadline {
+            tracing::warn!(runs = state.active_proving_runs(), "proving runs still active at the shutdown deadline; they resume from their last checkpoint");
+            return;
+        }
+        tokio::time::sleep(RUN_POLL).await;
+    }
+}
@@ -1,80 +1,594 @@
+use crate::config::Config;
 use crate::errors::ApiError;
 use crate::db::Db;
-use std::path::PathBuf;
-use std::sync::Arc;
-use tokio::sync::OnceCell;
+use crate::admission::{estimate_proof_bytes, ProvingAdmission};
+use crate::dataset::EncryptedSpool;
+use crate::deadline::Cancellation;
+use crate::models::{ProofBlobAuditReport, ZkSelfTestReport};
+use crate::progress::ProgressHub;
+use crate::proof_files::ProofFiles;
+use crate::rate_limit::RateLimiter;
+use crate::salt::SaltSealer;
+use crate::upload::UploadStore;
+use crate::store::{LedgerStore, SqliteStore};
+use crate::stream::StreamStore;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tokio::sync::{Notify, OnceCell};
+use uuid::Uuid;
+use zk_proofs::aggregate::{setup_aggregate_keys, AggregateShape};
+use zk_proofs::query::setup_query_keys;
 use zk_proofs::constants::DEFAULT_SHARD_SIZE;
-use zk_proofs::groth16::{deserialize_pk, deserialize_vk, serialize_pk, serialize_vk, setup_keys};
+use zk_proofs::groth16::{deserialize_pk, deserialize_vk, serialize_pk, serialize_vk, vk_revision, vk_sha256_commitment};
+use zk_proofs::groth16::deserialize_pk_on;
+use zk_proofs::registry::{circuit_metrics, setup_keys_for, setup_keys_on_for};
+use zk_proofs::types::{AgeBuckets, CircuitRevision, Curve, FieldSet};
 
+use ark_bls12_381::Bls12_381;
 use ark_bn254::Bn254;
 use ark_groth16::{ProvingKey, VerifyingKey};
 use rand::rngs::OsRng;
+use ring::signature::Ed25519KeyPair;
+use rand::{RngCore, SeedableRng};
+use rand_chacha::ChaCha20Rng;
+use sha2::{Digest, Sha256};
 
 #[derive(Clone)]
 pub struct AppState {
     pub db: Db,
+    /// Datasets, shards, queries and the audit log.
+    pub store: Arc<dyn LedgerStore>,
     pub data_dir: PathBuf,
-    keys: Arc<OnceCell<ZkKeys>>,
+    /// Proof blob files of the SQLite ledger.
+    pub proof_files: ProofFiles,
+    /// In-progress chunked uploads (memory only).
+    pub uploads: UploadStore,
+    /// Open ingestion streams (memory only).
+    pub streams: StreamStore,
+    /// Wakes job workers when a job is enqueued.
+    pub jobs_notify: Arc<Notify>,
+    /// Encrypted record spools of uploaded datasets waiting for their proving job (memory only;
+    /// the spool key is lost on restart).
+    pub spools: Arc<tokio::sync::Mutex<HashMap<Uuid, Arc<EncryptedSpool>>>>,
+    /// Memory-aware gate every shard proof passes through.
+    pub proving_admission: Arc<ProvingAdmission>,
+    /// Progress of this instance's proving runs (memory only; see `progress`).
+    pub progress: Arc<ProgressHub>,
+    /// Cancellation tokens of the dataset proving runs this instance executes.
+    proving_runs: Arc<Mutex<HashMap<Uuid, Cancellation>>>,
+    /// Cancelled once the instance is shutting down (see `shutdown`).
+    pub shutdown: Cancellation,
+    /// Per-key token buckets (memory only; see `rate_limit`).
+    pub rate_limiter: Arc<RateLimiter>,
+    /// Seals shard master salts before they are stored.
+    pub salt_sealer: Arc<SaltSealer>,
+    /// The instance's Ed25519 key: signs exports, archives, manifest bucket counts and query
+    /// receipts (see `export::signing_key`).
+    pub signing_key: Arc<Ed25519KeyPair>,
+    /// Latest ZK self-test; proving waits until one has passed.
+    zk_self_test: Arc<Mutex<Option<ZkSelfTestReport>>>,
+    /// Latest proof blob integrity audit.
+    proof_blob_audit: Arc<Mutex<Option<ProofBlobAuditReport>>>,
+    /// Groth16 keys per shard size, field set, age bucket layout and dual-commitment variant, set up
+    /// lazily on first use.
+    keys: Arc<Mutex<KeyCells>>,
+    /// Groth16 keys of the dataset aggregate and query circuits per shape, set up lazily on first
+    /// use.
+    aggregate_keys: Arc<Mutex<ShapeKeyCells>>,
+    /// BLS12-381 keys per shard size, field set and age bucket layout (curve migrations), set up
+    /// lazily on first use.
+    bls_keys: Arc<Mutex<BlsKeyCells>>,
+    /// BN254 shard verifying keys by id (`vk_version`), every generation loaded so far: proofs
+    /// stay checkable against the key they were made with after its key files are replaced.
+    shard_vks: Arc<Mutex<HashMap<String, Arc<VerifyingKey<Bn254>>>>>,
+    /// Seed for deterministic key setup (ephemeral mode); `None` uses OS randomness.
+    key_seed: Option<u64>,
+    /// Startup configuration (see `config`).
+    pub config: Arc<Config>,
+    /// This instance's stable id (`jobs::instance_id`): which jobs it may claim and is running.
+    pub instance_id: Arc<str>,
 }
 
+type KeyCells = HashMap<(usize, FieldSet, AgeBuckets, bool), Arc<OnceCell<ZkKeys>>>;
+
+type BlsKeyCells = HashMap<(usize, FieldSet, AgeBuckets), Arc<OnceCell<BlsKeys>>>;
+
+type ShapeKeyCells = HashMap<(ShapeCircuit, AggregateShape), Arc<OnceCell<AggregateKeys>>>;
+
 #[derive(Clone)]
 pub struct ZkKeys {
     pub pk: Arc<ProvingKey<Bn254>>,
     pub vk: Arc<VerifyingKey<Bn254>>,
+    /// Hex SHA-256 of the serialized verifying key.
+    pub key_id: String,
+    /// Estimated peak memory of one proof with these keys.
+    pub proof_bytes: u64,
+    /// Circuit revision the keys were set up for; older keys keep proving their revision.
+    pub revision: CircuitRevision,
+    /// Whether the keys also prove a SHA-256 commitment (dual-commitment datasets).
+    pub sha256_commitment: bool,
+}
+
+/// Circuits over all of a dataset's shard inputs, keyed by `AggregateShape`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ShapeCircuit {
+    /// `zk_proofs::aggregate`.
+    Aggregate,
+    /// `zk_proofs::query`.
+    Query,
+}
+
+impl ShapeCircuit {
+    pub fn name(self) -> &'static str {
+        match self {
+            ShapeCircuit::Aggregate => "aggregate",
+            ShapeCircuit::Query => "query",
+        }
+    }
+}
+
+/// Keys of the dataset aggregate or query circuit for one `AggregateShape`.
+#[derive(Clone)]
+pub struct AggregateKeys {
+    pub pk: Arc<ProvingKey<Bn254>>,
+    pub vk: Arc<VerifyingKey<Bn254>>,
+    /// Hex SHA-256 of the serialized verifying key.
+    pub key_id: String,
+    /// Estimated peak memory of one proof with these keys.
+    pub proof_bytes: u64,
+}
+
+/// Keys of the latest shard circuit on BLS12-381 for one shard size, field set and bucket layout.
+#[derive(Clone)]
+pub struct BlsKeys {
+    pub pk: Arc<ProvingKey<Bls12_381>>,
+    pub vk: Arc<VerifyingKey<Bls12_381>>,
+    /// Hex SHA-256 of the serialized verifying key.
+    pub key_id: String,
+    /// Estimated peak memory of one proof with these keys.
+    pub proof_bytes: u64,
 }
 
 impl AppState {
-    pub fn new(db: Db, data_dir: PathBuf) -> Self {
+    pub fn new(db: Db, data_dir: PathBuf, salt_sealer: SaltSealer, signing_key: Ed25519KeyPair) -> Self {
+        let proof_files = ProofFiles::new(&data_dir);
         Self {
+            store: Arc::new(SqliteStore::new(db.clone(), proof_files.clone())),
+            proof_files,
             db,
             data_dir,
-            keys: Arc::new(OnceCell::new()),
+            uploads: UploadStore::default(),
+            streams: StreamStore::default(),
+            jobs_notify: Arc::new(Notify::new()),
+            spools: Arc::new(tokio::sync::Mutex::new(HashMap::new())),
+            proving_admission: Arc::new(ProvingAdmission::default()),
+            progress: Arc::new(ProgressHub::default()),
+            proving_runs: Arc::new(Mutex::new(HashMap::new())),
+            shutdown: Cancellation::none(),
+            rate_limiter: Arc::new(RateLimiter::default()),
+            salt_sealer: Arc::new(salt_sealer),
+            signing_key: Arc::new(signing_key),
+            zk_self_test: Arc::new(Mutex::new(None)),
+            proof_blob_audit: Arc::new(Mutex::new(None)),
+            keys: Arc::new(Mutex::new(HashMap::new())),
+            aggregate_keys: Arc::new(Mutex::new(HashMap::new())),
+            bls_keys: Arc::new(Mutex::new(HashMap::new())),
+            shard_vks: Arc::new(Mutex::new(HashMap::new())),
+            key_seed: None,
+            config: Arc::new(Config::default()),
+            instance_id: Arc::from("local"),
         }
     }
 
-    /// Ensure Groth16 keys exist on disk and in memory.
+    pub fn with_config(mut self, config: Config) -> Self {
+        self.config = Arc::new(config);
+        self
+    }
+
+    /// Keep the ledger in `store` instead of the SQLite pool (see `DATABASE_URL`).
+    pub fn with_store(mut self, store: Arc<dyn LedgerStore>) -> Self {
+        self.store = store;
+        self
+    }
+
+    pub fn with_instance_id(mut self, instance_id: String) -> Self {
+        self.instance_id = Arc::from(instance_id);
+        self
+    }
+
+    /// Set up missing keys from `seed` instead of OS randomness (ephemeral mode only).
+    pub fn with_key_seed(mut self, seed: u64) -> Self {
+        self.key_seed = Some(seed);
+        self
+    }
+
+    /// The BN254 shard verifying key generation `key_id`, if this ledger has it: a live key or an
+    /// archived one (see `archived_vk`), kept in memory once loaded.
+    pub fn shard_vk(&self, key_id: &str) -> Result<Option<Arc<VerifyingKey<Bn254>>>, ApiError> {
+        if let Some(vk) = self.shard_vks.lock().map_err(|_| ApiError::Internal)?.get(key_id) {
+            return Ok(Some(vk.clone()));
+        }
+        let Some(vk_bytes) = archived_vk(&self.data_dir.join("keys"), key_id) else {
+            return Ok(None);
+        };
+        let vk = Arc::new(deserialize_vk(&vk_bytes).map_err(|_| ApiError::Internal)?);
+        self.shard_vks
+            .lock()
+            .map_err(|_| ApiError::Internal)?
+            .insert(key_id.to_string(), vk.clone());
+        Ok(Some(vk))
+    }
+
+    pub fn zk_self_test(&self) -> Option<ZkSelfTestReport> {
+        self.zk_self_test.lock().ok().and_then(|r| r.clone())
+    }
+
+    pub fn set_zk_self_test(&self, report: ZkSelfTestReport) {
+        if let Ok(mut current) = self.zk_self_test.lock() {
+            *current = Some(report);
+        }
+    }
+
+    pub fn proof_blob_audit(&self) -> Option<ProofBlobAuditReport> {
+        self.proof_blob_audit.lock().ok().and_then(|r| r.clone())
+    }
+
+    pub fn set_proof_blob_audit(&self, report: ProofBlobAuditReport) {
+        if let Ok(mut current) = self.proof_blob_audit.lock() {
+            *current = Some(report);
+        }
+    }
+
+    /// Register a proving run of `dataset_id` and return its cancellation token.
+    pub fn start_proving_run(&self, dataset_id: Uuid) -> Cancellation {
+        let token = Cancellation::none();
+        if let Ok(mut runs) = self.proving_runs.lock() {
+            runs.insert(dataset_id, token.clone());
+        }
+        token
+    }
+
+    pub fn end_proving_run(&self, dataset_id: Uuid) {
+        if let Ok(mut runs) = self.proving_runs.lock() {
+            runs.remove(&dataset_id);
+        }
+    }
+
+    /// Proving runs this instance is executing.
+    pub fn active_proving_runs(&self) -> usize {
+        self.proving_runs.lock().map(|runs| runs.len()).unwrap_or_default()
+    }
+
+    /// Cancel `dataset_id`'s proving run if this instance executes it.
+    pub fn cancel_proving_run(&self, dataset_id: Uuid) {
+        if let Ok(runs) = self.proving_runs.lock()
+            && let Some(token) = runs.get(&dataset_id)
+        {
+            token.cancel();
+        }
+    }
+
+    /// Whether the latest ZK self-test ran and passed.
+    pub fn zk_ready(&self) -> bool {
+        self.zk_self_test().is_some_and(|r| r.ok)
+    }
+
+    /// Key sets loaded so far, by shard size, field set and age bucket layout.
+    pub fn loaded_keys(&self) -> Vec<(usize, FieldSet, AgeBuckets, ZkKeys)> {
+        let Ok(keys) = self.keys.lock() else { return Vec::new() };
+        let mut loaded: Vec<(usize, FieldSet, AgeBuckets, ZkKeys)> = keys
+            .iter()
+            .filter_map(|((size, field_set, buckets, _), cell)| cell.get().map(|k| (*size, *field_set, buckets.clone(), k.clone())))
+            .collect();
+        loaded.sort_by_key(|(size, field_set, buckets, keys)| (*size, field_set.name(), buckets.id(), keys.sha256_commitment));
+        loaded
+    }
+
+    /// Ensure Groth16 keys for `shard_size`, `field_set` and the age bucket layout `buckets` (the
+    /// dual-commitment variant with `sha256_commitment`) exist on disk and in memory.
     ///
-    /// This runs the trusted setup (prototype) on first use.
-    pub async fn ensure_keys(&self) -> Result<ZkKeys, ApiError> {
+    /// This runs the trusted setup (prototype) on first use of each combination.
+    pub async fn ensure_keys_for(
+        &self,
+        shard_size: usize,
+        field_set: FieldSet,
+        buckets: &AgeBuckets,
+        sha256_commitment: bool,
+    ) -> Result<ZkKeys, ApiError> {
         let data_dir = self.data_dir.clone();
+        let key_seed = self.key_seed;
+        let buckets = buckets.clone();
+        let cell = self
+            .keys
+            .lock()
+            .map_err(|_| ApiError::Internal)?
+            .entry((shard_size, field_set, buckets.clone(), sha256_commitment))
+            .or_default()
+            .clone();
 
-        self.keys
-            .get_or_try_init(|| async move {
-                tokio::task::spawn_blocking(move || {
-                    let keys_dir = data_dir.join("keys");
-                    std::fs::create_dir_all(&keys_dir).map_err(|_| ApiError::Internal)?;
+        cell.get_or_try_init(|| async move {
+            tokio::task::spawn_blocking(move || {
+                let keys_dir = data_dir.join("keys");
+                std::fs::create_dir_all(&keys_dir).map_err(|_| ApiError::Internal)?;
 
-                    let pk_path = keys_dir.join("groth16_pk.bin");
-                    let vk_path = keys_dir.join("groth16_vk.bin");
+                let (pk_path, vk_path) = key_paths(&keys_dir, shard_size, field_set, &buckets, sha256_commitment);
 
-                    if pk_path.exists() && vk_path.exists() {
-                        let pk_bytes = std::fs::read(&pk_path).map_err(|_| ApiError::Internal)?;
-                        let vk_bytes = std::fs::read(&vk_path).map_err(|_| ApiError::Internal)?;
+                if pk_path.exists() && vk_path.exists() {
+                    let pk_bytes = std::fs::read(&pk_path).map_err(|_| ApiError::Internal)?;
+                    let vk_bytes = std::fs::read(&vk_path).map_err(|_| ApiError::Internal)?;
 
-                        let pk = deserialize_pk(&pk_bytes).map_err(|_| ApiError::Internal)?;
-                        let vk = deserialize_vk(&vk_bytes).map_err(|_| ApiError::Internal)?;
+                    let pk = deserialize_pk(&pk_bytes).map_err(|_| ApiError::Internal)?;
+                    let vk = deserialize_vk(&vk_bytes).map_err(|_| ApiError::Internal)?;
+                    let key_id = hex::encode(Sha256::digest(&vk_bytes));
+                    archive_vk(&keys_dir, &key_id, &vk_bytes)?;
 
-                        return Ok::<ZkKeys, ApiError>(ZkKeys { pk: Arc::new(pk), vk: Arc::new(vk) });
+                    return Ok::<ZkKeys, ApiError>(ZkKeys {
+                        proof_bytes: estimate_proof_bytes(circuit_metrics(&pk)),
+                        revision: vk_revision(&vk, field_set, buckets.num_buckets()),
+                        sha256_commitment: vk_sha256_commitment(&vk, field_set, buckets.num_buckets()),
+                        pk: Arc::new(pk),
+                        vk: Arc::new(vk),
+                        key_id,
+                    });
+                }
+
+                // Trusted setup randomness (prototype).
+                //
+                // IMPORTANT: In production, finalize the keys from a ceremony (`backend ceremony`).
+                let (pk, vk) = match key_seed {
+                    Some(seed) => {
+                        let mut label = format!("phl-ephemeral-keys:{seed}:{shard_size}:{}", field_set.name());
+                        if !buckets.is_default() {
+                            label.push_str(&format!(":buckets={}", buckets.id()));
+                        }
+                        if sha256_commitment {
+                            label.push_str(":sha256");
+                        }
+                        let mut rng = ChaCha20Rng::from_seed(Sha256::digest(label.as_bytes()).into());
+                        setup_keys_for(shard_size, field_set, &buckets, sha256_commitment, &mut rng)
                     }
+                    None => setup_keys_for(shard_size, field_set, &buckets, sha256_commitment, &mut OsRng),
+                }
+                .map_err(|_| ApiError::Internal)?;
+
+                let pk_bytes = serialize_pk(&pk).map_err(|_| ApiError::Internal)?;
+                let vk_bytes = serialize_vk(&vk).map_err(|_| ApiError::Internal)?;
 
-                    // Trusted setup randomness (prototype).
-                    //
-                    // IMPORTANT: In production, use MPC setup or a transparent proof system.
-                    let mut rng = OsRng;
-                    let (pk, vk) = setup_keys::<DEFAULT_SHARD_SIZE>(&mut rng).map_err(|_| ApiError::Internal)?;
+                let key_id = hex::encode(Sha256::digest(&vk_bytes));
+
+                std::fs::write(&pk_path, pk_bytes).map_err(|_| ApiError::Internal)?;
+                std::fs::write(&vk_path, &vk_bytes).map_err(|_| ApiError::Internal)?;
+                archive_vk(&keys_dir, &key_id, &vk_bytes)?;
+
+                Ok::<ZkKeys, ApiError>(ZkKeys {
+                    proof_bytes: estimate_proof_bytes(circuit_metrics(&pk)),
+                    revision: vk_revision(&vk, field_set, buckets.num_buckets()),
+                    sha256_commitment,
+                    pk: Arc::new(pk),
+                    vk: Arc::new(vk),
+                    key_id,
+                })
+            })
+            .await
+            .map_err(|_| ApiError::Internal)?
+        })
+        .await
+        .cloned()
+    }
+
+    /// Ensure keys of the dataset aggregate circuit for `shape` exist on disk and in memory,
+    /// running its (prototype) trusted setup on first use.
+    pub async fn ensure_aggregate_keys(&self, shape: AggregateShape) -> Result<AggregateKeys, ApiError> {
+        self.ensure_shape_keys(ShapeCircuit::Aggregate, shape).await
+    }
+
+    /// Same as `ensure_aggregate_keys`, for the query circuit.
+    pub async fn ensure_query_keys(&self, shape: AggregateShape) -> Result<AggregateKeys, ApiError> {
+        self.ensure_shape_keys(ShapeCircuit::Query, shape).await
+    }
+
+    async fn ensure_shape_keys(&self, circuit: ShapeCircuit, shape: AggregateShape) -> Result<AggregateKeys, ApiError> {
+        let data_dir = self.data_dir.clone();
+        let key_seed = self.key_seed;
+        let cell = self
+            .aggregate_keys
+            .lock()
+            .map_err(|_| ApiError::Internal)?
+            .entry((circuit, shape))
+            .or_default()
+            .clone();
+
+        cell.get_or_try_init(|| async move {
+            tokio::task::spawn_blocking(move || {
+                let keys_dir = data_dir.join("keys");
+                std::fs::create_dir_all(&keys_dir).map_err(|_| ApiError::Internal)?;
+                let (pk_path, vk_path) = shape_key_paths(&keys_dir, circuit, shape);
+
+                let (pk, vk_bytes) = if pk_path.exists() && vk_path.exists() {
+                    let pk_bytes = std::fs::read(&pk_path).map_err(|_| ApiError::Internal)?;
+                    let vk_bytes = std::fs::read(&vk_path).map_err(|_| ApiError::Internal)?;
+                    (deserialize_pk(&pk_bytes).map_err(|_| ApiError::Internal)?, vk_bytes)
+                } else {
+                    let (mut seeded, mut os_rng) = (None, OsRng);
+                    let mut rng: &mut dyn RngCore = match key_seed {
+                        Some(seed) => {
+                            let label = format!(
+                                "phl-ephemeral-{}-keys:{seed}:{}:{}:{}",
+                                circuit.name(),
+                                shape.num_shards,
+                                shape.input_len,
+                                shape.num_summed
+                            );
+                            seeded.insert(ChaCha20Rng::from_seed(Sha256::digest(label.as_bytes()).into()))
+                        }
+                        None => &mut os_rng,
+                    };
+                    let (pk, vk) = match circuit {
+                        ShapeCircuit::Aggregate => setup_aggregate_keys(&mut rng, shape),
+                        ShapeCircuit::Query => setup_query_keys(&mut rng, shape),
+                    }
+                    .map_err(|_| ApiError::Internal)?;
 
                     let pk_bytes = serialize_pk(&pk).map_err(|_| ApiError::Internal)?;
                     let vk_bytes = serialize_vk(&vk).map_err(|_| ApiError::Internal)?;
+                    std::fs::write(&pk_path, pk_bytes).map_err(|_| ApiError::Internal)?;
+                    std::fs::write(&vk_path, &vk_bytes).map_err(|_| ApiError::Internal)?;
+                    (pk, vk_bytes)
+                };
+
+                Ok::<AggregateKeys, ApiError>(AggregateKeys {
+                    proof_bytes: estimate_proof_bytes(circuit_metrics(&pk)),
+                    vk: Arc::new(pk.vk.clone()),
+                    pk: Arc::new(pk),
+                    key_id: hex::encode(Sha256::digest(&vk_bytes)),
+                })
+            })
+            .await
+            .map_err(|_| ApiError::Internal)?
+        })
+        .await
+        .cloned()
+    }
 
+    /// Ensure BLS12-381 keys of the latest shard circuit for `shard_size`, `field_set` and
+    /// `buckets` exist on disk and in memory, running their (prototype) trusted setup on first use.
+    pub async fn ensure_bls_keys(&self, shard_size: usize, field_set: FieldSet, buckets: &AgeBuckets) -> Result<BlsKeys, ApiError> {
+        let data_dir = self.data_dir.clone();
+        let key_seed = self.key_seed;
+        let buckets = buckets.clone();
+        let cell = self
+            .bls_keys
+            .lock()
+            .map_err(|_| ApiError::Internal)?
+            .entry((shard_size, field_set, buckets.clone()))
+            .or_default()
+            .clone();
+
+        cell.get_or_try_init(|| async move {
+            tokio::task::spawn_blocking(move || {
+                let keys_dir = data_dir.join("keys");
+                std::fs::create_dir_all(&keys_dir).map_err(|_| ApiError::Internal)?;
+                let (pk_path, vk_path) = curve_key_paths(&keys_dir, Curve::Bls12_381, shard_size, field_set, &buckets);
+
+                let (pk, vk_bytes) = if pk_path.exists() && vk_path.exists() {
+                    let pk_bytes = std::fs::read(&pk_path).map_err(|_| ApiError::Internal)?;
+                    let vk_bytes = std::fs::read(&vk_path).map_err(|_| ApiError::Internal)?;
+                    (deserialize_pk_on::<Bls12_381>(&pk_bytes).map_err(|_| ApiError::Internal)?, vk_bytes)
+                } else {
+                    let (pk, vk) = match key_seed {
+                        Some(seed) => {
+                            let mut label = format!(
+                                "phl-ephemeral-keys:{seed}:{shard_size}:{}:{}",
+                                field_set.name(),
+                                Curve::Bls12_381.name()
+                            );
+                            if !buckets.is_default() {
+                                label.push_str(&format!(":buckets={}", buckets.id()));
+                            }
+                            let mut rng = ChaCha20Rng::from_seed(Sha256::digest(label.as_bytes()).into());
+                            setup_keys_on_for::<Bls12_381>(shard_size, field_set, &buckets, &mut rng)
+                        }
+                        None => setup_keys_on_for::<Bls12_381>(shard_size, field_set, &buckets, &mut OsRng),
+                    }
+                    .map_err(|_| ApiError::Internal)?;
+
+                    let pk_bytes = serialize_pk(&pk).map_err(|_| ApiError::Internal)?;
+                    let vk_bytes = serialize_vk(&vk).map_err(|_| ApiError::Internal)?;
                     std::fs::write(&pk_path, pk_bytes).map_err(|_| ApiError::Internal)?;
-                    std::fs::write(&vk_path, vk_bytes).map_err(|_| ApiError::Internal)?;
+                    std::fs::write(&vk_path, &vk_bytes).map_err(|_| ApiError::Internal)?;
+                    (pk, vk_bytes)
+                };
 
-                    Ok::<ZkKeys, ApiError>(ZkKeys { pk: Arc::new(pk), vk: Arc::new(vk) })
+                Ok::<BlsKeys, ApiError>(BlsKeys {
+                    proof_bytes: estimate_proof_bytes(circuit_metrics(&pk)),
+                    vk: Arc::new(pk.vk.clone()),
+                    pk: Arc::new(pk),
+                    key_id: hex::encode(Sha256::digest(&vk_bytes)),
                 })
-                .await
-                .map_err(|_| ApiError::Internal)?
             })
             .await
-            .cloned()
+            .map_err(|_| ApiError::Internal)?
+        })
+        .await
+        .cloned()
+    }
+}
+
+/// Location of the archived copy of the BN254 shard verifying key `key_id`.
+///
+/// Every shard key is archived by id when it is loaded or set up, so proofs made with it stay
+/// verifiable after its key files are replaced by keys of a newer circuit revision (see
+/// `circuit_migration`). Archived keys sit next to the live ones, so backups include them.
+pub fn archived_vk_path(keys_dir: &Path, key_id: &str) -> PathBuf {
+    keys_dir.join(format!("groth16_vk_archive_{key_id}.bin"))
+}
+
+fn archive_vk(keys_dir: &Path, key_id: &str, vk_bytes: &[u8]) -> Result<(), ApiError> {
+    let path = archived_vk_path(keys_dir, key_id);
+    if !path.exists() {
+        std::fs::write(path, vk_bytes).map_err(|_| ApiError::Internal)?;
+    }
+    Ok(())
+}
+
+/// The archived shard verifying key `key_id` (a hex SHA-256), if this ledger has it.
+pub fn archived_vk(keys_dir: &Path, key_id: &str) -> Option<Vec<u8>> {
+    if key_id.len() != 64 || !key_id.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+    std::fs::read(archived_vk_path(keys_dir, key_id)).ok()
+}
+
+/// Key file locations of the shard circuit on a curve other than BN254 (latest revision only):
+/// always suffixed with the shard size, field set and curve, after the bucket layout's suffix
+/// (see `key_paths`).
+pub fn curve_key_paths(keys_dir: &Path, curve: Curve, shard_size: usize, field_set: FieldSet, buckets: &AgeBuckets) -> (PathBuf, PathBuf) {
+    let suffix = format!("_n{shard_size}_{}{}_{}", field_set.name(), buckets_suffix(buckets), curve.name());
+    (
+        keys_dir.join(format!("groth16_pk{suffix}.bin")),
+        keys_dir.join(format!("groth16_vk{suffix}.bin")),
+    )
+}
+
+/// Key file locations of the dataset aggregate or query circuit for `shape`.
+pub fn shape_key_paths(keys_dir: &Path, circuit: ShapeCircuit, shape: AggregateShape) -> (PathBuf, PathBuf) {
+    let suffix = format!("_s{}_i{}_t{}", shape.num_shards, shape.input_len, shape.num_summed);
+    (
+        keys_dir.join(format!("groth16_{}_pk{suffix}.bin", circuit.name())),
+        keys_dir.join(format!("groth16_{}_vk{suffix}.bin", circuit.name())),
+    )
+}
+
+/// Key file locations for a shard size, field set, age bucket layout and dual-commitment variant.
+/// Glucose-only keys of the default size and layout keep the original unsuffixed names so existing
+/// deployments reuse their keys; other layouts add their bucket lower bounds (`_b18-65`).
+pub fn key_paths(
+    keys_dir: &Path,
+    shard_size: usize,
+    field_set: FieldSet,
+    buckets: &AgeBuckets,
+    sha256_commitment: bool,
+) -> (PathBuf, PathBuf) {
+    let mut suffix = match (shard_size, field_set, buckets.is_default()) {
+        (DEFAULT_SHARD_SIZE, FieldSet::Glucose, true) => String::new(),
+        (_, FieldSet::Glucose, _) => format!("_n{shard_size}"),
+        (_, other, _) => format!("_n{shard_size}_{}", other.name()),
+    };
+    suffix.push_str(&buckets_suffix(buckets));
+    if sha256_commitment {
+        suffix.push_str("_sha256");
+    }
+    (
+        keys_dir.join(format!("groth16_pk{suffix}.bin")),
+        keys_dir.join(format!("groth16_vk{suffix}.bin")),
+    )
+}
+
+/// Key file suffix of a non-default age bucket layout: its lower bounds past the first.
+fn buckets_suffix(buckets: &AgeBuckets) -> String {
+    if buckets.is_default() {
+        return String::new();
     }
+    format!("_b{}", buckets.id().replace(',', "-"))
 }
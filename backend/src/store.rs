@@ -0,0 +1,1384 @@
+//! Storage abstraction of the ledger's persistence layer.
+//!
+//! `LedgerStore` covers the ledger proper: datasets, their shards and the proof blobs those refer
+//! to, queries and the audit log. Handlers and background tasks reach it through
+//! `AppState::store`, so another backend (an embedded store on edge prover nodes, an in-memory
+//! fake in tests) only has to implement this trait. `SqliteStore` is the default implementation
+//! over `db`; `PgStore` keeps the ledger in Postgres (`pg`) when `DATABASE_URL` points there.
+//!
+//! The job queue lives here too, so with a shared store every instance works off one queue, and
+//! so does the operational state derived from the ledger (aggregate proofs, verification reports,
+//! anomaly analyses, curve migrations and their shards, key usage, log anchors): whichever
+//! instance produced it, every instance serves it.
+
+use crate::db::{
+    self, AggregateProofRow, AnomalyAnalysisRow, ApiKeyRow, AuditRow, BucketTotals, CellDisclosureRow, CurveMigrationRow, CurveShardRow, DatasetAclRow, DatasetQualityRow,
+    DatasetReverificationRow, DatasetRow, Db, JobRow, KeyUsageRow, LogAnchorRow, LogLeafRow, NewDataset, PrivacyBudgetRow, QueryResult, QueryRow, NewApiKey, QuerySpec,
+    ShardFailureRow, ShardListRow, TombstoneRow,
+};
+use crate::errors::ApiError;
+use crate::pg::{self, PgDb};
+use crate::proof_files::ProofFiles;
+use crate::quality::{IngestQuality, ShardQuality};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use std::ops::Range;
+use uuid::Uuid;
+use zk_proofs::types::{AgeBuckets, Curve, FieldSet, ShardStats};
+
+#[async_trait]
+pub trait LedgerStore: Send + Sync {
+    // --- Datasets ---
+
+    async fn insert_dataset(&self, dataset: &NewDataset<'_>) -> Result<(), ApiError>;
+
+    async fn get_dataset(&self, dataset_id: Uuid) -> Result<Option<DatasetRow>, ApiError>;
+
+    async fn list_dataset_ids(&self) -> Result<Vec<Uuid>, ApiError>;
+
+    async fn dataset_owner(&self, dataset_id: Uuid) -> Result<Option<String>, ApiError>;
+
+    /// No-op if the dataset's generation was cancelled.
+    async fn set_dataset_ready(&self, dataset_id: Uuid, commitment_hex: &str) -> Result<(), ApiError>;
+
+    /// Grow a ready dataset to `dataset_size` records with a new commitment; `false` if it is not
+    /// ready.
+    async fn extend_dataset(&self, dataset_id: Uuid, dataset_size: u64, commitment_hex: &str) -> Result<bool, ApiError>;
+
+    /// No-op if the dataset's generation was cancelled.
+    async fn set_dataset_failed(&self, dataset_id: Uuid, error: &str) -> Result<(), ApiError>;
+
+    /// `false` if the dataset is not generating.
+    async fn cancel_dataset(&self, dataset_id: Uuid) -> Result<bool, ApiError>;
+
+    async fn set_dataset_imported(
+        &self,
+        dataset_id: Uuid,
+        commitment_hex: &str,
+        vk_b64: &str,
+        imported_from: &str,
+    ) -> Result<(), ApiError>;
+
+    /// Attach a federated site's verifying key to an empty dataset.
+    async fn set_dataset_federated(&self, dataset_id: Uuid, vk_b64: &str, imported_from: &str) -> Result<(), ApiError>;
+
+    async fn get_dataset_external_vk(&self, dataset_id: Uuid) -> Result<Option<String>, ApiError>;
+
+    /// Datasets `key_id` owns and the records across those that have not failed.
+    async fn tenant_datasets(&self, key_id: &str) -> Result<(u64, u64), ApiError>;
+
+    async fn set_dataset_ingest_quality(&self, dataset_id: Uuid, quality: &IngestQuality) -> Result<(), ApiError>;
+
+    async fn set_dataset_manifest(&self, dataset_id: Uuid, manifest: &serde_json::Value) -> Result<(), ApiError>;
+
+    async fn get_dataset_manifest(&self, dataset_id: Uuid) -> Result<Option<serde_json::Value>, ApiError>;
+
+    /// `false` if the dataset was already frozen.
+    async fn freeze_dataset(&self, dataset_id: Uuid, frozen_by: &str) -> Result<bool, ApiError>;
+
+    /// `false` if the dataset was not frozen.
+    async fn unfreeze_dataset(&self, dataset_id: Uuid) -> Result<bool, ApiError>;
+
+    /// Delete the dataset's ledger rows and leave a tombstone; `None` if there is no such dataset.
+    async fn delete_dataset(&self, dataset_id: Uuid, deleted_by: &str, reason: &str) -> Result<Option<TombstoneRow>, ApiError>;
+
+    async fn dataset_tombstone(&self, dataset_id: Uuid) -> Result<Option<TombstoneRow>, ApiError>;
+
+    async fn expired_datasets(&self, cutoff: DateTime<Utc>) -> Result<Vec<Uuid>, ApiError>;
+
+    async fn dataset_acl(&self, dataset_id: Uuid) -> Result<DatasetAclRow, ApiError>;
+
+    async fn grant_dataset_access(
+        &self,
+        dataset_id: Uuid,
+        grantee_kind: &str,
+        grantee: &str,
+        granted_by: &str,
+    ) -> Result<bool, ApiError>;
+
+    async fn revoke_dataset_access(&self, dataset_id: Uuid, grantee_kind: &str, grantee: &str) -> Result<bool, ApiError>;
+
+    /// Store an issued API key, returning its creation time.
+    async fn insert_api_key(&self, key: &NewApiKey<'_>) -> Result<DateTime<Utc>, ApiError>;
+
+    async fn api_key_by_hash(&self, key_hash: &str) -> Result<Option<ApiKeyRow>, ApiError>;
+
+    async fn list_api_keys(&self) -> Result<Vec<ApiKeyRow>, ApiError>;
+
+    async fn revoke_api_key(&self, key_id: &str) -> Result<bool, ApiError>;
+
+    /// Count one more query for `key_id` on `day` (UTC, `YYYY-MM-DD`) unless it already has
+    /// `max`; `false` if the quota is used up.
+    async fn take_daily_query(&self, key_id: &str, day: &str, max: u64) -> Result<bool, ApiError>;
+
+    /// Give back a query counted by `take_daily_query` that wasn't created.
+    async fn refund_daily_query(&self, key_id: &str, day: &str) -> Result<(), ApiError>;
+
+    async fn daily_queries(&self, key_id: &str, day: &str) -> Result<u64, ApiError>;
+
+    async fn dataset_quality(&self, dataset_id: Uuid, buckets: &AgeBuckets) -> Result<DatasetQualityRow, ApiError>;
+
+    /// Summed stats and record count of the shards in `shards`.
+    async fn dataset_totals(
+        &self,
+        dataset_id: Uuid,
+        shards: Range<u64>,
+        field_set: FieldSet,
+        buckets: &AgeBuckets,
+    ) -> Result<(ShardStats, u64), ApiError>;
+
+    /// Totals of one age bucket and field over the shards in `shards`.
+    async fn aggregate_for_bucket(
+        &self,
+        dataset_id: Uuid,
+        shards: Range<u64>,
+        bucket_index: usize,
+        field_index: usize,
+        buckets: &AgeBuckets,
+    ) -> Result<BucketTotals, ApiError>;
+
+    // --- Shards ---
+
+    async fn insert_shard(
+        &self,
+        dataset_id: Uuid,
+        shard_index: u64,
+        shard_commitment_hex: &str,
+        stats: &ShardStats,
+        proof_b64: &str,
+        verified: bool,
+    ) -> Result<(), ApiError>;
+
+    async fn set_shard_quality(&self, dataset_id: Uuid, shard_index: u64, quality: &ShardQuality) -> Result<(), ApiError>;
+
+    async fn set_shard_sealed_master_salt(&self, dataset_id: Uuid, shard_index: u64, sealed: &[u8]) -> Result<(), ApiError>;
+
+    /// Record the id of the verifying key a stored shard's proof was checked with.
+    async fn set_shard_vk_version(&self, dataset_id: Uuid, shard_index: u64, vk_version: &str) -> Result<(), ApiError>;
+
+    /// `(shard_index, vk_version)` of the shards in `index_range` that have one.
+    async fn shard_vk_versions(&self, dataset_id: Uuid, index_range: Range<u64>) -> Result<Vec<(u64, String)>, ApiError>;
+
+    /// Record that the proofs of `shard_indices` verified just now against the key `vk_hash`.
+    async fn set_shards_verified(&self, dataset_id: Uuid, shard_indices: &[u64], vk_hash: &str) -> Result<(), ApiError>;
+
+    /// `(shard_index, verified_at, verifier_vk_hash)` of the shards in `index_range` verified since
+    /// they were stored.
+    async fn shard_verifications(&self, dataset_id: Uuid, index_range: Range<u64>) -> Result<Vec<(u64, DateTime<Utc>, String)>, ApiError>;
+
+    /// `(dataset_id, shard_index)` of up to `limit` random shards of ready datasets.
+    async fn sample_shards(&self, limit: u64) -> Result<Vec<(Uuid, u64)>, ApiError>;
+
+    async fn count_shards_done(&self, dataset_id: Uuid) -> Result<u64, ApiError>;
+
+    /// Shards with an index in `index_range`, ordered by index, paged by `offset` and `limit`.
+    async fn list_shards(
+        &self,
+        dataset_id: Uuid,
+        index_range: Range<u64>,
+        offset: u64,
+        limit: u64,
+        include_proof: bool,
+    ) -> Result<Vec<ShardListRow>, ApiError>;
+
+    async fn record_shard_failure(&self, dataset_id: Uuid, shard_index: u64, error_class: &str, error: &str) -> Result<(), ApiError>;
+
+    async fn list_shard_failures(&self, dataset_id: Uuid) -> Result<Vec<ShardFailureRow>, ApiError>;
+
+    // --- Proof blobs ---
+
+    /// Up to `limit` proof blobs `(hash, proof_b64)` with a hash greater than `after`, in hash order.
+    async fn list_proof_blobs(&self, after: &str, limit: u64) -> Result<Vec<(String, String)>, ApiError>;
+
+    /// Shards whose proof is the blob `hash`.
+    async fn shards_with_proof(&self, hash: &str) -> Result<Vec<(Uuid, u64)>, ApiError>;
+
+    /// Delete blobs no shard refers to; returns how many.
+    async fn delete_orphan_proof_blobs(&self) -> Result<u64, ApiError>;
+
+    /// Stored blobs, their total base64 size, and how many shards refer to a blob.
+    async fn proof_blob_stats(&self) -> Result<(u64, u64, u64), ApiError>;
+
+    // --- Queries ---
+
+    async fn insert_query(&self, query_id: Uuid, dataset_id: Uuid, spec: &QuerySpec<'_>, result: &QueryResult) -> Result<(), ApiError>;
+
+    async fn insert_unreleased_query(&self, query_id: Uuid, dataset_id: Uuid, spec: &QuerySpec<'_>, status: &str) -> Result<(), ApiError>;
+
+    async fn get_query(&self, query_id: Uuid) -> Result<Option<QueryRow>, ApiError>;
+    async fn list_dataset_queries(&self, dataset_id: Uuid, offset: u64, limit: u64) -> Result<Vec<QueryRow>, ApiError>;
+
+    /// Release a query in status `from_status` with `result`; `false` if it was in another status.
+    async fn release_query(
+        &self,
+        query_id: Uuid,
+        from_status: &str,
+        result: &QueryResult,
+        decided_by: Option<&str>,
+    ) -> Result<bool, ApiError>;
+
+    /// Move a query from status `from` to `to`; `false` if it was in another status.
+    async fn set_query_status(&self, query_id: Uuid, from: &str, to: &str) -> Result<bool, ApiError>;
+
+    async fn fail_query(&self, query_id: Uuid, error: &str) -> Result<(), ApiError>;
+
+    async fn reject_pending_query(&self, query_id: Uuid, decided_by: &str) -> Result<bool, ApiError>;
+
+    /// Release keys (`db::release_key`) of the queries on a dataset released since `since`.
+    async fn release_keys_since(&self, dataset_id: Uuid, since: DateTime<Utc>) -> Result<Vec<String>, ApiError>;
+
+    async fn insert_released_cells(&self, query_id: Uuid, dataset_id: Uuid, cells: &[(usize, &str)]) -> Result<(), ApiError>;
+
+    /// Spend `epsilon` and `delta` of a dataset's privacy budget unless that would take the
+    /// epsilon spent past `budget`; `None` if refused.
+    async fn spend_privacy_budget(
+        &self,
+        dataset_id: Uuid,
+        epsilon: f64,
+        delta: f64,
+        budget: Option<f64>,
+    ) -> Result<Option<PrivacyBudgetRow>, ApiError>;
+
+    async fn privacy_budget(&self, dataset_id: Uuid) -> Result<PrivacyBudgetRow, ApiError>;
+
+    async fn cell_disclosure(&self, dataset_id: Uuid) -> Result<Vec<CellDisclosureRow>, ApiError>;
+
+    // --- Jobs ---
+
+    /// Queue a job; with `instance`, only that instance may claim it.
+    async fn insert_job(&self, job_id: Uuid, kind: &str, subject_id: Uuid, tenant: &str, instance: Option<&str>) -> Result<(), ApiError>;
+
+    /// Atomically take the oldest queued job of `kind` that `instance` may run, skipping tenants
+    /// with `per_tenant_limit` such jobs running.
+    async fn claim_next_job(&self, kind: &str, instance: &str, per_tenant_limit: Option<u64>) -> Result<Option<JobRow>, ApiError>;
+
+    async fn finish_job(&self, job_id: Uuid, error: Option<&str>) -> Result<(), ApiError>;
+
+    /// Put a running job back on the queue (interrupted by a shutdown).
+    async fn requeue_job(&self, job_id: Uuid) -> Result<(), ApiError>;
+
+    /// Mark a running job as still being worked on.
+    async fn heartbeat_job(&self, job_id: Uuid) -> Result<(), ApiError>;
+
+    /// Requeue running jobs claimed by `instance` (it restarted) or whose last heartbeat is before
+    /// `stale_before` (their instance is gone). Returns how many were requeued.
+    async fn requeue_running_jobs(&self, instance: Option<&str>, stale_before: DateTime<Utc>) -> Result<u64, ApiError>;
+
+    async fn delete_jobs(&self, subject_id: Uuid) -> Result<(), ApiError>;
+
+    /// Dataset proving jobs of a tenant running and queued.
+    async fn tenant_proving_jobs(&self, key_id: &str) -> Result<(u64, u64), ApiError>;
+
+    /// Dataset proving jobs running and queued, across tenants.
+    async fn proving_backlog(&self) -> Result<(u64, u64), ApiError>;
+
+    /// Jobs for `subject_id` queued or running.
+    async fn active_jobs(&self, subject_id: Uuid) -> Result<u64, ApiError>;
+
+    /// Status and error of the most recent job of `kind` for `subject_id`.
+    async fn latest_job(&self, kind: &str, subject_id: Uuid) -> Result<Option<(String, Option<String>)>, ApiError>;
+
+    // --- Audit log ---
+
+    /// Append an entry to the hash-chained audit log; returns its entry hash.
+    async fn append_audit(&self, dataset_id: Option<Uuid>, event: &str, details: &serde_json::Value) -> Result<String, ApiError>;
+
+    async fn list_audit(&self, dataset_id: Uuid, offset: u64, limit: u64) -> Result<Vec<AuditRow>, ApiError>;
+
+    /// `Ok(entries)` if the audit chain is intact, else `Err(seq)` of the first broken entry.
+    async fn verify_audit_chain(&self) -> Result<Result<u64, u64>, ApiError>;
+
+    // --- Transparency log ---
+
+    /// Append a leaf to the transparency log; returns its index.
+    async fn append_log_leaf(
+        &self,
+        kind: &str,
+        dataset_id: Uuid,
+        shard_index: Option<u64>,
+        commitment_hex: &str,
+        leaf_hash: &str,
+    ) -> Result<u64, ApiError>;
+
+    async fn log_size(&self) -> Result<u64, ApiError>;
+
+    /// Leaf hashes (hex) of the first `tree_size` leaves, in order.
+    async fn log_leaf_hashes(&self, tree_size: u64) -> Result<Vec<String>, ApiError>;
+
+    async fn get_log_leaf(&self, seq: u64) -> Result<Option<LogLeafRow>, ApiError>;
+
+    /// Index of the first leaf logging `commitment_hex` as the commitment of `dataset_id`.
+    async fn find_dataset_log_leaf(&self, dataset_id: Uuid, commitment_hex: &str) -> Result<Option<u64>, ApiError>;
+
+    // --- Operational state ---
+
+    /// Store a dataset's aggregate proof, replacing any earlier one.
+    async fn put_aggregate_proof(&self, dataset_id: Uuid, row: &AggregateProofRow) -> Result<(), ApiError>;
+
+    async fn get_aggregate_proof(&self, dataset_id: Uuid) -> Result<Option<AggregateProofRow>, ApiError>;
+
+    /// Store a dataset's anomaly analysis, replacing any earlier one.
+    async fn put_anomaly_analysis(&self, dataset_id: Uuid, row: &AnomalyAnalysisRow) -> Result<(), ApiError>;
+
+    async fn get_anomaly_analysis(&self, dataset_id: Uuid) -> Result<Option<AnomalyAnalysisRow>, ApiError>;
+
+    /// Store a dataset's verification report, replacing any earlier one.
+    async fn put_dataset_reverification(&self, dataset_id: Uuid, row: &DatasetReverificationRow) -> Result<(), ApiError>;
+
+    async fn get_dataset_reverification(&self, dataset_id: Uuid) -> Result<Option<DatasetReverificationRow>, ApiError>;
+
+    /// Start (or restart) a migration to `curve`, clearing any earlier outcome but keeping the
+    /// shards already re-proven.
+    async fn put_curve_migration(&self, dataset_id: Uuid, curve: Curve, status: &str, reason: Option<&str>) -> Result<(), ApiError>;
+
+    /// `done`, `flagged` and `failed` also set `finished_at`.
+    async fn set_curve_migration_status(&self, dataset_id: Uuid, curve: Curve, status: &str, reason: Option<&str>) -> Result<(), ApiError>;
+
+    async fn set_curve_migration_done(&self, dataset_id: Uuid, curve: Curve, dataset_commitment_hex: &str, key_id: &str) -> Result<(), ApiError>;
+
+    async fn get_curve_migration(&self, dataset_id: Uuid, curve: Curve) -> Result<Option<CurveMigrationRow>, ApiError>;
+
+    /// Migrations to `curve`, oldest first.
+    async fn list_curve_migrations(&self, curve: Curve) -> Result<Vec<CurveMigrationRow>, ApiError>;
+
+    async fn insert_curve_shard(
+        &self,
+        dataset_id: Uuid,
+        curve: Curve,
+        shard: &CurveShardRow,
+        sealed_master_salt: &[u8],
+        key_id: &str,
+    ) -> Result<(), ApiError>;
+
+    /// Drop a dataset's shards on `curve` proven with any key but `key_id`.
+    async fn delete_curve_shards_except(&self, dataset_id: Uuid, curve: Curve, key_id: &str) -> Result<u64, ApiError>;
+
+    /// Re-proven shards on `curve` with `shard_index` in `index_range`, paged like `list_shards`.
+    async fn list_curve_shards(
+        &self,
+        dataset_id: Uuid,
+        curve: Curve,
+        index_range: Range<u64>,
+        offset: u64,
+        limit: u64,
+        include_proof: bool,
+    ) -> Result<Vec<CurveShardRow>, ApiError>;
+
+    /// Count one `circuit` proof made with `key_id` for `dataset_id`; `true` if the key hadn't
+    /// proven anything for that dataset before.
+    async fn record_key_proof(&self, key_id: &str, circuit: &str, dataset_id: Uuid) -> Result<bool, ApiError>;
+
+    /// Usage of every key (`None`) or of one key, oldest first.
+    async fn list_key_usage(&self, key_id: Option<&str>) -> Result<Vec<KeyUsageRow>, ApiError>;
+
+    async fn insert_log_anchor(&self, row: &LogAnchorRow) -> Result<(), ApiError>;
+
+    /// The largest tree size anchored so far.
+    async fn anchored_tree_size(&self) -> Result<u64, ApiError>;
+
+    /// The first anchor of a tree containing leaf `leaf_index`.
+    async fn anchor_covering(&self, leaf_index: u64) -> Result<Option<LogAnchorRow>, ApiError>;
+
+    /// A round trip to the database (`/readyz`).
+    async fn ping(&self) -> Result<(), ApiError>;
+
+    /// Whether the ledger lives in the SQLite file `backup` copies.
+    fn is_sqlite(&self) -> bool;
+}
+
+/// `LedgerStore` over the SQLite pool, with proof blobs in `proofs` (see `proof_files`).
+#[derive(Clone)]
+pub struct SqliteStore {
+    db: Db,
+    proofs: ProofFiles,
+}
+
+impl SqliteStore {
+    pub fn new(db: Db, proofs: ProofFiles) -> Self {
+        Self { db, proofs }
+    }
+}
+
+#[async_trait]
+impl LedgerStore for SqliteStore {
+    async fn insert_dataset(&self, dataset: &NewDataset<'_>) -> Result<(), ApiError> {
+        db::insert_dataset(&self.db, dataset).await
+    }
+
+    async fn get_dataset(&self, dataset_id: Uuid) -> Result<Option<DatasetRow>, ApiError> {
+        db::get_dataset(&self.db, dataset_id).await
+    }
+
+    async fn list_dataset_ids(&self) -> Result<Vec<Uuid>, ApiError> {
+        db::list_dataset_ids(&self.db).await
+    }
+
+    async fn dataset_owner(&self, dataset_id: Uuid) -> Result<Option<String>, ApiError> {
+        db::dataset_owner(&self.db, dataset_id).await
+    }
+
+    async fn set_dataset_ready(&self, dataset_id: Uuid, commitment_hex: &str) -> Result<(), ApiError> {
+        db::set_dataset_ready(&self.db, dataset_id, commitment_hex).await
+    }
+
+    async fn extend_dataset(&self, dataset_id: Uuid, dataset_size: u64, commitment_hex: &str) -> Result<bool, ApiError> {
+        db::extend_dataset(&self.db, dataset_id, dataset_size, commitment_hex).await
+    }
+
+    async fn set_dataset_failed(&self, dataset_id: Uuid, error: &str) -> Result<(), ApiError> {
+        db::set_dataset_failed(&self.db, dataset_id, error).await
+    }
+
+    async fn cancel_dataset(&self, dataset_id: Uuid) -> Result<bool, ApiError> {
+        db::cancel_dataset(&self.db, dataset_id).await
+    }
+
+    async fn set_dataset_imported(
+        &self,
+        dataset_id: Uuid,
+        commitment_hex: &str,
+        vk_b64: &str,
+        imported_from: &str,
+    ) -> Result<(), ApiError> {
+        db::set_dataset_imported(&self.db, dataset_id, commitment_hex, vk_b64, imported_from).await
+    }
+
+    async fn set_dataset_federated(&self, dataset_id: Uuid, vk_b64: &str, imported_from: &str) -> Result<(), ApiError> {
+        db::set_dataset_federated(&self.db, dataset_id, vk_b64, imported_from).await
+    }
+
+    async fn get_dataset_external_vk(&self, dataset_id: Uuid) -> Result<Option<String>, ApiError> {
+        db::get_dataset_external_vk(&self.db, dataset_id).await
+    }
+
+    async fn tenant_datasets(&self, key_id: &str) -> Result<(u64, u64), ApiError> {
+        db::tenant_datasets(&self.db, key_id).await
+    }
+
+    async fn set_dataset_ingest_quality(&self, dataset_id: Uuid, quality: &IngestQuality) -> Result<(), ApiError> {
+        db::set_dataset_ingest_quality(&self.db, dataset_id, quality).await
+    }
+
+    async fn set_dataset_manifest(&self, dataset_id: Uuid, manifest: &serde_json::Value) -> Result<(), ApiError> {
+        db::set_dataset_manifest(&self.db, dataset_id, manifest).await
+    }
+
+    async fn get_dataset_manifest(&self, dataset_id: Uuid) -> Result<Option<serde_json::Value>, ApiError> {
+        db::get_dataset_manifest(&self.db, dataset_id).await
+    }
+
+    async fn freeze_dataset(&self, dataset_id: Uuid, frozen_by: &str) -> Result<bool, ApiError> {
+        db::freeze_dataset(&self.db, dataset_id, frozen_by).await
+    }
+
+    async fn unfreeze_dataset(&self, dataset_id: Uuid) -> Result<bool, ApiError> {
+        db::unfreeze_dataset(&self.db, dataset_id).await
+    }
+
+    async fn delete_dataset(&self, dataset_id: Uuid, deleted_by: &str, reason: &str) -> Result<Option<TombstoneRow>, ApiError> {
+        db::delete_dataset(&self.db, &self.proofs, dataset_id, deleted_by, reason).await
+    }
+
+    async fn dataset_tombstone(&self, dataset_id: Uuid) -> Result<Option<TombstoneRow>, ApiError> {
+        db::dataset_tombstone(&self.db, dataset_id).await
+    }
+
+    async fn expired_datasets(&self, cutoff: DateTime<Utc>) -> Result<Vec<Uuid>, ApiError> {
+        db::expired_datasets(&self.db, cutoff).await
+    }
+
+    async fn dataset_acl(&self, dataset_id: Uuid) -> Result<DatasetAclRow, ApiError> {
+        db::dataset_acl(&self.db, dataset_id).await
+    }
+
+    async fn grant_dataset_access(
+        &self,
+        dataset_id: Uuid,
+        grantee_kind: &str,
+        grantee: &str,
+        granted_by: &str,
+    ) -> Result<bool, ApiError> {
+        db::grant_dataset_access(&self.db, dataset_id, grantee_kind, grantee, granted_by).await
+    }
+
+    async fn revoke_dataset_access(&self, dataset_id: Uuid, grantee_kind: &str, grantee: &str) -> Result<bool, ApiError> {
+        db::revoke_dataset_access(&self.db, dataset_id, grantee_kind, grantee).await
+    }
+
+    async fn insert_api_key(&self, key: &NewApiKey<'_>) -> Result<DateTime<Utc>, ApiError> {
+        db::insert_api_key(&self.db, key).await
+    }
+
+    async fn api_key_by_hash(&self, key_hash: &str) -> Result<Option<ApiKeyRow>, ApiError> {
+        db::api_key_by_hash(&self.db, key_hash).await
+    }
+
+    async fn list_api_keys(&self) -> Result<Vec<ApiKeyRow>, ApiError> {
+        db::list_api_keys(&self.db).await
+    }
+
+    async fn revoke_api_key(&self, key_id: &str) -> Result<bool, ApiError> {
+        db::revoke_api_key(&self.db, key_id).await
+    }
+
+    async fn take_daily_query(&self, key_id: &str, day: &str, max: u64) -> Result<bool, ApiError> {
+        db::take_daily_query(&self.db, key_id, day, max).await
+    }
+
+    async fn refund_daily_query(&self, key_id: &str, day: &str) -> Result<(), ApiError> {
+        db::refund_daily_query(&self.db, key_id, day).await
+    }
+
+    async fn daily_queries(&self, key_id: &str, day: &str) -> Result<u64, ApiError> {
+        db::daily_queries(&self.db, key_id, day).await
+    }
+
+    async fn dataset_quality(&self, dataset_id: Uuid, buckets: &AgeBuckets) -> Result<DatasetQualityRow, ApiError> {
+        db::dataset_quality(&self.db, dataset_id, buckets).await
+    }
+
+    async fn dataset_totals(
+        &self,
+        dataset_id: Uuid,
+        shards: Range<u64>,
+        field_set: FieldSet,
+        buckets: &AgeBuckets,
+    ) -> Result<(ShardStats, u64), ApiError> {
+        db::dataset_totals(&self.db, dataset_id, shards, field_set, buckets).await
+    }
+
+    async fn aggregate_for_bucket(
+        &self,
+        dataset_id: Uuid,
+        shards: Range<u64>,
+        bucket_index: usize,
+        field_index: usize,
+        buckets: &AgeBuckets,
+    ) -> Result<BucketTotals, ApiError> {
+        db::aggregate_for_bucket(&self.db, dataset_id, shards, bucket_index, field_index, buckets).await
+    }
+
+    async fn insert_shard(
+        &self,
+        dataset_id: Uuid,
+        shard_index: u64,
+        shard_commitment_hex: &str,
+        stats: &ShardStats,
+        proof_b64: &str,
+        verified: bool,
+    ) -> Result<(), ApiError> {
+        let _guard = self.proofs.lock().await;
+        let proof = db::write_proof_file(&self.proofs, proof_b64).await?;
+        db::insert_shard(&self.db, dataset_id, shard_index, shard_commitment_hex, stats, &proof, verified).await
+    }
+
+    async fn set_shard_quality(&self, dataset_id: Uuid, shard_index: u64, quality: &ShardQuality) -> Result<(), ApiError> {
+        db::set_shard_quality(&self.db, dataset_id, shard_index, quality).await
+    }
+
+    async fn set_shard_sealed_master_salt(&self, dataset_id: Uuid, shard_index: u64, sealed: &[u8]) -> Result<(), ApiError> {
+        db::set_shard_sealed_master_salt(&self.db, dataset_id, shard_index, sealed).await
+    }
+
+    async fn set_shard_vk_version(&self, dataset_id: Uuid, shard_index: u64, vk_version: &str) -> Result<(), ApiError> {
+        db::set_shard_vk_version(&self.db, dataset_id, shard_index, vk_version).await
+    }
+
+    async fn shard_vk_versions(&self, dataset_id: Uuid, index_range: Range<u64>) -> Result<Vec<(u64, String)>, ApiError> {
+        db::shard_vk_versions(&self.db, dataset_id, index_range).await
+    }
+
+    async fn set_shards_verified(&self, dataset_id: Uuid, shard_indices: &[u64], vk_hash: &str) -> Result<(), ApiError> {
+        db::set_shards_verified(&self.db, dataset_id, shard_indices, vk_hash).await
+    }
+
+    async fn shard_verifications(&self, dataset_id: Uuid, index_range: Range<u64>) -> Result<Vec<(u64, DateTime<Utc>, String)>, ApiError> {
+        db::shard_verifications(&self.db, dataset_id, index_range).await
+    }
+
+    async fn sample_shards(&self, limit: u64) -> Result<Vec<(Uuid, u64)>, ApiError> {
+        db::sample_shards(&self.db, limit).await
+    }
+
+    async fn count_shards_done(&self, dataset_id: Uuid) -> Result<u64, ApiError> {
+        db::count_shards_done(&self.db, dataset_id).await
+    }
+
+    async fn list_shards(
+        &self,
+        dataset_id: Uuid,
+        index_range: Range<u64>,
+        offset: u64,
+        limit: u64,
+        include_proof: bool,
+    ) -> Result<Vec<ShardListRow>, ApiError> {
+        db::list_shards(&self.db, &self.proofs, dataset_id, index_range, offset, limit, include_proof).await
+    }
+
+    async fn record_shard_failure(&self, dataset_id: Uuid, shard_index: u64, error_class: &str, error: &str) -> Result<(), ApiError> {
+        db::record_shard_failure(&self.db, dataset_id, shard_index, error_class, error).await
+    }
+
+    async fn list_shard_failures(&self, dataset_id: Uuid) -> Result<Vec<ShardFailureRow>, ApiError> {
+        db::list_shard_failures(&self.db, dataset_id).await
+    }
+
+    async fn list_proof_blobs(&self, after: &str, limit: u64) -> Result<Vec<(String, String)>, ApiError> {
+        db::list_proof_blobs(&self.db, &self.proofs, after, limit).await
+    }
+
+    async fn shards_with_proof(&self, hash: &str) -> Result<Vec<(Uuid, u64)>, ApiError> {
+        db::shards_with_proof(&self.db, hash).await
+    }
+
+    async fn delete_orphan_proof_blobs(&self) -> Result<u64, ApiError> {
+        db::delete_orphan_proof_blobs(&self.db, &self.proofs).await
+    }
+
+    async fn proof_blob_stats(&self) -> Result<(u64, u64, u64), ApiError> {
+        db::proof_blob_stats(&self.db).await
+    }
+
+    async fn insert_query(&self, query_id: Uuid, dataset_id: Uuid, spec: &QuerySpec<'_>, result: &QueryResult) -> Result<(), ApiError> {
+        db::insert_query(&self.db, query_id, dataset_id, spec, result).await
+    }
+
+    async fn insert_unreleased_query(&self, query_id: Uuid, dataset_id: Uuid, spec: &QuerySpec<'_>, status: &str) -> Result<(), ApiError> {
+        db::insert_unreleased_query(&self.db, query_id, dataset_id, spec, status).await
+    }
+
+    async fn get_query(&self, query_id: Uuid) -> Result<Option<QueryRow>, ApiError> {
+        db::get_query(&self.db, query_id).await
+    }
+
+    async fn list_dataset_queries(&self, dataset_id: Uuid, offset: u64, limit: u64) -> Result<Vec<QueryRow>, ApiError> {
+        db::list_dataset_queries(&self.db, dataset_id, offset, limit).await
+    }
+
+    async fn release_query(
+        &self,
+        query_id: Uuid,
+        from_status: &str,
+        result: &QueryResult,
+        decided_by: Option<&str>,
+    ) -> Result<bool, ApiError> {
+        db::release_query(&self.db, query_id, from_status, result, decided_by).await
+    }
+
+    async fn set_query_status(&self, query_id: Uuid, from: &str, to: &str) -> Result<bool, ApiError> {
+        db::set_query_status(&self.db, query_id, from, to).await
+    }
+
+    async fn fail_query(&self, query_id: Uuid, error: &str) -> Result<(), ApiError> {
+        db::fail_query(&self.db, query_id, error).await
+    }
+
+    async fn reject_pending_query(&self, query_id: Uuid, decided_by: &str) -> Result<bool, ApiError> {
+        db::reject_pending_query(&self.db, query_id, decided_by).await
+    }
+
+    async fn release_keys_since(&self, dataset_id: Uuid, since: DateTime<Utc>) -> Result<Vec<String>, ApiError> {
+        db::release_keys_since(&self.db, dataset_id, since).await
+    }
+
+    async fn insert_released_cells(&self, query_id: Uuid, dataset_id: Uuid, cells: &[(usize, &str)]) -> Result<(), ApiError> {
+        db::insert_released_cells(&self.db, query_id, dataset_id, cells).await
+    }
+
+    async fn spend_privacy_budget(
+        &self,
+        dataset_id: Uuid,
+        epsilon: f64,
+        delta: f64,
+        budget: Option<f64>,
+    ) -> Result<Option<PrivacyBudgetRow>, ApiError> {
+        db::spend_privacy_budget(&self.db, dataset_id, epsilon, delta, budget).await
+    }
+
+    async fn privacy_budget(&self, dataset_id: Uuid) -> Result<PrivacyBudgetRow, ApiError> {
+        db::privacy_budget(&self.db, dataset_id).await
+    }
+
+    async fn cell_disclosure(&self, dataset_id: Uuid) -> Result<Vec<CellDisclosureRow>, ApiError> {
+        db::cell_disclosure(&self.db, dataset_id).await
+    }
+
+    async fn insert_job(&self, job_id: Uuid, kind: &str, subject_id: Uuid, tenant: &str, instance: Option<&str>) -> Result<(), ApiError> {
+        db::insert_job(&self.db, job_id, kind, subject_id, tenant, instance).await
+    }
+
+    async fn claim_next_job(&self, kind: &str, instance: &str, per_tenant_limit: Option<u64>) -> Result<Option<JobRow>, ApiError> {
+        db::claim_next_job(&self.db, kind, instance, per_tenant_limit).await
+    }
+
+    async fn finish_job(&self, job_id: Uuid, error: Option<&str>) -> Result<(), ApiError> {
+        db::finish_job(&self.db, job_id, error).await
+    }
+
+    async fn requeue_job(&self, job_id: Uuid) -> Result<(), ApiError> {
+        db::requeue_job(&self.db, job_id).await
+    }
+
+    async fn heartbeat_job(&self, job_id: Uuid) -> Result<(), ApiError> {
+        db::heartbeat_job(&self.db, job_id).await
+    }
+
+    async fn requeue_running_jobs(&self, instance: Option<&str>, stale_before: DateTime<Utc>) -> Result<u64, ApiError> {
+        db::requeue_running_jobs(&self.db, instance, stale_before).await
+    }
+
+    async fn delete_jobs(&self, subject_id: Uuid) -> Result<(), ApiError> {
+        db::delete_jobs(&self.db, subject_id).await
+    }
+
+    async fn tenant_proving_jobs(&self, key_id: &str) -> Result<(u64, u64), ApiError> {
+        db::tenant_proving_jobs(&self.db, key_id).await
+    }
+
+    async fn proving_backlog(&self) -> Result<(u64, u64), ApiError> {
+        db::proving_backlog(&self.db).await
+    }
+
+    async fn active_jobs(&self, subject_id: Uuid) -> Result<u64, ApiError> {
+        db::active_jobs(&self.db, subject_id).await
+    }
+
+    async fn latest_job(&self, kind: &str, subject_id: Uuid) -> Result<Option<(String, Option<String>)>, ApiError> {
+        db::latest_job(&self.db, kind, subject_id).await
+    }
+
+    async fn append_audit(&self, dataset_id: Option<Uuid>, event: &str, details: &serde_json::Value) -> Result<String, ApiError> {
+        db::append_audit(&self.db, dataset_id, event, details).await
+    }
+
+    async fn list_audit(&self, dataset_id: Uuid, offset: u64, limit: u64) -> Result<Vec<AuditRow>, ApiError> {
+        db::list_audit(&self.db, dataset_id, offset, limit).await
+    }
+
+    async fn verify_audit_chain(&self) -> Result<Result<u64, u64>, ApiError> {
+        db::verify_audit_chain(&self.db).await
+    }
+
+    async fn append_log_leaf(
+        &self,
+        kind: &str,
+        dataset_id: Uuid,
+        shard_index: Option<u64>,
+        commitment_hex: &str,
+        leaf_hash: &str,
+    ) -> Result<u64, ApiError> {
+        db::append_log_leaf(&self.db, kind, dataset_id, shard_index, commitment_hex, leaf_hash).await
+    }
+
+    async fn log_size(&self) -> Result<u64, ApiError> {
+        db::log_size(&self.db).await
+    }
+
+    async fn log_leaf_hashes(&self, tree_size: u64) -> Result<Vec<String>, ApiError> {
+        db::log_leaf_hashes(&self.db, tree_size).await
+    }
+
+    async fn get_log_leaf(&self, seq: u64) -> Result<Option<LogLeafRow>, ApiError> {
+        db::get_log_leaf(&self.db, seq).await
+    }
+
+    async fn find_dataset_log_leaf(&self, dataset_id: Uuid, commitment_hex: &str) -> Result<Option<u64>, ApiError> {
+        db::find_dataset_log_leaf(&self.db, dataset_id, commitment_hex).await
+    }
+
+    async fn put_aggregate_proof(&self, dataset_id: Uuid, row: &AggregateProofRow) -> Result<(), ApiError> {
+        db::put_aggregate_proof(&self.db, dataset_id, row).await
+    }
+
+    async fn get_aggregate_proof(&self, dataset_id: Uuid) -> Result<Option<AggregateProofRow>, ApiError> {
+        db::get_aggregate_proof(&self.db, dataset_id).await
+    }
+
+    async fn put_anomaly_analysis(&self, dataset_id: Uuid, row: &AnomalyAnalysisRow) -> Result<(), ApiError> {
+        db::put_anomaly_analysis(&self.db, dataset_id, row).await
+    }
+
+    async fn get_anomaly_analysis(&self, dataset_id: Uuid) -> Result<Option<AnomalyAnalysisRow>, ApiError> {
+        db::get_anomaly_analysis(&self.db, dataset_id).await
+    }
+
+    async fn put_dataset_reverification(&self, dataset_id: Uuid, row: &DatasetReverificationRow) -> Result<(), ApiError> {
+        db::put_dataset_reverification(&self.db, dataset_id, row).await
+    }
+
+    async fn get_dataset_reverification(&self, dataset_id: Uuid) -> Result<Option<DatasetReverificationRow>, ApiError> {
+        db::get_dataset_reverification(&self.db, dataset_id).await
+    }
+
+    async fn put_curve_migration(&self, dataset_id: Uuid, curve: Curve, status: &str, reason: Option<&str>) -> Result<(), ApiError> {
+        db::put_curve_migration(&self.db, dataset_id, curve, status, reason).await
+    }
+
+    async fn set_curve_migration_status(&self, dataset_id: Uuid, curve: Curve, status: &str, reason: Option<&str>) -> Result<(), ApiError> {
+        db::set_curve_migration_status(&self.db, dataset_id, curve, status, reason).await
+    }
+
+    async fn set_curve_migration_done(&self, dataset_id: Uuid, curve: Curve, dataset_commitment_hex: &str, key_id: &str) -> Result<(), ApiError> {
+        db::set_curve_migration_done(&self.db, dataset_id, curve, dataset_commitment_hex, key_id).await
+    }
+
+    async fn get_curve_migration(&self, dataset_id: Uuid, curve: Curve) -> Result<Option<CurveMigrationRow>, ApiError> {
+        db::get_curve_migration(&self.db, dataset_id, curve).await
+    }
+
+    async fn list_curve_migrations(&self, curve: Curve) -> Result<Vec<CurveMigrationRow>, ApiError> {
+        db::list_curve_migrations(&self.db, curve).await
+    }
+
+    async fn insert_curve_shard(
+        &self,
+        dataset_id: Uuid,
+        curve: Curve,
+        shard: &CurveShardRow,
+        sealed_master_salt: &[u8],
+        key_id: &str,
+    ) -> Result<(), ApiError> {
+        db::insert_curve_shard(&self.db, dataset_id, curve, shard, sealed_master_salt, key_id).await
+    }
+
+    async fn delete_curve_shards_except(&self, dataset_id: Uuid, curve: Curve, key_id: &str) -> Result<u64, ApiError> {
+        db::delete_curve_shards_except(&self.db, dataset_id, curve, key_id).await
+    }
+
+    async fn list_curve_shards(
+        &self,
+        dataset_id: Uuid,
+        curve: Curve,
+        index_range: Range<u64>,
+        offset: u64,
+        limit: u64,
+        include_proof: bool,
+    ) -> Result<Vec<CurveShardRow>, ApiError> {
+        db::list_curve_shards(&self.db, dataset_id, curve, index_range, offset, limit, include_proof).await
+    }
+
+    async fn record_key_proof(&self, key_id: &str, circuit: &str, dataset_id: Uuid) -> Result<bool, ApiError> {
+        db::record_key_proof(&self.db, key_id, circuit, dataset_id).await
+    }
+
+    async fn list_key_usage(&self, key_id: Option<&str>) -> Result<Vec<KeyUsageRow>, ApiError> {
+        db::list_key_usage(&self.db, key_id).await
+    }
+
+    async fn insert_log_anchor(&self, row: &LogAnchorRow) -> Result<(), ApiError> {
+        db::insert_log_anchor(&self.db, row).await
+    }
+
+    async fn anchored_tree_size(&self) -> Result<u64, ApiError> {
+        db::anchored_tree_size(&self.db).await
+    }
+
+    async fn anchor_covering(&self, leaf_index: u64) -> Result<Option<LogAnchorRow>, ApiError> {
+        db::anchor_covering(&self.db, leaf_index).await
+    }
+
+    async fn ping(&self) -> Result<(), ApiError> {
+        db::ping(&self.db).await
+    }
+
+    fn is_sqlite(&self) -> bool {
+        true
+    }
+}
+
+/// `LedgerStore` over a Postgres pool, shared by every instance pointed at it.
+#[derive(Clone)]
+pub struct PgStore {
+    db: PgDb,
+}
+
+impl PgStore {
+    pub fn new(db: PgDb) -> Self {
+        Self { db }
+    }
+}
+
+#[async_trait]
+impl LedgerStore for PgStore {
+    async fn insert_dataset(&self, dataset: &NewDataset<'_>) -> Result<(), ApiError> {
+        pg::insert_dataset(&self.db, dataset).await
+    }
+
+    async fn get_dataset(&self, dataset_id: Uuid) -> Result<Option<DatasetRow>, ApiError> {
+        pg::get_dataset(&self.db, dataset_id).await
+    }
+
+    async fn list_dataset_ids(&self) -> Result<Vec<Uuid>, ApiError> {
+        pg::list_dataset_ids(&self.db).await
+    }
+
+    async fn dataset_owner(&self, dataset_id: Uuid) -> Result<Option<String>, ApiError> {
+        pg::dataset_owner(&self.db, dataset_id).await
+    }
+
+    async fn set_dataset_ready(&self, dataset_id: Uuid, commitment_hex: &str) -> Result<(), ApiError> {
+        pg::set_dataset_ready(&self.db, dataset_id, commitment_hex).await
+    }
+
+    async fn extend_dataset(&self, dataset_id: Uuid, dataset_size: u64, commitment_hex: &str) -> Result<bool, ApiError> {
+        pg::extend_dataset(&self.db, dataset_id, dataset_size, commitment_hex).await
+    }
+
+    async fn set_dataset_failed(&self, dataset_id: Uuid, error: &str) -> Result<(), ApiError> {
+        pg::set_dataset_failed(&self.db, dataset_id, error).await
+    }
+
+    async fn cancel_dataset(&self, dataset_id: Uuid) -> Result<bool, ApiError> {
+        pg::cancel_dataset(&self.db, dataset_id).await
+    }
+
+    async fn set_dataset_imported(
+        &self,
+        dataset_id: Uuid,
+        commitment_hex: &str,
+        vk_b64: &str,
+        imported_from: &str,
+    ) -> Result<(), ApiError> {
+        pg::set_dataset_imported(&self.db, dataset_id, commitment_hex, vk_b64, imported_from).await
+    }
+
+    async fn set_dataset_federated(&self, dataset_id: Uuid, vk_b64: &str, imported_from: &str) -> Result<(), ApiError> {
+        pg::set_dataset_federated(&self.db, dataset_id, vk_b64, imported_from).await
+    }
+
+    async fn get_dataset_external_vk(&self, dataset_id: Uuid) -> Result<Option<String>, ApiError> {
+        pg::get_dataset_external_vk(&self.db, dataset_id).await
+    }
+
+    async fn tenant_datasets(&self, key_id: &str) -> Result<(u64, u64), ApiError> {
+        pg::tenant_datasets(&self.db, key_id).await
+    }
+
+    async fn set_dataset_ingest_quality(&self, dataset_id: Uuid, quality: &IngestQuality) -> Result<(), ApiError> {
+        pg::set_dataset_ingest_quality(&self.db, dataset_id, quality).await
+    }
+
+    async fn set_dataset_manifest(&self, dataset_id: Uuid, manifest: &serde_json::Value) -> Result<(), ApiError> {
+        pg::set_dataset_manifest(&self.db, dataset_id, manifest).await
+    }
+
+    async fn get_dataset_manifest(&self, dataset_id: Uuid) -> Result<Option<serde_json::Value>, ApiError> {
+        pg::get_dataset_manifest(&self.db, dataset_id).await
+    }
+
+    async fn freeze_dataset(&self, dataset_id: Uuid, frozen_by: &str) -> Result<bool, ApiError> {
+        pg::freeze_dataset(&self.db, dataset_id, frozen_by).await
+    }
+
+    async fn unfreeze_dataset(&self, dataset_id: Uuid) -> Result<bool, ApiError> {
+        pg::unfreeze_dataset(&self.db, dataset_id).await
+    }
+
+    async fn delete_dataset(&self, dataset_id: Uuid, deleted_by: &str, reason: &str) -> Result<Option<TombstoneRow>, ApiError> {
+        pg::delete_dataset(&self.db, dataset_id, deleted_by, reason).await
+    }
+
+    async fn dataset_tombstone(&self, dataset_id: Uuid) -> Result<Option<TombstoneRow>, ApiError> {
+        pg::dataset_tombstone(&self.db, dataset_id).await
+    }
+
+    async fn expired_datasets(&self, cutoff: DateTime<Utc>) -> Result<Vec<Uuid>, ApiError> {
+        pg::expired_datasets(&self.db, cutoff).await
+    }
+
+    async fn dataset_acl(&self, dataset_id: Uuid) -> Result<DatasetAclRow, ApiError> {
+        pg::dataset_acl(&self.db, dataset_id).await
+    }
+
+    async fn grant_dataset_access(
+        &self,
+        dataset_id: Uuid,
+        grantee_kind: &str,
+        grantee: &str,
+        granted_by: &str,
+    ) -> Result<bool, ApiError> {
+        pg::grant_dataset_access(&self.db, dataset_id, grantee_kind, grantee, granted_by).await
+    }
+
+    async fn revoke_dataset_access(&self, dataset_id: Uuid, grantee_kind: &str, grantee: &str) -> Result<bool, ApiError> {
+        pg::revoke_dataset_access(&self.db, dataset_id, grantee_kind, grantee).await
+    }
+
+    async fn insert_api_key(&self, key: &NewApiKey<'_>) -> Result<DateTime<Utc>, ApiError> {
+        pg::insert_api_key(&self.db, key).await
+    }
+
+    async fn api_key_by_hash(&self, key_hash: &str) -> Result<Option<ApiKeyRow>, ApiError> {
+        pg::api_key_by_hash(&self.db, key_hash).await
+    }
+
+    async fn list_api_keys(&self) -> Result<Vec<ApiKeyRow>, ApiError> {
+        pg::list_api_keys(&self.db).await
+    }
+
+    async fn revoke_api_key(&self, key_id: &str) -> Result<bool, ApiError> {
+        pg::revoke_api_key(&self.db, key_id).await
+    }
+
+    async fn take_daily_query(&self, key_id: &str, day: &str, max: u64) -> Result<bool, ApiError> {
+        pg::take_daily_query(&self.db, key_id, day, max).await
+    }
+
+    async fn refund_daily_query(&self, key_id: &str, day: &str) -> Result<(), ApiError> {
+        pg::refund_daily_query(&self.db, key_id, day).await
+    }
+
+    async fn daily_queries(&self, key_id: &str, day: &str) -> Result<u64, ApiError> {
+        pg::daily_queries(&self.db, key_id, day).await
+    }
+
+    async fn dataset_quality(&self, dataset_id: Uuid, buckets: &AgeBuckets) -> Result<DatasetQualityRow, ApiError> {
+        pg::dataset_quality(&self.db, dataset_id, buckets).await
+    }
+
+    async fn dataset_totals(
+        &self,
+        dataset_id: Uuid,
+        shards: Range<u64>,
+        field_set: FieldSet,
+        buckets: &AgeBuckets,
+    ) -> Result<(ShardStats, u64), ApiError> {
+        pg::dataset_totals(&self.db, dataset_id, shards, field_set, buckets).await
+    }
+
+    async fn aggregate_for_bucket(
+        &self,
+        dataset_id: Uuid,
+        shards: Range<u64>,
+        bucket_index: usize,
+        field_index: usize,
+        buckets: &AgeBuckets,
+    ) -> Result<BucketTotals, ApiError> {
+        pg::aggregate_for_bucket(&self.db, dataset_id, shards, bucket_index, field_index, buckets).await
+    }
+
+    async fn insert_shard(
+        &self,
+        dataset_id: Uuid,
+        shard_index: u64,
+        shard_commitment_hex: &str,
+        stats: &ShardStats,
+        proof_b64: &str,
+        verified: bool,
+    ) -> Result<(), ApiError> {
+        pg::insert_shard(&self.db, dataset_id, shard_index, shard_commitment_hex, stats, proof_b64, verified).await
+    }
+
+    async fn set_shard_quality(&self, dataset_id: Uuid, shard_index: u64, quality: &ShardQuality) -> Result<(), ApiError> {
+        pg::set_shard_quality(&self.db, dataset_id, shard_index, quality).await
+    }
+
+    async fn set_shard_sealed_master_salt(&self, dataset_id: Uuid, shard_index: u64, sealed: &[u8]) -> Result<(), ApiError> {
+        pg::set_shard_sealed_master_salt(&self.db, dataset_id, shard_index, sealed).await
+    }
+
+    async fn set_shard_vk_version(&self, dataset_id: Uuid, shard_index: u64, vk_version: &str) -> Result<(), ApiError> {
+        pg::set_shard_vk_version(&self.db, dataset_id, shard_index, vk_version).await
+    }
+
+    async fn shard_vk_versions(&self, dataset_id: Uuid, index_range: Range<u64>) -> Result<Vec<(u64, String)>, ApiError> {
+        pg::shard_vk_versions(&self.db, dataset_id, index_range).await
+    }
+
+    async fn set_shards_verified(&self, dataset_id: Uuid, shard_indices: &[u64], vk_hash: &str) -> Result<(), ApiError> {
+        pg::set_shards_verified(&self.db, dataset_id, shard_indices, vk_hash).await
+    }
+
+    async fn shard_verifications(&self, dataset_id: Uuid, index_range: Range<u64>) -> Result<Vec<(u64, DateTime<Utc>, String)>, ApiError> {
+        pg::shard_verifications(&self.db, dataset_id, index_range).await
+    }
+
+    async fn sample_shards(&self, limit: u64) -> Result<Vec<(Uuid, u64)>, ApiError> {
+        pg::sample_shards(&self.db, limit).await
+    }
+
+    async fn count_shards_done(&self, dataset_id: Uuid) -> Result<u64, ApiError> {
+        pg::count_shards_done(&self.db, dataset_id).await
+    }
+
+    async fn list_shards(
+        &self,
+        dataset_id: Uuid,
+        index_range: Range<u64>,
+        offset: u64,
+        limit: u64,
+        include_proof: bool,
+    ) -> Result<Vec<ShardListRow>, ApiError> {
+        pg::list_shards(&self.db, dataset_id, index_range, offset, limit, include_proof).await
+    }
+
+    async fn record_shard_failure(&self, dataset_id: Uuid, shard_index: u64, error_class: &str, error: &str) -> Result<(), ApiError> {
+        pg::record_shard_failure(&self.db, dataset_id, shard_index, error_class, error).await
+    }
+
+    async fn list_shard_failures(&self, dataset_id: Uuid) -> Result<Vec<ShardFailureRow>, ApiError> {
+        pg::list_shard_failures(&self.db, dataset_id).await
+    }
+
+    async fn list_proof_blobs(&self, after: &str, limit: u64) -> Result<Vec<(String, String)>, ApiError> {
+        pg::list_proof_blobs(&self.db, after, limit).await
+    }
+
+    async fn shards_with_proof(&self, hash: &str) -> Result<Vec<(Uuid, u64)>, ApiError> {
+        pg::shards_with_proof(&self.db, hash).await
+    }
+
+    async fn delete_orphan_proof_blobs(&self) -> Result<u64, ApiError> {
+        pg::delete_orphan_proof_blobs(&self.db).await
+    }
+
+    async fn proof_blob_stats(&self) -> Result<(u64, u64, u64), ApiError> {
+        pg::proof_blob_stats(&self.db).await
+    }
+
+    async fn insert_query(&self, query_id: Uuid, dataset_id: Uuid, spec: &QuerySpec<'_>, result: &QueryResult) -> Result<(), ApiError> {
+        pg::insert_query(&self.db, query_id, dataset_id, spec, result).await
+    }
+
+    async fn insert_unreleased_query(&self, query_id: Uuid, dataset_id: Uuid, spec: &QuerySpec<'_>, status: &str) -> Result<(), ApiError> {
+        pg::insert_unreleased_query(&self.db, query_id, dataset_id, spec, status).await
+    }
+
+    async fn get_query(&self, query_id: Uuid) -> Result<Option<QueryRow>, ApiError> {
+        pg::get_query(&self.db, query_id).await
+    }
+
+    async fn list_dataset_queries(&self, dataset_id: Uuid, offset: u64, limit: u64) -> Result<Vec<QueryRow>, ApiError> {
+        pg::list_dataset_queries(&self.db, dataset_id, offset, limit).await
+    }
+
+    async fn release_query(
+        &self,
+        query_id: Uuid,
+        from_status: &str,
+        result: &QueryResult,
+        decided_by: Option<&str>,
+    ) -> Result<bool, ApiError> {
+        pg::release_query(&self.db, query_id, from_status, result, decided_by).await
+    }
+
+    async fn set_query_status(&self, query_id: Uuid, from: &str, to: &str) -> Result<bool, ApiError> {
+        pg::set_query_status(&self.db, query_id, from, to).await
+    }
+
+    async fn fail_query(&self, query_id: Uuid, error: &str) -> Result<(), ApiError> {
+        pg::fail_query(&self.db, query_id, error).await
+    }
+
+    async fn reject_pending_query(&self, query_id: Uuid, decided_by: &str) -> Result<bool, ApiError> {
+        pg::reject_pending_query(&self.db, query_id, decided_by).await
+    }
+
+    async fn release_keys_since(&self, dataset_id: Uuid, since: DateTime<Utc>) -> Result<Vec<String>, ApiError> {
+        pg::release_keys_since(&self.db, dataset_id, since).await
+    }
+
+    async fn insert_released_cells(&self, query_id: Uuid, dataset_id: Uuid, cells: &[(usize, &str)]) -> Result<(), ApiError> {
+        pg::insert_released_cells(&self.db, query_id, dataset_id, cells).await
+    }
+
+    async fn spend_privacy_budget(
+        &self,
+        dataset_id: Uuid,
+        epsilon: f64,
+        delta: f64,
+        budget: Option<f64>,
+    ) -> Result<Option<PrivacyBudgetRow>, ApiError> {
+        pg::spend_privacy_budget(&self.db, dataset_id, epsilon, delta, budget).await
+    }
+
+    async fn privacy_budget(&self, dataset_id: Uuid) -> Result<PrivacyBudgetRow, ApiError> {
+        pg::privacy_budget(&self.db, dataset_id).await
+    }
+
+    async fn cell_disclosure(&self, dataset_id: Uuid) -> Result<Vec<CellDisclosureRow>, ApiError> {
+        pg::cell_disclosure(&self.db, dataset_id).await
+    }
+
+    async fn insert_job(&self, job_id: Uuid, kind: &str, subject_id: Uuid, tenant: &str, instance: Option<&str>) -> Result<(), ApiError> {
+        pg::insert_job(&self.db, job_id, kind, subject_id, tenant, instance).await
+    }
+
+    async fn claim_next_job(&self, kind: &str, instance: &str, per_tenant_limit: Option<u64>) -> Result<Option<JobRow>, ApiError> {
+        pg::claim_next_job(&self.db, kind, instance, per_tenant_limit).await
+    }
+
+    async fn finish_job(&self, job_id: Uuid, error: Option<&str>) -> Result<(), ApiError> {
+        pg::finish_job(&self.db, job_id, error).await
+    }
+
+    async fn requeue_job(&self, job_id: Uuid) -> Result<(), ApiError> {
+        pg::requeue_job(&self.db, job_id).await
+    }
+
+    async fn heartbeat_job(&self, job_id: Uuid) -> Result<(), ApiError> {
+        pg::heartbeat_job(&self.db, job_id).await
+    }
+
+    async fn requeue_running_jobs(&self, instance: Option<&str>, stale_before: DateTime<Utc>) -> Result<u64, ApiError> {
+        pg::requeue_running_jobs(&self.db, instance, stale_before).await
+    }
+
+    async fn delete_jobs(&self, subject_id: Uuid) -> Result<(), ApiError> {
+        pg::delete_jobs(&self.db, subject_id).await
+    }
+
+    async fn tenant_proving_jobs(&self, key_id: &str) -> Result<(u64, u64), ApiError> {
+        pg::tenant_proving_jobs(&self.db, key_id).await
+    }
+
+    async fn proving_backlog(&self) -> Result<(u64, u64), ApiError> {
+        pg::proving_backlog(&self.db).await
+    }
+
+    async fn active_jobs(&self, subject_id: Uuid) -> Result<u64, ApiError> {
+        pg::active_jobs(&self.db, subject_id).await
+    }
+
+    async fn latest_job(&self, kind: &str, subject_id: Uuid) -> Result<Option<(String, Option<String>)>, ApiError> {
+        pg::latest_job(&self.db, kind, subject_id).await
+    }
+
+    async fn append_audit(&self, dataset_id: Option<Uuid>, event: &str, details: &serde_json::Value) -> Result<String, ApiError> {
+        pg::append_audit(&self.db, dataset_id, event, details).await
+    }
+
+    async fn list_audit(&self, dataset_id: Uuid, offset: u64, limit: u64) -> Result<Vec<AuditRow>, ApiError> {
+        pg::list_audit(&self.db, dataset_id, offset, limit).await
+    }
+
+    async fn verify_audit_chain(&self) -> Result<Result<u64, u64>, ApiError> {
+        pg::verify_audit_chain(&self.db).await
+    }
+
+    async fn append_log_leaf(
+        &self,
+        kind: &str,
+        dataset_id: Uuid,
+        shard_index: Option<u64>,
+        commitment_hex: &str,
+        leaf_hash: &str,
+    ) -> Result<u64, ApiError> {
+        pg::append_log_leaf(&self.db, kind, dataset_id, shard_index, commitment_hex, leaf_hash).await
+    }
+
+    async fn log_size(&self) -> Result<u64, ApiError> {
+        pg::log_size(&self.db).await
+    }
+
+    async fn log_leaf_hashes(&self, tree_size: u64) -> Result<Vec<String>, ApiError> {
+        pg::log_leaf_hashes(&self.db, tree_size).await
+    }
+
+    async fn get_log_leaf(&self, seq: u64) -> Result<Option<LogLeafRow>, ApiError> {
+        pg::get_log_leaf(&self.db, seq).await
+    }
+
+    async fn find_dataset_log_leaf(&self, dataset_id: Uuid, commitment_hex: &str) -> Result<Option<u64>, ApiError> {
+        pg::find_dataset_log_leaf(&self.db, dataset_id, commitment_hex).await
+    }
+
+    async fn put_aggregate_proof(&self, dataset_id: Uuid, row: &AggregateProofRow) -> Result<(), ApiError> {
+        pg::put_aggregate_proof(&self.db, dataset_id, row).await
+    }
+
+    async fn get_aggregate_proof(&self, dataset_id: Uuid) -> Result<Option<AggregateProofRow>, ApiError> {
+        pg::get_aggregate_proof(&self.db, dataset_id).await
+    }
+
+    async fn put_anomaly_analysis(&self, dataset_id: Uuid, row: &AnomalyAnalysisRow) -> Result<(), ApiError> {
+        pg::put_anomaly_analysis(&self.db, dataset_id, row).await
+    }
+
+    async fn get_anomaly_analysis(&self, dataset_id: Uuid) -> Result<Option<AnomalyAnalysisRow>, ApiError> {
+        pg::get_anomaly_analysis(&self.db, dataset_id).await
+    }
+
+    async fn put_dataset_reverification(&self, dataset_id: Uuid, row: &DatasetReverificationRow) -> Result<(), ApiError> {
+        pg::put_dataset_reverification(&self.db, dataset_id, row).await
+    }
+
+    async fn get_dataset_reverification(&self, dataset_id: Uuid) -> Result<Option<DatasetReverificationRow>, ApiError> {
+        pg::get_dataset_reverification(&self.db, dataset_id).await
+    }
+
+    async fn put_curve_migration(&self, dataset_id: Uuid, curve: Curve, status: &str, reason: Option<&str>) -> Result<(), ApiError> {
+        pg::put_curve_migration(&self.db, dataset_id, curve, status, reason).await
+    }
+
+    async fn set_curve_migration_status(&self, dataset_id: Uuid, curve: Curve, status: &str, reason: Option<&str>) -> Result<(), ApiError> {
+        pg::set_curve_migration_status(&self.db, dataset_id, curve, status, reason).await
+    }
+
+    async fn set_curve_migration_done(&self, dataset_id: Uuid, curve: Curve, dataset_commitment_hex: &str, key_id: &str) -> Result<(), ApiError> {
+        pg::set_curve_migration_done(&self.db, dataset_id, curve, dataset_commitment_hex, key_id).await
+    }
+
+    async fn get_curve_migration(&self, dataset_id: Uuid, curve: Curve) -> Result<Option<CurveMigrationRow>, ApiError> {
+        pg::get_curve_migration(&self.db, dataset_id, curve).await
+    }
+
+    async fn list_curve_migrations(&self, curve: Curve) -> Result<Vec<CurveMigrationRow>, ApiError> {
+        pg::list_curve_migrations(&self.db, curve).await
+    }
+
+    async fn insert_curve_shard(
+        &self,
+        dataset_id: Uuid,
+        curve: Curve,
+        shard: &CurveShardRow,
+        sealed_master_salt: &[u8],
+        key_id: &str,
+    ) -> Result<(), ApiError> {
+        pg::insert_curve_shard(&self.db, dataset_id, curve, shard, sealed_master_salt, key_id).await
+    }
+
+    async fn delete_curve_shards_except(&self, dataset_id: Uuid, curve: Curve, key_id: &str) -> Result<u64, ApiError> {
+        pg::delete_curve_shards_except(&self.db, dataset_id, curve, key_id).await
+    }
+
+    async fn list_curve_shards(
+        &self,
+        dataset_id: Uuid,
+        curve: Curve,
+        index_range: Range<u64>,
+        offset: u64,
+        limit: u64,
+        include_proof: bool,
+    ) -> Result<Vec<CurveShardRow>, ApiError> {
+        pg::list_curve_shards(&self.db, dataset_id, curve, index_range, offset, limit, include_proof).await
+    }
+
+    async fn record_key_proof(&self, key_id: &str, circuit: &str, dataset_id: Uuid) -> Result<bool, ApiError> {
+        pg::record_key_proof(&self.db, key_id, circuit, dataset_id).await
+    }
+
+    async fn list_key_usage(&self, key_id: Option<&str>) -> Result<Vec<KeyUsageRow>, ApiError> {
+        pg::list_key_usage(&self.db, key_id).await
+    }
+
+    async fn insert_log_anchor(&self, row: &LogAnchorRow) -> Result<(), ApiError> {
+        pg::insert_log_anchor(&self.db, row).await
+    }
+
+    async fn anchored_tree_size(&self) -> Result<u64, ApiError> {
+        pg::anchored_tree_size(&self.db).await
+    }
+
+    async fn anchor_covering(&self, leaf_index: u64) -> Result<Option<LogAnchorRow>, ApiError> {
+        pg::anchor_covering(&self.db, leaf_index).await
+    }
+
+    async fn ping(&self) -> Result<(), ApiError> {
+        pg::ping(&self.db).await
+    }
+
+    fn is_sqlite(&self) -> bool {
+        false
+    }
+}
@@ -0,0 +1,356 @@
+//! Ingestion streams: datasets fed continuously by a data custodian.
+//!
+//! Protocol:
+//! 1) `POST /api/v1/streams` opens a session on a new, empty dataset, or reopens one of the
+//!    caller's uploaded or streamed datasets to append to it.
+//! 2) `POST /api/v1/streams/:id/records?sequence=n` sends the next batch of records as CSV (the
+//!    upload format). Batches are numbered from 0 per session; re-sending the last batch unchanged
+//!    is a no-op, so clients can blindly retry after a dropped connection.
+//! 3) Accepted records are buffered until they fill a shard. Each full shard is proven in the
+//!    background and appended: it is stored, then the dataset grows by one shard under a new
+//!    commitment (the chain over all its shards so far), and `shard_appended` is recorded in the
+//!    audit chain. Readers never see a dataset size covering a shard that isn't stored.
+//! 4) `GET /api/v1/streams/:id` reports progress; `POST /api/v1/streams/:id/close` takes no more
+//!    records, drops the unfilled remainder and lets pending shards finish.
+//!
+//! Buffered and pending records are held in memory only, like upload chunks; batches that would
+//! leave more than `STREAM_MAX_PENDING_SHARDS` (default 4) full shards waiting to be proven are
+//! refused with `429`. A restart loses them, but never an appended shard: reopen the stream and
+//! carry on from the dataset's size.
+
+use crate::chain::DatasetChain;
+use crate::dataset::{self, field_hex, parse_field_hex, RecordSource};
+use crate::db;
+use crate::errors::ApiError;
+use crate::models::StreamStatusResponse;
+use crate::quality::IngestQuality;
+use crate::retention;
+use crate::state::{AppState, ZkKeys};
+use crate::transparency;
+use chrono::{DateTime, Utc};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{mpsc, Mutex};
+use uuid::Uuid;
+use zk_proofs::types::Record;
+
+const DEFAULT_STREAM_MAX_PENDING_SHARDS: u64 = 4;
+
+/// How long the prover waits before checking the ZK self-test again.
+const SELF_TEST_POLL: Duration = Duration::from_secs(5);
+
+/// Open streams by dataset id.
+pub type StreamStore = Arc<Mutex<HashMap<Uuid, StreamSession>>>;
+
+pub struct StreamSession {
+    /// API key that opened the stream; only it may send records or close it.
+    pub owner: String,
+    pub opened_at: DateTime<Utc>,
+    pub last_activity: DateTime<Utc>,
+    pub shard_size: usize,
+    pub field_set: zk_proofs::types::FieldSet,
+    /// Accepted records not yet filling a shard.
+    buffer: Vec<Record>,
+    /// Rows seen across all batches of the dataset (earlier sessions and uploads included).
+    pub ingest: IngestQuality,
+    next_sequence: u64,
+    /// Hex SHA-256 of the last batch, to recognize a re-send.
+    last_batch_sha256: Option<String>,
+    /// Full shards go to the prover through this; `None` once the stream is closed or failed.
+    shards: Option<mpsc::UnboundedSender<Vec<Record>>>,
+    shards_queued: u64,
+    shards_appended: u64,
+    records_discarded: u64,
+    closed: bool,
+    error: Option<String>,
+}
+
+impl StreamSession {
+    pub fn status(&self, dataset_id: Uuid, dataset: &db::DatasetRow) -> StreamStatusResponse {
+        StreamStatusResponse {
+            dataset_id,
+            opened_at: self.opened_at,
+            last_activity: self.last_activity,
+            shard_size: self.shard_size as u64,
+            next_sequence: self.next_sequence,
+            records_buffered: self.buffer.len() as u64,
+            shards_pending: self.shards_pending(),
+            shards_appended: self.shards_appended,
+            dataset_size: dataset.dataset_size,
+            dataset_commitment_hex: dataset.commitment_hex.clone(),
+            ingest: self.ingest.clone(),
+            closed: self.closed,
+            records_discarded: self.records_discarded,
+            error: self.error.clone(),
+        }
+    }
+
+    pub fn check_owner(&self, key_id: &str) -> Result<(), ApiError> {
+        if self.owner != key_id {
+            return Err(ApiError::Forbidden("the stream was opened with another API key".to_string()));
+        }
+        Ok(())
+    }
+
+    fn shards_pending(&self) -> u64 {
+        self.shards_queued - self.shards_appended
+    }
+
+    /// Records accepted but not yet appended to the dataset.
+    pub fn records_unappended(&self) -> u64 {
+        self.buffer.len() as u64 + self.shards_pending() * self.shard_size as u64
+    }
+
+    /// Buffer batch `sequence` (its accepted `records` and parse report) and hand every shard it
+    /// fills to the prover.
+    ///
+    /// Idempotent for a re-send of the last batch; any other sequence than the next is a conflict.
+    pub fn accept(&mut self, sequence: u64, sha256_hex: &str, records: Vec<Record>, quality: &IngestQuality) -> Result<(), ApiError> {
+        if let Some(error) = &self.error {
+            return Err(ApiError::Conflict(format!("stream failed: {error}")));
+        }
+        if self.closed {
+            return Err(ApiError::Conflict("stream is closed".to_string()));
+        }
+        if sequence + 1 == self.next_sequence && self.last_batch_sha256.as_deref() == Some(sha256_hex) {
+            self.last_activity = Utc::now();
+            return Ok(());
+        }
+        if sequence != self.next_sequence {
+            return Err(ApiError::Conflict(format!("expected batch sequence {}", self.next_sequence)));
+        }
+
+        let filled = ((self.buffer.len() + records.len()) / self.shard_size) as u64;
+        let max_pending = max_pending_shards();
+        if self.shards_pending() + filled > max_pending {
+            return Err(ApiError::TooManyRequests(format!(
+                "{} shard(s) are waiting to be proven (at most {max_pending}); retry later",
+                self.shards_pending()
+            )));
+        }
+        let shards = self.shards.as_ref().ok_or(ApiError::Internal)?;
+
+        self.buffer.extend(records);
+        while self.buffer.len() >= self.shard_size {
+            let shard: Vec<Record> = self.buffer.drain(..self.shard_size).collect();
+            shards.send(shard).map_err(|_| ApiError::Internal)?;
+            self.shards_queued += 1;
+        }
+
+        self.ingest.add(quality);
+        self.next_sequence += 1;
+        self.last_batch_sha256 = Some(sha256_hex.to_string());
+        self.last_activity = Utc::now();
+        Ok(())
+    }
+
+    /// Take no more records and drop the unfilled remainder. Returns whether the session can go
+    /// now (nothing left for the prover to append).
+    pub fn close(&mut self) -> bool {
+        self.closed = true;
+        self.shards = None;
+        self.records_discarded += self.buffer.len() as u64;
+        self.buffer.clear();
+        self.last_activity = Utc::now();
+        self.shards_pending() == 0 || self.error.is_some()
+    }
+}
+
+/// Full shards allowed to wait for proving per stream (`STREAM_MAX_PENDING_SHARDS`).
+pub fn max_pending_shards() -> u64 {
+    std::env::var("STREAM_MAX_PENDING_SHARDS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .filter(|n| *n > 0)
+        .unwrap_or(DEFAULT_STREAM_MAX_PENDING_SHARDS)
+}
+
+/// Open a stream on `dataset_id` for `owner` and start its prover.
+///
+/// The dataset must be one of `owner`'s uploaded or streamed datasets, proven here with the keys
+/// loaded now (shards of one dataset verify against one key), and either ready or still empty.
+pub async fn open(state: &AppState, owner: &str, dataset_id: Uuid) -> Result<(), ApiError> {
+    if state.streams.lock().await.contains_key(&dataset_id) {
+        return Err(ApiError::Conflict("a stream is already open for this dataset".to_string()));
+    }
+
+    let Some(dataset) = state.store.get_dataset(dataset_id).await? else {
+        return Err(retention::missing_dataset(state, dataset_id).await);
+    };
+    if state.store.dataset_owner(dataset_id).await?.as_deref() != Some(owner) {
+        return Err(ApiError::Forbidden("only the API key that created the dataset can stream into it".to_string()));
+    }
+    if dataset.generator.is_some() || dataset.imported_from.is_some() {
+        return Err(ApiError::BadRequest(
+            "only uploaded or streamed datasets proven here can take streamed records".to_string(),
+        ));
+    }
+    if dataset.frozen_at.is_some() {
+        return Err(ApiError::Conflict("dataset is frozen".to_string()));
+    }
+    match dataset.status.as_str() {
+        "ready" => {}
+        "generating" if dataset.dataset_size == 0 => {}
+        other => return Err(ApiError::Conflict(format!("dataset is {other}"))),
+    }
+
+    let shard_size = dataset.shard_size as usize;
+    let keys = state
+        .ensure_keys_for(shard_size, dataset.field_set, &dataset.age_buckets, dataset.sha256_commitment)
+        .await?;
+    if let Some(manifest) = state.store.get_dataset_manifest(dataset_id).await?
+        && manifest.get("key_id").and_then(|k| k.as_str()) != Some(keys.key_id.as_str())
+    {
+        return Err(ApiError::Conflict(
+            "dataset was proven with keys that are no longer loaded".to_string(),
+        ));
+    }
+
+    // Resume the commitment chain over the shards appended so far.
+    let shards_total = dataset.shards_total();
+    let mut chain = DatasetChain::new(dataset.chain_hash);
+    for (_, commitment_hex, _, _, _) in state.store.list_shards(dataset_id, 0..shards_total, 0, shards_total, false).await? {
+        chain.absorb(&parse_field_hex(&commitment_hex).ok_or(ApiError::Internal)?)?;
+    }
+    if shards_total > 0 && Some(chain.clone().finish_hex()?) != dataset.commitment_hex {
+        return Err(ApiError::Conflict("stored shards don't chain to the dataset commitment".to_string()));
+    }
+
+    let ingest = state.store.dataset_quality(dataset_id, &dataset.age_buckets).await?.ingest.unwrap_or_default();
+    let (shards, rx) = mpsc::unbounded_channel();
+    let now = Utc::now();
+    let session = StreamSession {
+        owner: owner.to_string(),
+        opened_at: now,
+        last_activity: now,
+        shard_size,
+        field_set: dataset.field_set,
+        buffer: Vec::new(),
+        ingest,
+        next_sequence: 0,
+        last_batch_sha256: None,
+        shards: Some(shards),
+        shards_queued: 0,
+        shards_appended: 0,
+        records_discarded: 0,
+        closed: false,
+        error: None,
+    };
+    {
+        let mut streams = state.streams.lock().await;
+        if streams.contains_key(&dataset_id) {
+            return Err(ApiError::Conflict("a stream is already open for this dataset".to_string()));
+        }
+        streams.insert(dataset_id, session);
+    }
+
+    state.store.append_audit(
+        Some(dataset_id),
+        "stream_opened",
+        &serde_json::json!({ "opened_by": owner, "dataset_size": dataset.dataset_size }),
+    )
+    .await?;
+
+    tokio::spawn(run_prover(state.clone(), dataset_id, keys, chain, shards_total, rx));
+    Ok(())
+}
+
+/// Prove and append the stream's shards in order until it is closed (or a shard fails), then drop
+/// the session once closed.
+async fn run_prover(
+    state: AppState,
+    dataset_id: Uuid,
+    keys: ZkKeys,
+    mut chain: DatasetChain,
+    mut shard_index: u64,
+    mut rx: mpsc::UnboundedReceiver<Vec<Record>>,
+) {
+    while let Some(records) = rx.recv().await {
+        let res = append_shard(&state, dataset_id, &keys, &mut chain, shard_index, records).await;
+
+        let mut streams = state.streams.lock().await;
+        let Some(session) = streams.get_mut(&dataset_id) else {
+            return;
+        };
+        match res {
+            Ok(()) => {
+                session.shards_appended += 1;
+                shard_index += 1;
+            }
+            Err(e) => {
+                tracing::warn!(%dataset_id, shard_index, error = %e, "stream shard failed");
+                session.error = Some(format!("shard {shard_index}: {e}"));
+                session.shards = None;
+                // Shards queued behind the failed one are dropped with it.
+                session.records_discarded += session.shards_pending() * session.shard_size as u64;
+                session.shards_queued = session.shards_appended;
+                if session.closed {
+                    streams.remove(&dataset_id);
+                }
+                return;
+            }
+        }
+    }
+
+    // The channel ends when the session is closed. If it was closed with nothing pending it is
+    // gone already, and the dataset may have been reopened since.
+    let mut streams = state.streams.lock().await;
+    if streams.get(&dataset_id).is_some_and(|s| s.closed) {
+        streams.remove(&dataset_id);
+    }
+    tracing::info!(%dataset_id, "stream closed");
+}
+
+/// Prove one full shard as shard `shard_index` of the dataset, store it and extend the dataset
+/// over it.
+async fn append_shard(
+    state: &AppState,
+    dataset_id: Uuid,
+    keys: &ZkKeys,
+    chain: &mut DatasetChain,
+    shard_index: u64,
+    records: Vec<Record>,
+) -> Result<(), ApiError> {
+    // Nothing is proven until the ZK self-test has passed with the loaded keys.
+    while !state.zk_ready() {
+        let _ = tokio::time::timeout(SELF_TEST_POLL, state.jobs_notify.notified()).await;
+    }
+
+    let Some(mut dataset) = state.store.get_dataset(dataset_id).await? else {
+        return Err(ApiError::NotFound("dataset not found".to_string()));
+    };
+    if dataset.frozen_at.is_some() {
+        return Err(ApiError::Conflict("dataset is frozen".to_string()));
+    }
+
+    let source = RecordSource::Streamed(Arc::new(records));
+    let shard_commitment = dataset::prove_and_store_shard(state, dataset_id, &dataset, keys, &source, shard_index).await?;
+
+    chain.absorb(&shard_commitment)?;
+    let dataset_commitment_hex = chain.clone().finish_hex()?;
+    let live_from = dataset.live_shards().start;
+    dataset.dataset_size = (shard_index + 1) * dataset.shard_size;
+    if !state.store.extend_dataset(dataset_id, dataset.dataset_size, &dataset_commitment_hex).await? {
+        return Err(ApiError::Conflict("dataset is frozen".to_string()));
+    }
+    transparency::log_dataset(state, dataset_id, &dataset_commitment_hex).await?;
+
+    let manifest = dataset::build_manifest(dataset_id, &dataset, &source, keys);
+    state.store.set_dataset_manifest(dataset_id, &serde_json::to_value(&manifest).map_err(|_| ApiError::Internal)?).await?;
+    state.store.append_audit(
+        Some(dataset_id),
+        "shard_appended",
+        &serde_json::json!({
+            "shard_index": shard_index,
+            "shard_commitment_hex": field_hex(shard_commitment)?,
+            "dataset_size": dataset.dataset_size,
+            "dataset_commitment_hex": dataset_commitment_hex,
+        }),
+    )
+    .await?;
+    dataset::audit_expired_shards(state, dataset_id, &dataset, live_from).await?;
+
+    tracing::info!(%dataset_id, shard_index, "appended streamed shard");
+    Ok(())
+}
@@ -0,0 +1,119 @@
+//! Dataset statistics summary (`GET /api/v1/datasets/:id/summary`): per age bucket, the record
+//! count and each measurement's mean, plus (for blood glucose, the one measurement shards prove
+//! sums of squares for) its standard deviation and a normal-approximation confidence interval of
+//! the mean.
+//!
+//! Everything is computed from the per-bucket aggregates of the live shards, the same totals a
+//! query sums (`aggregate_for_bucket`), so the table is `server_verified` exactly when every live
+//! shard's proof was. Buckets below `MIN_CELL_COUNT` are suppressed as a query's would be, along
+//! with enough others that the dataset size minus the released counts doesn't give them away.
+
+use crate::db;
+use crate::errors::ApiError;
+use crate::models::{DatasetSummaryResponse, MeasurementSummary, QueryShardSet, QuerySuppression, SummaryBucket};
+use crate::policy;
+use crate::state::AppState;
+use uuid::Uuid;
+use zk_proofs::types::Measurement;
+
+/// Confidence level of the intervals, and its two-sided standard normal quantile.
+pub const CONFIDENCE_LEVEL: f64 = 0.95;
+const Z: f64 = 1.959_963_984_540_054;
+
+/// Sample standard deviation (Bessel-corrected) from a bucket's sum, sum of squares and count,
+/// with the numerator computed exactly in integers.
+fn sample_sd(sum: u64, sum_sq: u64, count: u64) -> Option<f64> {
+    if count < 2 {
+        return None;
+    }
+    let n = count as u128;
+    let numerator = (n * sum_sq as u128).saturating_sub(sum as u128 * sum as u128);
+    Some((numerator as f64 / (n * (n - 1)) as f64).sqrt())
+}
+
+fn measurement_summary(measurement: Measurement, totals: &db::BucketTotals) -> MeasurementSummary {
+    let mean = (totals.count > 0).then(|| totals.sum as f64 / totals.count as f64);
+    // Shards prove sums of squares of blood glucose only.
+    let sd = totals
+        .sum_sq
+        .filter(|_| measurement == Measurement::BloodGlucose)
+        .and_then(|sum_sq| sample_sd(totals.sum, sum_sq, totals.count));
+    let half_width = sd.map(|sd| Z * sd / (totals.count as f64).sqrt());
+    MeasurementSummary {
+        measurement,
+        mean,
+        sd,
+        ci_low: mean.zip(half_width).map(|(mean, h)| mean - h),
+        ci_high: mean.zip(half_width).map(|(mean, h)| mean + h),
+    }
+}
+
+/// The summary table of `dataset`, which must be ready.
+pub async fn summarize(state: &AppState, dataset_id: Uuid, dataset: &db::DatasetRow) -> Result<DatasetSummaryResponse, ApiError> {
+    let live_shards = dataset.live_shards();
+    let min_count = policy::min_cell_count();
+    let mut rows = Vec::with_capacity(dataset.age_buckets.num_buckets());
+    let mut shard_set = None;
+    for bucket_index in 0..dataset.age_buckets.num_buckets() {
+        let mut count = 0;
+        let mut measurements = Vec::with_capacity(dataset.field_set.measurements().len());
+        for (field_index, measurement) in dataset.field_set.measurements().iter().enumerate() {
+            let totals =
+                state.store.aggregate_for_bucket(dataset_id, live_shards.clone(), bucket_index, field_index, &dataset.age_buckets).await?;
+            count = totals.count;
+            measurements.push(measurement_summary(*measurement, &totals));
+            // Every bucket and field is summed over the same shards, so any one's shard set is the table's.
+            shard_set.get_or_insert_with(|| QueryShardSet {
+                dataset_commitment_hex: dataset.commitment_hex.clone(),
+                shards_total: totals.shards_total,
+                verified_bitmap_hex: hex::encode(&totals.verified_bitmap),
+                first_shard_index: live_shards.start,
+            });
+        }
+
+        rows.push((count, measurements));
+    }
+
+    let counts: Vec<u64> = rows.iter().map(|(count, _)| *count).collect();
+    let withheld = policy::suppressed_cells(&counts, min_count);
+    let buckets = rows
+        .into_iter()
+        .enumerate()
+        .map(|(bucket_index, (count, measurements))| {
+            let suppressed = if policy::below_min_count(count, min_count) {
+                Some("the bucket has too few records to release its aggregates")
+            } else if withheld[bucket_index] {
+                Some("another bucket has too few records; with this one released, the dataset size would reveal it")
+            } else {
+                None
+            }
+            .map(|reason| QuerySuppression {
+                min_count: min_count.unwrap_or_default(),
+                reason: reason.to_string(),
+            });
+            let released = suppressed.is_none();
+            SummaryBucket {
+                bucket_index,
+                bucket_range: dataset.age_buckets.bounds()[bucket_index],
+                count: released.then_some(count),
+                measurements: if released { measurements } else { Vec::new() },
+                suppressed,
+            }
+        })
+        .collect();
+
+    let shard_set = shard_set.ok_or(ApiError::Internal)?;
+    let shards_verified: u64 = hex::decode(&shard_set.verified_bitmap_hex)
+        .map_err(|_| ApiError::Internal)?
+        .iter()
+        .map(|b| b.count_ones() as u64)
+        .sum();
+    Ok(DatasetSummaryResponse {
+        dataset_id,
+        server_verified: shards_verified == live_shards.end - live_shards.start,
+        confidence_level: CONFIDENCE_LEVEL,
+        shard_set,
+        buckets,
+        aggregates_endpoint: format!("/api/v1/datasets/{dataset_id}/aggregates"),
+    })
+}
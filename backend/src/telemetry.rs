@@ -0,0 +1,138 @@
+//! Logging and tracing.
+//!
+//! Events go to stdout (`RUST_LOG` filters them, `info` by default). Work worth following end to
+//! end runs in spans: every API request (`request`, with its method, route, status and, for
+//! routes or queries naming a dataset, `dataset_id`), every dataset proving run (`prove_dataset`)
+//! and every shard proof and store within it (`prove_shard`, `store_shard`, with `dataset_id` and
+//! `shard_index`), including the wait for proving memory (`proving_admission`). Database
+//! statements slower than a second are logged by sqlx inside whichever of those spans issued them.
+//!
+//! Built with the `otlp` feature, setting `OTEL_EXPORTER_OTLP_ENDPOINT` (e.g.
+//! `http://collector:4318`) also exports the spans over OTLP/HTTP to a collector, as service
+//! `OTEL_SERVICE_NAME` (default `privacy-health-ledger`); the exporter honours the other standard
+//! `OTEL_EXPORTER_OTLP_*` variables (headers, timeout).
+
+use axum::{
+    extract::{MatchedPath, Request},
+    middleware::Next,
+    response::Response,
+};
+use tracing::Instrument;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::EnvFilter;
+use uuid::Uuid;
+
+#[cfg(feature = "otlp")]
+const DEFAULT_SERVICE_NAME: &str = "privacy-health-ledger";
+
+/// Installed tracing; `shutdown` flushes spans not yet exported.
+pub struct Telemetry {
+    #[cfg(feature = "otlp")]
+    provider: Option<opentelemetry_sdk::trace::TracerProvider>,
+}
+
+impl Telemetry {
+    pub fn shutdown(self) {
+        #[cfg(feature = "otlp")]
+        if let Some(provider) = self.provider {
+            if let Err(e) = provider.shutdown() {
+                tracing::warn!(error = %e, "OTLP exporter shutdown failed");
+            }
+        }
+    }
+}
+
+fn otlp_endpoint() -> Option<String> {
+    std::env::var("OTEL_EXPORTER_OTLP_ENDPOINT").ok().filter(|url| !url.trim().is_empty())
+}
+
+/// An OTLP/HTTP span exporter; it reads `OTEL_EXPORTER_OTLP_ENDPOINT` itself and posts to its
+/// `/v1/traces`.
+#[cfg(feature = "otlp")]
+fn otlp_provider() -> Result<opentelemetry_sdk::trace::TracerProvider, String> {
+    let exporter = opentelemetry_otlp::SpanExporter::builder()
+        .with_http()
+        .build()
+        .map_err(|e| e.to_string())?;
+    let service_name = std::env::var("OTEL_SERVICE_NAME").unwrap_or_else(|_| DEFAULT_SERVICE_NAME.to_string());
+    Ok(opentelemetry_sdk::trace::TracerProvider::builder()
+        .with_batch_exporter(exporter, opentelemetry_sdk::runtime::Tokio)
+        .with_resource(opentelemetry_sdk::Resource::new([opentelemetry::KeyValue::new("service.name", service_name)]))
+        .build())
+}
+
+/// Install the global subscriber. Call from within the Tokio runtime (the OTLP exporter runs on it).
+pub fn init() -> Telemetry {
+    // arkworks opens an INFO span for every constraint-system namespace (target `r1cs`);
+    // recording those takes gigabytes during key setup and proving.
+    let filter = EnvFilter::from_default_env()
+        .add_directive("info".parse().unwrap())
+        .add_directive("r1cs=off".parse().unwrap());
+    let registry = tracing_subscriber::registry().with(filter).with(tracing_subscriber::fmt::layer());
+
+    #[cfg(feature = "otlp")]
+    {
+        use opentelemetry::trace::TracerProvider as _;
+
+        let provider = otlp_endpoint().and_then(|endpoint| match otlp_provider() {
+            Ok(provider) => Some((endpoint, provider)),
+            Err(e) => {
+                eprintln!("OTLP exporter for {endpoint} not started: {e}");
+                None
+            }
+        });
+        let layer = provider
+            .as_ref()
+            .map(|(_, provider)| tracing_opentelemetry::layer().with_tracer(provider.tracer("backend")));
+        registry.with(layer).init();
+        if let Some((endpoint, _)) = &provider {
+            tracing::info!(%endpoint, "exporting spans over OTLP");
+        }
+        Telemetry { provider: provider.map(|(_, provider)| provider) }
+    }
+
+    #[cfg(not(feature = "otlp"))]
+    {
+        registry.init();
+        if otlp_endpoint().is_some() {
+            tracing::warn!("OTEL_EXPORTER_OTLP_ENDPOINT is set, but this build has no `otlp` feature; spans are not exported");
+        }
+        Telemetry {}
+    }
+}
+
+/// The dataset a request is about: the `:id` after `datasets`/`dataset` in its route, or its
+/// `dataset_id` query parameter.
+fn request_dataset_id(route: &str, req: &Request) -> Option<Uuid> {
+    let mut previous = "";
+    for (pattern, segment) in route.split('/').zip(req.uri().path().split('/')) {
+        if pattern == ":id" && matches!(previous, "datasets" | "dataset") {
+            return segment.parse().ok();
+        }
+        previous = pattern;
+    }
+    req.uri()
+        .query()?
+        .split('&')
+        .find_map(|pair| pair.strip_prefix("dataset_id="))
+        .and_then(|id| id.parse().ok())
+}
+
+/// Run each request in a `request` span.
+pub async fn middleware(req: Request, next: Next) -> Response {
+    let route = req.extensions().get::<MatchedPath>().map(|path| path.as_str().to_string());
+    let span = tracing::info_span!(
+        "request",
+        method = %req.method(),
+        route = route.as_deref().unwrap_or("unmatched"),
+        dataset_id = tracing::field::Empty,
+        status = tracing::field::Empty,
+    );
+    if let Some(dataset_id) = route.as_deref().and_then(|route| request_dataset_id(route, &req)) {
+        span.record("dataset_id", tracing::field::display(dataset_id));
+    }
+    let response = next.run(req).instrument(span.clone()).await;
+    span.record("status", response.status().as_u16());
+    response
+}
@@ -0,0 +1,226 @@
+//! Append-only Merkle transparency log of dataset and shard commitments.
+//!
+//! Every shard commitment the ledger stores (generated, streamed, federated, imported or re-proved)
+//! and every dataset commitment it publishes (on ready, on each append, on import) is appended as
+//! a leaf, in order, and never removed, not even when its dataset is deleted. The tree is the
+//! RFC 6962 Merkle tree over those leaves:
+//!
+//! - leaf hash: `SHA-256(0x00 || leaf)`, where `leaf` is the compact JSON of the
+//!   `TransparencyLeaf` (`kind`, `dataset_id`, `shard_index` if any, `commitment_hex`, in that order);
+//! - node hash: `SHA-256(0x01 || left || right)`, splitting `n` leaves at the largest power of two
+//!   below `n`; the empty tree's root is `SHA-256("")`.
+//!
+//! `GET /api/v1/log/sth` returns the current signed tree head (size, timestamp, root, Ed25519
+//! signature with the instance's signing key), `GET /api/v1/log/proof?first=M` the consistency
+//! proof from an earlier head of size `M`, and `GET /api/v1/log/proof?leaf_index=I` the inclusion
+//! proof of one leaf. An auditor keeping the heads it has seen can so check that the ledger never
+//! rewrote or dropped a commitment it once published.
+
+use crate::errors::ApiError;
+use crate::models::{LogProofParams, LogProofResponse, SignedTreeHead, TransparencyLeaf, TreeHeadSignature};
+use crate::state::AppState;
+use chrono::{DateTime, Utc};
+use ring::signature::KeyPair;
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+/// Prefix of every signed tree head message.
+pub const STH_DOMAIN: &[u8] = b"phl-sth-v1\n";
+
+type Hash = [u8; 32];
+
+fn leaf_hash(leaf: &TransparencyLeaf) -> Result<Hash, ApiError> {
+    let mut h = Sha256::new();
+    h.update([0x00]);
+    h.update(serde_json::to_vec(leaf).map_err(|_| ApiError::Internal)?);
+    Ok(h.finalize().into())
+}
+
+fn node_hash(left: &Hash, right: &Hash) -> Hash {
+    let mut h = Sha256::new();
+    h.update([0x01]);
+    h.update(left);
+    h.update(right);
+    h.finalize().into()
+}
+
+/// The largest power of two below `n` (`n > 1`).
+fn split(n: usize) -> usize {
+    1 << (usize::BITS - 1 - (n - 1).leading_zeros())
+}
+
+/// `MTH(D[n])` over leaf hashes.
+fn root(leaves: &[Hash]) -> Hash {
+    match leaves {
+        [] => Sha256::digest(b"").into(),
+        [leaf] => *leaf,
+        _ => {
+            let k = split(leaves.len());
+            node_hash(&root(&leaves[..k]), &root(&leaves[k..]))
+        }
+    }
+}
+
+/// `PATH(m, D[n])`: the inclusion proof of leaf `m`.
+fn path(m: usize, leaves: &[Hash]) -> Vec<Hash> {
+    if leaves.len() <= 1 {
+        return Vec::new();
+    }
+    let k = split(leaves.len());
+    let (mut proof, sibling) = if m < k {
+        (path(m, &leaves[..k]), root(&leaves[k..]))
+    } else {
+        (path(m - k, &leaves[k..]), root(&leaves[..k]))
+    };
+    proof.push(sibling);
+    proof
+}
+
+/// `SUBPROOF(m, D[n], complete)`: the consistency proof from the first `m` leaves.
+fn subproof(m: usize, leaves: &[Hash], complete: bool) -> Vec<Hash> {
+    if m == leaves.len() {
+        return if complete { Vec::new() } else { vec![root(leaves)] };
+    }
+    let k = split(leaves.len());
+    let (mut proof, sibling) = if m <= k {
+        (subproof(m, &leaves[..k], complete), root(&leaves[k..]))
+    } else {
+        (subproof(m - k, &leaves[k..], false), root(&leaves[..k]))
+    };
+    proof.push(sibling);
+    proof
+}
+
+/// Append a leaf; returns its index.
+async fn append(state: &AppState, leaf: TransparencyLeaf) -> Result<u64, ApiError> {
+    let hash = hex::encode(leaf_hash(&leaf)?);
+    state
+        .store
+        .append_log_leaf(&leaf.kind, leaf.dataset_id, leaf.shard_index, &leaf.commitment_hex, &hash)
+        .await
+}
+
+/// Log a stored shard commitment.
+pub async fn log_shard(state: &AppState, dataset_id: Uuid, shard_index: u64, commitment_hex: &str) -> Result<u64, ApiError> {
+    append(
+        state,
+        TransparencyLeaf {
+            kind: "shard".to_string(),
+            dataset_id,
+            shard_index: Some(shard_index),
+            commitment_hex: commitment_hex.to_string(),
+        },
+    )
+    .await
+}
+
+/// Log a published dataset commitment.
+pub async fn log_dataset(state: &AppState, dataset_id: Uuid, commitment_hex: &str) -> Result<u64, ApiError> {
+    append(
+        state,
+        TransparencyLeaf {
+            kind: "dataset".to_string(),
+            dataset_id,
+            shard_index: None,
+            commitment_hex: commitment_hex.to_string(),
+        },
+    )
+    .await
+}
+
+/// Leaf hashes of the first `tree_size` leaves.
+async fn leaves(state: &AppState, tree_size: u64) -> Result<Vec<Hash>, ApiError> {
+    let hashes = state.store.log_leaf_hashes(tree_size).await?;
+    if hashes.len() as u64 != tree_size {
+        return Err(ApiError::Internal);
+    }
+    hashes
+        .iter()
+        .map(|h| hex::decode(h).ok().and_then(|b| b.try_into().ok()).ok_or(ApiError::Internal))
+        .collect()
+}
+
+/// The message a tree head signature signs.
+fn sth_message(tree_size: u64, timestamp: DateTime<Utc>, root: &Hash) -> Vec<u8> {
+    let mut message = STH_DOMAIN.to_vec();
+    message.extend_from_slice(&tree_size.to_be_bytes());
+    message.extend_from_slice(&(timestamp.timestamp_millis() as u64).to_be_bytes());
+    message.extend_from_slice(root);
+    message
+}
+
+/// The current tree head, signed now.
+pub async fn tree_head(state: &AppState) -> Result<SignedTreeHead, ApiError> {
+    let tree_size = state.store.log_size().await?;
+    let leaves = leaves(state, tree_size).await?;
+    let root = tokio::task::spawn_blocking(move || root(&leaves)).await.map_err(|_| ApiError::Internal)?;
+    // Millisecond precision, so the signed timestamp is exactly the one returned.
+    let timestamp = DateTime::from_timestamp_millis(Utc::now().timestamp_millis()).ok_or(ApiError::Internal)?;
+    let signature = state.signing_key.sign(&sth_message(tree_size, timestamp, &root));
+    Ok(SignedTreeHead {
+        tree_size,
+        timestamp,
+        root_hash_hex: hex::encode(root),
+        signature: TreeHeadSignature {
+            alg: "ed25519".to_string(),
+            public_key_hex: hex::encode(state.signing_key.public_key().as_ref()),
+            signature_hex: hex::encode(signature.as_ref()),
+            sth_domain: String::from_utf8_lossy(STH_DOMAIN).into_owned(),
+        },
+    })
+}
+
+/// A consistency proof (`first`) or inclusion proof (`leaf_index`) against the tree of
+/// `tree_size` leaves (default: the current tree).
+pub async fn proof(state: &AppState, params: &LogProofParams) -> Result<LogProofResponse, ApiError> {
+    let current = state.store.log_size().await?;
+    let tree_size = params.tree_size.unwrap_or(current);
+    if tree_size > current {
+        return Err(ApiError::BadRequest(format!("tree_size {tree_size} is larger than the log ({current} leaves)")));
+    }
+    let leaf = match (params.first, params.leaf_index) {
+        (Some(first), None) => {
+            if first == 0 || first > tree_size {
+                return Err(ApiError::BadRequest(format!("first must be between 1 and {tree_size}")));
+            }
+            None
+        }
+        (None, Some(leaf_index)) => {
+            if leaf_index >= tree_size {
+                return Err(ApiError::BadRequest(format!("leaf_index must be below {tree_size}")));
+            }
+            Some(state.store.get_log_leaf(leaf_index).await?.ok_or(ApiError::Internal)?)
+        }
+        _ => return Err(ApiError::BadRequest("pass exactly one of first or leaf_index".to_string())),
+    };
+
+    let leaves = leaves(state, tree_size).await?;
+    let (first, leaf_index) = (params.first, params.leaf_index);
+    let (root, proof) = tokio::task::spawn_blocking(move || {
+        let proof = match (first, leaf_index) {
+            (Some(first), _) => subproof(first as usize, &leaves, true),
+            (_, Some(leaf_index)) => path(leaf_index as usize, &leaves),
+            _ => Vec::new(),
+        };
+        (root(&leaves), proof)
+    })
+    .await
+    .map_err(|_| ApiError::Internal)?;
+
+    Ok(LogProofResponse {
+        kind: if leaf.is_some() { "inclusion" } else { "consistency" }.to_string(),
+        first,
+        tree_size,
+        root_hash_hex: hex::encode(root),
+        leaf_index,
+        leaf_hash_hex: leaf.as_ref().map(|l| l.leaf_hash.clone()),
+        logged_at: leaf.as_ref().map(|l| l.created_at),
+        leaf: leaf.map(|l| TransparencyLeaf {
+            kind: l.kind,
+            dataset_id: l.dataset_id,
+            shard_index: l.shard_index,
+            commitment_hex: l.commitment_hex,
+        }),
+        proof_hex: proof.iter().map(hex::encode).collect(),
+    })
+}
@@ -0,0 +1,215 @@
+//! Resumable chunked uploads feeding the ingestion pipeline.
+//!
+//! Protocol:
+//! 1) `POST /api/v1/uploads` opens a session (`datasets:create`). Only the API key that opened it
+//!    can send chunks to it, read its status or commit it.
+//! 2) `POST /api/v1/uploads/:id/chunks` sends chunk `index` together with its SHA-256. Re-sending a
+//!    chunk with the same hash is a no-op, so clients can blindly retry after a dropped connection.
+//! 3) `GET /api/v1/uploads/:id` lists received chunks so an interrupted client can resume.
+//! 4) `POST /api/v1/uploads/:id/commit` checks the chunk set (and optional whole-file hash),
+//!    parses the records and hands them to the proving pipeline.
+//!
+//! Chunks are held in memory only (raw records never touch disk), so each session can buffer up to
+//! `MAX_UPLOAD_BYTES`; open sessions are capped per key (`UPLOAD_MAX_SESSIONS_PER_KEY`) and in
+//! total (`UPLOAD_MAX_SESSIONS`). Abandoned sessions are garbage-collected after `UPLOAD_TTL_SECS`.
+
+use crate::errors::ApiError;
+use crate::state::AppState;
+use chrono::{DateTime, Utc};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Mutex;
+use uuid::Uuid;
+
+/// Default cap on the assembled size of a single upload.
+const DEFAULT_MAX_UPLOAD_BYTES: u64 = 256 * 1024 * 1024;
+
+/// Missing chunk indices listed when a commit finds some.
+const MAX_REPORTED_MISSING_CHUNKS: usize = 20;
+
+/// Default time after the last chunk before an unfinished upload is discarded.
+const DEFAULT_UPLOAD_TTL_SECS: u64 = 24 * 60 * 60;
+
+/// Default cap on the sessions one API key can have open.
+const DEFAULT_UPLOAD_MAX_SESSIONS_PER_KEY: usize = 4;
+
+/// Default cap on the sessions open across all keys.
+const DEFAULT_UPLOAD_MAX_SESSIONS: usize = 16;
+
+pub type UploadStore = Arc<Mutex<HashMap<Uuid, UploadSession>>>;
+
+pub struct UploadSession {
+    /// Key id of the API key that opened the session.
+    pub owner: String,
+    pub created_at: DateTime<Utc>,
+    pub last_activity: DateTime<Utc>,
+    chunks: BTreeMap<u32, Vec<u8>>,
+    bytes: u64,
+}
+
+impl UploadSession {
+    pub fn new(owner: &str) -> Self {
+        let now = Utc::now();
+        Self {
+            owner: owner.to_string(),
+            created_at: now,
+            last_activity: now,
+            chunks: BTreeMap::new(),
+            bytes: 0,
+        }
+    }
+
+    pub fn check_owner(&self, key_id: &str) -> Result<(), ApiError> {
+        if self.owner != key_id {
+            return Err(ApiError::Forbidden("the upload was opened with another API key".to_string()));
+        }
+        Ok(())
+    }
+
+    /// Store one chunk after checking its hash.
+    ///
+    /// Idempotent for identical re-sends; a different payload for an existing index is a conflict.
+    pub fn put_chunk(&mut self, index: u32, data: Vec<u8>, sha256_hex: &str) -> Result<(), ApiError> {
+        if sha256_hex_of(&data) != sha256_hex.to_ascii_lowercase() {
+            return Err(ApiError::BadRequest(format!("chunk {index} hash mismatch")));
+        }
+
+        if let Some(existing) = self.chunks.get(&index) {
+            if *existing == data {
+                self.last_activity = Utc::now();
+                return Ok(());
+            }
+            return Err(ApiError::Conflict(format!("chunk {index} already uploaded with different content")));
+        }
+
+        if self.bytes + data.len() as u64 > max_upload_bytes() {
+            return Err(ApiError::BadRequest(format!(
+                "upload exceeds max size ({} bytes)",
+                max_upload_bytes()
+            )));
+        }
+
+        self.bytes += data.len() as u64;
+        self.chunks.insert(index, data);
+        self.last_activity = Utc::now();
+        Ok(())
+    }
+
+    pub fn received_chunks(&self) -> Vec<u32> {
+        self.chunks.keys().copied().collect()
+    }
+
+    pub fn received_bytes(&self) -> u64 {
+        self.bytes
+    }
+
+    /// Concatenate chunks `0..total_chunks`, failing if any are missing or extra.
+    pub fn assemble(&self, total_chunks: u32) -> Result<Vec<u8>, ApiError> {
+        let received = self.chunks.len() as u64;
+        if received > u64::from(total_chunks) || self.chunks.keys().next_back().is_some_and(|last| *last >= total_chunks) {
+            return Err(ApiError::BadRequest("chunks received beyond total_chunks".to_string()));
+        }
+        // Every received index is below `total_chunks`, so fewer chunks means some are missing;
+        // the scan stops after the first few (`total_chunks` is the client's).
+        if received < u64::from(total_chunks) {
+            let missing: Vec<u32> = (0..total_chunks)
+                .filter(|i| !self.chunks.contains_key(i))
+                .take(MAX_REPORTED_MISSING_CHUNKS)
+                .collect();
+            return Err(ApiError::Conflict(format!(
+                "{} chunks missing, starting with {missing:?}",
+                u64::from(total_chunks) - received
+            )));
+        }
+
+        let mut out = Vec::with_capacity(self.bytes as usize);
+        for chunk in self.chunks.values() {
+            out.extend_from_slice(chunk);
+        }
+        Ok(out)
+    }
+}
+
+/// Open a session for `owner`, refusing it when the per-key or total session cap is reached.
+pub async fn open_session(store: &UploadStore, owner: &str) -> Result<Uuid, ApiError> {
+    let mut uploads = store.lock().await;
+    let max_total = max_sessions();
+    if uploads.len() >= max_total {
+        return Err(ApiError::TooManyRequests(format!(
+            "{} uploads are open (at most {max_total}); retry later",
+            uploads.len()
+        )));
+    }
+    let max_per_key = max_sessions_per_key();
+    let owned = uploads.values().filter(|s| s.owner == owner).count();
+    if owned >= max_per_key {
+        return Err(ApiError::TooManyRequests(format!(
+            "this API key has {owned} uploads open (at most {max_per_key}); commit or let one expire first"
+        )));
+    }
+
+    let upload_id = Uuid::new_v4();
+    uploads.insert(upload_id, UploadSession::new(owner));
+    Ok(upload_id)
+}
+
+pub fn sha256_hex_of(bytes: &[u8]) -> String {
+    hex::encode(Sha256::digest(bytes))
+}
+
+pub fn max_upload_bytes() -> u64 {
+    std::env::var("MAX_UPLOAD_BYTES")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(DEFAULT_MAX_UPLOAD_BYTES)
+}
+
+/// Sessions one API key can have open (`UPLOAD_MAX_SESSIONS_PER_KEY`).
+pub fn max_sessions_per_key() -> usize {
+    std::env::var("UPLOAD_MAX_SESSIONS_PER_KEY")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .filter(|n| *n > 0)
+        .unwrap_or(DEFAULT_UPLOAD_MAX_SESSIONS_PER_KEY)
+}
+
+/// Sessions open across all keys (`UPLOAD_MAX_SESSIONS`).
+pub fn max_sessions() -> usize {
+    std::env::var("UPLOAD_MAX_SESSIONS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .filter(|n| *n > 0)
+        .unwrap_or(DEFAULT_UPLOAD_MAX_SESSIONS)
+}
+
+pub fn upload_ttl() -> Duration {
+    let secs = std::env::var("UPLOAD_TTL_SECS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(DEFAULT_UPLOAD_TTL_SECS);
+    Duration::from_secs(secs)
+}
+
+/// Drop sessions idle for longer than `ttl`. Returns how many were removed.
+pub async fn gc_stale_uploads(store: &UploadStore, ttl: Duration) -> usize {
+    let cutoff = Utc::now() - chrono::Duration::from_std(ttl).unwrap_or(chrono::Duration::MAX);
+    let mut uploads = store.lock().await;
+    let before = uploads.len();
+    uploads.retain(|_, session| session.last_activity >= cutoff);
+    before - uploads.len()
+}
+
+/// Background loop: periodically garbage-collect abandoned uploads.
+pub async fn run_gc(state: AppState) {
+    let ttl = upload_ttl();
+    let mut interval = tokio::time::interval(Duration::from_secs(60));
+    loop {
+        interval.tick().await;
+        let removed = gc_stale_uploads(&state.uploads, ttl).await;
+        if removed > 0 {
+            tracing::info!(removed, "garbage-collected stale uploads");
+        }
+    }
+}
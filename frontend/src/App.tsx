@@ -22,16 +22,7 @@ import {
   Cell
 } from 'recharts'
 import './App.css'
-import { createDataset, createQuery, getDataset, type DatasetGetResponse, type Metric } from './api'
-
-const AGE_BUCKETS = [
-  { label: '0–17', min: 0, max: 17 },
-  { label: '18–29', min: 18, max: 29 },
-  { label: '30–39', min: 30, max: 39 },
-  { label: '40–49', min: 40, max: 49 },
-  { label: '50–64', min: 50, max: 64 },
-  { label: '65–120', min: 65, max: 120 },
-] as const
+import { createDataset, createQuery, getDataset, getZkSchema, type DatasetGetResponse, type Metric } from './api'
 
 
 function App() {
@@ -43,16 +34,22 @@ function App() {
   const [bucketIndex, setBucketIndex] = useState(2)
   const [metric, setMetric] = useState<Metric>('mean')
   const [isQuerying, setIsQuerying] = useState(false)
+  const [defaultAgeBuckets, setDefaultAgeBuckets] = useState<[number, number][]>([])
 
   const [queryResult, setQueryResult] = useState<{
-    sum: number
-    count: number
+    sum: number | null
+    count: number | null
     mean: number | null
     serverVerified: boolean
     shardProofsEndpoint: string
   } | null>(null)
 
-  const selectedBucket = AGE_BUCKETS[bucketIndex]
+  // Datasets may use their own bucket layout; queries must name one of its buckets.
+  const ageBuckets = useMemo(() => {
+    return (dataset?.age_buckets ?? defaultAgeBuckets).map(([min, max]) => ({ label: `${min}–${max}`, min, max }))
+  }, [dataset?.age_buckets, defaultAgeBuckets])
+
+  const selectedBucket = ageBuckets[Math.min(bucketIndex, ageBuckets.length - 1)]
 
   const canQuery = useMemo(() => {
     return dataset?.status === 'ready' && !!datasetId
@@ -63,6 +60,13 @@ function App() {
     return Math.round((dataset.shards_done / dataset.shards_total) * 100)
   }, [dataset])
 
+  // The deployment's default layout, until a dataset with its own is loaded.
+  useEffect(() => {
+    getZkSchema()
+      .then((schema) => setDefaultAgeBuckets(schema.age_buckets))
+      .catch((e) => setBackendError((e as Error).message))
+  }, [])
+
   useEffect(() => {
     if (!datasetId) return
     let cancelled = false
@@ -105,7 +109,7 @@ function App() {
   }
 
   const onRunQuery = async () => {
-    if (!datasetId) return
+    if (!datasetId || !selectedBucket) return
     setBackendError(null)
     setIsQuerying(true)
     try {
@@ -116,9 +120,9 @@ function App() {
         age_range: { min_age: selectedBucket.min, max_age: selectedBucket.max },
       })
       setQueryResult({
-        sum: resp.sum_glucose,
+        sum: resp.sum,
         count: resp.count,
-        mean: resp.mean_glucose ?? null,
+        mean: resp.mean ?? null,
         serverVerified: resp.server_verified,
         shardProofsEndpoint: resp.shard_proofs_endpoint,
       })
@@ -132,12 +136,12 @@ function App() {
   // Sample data for the chart - in a real app, we'd fetch all buckets to show the distribution
   const chartData = useMemo(() => {
     if (!queryResult) return []
-    return AGE_BUCKETS.map((b, i) => ({
+    return ageBuckets.map((b, i) => ({
       name: b.label,
       value: i === bucketIndex ? (queryResult.mean ?? 0) : 0, // Simplified: only show active query results
       isActive: i === bucketIndex
     }))
-  }, [queryResult, bucketIndex])
+  }, [queryResult, bucketIndex, ageBuckets])
 
   return (
     <div className="page animate-fade-in">
@@ -242,7 +246,7 @@ function App() {
               <div className="control-group" style={{ flex: 1 }}>
                 <label>Cohort Age Range</label>
                 <select value={bucketIndex} onChange={(e) => setBucketIndex(Number(e.target.value))}>
-                  {AGE_BUCKETS.map((b, i) => (
+                  {ageBuckets.map((b, i) => (
                     <option key={b.label} value={i}>{b.label}</option>
                   ))}
                 </select>
@@ -279,11 +283,11 @@ function App() {
                 <div className="stats-grid">
                   <div className="stat-card">
                     <div className="stat-k">SUM GLUCOSE</div>
-                    <div className="stat-v">{queryResult.sum.toLocaleString()}</div>
+                    <div className="stat-v">{queryResult.sum == null ? 'suppressed' : queryResult.sum.toLocaleString()}</div>
                   </div>
                   <div className="stat-card">
                     <div className="stat-k">POPULATION</div>
-                    <div className="stat-v">{queryResult.count.toLocaleString()}</div>
+                    <div className="stat-v">{queryResult.count == null ? 'suppressed' : queryResult.count.toLocaleString()}</div>
                   </div>
                   <div className="stat-card" style={{ gridColumn: 'span 2' }}>
                     <div className="stat-k">MEAN BLOOD GLUCOSE (mg/dL)</div>
@@ -1,7 +1,30 @@
-export type DatasetStatus = 'generating' | 'ready' | 'failed'
+export type DatasetStatus = 'generating' | 'ready' | 'failed' | 'cancelled'
+
+export type FieldSet = 'glucose' | 'vitals'
+
+export type ChainHash = 'poseidon' | 'sha256' | 'blake3'
+
+export type Curve = 'bn254' | 'bls12_381'
+
+export type Measurement = 'blood_glucose' | 'systolic_bp' | 'heart_rate' | 'bmi'
 
 export type DatasetCreateRequest = {
   dataset_size?: number
+  shard_size?: number
+  consent_scope?: string[]
+  requires_approval?: boolean
+  release_limit?: number
+  generator?: string
+  /** Overrides of a configurable generator's default parameters. */
+  generator_params?: Record<string, number>
+  field_set?: FieldSet
+  chain_hash?: ChainHash
+  /** Dual-commitment mode: shards also get a circuit-bound SHA-256 commitment. */
+  sha256_commitment?: boolean
+  /** Inclusive [min_age, max_age] buckets covering 0..=120 in order; defaults to the six standard buckets. */
+  buckets?: [number, number][]
+  /** Rolling window: only the last this many shards are queried; earlier ones expire. */
+  window_shards?: number
 }
 
 export type DatasetCreateResponse = {
@@ -13,21 +36,276 @@ export type DatasetGetResponse = {
   created_at: string
   dataset_size: number
   shard_size: number
+  field_set: FieldSet
+  chain_hash: ChainHash
+  sha256_commitment?: boolean
   num_buckets: number
+  age_buckets?: [number, number][]
   status: DatasetStatus
   shards_total: number
   shards_done: number
   dataset_commitment_hex?: string | null
   error?: string | null
+  consent_scope?: string[] | null
+  requires_approval: boolean
+  release_limit?: number | null
+  generator?: string | null
+  generator_params?: Record<string, number>
+  frozen_at?: string | null
+  /** Signer public key of the export this dataset was imported from. */
+  imported_from?: string | null
+  /** Rolling window in shards, if the dataset has one. */
+  window_shards?: number
+  /** Shards that expired from the rolling window. */
+  shards_expired?: number
+  /** Curve proofs are served on when a request doesn't name one. */
+  default_curve?: Curve
+  /** Commitments of the dataset re-proven on other curves. */
+  curve_commitments?: CurveCommitment[]
+  /** Warnings of the latest anomaly analysis (see `getAnomalies`). */
+  anomaly_warnings?: number
+  /** First external anchor of the transparency log covering the current commitment. */
+  anchor?: DatasetAnchor
+}
+
+export type DatasetAnchor = {
+  method: 'opentimestamps' | 'ethereum' | 'webhook'
+  anchored_at: string
+  tree_size: number
+  root_hash_hex: string
+  /** Prove it into the root with `getLogProof({ leaf_index, tree_size })`. */
+  leaf_index: number
+  txid?: string
+  receipt_hex?: string
+}
+
+export type AnomalyKind = 'mean_out_of_range' | 'empty_bucket' | 'constant_glucose' | 'count_mismatch'
+
+export type AnomalyWarning = {
+  kind: AnomalyKind
+  shard_index: number
+  bucket_index?: number
+  field?: Measurement
+  /** The offending mean, expected count or record count. */
+  observed: number
+  message: string
+}
+
+export type DatasetAnomaliesResponse = {
+  dataset_id: string
+  /** Absent until the dataset is ready and first analyzed. */
+  analyzed_at: string | null
+  dataset_commitment_hex: string | null
+  shards_analyzed: number
+  warnings_total: number
+  warnings: AnomalyWarning[]
+}
+
+export type CurveCommitment = {
+  curve: Curve
+  dataset_commitment_hex: string
+  key_id: string
+  migrated_at: string
+  dual_serve_until: string
+}
+
+export type CurveMigrationRequest = {
+  curve?: Curve
+  /** All datasets if omitted. */
+  dataset_ids?: string[]
+  dry_run?: boolean
+}
+
+export type CurveMigrationPlanItem = {
+  dataset_id: string
+  action: 'reprove' | 'flag' | 'skip'
+  reason?: string | null
+}
+
+export type CurveMigrationPlanResponse = {
+  curve: Curve
+  dry_run: boolean
+  datasets: CurveMigrationPlanItem[]
+}
+
+export type CurveMigrationItem = {
+  dataset_id: string
+  status: 'queued' | 'running' | 'done' | 'flagged' | 'failed'
+  reason?: string | null
+  shards_done: number
+  shards_total: number
+  dataset_commitment_hex?: string | null
+  key_id?: string | null
+  created_at: string
+  finished_at?: string | null
+  dual_serve_until?: string | null
+}
+
+export type CurveMigrationListResponse = {
+  curve: Curve
+  transition_days: number
+  migrations: CurveMigrationItem[]
+}
+
+/** Shard circuit revision, by version tag. */
+export type CircuitRevision = 'shard-aggregate-v1' | 'shard-aggregate-v2' | 'shard-aggregate-v3' | 'shard-aggregate-v4' | 'shard-aggregate-v5'
+
+export type CircuitMigrationRequest = {
+  /** Every revision older than `to` if omitted. */
+  from?: CircuitRevision
+  /** The latest revision if omitted. */
+  to?: CircuitRevision
+  /** All datasets if omitted. */
+  dataset_ids?: string[]
+  dry_run?: boolean
 }
 
-export type Metric = 'count' | 'sum' | 'mean'
+export type CircuitMigrationPlanItem = {
+  dataset_id: string
+  circuit_revision: CircuitRevision | null
+  key_id: string | null
+  affected: boolean
+  proofs_verifiable: boolean
+  action: 'reprove' | 'flag' | 'skip'
+  reason?: string | null
+}
+
+export type CircuitMigrationPlanResponse = {
+  from?: CircuitRevision
+  to: CircuitRevision
+  dry_run: boolean
+  datasets: CircuitMigrationPlanItem[]
+}
+
+export type DatasetCancelResponse = {
+  dataset_id: string
+  status: DatasetStatus
+  shards_done: number
+  shards_total: number
+  audit_entry_hash: string
+}
+
+export type DatasetFreezeResponse = {
+  dataset_id: string
+  frozen: boolean
+  dataset_commitment_hex?: string | null
+  audit_entry_hash: string
+}
+
+export type DatasetDeleteResponse = {
+  dataset_id: string
+  deleted_at: string
+  dataset_commitment_hex?: string | null
+  archive_sha256?: string | null
+  audit_entry_hash: string
+}
+
+export type ShareLinkRequest = {
+  ttl_secs?: number
+  label?: string
+}
+
+export type ShareLinkResponse = {
+  share_id: string
+  dataset_id: string
+  token: string
+  expires_at: string
+  endpoints: string[]
+  audit_entry_hash: string
+}
+
+export type DatasetVerificationReport = {
+  dataset_id: string
+  generated_at: string
+  status: string
+  frozen_at?: string | null
+  chain_hash: ChainHash
+  dataset_commitment_hex?: string | null
+  recomputed_commitment_hex: string
+  commitment_matches: boolean
+  vk_key_id?: string | null
+  shards_total: number
+  shards_stored: number
+  shards_verified: number
+  verified_bitmap_hex: string
+  shard_failures: number
+  audit_chain_intact: boolean
+}
+
+export type Role = 'researcher' | 'approver' | 'admin'
+
+export type Scope = 'datasets:create' | 'queries:create' | 'queries:approve' | 'verify'
+
+export type ApiKeyCreateRequest = {
+  name: string
+  role: Role
+  /** Omit for every scope of the role. */
+  scopes?: Scope[]
+}
+
+export type ApiKeyInfo = {
+  key_id: string
+  name: string
+  role: Role
+  scopes: Scope[] | null
+  created_by: string
+  created_at: string
+  revoked_at: string | null
+}
+
+export type ApiKeyCreateResponse = {
+  /** Shown only in this response. */
+  key: string
+  api_key: ApiKeyInfo
+  audit_entry_hash: string
+}
+
+export type ApiKeyRevokeResponse = {
+  api_key: ApiKeyInfo
+  audit_entry_hash: string
+}
+
+/** Exactly one of `key_id` (an API key fingerprint) or `role`. */
+export type DatasetAccessGrant = { key_id: string; role?: undefined } | { role: Role; key_id?: undefined }
+
+export type DatasetAclEntry = DatasetAccessGrant & {
+  granted_by: string
+  granted_at: string
+}
+
+export type DatasetAclResponse = {
+  dataset_id: string
+  owner_key_id?: string | null
+  restricted: boolean
+  entries: DatasetAclEntry[]
+  audit_entry_hash?: string
+}
+
+export type Metric = 'count' | 'sum' | 'mean' | 'variance' | 'stddev' | 'histogram'
 
 export type QueryRequest = {
   dataset_id: string
   metric: Metric
-  field: 'blood_glucose'
-  age_range: { min_age: number; max_age: number }
+  field: Measurement
+  /** One bucket, or from one bucket's `min_age` to a later bucket's `max_age`; optional with `group_by`. */
+  age_range?: { min_age: number; max_age: number }
+  /** Answer every bucket of `age_range` separately (see `createQueryBreakdown`). */
+  group_by?: 'age_bucket'
+  /** Query everyone outside `age_range` (not with `epsilon`). */
+  complement?: boolean
+  purpose?: QueryPurpose
+  mode?: 'sync' | 'async'
+  /** Release a noisy, differentially private answer (sum/count/mean; glucose for sum/mean). */
+  epsilon?: number
+  dp_mechanism?: DpMechanism
+}
+
+export type DpMechanism = 'laplace' | 'gaussian'
+
+export type QueryPurpose = {
+  category: string
+  study_id: string
+  irb_reference: string
 }
 
 export type QueryResponse = {
@@ -35,11 +313,540 @@ export type QueryResponse = {
   dataset_id: string
   bucket_index: number
   bucket_range: [number, number]
-  sum_glucose: number
-  count: number
+  field: Measurement
+  /** In the field's unit; BMI is in tenths of kg/m². `null` when `suppressed`. */
+  sum: number | null
+  count: number | null
+  mean?: number | null
+  /** The bucket has fewer records than `MIN_CELL_COUNT`; no aggregate is released. */
+  suppressed?: { min_count: number; reason: string }
+  /** `variance` / `stddev` queries (blood glucose only). */
+  sum_sq?: number
+  variance?: number | null
+  stddev?: number | null
+  /** `histogram` queries (blood glucose only). */
+  histogram?: HistogramBin[]
+  /** Glucose queries only. */
+  sum_glucose?: number
   mean_glucose?: number | null
   server_verified: boolean
   shard_proofs_endpoint: string
+  shard_set?: QueryShardSet | null
+  /** Proof that `sum` and `count` total the shards chained into `shard_set.dataset_commitment_hex`. */
+  query_proof_b64?: string
+  query_proof?: QueryProofStatement
+  /** Present on noisy answers, which carry no query proof. */
+  dp?: DpRelease
+  /** Age ranges spanning several buckets: the bucket totals added up. */
+  combined_buckets?: { bucket_index: number; bucket_range: [number, number] }[]
+  /** Complement answers: the excluded bucket and the bucket totals added up. */
+  complement?: QueryComplement
+  /** Detached signature over the rest of the response; check it with `verifyReceipt`. */
+  receipt?: QueryReceipt
+}
+
+export type QueryReceipt = {
+  alg: 'ed25519'
+  public_key_hex: string
+  signed_at: string
+  signature_hex: string
+}
+
+export type ReceiptVerifyResponse = {
+  valid: boolean
+  signed_by_this_instance: boolean
+  public_key_hex: string
+  signed_at: string
+  query_id?: string | null
+}
+
+export type SigningKeyResponse = {
+  alg: 'ed25519'
+  public_key_hex: string
+  receipt_domain: string
+}
+
+export type TransparencyLeaf = {
+  kind: 'dataset' | 'shard'
+  dataset_id: string
+  shard_index?: number
+  commitment_hex: string
+}
+
+export type SignedTreeHead = {
+  tree_size: number
+  timestamp: string
+  root_hash_hex: string
+  signature: { alg: 'ed25519'; public_key_hex: string; signature_hex: string; sth_domain: string }
+}
+
+export type LogProofResponse = {
+  kind: 'consistency' | 'inclusion'
+  first?: number
+  tree_size: number
+  root_hash_hex: string
+  leaf_index?: number
+  leaf?: TransparencyLeaf
+  leaf_hash_hex?: string
+  logged_at?: string
+  proof_hex: string[]
+}
+
+export type QueryComplement = {
+  excluded_bucket_index: number
+  excluded_range: [number, number]
+  combined: { bucket_index: number; bucket_range: [number, number] }[]
+  aggregates_endpoint: string
+}
+
+/** Pools one measurement over 2–16 ready datasets with the same age buckets. */
+/** Per-bucket answers of a `group_by` query; none carries a query proof. */
+export type QueryBreakdownResponse = {
+  dataset_id: string
+  group_by: 'age_bucket'
+  field: Measurement
+  buckets: QueryResponse[]
+}
+
+export type CohortQueryRequest = {
+  dataset_ids: string[]
+  field: Measurement
+  purpose?: QueryPurpose
+}
+
+export type CohortQueryResponse = {
+  cohort_id: string
+  field: Measurement
+  buckets: CohortBucket[]
+  /** True only if every live shard of every dataset is verified. */
+  server_verified: boolean
+  datasets: CohortDataset[]
+}
+
+export type CohortBucket = {
+  bucket_index: number
+  bucket_range: [number, number]
+  /** `null` when `suppressed` (the pooled bucket or a dataset's share is below `MIN_CELL_COUNT`). */
+  sum: number | null
+  count: number | null
+  mean: number | null
+  suppressed?: { min_count: number; reason: string }
+}
+
+export type CohortDataset = {
+  dataset_id: string
+  server_verified: boolean
+  shard_set: QueryShardSet
+  shard_proofs_endpoint: string
+  /** The dataset's share of each released bucket, recorded as a query of the dataset. */
+  query_ids: { bucket_index: number; query_id: string }[]
+}
+
+export type DpRelease = {
+  mechanism: DpMechanism
+  epsilon: number
+  delta?: number
+  sum_sensitivity: number
+  count_sensitivity: number
+  sum_noise_scale: number
+  count_noise_scale: number
+  epsilon_remaining?: number
+}
+
+export type SchemaField = {
+  name: Measurement
+  unit: string
+  /** Bits each value is range-checked to in the circuit. */
+  bits: number
+  field_sets: FieldSet[]
+  plausible_range?: [number, number]
+}
+
+export type ZkSchemaResponse = {
+  dataset_id?: string
+  age_buckets: [number, number][]
+  num_buckets: number
+  max_buckets: number
+  max_age: number
+  age_bits: number
+  shard_size: number
+  supported_shard_sizes: number[]
+  field_set: FieldSet
+  fields: SchemaField[]
+  glucose_ranges: [number, number][]
+  circuit_version: string
+  circuit_versions: string[]
+  circuit_id: string
+  chain_hash: ChainHash
+  poseidon: { width: number; full_rounds: number; partial_rounds: number; alpha: number }
+  curves: Curve[]
+}
+
+export type PrivacyBudgetResponse = {
+  dataset_id: string
+  /** `null` means uncapped. */
+  epsilon_budget: number | null
+  epsilon_spent: number
+  epsilon_remaining: number | null
+  delta_spent: number
+  releases: number
+}
+
+export type QueryProofStatement = {
+  shard_inputs_root_hex: string
+  sum_index: number
+  count_index: number
+  vk_b64: string
+  key_id: string
+}
+
+export type HistogramBin = {
+  /** Inclusive glucose bounds, mg/dL. */
+  glucose_range: [number, number]
+  count: number
+}
+
+export type QueryShardSet = {
+  dataset_commitment_hex?: string | null
+  shards_total: number
+  verified_bitmap_hex: string
+  /** First shard summed; earlier ones had expired from the rolling window. */
+  first_shard_index?: number
+}
+
+/** Per-bucket descriptive statistics from the live shards' proven aggregates. */
+export type DatasetSummaryResponse = {
+  dataset_id: string
+  server_verified: boolean
+  confidence_level: number
+  shard_set: QueryShardSet
+  buckets: SummaryBucket[]
+  aggregates_endpoint: string
+}
+
+export type SummaryBucket = {
+  bucket_index: number
+  bucket_range: [number, number]
+  /** `null` (and `measurements` empty) when `suppressed`. */
+  count?: number | null
+  measurements: MeasurementSummary[]
+  suppressed?: { min_count: number; reason: string }
+}
+
+export type MeasurementSummary = {
+  measurement: Measurement
+  mean?: number | null
+  /** Sample standard deviation; blood glucose only. */
+  sd?: number | null
+  ci_low?: number | null
+  ci_high?: number | null
+}
+
+export type CellDisclosure = {
+  bucket_index: number
+  bucket_range: [number, number]
+  filter: string
+  release_count: number
+  first_released_at: string
+  last_released_at: string
+  level: 'ok' | 'approaching' | 'exceeded'
+}
+
+export type DisclosureResponse = {
+  dataset_id: string
+  threshold: number
+  cells: CellDisclosure[]
+}
+
+export type UsageResponse = {
+  key_id: string
+  datasets: number
+  records: number
+  proving_running: number
+  proving_queued: number
+  /** Queries submitted today (UTC). */
+  queries_today: number
+  /** `null` means unlimited. */
+  limits: {
+    max_datasets: number | null
+    max_records: number | null
+    max_concurrent_proving: number | null
+    max_queries_per_day: number | null
+  }
+  /** Token buckets per key; `null` means unlimited. */
+  rate_limits: {
+    requests: RateLimit | null
+    proving: RateLimit | null
+  }
+}
+
+export type RateLimit = {
+  tokens_per_sec: number
+  burst: number
+}
+
+export type ShardFailure = {
+  shard_index: number
+  error_class: 'records' | 'prove' | 'verify' | 'serialize' | 'panic'
+  attempts: number
+  last_error: string
+  first_failed_at: string
+  last_failed_at: string
+}
+
+export type ShardFailuresResponse = {
+  dataset_id: string
+  failures: ShardFailure[]
+}
+
+/** Returned with 202 when the dataset requires approval before results are released. */
+export type QueryPendingResponse = {
+  query_id: string
+  dataset_id: string
+  status: 'pending_approval' | 'queued'
+  status_endpoint: string
+}
+
+export type QueryStatus = 'pending_approval' | 'queued' | 'running' | 'released' | 'rejected' | 'failed'
+
+export type QueryStatusResponse = {
+  query_id: string
+  dataset_id: string
+  status: QueryStatus
+  result?: QueryResponse | null
+  error?: string | null
+}
+
+/** A stored query, for reproducibility audits. */
+export type QueryRecord = {
+  query_id: string
+  dataset_id: string
+  status: QueryStatus
+  created_at: string
+  released_at?: string | null
+  decided_by?: string | null
+  query_json: Record<string, unknown>
+  result?: QueryResponse | null
+  /** Exact stored result, even when suppressed; admins only. */
+  result_json?: Record<string, unknown>
+  server_verified?: boolean | null
+  error?: string | null
+}
+
+export type QueryListResponse = {
+  dataset_id: string
+  offset: number
+  limit: number
+  queries: QueryRecord[]
+}
+
+/** One entry of a `POST /api/v1/verify/shards` batch. */
+export type ShardProofRequest = {
+  proof_b64: string
+  public_shard_commitment_hex: string
+  public_sum_glucose_by_bucket: number[]
+  public_count_by_bucket: number[]
+  public_extra_sums_by_bucket?: number[][]
+  public_sum_glucose_sq_by_bucket?: number[] | null
+  public_glucose_histogram_by_bucket?: number[][] | null
+  public_salt_commitment_hex?: string | null
+  public_sha256_commitment_hex?: string | null
+  /** Required for proofs that bound glucose (see `ShardListItem.glucose_bounds`). */
+  public_glucose_bounds?: [number, number] | null
+}
+
+/** Exactly one of `vk_b64` or `key_id` (a BN254 shard key this ledger has used). */
+export type VerifyShardsRequest = ({ vk_b64: string; key_id?: undefined } | { key_id: string; vk_b64?: undefined }) & {
+  /** Curve of the key and proofs; `bn254` if omitted. */
+  curve?: Curve
+  shards: ShardProofRequest[]
+}
+
+export type VerifyShardsResponse = {
+  ok: boolean
+  shards_total: number
+  /** Indices into `shards` of proofs that failed verification or couldn't be decoded. */
+  invalid: number[]
+  /** One per entry of `shards`, in order. */
+  results: { ok: boolean; error?: string }[]
+  /** False if the request's deadline was reached before every entry was checked. */
+  complete: boolean
+  /** Indices into `shards` that weren't checked; resubmit them. */
+  unchecked: number[]
+}
+
+/** One shard of `GET /api/v1/datasets/:id/shards`; pass it as JSON to the WASM verifier's `verifyShardProof`. */
+export type ShardListItem = {
+  shard_index: number
+  shard_commitment_hex: string
+  sum_glucose_by_bucket: number[]
+  count_by_bucket: number[]
+  extra_sums_by_bucket?: number[][]
+  sum_glucose_sq_by_bucket?: number[]
+  glucose_histogram_by_bucket?: number[][]
+  salt_commitment_hex?: string
+  sha256_commitment_hex?: string
+  /** Inclusive `[min, max]` mg/dL every record's glucose is proven to lie within; absent for shards proven with older keys. */
+  glucose_bounds?: [number, number]
+  /** Age buckets zeroed by `mask_small_counts=true`. */
+  masked_buckets?: number[]
+  verified: boolean
+  expired: boolean
+  /** Id of the verifying key the proof was checked with (`getVkVersion`); absent for shards stored before key versions were recorded. */
+  vk_version?: string
+  /** When the proof last verified (stored, re-verified or sampled), and against which key id. */
+  verified_at?: string
+  verifier_vk_hash?: string
+  /** Only with `include_proof=true`. */
+  proof_b64: string | null
+}
+
+/** One line of `GET /api/v1/datasets/:id/shards/export`. */
+export type ShardExportLine = ShardListItem & {
+  /** Hex field elements the proof verifies against, in circuit order. */
+  public_inputs_hex: string[]
+  /** snarkjs `proof.json` of the shard (`format: 'snarkjs'`). */
+  snarkjs_proof?: SnarkjsProof
+  /** snarkjs `public.json`: the public inputs as decimal strings (`format: 'snarkjs'`). */
+  snarkjs_public_signals?: string[]
+}
+
+export type SnarkjsProof = {
+  pi_a: string[]
+  pi_b: string[][]
+  pi_c: string[]
+  protocol: 'groth16'
+  curve: 'bn128'
+}
+
+/** A BN254 verifying key as snarkjs' `verification_key.json`. */
+export type SnarkjsVerifyingKey = {
+  protocol: 'groth16'
+  curve: 'bn128'
+  nPublic: number
+  vk_alpha_1: string[]
+  vk_beta_2: string[][]
+  vk_gamma_2: string[][]
+  vk_delta_2: string[][]
+  IC: string[][]
+}
+
+export type ShardListResponse = {
+  dataset_id: string
+  offset: number
+  limit: number
+  shard_index_from: number | null
+  shard_index_to: number | null
+  shards_total: number
+  curve: Curve
+  shards: ShardListItem[]
+}
+
+export type ZkVkResponse = {
+  curve: string
+  proof_system: string
+  vk_b64: string
+  /** Hex SHA-256 of the verifying key. */
+  key_id: string
+}
+
+export type ZkKeyUsage = {
+  key_id: string
+  circuit: 'shard' | 'shard-bls12-381' | 'aggregate' | 'query'
+  first_seen_at: string
+  last_proof_at: string
+  age_days: number
+  proofs_created: number
+  datasets_covered: number
+  rotation_due: boolean
+  rotation_reasons: string[]
+}
+
+export type ZkKeysResponse = {
+  /** `null` means no threshold. */
+  thresholds: {
+    max_age_days: number | null
+    max_proofs: number | null
+  }
+  keys: ZkKeyUsage[]
+}
+
+/** Public inputs summed over every shard; shaped like one shard's aggregates. */
+export type ShardTotals = {
+  sum_glucose_by_bucket: number[]
+  count_by_bucket: number[]
+  extra_sums_by_bucket?: number[][]
+  sum_glucose_sq_by_bucket?: number[] | null
+  glucose_histogram_by_bucket?: number[][] | null
+}
+
+export type AggregateShardPath = {
+  shard_index: number
+  leaf_hex: string
+  /** From the leaf level up; bit `d` of `shard_index` marks a right child at depth `d`. */
+  siblings_hex: string[]
+}
+
+export type AggregateProofResponse = {
+  dataset_id: string
+  created_at: string
+  dataset_commitment_hex: string
+  shard_inputs_root_hex: string
+  shards_total: number
+  totals: ShardTotals
+  proof_b64: string
+  vk_b64: string
+  key_id: string
+  shard_path?: AggregateShardPath
+}
+
+/** `event: progress` of `GET /api/v1/datasets/:id/events`. */
+export type DatasetProgressEvent = {
+  dataset_id: string
+  status: DatasetStatus
+  shards_done: number
+  shards_total: number
+  shards_per_sec?: number
+  eta_secs?: number
+}
+
+/** The latest re-verification of every shard proof of a dataset. */
+export type DatasetReverificationReport = {
+  dataset_id: string
+  finished_at: string
+  status: 'passed' | 'failed'
+  dataset_commitment_hex: string
+  /** False once the dataset has changed since; re-verify to cover it. */
+  current: boolean
+  shards_total: number
+  shards_checked: number
+  failed_shards: number[]
+  commitment_matches: boolean
+  duration_ms: number
+}
+
+/** Returned with 202 while a re-verification is queued or running. */
+export type DatasetReverificationPending = {
+  dataset_id: string
+  status: 'queued' | 'running'
+  events_endpoint: string
+  report_endpoint: string
+  error?: string
+}
+
+export type DatasetReverificationEvent = {
+  dataset_id: string
+  status: 'queued' | 'running' | 'passed' | 'failed' | 'error'
+  shards_checked: number
+  shards_total: number
+  failures: number
+}
+
+/** Returned with 202 while the aggregate proof is being made. */
+export type AggregatePendingResponse = {
+  dataset_id: string
+  status: 'queued' | 'running'
+  error?: string
 }
 
 const API_KEY = 'dev-secret-key'
@@ -79,9 +886,288 @@ export function getDataset(id: string): Promise<DatasetGetResponse> {
   return fetchJson<DatasetGetResponse>(`/api/v1/datasets/${id}`)
 }
 
+/** Follow a dataset's proving progress until it is ready or failed; returns a function that stops. */
+export function watchDatasetProgress(id: string, onEvent: (event: DatasetProgressEvent) => void): () => void {
+  const source = new EventSource(`/api/v1/datasets/${id}/events`)
+  source.addEventListener('progress', (msg) => {
+    const event = JSON.parse((msg as MessageEvent<string>).data) as DatasetProgressEvent
+    onEvent(event)
+    if (event.status !== 'generating') source.close()
+  })
+  return () => source.close()
+}
+
+export function getAnomalies(id: string): Promise<DatasetAnomaliesResponse> {
+  return fetchJson<DatasetAnomaliesResponse>(`/api/v1/datasets/${id}/anomalies`)
+}
+
+export function getAggregateProof(
+  id: string,
+  shardIndex?: number,
+): Promise<AggregateProofResponse | AggregatePendingResponse> {
+  const query = shardIndex === undefined ? '' : `?shard_index=${shardIndex}`
+  return fetchJson<AggregateProofResponse | AggregatePendingResponse>(`/api/v1/datasets/${id}/aggregate-proof${query}`)
+}
+
+export function getDatasetSummary(id: string): Promise<DatasetSummaryResponse> {
+  return fetchJson<DatasetSummaryResponse>(`/api/v1/datasets/${id}/summary`)
+}
+
+export function listShards(
+  id: string,
+  opts: { includeProof?: boolean; offset?: number; limit?: number } = {},
+): Promise<ShardListResponse> {
+  const params = new URLSearchParams()
+  if (opts.includeProof) params.set('include_proof', 'true')
+  if (opts.offset !== undefined) params.set('offset', String(opts.offset))
+  if (opts.limit !== undefined) params.set('limit', String(opts.limit))
+  const query = params.toString() ? `?${params}` : ''
+  return fetchJson<ShardListResponse>(`/api/v1/datasets/${id}/shards${query}`)
+}
+
+/** Every shard of a dataset, read from the NDJSON export as it streams in. */
+export async function* exportShards(
+  id: string,
+  opts: { includeProof?: boolean; shardIndexFrom?: number; shardIndexTo?: number; format?: 'snarkjs' } = {},
+): AsyncGenerator<ShardExportLine> {
+  const params = new URLSearchParams()
+  if (opts.includeProof === false) params.set('include_proof', 'false')
+  if (opts.format) params.set('format', opts.format)
+  if (opts.shardIndexFrom !== undefined) params.set('shard_index_from', String(opts.shardIndexFrom))
+  if (opts.shardIndexTo !== undefined) params.set('shard_index_to', String(opts.shardIndexTo))
+  const query = params.toString() ? `?${params}` : ''
+  const res = await fetch(`/api/v1/datasets/${id}/shards/export${query}`, { headers: { 'x-api-key': API_KEY } })
+  if (!res.ok || !res.body) throw new Error(`${res.status} ${res.statusText}`)
+
+  const reader = res.body.pipeThrough(new TextDecoderStream()).getReader()
+  let buffered = ''
+  for (;;) {
+    const { done, value } = await reader.read()
+    if (done) break
+    buffered += value
+    const lines = buffered.split('\n')
+    buffered = lines.pop() ?? ''
+    for (const line of lines) if (line.trim()) yield JSON.parse(line) as ShardExportLine
+  }
+  if (buffered.trim()) yield JSON.parse(buffered) as ShardExportLine
+}
+
+/** The verifying key a dataset's shards were proven with. */
+export function getSigningKey(): Promise<SigningKeyResponse> {
+  return fetchJson<SigningKeyResponse>('/api/v1/keys/signing')
+}
+
+/** Check a query response's receipt (or `receipt`, if it was kept apart from the response). */
+export function verifyReceipt(response: QueryResponse, receipt?: QueryReceipt): Promise<ReceiptVerifyResponse> {
+  return fetchJson<ReceiptVerifyResponse>('/api/v1/receipts/verify', {
+    method: 'POST',
+    body: JSON.stringify({ response, receipt }),
+  })
+}
+
+/** The current signed head of the transparency log of dataset and shard commitments. */
+export function getLogTreeHead(): Promise<SignedTreeHead> {
+  return fetchJson<SignedTreeHead>('/api/v1/log/sth')
+}
+
+/** Consistency proof from an earlier tree size `first`, or inclusion proof of `leaf_index`. */
+export function getLogProof(params: { first?: number; leaf_index?: number; tree_size?: number }): Promise<LogProofResponse> {
+  const query = new URLSearchParams()
+  for (const [key, value] of Object.entries(params)) {
+    if (value !== undefined) query.set(key, String(value))
+  }
+  return fetchJson<LogProofResponse>(`/api/v1/log/proof?${query}`)
+}
+
+export function getVk(datasetId: string): Promise<ZkVkResponse> {
+  return fetchJson<ZkVkResponse>(`/api/v1/zk/vk?dataset_id=${datasetId}`)
+}
+
+/** A shard verifying key generation by id (a shard's `vk_version`). */
+export function getVkVersion(version: string): Promise<ZkVkResponse> {
+  return fetchJson<ZkVkResponse>(`/api/v1/zk/vk/${version}`)
+}
+
+/** Solidity source of a contract verifying a dataset's shard proofs on-chain. */
+export async function getSolidityVerifier(datasetId: string): Promise<string> {
+  const res = await fetch(`/api/v1/zk/verifier.sol?dataset_id=${datasetId}`, { headers: { 'x-api-key': API_KEY } })
+  if (!res.ok) throw new Error(`${res.status} ${res.statusText}`)
+  return res.text()
+}
+
+/** A dataset's verifying key as snarkjs' `verification_key.json`. */
+export function getSnarkjsVk(datasetId: string): Promise<SnarkjsVerifyingKey> {
+  return fetchJson<SnarkjsVerifyingKey>(`/api/v1/zk/vk?dataset_id=${datasetId}&format=snarkjs`)
+}
+
+export function cancelDataset(id: string): Promise<DatasetCancelResponse> {
+  return fetchJson<DatasetCancelResponse>(`/api/v1/datasets/${id}/cancel`, { method: 'POST' })
+}
+
+export function freezeDataset(id: string): Promise<DatasetFreezeResponse> {
+  return fetchJson<DatasetFreezeResponse>(`/api/v1/datasets/${id}/freeze`, { method: 'POST' })
+}
+
+export function unfreezeDataset(id: string): Promise<DatasetFreezeResponse> {
+  return fetchJson<DatasetFreezeResponse>(`/api/v1/datasets/${id}/unfreeze`, { method: 'POST' })
+}
+
+export function deleteDataset(id: string): Promise<DatasetDeleteResponse> {
+  return fetchJson<DatasetDeleteResponse>(`/api/v1/datasets/${id}`, { method: 'DELETE' })
+}
+
+export function createShareLink(id: string, req: ShareLinkRequest = {}): Promise<ShareLinkResponse> {
+  return fetchJson<ShareLinkResponse>(`/api/v1/datasets/${id}/share`, { method: 'POST', body: JSON.stringify(req) })
+}
+
+export function reverifyDataset(id: string): Promise<DatasetReverificationPending> {
+  return fetchJson<DatasetReverificationPending>(`/api/v1/verify/dataset/${id}`, { method: 'POST' })
+}
+
+export function getDatasetReverification(id: string): Promise<DatasetReverificationReport | DatasetReverificationPending> {
+  return fetchJson<DatasetReverificationReport | DatasetReverificationPending>(`/api/v1/verify/dataset/${id}`)
+}
+
+/** A dataset's re-verification progress, read from its SSE stream until the run has ended. */
+export async function* watchDatasetReverification(id: string): AsyncGenerator<DatasetReverificationEvent> {
+  // EventSource can't send the API key, so the stream is read with fetch.
+  const res = await fetch(`/api/v1/verify/dataset/${id}/events`, { headers: { 'x-api-key': API_KEY } })
+  if (!res.ok || !res.body) throw new Error(`${res.status} ${res.statusText}`)
+
+  const reader = res.body.pipeThrough(new TextDecoderStream()).getReader()
+  let buffered = ''
+  for (;;) {
+    const { done, value } = await reader.read()
+    if (done) break
+    buffered += value
+    const messages = buffered.split('\n\n')
+    buffered = messages.pop() ?? ''
+    for (const message of messages) {
+      const data = message
+        .split('\n')
+        .filter((line) => line.startsWith('data:'))
+        .map((line) => line.slice(5).trimStart())
+        .join('\n')
+      if (data) yield JSON.parse(data) as DatasetReverificationEvent
+    }
+  }
+}
+
+export function getVerificationReport(id: string): Promise<DatasetVerificationReport> {
+  return fetchJson<DatasetVerificationReport>(`/api/v1/datasets/${id}/verification-report`)
+}
+
+export function createApiKey(req: ApiKeyCreateRequest): Promise<ApiKeyCreateResponse> {
+  return fetchJson<ApiKeyCreateResponse>('/api/v1/admin/keys', { method: 'POST', body: JSON.stringify(req) })
+}
+
+export function listApiKeys(): Promise<{ keys: ApiKeyInfo[] }> {
+  return fetchJson<{ keys: ApiKeyInfo[] }>('/api/v1/admin/keys')
+}
+
+export function revokeApiKey(keyId: string): Promise<ApiKeyRevokeResponse> {
+  return fetchJson<ApiKeyRevokeResponse>(`/api/v1/admin/keys/${keyId}`, { method: 'DELETE' })
+}
+
+export function getDatasetAcl(id: string): Promise<DatasetAclResponse> {
+  return fetchJson<DatasetAclResponse>(`/api/v1/datasets/${id}/acl`)
+}
+
+export function grantDatasetAccess(id: string, grant: DatasetAccessGrant): Promise<DatasetAclResponse> {
+  return fetchJson<DatasetAclResponse>(`/api/v1/datasets/${id}/acl`, { method: 'POST', body: JSON.stringify(grant) })
+}
+
+export function revokeDatasetAccess(id: string, grant: DatasetAccessGrant): Promise<DatasetAclResponse> {
+  const query = grant.key_id !== undefined ? `key_id=${grant.key_id}` : `role=${grant.role}`
+  return fetchJson<DatasetAclResponse>(`/api/v1/datasets/${id}/acl?${query}`, { method: 'DELETE' })
+}
+
 export function createQuery(req: QueryRequest): Promise<QueryResponse> {
   return fetchJson<QueryResponse>('/api/v1/queries', {
     method: 'POST',
     body: JSON.stringify(req),
   })
 }
+
+/** Sum/count/mean (or any metric) of every age bucket in one request. */
+export function createQueryBreakdown(
+  req: Omit<QueryRequest, 'group_by' | 'complement' | 'mode' | 'epsilon' | 'dp_mechanism'>,
+): Promise<QueryBreakdownResponse> {
+  return fetchJson<QueryBreakdownResponse>('/api/v1/queries', {
+    method: 'POST',
+    body: JSON.stringify({ ...req, group_by: 'age_bucket' }),
+  })
+}
+
+export function createCohortQuery(req: CohortQueryRequest): Promise<CohortQueryResponse> {
+  return fetchJson<CohortQueryResponse>('/api/v1/queries/cohort', {
+    method: 'POST',
+    body: JSON.stringify(req),
+  })
+}
+
+export function getUsage(): Promise<UsageResponse> {
+  return fetchJson<UsageResponse>('/api/v1/usage')
+}
+
+export function getZkKeys(): Promise<ZkKeysResponse> {
+  return fetchJson<ZkKeysResponse>('/api/v1/zk/keys')
+}
+
+export function getZkSchema(datasetId?: string): Promise<ZkSchemaResponse> {
+  const query = datasetId ? `?dataset_id=${datasetId}` : ''
+  return fetchJson<ZkSchemaResponse>(`/api/v1/zk/schema${query}`)
+}
+
+export function getPrivacyBudget(datasetId: string): Promise<PrivacyBudgetResponse> {
+  return fetchJson<PrivacyBudgetResponse>(`/api/v1/datasets/${datasetId}/privacy-budget`)
+}
+
+export function getQueryStatus(id: string): Promise<QueryStatusResponse> {
+  return fetchJson<QueryStatusResponse>(`/api/v1/queries/${id}/status`)
+}
+
+export function getQuery(id: string): Promise<QueryRecord> {
+  return fetchJson<QueryRecord>(`/api/v1/queries/${id}`)
+}
+
+export function listDatasetQueries(id: string, opts: { offset?: number; limit?: number } = {}): Promise<QueryListResponse> {
+  const params = new URLSearchParams()
+  if (opts.offset !== undefined) params.set('offset', String(opts.offset))
+  if (opts.limit !== undefined) params.set('limit', String(opts.limit))
+  const query = params.toString() ? `?${params}` : ''
+  return fetchJson<QueryListResponse>(`/api/v1/datasets/${id}/queries${query}`)
+}
+
+export function approveQuery(id: string): Promise<QueryResponse> {
+  return fetchJson<QueryResponse>(`/api/v1/queries/${id}/approve`, { method: 'POST' })
+}
+
+export function rejectQuery(id: string): Promise<{ query_id: string; status: 'rejected' }> {
+  return fetchJson(`/api/v1/queries/${id}/reject`, { method: 'POST' })
+}
+
+export function planCurveMigration(req: CurveMigrationRequest): Promise<CurveMigrationPlanResponse> {
+  return fetchJson<CurveMigrationPlanResponse>('/api/v1/admin/curve-migrations', {
+    method: 'POST',
+    body: JSON.stringify(req),
+  })
+}
+
+export function listCurveMigrations(): Promise<CurveMigrationListResponse> {
+  return fetchJson<CurveMigrationListResponse>('/api/v1/admin/curve-migrations')
+}
+
+export function planCircuitMigration(req: CircuitMigrationRequest): Promise<CircuitMigrationPlanResponse> {
+  return fetchJson<CircuitMigrationPlanResponse>('/api/v1/admin/circuit-migrations', {
+    method: 'POST',
+    body: JSON.stringify(req),
+  })
+}
+
+export function verifyShards(req: VerifyShardsRequest): Promise<VerifyShardsResponse> {
+  return fetchJson<VerifyShardsResponse>('/api/v1/verify/shards', {
+    method: 'POST',
+    body: JSON.stringify(req),
+  })
+}
@@ -0,0 +1,117 @@
+//! Local state of the agent, kept in its state directory so it can resume after an outage or a
+//! restart:
+//! - `state.json`: the registered dataset, the files already read, the records not yet filling a
+//!   shard, and the proven shards the backend has not acknowledged yet. It is rewritten atomically
+//!   after every change, so a shard is either still buffered as records or queued as a proof,
+//!   never both or neither.
+//! - `keys/`: the site's Groth16 keys, set up on first run. The proving key never leaves the site;
+//!   the verifying key is registered with the backend.
+//!
+//! The buffered records are the site's own data, as sensitive as the files they were read from.
+
+use rand::rngs::OsRng;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+use zk_proofs::groth16::{deserialize_pk, serialize_pk, serialize_vk};
+use zk_proofs::registry::setup_keys_for;
+use zk_proofs::types::{AgeBuckets, FieldSet, Record, ShardStats};
+
+use ark_bn254::Bn254;
+use ark_groth16::ProvingKey;
+
+const STATE_FILE: &str = "state.json";
+
+/// A proven shard waiting to be pushed; serialized as the backend's shard push body.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct PendingShard {
+    pub shard_index: u64,
+    pub shard_commitment_hex: String,
+    pub stats: ShardStats,
+    pub proof_b64: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct AgentState {
+    pub site: String,
+    pub shard_size: usize,
+    pub field_set: FieldSet,
+    /// Federated dataset registered with the backend; `None` until the first registration.
+    pub dataset_id: Option<Uuid>,
+    /// Index the next proven shard gets.
+    pub next_shard_index: u64,
+    /// Watched files already read, by name.
+    pub files_done: BTreeSet<String>,
+    /// Records read but not yet filling a shard.
+    pub buffer: Vec<Record>,
+    /// Proven shards not yet acknowledged by the backend, in shard order.
+    pub outbox: Vec<PendingShard>,
+}
+
+impl AgentState {
+    /// Load the state of `dir`, or start a fresh one; an existing state must have been set up for
+    /// the same site, shard size and field set.
+    pub fn load_or_new(dir: &Path, site: &str, shard_size: usize, field_set: FieldSet) -> Result<Self, String> {
+        let path = dir.join(STATE_FILE);
+        let state = match std::fs::read(&path) {
+            Ok(bytes) => serde_json::from_slice::<AgentState>(&bytes).map_err(|e| format!("{}: {e}", path.display()))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => AgentState {
+                site: site.to_string(),
+                shard_size,
+                field_set,
+                dataset_id: None,
+                next_shard_index: 0,
+                files_done: BTreeSet::new(),
+                buffer: Vec::new(),
+                outbox: Vec::new(),
+            },
+            Err(e) => return Err(format!("{}: {e}", path.display())),
+        };
+        if state.site != site || state.shard_size != shard_size || state.field_set != field_set {
+            return Err(format!(
+                "{} was set up for site '{}', shard size {}, field set {}",
+                dir.display(),
+                state.site,
+                state.shard_size,
+                state.field_set.name()
+            ));
+        }
+        Ok(state)
+    }
+
+    /// Write the state to `dir`, replacing the previous one atomically.
+    pub fn save(&self, dir: &Path) -> Result<(), String> {
+        let tmp = dir.join(format!("{STATE_FILE}.tmp"));
+        let bytes = serde_json::to_vec(self).map_err(|e| e.to_string())?;
+        std::fs::write(&tmp, bytes).map_err(|e| format!("{}: {e}", tmp.display()))?;
+        std::fs::rename(&tmp, dir.join(STATE_FILE)).map_err(|e| format!("{}: {e}", dir.display()))
+    }
+}
+
+fn key_paths(dir: &Path) -> (PathBuf, PathBuf) {
+    let keys_dir = dir.join("keys");
+    (keys_dir.join("groth16_pk.bin"), keys_dir.join("groth16_vk.bin"))
+}
+
+/// The site's keys for `shard_size` records of `field_set`, set up on first use (the site runs its
+/// own trusted setup; the randomness never leaves this process). Returns the proving key and the
+/// serialized verifying key.
+pub fn ensure_keys(dir: &Path, shard_size: usize, field_set: FieldSet) -> Result<(ProvingKey<Bn254>, Vec<u8>), String> {
+    let (pk_path, vk_path) = key_paths(dir);
+    if pk_path.exists() && vk_path.exists() {
+        let pk_bytes = std::fs::read(&pk_path).map_err(|e| format!("{}: {e}", pk_path.display()))?;
+        let vk_bytes = std::fs::read(&vk_path).map_err(|e| format!("{}: {e}", vk_path.display()))?;
+        let pk = deserialize_pk(&pk_bytes).map_err(|e| format!("{}: {e}", pk_path.display()))?;
+        return Ok((pk, vk_bytes));
+    }
+
+    println!("setting up keys for {shard_size}-record {} shards (first run)", field_set.name());
+    let (pk, vk) = setup_keys_for(shard_size, field_set, &AgeBuckets::default(), false, &mut OsRng).map_err(|e| e.to_string())?;
+    let pk_bytes = serialize_pk(&pk).map_err(|e| e.to_string())?;
+    let vk_bytes = serialize_vk(&vk).map_err(|e| e.to_string())?;
+    std::fs::create_dir_all(dir.join("keys")).map_err(|e| format!("{}: {e}", dir.display()))?;
+    std::fs::write(&pk_path, pk_bytes).map_err(|e| format!("{}: {e}", pk_path.display()))?;
+    std::fs::write(&vk_path, &vk_bytes).map_err(|e| format!("{}: {e}", vk_path.display()))?;
+    Ok((pk, vk_bytes))
+}
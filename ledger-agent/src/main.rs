@@ -0,0 +1,305 @@
+//! Edge prover agent for federated sites (e.g. a hospital).
+//!
+//! Watches a directory for record exports (CSV, the backend's upload format), cuts the records
+//! into shards and proves them locally with the site's own Groth16 keys, then pushes only each
+//! shard's commitment, public stats and proof to the central backend (`/api/v1/federated`).
+//! Records never leave the site.
+//!
+//! ```text
+//! ledger-agent --site NAME --watch DIR [--url http://127.0.0.1:8080] [--api-key KEY]
+//!     [--state-dir ./agent-state] [--shard-size 1000] [--field-set glucose|vitals]
+//!     [--poll 30] [--once]
+//! ```
+//!
+//! Every file in the watched directory is read once, in name order; write exports elsewhere and
+//! move them in when complete. Records that don't fill a shard wait for the next file. Progress is
+//! kept in the state directory (see `local`), so the agent picks up where it left off after an
+//! outage of the backend or a restart: proven shards are pushed again until acknowledged, which
+//! the backend treats as a no-op for shards it already has.
+//!
+//! Exits 2 on bad arguments and 1 on errors retrying won't fix (a rejected shard, unreadable
+//! state); backend outages are retried every `--poll` seconds.
+
+mod local;
+mod records;
+
+use crate::local::{AgentState, PendingShard};
+use ark_serialize::CanonicalSerialize;
+use base64::Engine;
+use serde_json::{json, Value};
+use std::path::PathBuf;
+use std::time::Duration;
+use uuid::Uuid;
+use zk_proofs::constants::DEFAULT_SHARD_SIZE;
+use zk_proofs::groth16::{deserialize_vk, serialize_proof, verify_shard_proof};
+use zk_proofs::registry::{self, prove_shard_for};
+use zk_proofs::types::{AgeBuckets, FieldSet};
+
+use ark_bn254::Bn254;
+use ark_groth16::{ProvingKey, VerifyingKey};
+
+const USAGE: &str = "usage: ledger-agent --site NAME --watch DIR [--url URL] [--api-key KEY] [--state-dir DIR] \
+[--shard-size N] [--field-set glucose|vitals] [--poll SECS] [--once]";
+
+struct Config {
+    url: String,
+    api_key: String,
+    site: String,
+    watch_dir: PathBuf,
+    state_dir: PathBuf,
+    shard_size: usize,
+    field_set: FieldSet,
+    poll: Duration,
+    once: bool,
+}
+
+impl Config {
+    fn from_args(args: &[String]) -> Result<Self, String> {
+        let default_addr = std::env::var("BACKEND_ADDR").unwrap_or_else(|_| "127.0.0.1:8080".to_string());
+        let mut config = Config {
+            url: format!("http://{default_addr}"),
+            api_key: std::env::var("API_KEY").unwrap_or_else(|_| "dev-secret-key".to_string()),
+            site: String::new(),
+            watch_dir: PathBuf::new(),
+            state_dir: PathBuf::from("agent-state"),
+            shard_size: DEFAULT_SHARD_SIZE,
+            field_set: FieldSet::default(),
+            poll: Duration::from_secs(30),
+            once: false,
+        };
+
+        let (mut site, mut watch_dir) = (None, None);
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().ok_or_else(|| format!("{arg} needs a value"));
+            match arg.as_str() {
+                "--url" => config.url = value()?.trim_end_matches('/').to_string(),
+                "--api-key" => config.api_key = value()?.clone(),
+                "--site" => site = Some(value()?.clone()),
+                "--watch" => watch_dir = Some(PathBuf::from(value()?)),
+                "--state-dir" => config.state_dir = PathBuf::from(value()?),
+                "--shard-size" => {
+                    config.shard_size = value()?
+                        .parse()
+                        .ok()
+                        .filter(|n| registry::is_supported(*n))
+                        .ok_or_else(|| format!("--shard-size must be one of {:?}", registry::SUPPORTED_SHARD_SIZES))?
+                }
+                "--field-set" => {
+                    config.field_set = FieldSet::parse(value()?).ok_or("--field-set must be glucose or vitals")?
+                }
+                "--poll" => {
+                    let secs: u64 = value()?.parse().ok().filter(|n| *n > 0).ok_or("--poll must be a positive integer")?;
+                    config.poll = Duration::from_secs(secs);
+                }
+                "--once" => config.once = true,
+                other => return Err(format!("unknown argument {other}")),
+            }
+        }
+        config.site = site.filter(|s| !s.trim().is_empty()).ok_or("--site is required")?;
+        config.watch_dir = watch_dir.ok_or("--watch is required")?;
+        Ok(config)
+    }
+}
+
+/// Why a cycle stopped.
+enum AgentError {
+    /// The backend is unreachable, overloaded or failing; try again next cycle.
+    Transient(String),
+    /// Retrying won't help.
+    Fatal(String),
+}
+
+impl From<String> for AgentError {
+    fn from(e: String) -> Self {
+        AgentError::Fatal(e)
+    }
+}
+
+struct Agent {
+    config: Config,
+    client: reqwest::Client,
+    pk: ProvingKey<Bn254>,
+    vk: VerifyingKey<Bn254>,
+    vk_b64: String,
+    state: AgentState,
+}
+
+impl Agent {
+    async fn post_json(&self, path: &str, body: &Value) -> Result<Value, AgentError> {
+        let res = self
+            .client
+            .post(format!("{}{path}", self.config.url))
+            .header("X-API-KEY", &self.config.api_key)
+            .json(body)
+            .send()
+            .await
+            .map_err(|e| AgentError::Transient(format!("POST {path}: {e}")))?;
+        let status = res.status();
+        let body: Value = res.json().await.unwrap_or(Value::Null);
+        if status.is_server_error() || status == reqwest::StatusCode::TOO_MANY_REQUESTS {
+            return Err(AgentError::Transient(format!("POST {path}: {status} {body}")));
+        }
+        if !status.is_success() {
+            return Err(AgentError::Fatal(format!("POST {path}: {status} {body}")));
+        }
+        Ok(body)
+    }
+
+    /// Register the site's dataset on first run.
+    async fn ensure_registered(&mut self) -> Result<Uuid, AgentError> {
+        if let Some(dataset_id) = self.state.dataset_id {
+            return Ok(dataset_id);
+        }
+        let body = json!({
+            "site": self.config.site,
+            "vk_b64": self.vk_b64,
+            "shard_size": self.config.shard_size,
+            "field_set": self.config.field_set,
+        });
+        let res = self.post_json("/api/v1/federated", &body).await?;
+        let dataset_id = res["dataset_id"]
+            .as_str()
+            .and_then(|s| s.parse().ok())
+            .ok_or_else(|| AgentError::Fatal(format!("unexpected registration response {res}")))?;
+        println!("registered dataset {dataset_id} for site '{}'", self.config.site);
+        self.state.dataset_id = Some(dataset_id);
+        self.state.save(&self.config.state_dir)?;
+        Ok(dataset_id)
+    }
+
+    /// Push queued shards in order until the outbox is empty.
+    async fn flush(&mut self, dataset_id: Uuid) -> Result<(), AgentError> {
+        while let Some(shard) = self.state.outbox.first() {
+            let body = serde_json::to_value(shard).map_err(|e| e.to_string())?;
+            let res = self.post_json(&format!("/api/v1/federated/{dataset_id}/shards"), &body).await?;
+            println!(
+                "shard {}: {} (dataset size {})",
+                shard.shard_index,
+                res["outcome"].as_str().unwrap_or("?"),
+                res["dataset_size"]
+            );
+            self.state.outbox.remove(0);
+            self.state.save(&self.config.state_dir)?;
+        }
+        Ok(())
+    }
+
+    /// Read new files into the buffer and prove every full shard.
+    async fn ingest(&mut self) -> Result<(), AgentError> {
+        for (name, path) in records::list_csv_files(&self.config.watch_dir)? {
+            if self.state.files_done.contains(&name) {
+                continue;
+            }
+            let text = std::fs::read_to_string(&path).map_err(|e| format!("{}: {e}", path.display()))?;
+            let parsed = records::parse_csv(&text, self.config.field_set).map_err(|e| format!("{name}: {e}"))?;
+            println!("{name}: {} records, {} rows rejected", parsed.records.len(), parsed.rows_rejected());
+            self.state.buffer.extend(parsed.records);
+            self.state.files_done.insert(name);
+            self.state.save(&self.config.state_dir)?;
+
+            while self.state.buffer.len() >= self.config.shard_size {
+                tokio::task::block_in_place(|| self.prove_next())?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Prove the oldest full shard of the buffer and queue it.
+    fn prove_next(&mut self) -> Result<(), String> {
+        let shard_index = self.state.next_shard_index;
+        let records: Vec<_> = self.state.buffer[..self.config.shard_size].to_vec();
+        let (proof, commitment, stats, _) = prove_shard_for(
+            self.config.shard_size,
+            self.config.field_set,
+            &AgeBuckets::default(),
+            &mut rand::rngs::OsRng,
+            &self.pk,
+            records,
+            None,
+        )
+        .map_err(|e| format!("proving shard {shard_index}: {e}"))?;
+        verify_shard_proof(&self.vk, &proof, commitment, &stats).map_err(|e| format!("shard {shard_index} does not verify: {e}"))?;
+
+        let mut commitment_bytes = Vec::new();
+        commitment.serialize_compressed(&mut commitment_bytes).map_err(|e| e.to_string())?;
+        let proof_bytes = serialize_proof(&proof).map_err(|e| e.to_string())?;
+        self.state.outbox.push(PendingShard {
+            shard_index,
+            shard_commitment_hex: hex::encode(commitment_bytes),
+            stats,
+            proof_b64: base64::engine::general_purpose::STANDARD.encode(proof_bytes),
+        });
+        self.state.buffer.drain(..self.config.shard_size);
+        self.state.next_shard_index += 1;
+        self.state.save(&self.config.state_dir)?;
+        println!("proved shard {shard_index}");
+        Ok(())
+    }
+
+    async fn cycle(&mut self) -> Result<(), AgentError> {
+        let dataset_id = self.ensure_registered().await?;
+        self.flush(dataset_id).await?;
+        self.ingest().await?;
+        self.flush(dataset_id).await
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.iter().any(|a| a == "--help" || a == "-h") {
+        println!("{USAGE}");
+        return;
+    }
+    let config = match Config::from_args(&args) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("{e}\n{USAGE}");
+            std::process::exit(2);
+        }
+    };
+
+    let setup = std::fs::create_dir_all(&config.state_dir)
+        .map_err(|e| format!("{}: {e}", config.state_dir.display()))
+        .and_then(|_| AgentState::load_or_new(&config.state_dir, &config.site, config.shard_size, config.field_set))
+        .and_then(|state| {
+            let (pk, vk_bytes) = local::ensure_keys(&config.state_dir, config.shard_size, config.field_set)?;
+            let vk = deserialize_vk(&vk_bytes).map_err(|e| e.to_string())?;
+            Ok((state, pk, vk, base64::engine::general_purpose::STANDARD.encode(vk_bytes)))
+        });
+    let (state, pk, vk, vk_b64) = match setup {
+        Ok(setup) => setup,
+        Err(e) => {
+            eprintln!("{e}");
+            std::process::exit(1);
+        }
+    };
+
+    let mut agent = Agent {
+        client: reqwest::Client::new(),
+        config,
+        pk,
+        vk,
+        vk_b64,
+        state,
+    };
+    loop {
+        match agent.cycle().await {
+            Ok(()) => {}
+            Err(AgentError::Transient(e)) if agent.config.once => {
+                eprintln!("{e}");
+                std::process::exit(1);
+            }
+            Err(AgentError::Transient(e)) => eprintln!("{e}; retrying in {}s", agent.config.poll.as_secs()),
+            Err(AgentError::Fatal(e)) => {
+                eprintln!("{e}");
+                std::process::exit(1);
+            }
+        }
+        if agent.config.once {
+            break;
+        }
+        tokio::time::sleep(agent.config.poll).await;
+    }
+}
@@ -0,0 +1,102 @@
+//! Reading record exports dropped into the watched directory.
+//!
+//! Files are CSV in the backend's upload format: header `age,blood_glucose` (or
+//! `blood_glucose_mg_dl`), plus `systolic_bp`, `heart_rate` and `bmi` for vitals. Column order is
+//! taken from the header and extra columns are ignored. Rows with a missing or invalid value,
+//! including glucose outside `PLAUSIBLE_GLUCOSE_MG_DL` (which the shard circuit can't prove), are
+//! skipped and counted.
+
+use std::path::{Path, PathBuf};
+use zk_proofs::constants::{MAX_AGE, PLAUSIBLE_GLUCOSE_MG_DL};
+use zk_proofs::types::{FieldSet, Record};
+
+/// Records parsed from one file.
+pub struct Parsed {
+    pub records: Vec<Record>,
+    pub rows_read: u64,
+}
+
+impl Parsed {
+    pub fn rows_rejected(&self) -> u64 {
+        self.rows_read - self.records.len() as u64
+    }
+}
+
+/// `.csv` files in `dir`, by name.
+pub fn list_csv_files(dir: &Path) -> Result<Vec<(String, PathBuf)>, String> {
+    let entries = std::fs::read_dir(dir).map_err(|e| format!("{}: {e}", dir.display()))?;
+    let mut files: Vec<(String, PathBuf)> = entries
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|p| p.is_file() && p.extension().is_some_and(|ext| ext == "csv"))
+        .filter_map(|p| Some((p.file_name()?.to_str()?.to_string(), p)))
+        .collect();
+    files.sort();
+    Ok(files)
+}
+
+pub fn parse_csv(text: &str, field_set: FieldSet) -> Result<Parsed, String> {
+    let mut lines = text.lines().filter(|l| !l.trim().is_empty());
+    let header = lines.next().ok_or("csv is empty")?;
+    let columns: Vec<&str> = header.split(',').map(|c| c.trim()).collect();
+    let column = |names: &[&str]| {
+        columns
+            .iter()
+            .position(|c| names.contains(c))
+            .ok_or_else(|| format!("csv header must contain '{}'", names[0]))
+    };
+    let age_col = column(&["age"])?;
+    let glucose_col = column(&["blood_glucose", "blood_glucose_mg_dl"])?;
+    let vitals_cols = match field_set {
+        FieldSet::Glucose => None,
+        FieldSet::Vitals => Some([column(&["systolic_bp"])?, column(&["heart_rate"])?, column(&["bmi"])?]),
+    };
+
+    let mut parsed = Parsed {
+        records: Vec::new(),
+        rows_read: 0,
+    };
+    for line in lines {
+        parsed.rows_read += 1;
+        let fields: Vec<&str> = line.split(',').map(|f| f.trim()).collect();
+        let field = |col: usize| fields.get(col).copied().filter(|f| !f.is_empty());
+
+        let Some(age) = field(age_col).and_then(|f| f.parse::<u8>().ok()).filter(|a| *a <= MAX_AGE) else {
+            continue;
+        };
+        let (min_glucose, max_glucose) = PLAUSIBLE_GLUCOSE_MG_DL;
+        let glucose = field(glucose_col).and_then(|f| f.parse::<u16>().ok());
+        let Some(glucose) = glucose.filter(|g| (min_glucose..=max_glucose).contains(g)) else {
+            continue;
+        };
+        let mut record = Record {
+            age,
+            blood_glucose_mg_dl: glucose,
+            ..Record::default()
+        };
+        if let Some([bp_col, hr_col, bmi_col]) = vitals_cols {
+            let bp = field(bp_col).and_then(|f| f.parse::<u16>().ok());
+            let hr = field(hr_col).and_then(|f| f.parse::<u16>().ok());
+            let bmi = field(bmi_col).and_then(parse_bmi_x10);
+            let (Some(bp), Some(hr), Some(bmi)) = (bp, hr, bmi) else {
+                continue;
+            };
+            record.systolic_bp_mm_hg = bp;
+            record.heart_rate_bpm = hr;
+            record.bmi_x10 = bmi;
+        }
+        parsed.records.push(record);
+    }
+    Ok(parsed)
+}
+
+/// BMI in tenths of kg/m² from a decimal like `23.1`; more than one decimal is rejected.
+fn parse_bmi_x10(s: &str) -> Option<u16> {
+    let (whole, tenths) = s.split_once('.').unwrap_or((s, "0"));
+    if tenths.len() != 1 {
+        return None;
+    }
+    let whole: u16 = whole.parse().ok()?;
+    let tenths: u16 = tenths.parse().ok()?;
+    whole.checked_mul(10)?.checked_add(tenths)
+}
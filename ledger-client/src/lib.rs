@@ -0,0 +1,259 @@
+//! Client SDK for trusted partner applications contributing to the ledger.
+//!
+//! `Client` covers what a partner needs to add data without running a backend: register a
+//! federated dataset (`POST /api/v1/federated`) with a shard verifying key, then push each shard's
+//! commitment, public stats and proof (`POST /api/v1/federated/:id/shards`). Records never leave
+//! the partner.
+//!
+//! With the `prove` feature, `prove::prove_shard` proves a small shard locally with the backend's
+//! own proving key, downloaded once with `Client::download_pk` (`GET /api/v1/zk/pk`; resumable, so
+//! an interrupted download continues where it stopped). Register the dataset with the matching
+//! verifying key from `Client::get_vk`. The backend only serves proving keys for small shards
+//! (`ZK_PK_MAX_SHARD_SIZE`, default 100 records); sites proving larger shards set up their own
+//! keys, as `ledger-agent` does.
+//!
+//! ```text
+//! let client = Client::new("http://127.0.0.1:8080", "KEY");
+//! let key = client.download_pk(&PkRequest::new(100), Path::new("pk_n100.bin")).await?;
+//! let vk = client.get_vk(&PkRequest::new(100)).await?;
+//! let dataset_id = client.register_federated(&FederatedDataset::new("clinic-a", &vk.vk_b64, 100)).await?;
+//! let pk = prove::load_pk(&key.path)?;
+//! let shard = prove::prove_shard(&pk, 100, FieldSet::Glucose, 0, records)?;
+//! client.push_shard(dataset_id, &shard).await?;
+//! ```
+
+#[cfg(feature = "prove")]
+pub mod prove;
+
+use reqwest::{Method, StatusCode};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+use tokio::io::AsyncWriteExt;
+use uuid::Uuid;
+use zk_proofs_verifier::types::{FieldSet, ShardStats};
+
+#[derive(Debug, Error)]
+pub enum ClientError {
+    #[error("request failed: {0}")]
+    Http(#[from] reqwest::Error),
+
+    #[error("{method} {path}: {status} {body}")]
+    Api { method: Method, path: String, status: StatusCode, body: Value },
+
+    #[error("{path}: {error}")]
+    Io { path: PathBuf, error: std::io::Error },
+
+    #[error("proving failed: {0}")]
+    Proving(String),
+}
+
+pub type Result<T> = std::result::Result<T, ClientError>;
+
+fn io_error(path: &Path) -> impl FnOnce(std::io::Error) -> ClientError + '_ {
+    move |error| ClientError::Io { path: path.to_path_buf(), error }
+}
+
+/// Which shard key `download_pk` and `get_vk` fetch (default age buckets).
+#[derive(Debug, Clone, Serialize)]
+pub struct PkRequest {
+    pub shard_size: usize,
+    pub field_set: FieldSet,
+    pub sha256_commitment: bool,
+}
+
+impl PkRequest {
+    pub fn new(shard_size: usize) -> Self {
+        PkRequest { shard_size, field_set: FieldSet::default(), sha256_commitment: false }
+    }
+}
+
+/// A downloaded proving key.
+#[derive(Debug, Clone)]
+pub struct PkFile {
+    pub path: PathBuf,
+    /// Id of the key pair (hex SHA-256 of its verifying key), as in `GET /api/v1/zk/vk`.
+    pub key_id: String,
+    pub len: u64,
+}
+
+/// `GET /api/v1/zk/vk`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct VerifyingKey {
+    pub vk_b64: String,
+    pub key_id: String,
+}
+
+/// `POST /api/v1/federated` body. Unset fields take the server defaults.
+#[derive(Debug, Clone, Serialize)]
+pub struct FederatedDataset {
+    pub site: String,
+    pub vk_b64: String,
+    pub shard_size: usize,
+    pub field_set: FieldSet,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub consent_scope: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub requires_approval: Option<bool>,
+}
+
+impl FederatedDataset {
+    pub fn new(site: &str, vk_b64: &str, shard_size: usize) -> Self {
+        FederatedDataset {
+            site: site.to_string(),
+            vk_b64: vk_b64.to_string(),
+            shard_size,
+            field_set: FieldSet::default(),
+            consent_scope: None,
+            requires_approval: None,
+        }
+    }
+}
+
+/// A proven shard, as `POST /api/v1/federated/:id/shards` takes it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FederatedShard {
+    pub shard_index: u64,
+    pub shard_commitment_hex: String,
+    pub stats: ShardStats,
+    pub proof_b64: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct FederatedShardResponse {
+    pub dataset_id: Uuid,
+    pub shard_index: u64,
+    /// `appended`, or `already_present` for a re-sent shard.
+    pub outcome: String,
+    pub dataset_size: u64,
+    pub dataset_commitment_hex: Option<String>,
+}
+
+pub struct Client {
+    url: String,
+    api_key: String,
+    http: reqwest::Client,
+}
+
+impl Client {
+    pub fn new(url: &str, api_key: &str) -> Self {
+        Client {
+            url: url.trim_end_matches('/').to_string(),
+            api_key: api_key.to_string(),
+            http: reqwest::Client::new(),
+        }
+    }
+
+    fn request(&self, method: Method, path: &str) -> reqwest::RequestBuilder {
+        self.http.request(method, format!("{}{path}", self.url)).header("X-API-KEY", &self.api_key)
+    }
+
+    /// Fail with the status and error body of a non-success response.
+    async fn check(method: Method, path: &str, res: reqwest::Response) -> Result<reqwest::Response> {
+        let status = res.status();
+        if status.is_success() {
+            return Ok(res);
+        }
+        let body = res.json().await.unwrap_or(Value::Null);
+        Err(ClientError::Api { method, path: path.to_string(), status, body })
+    }
+
+    async fn send_json<T: DeserializeOwned>(&self, method: Method, path: &str, body: Option<&impl Serialize>) -> Result<T> {
+        let mut req = self.request(method.clone(), path);
+        if let Some(body) = body {
+            req = req.json(body);
+        }
+        let res = Self::check(method, path, req.send().await?).await?;
+        Ok(res.json().await?)
+    }
+
+    /// The shard verifying key matching `download_pk(key)`.
+    pub async fn get_vk(&self, key: &PkRequest) -> Result<VerifyingKey> {
+        let path = format!(
+            "/api/v1/zk/vk?shard_size={}&field_set={}&sha256_commitment={}",
+            key.shard_size,
+            key.field_set.name(),
+            key.sha256_commitment
+        );
+        self.send_json(Method::GET, &path, None::<&()>).await
+    }
+
+    /// Download the proving key to `dest`, resuming a previous partial download of the same key.
+    ///
+    /// The key is written to `dest.part` as it streams in, with its id in `dest.etag`; once it is
+    /// complete it is renamed to `dest`. An existing `dest` is taken as complete and not
+    /// re-downloaded.
+    pub async fn download_pk(&self, key: &PkRequest, dest: &Path) -> Result<PkFile> {
+        let part = dest.with_extension("part");
+        let etag_path = dest.with_extension("etag");
+        let etag = tokio::fs::read_to_string(&etag_path).await.ok();
+        if let Ok(meta) = tokio::fs::metadata(dest).await
+            && let Some(key_id) = &etag
+        {
+            return Ok(PkFile { path: dest.to_path_buf(), key_id: key_id.trim().to_string(), len: meta.len() });
+        }
+
+        let have = match (&etag, tokio::fs::metadata(&part).await) {
+            (Some(_), Ok(meta)) => meta.len(),
+            _ => 0,
+        };
+        let path = format!(
+            "/api/v1/zk/pk?shard_size={}&field_set={}&sha256_commitment={}",
+            key.shard_size,
+            key.field_set.name(),
+            key.sha256_commitment
+        );
+        let mut req = self.request(Method::GET, &path);
+        if let Some(etag) = etag.as_deref().filter(|_| have > 0) {
+            req = req.header("Range", format!("bytes={have}-")).header("If-Range", format!("\"{}\"", etag.trim()));
+        }
+        let mut res = Self::check(Method::GET, &path, req.send().await?).await?;
+        let key_id = res
+            .headers()
+            .get("ETag")
+            .and_then(|v| v.to_str().ok())
+            .map(|v| v.trim_matches('"').to_string())
+            .unwrap_or_default();
+
+        // `206` continues the part file; `200` (a new key, or no range asked) starts it over.
+        let resumed = res.status() == StatusCode::PARTIAL_CONTENT;
+        let mut file = tokio::fs::OpenOptions::new()
+            .create(true)
+            .write(true)
+            .append(resumed)
+            .truncate(!resumed)
+            .open(&part)
+            .await
+            .map_err(io_error(&part))?;
+        tokio::fs::write(&etag_path, &key_id).await.map_err(io_error(&etag_path))?;
+        let mut len = if resumed { have } else { 0 };
+        while let Some(chunk) = res.chunk().await? {
+            file.write_all(&chunk).await.map_err(io_error(&part))?;
+            len += chunk.len() as u64;
+        }
+        file.flush().await.map_err(io_error(&part))?;
+        tokio::fs::rename(&part, dest).await.map_err(io_error(dest))?;
+        Ok(PkFile { path: dest.to_path_buf(), key_id, len })
+    }
+
+    /// Register a federated dataset; returns its id.
+    pub async fn register_federated(&self, dataset: &FederatedDataset) -> Result<Uuid> {
+        let res: Value = self.send_json(Method::POST, "/api/v1/federated", Some(dataset)).await?;
+        res["dataset_id"]
+            .as_str()
+            .and_then(|s| s.parse().ok())
+            .ok_or_else(|| ClientError::Api {
+                method: Method::POST,
+                path: "/api/v1/federated".to_string(),
+                status: StatusCode::OK,
+                body: res.clone(),
+            })
+    }
+
+    /// Push the dataset's next shard; re-sending a stored shard unchanged is a no-op.
+    pub async fn push_shard(&self, dataset_id: Uuid, shard: &FederatedShard) -> Result<FederatedShardResponse> {
+        self.send_json(Method::POST, &format!("/api/v1/federated/{dataset_id}/shards"), Some(shard)).await
+    }
+}
@@ -0,0 +1,50 @@
+//! Local shard proving (`prove` feature).
+//!
+//! Proves a shard with a proving key from `Client::download_pk`, checks the proof against the
+//! key's own verifying key, and encodes it as `Client::push_shard` sends it. The shard's master
+//! salt stays here: the backend only ever sees the salted commitment.
+
+use crate::{ClientError, FederatedShard, Result, io_error};
+use ark_bn254::Bn254;
+use ark_groth16::ProvingKey;
+use ark_serialize::CanonicalSerialize;
+use base64::Engine;
+use std::path::Path;
+use zk_proofs::groth16::{deserialize_pk, serialize_proof, verify_shard_proof};
+use zk_proofs::registry::{self, prove_shard_for};
+use zk_proofs::types::{AgeBuckets, FieldSet, Record};
+
+/// Read a proving key file.
+pub fn load_pk(path: &Path) -> Result<ProvingKey<Bn254>> {
+    let bytes = std::fs::read(path).map_err(io_error(path))?;
+    deserialize_pk(&bytes).map_err(|e| ClientError::Proving(format!("{}: {e}", path.display())))
+}
+
+/// Prove shard `shard_index` of `records` (exactly `shard_size` of them). CPU-bound: call it from
+/// a blocking task in async code.
+pub fn prove_shard(
+    pk: &ProvingKey<Bn254>,
+    shard_size: usize,
+    field_set: FieldSet,
+    shard_index: u64,
+    records: Vec<Record>,
+) -> Result<FederatedShard> {
+    if !registry::is_supported(shard_size) {
+        return Err(ClientError::Proving(format!("shard_size must be one of {:?}", registry::SUPPORTED_SHARD_SIZES)));
+    }
+    let (proof, commitment, stats, _) =
+        prove_shard_for(shard_size, field_set, &AgeBuckets::default(), &mut rand::rngs::OsRng, pk, records, None)
+            .map_err(|e| ClientError::Proving(format!("shard {shard_index}: {e}")))?;
+    verify_shard_proof(&pk.vk, &proof, commitment, &stats)
+        .map_err(|e| ClientError::Proving(format!("shard {shard_index} does not verify: {e}")))?;
+
+    let mut commitment_bytes = Vec::new();
+    commitment.serialize_compressed(&mut commitment_bytes).map_err(|e| ClientError::Proving(e.to_string()))?;
+    let proof_bytes = serialize_proof(&proof).map_err(|e| ClientError::Proving(e.to_string()))?;
+    Ok(FederatedShard {
+        shard_index,
+        shard_commitment_hex: hex::encode(commitment_bytes),
+        stats,
+        proof_b64: base64::engine::general_purpose::STANDARD.encode(proof_bytes),
+    })
+}
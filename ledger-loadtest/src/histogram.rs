@@ -0,0 +1,101 @@
+//! Latency recording with exact percentiles and a log-scale bucket view.
+
+use serde::Serialize;
+use std::time::Duration;
+
+/// Every sample of one operation, in microseconds. A run records at most a few million requests,
+/// so keeping them all is cheap and percentiles are exact.
+#[derive(Debug, Default, Clone)]
+pub struct LatencyHistogram {
+    micros: Vec<u64>,
+    sorted: bool,
+}
+
+/// One bucket of the printed histogram: samples in `(previous bound, le_us]`.
+#[derive(Debug, Serialize)]
+pub struct Bucket {
+    pub le_us: u64,
+    pub count: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Summary {
+    pub count: u64,
+    pub mean_us: u64,
+    pub p50_us: u64,
+    pub p90_us: u64,
+    pub p99_us: u64,
+    pub p999_us: u64,
+    pub max_us: u64,
+    pub buckets: Vec<Bucket>,
+}
+
+impl LatencyHistogram {
+    pub fn record(&mut self, latency: Duration) {
+        self.micros.push(latency.as_micros() as u64);
+        self.sorted = false;
+    }
+
+    pub fn merge(&mut self, other: &LatencyHistogram) {
+        self.micros.extend_from_slice(&other.micros);
+        self.sorted = false;
+    }
+
+    /// Nearest-rank percentile, `q` in `[0, 1]`.
+    fn percentile(&mut self, q: f64) -> u64 {
+        if self.micros.is_empty() {
+            return 0;
+        }
+        if !self.sorted {
+            self.micros.sort_unstable();
+            self.sorted = true;
+        }
+        let rank = (q * self.micros.len() as f64).ceil() as usize;
+        self.micros[rank.clamp(1, self.micros.len()) - 1]
+    }
+
+    /// Counts per power-of-two bucket (1µs, 2µs, 4µs, ...), from the first to the last non-empty.
+    fn buckets(&self) -> Vec<Bucket> {
+        let mut counts = [0u64; 64];
+        for &us in &self.micros {
+            counts[bucket_index(us)] += 1;
+        }
+        let first = counts.iter().position(|&c| c > 0).unwrap_or(0);
+        let last = counts.iter().rposition(|&c| c > 0).unwrap_or(0);
+        (first..=last)
+            .map(|i| Bucket {
+                le_us: 1u64 << i,
+                count: counts[i],
+            })
+            .collect()
+    }
+
+    pub fn summary(&mut self) -> Summary {
+        let count = self.micros.len() as u64;
+        let mean_us = self.micros.iter().sum::<u64>().checked_div(count).unwrap_or(0);
+        Summary {
+            count,
+            mean_us,
+            p50_us: self.percentile(0.50),
+            p90_us: self.percentile(0.90),
+            p99_us: self.percentile(0.99),
+            p999_us: self.percentile(0.999),
+            max_us: self.percentile(1.0),
+            buckets: self.buckets(),
+        }
+    }
+}
+
+/// Smallest `i` with `us <= 2^i`.
+fn bucket_index(us: u64) -> usize {
+    if us <= 1 { 0 } else { (64 - (us - 1).leading_zeros()) as usize }
+}
+
+/// `1.23ms`-style rendering of a microsecond value.
+pub fn format_us(us: u64) -> String {
+    match us {
+        0..1_000 => format!("{us}µs"),
+        1_000..1_000_000 => format!("{:.2}ms", us as f64 / 1e3),
+        _ => format!("{:.2}s", us as f64 / 1e6),
+    }
+}
@@ -0,0 +1,426 @@
+//! Load generator for the verification endpoints.
+//!
+//! Runs `--concurrency` closed-loop workers against a live backend for `--duration` seconds,
+//! mixing `POST /api/v1/verify/shard`, `POST /api/v1/verify/shards` and shard listings of one
+//! ready dataset, and reports throughput and latency percentiles and histograms per operation.
+//! The proofs come from the dataset itself, so every verification does real pairing work.
+//!
+//! ```text
+//! ledger-loadtest --dataset ID [--url http://127.0.0.1:8080] [--api-key KEY]
+//!     [--concurrency 8] [--duration 30] [--warmup 5] [--mix verify=8,list=2,batch=0]
+//!     [--batch-size 16] [--list-limit 50] [--list-proofs] [--max-shards 256] [--json]
+//! ```
+//!
+//! Exits non-zero if any request failed or any verification came back `ok: false`.
+
+mod histogram;
+
+use crate::histogram::{format_us, LatencyHistogram, Summary};
+use rand::distributions::{Distribution, WeightedIndex};
+use rand::seq::SliceRandom;
+use rand::Rng;
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+const USAGE: &str = "usage: ledger-loadtest --dataset ID [--url URL] [--api-key KEY] [--concurrency N] \
+[--duration SECS] [--warmup SECS] [--mix verify=W,list=W,batch=W] [--batch-size N] [--list-limit N] \
+[--list-proofs] [--max-shards N] [--json]";
+
+/// Distinct pre-encoded batch bodies to rotate through.
+const BATCH_BODIES: usize = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Op {
+    Verify,
+    Batch,
+    List,
+}
+
+impl Op {
+    const ALL: [Op; 3] = [Op::Verify, Op::Batch, Op::List];
+
+    fn name(self) -> &'static str {
+        match self {
+            Op::Verify => "verify",
+            Op::Batch => "batch",
+            Op::List => "list",
+        }
+    }
+}
+
+struct Config {
+    url: String,
+    api_key: String,
+    dataset_id: Uuid,
+    concurrency: usize,
+    duration: Duration,
+    warmup: Duration,
+    /// Relative weight of each operation, in `Op::ALL` order.
+    mix: [u32; 3],
+    batch_size: usize,
+    list_limit: u64,
+    list_proofs: bool,
+    max_shards: usize,
+    json: bool,
+}
+
+impl Config {
+    fn from_args(args: &[String]) -> Result<Self, String> {
+        let default_addr = std::env::var("BACKEND_ADDR").unwrap_or_else(|_| "127.0.0.1:8080".to_string());
+        let mut config = Config {
+            url: format!("http://{default_addr}"),
+            api_key: std::env::var("API_KEY").unwrap_or_else(|_| "dev-secret-key".to_string()),
+            dataset_id: Uuid::nil(),
+            concurrency: 8,
+            duration: Duration::from_secs(30),
+            warmup: Duration::from_secs(5),
+            mix: [8, 0, 2],
+            batch_size: 16,
+            list_limit: 50,
+            list_proofs: false,
+            max_shards: 256,
+            json: false,
+        };
+
+        let mut dataset_id = None;
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().ok_or_else(|| format!("{arg} needs a value"));
+            match arg.as_str() {
+                "--url" => config.url = value()?.trim_end_matches('/').to_string(),
+                "--api-key" => config.api_key = value()?.clone(),
+                "--dataset" => dataset_id = Some(value()?.parse().map_err(|_| "--dataset must be a UUID")?),
+                "--concurrency" => config.concurrency = parse_positive(arg, value()?)?,
+                "--duration" => config.duration = Duration::from_secs(parse_positive(arg, value()?)? as u64),
+                "--warmup" => config.warmup = Duration::from_secs(value()?.parse().map_err(|_| "--warmup must be a number")?),
+                "--mix" => config.mix = parse_mix(value()?)?,
+                "--batch-size" => config.batch_size = parse_positive(arg, value()?)?,
+                "--list-limit" => config.list_limit = parse_positive(arg, value()?)? as u64,
+                "--list-proofs" => config.list_proofs = true,
+                "--max-shards" => config.max_shards = parse_positive(arg, value()?)?,
+                "--json" => config.json = true,
+                other => return Err(format!("unknown argument {other}")),
+            }
+        }
+        config.dataset_id = dataset_id.ok_or("--dataset is required")?;
+        Ok(config)
+    }
+}
+
+fn parse_positive(flag: &str, value: &str) -> Result<usize, String> {
+    value.parse().ok().filter(|n| *n > 0).ok_or_else(|| format!("{flag} must be a positive integer"))
+}
+
+/// `verify=8,list=2` → weights in `Op::ALL` order; unnamed operations get weight 0.
+fn parse_mix(spec: &str) -> Result<[u32; 3], String> {
+    let mut mix = [0; 3];
+    for part in spec.split(',').filter(|p| !p.trim().is_empty()) {
+        let (name, weight) = part.split_once('=').ok_or_else(|| format!("bad --mix entry '{part}'"))?;
+        let i = Op::ALL
+            .iter()
+            .position(|op| op.name() == name.trim())
+            .ok_or_else(|| format!("unknown operation '{name}' (verify, batch, list)"))?;
+        mix[i] = weight.trim().parse().map_err(|_| format!("bad weight in '{part}'"))?;
+    }
+    if mix.iter().all(|w| *w == 0) {
+        return Err("--mix needs at least one non-zero weight".to_string());
+    }
+    Ok(mix)
+}
+
+/// Request bodies prepared up front, so workers spend their time waiting on the server.
+struct Fixtures {
+    /// One `/verify/shard` body per shard.
+    verify: Vec<Vec<u8>>,
+    /// `/verify/shards` bodies of `batch_size` shards each.
+    batch: Vec<Vec<u8>>,
+    shards_total: u64,
+}
+
+async fn load_fixtures(client: &reqwest::Client, config: &Config) -> Result<Fixtures, String> {
+    let vk = get_json(client, config, &format!("/api/v1/zk/vk?dataset_id={}", config.dataset_id)).await?;
+    let vk_b64 = vk["vk_b64"].clone();
+
+    let mut shards = Vec::new();
+    let mut shards_total = 0;
+    while shards.len() < config.max_shards {
+        let page = get_json(
+            client,
+            config,
+            &format!(
+                "/api/v1/datasets/{}/shards?offset={}&limit={}&include_proof=true",
+                config.dataset_id,
+                shards.len(),
+                (config.max_shards - shards.len()).min(500)
+            ),
+        )
+        .await?;
+        shards_total = page["shards_total"].as_u64().unwrap_or(0);
+        let page = page["shards"].as_array().cloned().unwrap_or_default();
+        if page.is_empty() {
+            break;
+        }
+        shards.extend(page.iter().map(shard_proof_body));
+    }
+    if shards.is_empty() {
+        return Err("dataset has no proven shards".to_string());
+    }
+
+    let encode = |v: &Value| serde_json::to_vec(v).map_err(|e| e.to_string());
+    let verify = shards
+        .iter()
+        .map(|shard| {
+            let mut body = shard.clone();
+            body["vk_b64"] = vk_b64.clone();
+            encode(&body)
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut rng = rand::thread_rng();
+    let batch = (0..BATCH_BODIES)
+        .map(|_| {
+            let picked: Vec<&Value> = (0..config.batch_size).map(|_| shards.choose(&mut rng).expect("non-empty")).collect();
+            encode(&json!({ "vk_b64": vk_b64, "shards": picked }))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(Fixtures { verify, batch, shards_total })
+}
+
+/// A shard listing entry rewritten as a `/verify/shard` body (without `vk_b64`).
+fn shard_proof_body(shard: &Value) -> Value {
+    json!({
+        "proof_b64": shard["proof_b64"],
+        "public_shard_commitment_hex": shard["shard_commitment_hex"],
+        "public_sum_glucose_by_bucket": shard["sum_glucose_by_bucket"],
+        "public_count_by_bucket": shard["count_by_bucket"],
+        "public_extra_sums_by_bucket": shard.get("extra_sums_by_bucket").cloned().unwrap_or_else(|| json!([])),
+        "public_sum_glucose_sq_by_bucket": shard.get("sum_glucose_sq_by_bucket").cloned().unwrap_or(Value::Null),
+        "public_glucose_histogram_by_bucket": shard.get("glucose_histogram_by_bucket").cloned().unwrap_or(Value::Null),
+        "public_salt_commitment_hex": shard.get("salt_commitment_hex").cloned().unwrap_or(Value::Null),
+        "public_sha256_commitment_hex": shard.get("sha256_commitment_hex").cloned().unwrap_or(Value::Null),
+    })
+}
+
+async fn get_json(client: &reqwest::Client, config: &Config, path: &str) -> Result<Value, String> {
+    let res = client
+        .get(format!("{}{path}", config.url))
+        .header("X-API-KEY", &config.api_key)
+        .send()
+        .await
+        .map_err(|e| format!("GET {path}: {e}"))?;
+    let status = res.status();
+    let body: Value = res.json().await.map_err(|e| format!("GET {path}: {e}"))?;
+    if !status.is_success() {
+        return Err(format!("GET {path}: {status} {body}"));
+    }
+    Ok(body)
+}
+
+#[derive(Default)]
+struct OpStats {
+    latency: LatencyHistogram,
+    /// Transport errors and non-2xx responses.
+    errors: u64,
+    /// Verifications answered `ok: false` (the fixtures are all valid proofs).
+    rejected: u64,
+}
+
+type Stats = BTreeMap<Op, OpStats>;
+
+/// Issue one request; returns whether it succeeded (2xx and, for verifications, `ok: true`) and
+/// whether it was a rejection.
+async fn issue(client: &reqwest::Client, config: &Config, fixtures: &Fixtures, op: Op) -> (bool, bool) {
+    let mut rng = rand::rngs::OsRng;
+    let request = match op {
+        Op::Verify => client
+            .post(format!("{}/api/v1/verify/shard", config.url))
+            .body(fixtures.verify[rng.gen_range(0..fixtures.verify.len())].clone()),
+        Op::Batch => client
+            .post(format!("{}/api/v1/verify/shards", config.url))
+            .body(fixtures.batch[rng.gen_range(0..fixtures.batch.len())].clone()),
+        Op::List => {
+            let offset = rng.gen_range(0..fixtures.shards_total.max(1));
+            client.get(format!(
+                "{}/api/v1/datasets/{}/shards?offset={offset}&limit={}&include_proof={}",
+                config.url, config.dataset_id, config.list_limit, config.list_proofs
+            ))
+        }
+    };
+    let res = request
+        .header("X-API-KEY", &config.api_key)
+        .header("content-type", "application/json")
+        .send()
+        .await;
+    let Ok(res) = res else { return (false, false) };
+    if !res.status().is_success() {
+        return (false, false);
+    }
+    match op {
+        Op::Verify | Op::Batch => match res.json::<Value>().await {
+            Ok(body) if body["ok"] == true => (true, false),
+            Ok(_) => (false, true),
+            Err(_) => (false, false),
+        },
+        Op::List => (res.bytes().await.is_ok(), false),
+    }
+}
+
+async fn run_worker(client: reqwest::Client, config: Arc<Config>, fixtures: Arc<Fixtures>, measure_from: Instant, until: Instant) -> Stats {
+    let weights = WeightedIndex::new(config.mix).expect("validated in parse_mix");
+    let mut stats = Stats::new();
+    while Instant::now() < until {
+        let op = Op::ALL[weights.sample(&mut rand::thread_rng())];
+        let started = Instant::now();
+        let (ok, rejected) = issue(&client, &config, &fixtures, op).await;
+        if started < measure_from {
+            continue;
+        }
+        let entry = stats.entry(op).or_default();
+        entry.latency.record(started.elapsed());
+        entry.errors += u64::from(!ok && !rejected);
+        entry.rejected += u64::from(rejected);
+    }
+    stats
+}
+
+#[derive(Serialize)]
+struct OpReport {
+    requests: u64,
+    errors: u64,
+    rejected: u64,
+    throughput_rps: f64,
+    latency: Summary,
+}
+
+#[derive(Serialize)]
+struct Report {
+    url: String,
+    dataset_id: Uuid,
+    concurrency: usize,
+    duration_secs: u64,
+    shards_loaded: usize,
+    batch_size: usize,
+    operations: BTreeMap<&'static str, OpReport>,
+}
+
+fn print_report(report: &Report) {
+    println!(
+        "{} for {}s at concurrency {}, {} shard proofs loaded (batch size {})",
+        report.url, report.duration_secs, report.concurrency, report.shards_loaded, report.batch_size
+    );
+    for (name, op) in &report.operations {
+        let l = &op.latency;
+        println!();
+        println!(
+            "{name}: {} requests, {:.1} req/s, {} errors, {} rejected",
+            op.requests, op.throughput_rps, op.errors, op.rejected
+        );
+        println!(
+            "  mean {}  p50 {}  p90 {}  p99 {}  p99.9 {}  max {}",
+            format_us(l.mean_us),
+            format_us(l.p50_us),
+            format_us(l.p90_us),
+            format_us(l.p99_us),
+            format_us(l.p999_us),
+            format_us(l.max_us)
+        );
+        let widest = l.buckets.iter().map(|b| b.count).max().unwrap_or(0).max(1);
+        for bucket in &l.buckets {
+            let bar = "#".repeat((bucket.count * 50).div_ceil(widest) as usize);
+            println!("  <= {:>9} {:>8} {bar}", format_us(bucket.le_us), bucket.count);
+        }
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.iter().any(|a| a == "--help" || a == "-h") {
+        println!("{USAGE}");
+        return;
+    }
+    let config = match Config::from_args(&args) {
+        Ok(config) => Arc::new(config),
+        Err(e) => {
+            eprintln!("{e}\n{USAGE}");
+            std::process::exit(2);
+        }
+    };
+
+    let client = reqwest::Client::builder()
+        .pool_max_idle_per_host(config.concurrency)
+        .build()
+        .expect("http client");
+
+    let fixtures = match load_fixtures(&client, &config).await {
+        Ok(fixtures) => Arc::new(fixtures),
+        Err(e) => {
+            eprintln!("loading fixtures: {e}");
+            std::process::exit(1);
+        }
+    };
+
+    // Measuring failures would be meaningless: make sure a fixture verifies before starting.
+    if issue(&client, &config, &fixtures, Op::Verify).await != (true, false) {
+        eprintln!("a fixture proof did not verify; is the dataset ready and the key current?");
+        std::process::exit(1);
+    }
+
+    let measure_from = Instant::now() + config.warmup;
+    let until = measure_from + config.duration;
+    let workers: Vec<_> = (0..config.concurrency)
+        .map(|_| tokio::spawn(run_worker(client.clone(), config.clone(), fixtures.clone(), measure_from, until)))
+        .collect();
+
+    let mut stats = Stats::new();
+    for worker in workers {
+        let Ok(worker_stats) = worker.await else { continue };
+        for (op, s) in worker_stats {
+            let entry = stats.entry(op).or_default();
+            entry.latency.merge(&s.latency);
+            entry.errors += s.errors;
+            entry.rejected += s.rejected;
+        }
+    }
+
+    let secs = config.duration.as_secs_f64();
+    let operations: BTreeMap<&'static str, OpReport> = stats
+        .into_iter()
+        .map(|(op, mut s)| {
+            let latency = s.latency.summary();
+            let report = OpReport {
+                requests: latency.count,
+                errors: s.errors,
+                rejected: s.rejected,
+                throughput_rps: latency.count as f64 / secs,
+                latency,
+            };
+            (op.name(), report)
+        })
+        .collect();
+    let failed = operations.values().any(|op| op.errors > 0 || op.rejected > 0);
+
+    let report = Report {
+        url: config.url.clone(),
+        dataset_id: config.dataset_id,
+        concurrency: config.concurrency,
+        duration_secs: config.duration.as_secs(),
+        shards_loaded: fixtures.verify.len(),
+        batch_size: config.batch_size,
+        operations,
+    };
+    if config.json {
+        println!("{}", serde_json::to_string_pretty(&report).expect("report serializes"));
+    } else {
+        print_report(&report);
+    }
+
+    if failed {
+        std::process::exit(1);
+    }
+}
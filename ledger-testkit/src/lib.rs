@@ -0,0 +1,424 @@
+//! Black-box test harness for the ledger backend.
+//!
+//! `TestBackend::start` builds the real `backend` binary and boots it in ephemeral mode (in-memory
+//! DB, deterministic keys, everything under a scratch directory) on a free local port; the helpers
+//! then drive it over HTTP the way any client would. `verify_all_shards` checks the proofs with
+//! `zk-proofs` on the test side, so a passing test doesn't depend on the server's own `verified`
+//! flags.
+//!
+//! Proving is slow in debug builds; `cargo test --release -p ledger-testkit` runs the suite in a
+//! fraction of the time.
+
+pub mod models;
+
+use crate::models::*;
+use ark_bn254::Fr;
+use ark_serialize::CanonicalDeserialize;
+use base64::Engine;
+use reqwest::{Method, StatusCode};
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use std::net::TcpListener;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+use thiserror::Error;
+use uuid::Uuid;
+use zk_proofs::constants::NUM_BUCKETS;
+use zk_proofs::groth16::{deserialize_proof, deserialize_vk, invalid_shard_proofs, verify_shard_proofs_batch, ShardProofInstance};
+
+/// Admin key the harness configures and sends.
+pub const API_KEY: &str = "testkit-admin-key";
+
+/// How long the backend may take to answer `/readyz` after spawning.
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Default wait for a dataset to finish proving (key setup included; debug builds are slow).
+pub const DATASET_TIMEOUT: Duration = Duration::from_secs(30 * 60);
+
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Page size for shard listings (the server's maximum).
+const SHARD_PAGE: u64 = 500;
+
+#[derive(Debug, Error)]
+pub enum TestkitError {
+    #[error("building backend: {0}")]
+    Build(String),
+
+    #[error("starting backend: {0}")]
+    Startup(String),
+
+    #[error("http: {0}")]
+    Http(#[from] reqwest::Error),
+
+    #[error("encoding request: {0}")]
+    Encode(#[from] serde_json::Error),
+
+    #[error("{method} {path} returned {status}: {body}")]
+    Status {
+        method: Method,
+        path: String,
+        status: StatusCode,
+        body: Value,
+    },
+
+    #[error("timed out: {0}")]
+    Timeout(String),
+
+    #[error("dataset {0} failed: {1}")]
+    DatasetFailed(Uuid, String),
+
+    #[error("query {0} ended as {1}: {2}")]
+    QueryNotReleased(Uuid, String, String),
+
+    #[error("verification: {0}")]
+    Verification(String),
+}
+
+pub type Result<T> = std::result::Result<T, TestkitError>;
+
+/// A running backend process, stopped and cleaned up on drop.
+pub struct TestBackend {
+    child: Child,
+    base_url: String,
+    client: reqwest::Client,
+    scratch_dir: PathBuf,
+}
+
+impl TestBackend {
+    /// Boot a fresh backend with the default configuration.
+    pub async fn start() -> Result<Self> {
+        Self::start_with_env(&[]).await
+    }
+
+    /// Boot a fresh backend with extra environment variables (e.g. `REQUIRE_QUERY_PURPOSE`).
+    pub async fn start_with_env(env: &[(&str, &str)]) -> Result<Self> {
+        let binary = tokio::task::spawn_blocking(backend_binary)
+            .await
+            .map_err(|e| TestkitError::Build(e.to_string()))??;
+
+        // The backend's ephemeral directory lives under the temp dir, so pointing that at a
+        // scratch directory we own lets drop remove it even though the process is killed.
+        let scratch_dir = std::env::temp_dir().join(format!("phl-testkit-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&scratch_dir).map_err(|e| TestkitError::Startup(e.to_string()))?;
+        let log = std::fs::File::create(scratch_dir.join("backend.log")).map_err(|e| TestkitError::Startup(e.to_string()))?;
+        let log_err = log.try_clone().map_err(|e| TestkitError::Startup(e.to_string()))?;
+
+        let addr = free_local_addr().map_err(|e| TestkitError::Startup(e.to_string()))?;
+        let child = Command::new(binary)
+            .current_dir(&scratch_dir)
+            .env("EPHEMERAL", "1")
+            .env("BACKEND_ADDR", &addr)
+            .env("API_KEY", API_KEY)
+            .env("TMPDIR", &scratch_dir)
+            .env("TMP", &scratch_dir)
+            .env("TEMP", &scratch_dir)
+            .envs(env.iter().copied())
+            .stdin(Stdio::null())
+            .stdout(log)
+            .stderr(log_err)
+            .spawn()
+            .map_err(|e| TestkitError::Startup(e.to_string()))?;
+
+        let mut backend = Self {
+            child,
+            base_url: format!("http://{addr}"),
+            client: reqwest::Client::new(),
+            scratch_dir,
+        };
+        backend.wait_ready().await?;
+        Ok(backend)
+    }
+
+    async fn wait_ready(&mut self) -> Result<()> {
+        let deadline = Instant::now() + STARTUP_TIMEOUT;
+        loop {
+            if let Ok(Some(status)) = self.child.try_wait() {
+                return Err(TestkitError::Startup(format!("backend exited with {status}\n{}", self.log_tail())));
+            }
+            if let Ok(res) = self.client.get(self.url("/readyz")).send().await
+                && res.status().is_success()
+            {
+                return Ok(());
+            }
+            if Instant::now() > deadline {
+                return Err(TestkitError::Startup(format!("not ready after {STARTUP_TIMEOUT:?}\n{}", self.log_tail())));
+            }
+            tokio::time::sleep(POLL_INTERVAL).await;
+        }
+    }
+
+    /// The last lines of the backend's log, for failure messages.
+    pub fn log_tail(&self) -> String {
+        let log = std::fs::read_to_string(self.scratch_dir.join("backend.log")).unwrap_or_default();
+        let lines: Vec<&str> = log.lines().collect();
+        lines[lines.len().saturating_sub(40)..].join("\n")
+    }
+
+    pub fn url(&self, path: &str) -> String {
+        format!("{}{path}", self.base_url)
+    }
+
+    /// Send a request with the admin key and return the status and JSON body (`Null` if empty),
+    /// whatever the status. For asserting on error responses.
+    pub async fn request(&self, method: Method, path: &str, body: Option<&Value>) -> Result<(StatusCode, Value)> {
+        self.request_with_key(method, path, body, Some(API_KEY)).await
+    }
+
+    /// Like `request`, with another API key or none at all.
+    pub async fn request_with_key(
+        &self,
+        method: Method,
+        path: &str,
+        body: Option<&Value>,
+        api_key: Option<&str>,
+    ) -> Result<(StatusCode, Value)> {
+        let mut req = self.client.request(method, self.url(path));
+        if let Some(key) = api_key {
+            req = req.header("X-API-KEY", key);
+        }
+        if let Some(body) = body {
+            req = req.json(body);
+        }
+        let res = req.send().await?;
+        let status = res.status();
+        let bytes = res.bytes().await?;
+        let body = serde_json::from_slice(&bytes).unwrap_or(Value::Null);
+        Ok((status, body))
+    }
+
+    async fn call<T: DeserializeOwned>(&self, method: Method, path: &str, body: Option<&Value>) -> Result<T> {
+        let (status, value) = self.request(method.clone(), path, body).await?;
+        if !status.is_success() {
+            return Err(TestkitError::Status {
+                method,
+                path: path.to_string(),
+                status,
+                body: value,
+            });
+        }
+        serde_json::from_value(value).map_err(|e| TestkitError::Status {
+            method,
+            path: path.to_string(),
+            status,
+            body: Value::String(format!("unexpected response shape: {e}")),
+        })
+    }
+
+    /// `GET` a JSON resource; non-2xx statuses are errors.
+    pub async fn get<T: DeserializeOwned>(&self, path: &str) -> Result<T> {
+        self.call(Method::GET, path, None).await
+    }
+
+    /// `POST` a JSON body; non-2xx statuses are errors.
+    pub async fn post<B: Serialize, T: DeserializeOwned>(&self, path: &str, body: &B) -> Result<T> {
+        let body = serde_json::to_value(body)?;
+        self.call(Method::POST, path, Some(&body)).await
+    }
+
+    pub async fn create_dataset(&self, req: &DatasetRequest) -> Result<Uuid> {
+        let created: DatasetCreated = self.post("/api/v1/datasets", req).await?;
+        Ok(created.dataset_id)
+    }
+
+    pub async fn get_dataset(&self, dataset_id: Uuid) -> Result<Dataset> {
+        self.get(&format!("/api/v1/datasets/{dataset_id}")).await
+    }
+
+    /// Poll a dataset until it is `ready`; `failed` or running out of time are errors.
+    pub async fn wait_for_dataset(&self, dataset_id: Uuid, timeout: Duration) -> Result<Dataset> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            let dataset = self.get_dataset(dataset_id).await?;
+            match dataset.status.as_str() {
+                "ready" => return Ok(dataset),
+                "failed" => return Err(TestkitError::DatasetFailed(dataset_id, dataset.error.unwrap_or_default())),
+                _ if Instant::now() > deadline => {
+                    return Err(TestkitError::Timeout(format!(
+                        "dataset {dataset_id} at {}/{} shards after {timeout:?}",
+                        dataset.shards_done, dataset.shards_total
+                    )));
+                }
+                _ => tokio::time::sleep(POLL_INTERVAL).await,
+            }
+        }
+    }
+
+    /// Create a dataset and wait (up to `DATASET_TIMEOUT`) until every shard is proven.
+    pub async fn create_dataset_and_wait(&self, req: &DatasetRequest) -> Result<Dataset> {
+        let dataset_id = self.create_dataset(req).await?;
+        self.wait_for_dataset(dataset_id, DATASET_TIMEOUT).await
+    }
+
+    /// Run a query to its released answer, polling the status endpoint if the server defers it
+    /// (`mode: async`). Queries held for approval or rejected are errors.
+    pub async fn run_query(&self, req: &QueryRequest) -> Result<QueryResponse> {
+        let body = serde_json::to_value(req)?;
+        let (status, value) = self.request(Method::POST, "/api/v1/queries", Some(&body)).await?;
+        let unexpected = |body: Value| TestkitError::Status {
+            method: Method::POST,
+            path: "/api/v1/queries".to_string(),
+            status,
+            body,
+        };
+        match status {
+            StatusCode::OK => serde_json::from_value(value).map_err(|e| unexpected(Value::String(e.to_string()))),
+            StatusCode::ACCEPTED => {
+                let pending: QueryPending = serde_json::from_value(value).map_err(|e| unexpected(Value::String(e.to_string())))?;
+                self.wait_for_query(pending.query_id, DATASET_TIMEOUT).await
+            }
+            _ => Err(unexpected(value)),
+        }
+    }
+
+    /// Poll a deferred query until it is released.
+    pub async fn wait_for_query(&self, query_id: Uuid, timeout: Duration) -> Result<QueryResponse> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            let status: QueryStatus = self.get(&format!("/api/v1/queries/{query_id}/status")).await?;
+            match (status.status.as_str(), status.result) {
+                ("released", Some(result)) => return Ok(result),
+                ("queued" | "running", _) if Instant::now() <= deadline => tokio::time::sleep(POLL_INTERVAL).await,
+                ("queued" | "running", _) => return Err(TestkitError::Timeout(format!("query {query_id} after {timeout:?}"))),
+                (other, _) => {
+                    return Err(TestkitError::QueryNotReleased(query_id, other.to_string(), status.error.unwrap_or_default()));
+                }
+            }
+        }
+    }
+
+    /// Every shard of a dataset in index order, optionally with proofs.
+    pub async fn list_all_shards(&self, dataset_id: Uuid, include_proof: bool) -> Result<Vec<Shard>> {
+        let mut shards = Vec::new();
+        loop {
+            let page: ShardPage = self
+                .get(&format!(
+                    "/api/v1/datasets/{dataset_id}/shards?offset={}&limit={SHARD_PAGE}&include_proof={include_proof}",
+                    shards.len()
+                ))
+                .await?;
+            let done = page.shards.is_empty() || shards.len() + page.shards.len() >= page.shards_total as usize;
+            shards.extend(page.shards);
+            if done {
+                return Ok(shards);
+            }
+        }
+    }
+
+    /// Fetch a dataset's verifying key and every shard proof, and verify them all locally with
+    /// `zk-proofs`. Fails naming the shards whose proofs don't verify.
+    pub async fn verify_all_shards(&self, dataset_id: Uuid) -> Result<VerifiedShards> {
+        let vk: VerifyingKeyResponse = self.get(&format!("/api/v1/zk/vk?dataset_id={dataset_id}")).await?;
+        let shards = self.list_all_shards(dataset_id, true).await?;
+
+        let b64 = base64::engine::general_purpose::STANDARD;
+        let vk_bytes = b64.decode(&vk.vk_b64).map_err(|e| TestkitError::Verification(format!("vk: {e}")))?;
+        let vk = deserialize_vk(&vk_bytes).map_err(|e| TestkitError::Verification(format!("vk: {e}")))?;
+
+        let instances = shards.iter().map(shard_instance).collect::<Result<Vec<_>>>()?;
+        if verify_shard_proofs_batch(&vk, &instances).is_err() {
+            let invalid: Vec<u64> = invalid_shard_proofs(&vk, &instances).into_iter().map(|i| shards[i].shard_index).collect();
+            return Err(TestkitError::Verification(format!("invalid proofs for shards {invalid:?}")));
+        }
+
+        let totals = proven_totals(&shards);
+        Ok(VerifiedShards { shards, totals })
+    }
+}
+
+impl Drop for TestBackend {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+        let _ = std::fs::remove_dir_all(&self.scratch_dir);
+    }
+}
+
+fn shard_instance(shard: &Shard) -> Result<ShardProofInstance> {
+    let invalid = |what: &str| TestkitError::Verification(format!("shard {}: invalid {what}", shard.shard_index));
+
+    let proof_b64 = shard.proof_b64.as_deref().ok_or_else(|| invalid("proof (missing)"))?;
+    let proof_bytes = base64::engine::general_purpose::STANDARD.decode(proof_b64).map_err(|_| invalid("proof_b64"))?;
+    let proof = deserialize_proof(&proof_bytes).map_err(|_| invalid("proof"))?;
+
+    let commitment_bytes = hex::decode(&shard.shard_commitment_hex).map_err(|_| invalid("commitment hex"))?;
+    let commitment = Fr::deserialize_compressed(&commitment_bytes[..]).map_err(|_| invalid("commitment bytes"))?;
+
+    Ok(ShardProofInstance {
+        proof,
+        commitment,
+        stats: shard.stats.clone(),
+    })
+}
+
+fn proven_totals(shards: &[Shard]) -> ProvenTotals {
+    // Datasets with their own age bucket layout have as many buckets as it does.
+    let num_buckets = shards.first().map_or(NUM_BUCKETS, |shard| shard.stats.count_by_bucket.len());
+    let mut totals = ProvenTotals {
+        sum_glucose_by_bucket: vec![0; num_buckets],
+        count_by_bucket: vec![0; num_buckets],
+        glucose_histogram_by_bucket: Some(vec![Default::default(); num_buckets]),
+    };
+    for shard in shards {
+        for b in 0..num_buckets {
+            totals.sum_glucose_by_bucket[b] += shard.stats.sum_glucose_by_bucket[b];
+            totals.count_by_bucket[b] += shard.stats.count_by_bucket[b];
+        }
+        totals.glucose_histogram_by_bucket = match (totals.glucose_histogram_by_bucket.take(), &shard.stats.glucose_histogram_by_bucket) {
+            (Some(mut acc), Some(histogram)) => {
+                for (acc, bucket) in acc.iter_mut().zip(histogram) {
+                    for (a, c) in acc.iter_mut().zip(bucket) {
+                        *a += c;
+                    }
+                }
+                Some(acc)
+            }
+            _ => None,
+        };
+    }
+    totals
+}
+
+/// Path of the `backend` binary next to the running test executable, built on first use
+/// (`LEDGER_BACKEND_BIN` overrides both).
+fn backend_binary() -> Result<PathBuf> {
+    static BINARY: OnceLock<std::result::Result<PathBuf, String>> = OnceLock::new();
+    BINARY
+        .get_or_init(|| {
+            if let Ok(path) = std::env::var("LEDGER_BACKEND_BIN") {
+                return Ok(PathBuf::from(path));
+            }
+            build_backend()
+        })
+        .clone()
+        .map_err(TestkitError::Build)
+}
+
+fn build_backend() -> std::result::Result<PathBuf, String> {
+    // Test executables live in `<target>/<profile>/deps/`.
+    let exe = std::env::current_exe().map_err(|e| e.to_string())?;
+    let profile_dir = exe.parent().and_then(Path::parent).ok_or("unexpected test executable location")?;
+    let target_dir = profile_dir.parent().ok_or("unexpected test executable location")?;
+
+    let cargo = std::env::var("CARGO").unwrap_or_else(|_| "cargo".to_string());
+    let mut build = Command::new(cargo);
+    build.args(["build", "-q", "-p", "backend", "--target-dir"]).arg(target_dir);
+    if !cfg!(debug_assertions) {
+        build.arg("--release");
+    }
+    let status = build.status().map_err(|e| e.to_string())?;
+    if !status.success() {
+        return Err(format!("cargo build -p backend: {status}"));
+    }
+
+    Ok(profile_dir.join(format!("backend{}", std::env::consts::EXE_SUFFIX)))
+}
+
+/// A `127.0.0.1` address with a port the OS just handed out.
+fn free_local_addr() -> std::io::Result<String> {
+    let listener = TcpListener::bind("127.0.0.1:0")?;
+    Ok(listener.local_addr()?.to_string())
+}
@@ -0,0 +1,184 @@
+//! Client-side views of the backend's JSON API, limited to what the helpers and tests use.
+//!
+//! These mirror `backend/src/models.rs` by field name; the backend is a binary crate, so its
+//! types can't be shared directly (same situation as `frontend/src/api.ts`).
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use uuid::Uuid;
+use zk_proofs::constants::NUM_GLUCOSE_RANGES;
+use zk_proofs::types::{FieldSet, ShardStats};
+
+/// `POST /api/v1/datasets` body. Unset fields take the server defaults.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct DatasetRequest {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dataset_size: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub shard_size: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub generator: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub field_set: Option<FieldSet>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub chain_hash: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sha256_commitment: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub consent_scope: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub requires_approval: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub release_limit: Option<u64>,
+    /// Inclusive `(min_age, max_age)` age buckets covering 0..=120.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub buckets: Option<Vec<(u8, u8)>>,
+}
+
+impl DatasetRequest {
+    /// A synthetic dataset of `dataset_size` records in shards of `shard_size`.
+    pub fn synthetic(dataset_size: u64, shard_size: u64) -> Self {
+        Self {
+            dataset_size: Some(dataset_size),
+            shard_size: Some(shard_size),
+            ..Self::default()
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct DatasetCreated {
+    pub dataset_id: Uuid,
+}
+
+/// `GET /api/v1/datasets/:id`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Dataset {
+    pub dataset_id: Uuid,
+    pub dataset_size: u64,
+    pub shard_size: u64,
+    pub field_set: FieldSet,
+    pub chain_hash: String,
+    #[serde(default)]
+    pub sha256_commitment: bool,
+    /// `generating`, `ready` or `failed`.
+    pub status: String,
+    pub shards_total: u64,
+    pub shards_done: u64,
+    pub dataset_commitment_hex: Option<String>,
+    pub error: Option<String>,
+}
+
+/// `POST /api/v1/queries` body.
+#[derive(Debug, Clone, Serialize)]
+pub struct QueryRequest {
+    pub dataset_id: Uuid,
+    /// `count`, `sum`, `mean`, `variance`, `stddev` or `histogram`.
+    pub metric: String,
+    pub field: String,
+    pub age_range: AgeRange,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub purpose: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mode: Option<String>,
+}
+
+impl QueryRequest {
+    /// A synchronous blood glucose query for one age bucket.
+    pub fn glucose(dataset_id: Uuid, metric: &str, (min_age, max_age): (u8, u8)) -> Self {
+        Self {
+            dataset_id,
+            metric: metric.to_string(),
+            field: "blood_glucose".to_string(),
+            age_range: AgeRange { min_age, max_age },
+            purpose: None,
+            mode: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct AgeRange {
+    pub min_age: u8,
+    pub max_age: u8,
+}
+
+/// A released query answer.
+#[derive(Debug, Clone, Deserialize)]
+pub struct QueryResponse {
+    pub query_id: Uuid,
+    pub dataset_id: Uuid,
+    pub bucket_index: usize,
+    pub bucket_range: (u8, u8),
+    pub sum: u64,
+    pub count: u64,
+    pub mean: Option<f64>,
+    pub sum_sq: Option<u64>,
+    pub variance: Option<f64>,
+    pub stddev: Option<f64>,
+    #[serde(default)]
+    pub histogram: Vec<HistogramBin>,
+    pub server_verified: bool,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct HistogramBin {
+    pub glucose_range: (u16, u16),
+    pub count: u64,
+}
+
+/// Returned with `202` by `POST /api/v1/queries` for held or queued queries.
+#[derive(Debug, Clone, Deserialize)]
+pub struct QueryPending {
+    pub query_id: Uuid,
+    pub status: String,
+}
+
+/// `GET /api/v1/queries/:id/status`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct QueryStatus {
+    pub query_id: Uuid,
+    pub status: String,
+    pub result: Option<QueryResponse>,
+    pub error: Option<String>,
+}
+
+/// One page of `GET /api/v1/datasets/:id/shards`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ShardPage {
+    pub shards_total: u64,
+    pub shards: Vec<Shard>,
+}
+
+/// A shard's commitment, proven public outputs and (if requested) proof.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Shard {
+    pub shard_index: u64,
+    pub shard_commitment_hex: String,
+    #[serde(flatten)]
+    pub stats: ShardStats,
+    pub verified: bool,
+    pub proof_b64: Option<String>,
+}
+
+/// `GET /api/v1/zk/vk`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct VerifyingKeyResponse {
+    pub vk_b64: String,
+}
+
+/// Per-bucket totals over every shard of a dataset, taken from the proven public inputs.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ProvenTotals {
+    pub sum_glucose_by_bucket: Vec<u64>,
+    pub count_by_bucket: Vec<u64>,
+    /// `None` if any shard was proven without a histogram.
+    pub glucose_histogram_by_bucket: Option<Vec<[u64; NUM_GLUCOSE_RANGES]>>,
+}
+
+/// Outcome of `TestBackend::verify_all_shards`.
+#[derive(Debug, Clone)]
+pub struct VerifiedShards {
+    pub shards: Vec<Shard>,
+    pub totals: ProvenTotals,
+}
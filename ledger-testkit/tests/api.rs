@@ -0,0 +1,90 @@
+//! Request validation and auth, none of which needs a proof.
+
+use ledger_testkit::models::DatasetRequest;
+use ledger_testkit::{Result, TestBackend, TestkitError};
+use reqwest::{Method, StatusCode};
+use serde_json::json;
+use uuid::Uuid;
+
+#[tokio::test]
+async fn protected_routes_require_an_api_key() -> Result<()> {
+    let backend = TestBackend::start().await?;
+    let body = json!({ "dataset_size": 100, "shard_size": 100 });
+
+    for key in [None, Some("not-a-key")] {
+        let (status, _) = backend.request_with_key(Method::POST, "/api/v1/datasets", Some(&body), key).await?;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn dataset_parameters_are_validated() -> Result<()> {
+    let backend = TestBackend::start().await?;
+
+    for req in [DatasetRequest::synthetic(300, 300), DatasetRequest::synthetic(150, 100)] {
+        match backend.create_dataset(&req).await {
+            Err(TestkitError::Status { status, body, .. }) => {
+                assert_eq!(status, StatusCode::BAD_REQUEST);
+                assert!(body["error"].as_str().unwrap_or_default().contains("shard_size"), "{body}");
+            }
+            other => panic!("expected 400, got {other:?}"),
+        }
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn unknown_dataset_is_not_found() -> Result<()> {
+    let backend = TestBackend::start().await?;
+
+    let (status, _) = backend.request(Method::GET, &format!("/api/v1/datasets/{}", Uuid::new_v4()), None).await?;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    Ok(())
+}
+
+#[tokio::test]
+async fn malformed_batch_verification_is_rejected() -> Result<()> {
+    let backend = TestBackend::start().await?;
+
+    let body = json!({ "vk_b64": "not base64", "shards": [] });
+    let (status, _) = backend.request(Method::POST, "/api/v1/verify/shards", Some(&body)).await?;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    Ok(())
+}
+
+#[tokio::test]
+async fn uploads_belong_to_the_key_that_opened_them() -> Result<()> {
+    let backend = TestBackend::start_with_env(&[("UPLOAD_MAX_SESSIONS_PER_KEY", "1")]).await?;
+
+    let mut keys = Vec::new();
+    for scopes in [json!(["datasets:create"]), json!(["queries:create"])] {
+        let body = json!({ "name": "uploader", "role": "researcher", "scopes": scopes });
+        let (status, created) = backend.request(Method::POST, "/api/v1/admin/keys", Some(&body)).await?;
+        assert_eq!(status, StatusCode::CREATED, "{created}");
+        keys.push(created["key"].as_str().unwrap_or_default().to_string());
+    }
+    let (uploader, querier) = (keys[0].as_str(), keys[1].as_str());
+
+    let (status, _) = backend.request_with_key(Method::POST, "/api/v1/uploads", None, Some(querier)).await?;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    let (status, opened) = backend.request_with_key(Method::POST, "/api/v1/uploads", None, Some(uploader)).await?;
+    assert_eq!(status, StatusCode::OK, "{opened}");
+    let path = format!("/api/v1/uploads/{}", opened["upload_id"].as_str().unwrap_or_default());
+
+    let (status, _) = backend.request_with_key(Method::POST, "/api/v1/uploads", None, Some(uploader)).await?;
+    assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+
+    let (status, _) = backend.request(Method::GET, &path, None).await?;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let chunk = json!({ "index": 0, "data_b64": "", "sha256_hex": "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855" });
+    let (status, _) = backend.request(Method::POST, &format!("{path}/chunks"), Some(&chunk)).await?;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (status, _) = backend.request(Method::POST, &format!("{path}/commit"), Some(&json!({ "total_chunks": 1 }))).await?;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    let (status, _) = backend.request_with_key(Method::GET, &path, None, Some(uploader)).await?;
+    assert_eq!(status, StatusCode::OK);
+    Ok(())
+}
@@ -0,0 +1,13 @@
+//! The canonical record encoding against its golden vectors, on both proving curves.
+
+use zk_proofs::canonical_encoding::check_golden_vectors;
+
+#[test]
+fn golden_vectors_bn254() {
+    check_golden_vectors::<ark_bn254::Fr>().unwrap();
+}
+
+#[test]
+fn golden_vectors_bls12_381() {
+    check_golden_vectors::<ark_bls12_381::Fr>().unwrap();
+}
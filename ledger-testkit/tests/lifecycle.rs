@@ -0,0 +1,80 @@
+//! Dataset → prove → query → verify against a real backend.
+
+use ledger_testkit::models::{DatasetRequest, QueryRequest};
+use ledger_testkit::{Result, TestBackend};
+use reqwest::Method;
+use serde_json::json;
+use zk_proofs::constants::{AGE_BUCKETS, GLUCOSE_RANGES};
+use zk_proofs::types::FrHex;
+
+#[tokio::test]
+async fn dataset_prove_query_verify() -> Result<()> {
+    let backend = TestBackend::start().await?;
+
+    let dataset = backend.create_dataset_and_wait(&DatasetRequest::synthetic(200, 100)).await?;
+    assert_eq!((dataset.shards_done, dataset.shards_total), (2, 2));
+    assert!(dataset.dataset_commitment_hex.is_some());
+
+    // Every proof verifies on the client side, independently of the server's flags.
+    let verified = backend.verify_all_shards(dataset.dataset_id).await?;
+    assert_eq!(verified.shards.len(), 2);
+    assert!(verified.shards.iter().all(|s| s.verified));
+    // The latest circuit salts every shard commitment from its own master salt.
+    let salt_commitments: Vec<_> = verified.shards.iter().map(|s| s.stats.salt_commitment).collect();
+    assert!(salt_commitments.iter().all(Option::is_some));
+    assert_ne!(salt_commitments[0], salt_commitments[1]);
+    let totals = &verified.totals;
+    assert_eq!(totals.count_by_bucket.iter().sum::<u64>(), 200);
+
+    // Released answers are exactly the sums of the proven shard outputs.
+    for (b, &age_range) in AGE_BUCKETS.iter().enumerate() {
+        let answer = backend.run_query(&QueryRequest::glucose(dataset.dataset_id, "mean", age_range)).await?;
+        assert_eq!(answer.bucket_index, b);
+        assert_eq!(answer.sum, totals.sum_glucose_by_bucket[b]);
+        assert_eq!(answer.count, totals.count_by_bucket[b]);
+        assert!(answer.server_verified);
+    }
+
+    let mut histogram = QueryRequest::glucose(dataset.dataset_id, "histogram", AGE_BUCKETS[0]);
+    histogram.mode = Some("async".to_string());
+    let answer = backend.run_query(&histogram).await?;
+    let proven = &totals.glucose_histogram_by_bucket.as_ref().expect("latest circuit proves histograms")[0];
+    assert_eq!(answer.histogram.len(), GLUCOSE_RANGES.len());
+    for ((bin, range), count) in answer.histogram.iter().zip(GLUCOSE_RANGES).zip(proven) {
+        assert_eq!(bin.glucose_range, range);
+        assert_eq!(bin.count, *count);
+    }
+    assert_eq!(answer.histogram.iter().map(|bin| bin.count).sum::<u64>(), answer.count);
+
+    // The batch endpoint agrees, and names a shard whose public inputs were altered.
+    let vk: serde_json::Value = backend.get(&format!("/api/v1/zk/vk?dataset_id={}", dataset.dataset_id)).await?;
+    let mut shards: Vec<serde_json::Value> = verified
+        .shards
+        .iter()
+        .map(|s| {
+            json!({
+                "proof_b64": s.proof_b64,
+                "public_shard_commitment_hex": s.shard_commitment_hex,
+                "public_sum_glucose_by_bucket": s.stats.sum_glucose_by_bucket,
+                "public_count_by_bucket": s.stats.count_by_bucket,
+                "public_sum_glucose_sq_by_bucket": s.stats.sum_glucose_sq_by_bucket,
+                "public_glucose_histogram_by_bucket": s.stats.glucose_histogram_by_bucket,
+                "public_salt_commitment_hex": s.stats.salt_commitment.as_ref().map(|c| FrHex::from_fr(c).hex),
+                "public_sha256_commitment_hex": s.stats.sha256_commitment.map(hex::encode),
+                "public_glucose_bounds": s.stats.glucose_bounds,
+            })
+        })
+        .collect();
+    let batch = |shards: &[serde_json::Value]| json!({ "vk_b64": vk["vk_b64"], "shards": shards });
+
+    let (status, report) = backend.request(Method::POST, "/api/v1/verify/shards", Some(&batch(&shards))).await?;
+    assert!(status.is_success(), "{report}");
+    assert_eq!(report["ok"], true);
+
+    shards[1]["public_count_by_bucket"][0] = json!(verified.shards[1].stats.count_by_bucket[0] + 1);
+    let (_, report) = backend.request(Method::POST, "/api/v1/verify/shards", Some(&batch(&shards))).await?;
+    assert_eq!(report["ok"], false);
+    assert_eq!(report["invalid"], json!([1]));
+
+    Ok(())
+}
@@ -0,0 +1,62 @@
+//! Small-cell suppression with `MIN_CELL_COUNT` set.
+
+use ledger_testkit::models::DatasetRequest;
+use ledger_testkit::{Result, TestBackend};
+use reqwest::Method;
+use serde_json::{json, Value};
+
+#[tokio::test]
+async fn complement_of_a_small_bucket_is_suppressed() -> Result<()> {
+    let backend = TestBackend::start_with_env(&[("MIN_CELL_COUNT", "5")]).await?;
+
+    // Ages are uniform over 0–120, so a bucket of newborns gets about one record in a hundred.
+    let request = DatasetRequest {
+        buckets: Some(vec![(0, 0), (1, 120)]),
+        ..DatasetRequest::synthetic(100, 100)
+    };
+    let dataset = backend.create_dataset_and_wait(&request).await?;
+    let counts = backend.verify_all_shards(dataset.dataset_id).await?.totals.count_by_bucket;
+    assert!(counts[0] < 5 && counts[1] >= 5, "{counts:?}");
+
+    // Everyone but the newborns: the dataset size minus this count would be theirs.
+    let query = json!({
+        "dataset_id": dataset.dataset_id,
+        "metric": "count",
+        "field": "blood_glucose",
+        "age_range": { "min_age": 0, "max_age": 0 },
+        "complement": true,
+    });
+    let (status, answer) = backend.request(Method::POST, "/api/v1/queries", Some(&query)).await?;
+    assert!(status.is_success(), "{answer}");
+    assert_eq!(answer["count"], Value::Null);
+    assert_eq!(answer["sum"], Value::Null);
+    assert_eq!(answer["suppressed"]["min_count"], 5);
+    assert!(answer["suppressed"]["reason"].as_str().unwrap_or_default().contains("excluded bucket"), "{answer}");
+
+    // The same goes for the per-bucket tables: releasing the other bucket would give it away.
+    let aggregates: Value = backend.get(&format!("/api/v1/datasets/{}/aggregates", dataset.dataset_id)).await?;
+    assert_eq!(aggregates["buckets"].as_array().map(Vec::len), Some(2), "{aggregates}");
+    for bucket in aggregates["buckets"].as_array().into_iter().flatten() {
+        assert_eq!(bucket["suppressed"], true, "{bucket}");
+        assert_eq!(bucket["count"], 0, "{bucket}");
+    }
+    let summary: Value = backend.get(&format!("/api/v1/datasets/{}/summary", dataset.dataset_id)).await?;
+    assert_eq!(summary["buckets"].as_array().map(Vec::len), Some(2), "{summary}");
+    for bucket in summary["buckets"].as_array().into_iter().flatten() {
+        assert_eq!(bucket["count"], Value::Null, "{bucket}");
+    }
+    let quality: Value = backend.get(&format!("/api/v1/datasets/{}/quality", dataset.dataset_id)).await?;
+    assert_eq!(quality["buckets"].as_array().map(Vec::len), Some(2), "{quality}");
+    for bucket in quality["buckets"].as_array().into_iter().flatten() {
+        assert_eq!(bucket["suppressed"], true, "{bucket}");
+        assert_eq!(bucket["count"], 0, "{bucket}");
+    }
+    let manifest: Value = backend.get(&format!("/api/v1/datasets/{}/manifest", dataset.dataset_id)).await?;
+    let counts = &manifest["bucket_counts"]["counts"];
+    assert_eq!(counts["min_count"], 5, "{counts}");
+    assert_eq!(counts["total"], 0, "{counts}");
+    for bucket in counts["buckets"].as_array().into_iter().flatten() {
+        assert_eq!(bucket["count"], Value::Null, "{bucket}");
+    }
+    Ok(())
+}
@@ -0,0 +1,246 @@
+//! Checking a dataset archive (`--archive`).
+//!
+//! An archive is JSONL: a `header`, the `dataset` (parameters, manifest, dataset commitment and
+//! verifying key), its `shard`s, its `audit` entries, the archiving instance's `attestation` and
+//! a `signature` line, Ed25519 over every byte before it. Beyond the shard proofs this checks the
+//! signature (and its key, with `--signer`), that the key's id is the one the dataset line, the
+//! manifest and the attestation name, that the dataset commitment recomputes from the shard
+//! commitments, that every audit entry's hash recomputes from its fields, and that the
+//! attestation describes what the archive holds.
+
+use crate::{check, read, Config, Report, ShardEntry, MAX_LISTED};
+use base64::Engine;
+use ring::signature::{UnparsedPublicKey, ED25519};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::time::Instant;
+use zk_proofs_verifier::chain::dataset_commitment_hex;
+
+const ARCHIVE_FORMAT: &str = "phl-dataset-archive";
+const ARCHIVE_VERSION: u32 = 1;
+
+#[derive(Deserialize)]
+struct Header {
+    format: String,
+    version: u32,
+    archived_at: String,
+}
+
+#[derive(Deserialize)]
+struct ArchivedDataset {
+    dataset_id: String,
+    dataset_size: u64,
+    shard_size: u64,
+    chain_hash: String,
+    dataset_commitment_hex: String,
+    manifest: Option<Value>,
+    curve: String,
+    key_id: String,
+    vk_b64: String,
+}
+
+#[derive(Deserialize)]
+struct AuditLine {
+    seq: u64,
+    created_at: String,
+    event: String,
+    details_json: String,
+    prev_hash: String,
+    entry_hash: String,
+}
+
+#[derive(Deserialize)]
+struct Attestation {
+    dataset_id: String,
+    dataset_commitment_hex: String,
+    key_id: String,
+    shards_verified: u64,
+    audit_entries: u64,
+    archived_by: String,
+    reason: String,
+}
+
+#[derive(Deserialize)]
+struct Signature {
+    alg: String,
+    public_key_hex: String,
+    signature_hex: String,
+}
+
+#[derive(Serialize)]
+pub struct ArchiveReport {
+    dataset_id: String,
+    archived_at: String,
+    archived_by: String,
+    reason: String,
+    signer_public_key_hex: String,
+    /// The signature verifies, and is by `--signer` if given.
+    signature_ok: bool,
+    chain_hash: String,
+    dataset_commitment_hex: String,
+    /// The dataset commitment recomputes from the shard commitments.
+    commitment_ok: bool,
+    /// The verifying key is the one the dataset line, its manifest and the attestation name.
+    key_ok: bool,
+    audit_entries: u64,
+    /// Audit entries whose hash doesn't recompute, by `seq` (first `MAX_LISTED`).
+    invalid_audit_seqs: Vec<u64>,
+    /// The attestation names this dataset and commitment, and counts its shards and audit entries.
+    attestation_ok: bool,
+}
+
+impl ArchiveReport {
+    fn ok(&self) -> bool {
+        self.signature_ok && self.commitment_ok && self.key_ok && self.invalid_audit_seqs.is_empty() && self.attestation_ok
+    }
+}
+
+/// Hash of an audit entry, as the ledger chains them: SHA-256 over each part, length-prefixed.
+fn audit_entry_hash(prev_hash: &str, created_at: &str, dataset_id: &str, event: &str, details_json: &str) -> String {
+    let mut h = Sha256::new();
+    for part in [prev_hash, created_at, dataset_id, event, details_json] {
+        h.update((part.len() as u64).to_le_bytes());
+        h.update(part.as_bytes());
+    }
+    hex::encode(h.finalize())
+}
+
+/// Split off the trailing signature line; returns (signed body, signature).
+fn split_signature(bytes: &[u8]) -> Option<(&[u8], Signature)> {
+    let trimmed = bytes.strip_suffix(b"\n").unwrap_or(bytes);
+    let split = trimmed.iter().rposition(|b| *b == b'\n').map(|i| i + 1).unwrap_or(0);
+    let (body, sig_line) = trimmed.split_at(split);
+    let line: Value = serde_json::from_slice(sig_line).ok()?;
+    if line["type"] != "signature" {
+        return None;
+    }
+    Some((body, serde_json::from_value(line).ok()?))
+}
+
+fn signature_verifies(body: &[u8], signature: &Signature) -> bool {
+    let (Ok(public_key), Ok(sig)) = (hex::decode(&signature.public_key_hex), hex::decode(&signature.signature_hex)) else {
+        return false;
+    };
+    signature.alg == "ed25519" && UnparsedPublicKey::new(&ED25519, &public_key).verify(body, &sig).is_ok()
+}
+
+pub fn run(config: &Config, path: &str) -> Result<Report, String> {
+    let started = Instant::now();
+
+    let bytes = read(path)?;
+    let (body, signature) = split_signature(&bytes).ok_or_else(|| format!("{path}: archive must end with a signature line"))?;
+    let signer = signature.public_key_hex.to_ascii_lowercase();
+    let signature_ok = signature_verifies(body, &signature) && config.signer.as_ref().is_none_or(|s| *s == signer);
+
+    let mut lines = body.split(|b| *b == b'\n').filter(|l| !l.trim_ascii().is_empty());
+    let header: Header = lines
+        .next()
+        .and_then(|line| serde_json::from_slice(line).ok())
+        .filter(|h: &Header| h.format == ARCHIVE_FORMAT && h.version == ARCHIVE_VERSION)
+        .ok_or_else(|| format!("{path}: not a {ARCHIVE_FORMAT} v{ARCHIVE_VERSION} archive"))?;
+
+    let mut dataset: Option<ArchivedDataset> = None;
+    let mut shards: BTreeMap<u64, ShardEntry> = BTreeMap::new();
+    let mut audit: Vec<AuditLine> = Vec::new();
+    let mut attestation: Option<Attestation> = None;
+    for (n, line) in lines.enumerate() {
+        let at = |e: serde_json::Error| format!("{path}: line {}: {e}", n + 2);
+        let line: Value = serde_json::from_slice(line).map_err(at)?;
+        match line["type"].as_str() {
+            Some("dataset") if dataset.is_none() => dataset = Some(serde_json::from_value(line).map_err(at)?),
+            Some("shard") => {
+                let entry: ShardEntry = serde_json::from_value(line).map_err(at)?;
+                if shards.insert(entry.shard_index, entry).is_some() {
+                    return Err(format!("{path}: line {}: a shard is listed twice", n + 2));
+                }
+            }
+            Some("audit") => audit.push(serde_json::from_value(line).map_err(at)?),
+            Some("attestation") if attestation.is_none() => attestation = Some(serde_json::from_value(line).map_err(at)?),
+            _ => return Err(format!("{path}: line {}: unexpected record", n + 2)),
+        }
+    }
+    let dataset = dataset.ok_or_else(|| format!("{path}: no dataset record"))?;
+    let attestation = attestation.ok_or_else(|| format!("{path}: no attestation"))?;
+    if dataset.curve != "bn254" {
+        return Err(format!("{path}: only bn254 archives can be checked offline, not {}", dataset.curve));
+    }
+    if dataset.shard_size == 0 {
+        return Err(format!("{path}: shard_size is 0"));
+    }
+
+    let vk_bytes = base64::engine::general_purpose::STANDARD
+        .decode(dataset.vk_b64.trim())
+        .map_err(|_| format!("{path}: vk_b64 is not base64"))?;
+    let shards_expected = dataset.dataset_size / dataset.shard_size;
+    let mut report = check(config, path, &vk_bytes, &shards, Some(shards_expected), &BTreeMap::new(), started)?;
+
+    let manifest_key_id = dataset.manifest.as_ref().and_then(|m| m.get("key_id")).and_then(Value::as_str);
+    let key_ok = [Some(dataset.key_id.as_str()), manifest_key_id, Some(attestation.key_id.as_str())]
+        .into_iter()
+        .flatten()
+        .all(|key_id| key_id == report.key_id);
+
+    let commitments: Vec<&str> = shards.values().map(|s| s.shard.shard_commitment_hex.as_str()).collect();
+    let commitment_ok = dataset_commitment_hex(&dataset.chain_hash, &commitments)
+        .is_ok_and(|recomputed| recomputed == dataset.dataset_commitment_hex);
+
+    let invalid_audit_seqs: Vec<u64> = audit
+        .iter()
+        .filter(|e| {
+            audit_entry_hash(&e.prev_hash, &e.created_at, &dataset.dataset_id, &e.event, &e.details_json) != e.entry_hash
+        })
+        .map(|e| e.seq)
+        .take(MAX_LISTED)
+        .collect();
+
+    let attestation_ok = attestation.dataset_id == dataset.dataset_id
+        && attestation.dataset_commitment_hex == dataset.dataset_commitment_hex
+        && attestation.shards_verified == shards.len() as u64
+        && attestation.audit_entries == audit.len() as u64;
+
+    let archive = ArchiveReport {
+        dataset_id: dataset.dataset_id,
+        archived_at: header.archived_at,
+        archived_by: attestation.archived_by,
+        reason: attestation.reason,
+        signer_public_key_hex: signer,
+        signature_ok,
+        chain_hash: dataset.chain_hash,
+        dataset_commitment_hex: dataset.dataset_commitment_hex,
+        commitment_ok,
+        key_ok,
+        audit_entries: audit.len() as u64,
+        invalid_audit_seqs,
+        attestation_ok,
+    };
+    report.ok = report.ok && archive.ok();
+    report.elapsed_ms = started.elapsed().as_millis() as u64;
+    report.archive = Some(archive);
+    Ok(report)
+}
+
+fn pass(ok: bool) -> &'static str {
+    if ok { "ok" } else { "FAILED" }
+}
+
+pub fn print_report(archive: &ArchiveReport) {
+    println!("dataset           {}", archive.dataset_id);
+    println!("archived          {} by {} ({})", archive.archived_at, archive.archived_by, archive.reason);
+    println!("signature         {} ({})", pass(archive.signature_ok), archive.signer_public_key_hex);
+    println!("key               {}", pass(archive.key_ok));
+    println!(
+        "commitment        {} ({} chain, {})",
+        pass(archive.commitment_ok),
+        archive.chain_hash,
+        archive.dataset_commitment_hex
+    );
+    println!(
+        "audit entries     {} ({} invalid{})",
+        archive.audit_entries,
+        archive.invalid_audit_seqs.len(),
+        if archive.invalid_audit_seqs.is_empty() { String::new() } else { format!(", seq {:?}", archive.invalid_audit_seqs) }
+    );
+    println!("attestation       {}", pass(archive.attestation_ok));
+}
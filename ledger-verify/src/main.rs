@@ -0,0 +1,457 @@
+//! Offline verifier for ledger shard proofs.
+//!
+//! Checks every shard proof of a dataset against its verifying key with no backend to talk to and
+//! no arkworks code on the auditor's side; it only links the verify-only `zk-proofs-verifier`.
+//!
+//! ```text
+//! ledger-verify --vk FILE --shards FILE [--shards FILE ...] [--proofs FILE]
+//!     [--batch-size 64] [--report FILE] [--json] [--quiet]
+//! ledger-verify --archive FILE [--signer HEX] [--batch-size 64] [--report FILE] [--json] [--quiet]
+//! ```
+//!
+//! - `--vk`: the verifying key as raw bytes (`data/keys/groth16_vk_*.bin`), as base64 text, or the
+//!   response of `GET /api/v1/zk/vk?dataset_id=…`.
+//! - `--shards`: shard public inputs, as saved from `GET /api/v1/datasets/:id/shards` (pass every
+//!   page as its own `--shards`), a JSON array of such shard entries, or the NDJSON of
+//!   `GET /api/v1/datasets/:id/shards/export`.
+//! - `--proofs`: proofs kept apart from the public inputs, as a JSON array of
+//!   `{ "shard_index", "proof_b64" }` or an object from shard index to proof; they take precedence
+//!   over `proof_b64` in the listing (`?include_proof=true`).
+//! - `--archive`: a dataset archive (`GET /api/v1/datasets/:id/archive`, or the file the backend
+//!   keeps for a deleted dataset), which carries its own key and shards; see `archive` for what
+//!   else is checked. `--signer` pins the archive's signing key.
+//!
+//! Proofs are batch-verified `--batch-size` at a time; a batch that fails is bisected to name the
+//! invalid shards. Progress goes to stderr, the report (key id to compare with the dataset
+//! manifest, circuit revision, counts, shards missing from the listing, invalid shards with the
+//! reason) to stdout, as JSON with `--json`, and as JSON to `--report FILE`.
+//!
+//! Exits 0 if every proof verifies and no shard is missing, 1 otherwise, 2 on bad usage or
+//! unreadable input.
+
+mod archive;
+
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::io::{IsTerminal, Write};
+use std::time::Instant;
+use zk_proofs_verifier::types::FieldSet;
+use zk_proofs_verifier::verification::{decode_shard_instance, EncodedShard};
+use zk_proofs_verifier::verify::{
+    deserialize_vk, invalid_shard_proofs, verify_shard_proofs_batch, vk_revision, ShardProofInstance,
+};
+
+const USAGE: &str = "usage: ledger-verify --vk FILE --shards FILE [--shards FILE ...] [--proofs FILE] \
+[--batch-size N] [--report FILE] [--json] [--quiet]
+       ledger-verify --archive FILE [--signer HEX] [--batch-size N] [--report FILE] [--json] [--quiet]";
+
+/// Shards named in each of the report's lists; the rest are only counted.
+const MAX_LISTED: usize = 100;
+
+struct Config {
+    vk: String,
+    shards: Vec<String>,
+    proofs: Option<String>,
+    archive: Option<String>,
+    signer: Option<String>,
+    batch_size: usize,
+    report: Option<String>,
+    json: bool,
+    quiet: bool,
+}
+
+impl Config {
+    fn from_args(args: &[String]) -> Result<Self, String> {
+        let mut config = Config {
+            vk: String::new(),
+            shards: Vec::new(),
+            proofs: None,
+            archive: None,
+            signer: None,
+            batch_size: 64,
+            report: None,
+            json: false,
+            quiet: false,
+        };
+
+        let mut vk = None;
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().ok_or_else(|| format!("{arg} needs a value"));
+            match arg.as_str() {
+                "--vk" => vk = Some(value()?.clone()),
+                "--shards" => config.shards.push(value()?.clone()),
+                "--proofs" => config.proofs = Some(value()?.clone()),
+                "--archive" => config.archive = Some(value()?.clone()),
+                "--signer" => config.signer = Some(value()?.to_ascii_lowercase()),
+                "--batch-size" => {
+                    config.batch_size = value()?
+                        .parse()
+                        .ok()
+                        .filter(|n| *n > 0)
+                        .ok_or("--batch-size must be a positive integer")?
+                }
+                "--report" => config.report = Some(value()?.clone()),
+                "--json" => config.json = true,
+                "--quiet" => config.quiet = true,
+                other => return Err(format!("unknown argument {other}")),
+            }
+        }
+        if config.archive.is_some() {
+            if vk.is_some() || !config.shards.is_empty() || config.proofs.is_some() {
+                return Err("--archive carries its own key, shards and proofs".to_string());
+            }
+            return Ok(config);
+        }
+        if config.signer.is_some() {
+            return Err("--signer only applies to --archive".to_string());
+        }
+        config.vk = vk.ok_or("--vk is required")?;
+        if config.shards.is_empty() {
+            return Err("at least one --shards file is required".to_string());
+        }
+        Ok(config)
+    }
+}
+
+/// One shard's public inputs (and proof, if listed) as listed by the backend.
+#[derive(Deserialize)]
+struct ShardEntry {
+    shard_index: u64,
+    #[serde(flatten)]
+    shard: EncodedShard,
+}
+
+#[derive(Deserialize)]
+struct ProofEntry {
+    shard_index: u64,
+    proof_b64: String,
+}
+
+#[derive(Serialize)]
+struct InvalidShard {
+    shard_index: u64,
+    reason: String,
+}
+
+#[derive(Serialize)]
+struct Report {
+    /// Hex SHA-256 of the verifying key, as `key_id` in the dataset manifest.
+    key_id: String,
+    circuit_revision: String,
+    field_set: String,
+    num_buckets: usize,
+    /// Number of shards the listings say the dataset has, if they say.
+    shards_expected: Option<u64>,
+    shards_checked: u64,
+    shards_valid: u64,
+    /// Shards the listings announce (`shards_total`) but don't contain (first `MAX_LISTED`).
+    missing_shard_indices: Vec<u64>,
+    missing_total: u64,
+    /// Shards whose proof doesn't verify or couldn't be decoded (first `MAX_LISTED`).
+    invalid: Vec<InvalidShard>,
+    invalid_total: u64,
+    elapsed_ms: u64,
+    /// The archive's own checks, with `--archive`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    archive: Option<archive::ArchiveReport>,
+    ok: bool,
+}
+
+/// Read a file, with its name in the error.
+fn read(path: &str) -> Result<Vec<u8>, String> {
+    std::fs::read(path).map_err(|e| format!("{path}: {e}"))
+}
+
+fn read_json(path: &str) -> Result<Value, String> {
+    serde_json::from_slice(&read(path)?).map_err(|e| format!("{path}: {e}"))
+}
+
+/// The shard entries of a `--shards` file, and the shard count it announces if it is a listing.
+fn read_shard_entries(path: &str) -> Result<(Option<u64>, Vec<Value>), String> {
+    let bytes = read(path)?;
+    let listing = match serde_json::from_slice(&bytes) {
+        Ok(listing) => listing,
+        // An export: one shard per line.
+        Err(e) => {
+            let lines = bytes.split(|b| *b == b'\n').filter(|line| !line.trim_ascii().is_empty());
+            let entries = lines.map(serde_json::from_slice).collect::<Result<Vec<Value>, _>>();
+            return entries.map(|entries| (None, entries)).map_err(|_| format!("{path}: {e}"));
+        }
+    };
+    match listing {
+        Value::Array(entries) => Ok((None, entries)),
+        Value::Object(mut listing) => {
+            if listing.contains_key("shard_index") {
+                return Ok((None, vec![Value::Object(listing)]));
+            }
+            let total = listing.get("shards_total").and_then(Value::as_u64);
+            match listing.remove("shards") {
+                Some(Value::Array(entries)) => Ok((total, entries)),
+                _ => Err(format!("{path}: no shards array")),
+            }
+        }
+        _ => Err(format!("{path}: expected a shard listing or an array of shards")),
+    }
+}
+
+/// The verifying key's bytes: the file itself if it is a serialized key, else base64 text or a
+/// `GET /api/v1/zk/vk` response.
+fn read_vk_bytes(path: &str) -> Result<Vec<u8>, String> {
+    let bytes = read(path)?;
+    if deserialize_vk(&bytes).is_ok() {
+        return Ok(bytes);
+    }
+    let text = std::str::from_utf8(&bytes).map_err(|_| format!("{path}: not a verifying key"))?.trim();
+    let b64 = match serde_json::from_str::<Value>(text) {
+        Ok(response) => {
+            if let Some(curve) = response["curve"].as_str().filter(|c| *c != "bn254") {
+                return Err(format!("{path}: only bn254 keys can be checked offline, not {curve}"));
+            }
+            response["vk_b64"].as_str().ok_or_else(|| format!("{path}: no vk_b64"))?.to_string()
+        }
+        Err(_) => text.to_string(),
+    };
+    base64::engine::general_purpose::STANDARD
+        .decode(b64.trim())
+        .map_err(|_| format!("{path}: not a verifying key, base64 or a /zk/vk response"))
+}
+
+/// Proofs by shard index from a `--proofs` file.
+fn read_proofs(path: &str) -> Result<BTreeMap<u64, String>, String> {
+    match read_json(path)? {
+        Value::Array(entries) => entries
+            .into_iter()
+            .map(|e| {
+                serde_json::from_value::<ProofEntry>(e)
+                    .map(|p| (p.shard_index, p.proof_b64))
+                    .map_err(|e| format!("{path}: {e}"))
+            })
+            .collect(),
+        Value::Object(entries) => entries
+            .into_iter()
+            .map(|(index, proof)| {
+                let index = index.parse().map_err(|_| format!("{path}: key '{index}' is not a shard index"))?;
+                let proof = proof.as_str().ok_or_else(|| format!("{path}: proof of shard {index} is not a string"))?;
+                Ok((index, proof.to_string()))
+            })
+            .collect(),
+        _ => Err(format!("{path}: expected an array or object of proofs")),
+    }
+}
+
+/// Decode one shard into a batch instance, or say why it can't be checked.
+fn decode_shard(entry: &ShardEntry, proof_b64: Option<&String>) -> Result<ShardProofInstance, String> {
+    let proof_b64 = proof_b64.ok_or("no proof (list shards with include_proof=true, or pass --proofs)")?;
+    let shard = EncodedShard {
+        proof_b64: Some(proof_b64.clone()),
+        ..entry.shard.clone()
+    };
+    decode_shard_instance(&shard).map_err(|e| e.to_string())
+}
+
+/// Redraws a one-line progress bar on stderr (only when it is a terminal).
+struct Progress {
+    total: usize,
+    enabled: bool,
+}
+
+impl Progress {
+    fn update(&self, done: usize, invalid: u64) {
+        if !self.enabled {
+            return;
+        }
+        const WIDTH: usize = 40;
+        let filled = (done * WIDTH).checked_div(self.total).unwrap_or(WIDTH);
+        eprint!(
+            "\r[{}{}] {done}/{} shards, {invalid} invalid",
+            "#".repeat(filled),
+            " ".repeat(WIDTH - filled),
+            self.total
+        );
+        let _ = std::io::stderr().flush();
+        if done == self.total {
+            eprintln!();
+        }
+    }
+}
+
+fn run(config: &Config) -> Result<Report, String> {
+    let started = Instant::now();
+
+    let vk_bytes = read_vk_bytes(&config.vk)?;
+
+    let mut shards: BTreeMap<u64, ShardEntry> = BTreeMap::new();
+    let mut shards_expected: Option<u64> = None;
+    for path in &config.shards {
+        let (total, entries) = read_shard_entries(path)?;
+        if let Some(total) = total {
+            shards_expected = Some(shards_expected.unwrap_or(0).max(total));
+        }
+        for entry in entries {
+            let entry: ShardEntry = serde_json::from_value(entry).map_err(|e| format!("{path}: {e}"))?;
+            if shards.insert(entry.shard_index, entry).is_some() {
+                return Err(format!("{path}: a shard is listed twice"));
+            }
+        }
+    }
+    let proofs = config.proofs.as_deref().map(read_proofs).transpose()?.unwrap_or_default();
+
+    check(config, &config.vk, &vk_bytes, &shards, shards_expected, &proofs, started)
+}
+
+/// Verify every shard's proof (from `proofs`, else its own) against the key, and count the shards
+/// missing from the `shards_expected` announced.
+fn check(
+    config: &Config,
+    vk_source: &str,
+    vk_bytes: &[u8],
+    shards: &BTreeMap<u64, ShardEntry>,
+    shards_expected: Option<u64>,
+    proofs: &BTreeMap<u64, String>,
+    started: Instant,
+) -> Result<Report, String> {
+    let vk = deserialize_vk(vk_bytes).map_err(|e| format!("{vk_source}: {e}"))?;
+    let key_id = hex::encode(Sha256::digest(vk_bytes));
+
+    // The key's revision, for the field set and bucket count the shards carry.
+    let Some(first) = shards.values().next() else {
+        return Err("the listings contain no shards".to_string());
+    };
+    let field_set = if first.shard.stats.extra_sums_by_bucket.is_empty() { FieldSet::Glucose } else { FieldSet::Vitals };
+    let num_buckets = first.shard.stats.count_by_bucket.len();
+    let revision = vk_revision(&vk, field_set, num_buckets);
+
+    let missing: Vec<u64> = match shards_expected {
+        Some(total) => (0..total).filter(|i| !shards.contains_key(i)).collect(),
+        None => Vec::new(),
+    };
+
+    let progress = Progress {
+        total: shards.len(),
+        enabled: !config.quiet && std::io::stderr().is_terminal(),
+    };
+    let mut invalid: Vec<InvalidShard> = Vec::new();
+    let mut done = 0;
+    let entries: Vec<&ShardEntry> = shards.values().collect();
+    for chunk in entries.chunks(config.batch_size) {
+        let mut batch = Vec::with_capacity(chunk.len());
+        let mut batch_indices = Vec::with_capacity(chunk.len());
+        for entry in chunk {
+            let proof_b64 = proofs.get(&entry.shard_index).or(entry.shard.proof_b64.as_ref());
+            match decode_shard(entry, proof_b64) {
+                Ok(instance) => {
+                    batch.push(instance);
+                    batch_indices.push(entry.shard_index);
+                }
+                Err(reason) => invalid.push(InvalidShard {
+                    shard_index: entry.shard_index,
+                    reason,
+                }),
+            }
+        }
+        if verify_shard_proofs_batch(&vk, &batch).is_err() {
+            for i in invalid_shard_proofs(&vk, &batch) {
+                invalid.push(InvalidShard {
+                    shard_index: batch_indices[i],
+                    reason: "proof does not verify against the key and public inputs".to_string(),
+                });
+            }
+        }
+        done += chunk.len();
+        progress.update(done, invalid.len() as u64);
+    }
+    invalid.sort_by_key(|s| s.shard_index);
+
+    let shards_checked = shards.len() as u64;
+    let invalid_total = invalid.len() as u64;
+    let missing_total = missing.len() as u64;
+    invalid.truncate(MAX_LISTED);
+    Ok(Report {
+        key_id,
+        circuit_revision: revision.version().to_string(),
+        field_set: field_set.name().to_string(),
+        num_buckets,
+        shards_expected,
+        shards_checked,
+        shards_valid: shards_checked - invalid_total,
+        missing_shard_indices: missing.into_iter().take(MAX_LISTED).collect(),
+        missing_total,
+        invalid,
+        invalid_total,
+        elapsed_ms: started.elapsed().as_millis() as u64,
+        archive: None,
+        ok: invalid_total == 0 && missing_total == 0,
+    })
+}
+
+fn print_report(report: &Report) {
+    println!("key id            {}", report.key_id);
+    println!("circuit revision  {} ({}, {} buckets)", report.circuit_revision, report.field_set, report.num_buckets);
+    match report.shards_expected {
+        Some(expected) => println!("shards            {} of {expected} checked", report.shards_checked),
+        None => println!("shards            {} checked", report.shards_checked),
+    }
+    println!("valid             {}", report.shards_valid);
+    if report.missing_total > 0 {
+        println!("missing           {} (e.g. {:?})", report.missing_total, report.missing_shard_indices);
+    }
+    if report.invalid_total > 0 {
+        println!("invalid           {}", report.invalid_total);
+        for shard in &report.invalid {
+            println!("  shard {:>8}  {}", shard.shard_index, shard.reason);
+        }
+    }
+    if let Some(archive) = &report.archive {
+        archive::print_report(archive);
+    }
+    println!("elapsed           {} ms", report.elapsed_ms);
+    println!("result            {}", if report.ok { "OK" } else { "FAILED" });
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.iter().any(|a| a == "--help" || a == "-h") {
+        println!("{USAGE}");
+        return;
+    }
+    let config = match Config::from_args(&args) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("{e}\n{USAGE}");
+            std::process::exit(2);
+        }
+    };
+
+    let outcome = match &config.archive {
+        Some(path) => archive::run(&config, path),
+        None => run(&config),
+    };
+    let report = match outcome {
+        Ok(report) => report,
+        Err(e) => {
+            eprintln!("{e}");
+            std::process::exit(2);
+        }
+    };
+
+    let json = serde_json::to_string_pretty(&report).expect("report serializes");
+    if let Some(path) = &config.report
+        && let Err(e) = std::fs::write(path, &json)
+    {
+        eprintln!("{path}: {e}");
+        std::process::exit(2);
+    }
+    if config.json {
+        println!("{json}");
+    } else {
+        print_report(&report);
+    }
+
+    if !report.ok {
+        std::process::exit(1);
+    }
+}
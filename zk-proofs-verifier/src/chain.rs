@@ -0,0 +1,60 @@
+//! Recomputing a dataset commitment from its BN254 shard commitments.
+//!
+//! The ledger folds shard commitments in shard order with the dataset's chain hash: a Poseidon
+//! sponge over the field elements, or SHA-256 / BLAKE3 over `DATASET_CHAIN_DOMAIN` followed by each
+//! 32-byte compressed commitment. Verifiers holding only a dataset's shard listing use this to
+//! check the dataset commitment its manifest or archive states.
+
+use crate::constants::{poseidon_config_for, DATASET_CHAIN_DOMAIN};
+use ark_bn254::Fr;
+use ark_crypto_primitives::sponge::poseidon::PoseidonSponge;
+use ark_crypto_primitives::sponge::CryptographicSponge;
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize};
+use sha2::{Digest, Sha256};
+
+fn compressed(f: &Fr) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(32);
+    f.serialize_compressed(&mut bytes).expect("field elements serialize");
+    bytes
+}
+
+/// Hex of the dataset commitment over the shard commitments (compressed hex, in shard order) with
+/// the chain hash named `chain_hash` (`poseidon`, `sha256` or `blake3`).
+pub fn dataset_commitment_hex(chain_hash: &str, shard_commitment_hexes: &[&str]) -> Result<String, String> {
+    let shard_commitments = shard_commitment_hexes
+        .iter()
+        .enumerate()
+        .map(|(i, h)| {
+            hex::decode(h.trim())
+                .ok()
+                .and_then(|bytes| Fr::deserialize_compressed(&bytes[..]).ok())
+                .ok_or_else(|| format!("shard commitment {i} is not a field element"))
+        })
+        .collect::<Result<Vec<Fr>, _>>()?;
+
+    match chain_hash {
+        "poseidon" => {
+            let mut sponge = PoseidonSponge::<Fr>::new(&poseidon_config_for::<Fr>());
+            for c in &shard_commitments {
+                sponge.absorb(c);
+            }
+            Ok(hex::encode(compressed(&sponge.squeeze_field_elements::<Fr>(1)[0])))
+        }
+        "sha256" => {
+            let mut hasher = Sha256::new_with_prefix(DATASET_CHAIN_DOMAIN);
+            for c in &shard_commitments {
+                hasher.update(compressed(c));
+            }
+            Ok(hex::encode(hasher.finalize()))
+        }
+        "blake3" => {
+            let mut hasher = blake3::Hasher::new();
+            hasher.update(DATASET_CHAIN_DOMAIN);
+            for c in &shard_commitments {
+                hasher.update(&compressed(c));
+            }
+            Ok(hasher.finalize().to_hex().to_string())
+        }
+        other => Err(format!("unknown chain hash '{other}'")),
+    }
+}
@@ -0,0 +1,115 @@
+//! Public circuit parameters shared by the prover and every verifier.
+
+use ark_crypto_primitives::sponge::poseidon::{find_poseidon_ark_and_mds, PoseidonConfig};
+use ark_ff::PrimeField;
+
+/// Default number of records per shard.
+///
+/// We choose 1000 so the canonical "1,000,000 record" synthetic dataset partitions into exactly
+/// 1000 shards.
+pub const DEFAULT_SHARD_SIZE: usize = 1000;
+
+/// Number of age buckets in the default layout.
+pub const NUM_BUCKETS: usize = 6;
+
+/// Oldest age a bucket layout covers; every layout covers exactly `0..=MAX_AGE`.
+pub const MAX_AGE: u8 = 120;
+
+/// Most buckets a dataset's layout may have. Every bucket costs a range check per record in the
+/// shard circuit and adds public inputs per measurement.
+pub const MAX_BUCKETS: usize = 24;
+
+/// Inclusive (min_age, max_age) bounds for each bucket of the default layout (see
+/// `types::AgeBuckets`).
+///
+/// Buckets cover [0, 120] and are designed for the demo query:
+/// "Average blood glucose by age range".
+pub const AGE_BUCKETS: [(u8, u8); NUM_BUCKETS] = [
+    (0, 17),
+    (18, 29),
+    (30, 39),
+    (40, 49),
+    (50, 64),
+    (65, 120),
+];
+
+/// Bits the shard circuit range-checks each record's age to.
+pub const AGE_BITS: u32 = 8;
+
+/// Bits the shard circuit range-checks each measurement to.
+pub const MEASUREMENT_BITS: u32 = 16;
+
+/// Number of blood glucose ranges in the per-bucket glucose histogram.
+pub const NUM_GLUCOSE_RANGES: usize = 4;
+
+/// Inclusive (min, max) blood glucose bounds, mg/dL, of each histogram range.
+///
+/// The ranges must be contiguous and cover all of `0..=u16::MAX`: the circuit only compares
+/// glucose against each range's lower bound. The defaults follow the usual fasting-glucose cut
+/// points (hypoglycaemia, normal, prediabetes, diabetes).
+pub const GLUCOSE_RANGES: [(u16, u16); NUM_GLUCOSE_RANGES] = [
+    (0, 69),
+    (70, 99),
+    (100, 125),
+    (126, u16::MAX),
+];
+
+/// Inclusive physiologically plausible blood glucose range, mg/dL.
+///
+/// From circuit v5 on every record's glucose must lie within the shard's public glucose bounds
+/// (`ShardStats::glucose_bounds`), which the prover sets to this range, so absurd values can't
+/// inflate the proven sums. Verifiers reject shards claiming wider bounds.
+pub const PLAUSIBLE_GLUCOSE_MG_DL: (u16, u16) = (20, 600);
+
+/// Public inputs a dual-commitment shard circuit adds for its SHA-256 commitment: the digest's two
+/// 16-byte halves, each read as a big-endian integer (a whole digest doesn't fit in a field element).
+pub const SHA256_COMMITMENT_INPUTS: usize = 2;
+
+/// Prefix of the SHA-256 and BLAKE3 dataset commitment chains (see `chain`).
+pub const DATASET_CHAIN_DOMAIN: &[u8] = b"phl-dataset-chain-v1";
+
+// Poseidon sponge configuration.
+//
+// We use a width-3 sponge (rate=2, capacity=1) to efficiently absorb pairs of field elements.
+// The specific round counts chosen here are consistent with widely used Poseidon instantiations.
+//
+// NOTE: This is a prototype. For production, parameters should be reviewed by cryptographers
+// and ideally fixed via audited constants / standard sets.
+pub const POSEIDON_RATE: usize = 2;
+pub const POSEIDON_CAPACITY: usize = 1;
+
+// Typical Poseidon parameters for width=3.
+pub const POSEIDON_FULL_ROUNDS: usize = 8;
+pub const POSEIDON_PARTIAL_ROUNDS: usize = 57;
+
+/// Poseidon S-box exponent (alpha). Common choices are 5 or 17.
+pub const POSEIDON_ALPHA: u64 = 5;
+
+/// Poseidon parameters for the scalar field `F` of any curve: the same width and round counts,
+/// with round constants and MDS matrix derived for `F` (so they differ from BN254's).
+///
+/// This uses arkworks' parameter derivation helper (Ark + MDS) so both the native hasher
+/// and the in-circuit gadget agree on the same constants.
+pub fn poseidon_config_for<F: PrimeField>() -> PoseidonConfig<F> {
+    // The helper expects the prime field size in bits.
+    let prime_bits = F::MODULUS_BIT_SIZE as u64;
+
+    // Derive the round constants (ARK) and MDS matrix.
+    let (ark, mds) = find_poseidon_ark_and_mds::<F>(
+        prime_bits,
+        POSEIDON_RATE,
+        POSEIDON_FULL_ROUNDS as u64,
+        POSEIDON_PARTIAL_ROUNDS as u64,
+        0,
+    );
+
+    PoseidonConfig::new(
+        POSEIDON_FULL_ROUNDS,
+        POSEIDON_PARTIAL_ROUNDS,
+        POSEIDON_ALPHA,
+        mds,
+        ark,
+        POSEIDON_RATE,
+        POSEIDON_CAPACITY,
+    )
+}
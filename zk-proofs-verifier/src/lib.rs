@@ -0,0 +1,22 @@
+//! Verify-only subset of the ZK layer for the Privacy-Preserving Health-Data Ledger.
+//!
+//! This crate contains:
+//! - The public circuit parameters (shard size, default age buckets, measured field sets, Poseidon
+//!   parameters).
+//! - Public-input types and their JSON representation.
+//! - Groth16 VK/proof decoding, shard proof verification and dataset aggregate and query proof
+//!   verification.
+//! - Decoding of keys, proofs and public inputs from their wire encodings, shared by every
+//!   verifier of the ledger's proofs.
+//! - Recomputation of dataset commitments from shard commitments.
+//!
+//! It deliberately has no prover and no randomness, so auditors, the WASM build and the client
+//! SDK can verify ledger proofs without pulling in the proving stack.
+
+pub mod chain;
+pub mod constants;
+pub mod types;
+pub mod verification;
+pub mod verify;
+#[cfg(feature = "wasm-bindgen")]
+pub mod wasm;
@@ -0,0 +1,557 @@
+//! Public-input types shared between the prover and verifiers.
+
+use crate::constants::{AGE_BUCKETS, GLUCOSE_RANGES, MAX_AGE, MAX_BUCKETS, NUM_GLUCOSE_RANGES, PLAUSIBLE_GLUCOSE_MG_DL};
+use ark_bn254::Fr;
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize};
+use serde::{Deserialize, Serialize};
+
+/// A measured health field that shard proofs can aggregate.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Measurement {
+    /// Blood glucose, mg/dL.
+    BloodGlucose,
+    /// Systolic blood pressure, mmHg.
+    SystolicBp,
+    /// Heart rate, beats per minute.
+    HeartRate,
+    /// Body-mass index in tenths of kg/m² (e.g. 231 = 23.1).
+    Bmi,
+}
+
+impl Measurement {
+    pub const ALL: [Measurement; 4] = [
+        Measurement::BloodGlucose,
+        Measurement::SystolicBp,
+        Measurement::HeartRate,
+        Measurement::Bmi,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Measurement::BloodGlucose => "blood_glucose",
+            Measurement::SystolicBp => "systolic_bp",
+            Measurement::HeartRate => "heart_rate",
+            Measurement::Bmi => "bmi",
+        }
+    }
+
+    /// Unit of the field's values.
+    pub fn unit(self) -> &'static str {
+        match self {
+            Measurement::BloodGlucose => "mg/dL",
+            Measurement::SystolicBp => "mmHg",
+            Measurement::HeartRate => "bpm",
+            Measurement::Bmi => "0.1 kg/m²",
+        }
+    }
+
+    /// Parse a field name; `blood_glucose_mg_dl` is accepted for blood glucose.
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "blood_glucose_mg_dl" => Some(Measurement::BloodGlucose),
+            other => Self::ALL.into_iter().find(|m| m.name() == other),
+        }
+    }
+}
+
+/// The measurements a shard circuit proves aggregates for.
+///
+/// Blood glucose always comes first, so its sums stay in `ShardStats::sum_glucose_by_bucket`; the
+/// sums of the other measurements follow in `ShardStats::extra_sums_by_bucket`. Each field set is
+/// a distinct circuit with its own keys.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FieldSet {
+    /// Blood glucose only (the original circuit).
+    #[default]
+    Glucose,
+    /// Blood glucose, systolic blood pressure, heart rate and BMI.
+    Vitals,
+}
+
+impl FieldSet {
+    pub const ALL: [FieldSet; 2] = [FieldSet::Glucose, FieldSet::Vitals];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            FieldSet::Glucose => "glucose",
+            FieldSet::Vitals => "vitals",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|s| s.name() == name)
+    }
+
+    pub fn measurements(self) -> &'static [Measurement] {
+        match self {
+            FieldSet::Glucose => &Measurement::ALL[..1],
+            FieldSet::Vitals => &Measurement::ALL,
+        }
+    }
+
+    /// Index of `measurement` within this set, if it is measured.
+    pub fn position(self, measurement: Measurement) -> Option<usize> {
+        self.measurements().iter().position(|m| *m == measurement)
+    }
+}
+
+/// Pairing curve a Groth16 key, proof and commitment live on.
+///
+/// The ledger proves on BN254; BLS12-381 (about 120-bit security, against roughly 100 for BN254
+/// after the exTNFS attacks) is the target of curve migrations. Commitments are field elements of
+/// the curve's scalar field, so one dataset has a different commitment per curve.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Curve {
+    #[default]
+    Bn254,
+    Bls12_381,
+}
+
+impl Curve {
+    pub const ALL: [Curve; 2] = [Curve::Bn254, Curve::Bls12_381];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Curve::Bn254 => "bn254",
+            Curve::Bls12_381 => "bls12_381",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|c| c.name() == name)
+    }
+}
+
+/// Inclusive (min_age, max_age) bounds of a dataset's age buckets, chosen when the dataset is
+/// created.
+///
+/// Buckets are in ascending order, contiguous, and cover exactly `0..=MAX_AGE`, so every record
+/// falls in exactly one. The bounds are constants of the shard circuit: each layout is a distinct
+/// circuit with its own keys. The default is `AGE_BUCKETS`.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(try_from = "Vec<(u8, u8)>", into = "Vec<(u8, u8)>")]
+pub struct AgeBuckets(Vec<(u8, u8)>);
+
+impl Default for AgeBuckets {
+    fn default() -> Self {
+        Self(AGE_BUCKETS.to_vec())
+    }
+}
+
+impl TryFrom<Vec<(u8, u8)>> for AgeBuckets {
+    type Error = String;
+
+    fn try_from(bounds: Vec<(u8, u8)>) -> Result<Self, String> {
+        Self::new(bounds)
+    }
+}
+
+impl From<AgeBuckets> for Vec<(u8, u8)> {
+    fn from(buckets: AgeBuckets) -> Self {
+        buckets.0
+    }
+}
+
+impl AgeBuckets {
+    /// Validate a layout: 1 to `MAX_BUCKETS` buckets, each `min <= max`, the first starting at 0,
+    /// each next one starting right after the previous one ends, and the last ending at `MAX_AGE`.
+    pub fn new(bounds: Vec<(u8, u8)>) -> Result<Self, String> {
+        if bounds.is_empty() || bounds.len() > MAX_BUCKETS {
+            return Err(format!("expected 1 to {MAX_BUCKETS} age buckets, got {}", bounds.len()));
+        }
+        if let Some((min, max)) = bounds.iter().find(|(min, max)| min > max) {
+            return Err(format!("age bucket ({min}, {max}) is empty"));
+        }
+        if bounds[0].0 != 0 {
+            return Err("age buckets must start at age 0".to_string());
+        }
+        if let Some(w) = bounds.windows(2).find(|w| u16::from(w[0].1) + 1 != u16::from(w[1].0)) {
+            return Err(format!(
+                "age buckets ({}, {}) and ({}, {}) overlap or leave a gap",
+                w[0].0, w[0].1, w[1].0, w[1].1
+            ));
+        }
+        if bounds[bounds.len() - 1].1 != MAX_AGE {
+            return Err(format!("age buckets must end at age {MAX_AGE}"));
+        }
+        Ok(Self(bounds))
+    }
+
+    pub fn bounds(&self) -> &[(u8, u8)] {
+        &self.0
+    }
+
+    pub fn num_buckets(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_default(&self) -> bool {
+        self.0 == AGE_BUCKETS
+    }
+
+    /// Map an age to its bucket index. Ages above `MAX_AGE` are clamped to the last bucket.
+    ///
+    /// Used by the host to compute expected public outputs (sum/count) that the circuit will enforce.
+    pub fn bucket_for_age(&self, age: u8) -> usize {
+        self.0
+            .iter()
+            .position(|(min, max)| age >= *min && age <= *max)
+            .unwrap_or(self.0.len() - 1)
+    }
+
+    /// The lower bounds of the buckets past the first, comma-separated: the layout's part of a
+    /// circuit id.
+    pub fn id(&self) -> String {
+        let mins: Vec<String> = self.0[1..].iter().map(|(min, _)| min.to_string()).collect();
+        mins.join(",")
+    }
+}
+
+/// Revision of the shard circuit's public outputs.
+///
+/// Each revision appends outputs after those of the previous one, and keys set up for an older
+/// revision keep proving and verifying it. Bump on any constraint change.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum CircuitRevision {
+    /// Sums of every measurement and counts, per age bucket.
+    V1,
+    /// Adds glucose sums of squares (for variance).
+    V2,
+    /// Adds the glucose histogram (counts per glucose range).
+    V3,
+    /// Salts every record in the shard commitment with a witness derived from a per-shard master
+    /// salt, and adds a commitment to that master salt.
+    V4,
+    /// Bounds every record's glucose by public `(min, max)` glucose bounds.
+    V5,
+}
+
+impl CircuitRevision {
+    pub const ALL: [CircuitRevision; 5] =
+        [CircuitRevision::V1, CircuitRevision::V2, CircuitRevision::V3, CircuitRevision::V4, CircuitRevision::V5];
+
+    /// The revision new keys are set up for.
+    pub const LATEST: CircuitRevision = CircuitRevision::V5;
+
+    /// Version tag, part of the circuit id.
+    pub fn version(self) -> &'static str {
+        match self {
+            CircuitRevision::V1 => "shard-aggregate-v1",
+            CircuitRevision::V2 => "shard-aggregate-v2",
+            CircuitRevision::V3 => "shard-aggregate-v3",
+            CircuitRevision::V4 => "shard-aggregate-v4",
+            CircuitRevision::V5 => "shard-aggregate-v5",
+        }
+    }
+
+    /// The revision with version tag `version`.
+    pub fn parse(version: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|r| r.version() == version)
+    }
+
+    /// The revision a circuit id (see `zk_proofs::constants::circuit_id`) names.
+    pub fn of_circuit_id(circuit_id: &str) -> Option<Self> {
+        Self::parse(circuit_id.split('/').next()?)
+    }
+
+    pub fn proves_sum_sq(self) -> bool {
+        self >= CircuitRevision::V2
+    }
+
+    pub fn proves_histogram(self) -> bool {
+        self >= CircuitRevision::V3
+    }
+
+    pub fn proves_salt(self) -> bool {
+        self >= CircuitRevision::V4
+    }
+
+    pub fn proves_glucose_bounds(self) -> bool {
+        self >= CircuitRevision::V5
+    }
+
+    /// Number of public inputs of the shard circuit for `field_set` with `num_buckets` age buckets.
+    pub fn num_public_inputs(self, field_set: FieldSet, num_buckets: usize) -> usize {
+        let sum_sq = if self.proves_sum_sq() { num_buckets } else { 0 };
+        let histogram = if self.proves_histogram() { num_buckets * NUM_GLUCOSE_RANGES } else { 0 };
+        let salt = usize::from(self.proves_salt());
+        let glucose_bounds = usize::from(self.proves_glucose_bounds());
+        1 + (1 + field_set.measurements().len()) * num_buckets + sum_sq + histogram + salt + glucose_bounds
+    }
+}
+
+/// A shard's aggregate statistics, bucketed by age (one entry per bucket of the dataset's
+/// `AgeBuckets`).
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ShardStats {
+    /// Sum of blood glucose per age bucket.
+    pub sum_glucose_by_bucket: Vec<u64>,
+    /// Count of records per age bucket.
+    pub count_by_bucket: Vec<u64>,
+    /// Sums per age bucket of the field set's further measurements, in `FieldSet::measurements`
+    /// order after glucose. Empty for glucose-only shards.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub extra_sums_by_bucket: Vec<Vec<u64>>,
+    /// Sum of squared blood glucose per age bucket, for variance. `None` for shards proven with
+    /// keys that predate it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sum_glucose_sq_by_bucket: Option<Vec<u64>>,
+    /// Count of records per age bucket and `GLUCOSE_RANGES` range. `None` for shards proven with
+    /// keys that predate it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub glucose_histogram_by_bucket: Option<Vec<[u64; NUM_GLUCOSE_RANGES]>>,
+    /// Poseidon hash of the master salt the shard's per-record salts were derived from, as
+    /// `FrHex` hex. `None` for shards proven with keys that predate salting.
+    #[serde(default, rename = "salt_commitment_hex", with = "opt_fr_hex", skip_serializing_if = "Option::is_none")]
+    pub salt_commitment: Option<Fr>,
+    /// SHA-256 of the shard's canonical record encoding (see `zk_proofs::groth16::sha256_commitment`),
+    /// for systems that anchor plain hashes. `None` unless the shard was proven with dual-commitment
+    /// keys, which bind it to the Poseidon commitment in-circuit.
+    #[serde(default, rename = "sha256_commitment_hex", with = "opt_digest_hex", skip_serializing_if = "Option::is_none")]
+    pub sha256_commitment: Option<[u8; 32]>,
+    /// Inclusive `(min, max)` the proof shows every record's glucose lies within (mg/dL). `None`
+    /// for shards proven with keys that predate it, which only bound glucose to 16 bits.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub glucose_bounds: Option<(u16, u16)>,
+}
+
+impl ShardStats {
+    pub fn zero() -> Self {
+        Self::zero_for(FieldSet::Glucose, &AgeBuckets::default())
+    }
+
+    pub fn zero_for(field_set: FieldSet, buckets: &AgeBuckets) -> Self {
+        let n = buckets.num_buckets();
+        Self {
+            sum_glucose_by_bucket: vec![0u64; n],
+            count_by_bucket: vec![0u64; n],
+            extra_sums_by_bucket: vec![vec![0u64; n]; field_set.measurements().len() - 1],
+            sum_glucose_sq_by_bucket: Some(vec![0u64; n]),
+            glucose_histogram_by_bucket: Some(vec![[0u64; NUM_GLUCOSE_RANGES]; n]),
+            salt_commitment: None,
+            sha256_commitment: None,
+            glucose_bounds: Some(PLAUSIBLE_GLUCOSE_MG_DL),
+        }
+    }
+
+    /// Drop the outputs that circuit `revision` doesn't prove.
+    pub fn restrict_to(&mut self, revision: CircuitRevision) {
+        if !revision.proves_sum_sq() {
+            self.sum_glucose_sq_by_bucket = None;
+        }
+        if !revision.proves_histogram() {
+            self.glucose_histogram_by_bucket = None;
+        }
+        if !revision.proves_salt() {
+            self.salt_commitment = None;
+            self.sha256_commitment = None;
+        }
+        if !revision.proves_glucose_bounds() {
+            self.glucose_bounds = None;
+        }
+    }
+
+    /// Whether the glucose bounds, if proven, lie within `PLAUSIBLE_GLUCOSE_MG_DL`. Wider bounds
+    /// would let a prover sum absurd values under a valid proof.
+    pub fn glucose_bounds_plausible(&self) -> bool {
+        let (lo, hi) = PLAUSIBLE_GLUCOSE_MG_DL;
+        self.glucose_bounds.is_none_or(|(min, max)| lo <= min && min <= max && max <= hi)
+    }
+
+    /// Per-bucket sums of the measurement at `index` in the shard's field set.
+    pub fn sums_by_bucket(&self, index: usize) -> Option<&Vec<u64>> {
+        match index {
+            0 => Some(&self.sum_glucose_by_bucket),
+            i => self.extra_sums_by_bucket.get(i - 1),
+        }
+    }
+
+    pub fn sums_by_bucket_mut(&mut self, index: usize) -> Option<&mut Vec<u64>> {
+        match index {
+            0 => Some(&mut self.sum_glucose_by_bucket),
+            i => self.extra_sums_by_bucket.get_mut(i - 1),
+        }
+    }
+}
+
+/// Public statement of a dataset aggregate proof (`zk_proofs::aggregate`): the dataset commitment,
+/// a Merkle root over every shard's public inputs, and the totals those inputs sum to.
+#[derive(Clone, Debug)]
+pub struct DatasetAggregate {
+    /// Poseidon chain over the shard commitments, in shard order.
+    pub dataset_commitment: Fr,
+    /// Poseidon Merkle root over the shards' public-input vectors, in shard order.
+    pub shard_inputs_root: Fr,
+    /// Element-wise sums of the shards' aggregates. Salt and SHA-256 commitments and glucose
+    /// bounds don't sum and are ignored.
+    pub totals: ShardStats,
+}
+
+/// Public statement of a query proof (`zk_proofs::query`): one released sum and count, each the
+/// total of one position of the shards' public inputs, over the shards the dataset commitment
+/// chains.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct QueryStatement {
+    /// Poseidon chain over the shard commitments, in shard order.
+    pub dataset_commitment: Fr,
+    /// Poseidon Merkle root over the shards' public-input vectors, in shard order.
+    pub shard_inputs_root: Fr,
+    /// Positions of the summed measurement and of the count in a shard's public-input vector
+    /// (see `query_input_indices`).
+    pub sum_index: usize,
+    pub count_index: usize,
+    pub sum: u64,
+    pub count: u64,
+}
+
+/// Widths of the bounds released in range-released mode: every bound is `[k·w, (k+1)·w − 1]` for
+/// the `k` that contains the true value, so only `value / w` is revealed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RangeWidths {
+    /// Width of the bounds on each per-bucket sum.
+    pub sum: u64,
+    /// Width of the bounds on each per-bucket count.
+    pub count: u64,
+}
+
+/// Public outputs of a shard proven in range-released mode: inclusive bounds `(lo, hi)` on each
+/// per-bucket sum and count instead of the exact values, which stay private. There are no sums of
+/// squares or histograms in this mode.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ShardRanges {
+    pub sum_glucose_by_bucket: Vec<(u64, u64)>,
+    pub count_by_bucket: Vec<(u64, u64)>,
+    /// Bounds on the sums of the field set's further measurements, as in
+    /// `ShardStats::extra_sums_by_bucket`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub extra_sums_by_bucket: Vec<Vec<(u64, u64)>>,
+    /// As `ShardStats::salt_commitment`. Range-released shards are always salted, so a proof never
+    /// verifies without it.
+    #[serde(default, rename = "salt_commitment_hex", with = "opt_fr_hex", skip_serializing_if = "Option::is_none")]
+    pub salt_commitment: Option<Fr>,
+}
+
+impl ShardRanges {
+    /// The bounds of `widths` around the exact aggregates in `stats`.
+    pub fn around(stats: &ShardStats, widths: RangeWidths) -> Self {
+        let bound = |value: u64, width: u64| {
+            let width = width.max(1);
+            let lo = value - value % width;
+            (lo, lo.saturating_add(width - 1))
+        };
+        let bounds = |values: &[u64], width: u64| values.iter().map(|v| bound(*v, width)).collect();
+        Self {
+            sum_glucose_by_bucket: bounds(&stats.sum_glucose_by_bucket, widths.sum),
+            count_by_bucket: bounds(&stats.count_by_bucket, widths.count),
+            extra_sums_by_bucket: stats.extra_sums_by_bucket.iter().map(|sums| bounds(sums, widths.sum)).collect(),
+            salt_commitment: stats.salt_commitment,
+        }
+    }
+
+    /// Whether every exact aggregate of `stats` lies within its bounds.
+    pub fn contains(&self, stats: &ShardStats) -> bool {
+        let within = |bounds: &[(u64, u64)], values: &[u64]| {
+            bounds.len() == values.len() && bounds.iter().zip(values).all(|((lo, hi), v)| lo <= v && v <= hi)
+        };
+        within(&self.sum_glucose_by_bucket, &stats.sum_glucose_by_bucket)
+            && within(&self.count_by_bucket, &stats.count_by_bucket)
+            && self.extra_sums_by_bucket.len() == stats.extra_sums_by_bucket.len()
+            && self.extra_sums_by_bucket.iter().zip(&stats.extra_sums_by_bucket).all(|(b, v)| within(b, v))
+    }
+
+    /// Number of public inputs of the range-released shard circuit for `field_set` with
+    /// `num_buckets` age buckets.
+    pub fn num_public_inputs(field_set: FieldSet, num_buckets: usize) -> usize {
+        1 + 2 * (1 + field_set.measurements().len()) * num_buckets + 1
+    }
+}
+
+/// JSON-friendly representation of a field element.
+///
+/// We expose Fr values as hex strings (big-endian) to avoid ambiguities.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct FrHex {
+    pub hex: String,
+}
+
+impl FrHex {
+    pub fn from_fr(x: &Fr) -> Self {
+        // Use arkworks' canonical compressed encoding so all components agree.
+        let mut bytes = Vec::new();
+        x.serialize_compressed(&mut bytes)
+            .expect("in-memory serialization");
+        Self { hex: hex::encode(bytes) }
+    }
+
+    pub fn to_fr(&self) -> Result<Fr, String> {
+        let bytes = hex::decode(&self.hex).map_err(|e| format!("invalid hex: {e}"))?;
+        Fr::deserialize_compressed(&bytes[..]).map_err(|e| format!("invalid field bytes: {e}"))
+    }
+}
+
+/// (De)serializes an optional field element as a bare `FrHex` hex string.
+mod opt_fr_hex {
+    use super::FrHex;
+    use ark_bn254::Fr;
+    use serde::de::Error;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<S: Serializer>(x: &Option<Fr>, s: S) -> Result<S::Ok, S::Error> {
+        x.as_ref().map(|x| FrHex::from_fr(x).hex).serialize(s)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<Option<Fr>, D::Error> {
+        Option::<String>::deserialize(d)?
+            .map(|hex| FrHex { hex }.to_fr().map_err(D::Error::custom))
+            .transpose()
+    }
+}
+
+/// (De)serializes an optional SHA-256 digest as a hex string.
+mod opt_digest_hex {
+    use serde::de::Error;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<S: Serializer>(x: &Option<[u8; 32]>, s: S) -> Result<S::Ok, S::Error> {
+        x.as_ref().map(hex::encode).serialize(s)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<Option<[u8; 32]>, D::Error> {
+        Option::<String>::deserialize(d)?
+            .map(|h| {
+                let bytes = hex::decode(&h).map_err(D::Error::custom)?;
+                <[u8; 32]>::try_from(bytes).map_err(|_| D::Error::custom("expected a 32-byte digest"))
+            })
+            .transpose()
+    }
+}
+
+/// Public inputs for a shard proof.
+///
+/// Ordering MUST match the circuit's public input allocation order.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ShardPublicInputs {
+    pub shard_commitment: FrHex,
+    pub sum_glucose_by_bucket: Vec<u64>,
+    pub count_by_bucket: Vec<u64>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub extra_sums_by_bucket: Vec<Vec<u64>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sum_glucose_sq_by_bucket: Option<Vec<u64>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub glucose_histogram_by_bucket: Option<Vec<[u64; NUM_GLUCOSE_RANGES]>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub salt_commitment: Option<FrHex>,
+    #[serde(default, rename = "sha256_commitment_hex", with = "opt_digest_hex", skip_serializing_if = "Option::is_none")]
+    pub sha256_commitment: Option<[u8; 32]>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub glucose_bounds: Option<(u16, u16)>,
+}
+
+/// Map a blood glucose value to its `GLUCOSE_RANGES` index.
+pub fn glucose_range_for(glucose: u16) -> usize {
+    GLUCOSE_RANGES.iter().position(|(min, max)| glucose >= *min && glucose <= *max).unwrap_or(NUM_GLUCOSE_RANGES - 1)
+}
@@ -0,0 +1,168 @@
+//! Decoding and verifying shard proofs from their wire encodings.
+//!
+//! Proofs and keys travel as base64 of their compressed arkworks serialization, field elements as
+//! hex of theirs, SHA-256 commitments as plain hex digests. The backend's `/verify` endpoints, the
+//! `ledger-verify` CLI and the WASM bindings all decode through here, so a malformed input is
+//! rejected the same way (and with the same message) wherever it is checked.
+//!
+//! `verify_encoded_shard` takes an `EncodedShard` (one entry of a shards listing deserializes into
+//! one) and gives a `VerificationOutcome`: valid, invalid, or malformed with the `DecodeError`.
+
+use crate::types::ShardStats;
+use crate::verify::{deserialize_proof_on, deserialize_vk_on, verify_shard_proof_on, ShardProofInstance};
+use ark_bn254::Bn254;
+use ark_ec::pairing::Pairing;
+use ark_groth16::{Proof, VerifyingKey};
+use ark_serialize::CanonicalDeserialize;
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+/// Why an encoded key, proof or public input couldn't be decoded.
+#[derive(Clone, Debug, Error, PartialEq, Eq)]
+pub enum DecodeError {
+    #[error("missing {0}")]
+    Missing(&'static str),
+
+    #[error("invalid {0}")]
+    Base64(&'static str),
+
+    #[error("invalid {0} hex")]
+    Hex(&'static str),
+
+    #[error("invalid {0} bytes")]
+    Bytes(&'static str),
+
+    #[error("invalid vk")]
+    Vk,
+
+    #[error("invalid proof")]
+    Proof,
+
+    #[error("invalid sha256 commitment")]
+    Sha256Commitment,
+
+    #[error("glucose bounds wider than the plausible range")]
+    GlucoseBounds,
+}
+
+/// A shard proof and its public inputs as they travel, named as in shard listings. The stats'
+/// own `salt_commitment` and `sha256_commitment` are ignored; those come from the hex fields.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct EncodedShard {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub proof_b64: Option<String>,
+    pub shard_commitment_hex: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub salt_commitment_hex: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sha256_commitment_hex: Option<String>,
+    #[serde(flatten)]
+    pub stats: ShardStats,
+}
+
+/// The decoded public inputs of a shard proof on curve `E`. `stats.salt_commitment` is unset, as
+/// it only holds BN254 elements; the salt commitment on `E` is `salt_commitment`.
+#[derive(Clone, Debug)]
+pub struct DecodedShard<E: Pairing> {
+    pub commitment: E::ScalarField,
+    pub stats: ShardStats,
+    pub salt_commitment: Option<E::ScalarField>,
+}
+
+/// Result of checking one encoded shard proof.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum VerificationOutcome {
+    Valid,
+    /// Well-formed, but the proof doesn't prove these public inputs under the key.
+    Invalid,
+    Malformed(DecodeError),
+}
+
+impl VerificationOutcome {
+    pub fn is_valid(&self) -> bool {
+        matches!(self, Self::Valid)
+    }
+}
+
+fn decode_b64(b64: &str, what: &'static str) -> Result<Vec<u8>, DecodeError> {
+    base64::engine::general_purpose::STANDARD
+        .decode(b64.trim())
+        .map_err(|_| DecodeError::Base64(what))
+}
+
+fn decode_field<F: CanonicalDeserialize>(hex_str: &str, what: &'static str) -> Result<F, DecodeError> {
+    let bytes = hex::decode(hex_str.trim()).map_err(|_| DecodeError::Hex(what))?;
+    F::deserialize_compressed(&bytes[..]).map_err(|_| DecodeError::Bytes(what))
+}
+
+/// Decode a base64 verifying key on curve `E`.
+pub fn decode_vk_b64<E: Pairing>(vk_b64: &str) -> Result<VerifyingKey<E>, DecodeError> {
+    deserialize_vk_on::<E>(&decode_b64(vk_b64, "vk_b64")?).map_err(|_| DecodeError::Vk)
+}
+
+/// Decode a base64 shard proof on curve `E`.
+pub fn decode_proof_b64<E: Pairing>(proof_b64: &str) -> Result<Proof<E>, DecodeError> {
+    deserialize_proof_on::<E>(&decode_b64(proof_b64, "proof_b64")?).map_err(|_| DecodeError::Proof)
+}
+
+/// Decode a shard's public inputs on curve `E`. Glucose bounds wider than
+/// `PLAUSIBLE_GLUCOSE_MG_DL` are rejected: a proof over them shows little.
+pub fn decode_public_inputs<E: Pairing>(shard: &EncodedShard) -> Result<DecodedShard<E>, DecodeError> {
+    if !shard.stats.glucose_bounds_plausible() {
+        return Err(DecodeError::GlucoseBounds);
+    }
+    let commitment = decode_field(&shard.shard_commitment_hex, "commitment")?;
+    let salt_commitment = shard
+        .salt_commitment_hex
+        .as_deref()
+        .map(|hex| decode_field(hex, "salt commitment"))
+        .transpose()?;
+    let sha256_commitment = shard
+        .sha256_commitment_hex
+        .as_deref()
+        .map(|h| {
+            hex::decode(h.trim())
+                .ok()
+                .and_then(|bytes| <[u8; 32]>::try_from(bytes).ok())
+                .ok_or(DecodeError::Sha256Commitment)
+        })
+        .transpose()?;
+
+    Ok(DecodedShard {
+        commitment,
+        stats: ShardStats {
+            salt_commitment: None,
+            sha256_commitment,
+            ..shard.stats.clone()
+        },
+        salt_commitment,
+    })
+}
+
+/// Decode a shard proof on curve `E` and its public inputs.
+pub fn decode_shard<E: Pairing>(shard: &EncodedShard) -> Result<(Proof<E>, DecodedShard<E>), DecodeError> {
+    let proof = decode_proof_b64::<E>(shard.proof_b64.as_deref().ok_or(DecodeError::Missing("proof_b64"))?)?;
+    Ok((proof, decode_public_inputs::<E>(shard)?))
+}
+
+/// Decode a BN254 shard proof and its public inputs into a batch verification instance.
+pub fn decode_shard_instance(shard: &EncodedShard) -> Result<ShardProofInstance, DecodeError> {
+    let (proof, DecodedShard { commitment, mut stats, salt_commitment }) = decode_shard::<Bn254>(shard)?;
+    stats.salt_commitment = salt_commitment;
+    Ok(ShardProofInstance { proof, commitment, stats })
+}
+
+/// Whether `proof` proves `inputs` under `vk`.
+pub fn verify_decoded<E: Pairing>(vk: &VerifyingKey<E>, proof: &Proof<E>, inputs: &DecodedShard<E>) -> bool {
+    verify_shard_proof_on(vk, proof, inputs.commitment, &inputs.stats, inputs.salt_commitment).is_ok()
+}
+
+/// Decode and verify one shard proof on curve `E`.
+pub fn verify_encoded_shard<E: Pairing>(vk: &VerifyingKey<E>, shard: &EncodedShard) -> VerificationOutcome {
+    match decode_shard::<E>(shard) {
+        Ok((proof, inputs)) if verify_decoded(vk, &proof, &inputs) => VerificationOutcome::Valid,
+        Ok(_) => VerificationOutcome::Invalid,
+        Err(e) => VerificationOutcome::Malformed(e),
+    }
+}
@@ -0,0 +1,381 @@
+//! Groth16 verification for shard proofs, one at a time or in batches.
+//!
+//! The ledger's proofs are on BN254; the `_on` variants take the curve as a type parameter, for
+//! shards re-proven on BLS12-381 by a curve migration.
+//!
+//! Public input ordering here is the contract with the circuit in `zk-proofs`; any change to the
+//! circuit's `new_input` allocation order must be mirrored in `shard_public_inputs_to_field_elems`
+//! (or `shard_range_inputs_to_field_elems` for range-released mode). Dataset aggregate proofs
+//! follow `aggregate_public_inputs_to_field_elems`, query proofs
+//! `query_public_inputs_to_field_elems`.
+
+use crate::constants::{NUM_GLUCOSE_RANGES, SHA256_COMMITMENT_INPUTS};
+use crate::types::{CircuitRevision, DatasetAggregate, FieldSet, QueryStatement, ShardRanges, ShardStats};
+use ark_bn254::{Bn254, Fr, G1Affine, G1Projective, G2Affine};
+use ark_ec::pairing::Pairing;
+use ark_ec::{CurveGroup, VariableBaseMSM};
+use ark_ff::{PrimeField, Zero};
+use ark_groth16::{prepare_verifying_key, Groth16, Proof, VerifyingKey};
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize};
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum ZkError {
+    #[error("invalid shard size: expected {expected}, got {got}")]
+    InvalidShardSize { expected: usize, got: usize },
+
+    #[error("unsupported shard size: {0}")]
+    UnsupportedShardSize(usize),
+
+    #[error("serialization error: {0}")]
+    Serialization(String),
+
+    #[error("record {index}: glucose {glucose} mg/dL is outside the circuit's bounds {min}..={max}")]
+    GlucoseOutOfBounds { index: usize, glucose: u16, min: u16, max: u16 },
+
+    #[error("invalid aggregate: {0}")]
+    InvalidAggregate(String),
+
+    #[error("proof verification failed")]
+    VerificationFailed,
+
+    #[error("arkworks error: {0}")]
+    Ark(String),
+
+    #[error("ceremony: {0}")]
+    Ceremony(String),
+}
+
+/// Convert (commitment, stats) to the public-input vector expected by Groth16.
+///
+/// ORDERING MUST MATCH the circuit's `new_input` allocation order.
+pub fn shard_public_inputs_to_field_elems(commitment: Fr, stats: &ShardStats) -> Vec<Fr> {
+    shard_public_inputs_on(commitment, stats, stats.salt_commitment)
+}
+
+/// `shard_public_inputs_to_field_elems` over the scalar field of any curve. `stats` carries BN254
+/// salt commitments, so the salt commitment on `F` is passed separately (and the one in `stats` is
+/// ignored).
+pub fn shard_public_inputs_on<F: PrimeField>(commitment: F, stats: &ShardStats, salt_commitment: Option<F>) -> Vec<F> {
+    let sq = usize::from(stats.sum_glucose_sq_by_bucket.is_some());
+    let histogram = if stats.glucose_histogram_by_bucket.is_some() { NUM_GLUCOSE_RANGES } else { 0 };
+    let salt = usize::from(salt_commitment.is_some());
+    let glucose_bounds = usize::from(stats.glucose_bounds.is_some());
+    let sha256 = if stats.sha256_commitment.is_some() { SHA256_COMMITMENT_INPUTS } else { 0 };
+    let num_buckets = stats.count_by_bucket.len();
+    let mut v = Vec::with_capacity(1 + (2 + stats.extra_sums_by_bucket.len() + sq + histogram) * num_buckets + salt + glucose_bounds + sha256);
+    v.push(commitment);
+    v.extend(stats.sum_glucose_by_bucket.iter().map(|s| F::from(*s)));
+    v.extend(stats.count_by_bucket.iter().map(|c| F::from(*c)));
+    // Further measurements come last so glucose-only inputs keep their original layout.
+    for sums in &stats.extra_sums_by_bucket {
+        v.extend(sums.iter().map(|s| F::from(*s)));
+    }
+    // Sums of squares were added later still, so they follow everything else.
+    if let Some(sums) = &stats.sum_glucose_sq_by_bucket {
+        v.extend(sums.iter().map(|s| F::from(*s)));
+    }
+    // Then the histogram, bucket by bucket, range by range.
+    if let Some(histogram) = &stats.glucose_histogram_by_bucket {
+        v.extend(histogram.iter().flatten().map(|c| F::from(*c)));
+    }
+    // Then the master salt commitment, the glucose bounds, and the SHA-256 commitment of
+    // dual-commitment keys.
+    v.extend(salt_commitment);
+    v.extend(stats.glucose_bounds.map(glucose_bounds_to_field_elem::<F>));
+    if let Some(digest) = &stats.sha256_commitment {
+        v.extend(sha256_digest_to_field_elems::<F>(digest));
+    }
+    v
+}
+
+/// The public input carrying glucose bounds `(min, max)`: `min + 2^16 · max`. One input rather
+/// than two keeps every revision's input count distinct from the others' dual-commitment variants
+/// (see `vk_revision`).
+pub fn glucose_bounds_to_field_elem<F: PrimeField>((min, max): (u16, u16)) -> F {
+    F::from(min as u64 + ((max as u64) << 16))
+}
+
+/// The two public inputs carrying a SHA-256 digest: bytes `0..16` and `16..32`, each as a
+/// big-endian `u128`.
+pub fn sha256_digest_to_field_elems<F: PrimeField>(digest: &[u8; 32]) -> [F; SHA256_COMMITMENT_INPUTS] {
+    let half = |bytes: &[u8]| F::from(u128::from_be_bytes(bytes.try_into().expect("16-byte half")));
+    [half(&digest[..16]), half(&digest[16..])]
+}
+
+/// Convert (commitment, ranges) to the public-input vector of the range-released shard circuit:
+/// the commitment, `(lo, hi)` per bucket for the glucose sums, the counts and each further
+/// measurement's sums, then the salt commitment.
+pub fn shard_range_inputs_to_field_elems(commitment: Fr, ranges: &ShardRanges) -> Vec<Fr> {
+    let mut v = Vec::with_capacity(2 + 2 * (2 + ranges.extra_sums_by_bucket.len()) * ranges.count_by_bucket.len());
+    v.push(commitment);
+    let bounds = [&ranges.sum_glucose_by_bucket, &ranges.count_by_bucket].into_iter().chain(&ranges.extra_sums_by_bucket);
+    for (lo, hi) in bounds.flatten() {
+        v.extend([Fr::from(*lo), Fr::from(*hi)]);
+    }
+    v.extend(ranges.salt_commitment);
+    v
+}
+
+/// The circuit revision `vk` was set up for (for `field_set` and `num_buckets` age buckets), told
+/// apart by its number of public inputs. Anything unrecognised is reported as `V1` (and won't
+/// verify).
+pub fn vk_revision(vk: &VerifyingKey<Bn254>, field_set: FieldSet, num_buckets: usize) -> CircuitRevision {
+    let sha256 = usize::from(vk_sha256_commitment(vk, field_set, num_buckets)) * SHA256_COMMITMENT_INPUTS;
+    CircuitRevision::ALL
+        .into_iter()
+        .find(|r| vk.gamma_abc_g1.len() == 1 + r.num_public_inputs(field_set, num_buckets) + sha256)
+        .unwrap_or(CircuitRevision::V1)
+}
+
+/// Whether `vk` was set up for dual-commitment shards, which also prove a SHA-256 commitment (only
+/// salted revisions offer it).
+pub fn vk_sha256_commitment(vk: &VerifyingKey<Bn254>, field_set: FieldSet, num_buckets: usize) -> bool {
+    CircuitRevision::ALL
+        .into_iter()
+        .filter(|r| r.proves_salt())
+        .any(|r| vk.gamma_abc_g1.len() == 1 + r.num_public_inputs(field_set, num_buckets) + SHA256_COMMITMENT_INPUTS)
+}
+
+/// Verify a shard proof.
+pub fn verify_shard_proof(
+    vk: &VerifyingKey<Bn254>,
+    proof: &Proof<Bn254>,
+    commitment: Fr,
+    stats: &ShardStats,
+) -> Result<(), ZkError> {
+    let public_inputs = shard_public_inputs_to_field_elems(commitment, stats);
+    let pvk = prepare_verifying_key(vk);
+    let ok = Groth16::<Bn254>::verify_proof(&pvk, proof, &public_inputs)
+        .map_err(|e| ZkError::Ark(format!("{e}")))?;
+    if !ok {
+        return Err(ZkError::VerificationFailed);
+    }
+    Ok(())
+}
+
+/// Verify a shard proof on any curve, with the salt commitment on that curve (see
+/// `shard_public_inputs_on`).
+pub fn verify_shard_proof_on<E: Pairing>(
+    vk: &VerifyingKey<E>,
+    proof: &Proof<E>,
+    commitment: E::ScalarField,
+    stats: &ShardStats,
+    salt_commitment: Option<E::ScalarField>,
+) -> Result<(), ZkError> {
+    let public_inputs = shard_public_inputs_on(commitment, stats, salt_commitment);
+    let pvk = prepare_verifying_key(vk);
+    let ok = Groth16::<E>::verify_proof(&pvk, proof, &public_inputs)
+        .map_err(|e| ZkError::Ark(format!("{e}")))?;
+    if !ok {
+        return Err(ZkError::VerificationFailed);
+    }
+    Ok(())
+}
+
+/// Verify a range-released shard proof: the shard's exact aggregates lie within `ranges`.
+pub fn verify_shard_range_proof(
+    vk: &VerifyingKey<Bn254>,
+    proof: &Proof<Bn254>,
+    commitment: Fr,
+    ranges: &ShardRanges,
+) -> Result<(), ZkError> {
+    let public_inputs = shard_range_inputs_to_field_elems(commitment, ranges);
+    let pvk = prepare_verifying_key(vk);
+    let ok = Groth16::<Bn254>::verify_proof(&pvk, proof, &public_inputs)
+        .map_err(|e| ZkError::Ark(format!("{e}")))?;
+    if !ok {
+        return Err(ZkError::VerificationFailed);
+    }
+    Ok(())
+}
+
+/// Public-input vector of the dataset aggregate circuit: the dataset commitment, the shard inputs
+/// root, then the totals laid out as a shard's aggregates are (without commitments).
+pub fn aggregate_public_inputs_to_field_elems(aggregate: &DatasetAggregate) -> Vec<Fr> {
+    let totals = ShardStats {
+        salt_commitment: None,
+        sha256_commitment: None,
+        glucose_bounds: None,
+        ..aggregate.totals.clone()
+    };
+    let mut v = vec![aggregate.dataset_commitment, aggregate.shard_inputs_root];
+    v.extend(shard_public_inputs_to_field_elems(Fr::zero(), &totals).into_iter().skip(1));
+    v
+}
+
+/// Positions in a shard's public-input vector of the sums of measurement `field_index` (its index
+/// in the field set) and of the counts, for age bucket `bucket_index` of `num_buckets`.
+pub fn query_input_indices(field_index: usize, bucket_index: usize, num_buckets: usize) -> (usize, usize) {
+    let sum_index = match field_index {
+        0 => 1 + bucket_index,
+        f => 1 + (1 + f) * num_buckets + bucket_index,
+    };
+    (sum_index, 1 + num_buckets + bucket_index)
+}
+
+/// Public-input vector of the query circuit: the dataset commitment, the shard inputs root, the
+/// sum and count positions, then the sum and count.
+pub fn query_public_inputs_to_field_elems(statement: &QueryStatement) -> Vec<Fr> {
+    vec![
+        statement.dataset_commitment,
+        statement.shard_inputs_root,
+        Fr::from(statement.sum_index as u64),
+        Fr::from(statement.count_index as u64),
+        Fr::from(statement.sum),
+        Fr::from(statement.count),
+    ]
+}
+
+/// Verify a query proof.
+pub fn verify_query_proof(vk: &VerifyingKey<Bn254>, proof: &Proof<Bn254>, statement: &QueryStatement) -> Result<(), ZkError> {
+    let public_inputs = query_public_inputs_to_field_elems(statement);
+    let pvk = prepare_verifying_key(vk);
+    let ok = Groth16::<Bn254>::verify_proof(&pvk, proof, &public_inputs)
+        .map_err(|e| ZkError::Ark(format!("{e}")))?;
+    if !ok {
+        return Err(ZkError::VerificationFailed);
+    }
+    Ok(())
+}
+
+/// Verify a dataset aggregate proof.
+pub fn verify_aggregate_proof(
+    vk: &VerifyingKey<Bn254>,
+    proof: &Proof<Bn254>,
+    aggregate: &DatasetAggregate,
+) -> Result<(), ZkError> {
+    let public_inputs = aggregate_public_inputs_to_field_elems(aggregate);
+    let pvk = prepare_verifying_key(vk);
+    let ok = Groth16::<Bn254>::verify_proof(&pvk, proof, &public_inputs)
+        .map_err(|e| ZkError::Ark(format!("{e}")))?;
+    if !ok {
+        return Err(ZkError::VerificationFailed);
+    }
+    Ok(())
+}
+
+/// One shard proof with its public inputs, for batch verification.
+#[derive(Clone, Debug)]
+pub struct ShardProofInstance {
+    pub proof: Proof<Bn254>,
+    pub commitment: Fr,
+    pub stats: ShardStats,
+}
+
+/// Verify many shard proofs against the same verifying key with one multi-pairing.
+///
+/// Each proof's check `e(A, B) = e(α, β) · e(L, γ) · e(C, δ)` is scaled by a 128-bit coefficient
+/// and all of them are multiplied together, so the batch costs one Miller loop per proof plus a
+/// single final exponentiation, and the input terms collapse into one MSM over the key. The
+/// coefficients are derived from a hash of the key and every proof and input (no RNG needed): a
+/// batch containing an invalid proof passes with probability about 2^-128. `Ok` means every proof
+/// verifies; use `invalid_shard_proofs` to find the culprits when it fails.
+pub fn verify_shard_proofs_batch(vk: &VerifyingKey<Bn254>, instances: &[ShardProofInstance]) -> Result<(), ZkError> {
+    if instances.is_empty() {
+        return Ok(());
+    }
+
+    let inputs: Vec<Vec<Fr>> = instances
+        .iter()
+        .map(|i| shard_public_inputs_to_field_elems(i.commitment, &i.stats))
+        .collect();
+    if inputs.iter().any(|x| x.len() + 1 != vk.gamma_abc_g1.len()) {
+        // Same outcome as a single verification with the wrong number of inputs.
+        return Err(ZkError::Ark("malformed verifying key".to_string()));
+    }
+
+    let coefficients = batch_coefficients(vk, instances, &inputs)?;
+
+    // Σ r_j·L_j = (Σ r_j)·IC_0 + Σ_i (Σ_j r_j·x_ji)·IC_i
+    let mut ic_scalars = vec![Fr::zero(); vk.gamma_abc_g1.len()];
+    for (r, x) in coefficients.iter().zip(&inputs) {
+        ic_scalars[0] += r;
+        for (s, x_i) in ic_scalars[1..].iter_mut().zip(x) {
+            *s += *r * x_i;
+        }
+    }
+    let sum_l = G1Projective::msm(&vk.gamma_abc_g1, &ic_scalars).map_err(|_| ZkError::VerificationFailed)?;
+    let c: Vec<G1Affine> = instances.iter().map(|i| i.proof.c).collect();
+    let sum_c = G1Projective::msm(&c, &coefficients).map_err(|_| ZkError::VerificationFailed)?;
+    let sum_r: Fr = coefficients.iter().sum();
+
+    // Π e(r_j·A_j, B_j) · e(-(Σ r_j)·α, β) · e(-Σ r_j·L_j, γ) · e(-Σ r_j·C_j, δ) = 1
+    let mut g1: Vec<G1Affine> = instances
+        .iter()
+        .zip(&coefficients)
+        .map(|(i, r)| (i.proof.a * r).into_affine())
+        .collect();
+    let mut g2: Vec<G2Affine> = instances.iter().map(|i| i.proof.b).collect();
+    g1.extend([(-(vk.alpha_g1 * sum_r)).into_affine(), (-sum_l).into_affine(), (-sum_c).into_affine()]);
+    g2.extend([vk.beta_g2, vk.gamma_g2, vk.delta_g2]);
+
+    if !Bn254::multi_pairing(g1, g2).is_zero() {
+        return Err(ZkError::VerificationFailed);
+    }
+    Ok(())
+}
+
+/// Indices of the proofs in `instances` that don't verify, found by bisecting failed batches.
+pub fn invalid_shard_proofs(vk: &VerifyingKey<Bn254>, instances: &[ShardProofInstance]) -> Vec<usize> {
+    let mut invalid = Vec::new();
+    let mut pending = Vec::new();
+    pending.push(0..instances.len());
+    while let Some(range) = pending.pop() {
+        if range.is_empty() || verify_shard_proofs_batch(vk, &instances[range.clone()]).is_ok() {
+            continue;
+        }
+        if range.len() == 1 {
+            invalid.push(range.start);
+            continue;
+        }
+        let mid = range.start + range.len() / 2;
+        pending.push(mid..range.end);
+        pending.push(range.start..mid);
+    }
+    invalid.sort_unstable();
+    invalid
+}
+
+/// Fiat-Shamir coefficients for a batch: 128-bit values from SHA-256 over the key, every proof
+/// and every public input.
+fn batch_coefficients(vk: &VerifyingKey<Bn254>, instances: &[ShardProofInstance], inputs: &[Vec<Fr>]) -> Result<Vec<Fr>, ZkError> {
+    let mut transcript = Vec::new();
+    let serialization = |e: ark_serialize::SerializationError| ZkError::Serialization(format!("{e}"));
+    vk.serialize_compressed(&mut transcript).map_err(serialization)?;
+    for (instance, x) in instances.iter().zip(inputs) {
+        instance.proof.serialize_compressed(&mut transcript).map_err(serialization)?;
+        x.serialize_compressed(&mut transcript).map_err(serialization)?;
+    }
+    let seed = Sha256::new().chain_update(b"phl-groth16-batch-v1").chain_update(&transcript).finalize();
+
+    Ok((0..instances.len() as u64)
+        .map(|j| {
+            let digest = Sha256::new().chain_update(seed).chain_update(j.to_le_bytes()).finalize();
+            let mut bytes = [0u8; 16];
+            bytes.copy_from_slice(&digest[..16]);
+            Fr::from(u128::from_le_bytes(bytes))
+        })
+        .collect())
+}
+
+pub fn deserialize_vk(bytes: &[u8]) -> Result<VerifyingKey<Bn254>, ZkError> {
+    deserialize_vk_on::<Bn254>(bytes)
+}
+
+pub fn deserialize_proof(bytes: &[u8]) -> Result<Proof<Bn254>, ZkError> {
+    deserialize_proof_on::<Bn254>(bytes)
+}
+
+/// `deserialize_vk` for keys on any curve.
+pub fn deserialize_vk_on<E: Pairing>(bytes: &[u8]) -> Result<VerifyingKey<E>, ZkError> {
+    VerifyingKey::<E>::deserialize_compressed(bytes)
+        .map_err(|e| ZkError::Serialization(format!("{e}")))
+}
+
+/// `deserialize_proof` for proofs on any curve.
+pub fn deserialize_proof_on<E: Pairing>(bytes: &[u8]) -> Result<Proof<E>, ZkError> {
+    Proof::<E>::deserialize_compressed(bytes)
+        .map_err(|e| ZkError::Serialization(format!("{e}")))
+}
@@ -0,0 +1,51 @@
+//! JavaScript bindings (feature `wasm-bindgen`) for verifying shard proofs in the browser, so a
+//! client can check the proofs of `GET /api/v1/datasets/:id/shards?include_proof=true` itself
+//! rather than trusting each shard's `verified` flag.
+//!
+//! ```text
+//! wasm-pack build zk-proofs-verifier --target web --features wasm-bindgen
+//! ```
+//!
+//! ```js
+//! const vk = deserializeVk(vkB64)                 // from GET /api/v1/zk/vk?dataset_id=…
+//! for (const shard of page.shards) {
+//!   const ok = verifyShardProof(vk, deserializeProof(shard.proof_b64), JSON.stringify(shard))
+//! }
+//! ```
+//!
+//! Keys and proofs are BN254 only. Malformed input throws; a well-formed proof that doesn't verify
+//! gives `false`.
+
+use crate::verification::{self, EncodedShard};
+use ark_bn254::Bn254;
+use ark_groth16::{Proof, VerifyingKey};
+use wasm_bindgen::prelude::*;
+
+/// A decoded Groth16 verifying key.
+#[wasm_bindgen]
+pub struct ShardVerifyingKey(VerifyingKey<Bn254>);
+
+/// A decoded Groth16 shard proof.
+#[wasm_bindgen]
+pub struct ShardProof(Proof<Bn254>);
+
+/// Decode a base64 verifying key (`vk_b64`).
+#[wasm_bindgen(js_name = deserializeVk)]
+pub fn deserialize_vk(vk_b64: &str) -> Result<ShardVerifyingKey, JsError> {
+    verification::decode_vk_b64(vk_b64).map(ShardVerifyingKey).map_err(JsError::from)
+}
+
+/// Decode a base64 shard proof (`proof_b64`).
+#[wasm_bindgen(js_name = deserializeProof)]
+pub fn deserialize_proof(proof_b64: &str) -> Result<ShardProof, JsError> {
+    verification::decode_proof_b64(proof_b64).map(ShardProof).map_err(JsError::from)
+}
+
+/// Whether `proof` proves the commitment and aggregates of `shard_json`, one entry of a shards
+/// listing as JSON.
+#[wasm_bindgen(js_name = verifyShardProof)]
+pub fn verify_shard_proof(vk: &ShardVerifyingKey, proof: &ShardProof, shard_json: &str) -> Result<bool, JsError> {
+    let shard: EncodedShard = serde_json::from_str(shard_json).map_err(|e| JsError::new(&format!("invalid shard: {e}")))?;
+    let inputs = verification::decode_public_inputs::<Bn254>(&shard)?;
+    Ok(verification::verify_decoded(&vk.0, &proof.0, &inputs))
+}
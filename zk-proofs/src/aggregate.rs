@@ -0,0 +1,320 @@
+//! Dataset aggregate proofs: one Groth16 proof covering every shard of a dataset.
+//!
+//! Recursively verifying the shard proofs would need a pairing-friendly cycle (or a BN254 pairing
+//! gadget, millions of constraints per proof), so the aggregate is a second circuit over the
+//! shards' *public inputs* instead. For `k` shards whose public-input vectors `x_j` (commitment
+//! first, then the aggregates, then the salt commitment, glucose bounds and SHA-256 commitment)
+//! are private witnesses, it proves:
+//! 1) The public dataset commitment equals the Poseidon chain over `x_j[0]` (absorb each shard
+//!    commitment in shard order, squeeze one element), i.e. the backend's `poseidon` chain.
+//! 2) A public `shard_inputs_root` is the Merkle root over the leaves `Poseidon(x_j)` (2-to-1
+//!    Poseidon nodes, leaves padded with zero to a power of two).
+//! 3) The public totals are the element-wise sums of the shards' aggregates (`x_j[1..=T]`).
+//!
+//! The shard proofs themselves are not re-verified in-circuit. The root binds the aggregate to
+//! exactly the public inputs those proofs verify against: the prover batch-verifies every shard
+//! proof before aggregating, and a verifier who doesn't trust it checks the shard proofs (all of
+//! them in one batch, or a random sample, each shard's inputs checked against the root with
+//! `verify_shard_inputs_path`).
+//!
+//! Cost is about 24 Poseidon permutations per glucose shard (~6k constraints) and the witnesses
+//! grow with the number of shards, so keys are set up per `AggregateShape`.
+
+use crate::constants::poseidon_config;
+use crate::groth16::ZkError;
+use ark_bn254::{Bn254, Fr};
+use ark_crypto_primitives::sponge::constraints::CryptographicSpongeVar;
+use ark_crypto_primitives::sponge::poseidon::constraints::PoseidonSpongeVar;
+use ark_crypto_primitives::sponge::poseidon::PoseidonSponge;
+use ark_crypto_primitives::sponge::CryptographicSponge;
+use ark_ff::Zero;
+use ark_groth16::{Groth16, Proof, ProvingKey, VerifyingKey};
+use ark_r1cs_std::alloc::AllocVar;
+use ark_r1cs_std::eq::EqGadget;
+use ark_r1cs_std::fields::fp::FpVar;
+use ark_relations::r1cs::{ConstraintSynthesizer, ConstraintSystemRef, SynthesisError};
+use rand::RngCore;
+use zk_proofs_verifier::constants::SHA256_COMMITMENT_INPUTS;
+use zk_proofs_verifier::types::{DatasetAggregate, ShardStats};
+use zk_proofs_verifier::verify::{aggregate_public_inputs_to_field_elems, shard_public_inputs_to_field_elems};
+
+pub use zk_proofs_verifier::verify::verify_aggregate_proof;
+
+/// Size parameters of the aggregate circuit; each shape needs its own keys.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct AggregateShape {
+    pub num_shards: usize,
+    /// Length of every shard's public-input vector.
+    pub input_len: usize,
+    /// Leading aggregates (after the commitment) that are summed into the totals.
+    pub num_summed: usize,
+}
+
+impl AggregateShape {
+    /// The shape for `num_shards` shards laid out like `stats`.
+    pub fn of(num_shards: usize, stats: &ShardStats) -> Self {
+        let input_len = shard_public_inputs_to_field_elems(Fr::zero(), stats).len();
+        let salt = usize::from(stats.salt_commitment.is_some());
+        let glucose_bounds = usize::from(stats.glucose_bounds.is_some());
+        let sha256 = if stats.sha256_commitment.is_some() { SHA256_COMMITMENT_INPUTS } else { 0 };
+        Self {
+            num_shards,
+            input_len,
+            num_summed: input_len - 1 - salt - glucose_bounds - sha256,
+        }
+    }
+}
+
+/// Circuit proving a dataset commitment, shard inputs root and totals over private shard inputs.
+#[derive(Clone, Debug)]
+pub struct DatasetAggregateCircuit {
+    pub shape: AggregateShape,
+
+    /// Private: every shard's public-input vector, in shard order.
+    pub shard_inputs: Vec<Vec<Fr>>,
+
+    /// Public outputs, in `aggregate_public_inputs_to_field_elems` order.
+    pub public_dataset_commitment: Fr,
+    pub public_shard_inputs_root: Fr,
+    pub public_totals: Vec<Fr>,
+}
+
+impl ConstraintSynthesizer<Fr> for DatasetAggregateCircuit {
+    fn generate_constraints(self, cs: ConstraintSystemRef<Fr>) -> Result<(), SynthesisError> {
+        let shape = self.shape;
+        if self.shard_inputs.len() != shape.num_shards
+            || self.shard_inputs.iter().any(|x| x.len() != shape.input_len)
+            || self.public_totals.len() != shape.num_summed
+        {
+            return Err(SynthesisError::Unsatisfiable);
+        }
+        // Public inputs (ORDER MATTERS).
+        let dataset_commitment = FpVar::<Fr>::new_input(cs.clone(), || Ok(self.public_dataset_commitment))?;
+        let root = FpVar::<Fr>::new_input(cs.clone(), || Ok(self.public_shard_inputs_root))?;
+        let totals = self
+            .public_totals
+            .iter()
+            .map(|t| FpVar::<Fr>::new_input(cs.clone(), || Ok(*t)))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let shards = alloc_shard_inputs(cs.clone(), &self.shard_inputs)?;
+
+        // 1) and 2): the commitment chain and the shard inputs root.
+        enforce_chain_and_root(cs, &shards, &dataset_commitment, &root)?;
+
+        // 3) Totals (linear, so one constraint each).
+        for (t, total) in totals.iter().enumerate() {
+            let sum: FpVar<Fr> = shards.iter().map(|x| x[1 + t].clone()).sum();
+            sum.enforce_equal(total)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Allocate every shard's public-input vector as private witnesses.
+pub(crate) fn alloc_shard_inputs(cs: ConstraintSystemRef<Fr>, shard_inputs: &[Vec<Fr>]) -> Result<Vec<Vec<FpVar<Fr>>>, SynthesisError> {
+    shard_inputs
+        .iter()
+        .map(|x| x.iter().map(|v| FpVar::<Fr>::new_witness(cs.clone(), || Ok(*v))).collect::<Result<Vec<_>, _>>())
+        .collect()
+}
+
+/// Enforce that `dataset_commitment` is the Poseidon chain over the shards' commitments and `root`
+/// the Merkle root over their hashed inputs.
+pub(crate) fn enforce_chain_and_root(
+    cs: ConstraintSystemRef<Fr>,
+    shards: &[Vec<FpVar<Fr>>],
+    dataset_commitment: &FpVar<Fr>,
+    root: &FpVar<Fr>,
+) -> Result<(), SynthesisError> {
+    let poseidon_cfg = poseidon_config();
+
+    // Dataset commitment chain over the shard commitments.
+    let mut chain = PoseidonSpongeVar::<Fr>::new(cs.clone(), &poseidon_cfg);
+    for x in shards {
+        chain.absorb(&x[0])?;
+    }
+    chain.squeeze_field_elements(1)?[0].enforce_equal(dataset_commitment)?;
+
+    // Merkle root over the hashed shard inputs.
+    let mut level = Vec::with_capacity(shards.len().next_power_of_two());
+    for x in shards {
+        let mut sponge = PoseidonSpongeVar::<Fr>::new(cs.clone(), &poseidon_cfg);
+        sponge.absorb(x)?;
+        level.push(sponge.squeeze_field_elements(1)?[0].clone());
+    }
+    level.resize(shards.len().next_power_of_two(), FpVar::Constant(Fr::zero()));
+    while level.len() > 1 {
+        level = level
+            .chunks(2)
+            .map(|pair| {
+                let mut sponge = PoseidonSpongeVar::<Fr>::new(cs.clone(), &poseidon_cfg);
+                sponge.absorb(&pair.to_vec())?;
+                Ok(sponge.squeeze_field_elements(1)?[0].clone())
+            })
+            .collect::<Result<Vec<_>, SynthesisError>>()?;
+    }
+    level[0].enforce_equal(root)
+}
+
+/// Public-input vectors of `shards` (commitment and stats of each), their leaves' Merkle root and
+/// the Poseidon chain over their commitments.
+pub(crate) fn shard_inputs_and_statement(shards: &[(Fr, ShardStats)]) -> (Vec<Vec<Fr>>, Fr, Fr) {
+    let shard_inputs: Vec<Vec<Fr>> = shards
+        .iter()
+        .map(|(commitment, stats)| shard_public_inputs_to_field_elems(*commitment, stats))
+        .collect();
+    let leaves: Vec<Fr> = shard_inputs.iter().map(|x| shard_inputs_leaf(x)).collect();
+
+    let mut chain = PoseidonSponge::<Fr>::new(&poseidon_config());
+    for (commitment, _) in shards {
+        chain.absorb(commitment);
+    }
+    let dataset_commitment = chain.squeeze_field_elements(1)[0];
+    (shard_inputs, dataset_commitment, shard_inputs_root(&leaves))
+}
+
+/// Leaf of the shard inputs tree: Poseidon over a shard's public-input vector.
+pub fn shard_inputs_leaf(inputs: &[Fr]) -> Fr {
+    let mut sponge = PoseidonSponge::<Fr>::new(&poseidon_config());
+    sponge.absorb(&inputs.to_vec());
+    sponge.squeeze_field_elements(1)[0]
+}
+
+fn hash_pair(left: Fr, right: Fr) -> Fr {
+    let mut sponge = PoseidonSponge::<Fr>::new(&poseidon_config());
+    sponge.absorb(&vec![left, right]);
+    sponge.squeeze_field_elements(1)[0]
+}
+
+/// Every level of the tree over `leaves` (padded with zero to a power of two), leaves first.
+fn tree_levels(leaves: &[Fr]) -> Vec<Vec<Fr>> {
+    let mut level = leaves.to_vec();
+    level.resize(leaves.len().next_power_of_two(), Fr::zero());
+    let mut levels = vec![level];
+    while levels[levels.len() - 1].len() > 1 {
+        let next = levels[levels.len() - 1].chunks(2).map(|pair| hash_pair(pair[0], pair[1])).collect();
+        levels.push(next);
+    }
+    levels
+}
+
+/// Merkle root over the shard leaves, in shard order.
+pub fn shard_inputs_root(leaves: &[Fr]) -> Fr {
+    tree_levels(leaves).pop().map_or(Fr::zero(), |root| root[0])
+}
+
+/// Siblings from leaf `index` up to the root, or `None` if there is no such leaf.
+pub fn shard_inputs_path(leaves: &[Fr], index: usize) -> Option<Vec<Fr>> {
+    if index >= leaves.len() {
+        return None;
+    }
+    let levels = tree_levels(leaves);
+    Some(levels[..levels.len() - 1].iter().enumerate().map(|(depth, level)| level[(index >> depth) ^ 1]).collect())
+}
+
+/// Check that `leaf` sits at `index` under `root`.
+pub fn verify_shard_inputs_path(leaf: Fr, index: usize, path: &[Fr], root: Fr) -> bool {
+    let node = path.iter().enumerate().fold(leaf, |node, (depth, sibling)| {
+        if (index >> depth) & 1 == 0 {
+            hash_pair(node, *sibling)
+        } else {
+            hash_pair(*sibling, node)
+        }
+    });
+    index >> path.len() == 0 && node == root
+}
+
+/// Element-wise sums of shard aggregates, laid out like the first shard's. Every shard must prove
+/// the same outputs.
+pub fn sum_shard_stats(shards: &[ShardStats]) -> Result<ShardStats, ZkError> {
+    let Some(first) = shards.first() else {
+        return Err(ZkError::InvalidAggregate("no shards".to_string()));
+    };
+    let shape = AggregateShape::of(1, first);
+    if shards.iter().any(|stats| AggregateShape::of(1, stats) != shape) {
+        return Err(ZkError::InvalidAggregate("shards prove different outputs".to_string()));
+    }
+
+    let add = |acc: &mut [u64], values: &[u64]| acc.iter_mut().zip(values).for_each(|(a, v)| *a += v);
+    let mut out = ShardStats {
+        salt_commitment: None,
+        sha256_commitment: None,
+        glucose_bounds: None,
+        ..first.clone()
+    };
+    for stats in &shards[1..] {
+        add(&mut out.sum_glucose_by_bucket, &stats.sum_glucose_by_bucket);
+        add(&mut out.count_by_bucket, &stats.count_by_bucket);
+        for (acc, sums) in out.extra_sums_by_bucket.iter_mut().zip(&stats.extra_sums_by_bucket) {
+            add(acc, sums);
+        }
+        if let (Some(acc), Some(sums)) = (out.sum_glucose_sq_by_bucket.as_mut(), &stats.sum_glucose_sq_by_bucket) {
+            add(acc, sums);
+        }
+        if let (Some(acc), Some(histogram)) = (out.glucose_histogram_by_bucket.as_mut(), &stats.glucose_histogram_by_bucket) {
+            for (acc, counts) in acc.iter_mut().zip(histogram) {
+                add(acc, counts);
+            }
+        }
+    }
+    Ok(out)
+}
+
+/// Generate a Groth16 keypair for the aggregate circuit of `shape`.
+pub fn setup_aggregate_keys(
+    rng: &mut impl RngCore,
+    shape: AggregateShape,
+) -> Result<(ProvingKey<Bn254>, VerifyingKey<Bn254>), ZkError> {
+    // Constraints only depend on the shape, so zero inputs do (the statement needn't hold).
+    let circuit = DatasetAggregateCircuit {
+        shape,
+        shard_inputs: vec![vec![Fr::zero(); shape.input_len]; shape.num_shards],
+        public_dataset_commitment: Fr::zero(),
+        public_shard_inputs_root: Fr::zero(),
+        public_totals: vec![Fr::zero(); shape.num_summed],
+    };
+
+    let pk = Groth16::<Bn254>::generate_random_parameters_with_reduction(circuit, rng)
+        .map_err(|e| ZkError::Ark(format!("{e}")))?;
+
+    let vk = pk.vk.clone();
+    Ok((pk, vk))
+}
+
+/// Prove the aggregate of `shards` (commitment and stats of each, in shard order) with keys for
+/// their `AggregateShape`; returns the proof and its public statement.
+pub fn prove_aggregate(
+    rng: &mut impl RngCore,
+    pk: &ProvingKey<Bn254>,
+    shards: &[(Fr, ShardStats)],
+) -> Result<(Proof<Bn254>, DatasetAggregate), ZkError> {
+    let stats: Vec<ShardStats> = shards.iter().map(|(_, s)| s.clone()).collect();
+    let totals = sum_shard_stats(&stats)?;
+    let shape = AggregateShape::of(shards.len(), &stats[0]);
+    if pk.vk.gamma_abc_g1.len() != 1 + 2 + shape.num_summed {
+        return Err(ZkError::InvalidAggregate("proving key is for a different shard layout".to_string()));
+    }
+
+    let (shard_inputs, dataset_commitment, shard_inputs_root) = shard_inputs_and_statement(shards);
+    let aggregate = DatasetAggregate {
+        dataset_commitment,
+        shard_inputs_root,
+        totals,
+    };
+    let public_totals = aggregate_public_inputs_to_field_elems(&aggregate).split_off(2);
+
+    let circuit = DatasetAggregateCircuit {
+        shape,
+        shard_inputs,
+        public_dataset_commitment: aggregate.dataset_commitment,
+        public_shard_inputs_root: aggregate.shard_inputs_root,
+        public_totals,
+    };
+
+    let proof = Groth16::<Bn254>::create_random_proof_with_reduction(circuit, pk, rng)
+        .map_err(|e| ZkError::Ark(format!("{e}")))?;
+
+    Ok((proof, aggregate))
+}
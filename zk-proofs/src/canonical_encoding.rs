@@ -0,0 +1,219 @@
+//! Canonical encoding of a `Record`, shared by the circuit and the host-side commitment code.
+//!
+//! A record of a `FieldSet` is encoded as:
+//! - field elements (Poseidon commitment): its age, then each measurement of the set in
+//!   `FieldSet::measurements` order, each as the integer's field element; salted revisions (v4 on)
+//!   append the record's salt `master_salt + i` for record index `i`. Nothing is padded: a record
+//!   of a set of `m` measurements is `1 + m` elements (`2 + m` salted).
+//! - bytes (SHA-256 commitment): its age as 1 byte, then each measurement in the same order as 2
+//!   bytes big-endian. A shard's byte encoding is the master salt's 32-byte compressed
+//!   little-endian encoding followed by its records'.
+//!
+//! Ages are 8 bits and measurements 16 bits wide; the circuit range-checks them to those widths,
+//! so every encoding decodes to exactly one record. Measurements outside the field set are not
+//! encoded and decode as 0.
+//!
+//! `GOLDEN_VECTORS` pins the encoding; `check_golden_vectors` recomputes them (the backend's ZK
+//! self-test runs it), so a change to either side that alters the encoding is caught.
+
+use crate::types::{FieldSet, Measurement, Record};
+use ark_ff::{BigInteger, PrimeField};
+use ark_r1cs_std::boolean::Boolean;
+use ark_r1cs_std::fields::fp::FpVar;
+use ark_r1cs_std::prelude::UInt8;
+
+/// Width of an encoded age.
+pub const AGE_BITS: usize = 8;
+
+/// Width of an encoded measurement.
+pub const MEASUREMENT_BITS: usize = 16;
+
+/// Bytes one record of `field_set` encodes to.
+pub fn record_len_bytes(field_set: FieldSet) -> usize {
+    AGE_BITS / 8 + field_set.measurements().len() * (MEASUREMENT_BITS / 8)
+}
+
+/// The record's unsalted field elements: age, then its measurements in set order.
+pub fn record_field_elems<F: PrimeField>(record: &Record, field_set: FieldSet) -> Vec<F> {
+    let mut elems = Vec::with_capacity(1 + field_set.measurements().len());
+    elems.push(F::from(record.age as u64));
+    elems.extend(field_set.measurements().iter().map(|m| F::from(record.value(*m) as u64)));
+    elems
+}
+
+/// Salt of record `index` of a shard with `master_salt`.
+pub fn record_salt<F: PrimeField>(master_salt: F, index: usize) -> F {
+    master_salt + F::from(index as u64)
+}
+
+/// What the commitment absorbs for record `index`: its field elements, then its salt if salted.
+pub fn absorbed_elems<F: PrimeField>(record: &Record, field_set: FieldSet, index: usize, master_salt: Option<F>) -> Vec<F> {
+    let mut elems = record_field_elems(record, field_set);
+    if let Some(master_salt) = master_salt {
+        elems.push(record_salt(master_salt, index));
+    }
+    elems
+}
+
+/// The record's bytes: age, then its measurements in set order, big-endian.
+pub fn record_bytes(record: &Record, field_set: FieldSet) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(record_len_bytes(field_set));
+    bytes.push(record.age);
+    for m in field_set.measurements() {
+        bytes.extend(record.value(*m).to_be_bytes());
+    }
+    bytes
+}
+
+/// The record `record_bytes` encoded; `None` if `bytes` has the wrong length.
+pub fn decode_record_bytes(bytes: &[u8], field_set: FieldSet) -> Option<Record> {
+    if bytes.len() != record_len_bytes(field_set) {
+        return None;
+    }
+    let mut record = Record {
+        age: bytes[0],
+        ..Record::default()
+    };
+    for (m, value) in field_set.measurements().iter().zip(bytes[1..].chunks(2)) {
+        set_value(&mut record, *m, u16::from_be_bytes([value[0], value[1]]));
+    }
+    Some(record)
+}
+
+/// The record `record_field_elems` encoded; `None` if there are the wrong number of elements or
+/// one exceeds its width.
+pub fn decode_field_elems<F: PrimeField>(elems: &[F], field_set: FieldSet) -> Option<Record> {
+    if elems.len() != 1 + field_set.measurements().len() {
+        return None;
+    }
+    let small = |e: &F, bits: usize| -> Option<u64> {
+        let bigint = e.into_bigint();
+        (bigint.num_bits() as usize <= bits).then(|| bigint.as_ref()[0])
+    };
+    let mut record = Record {
+        age: small(&elems[0], AGE_BITS)? as u8,
+        ..Record::default()
+    };
+    for (m, e) in field_set.measurements().iter().zip(&elems[1..]) {
+        set_value(&mut record, *m, small(e, MEASUREMENT_BITS)? as u16);
+    }
+    Some(record)
+}
+
+fn set_value(record: &mut Record, measurement: Measurement, value: u16) {
+    match measurement {
+        Measurement::BloodGlucose => record.blood_glucose_mg_dl = value,
+        Measurement::SystolicBp => record.systolic_bp_mm_hg = value,
+        Measurement::HeartRate => record.heart_rate_bpm = value,
+        Measurement::Bmi => record.bmi_x10 = value,
+    }
+}
+
+/// In-circuit `absorbed_elems`, over the record's allocated age and measurements.
+pub fn absorbed_vars<F: PrimeField>(age: &FpVar<F>, values: &[FpVar<F>], index: usize, master_salt: Option<&FpVar<F>>) -> Vec<FpVar<F>> {
+    let mut absorbed = Vec::with_capacity(2 + values.len());
+    absorbed.push(age.clone());
+    absorbed.extend(values.iter().cloned());
+    if let Some(master_salt) = master_salt {
+        // Linear, so deriving the salts costs no constraints.
+        absorbed.push(master_salt + F::from(index as u64));
+    }
+    absorbed
+}
+
+/// In-circuit `record_bytes`, regrouped from the range checks' little-endian bits of the age
+/// (`AGE_BITS`) and of each measurement (`MEASUREMENT_BITS`), so they cost nothing extra.
+pub fn record_byte_vars<F: PrimeField>(age_bits: &[Boolean<F>], value_bits: &[Vec<Boolean<F>>]) -> Vec<UInt8<F>> {
+    let mut bytes = vec![UInt8::from_bits_le(&age_bits[..AGE_BITS])];
+    for bits in value_bits {
+        bytes.push(UInt8::from_bits_le(&bits[8..MEASUREMENT_BITS]));
+        bytes.push(UInt8::from_bits_le(&bits[..8]));
+    }
+    bytes
+}
+
+/// A record with its expected encodings.
+pub struct GoldenVector {
+    pub record: Record,
+    pub field_set: FieldSet,
+    /// Master salt and record index, for the salted field elements.
+    pub salt: Option<(u64, usize)>,
+    /// `absorbed_elems`, as integers.
+    pub field_elems: &'static [u64],
+    pub bytes_hex: &'static str,
+}
+
+const fn record(age: u8, glucose: u16, systolic_bp: u16, heart_rate: u16, bmi_x10: u16) -> Record {
+    Record {
+        age,
+        blood_glucose_mg_dl: glucose,
+        systolic_bp_mm_hg: systolic_bp,
+        heart_rate_bpm: heart_rate,
+        bmi_x10,
+    }
+}
+
+pub const GOLDEN_VECTORS: [GoldenVector; 5] = [
+    // Measurements outside the field set are not encoded.
+    GoldenVector {
+        record: record(42, 105, 120, 72, 245),
+        field_set: FieldSet::Glucose,
+        salt: None,
+        field_elems: &[42, 105],
+        bytes_hex: "2a0069",
+    },
+    GoldenVector {
+        record: record(42, 105, 120, 72, 245),
+        field_set: FieldSet::Vitals,
+        salt: None,
+        field_elems: &[42, 105, 120, 72, 245],
+        bytes_hex: "2a00690078004800f5",
+    },
+    // The salt is `master_salt + index` and is not part of the byte encoding.
+    GoldenVector {
+        record: record(42, 105, 120, 72, 245),
+        field_set: FieldSet::Vitals,
+        salt: Some((7, 3)),
+        field_elems: &[42, 105, 120, 72, 245, 10],
+        bytes_hex: "2a00690078004800f5",
+    },
+    // Extremes of each width, big-endian.
+    GoldenVector {
+        record: record(0, 65535, 0, 0, 0),
+        field_set: FieldSet::Glucose,
+        salt: None,
+        field_elems: &[0, 65535],
+        bytes_hex: "00ffff",
+    },
+    GoldenVector {
+        record: record(255, 300, 256, 1, 65535),
+        field_set: FieldSet::Vitals,
+        salt: Some((0, 0)),
+        field_elems: &[255, 300, 256, 1, 65535, 0],
+        bytes_hex: "ff012c01000001ffff",
+    },
+];
+
+/// Recompute every golden vector's encodings (and their decoding) over `F`.
+pub fn check_golden_vectors<F: PrimeField>() -> Result<(), String> {
+    for (i, v) in GOLDEN_VECTORS.iter().enumerate() {
+        let elems = absorbed_elems::<F>(&v.record, v.field_set, v.salt.map_or(0, |(_, index)| index), v.salt.map(|(salt, _)| F::from(salt)));
+        let expected: Vec<F> = v.field_elems.iter().map(|e| F::from(*e)).collect();
+        if elems != expected {
+            return Err(format!("golden vector {i}: field elements differ"));
+        }
+        let bytes = record_bytes(&v.record, v.field_set);
+        if hex::encode(&bytes) != v.bytes_hex {
+            return Err(format!("golden vector {i}: bytes {} differ from {}", hex::encode(&bytes), v.bytes_hex));
+        }
+
+        let unsalted = &elems[..1 + v.field_set.measurements().len()];
+        for decoded in [decode_record_bytes(&bytes, v.field_set), decode_field_elems(unsalted, v.field_set)] {
+            let decoded = decoded.ok_or(format!("golden vector {i}: does not decode"))?;
+            if record_bytes(&decoded, v.field_set) != bytes {
+                return Err(format!("golden vector {i}: does not round-trip"));
+            }
+        }
+    }
+    Ok(())
+}
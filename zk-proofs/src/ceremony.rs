@@ -0,0 +1,338 @@
+//! Groth16 phase-2 trusted setup ceremony for shard keys.
+//!
+//! `setup_keys` samples all of a key's toxic waste on one machine: whoever knows it can forge
+//! shard proofs. A phase-2 ceremony lets several parties re-randomize the circuit-specific part,
+//! `delta`, in turn: each contributor multiplies `delta` by a secret factor (dividing the `H` and
+//! `L` queries by it) and then forgets the factor. The protocol is the one of bellman's `phase2`:
+//!
+//! - `initial_params` sets up the key to start from (default age buckets) and wraps it in
+//!   `Phase2Params`; its `initial_hash` binds every later contribution to it.
+//! - `contribute` applies one contribution and appends its public record: the new `delta_g1`, and
+//!   a proof of knowledge of the factor, `(s, s·δ)` in G1 and `r·δ` in G2, where
+//!   `r = hash_to_g2(transcript)` and the transcript hashes the initial hash, the previous
+//!   contributions, the contributor's name and `(s, s·δ)`.
+//! - `verify` checks a params file against the initial one: everything but `delta` and the `H` and
+//!   `L` queries is unchanged, each contribution's proof of knowledge holds and chains from the
+//!   previous `delta`, `delta_g1` and `delta_g2` agree, and the queries were divided by exactly the
+//!   accumulated factor (checked on random linear combinations).
+//! - `finalize` verifies and returns the proving and verifying keys.
+//!
+//! This does not yet make the key trustless. Phase 1 (`tau`, `alpha`, `beta`, `gamma`) comes from
+//! `initial_params`' local `setup_keys_for`, and whoever knows `tau`, `alpha` and `beta` can
+//! recover `[1/delta]` from the `L` query and forge proofs however many honest contributors
+//! followed. The final key is therefore only as sound as the machine that ran `initial_params`
+//! discarding its randomness; the contributions only add security once phase 2 starts from a
+//! public Powers of Tau transcript, which is not supported yet.
+
+use crate::groth16::ZkError;
+use crate::registry::setup_keys_for;
+use crate::types::{AgeBuckets, FieldSet};
+use ark_bn254::{Bn254, Fq, Fq2, Fr, G1Affine, G1Projective, G2Affine};
+use ark_ec::pairing::Pairing;
+use ark_ec::{AffineRepr, CurveGroup, VariableBaseMSM};
+use ark_ff::{Field, PrimeField, UniformRand, Zero};
+use ark_groth16::{ProvingKey, VerifyingKey};
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize};
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+
+/// First bytes of every params file.
+const MAGIC: &[u8] = b"phl-phase2-v1\n";
+const TRANSCRIPT_DOMAIN: &[u8] = b"phl-phase2-transcript-v1";
+const HASH_TO_G2_DOMAIN: &[u8] = b"phl-phase2-hash-to-g2-v1";
+
+/// The shard circuit a ceremony sets up keys for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CeremonyCircuit {
+    pub shard_size: usize,
+    pub field_set: FieldSet,
+    pub sha256_commitment: bool,
+}
+
+/// One contribution's public record.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Contribution {
+    pub name: String,
+    /// `delta_g1` after this contribution.
+    pub delta_after: G1Affine,
+    pub s: G1Affine,
+    pub s_delta: G1Affine,
+    pub r_delta: G2Affine,
+}
+
+/// The state of a ceremony: the current key and every contribution made to it.
+#[derive(Debug, Clone)]
+pub struct Phase2Params {
+    pub circuit: CeremonyCircuit,
+    /// SHA-256 of the initial circuit and key (`initial_params`).
+    pub initial_hash: [u8; 32],
+    pub pk: ProvingKey<Bn254>,
+    pub contributions: Vec<Contribution>,
+}
+
+fn ceremony_error(message: impl Into<String>) -> ZkError {
+    ZkError::Ceremony(message.into())
+}
+
+fn write(value: &impl CanonicalSerialize, out: &mut Vec<u8>) -> Result<(), ZkError> {
+    value.serialize_uncompressed(out).map_err(|e| ZkError::Serialization(format!("{e}")))
+}
+
+fn read<T: CanonicalDeserialize>(bytes: &mut &[u8]) -> Result<T, ZkError> {
+    T::deserialize_uncompressed(bytes).map_err(|e| ZkError::Serialization(format!("{e}")))
+}
+
+fn circuit_and_pk_bytes(circuit: &CeremonyCircuit, pk: &ProvingKey<Bn254>, out: &mut Vec<u8>) -> Result<(), ZkError> {
+    write(&(circuit.shard_size as u64), out)?;
+    write(&circuit.field_set.name().to_string(), out)?;
+    write(&circuit.sha256_commitment, out)?;
+    write(pk, out)
+}
+
+fn initial_hash(circuit: &CeremonyCircuit, pk: &ProvingKey<Bn254>) -> Result<[u8; 32], ZkError> {
+    let mut bytes = Vec::new();
+    circuit_and_pk_bytes(circuit, pk, &mut bytes)?;
+    Ok(Sha256::digest(&bytes).into())
+}
+
+fn contribution_bytes(contribution: &Contribution, out: &mut Vec<u8>) -> Result<(), ZkError> {
+    write(&contribution.name, out)?;
+    write(&contribution.delta_after, out)?;
+    write(&contribution.s, out)?;
+    write(&contribution.s_delta, out)?;
+    write(&contribution.r_delta, out)
+}
+
+/// SHA-256 of a contribution's record, for contributors to publish and auditors to match.
+pub fn contribution_hash(contribution: &Contribution) -> Result<[u8; 32], ZkError> {
+    let mut bytes = Vec::new();
+    contribution_bytes(contribution, &mut bytes)?;
+    Ok(Sha256::digest(&bytes).into())
+}
+
+impl Phase2Params {
+    pub fn to_bytes(&self) -> Result<Vec<u8>, ZkError> {
+        let mut out = MAGIC.to_vec();
+        circuit_and_pk_bytes(&self.circuit, &self.pk, &mut out)?;
+        write(&self.initial_hash, &mut out)?;
+        write(&(self.contributions.len() as u64), &mut out)?;
+        for contribution in &self.contributions {
+            contribution_bytes(contribution, &mut out)?;
+        }
+        Ok(out)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, ZkError> {
+        let mut bytes = bytes
+            .strip_prefix(MAGIC)
+            .ok_or_else(|| ceremony_error("not a phase-2 params file"))?;
+        let shard_size = read::<u64>(&mut bytes)? as usize;
+        let field_set: String = read(&mut bytes)?;
+        let field_set = FieldSet::parse(&field_set).ok_or_else(|| ceremony_error(format!("unknown field set {field_set}")))?;
+        let circuit = CeremonyCircuit { shard_size, field_set, sha256_commitment: read(&mut bytes)? };
+        let pk = read(&mut bytes)?;
+        let initial_hash = read(&mut bytes)?;
+        let count: u64 = read(&mut bytes)?;
+        let mut contributions = Vec::new();
+        for _ in 0..count {
+            contributions.push(Contribution {
+                name: read(&mut bytes)?,
+                delta_after: read(&mut bytes)?,
+                s: read(&mut bytes)?,
+                s_delta: read(&mut bytes)?,
+                r_delta: read(&mut bytes)?,
+            });
+        }
+        if !bytes.is_empty() {
+            return Err(ceremony_error("trailing bytes after the last contribution"));
+        }
+        Ok(Phase2Params { circuit, initial_hash, pk, contributions })
+    }
+}
+
+/// Set up the key a ceremony starts from.
+pub fn initial_params(circuit: CeremonyCircuit, rng: &mut impl RngCore) -> Result<Phase2Params, ZkError> {
+    let (pk, _) = setup_keys_for(
+        circuit.shard_size,
+        circuit.field_set,
+        &AgeBuckets::default(),
+        circuit.sha256_commitment,
+        rng,
+    )?;
+    Ok(Phase2Params {
+        circuit,
+        initial_hash: initial_hash(&circuit, &pk)?,
+        pk,
+        contributions: Vec::new(),
+    })
+}
+
+/// The transcript a contribution's proof of knowledge is bound to.
+fn transcript(initial_hash: &[u8; 32], previous: &[Contribution], name: &str, s: &G1Affine, s_delta: &G1Affine) -> Result<[u8; 32], ZkError> {
+    let mut bytes = TRANSCRIPT_DOMAIN.to_vec();
+    bytes.extend_from_slice(initial_hash);
+    for contribution in previous {
+        bytes.extend_from_slice(&contribution_hash(contribution)?);
+    }
+    write(&name.to_string(), &mut bytes)?;
+    write(s, &mut bytes)?;
+    write(s_delta, &mut bytes)?;
+    Ok(Sha256::digest(&bytes).into())
+}
+
+/// Try-and-increment hash onto G2: the first `x` derived from `digest` and a counter that is on
+/// the twist, with the cofactor cleared. Nobody knows the discrete log of the result.
+fn hash_to_g2(digest: &[u8; 32]) -> G2Affine {
+    let coordinate = |counter: u32, part: u8| {
+        let mut h = Sha256::new();
+        h.update(HASH_TO_G2_DOMAIN);
+        h.update(digest);
+        h.update(counter.to_be_bytes());
+        h.update([part]);
+        Fq::from_be_bytes_mod_order(&h.finalize())
+    };
+    let mut counter = 0u32;
+    loop {
+        let x = Fq2::new(coordinate(counter, 0), coordinate(counter, 1));
+        if let Some(point) = G2Affine::get_point_from_x_unchecked(x, false) {
+            let point = point.clear_cofactor();
+            if !point.is_zero() {
+                return point;
+            }
+        }
+        counter += 1;
+    }
+}
+
+/// `e(a, d) == e(b, c)`: `b / a` in G1 is the same ratio as `d / c` in G2.
+fn same_ratio(g1: (G1Affine, G1Affine), g2: (G2Affine, G2Affine)) -> bool {
+    Bn254::pairing(g1.0, g2.1) == Bn254::pairing(g1.1, g2.0)
+}
+
+/// Random linear combinations with the same coefficients of two equally long vectors.
+fn merge_pairs(left: &[G1Affine], right: &[G1Affine], rng: &mut impl RngCore) -> (G1Affine, G1Affine) {
+    let coefficients: Vec<Fr> = (0..left.len()).map(|_| Fr::rand(rng)).collect();
+    (
+        G1Projective::msm_unchecked(left, &coefficients).into_affine(),
+        G1Projective::msm_unchecked(right, &coefficients).into_affine(),
+    )
+}
+
+fn scale(points: &[G1Affine], factor: Fr) -> Vec<G1Affine> {
+    let scaled: Vec<G1Projective> = points.iter().map(|p| *p * factor).collect();
+    G1Projective::normalize_batch(&scaled)
+}
+
+/// Add a contribution under `name` with fresh randomness from `rng`; returns its hash. The
+/// factor only lives in this call.
+pub fn contribute(params: &mut Phase2Params, name: &str, rng: &mut impl RngCore) -> Result<[u8; 32], ZkError> {
+    let delta = loop {
+        let delta = Fr::rand(rng);
+        if !delta.is_zero() {
+            break delta;
+        }
+    };
+    let inverse = delta.inverse().ok_or_else(|| ceremony_error("zero contribution"))?;
+
+    let s = G1Projective::rand(rng).into_affine();
+    let s_delta = (s * delta).into_affine();
+    let r = hash_to_g2(&transcript(&params.initial_hash, &params.contributions, name, &s, &s_delta)?);
+    let r_delta = (r * delta).into_affine();
+
+    let pk = &mut params.pk;
+    pk.delta_g1 = (pk.delta_g1 * delta).into_affine();
+    pk.vk.delta_g2 = (pk.vk.delta_g2 * delta).into_affine();
+    pk.h_query = scale(&pk.h_query, inverse);
+    pk.l_query = scale(&pk.l_query, inverse);
+
+    let contribution = Contribution {
+        name: name.to_string(),
+        delta_after: pk.delta_g1,
+        s,
+        s_delta,
+        r_delta,
+    };
+    let hash = contribution_hash(&contribution)?;
+    params.contributions.push(contribution);
+    Ok(hash)
+}
+
+/// Verify `params` against the ceremony's `initial` params; returns each contribution's hash.
+pub fn verify(initial: &Phase2Params, params: &Phase2Params, rng: &mut impl RngCore) -> Result<Vec<[u8; 32]>, ZkError> {
+    if !initial.contributions.is_empty() {
+        return Err(ceremony_error("the initial params already have contributions"));
+    }
+    if initial.circuit != params.circuit {
+        return Err(ceremony_error("params are for a different circuit than the initial params"));
+    }
+    if initial_hash(&initial.circuit, &initial.pk)? != initial.initial_hash || params.initial_hash != initial.initial_hash {
+        return Err(ceremony_error("params don't start from these initial params"));
+    }
+
+    let (before, after) = (&initial.pk, &params.pk);
+    let unchanged = before.vk.alpha_g1 == after.vk.alpha_g1
+        && before.vk.beta_g2 == after.vk.beta_g2
+        && before.vk.gamma_g2 == after.vk.gamma_g2
+        && before.vk.gamma_abc_g1 == after.vk.gamma_abc_g1
+        && before.beta_g1 == after.beta_g1
+        && before.a_query == after.a_query
+        && before.b_g1_query == after.b_g1_query
+        && before.b_g2_query == after.b_g2_query
+        && before.h_query.len() == after.h_query.len()
+        && before.l_query.len() == after.l_query.len();
+    if !unchanged {
+        return Err(ceremony_error("a contribution changed more than delta and the H and L queries"));
+    }
+
+    let mut delta = before.delta_g1;
+    let mut hashes = Vec::new();
+    for (i, contribution) in params.contributions.iter().enumerate() {
+        if contribution.s.is_zero() || contribution.s_delta.is_zero() {
+            return Err(ceremony_error(format!("contribution {i} ({}) is degenerate", contribution.name)));
+        }
+        let r = hash_to_g2(&transcript(
+            &params.initial_hash,
+            &params.contributions[..i],
+            &contribution.name,
+            &contribution.s,
+            &contribution.s_delta,
+        )?);
+        if !same_ratio((contribution.s, contribution.s_delta), (r, contribution.r_delta)) {
+            return Err(ceremony_error(format!("contribution {i} ({}): invalid proof of knowledge", contribution.name)));
+        }
+        if !same_ratio((delta, contribution.delta_after), (r, contribution.r_delta)) {
+            return Err(ceremony_error(format!("contribution {i} ({}) doesn't follow from the previous delta", contribution.name)));
+        }
+        delta = contribution.delta_after;
+        hashes.push(contribution_hash(contribution)?);
+    }
+
+    if after.delta_g1 != delta {
+        return Err(ceremony_error("delta_g1 doesn't match the last contribution"));
+    }
+    let (g1, g2) = (G1Affine::generator(), G2Affine::generator());
+    if !same_ratio((g1, after.delta_g1), (g2, after.vk.delta_g2)) {
+        return Err(ceremony_error("delta_g1 and delta_g2 disagree"));
+    }
+    let delta_g2 = (before.vk.delta_g2, after.vk.delta_g2);
+    if !same_ratio(merge_pairs(&after.h_query, &before.h_query, rng), delta_g2) {
+        return Err(ceremony_error("the H query wasn't divided by the contributions' delta"));
+    }
+    if !same_ratio(merge_pairs(&after.l_query, &before.l_query, rng), delta_g2) {
+        return Err(ceremony_error("the L query wasn't divided by the contributions' delta"));
+    }
+    Ok(hashes)
+}
+
+/// Verify `params` and return the ceremony's keys.
+pub fn finalize(
+    initial: &Phase2Params,
+    params: &Phase2Params,
+    rng: &mut impl RngCore,
+) -> Result<(ProvingKey<Bn254>, VerifyingKey<Bn254>), ZkError> {
+    if params.contributions.is_empty() {
+        return Err(ceremony_error("no contributions yet"));
+    }
+    verify(initial, params, rng)?;
+    Ok((params.pk.clone(), params.pk.vk.clone()))
+}
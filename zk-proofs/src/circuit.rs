@@ -1,31 +1,51 @@
 //! R1CS circuit for proving shard-level aggregate correctness.
 //!
 //! What this circuit proves (for one shard):
-//! 1) The prover knows N private records (age, glucose).
-//! 2) A public commitment `C` equals Poseidon(records) (binding the proof to committed data).
-//! 3) The public sums/counts for each age bucket equal the aggregates computed from those records.
+//! 1) The prover knows N private records (age plus the measurements of a `FieldSet`).
+//! 2) A public commitment `C` equals Poseidon(records) (binding the proof to committed data). From
+//!    v4 on every record is absorbed with a salt `s + i` (record index `i`) derived from a private
+//!    master salt `s`, and `Poseidon(s)` is public; without the salt, a small shard's commitment
+//!    could be brute-forced over the few plausible (age, measurement) tuples.
+//! 3) The public sums (per measurement) and counts for each age bucket equal the aggregates
+//!    computed from those records. The bucket bounds (`AgeBuckets`) are constants of the circuit.
+//! 4) Optionally, the public sums of squared blood glucose per bucket (for variance) do too.
+//! 5) Optionally, so do the public counts per age bucket and blood glucose range (`GLUCOSE_RANGES`).
+//! 6) Optionally (dual-commitment keys, salted only), a public SHA-256 digest equals SHA-256 of the
+//!    same records' canonical encoding (`canonical_encoding`, `groth16::sha256_commitment`), so systems that only handle
+//!    plain hashes can anchor it while verification stays on the cheap Poseidon commitment.
+//! 7) Optionally (from v5 on), every record's glucose lies within public bounds `(min, max)`, not
+//!    just in 16 bits, so a prover can't inflate the sums with absurd values.
 //!
-//! Privacy: the records are witnesses (never public). Only aggregates + commitment are public.
+//! In range-released mode (`public_ranges`), the sums and counts of 3) are witnesses as well, and
+//! the circuit only proves that each lies within public bounds `(lo, hi)`; 4), 5) and 7) are not
+//! proven.
+//!
+//! Privacy: the records are witnesses (never public). Only aggregates (or bounds on them) +
+//! commitment are public.
 
-use crate::constants::{poseidon_config, AGE_BUCKETS, NUM_BUCKETS};
-use crate::types::Record;
+use crate::canonical_encoding;
+use crate::constants::{poseidon_config_for, GLUCOSE_RANGES, NUM_GLUCOSE_RANGES};
+use crate::types::{AgeBuckets, FieldSet, Record, ShardRanges};
 use ark_bn254::Fr;
+use ark_crypto_primitives::crh::sha256::constraints::Sha256Gadget;
 use ark_crypto_primitives::sponge::poseidon::constraints::PoseidonSpongeVar;
 use ark_crypto_primitives::sponge::poseidon::PoseidonSponge;
 use ark_crypto_primitives::sponge::{constraints::CryptographicSpongeVar, CryptographicSponge};
+use ark_ff::{BigInteger, PrimeField};
 use ark_r1cs_std::boolean::Boolean;
 use ark_r1cs_std::eq::EqGadget;
 use ark_r1cs_std::fields::fp::FpVar;
+use ark_r1cs_std::prelude::*;
 use ark_relations::r1cs::{ConstraintSynthesizer, ConstraintSystemRef, SynthesisError};
 
 /// Convert little-endian boolean bits into an FpVar.
-fn bits_le_to_fp(bits_le: &[Boolean<Fr>]) -> Result<FpVar<Fr>, SynthesisError> {
-    let mut acc = FpVar::<Fr>::constant(Fr::from(0u64));
-    let mut coeff = FpVar::<Fr>::constant(Fr::from(1u64));
+fn bits_le_to_fp<F: PrimeField>(bits_le: &[Boolean<F>]) -> Result<FpVar<F>, SynthesisError> {
+    let mut acc = FpVar::<F>::constant(F::from(0u64));
+    let mut coeff = FpVar::<F>::constant(F::from(1u64));
 
     for b in bits_le {
         // b ? coeff : 0
-        let term = b.select(&coeff, &FpVar::<Fr>::constant(Fr::from(0u64)))?;
+        let term = b.select(&coeff, &FpVar::<F>::constant(F::from(0u64)))?;
         acc += term;
         coeff += coeff.clone();
     }
@@ -34,7 +54,7 @@ fn bits_le_to_fp(bits_le: &[Boolean<Fr>]) -> Result<FpVar<Fr>, SynthesisError> {
 }
 
 /// Enforce that `v` is a u8 (fits in 8 bits) and return its 8 little-endian bits.
-fn constrain_u8(v: &FpVar<Fr>) -> Result<Vec<Boolean<Fr>>, SynthesisError> {
+fn constrain_u8<F: PrimeField>(v: &FpVar<F>) -> Result<Vec<Boolean<F>>, SynthesisError> {
     let bits = v.to_bits_le()?;
     let bits8 = bits[..8].to_vec();
     let reconstructed = bits_le_to_fp(&bits8)?;
@@ -43,7 +63,7 @@ fn constrain_u8(v: &FpVar<Fr>) -> Result<Vec<Boolean<Fr>>, SynthesisError> {
 }
 
 /// Enforce that `v` is a u16 (fits in 16 bits) and return its 16 little-endian bits.
-fn constrain_u16(v: &FpVar<Fr>) -> Result<Vec<Boolean<Fr>>, SynthesisError> {
+fn constrain_u16<F: PrimeField>(v: &FpVar<F>) -> Result<Vec<Boolean<F>>, SynthesisError> {
     let bits = v.to_bits_le()?;
     let bits16 = bits[..16].to_vec();
     let reconstructed = bits_le_to_fp(&bits16)?;
@@ -51,80 +71,250 @@ fn constrain_u16(v: &FpVar<Fr>) -> Result<Vec<Boolean<Fr>>, SynthesisError> {
     Ok(bits16)
 }
 
-/// Boolean gadget: `a <= c` where `a` is an 8-bit unsigned value in little-endian bits.
-fn leq_const_u8(a_bits_le: &[Boolean<Fr>], c: u8) -> Result<Boolean<Fr>, SynthesisError> {
+/// Enforce that `v` fits in 16 bits, allocating only those bits (`constrain_u16` decomposes all of
+/// `v`, for callers that need the bits).
+fn enforce_fits_u16<F: PrimeField>(v: &FpVar<F>) -> Result<(), SynthesisError> {
+    let value = v.value().ok().map(|v| v.into_bigint());
+    let mut bits = Vec::with_capacity(16);
+    for i in 0..16 {
+        bits.push(Boolean::new_witness(v.cs(), || value.map(|v| v.get_bit(i)).ok_or(SynthesisError::AssignmentMissing))?);
+    }
+    bits_le_to_fp(&bits)?.enforce_equal(v)
+}
+
+/// Enforce that `v` is a u64 (fits in 64 bits).
+fn constrain_u64<F: PrimeField>(v: &FpVar<F>) -> Result<(), SynthesisError> {
+    let bits = v.to_bits_le()?;
+    bits_le_to_fp(&bits[..64])?.enforce_equal(v)
+}
+
+/// Enforce `lo <= v <= hi` for a `v` known to be far below the field modulus: both differences
+/// must fit in 64 bits, which a wrapped-around negative one can't.
+fn enforce_within<F: PrimeField>(v: &FpVar<F>, lo: &FpVar<F>, hi: &FpVar<F>) -> Result<(), SynthesisError> {
+    constrain_u64(&(v - lo))?;
+    constrain_u64(&(hi - v))
+}
+
+/// Boolean gadget: `a <= c` where `a` is an unsigned value in little-endian bits (8 for ages, 16
+/// for measurements).
+fn leq_const<F: PrimeField>(a_bits_le: &[Boolean<F>], c: u64) -> Result<Boolean<F>, SynthesisError> {
     // Lexicographic compare from MSB to LSB.
     let mut less = Boolean::constant(false);
     let mut equal = Boolean::constant(true);
 
-    for i in (0..8).rev() {
+    for i in (0..a_bits_le.len()).rev() {
         let a_i = a_bits_le[i].clone();
-        let c_i = ((c >> i) & 1u8) == 1u8;
+        let c_i = ((c >> i) & 1) == 1;
 
         // equal && (!a_i) && c_i
         if c_i {
-            let not_a = a_i.not();
-            let less_i = equal.and(&not_a)?;
-            less = less.or(&less_i)?;
+            let less_i = equal.clone() & !&a_i;
+            less |= less_i;
         }
 
         // equal = equal && (a_i == c_i)
-        let a_eq_ci = if c_i { a_i } else { a_i.not() };
-        equal = equal.and(&a_eq_ci)?;
+        let a_eq_ci = if c_i { a_i } else { !a_i };
+        equal &= a_eq_ci;
     }
 
-    less.or(&equal)
+    Ok(less | equal)
 }
 
-/// Boolean gadget: `a >= c` where `a` is u8.
-fn geq_const_u8(a_bits_le: &[Boolean<Fr>], c: u8) -> Result<Boolean<Fr>, SynthesisError> {
+/// Boolean gadget: `a >= c` where `a` is unsigned, in little-endian bits.
+fn geq_const<F: PrimeField>(a_bits_le: &[Boolean<F>], c: u64) -> Result<Boolean<F>, SynthesisError> {
     if c == 0 {
         return Ok(Boolean::constant(true));
     }
     // a >= c  <=>  !(a <= c-1)
-    let leq_prev = leq_const_u8(a_bits_le, c - 1)?;
-    Ok(leq_prev.not())
+    let leq_prev = leq_const(a_bits_le, c - 1)?;
+    Ok(!leq_prev)
 }
 
 /// Boolean gadget: `min <= a <= max` for u8 value.
-fn in_range_u8(a_bits_le: &[Boolean<Fr>], min: u8, max: u8) -> Result<Boolean<Fr>, SynthesisError> {
-    let ge = geq_const_u8(a_bits_le, min)?;
-    let le = leq_const_u8(a_bits_le, max)?;
-    ge.and(&le)
+fn in_range_u8<F: PrimeField>(a_bits_le: &[Boolean<F>], min: u8, max: u8) -> Result<Boolean<F>, SynthesisError> {
+    let ge = geq_const(a_bits_le, min as u64)?;
+    let le = leq_const(a_bits_le, max as u64)?;
+    Ok(ge & le)
 }
 
 /// Circuit proving shard commitment binding and bucketed aggregates.
 ///
-/// `N` is the number of records in the shard.
+/// `N` is the number of records in the shard; `field_set` selects the measurements that are
+/// committed to and summed, and `buckets` the age bucket bounds (the constraint system, and so the
+/// keys, differ per field set and bucket layout). `F` is the scalar field of the proving curve:
+/// BN254's unless a curve migration re-proves the shard (see `groth16::prove_shard_on`).
 #[derive(Clone, Debug)]
-pub struct HealthShardCircuit<const N: usize> {
+pub struct HealthShardCircuit<const N: usize, F: PrimeField = Fr> {
+    /// Measured fields; glucose-only reproduces the original circuit exactly.
+    pub field_set: FieldSet,
+
+    /// Age bucket bounds, one aggregate per bucket; the default layout reproduces the original
+    /// circuit exactly.
+    pub buckets: AgeBuckets,
+
     /// Private records.
     pub records: Vec<Record>,
 
     /// Public commitment to the shard's records.
-    pub public_shard_commitment: Fr,
+    pub public_shard_commitment: F,
+
+    /// Public aggregate outputs (private witnesses in range-released mode).
+    pub public_sum_glucose_by_bucket: Vec<u64>,
+    pub public_count_by_bucket: Vec<u64>,
+    /// Sums of `field_set.measurements()[1..]`, one vector per measurement.
+    pub public_extra_sums_by_bucket: Vec<Vec<u64>>,
+    /// Sums of squared blood glucose; `None` synthesizes the circuit that predates them (legacy
+    /// keys).
+    pub public_sum_glucose_sq_by_bucket: Option<Vec<u64>>,
+    /// Counts per age bucket and glucose range; `None` synthesizes a circuit without them (keys set
+    /// up before v3).
+    pub public_glucose_histogram_by_bucket: Option<Vec<[u64; NUM_GLUCOSE_RANGES]>>,
+    /// Private master salt the per-record salts are derived from; ignored without
+    /// `public_salt_commitment`.
+    pub master_salt: F,
+    /// Poseidon hash of `master_salt`; `None` synthesizes a circuit without salts (keys set up
+    /// before v4).
+    pub public_salt_commitment: Option<F>,
+    /// Inclusive `(min, max)` every record's glucose must lie within; `None` synthesizes a circuit
+    /// without them (keys set up before v5). Requires `public_salt_commitment`.
+    pub public_glucose_bounds: Option<(u16, u16)>,
+    /// SHA-256 of the canonical record encoding; `Some` synthesizes the dual-commitment circuit,
+    /// which has its own keys. Requires `public_salt_commitment`.
+    pub public_sha256_commitment: Option<[u8; 32]>,
+    /// Public bounds on the sums and counts; `Some` synthesizes the range-released circuit (salted,
+    /// without sums of squares or histograms), which has its own keys.
+    pub public_ranges: Option<ShardRanges>,
+}
 
-    /// Public aggregate outputs.
-    pub public_sum_glucose_by_bucket: [u64; NUM_BUCKETS],
-    pub public_count_by_bucket: [u64; NUM_BUCKETS],
+/// Whether `GLUCOSE_RANGES` are contiguous and cover all of `0..=u16::MAX`, which the histogram
+/// constraints rely on.
+fn glucose_ranges_partition_u16() -> bool {
+    GLUCOSE_RANGES[0].0 == 0
+        && GLUCOSE_RANGES[NUM_GLUCOSE_RANGES - 1].1 == u16::MAX
+        && GLUCOSE_RANGES.windows(2).all(|w| w[0].1 < w[1].0 && w[0].1 + 1 == w[1].0)
 }
 
-impl<const N: usize> ConstraintSynthesizer<Fr> for HealthShardCircuit<N> {
-    fn generate_constraints(self, cs: ConstraintSystemRef<Fr>) -> Result<(), SynthesisError> {
+impl<const N: usize, F: PrimeField> ConstraintSynthesizer<F> for HealthShardCircuit<N, F> {
+    fn generate_constraints(self, cs: ConstraintSystemRef<F>) -> Result<(), SynthesisError> {
         // --- Public inputs ---
         // These are what the verifier checks.
-        let public_commitment = FpVar::<Fr>::new_input(cs.clone(), || Ok(self.public_shard_commitment))?;
+        let public_commitment = FpVar::<F>::new_input(cs.clone(), || Ok(self.public_shard_commitment))?;
 
         // IMPORTANT: Public input ordering MUST match `groth16::shard_public_inputs_to_field_elems`.
-        // We use: commitment, sums[0..B), counts[0..B).
-        let mut public_sums = Vec::<FpVar<Fr>>::with_capacity(NUM_BUCKETS);
-        let mut public_counts = Vec::<FpVar<Fr>>::with_capacity(NUM_BUCKETS);
+        // We use: commitment, glucose sums[0..B), counts[0..B), then sums[0..B) for each further
+        // measurement of the field set, then (if proven) glucose sums of squares[0..B), then (if
+        // proven) glucose histogram counts[0..B)[0..R), then (if salted) the master salt commitment,
+        // then (if proven) the packed glucose bounds, then (if dual-commitment) the two SHA-256
+        // digest halves.
+        let measurements = self.field_set.measurements();
+        let num_buckets = self.buckets.num_buckets();
+        if self.public_extra_sums_by_bucket.len() != measurements.len() - 1 {
+            return Err(SynthesisError::Unsatisfiable);
+        }
+        // Every per-bucket output has one entry per bucket of the layout.
+        let mut per_bucket = [&self.public_sum_glucose_by_bucket, &self.public_count_by_bucket]
+            .into_iter()
+            .chain(&self.public_extra_sums_by_bucket)
+            .chain(&self.public_sum_glucose_sq_by_bucket);
+        let histogram_len = self.public_glucose_histogram_by_bucket.as_ref().map_or(num_buckets, Vec::len);
+        if per_bucket.any(|v| v.len() != num_buckets) || histogram_len != num_buckets {
+            return Err(SynthesisError::Unsatisfiable);
+        }
+
+        // In range-released mode the exact aggregates are witnesses, followed by (lo, hi) inputs
+        // per bucket in the same order (glucose sums, counts, further sums).
+        let ranged = self.public_ranges.is_some();
+        let aggregate = |value: u64| {
+            if ranged {
+                FpVar::<F>::new_witness(cs.clone(), || Ok(F::from(value)))
+            } else {
+                FpVar::<F>::new_input(cs.clone(), || Ok(F::from(value)))
+            }
+        };
 
-        for i in 0..NUM_BUCKETS {
-            public_sums.push(FpVar::<Fr>::new_input(cs.clone(), || Ok(Fr::from(self.public_sum_glucose_by_bucket[i])))?);
+        let mut public_sums = vec![Vec::<FpVar<F>>::with_capacity(num_buckets); measurements.len()];
+        let mut public_counts = Vec::<FpVar<F>>::with_capacity(num_buckets);
+
+        for sum in &self.public_sum_glucose_by_bucket {
+            public_sums[0].push(aggregate(*sum)?);
+        }
+        for count in &self.public_count_by_bucket {
+            public_counts.push(aggregate(*count)?);
+        }
+        for (f, sums) in self.public_extra_sums_by_bucket.iter().enumerate() {
+            for sum in sums {
+                public_sums[f + 1].push(aggregate(*sum)?);
+            }
         }
-        for i in 0..NUM_BUCKETS {
-            public_counts.push(FpVar::<Fr>::new_input(cs.clone(), || Ok(Fr::from(self.public_count_by_bucket[i])))?);
+        let mut public_bounds = Vec::<(FpVar<F>, FpVar<F>)>::new();
+        if let Some(ranges) = &self.public_ranges {
+            let mut range_buckets = [&ranges.sum_glucose_by_bucket, &ranges.count_by_bucket].into_iter().chain(&ranges.extra_sums_by_bucket);
+            if ranges.extra_sums_by_bucket.len() != measurements.len() - 1
+                || range_buckets.any(|b| b.len() != num_buckets)
+                || self.public_sum_glucose_sq_by_bucket.is_some()
+                || self.public_glucose_histogram_by_bucket.is_some()
+                || self.public_salt_commitment.is_none()
+            {
+                return Err(SynthesisError::Unsatisfiable);
+            }
+            let bounds = [&ranges.sum_glucose_by_bucket, &ranges.count_by_bucket].into_iter().chain(&ranges.extra_sums_by_bucket);
+            for (lo, hi) in bounds.flatten() {
+                let lo = FpVar::<F>::new_input(cs.clone(), || Ok(F::from(*lo)))?;
+                let hi = FpVar::<F>::new_input(cs.clone(), || Ok(F::from(*hi)))?;
+                public_bounds.push((lo, hi));
+            }
+        }
+        let mut public_sums_sq = Vec::<FpVar<F>>::new();
+        if let Some(sums_sq) = &self.public_sum_glucose_sq_by_bucket {
+            for sum_sq in sums_sq {
+                public_sums_sq.push(FpVar::<F>::new_input(cs.clone(), || Ok(F::from(*sum_sq)))?);
+            }
+        }
+        let prove_sum_sq = !public_sums_sq.is_empty();
+        let mut public_histogram = Vec::<Vec<FpVar<F>>>::new();
+        if let Some(histogram) = &self.public_glucose_histogram_by_bucket {
+            if !glucose_ranges_partition_u16() {
+                return Err(SynthesisError::Unsatisfiable);
+            }
+            for counts in histogram {
+                let mut row = Vec::with_capacity(NUM_GLUCOSE_RANGES);
+                for count in counts {
+                    row.push(FpVar::<F>::new_input(cs.clone(), || Ok(F::from(*count)))?);
+                }
+                public_histogram.push(row);
+            }
+        }
+        let prove_histogram = !public_histogram.is_empty();
+        let public_salt_commitment = match self.public_salt_commitment {
+            Some(salt_commitment) => Some(FpVar::<F>::new_input(cs.clone(), || Ok(salt_commitment))?),
+            None => None,
+        };
+        // The bounds are one input, `min + 2^16 · max`, unpacked into two 16-bit witnesses.
+        let glucose_bounds = match self.public_glucose_bounds {
+            Some((min, max)) => {
+                if ranged || public_salt_commitment.is_none() {
+                    return Err(SynthesisError::Unsatisfiable);
+                }
+                let packed = FpVar::<F>::new_input(cs.clone(), || {
+                    Ok(zk_proofs_verifier::verify::glucose_bounds_to_field_elem::<F>((min, max)))
+                })?;
+                let min = FpVar::<F>::new_witness(cs.clone(), || Ok(F::from(min as u64)))?;
+                let max = FpVar::<F>::new_witness(cs.clone(), || Ok(F::from(max as u64)))?;
+                enforce_fits_u16(&min)?;
+                enforce_fits_u16(&max)?;
+                (&min + &max * F::from(1u64 << 16)).enforce_equal(&packed)?;
+                Some((min, max))
+            }
+            None => None,
+        };
+        let mut public_sha256_halves = Vec::<FpVar<F>>::new();
+        if let Some(digest) = &self.public_sha256_commitment {
+            if ranged || public_salt_commitment.is_none() {
+                return Err(SynthesisError::Unsatisfiable);
+            }
+            for half in zk_proofs_verifier::verify::sha256_digest_to_field_elems::<F>(digest) {
+                public_sha256_halves.push(FpVar::<F>::new_input(cs.clone(), || Ok(half))?);
+            }
         }
 
         // --- Witness (private) records ---
@@ -132,45 +322,113 @@ impl<const N: usize> ConstraintSynthesizer<Fr> for HealthShardCircuit<N> {
             return Err(SynthesisError::Unsatisfiable);
         }
 
-        let poseidon_cfg = poseidon_config();
-        let mut sponge = PoseidonSpongeVar::<Fr>::new(cs.clone(), &poseidon_cfg);
-
-        // Running aggregates.
-        let mut sum_vars = vec![FpVar::<Fr>::constant(Fr::from(0u64)); NUM_BUCKETS];
-        let mut count_vars = vec![FpVar::<Fr>::constant(Fr::from(0u64)); NUM_BUCKETS];
-
-        for rec in self.records {
-            // Allocate age and glucose as field elements.
-            let age = FpVar::<Fr>::new_witness(cs.clone(), || Ok(Fr::from(rec.age as u64)))?;
-            let glucose = FpVar::<Fr>::new_witness(cs.clone(), || Ok(Fr::from(rec.blood_glucose_mg_dl as u64)))?;
+        let poseidon_cfg = poseidon_config_for::<F>();
+        let mut sponge = PoseidonSpongeVar::<F>::new(cs.clone(), &poseidon_cfg);
+
+        // Salt record `i` with `master_salt + i`: linear, so deriving the salts costs no
+        // constraints, and they are distinct without being stored per record.
+        let master_salt = match &public_salt_commitment {
+            Some(public_salt_commitment) => {
+                let master_salt = FpVar::<F>::new_witness(cs.clone(), || Ok(self.master_salt))?;
+                let mut salt_sponge = PoseidonSpongeVar::<F>::new(cs.clone(), &poseidon_cfg);
+                salt_sponge.absorb(&master_salt)?;
+                salt_sponge.squeeze_field_elements(1)?[0].enforce_equal(public_salt_commitment)?;
+                Some(master_salt)
+            }
+            None => None,
+        };
+
+        // The SHA-256 encoding starts with the master salt's canonical little-endian bytes.
+        let mut sha256 = match &master_salt {
+            Some(master_salt) if !public_sha256_halves.is_empty() => {
+                let mut sha256 = Sha256Gadget::<F>::default();
+                sha256.update(&master_salt.to_bytes_le()?)?;
+                Some(sha256)
+            }
+            _ => None,
+        };
+
+        // Running aggregates, per measurement.
+        let mut sum_vars = vec![vec![FpVar::<F>::constant(F::from(0u64)); num_buckets]; measurements.len()];
+        let mut count_vars = vec![FpVar::<F>::constant(F::from(0u64)); num_buckets];
+        let mut sum_sq_vars = vec![FpVar::<F>::constant(F::from(0u64)); num_buckets];
+        // Per bucket, the number of records with glucose at or above each range's lower bound
+        // (range 0 starts at 0, so its entry is left unused in favour of the bucket count).
+        let mut at_least_vars = vec![vec![FpVar::<F>::constant(F::from(0u64)); NUM_GLUCOSE_RANGES]; num_buckets];
+
+        for (i, rec) in self.records.into_iter().enumerate() {
+            // Allocate age and the measurements as their canonical field elements.
+            let elems = canonical_encoding::record_field_elems::<F>(&rec, self.field_set);
+            let age = FpVar::<F>::new_witness(cs.clone(), || Ok(elems[0]))?;
+            let mut values = Vec::with_capacity(measurements.len());
+            for elem in &elems[1..] {
+                values.push(FpVar::<F>::new_witness(cs.clone(), || Ok(*elem))?);
+            }
 
             // Range constrain to avoid ambiguous representations.
             let age_bits = constrain_u8(&age)?;
-            let _glucose_bits = constrain_u16(&glucose)?;
+            let mut value_bits = Vec::with_capacity(values.len());
+            for value in &values {
+                value_bits.push(constrain_u16(value)?);
+            }
 
-            // Commitment binding: absorb private fields.
-            sponge.absorb(&[age.clone(), glucose.clone()])?;
+            // Commitment binding: absorb the record's canonical field elements.
+            sponge.absorb(&canonical_encoding::absorbed_vars(&age, &values, i, master_salt.as_ref()))?;
+
+            // The same record in the canonical byte encoding, for SHA-256.
+            if let Some(sha256) = sha256.as_mut() {
+                sha256.update(&canonical_encoding::record_byte_vars(&age_bits, &value_bits))?;
+            }
+
+            // Glucose and both bounds are 16-bit, so a difference fits in 16 bits only if it didn't
+            // wrap around the field: min <= glucose <= max.
+            if let Some((min, max)) = &glucose_bounds {
+                enforce_fits_u16(&(&values[0] - min))?;
+                enforce_fits_u16(&(max - &values[0]))?;
+            }
+
+            // Glucose is range-constrained to 16 bits, so its square cannot wrap.
+            let glucose_sq = if prove_sum_sq { Some(&values[0] * &values[0]) } else { None };
+
+            // 1 if glucose reaches range r's lower bound, for r >= 1. As the ranges partition the
+            // u16 values, the record falls in range r iff it reaches r's bound but not r+1's.
+            let mut glucose_at_least = Vec::with_capacity(NUM_GLUCOSE_RANGES);
+            if prove_histogram {
+                for (min_glucose, _) in &GLUCOSE_RANGES[1..] {
+                    let at_least = geq_const(&value_bits[0], *min_glucose as u64)?;
+                    glucose_at_least.push(at_least.select(&FpVar::<F>::constant(F::from(1u64)), &FpVar::<F>::constant(F::from(0u64)))?);
+                }
+            }
 
             // Bucket membership and aggregates.
             //
             // IMPORTANT: Every bucket constraint is explicit and non-overlapping.
             // The record contributes to exactly one bucket.
             let mut in_any_bucket = Boolean::constant(false);
-            for (b, (min_age, max_age)) in AGE_BUCKETS.iter().enumerate() {
+            for (b, (min_age, max_age)) in self.buckets.bounds().iter().enumerate() {
                 let in_bucket = in_range_u8(&age_bits, *min_age, *max_age)?;
-                in_any_bucket = in_any_bucket.or(&in_bucket)?;
-
-                // sum_b += in_bucket ? glucose : 0
-                let add_glucose = in_bucket.select(&glucose, &FpVar::<Fr>::constant(Fr::from(0u64)))?;
-                sum_vars[b] += add_glucose;
+                in_any_bucket |= &in_bucket;
+
+                // sum_b += in_bucket ? value : 0, for every measurement
+                for (f, value) in values.iter().enumerate() {
+                    let add_value = in_bucket.select(value, &FpVar::<F>::constant(F::from(0u64)))?;
+                    sum_vars[f][b] += add_value;
+                }
+                if let Some(glucose_sq) = &glucose_sq {
+                    sum_sq_vars[b] += in_bucket.select(glucose_sq, &FpVar::<F>::constant(F::from(0u64)))?;
+                }
+                for (r, at_least) in glucose_at_least.iter().enumerate() {
+                    at_least_vars[b][r + 1] += in_bucket.select(at_least, &FpVar::<F>::constant(F::from(0u64)))?;
+                }
 
                 // count_b += in_bucket ? 1 : 0
-                let add_one = in_bucket.select(&FpVar::<Fr>::constant(Fr::from(1u64)), &FpVar::<Fr>::constant(Fr::from(0u64)))?;
+                let add_one = in_bucket.select(&FpVar::<F>::constant(F::from(1u64)), &FpVar::<F>::constant(F::from(0u64)))?;
                 count_vars[b] += add_one;
             }
 
             // Enforce that every age falls into some configured bucket.
-            // (Buckets cover [0, 120], and the synthetic generator only emits ages in that range.)
+            // (`AgeBuckets` always cover [0, 120], and the synthetic generator only emits ages in
+            // that range.)
             in_any_bucket.enforce_equal(&Boolean::constant(true))?;
         }
 
@@ -179,15 +437,46 @@ impl<const N: usize> ConstraintSynthesizer<Fr> for HealthShardCircuit<N> {
         let commitment = sponge.squeeze_field_elements(1)?[0].clone();
         commitment.enforce_equal(&public_commitment)?;
 
+        // Each public half is its 16 digest bytes read big-endian.
+        if let Some(sha256) = sha256 {
+            let digest = sha256.finalize()?;
+            for (bytes, public_half) in digest.0.chunks(16).zip(&public_sha256_halves) {
+                let mut bits_le = Vec::with_capacity(128);
+                for byte in bytes.iter().rev() {
+                    bits_le.extend(byte.to_bits_le()?);
+                }
+                bits_le_to_fp(&bits_le)?.enforce_equal(public_half)?;
+            }
+        }
+
         // Enforce public outputs match computed aggregates.
-        for i in 0..NUM_BUCKETS {
-            sum_vars[i].enforce_equal(&public_sums[i])?;
+        for i in 0..num_buckets {
+            for f in 0..measurements.len() {
+                sum_vars[f][i].enforce_equal(&public_sums[f][i])?;
+            }
             count_vars[i].enforce_equal(&public_counts[i])?;
         }
+        // Bounds are in input order: glucose sums, counts, then further sums, bucket by bucket.
+        if ranged {
+            let aggregates = public_sums[..1].iter().chain([&public_counts]).chain(&public_sums[1..]);
+            for (value, (lo, hi)) in aggregates.flatten().zip(&public_bounds) {
+                enforce_within(value, lo, hi)?;
+            }
+        }
+        for (sum_sq, public_sum_sq) in sum_sq_vars.iter().zip(&public_sums_sq) {
+            sum_sq.enforce_equal(public_sum_sq)?;
+        }
+        for (b, public_counts) in public_histogram.iter().enumerate() {
+            for (r, public_count) in public_counts.iter().enumerate() {
+                let at_least = if r == 0 { count_vars[b].clone() } else { at_least_vars[b][r].clone() };
+                let in_range = if r + 1 < NUM_GLUCOSE_RANGES { at_least - &at_least_vars[b][r + 1] } else { at_least };
+                in_range.enforce_equal(public_count)?;
+            }
+        }
 
         // Optional: ensure the sponge isn't used elsewhere by accident.
         // (Not strictly needed, but helps prevent footguns when modifying circuit.)
-        let _ = PoseidonSponge::<Fr>::new(&poseidon_cfg);
+        let _ = PoseidonSponge::<F>::new(&poseidon_cfg);
 
         Ok(())
     }
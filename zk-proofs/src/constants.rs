@@ -1,72 +1,78 @@
 //! Crate-wide constants used by the ZK circuit and host-side orchestration.
 
 use ark_bn254::Fr;
-use ark_crypto_primitives::sponge::poseidon::{find_poseidon_ark_and_mds, PoseidonConfig};
-use ark_ff::PrimeField;
+use ark_crypto_primitives::sponge::poseidon::PoseidonConfig;
+use zk_proofs_verifier::types::{AgeBuckets, CircuitRevision, Curve, FieldSet};
 
-/// Default number of records per shard.
-///
-/// We choose 1000 so the canonical "1,000,000 record" synthetic dataset partitions into exactly
-/// 1000 shards.
-pub const DEFAULT_SHARD_SIZE: usize = 1000;
-
-/// Number of age buckets used by the prototype.
-pub const NUM_BUCKETS: usize = 6;
+// Public circuit parameters live in the verify-only crate so verifiers agree on them.
+pub use zk_proofs_verifier::constants::{
+    poseidon_config_for, AGE_BITS, AGE_BUCKETS, DATASET_CHAIN_DOMAIN, DEFAULT_SHARD_SIZE, GLUCOSE_RANGES, MAX_AGE,
+    MAX_BUCKETS, MEASUREMENT_BITS, NUM_BUCKETS, NUM_GLUCOSE_RANGES, PLAUSIBLE_GLUCOSE_MG_DL, POSEIDON_ALPHA,
+    POSEIDON_CAPACITY, POSEIDON_FULL_ROUNDS, POSEIDON_PARTIAL_ROUNDS, POSEIDON_RATE,
+};
 
-/// Inclusive (min_age, max_age) bounds for each bucket.
+/// Identifier of the shard circuit instance for `shard_size` records of `field_set` bucketed by
+/// `buckets`, at circuit `revision`.
 ///
-/// Buckets cover [0, 120] and are designed for the demo query:
-/// "Average blood glucose by age range".
-pub const AGE_BUCKETS: [(u8, u8); NUM_BUCKETS] = [
-    (0, 17),
-    (18, 29),
-    (30, 39),
-    (40, 49),
-    (50, 64),
-    (65, 120),
-];
-
-// Poseidon sponge configuration.
-//
-// We use a width-3 sponge (rate=2, capacity=1) to efficiently absorb pairs of field elements.
-// The specific round counts chosen here are consistent with widely used Poseidon instantiations.
-//
-// NOTE: This is a prototype. For production, parameters should be reviewed by cryptographers
-// and ideally fixed via audited constants / standard sets.
-pub const POSEIDON_RATE: usize = 2;
-pub const POSEIDON_CAPACITY: usize = 1;
-
-// Typical Poseidon parameters for width=3.
-pub const POSEIDON_FULL_ROUNDS: usize = 8;
-pub const POSEIDON_PARTIAL_ROUNDS: usize = 57;
+/// Two deployments with the same circuit id produce interchangeable keys for the same setup.
+/// Glucose-only circuits keep the id they had before field sets existed, and circuits of the
+/// default bucket layout the one they had before layouts were configurable; others name their
+/// bucket bounds. From v3 on the id also names the histogram's glucose range bounds, which are
+/// part of the constraints, and dual-commitment circuits (`sha256_commitment`) are marked as such.
+pub fn circuit_id(
+    shard_size: usize,
+    field_set: FieldSet,
+    buckets: &AgeBuckets,
+    revision: CircuitRevision,
+    sha256_commitment: bool,
+) -> String {
+    let mut base = format!(
+        "{}/n={shard_size}/buckets={}/poseidon-w3-r{POSEIDON_FULL_ROUNDS}-p{POSEIDON_PARTIAL_ROUNDS}",
+        revision.version(),
+        buckets.num_buckets()
+    );
+    if !buckets.is_default() {
+        base.push_str(&format!("/age-buckets={}", buckets.id()));
+    }
+    if revision.proves_histogram() {
+        let mins: Vec<String> = GLUCOSE_RANGES.iter().map(|(min, _)| min.to_string()).collect();
+        base.push_str(&format!("/glucose-ranges={}", mins.join(",")));
+    }
+    if sha256_commitment {
+        base.push_str("/dual-sha256");
+    }
+    match field_set {
+        FieldSet::Glucose => base,
+        other => {
+            let fields: Vec<&str> = other.measurements().iter().map(|m| m.name()).collect();
+            format!("{base}/fields={}", fields.join("+"))
+        }
+    }
+}
 
-/// Poseidon S-box exponent (alpha). Common choices are 5 or 17.
-pub const POSEIDON_ALPHA: u64 = 5;
+/// Identifier of the shard circuit instance for `shard_size` records of `field_set` on `curve`.
+///
+/// Other curves only have keys for revision v4 (without the dual-commitment variant); their ids add
+/// the curve, so BN254 ids are unchanged.
+pub fn circuit_id_on(
+    curve: Curve,
+    shard_size: usize,
+    field_set: FieldSet,
+    buckets: &AgeBuckets,
+    revision: CircuitRevision,
+    sha256_commitment: bool,
+) -> String {
+    let id = circuit_id(shard_size, field_set, buckets, revision, sha256_commitment);
+    match curve {
+        Curve::Bn254 => id,
+        other => format!("{id}/curve={}", other.name()),
+    }
+}
 
 /// Deterministically derive Poseidon parameters for BN254::Fr.
 ///
 /// This uses arkworks' parameter derivation helper (Ark + MDS) so both the native hasher
 /// and the in-circuit gadget agree on the same constants.
 pub fn poseidon_config() -> PoseidonConfig<Fr> {
-    // The helper expects the prime field size in bits.
-    let prime_bits = Fr::MODULUS_BIT_SIZE as u64;
-
-    // Derive the round constants (ARK) and MDS matrix.
-    let (ark, mds) = find_poseidon_ark_and_mds::<Fr>(
-        prime_bits,
-        POSEIDON_RATE,
-        POSEIDON_FULL_ROUNDS,
-        POSEIDON_PARTIAL_ROUNDS,
-        0,
-    );
-
-    PoseidonConfig::new(
-        POSEIDON_FULL_ROUNDS,
-        POSEIDON_PARTIAL_ROUNDS,
-        POSEIDON_ALPHA,
-        mds,
-        ark,
-        POSEIDON_RATE,
-        POSEIDON_CAPACITY,
-    )
+    poseidon_config_for::<Fr>()
 }
@@ -3,51 +3,113 @@
 //! SECURITY NOTE (prototype): Groth16 requires a trusted setup that produces a proving key (PK)
 //! and verifying key (VK). This prototype generates keys locally. In production, an MPC ceremony
 //! (or a transparent system) should be used.
+//!
+//! Keys and proofs are on BN254. The `_on` functions set up and prove the salted revision v4 on
+//! any pairing curve, for curve migrations (BLS12-381).
 
+use crate::canonical_encoding;
 use crate::circuit::HealthShardCircuit;
-use crate::constants::{poseidon_config, DEFAULT_SHARD_SIZE, NUM_BUCKETS};
-use crate::types::{bucket_for_age, Record, ShardPublicInputs, ShardStats};
-use ark_bn254::{Bn254, Fr};
+use crate::constants::{poseidon_config_for, DEFAULT_SHARD_SIZE, NUM_GLUCOSE_RANGES};
+use crate::types::{
+    glucose_range_for, AgeBuckets, CircuitRevision, FieldSet, RangeWidths, Record, ShardPublicInputs, ShardRanges, ShardStats,
+};
+use ark_bn254::{Bn254, Fr, G1Affine, G2Affine};
 use ark_crypto_primitives::sponge::poseidon::PoseidonSponge;
-use ark_crypto_primitives::sponge::CryptographicSponge;
+use ark_crypto_primitives::sponge::{Absorb, CryptographicSponge};
+use ark_ec::pairing::Pairing;
+use ark_ec::AffineRepr;
+use ark_ff::PrimeField;
 use ark_groth16::{Groth16, Proof, ProvingKey, VerifyingKey};
 use ark_serialize::{CanonicalDeserialize, CanonicalSerialize};
+use ark_std::UniformRand;
 use rand::RngCore;
-use thiserror::Error;
-
-#[derive(Debug, Error)]
-pub enum ZkError {
-    #[error("invalid shard size: expected {expected}, got {got}")]
-    InvalidShardSize { expected: usize, got: usize },
+use sha2::{Digest, Sha256};
 
-    #[error("serialization error: {0}")]
-    Serialization(String),
+// Verification (and its error type) live in the verify-only crate; re-exported for callers.
+pub use zk_proofs_verifier::verify::{
+    deserialize_proof, deserialize_proof_on, deserialize_vk, deserialize_vk_on, invalid_shard_proofs,
+    shard_public_inputs_on, shard_public_inputs_to_field_elems, sha256_digest_to_field_elems,
+    shard_range_inputs_to_field_elems, verify_shard_proof, verify_shard_proof_on, verify_shard_proofs_batch,
+    verify_shard_range_proof, vk_revision, vk_sha256_commitment, ShardProofInstance, ZkError,
+};
 
-    #[error("proof verification failed")]
-    VerificationFailed,
+/// Poseidon commitment to a shard's master salt (a public output from circuit v4 on).
+pub fn salt_commitment(master_salt: Fr) -> Fr {
+    salt_commitment_on(master_salt)
+}
 
-    #[error("arkworks error: {0}")]
-    Ark(String),
+/// `salt_commitment` over the scalar field of any curve.
+pub fn salt_commitment_on<F: PrimeField + Absorb>(master_salt: F) -> F {
+    let mut sponge = PoseidonSponge::<F>::new(&poseidon_config_for::<F>());
+    sponge.absorb(&master_salt);
+    sponge.squeeze_field_elements(1)[0]
 }
 
-/// Compute (commitment, stats) for a shard.
+/// SHA-256 commitment of a dual-commitment shard, over its canonical byte encoding (see
+/// `canonical_encoding`): the master salt, then its records.
 ///
-/// This MUST match the circuit's logic.
-pub fn compute_shard_commitment_and_stats<const N: usize>(records: &[Record]) -> Result<(Fr, ShardStats), ZkError> {
+/// The salt comes first so the digest is as hard to brute-force as the Poseidon commitment.
+pub fn sha256_commitment(records: &[Record], field_set: FieldSet, master_salt: Fr) -> Result<[u8; 32], ZkError> {
+    let mut salt = Vec::with_capacity(32);
+    master_salt
+        .serialize_compressed(&mut salt)
+        .map_err(|e| ZkError::Serialization(format!("{e}")))?;
+
+    let mut hasher = Sha256::new().chain_update(&salt);
+    for r in records {
+        hasher.update(canonical_encoding::record_bytes(r, field_set));
+    }
+    Ok(hasher.finalize().into())
+}
+
+/// Compute (commitment, stats) for a shard, including every output of the latest circuit revision,
+/// per bucket of `buckets`. Without a `master_salt` the records are committed unsalted, as before
+/// v4. Records are absorbed in their canonical encoding (see `canonical_encoding`), as in the
+/// circuit.
+pub fn compute_shard_commitment_and_stats<const N: usize>(
+    records: &[Record],
+    field_set: FieldSet,
+    buckets: &AgeBuckets,
+    master_salt: Option<Fr>,
+) -> Result<(Fr, ShardStats), ZkError> {
+    let (commitment, mut stats) = compute_shard_commitment_and_stats_on::<N, Fr>(records, field_set, buckets, master_salt)?;
+    stats.salt_commitment = master_salt.map(salt_commitment);
+    Ok((commitment, stats))
+}
+
+/// `compute_shard_commitment_and_stats` over the scalar field of any curve. The stats' salt
+/// commitment is left unset (it is a BN254 element); see `salt_commitment_on`.
+pub fn compute_shard_commitment_and_stats_on<const N: usize, F: PrimeField + Absorb>(
+    records: &[Record],
+    field_set: FieldSet,
+    buckets: &AgeBuckets,
+    master_salt: Option<F>,
+) -> Result<(F, ShardStats), ZkError> {
     if records.len() != N {
         return Err(ZkError::InvalidShardSize { expected: N, got: records.len() });
     }
 
-    let cfg = poseidon_config();
-    let mut sponge = PoseidonSponge::<Fr>::new(&cfg);
+    let cfg = poseidon_config_for::<F>();
+    let mut sponge = PoseidonSponge::<F>::new(&cfg);
 
-    let mut stats = ShardStats::zero();
+    let measurements = field_set.measurements();
+    let mut stats = ShardStats::zero_for(field_set, buckets);
 
-    for r in records {
-        sponge.absorb(&[Fr::from(r.age as u64), Fr::from(r.blood_glucose_mg_dl as u64)]);
+    for (i, r) in records.iter().enumerate() {
+        sponge.absorb(&canonical_encoding::absorbed_elems(r, field_set, i, master_salt));
 
-        let b = bucket_for_age(r.age);
-        stats.sum_glucose_by_bucket[b] += r.blood_glucose_mg_dl as u64;
+        let b = buckets.bucket_for_age(r.age);
+        for (f, m) in measurements.iter().enumerate() {
+            if let Some(sums) = stats.sums_by_bucket_mut(f) {
+                sums[b] += r.value(*m) as u64;
+            }
+        }
+        if let Some(sums_sq) = stats.sum_glucose_sq_by_bucket.as_mut() {
+            sums_sq[b] += (r.blood_glucose_mg_dl as u64).pow(2);
+        }
+        if let Some(histogram) = stats.glucose_histogram_by_bucket.as_mut() {
+            histogram[b][glucose_range_for(r.blood_glucose_mg_dl)] += 1;
+        }
         stats.count_by_bucket[b] += 1;
     }
 
@@ -55,34 +117,40 @@ pub fn compute_shard_commitment_and_stats<const N: usize>(records: &[Record]) ->
     Ok((commitment, stats))
 }
 
-/// Convert (commitment, stats) to the public-input vector expected by Groth16.
+/// Generate a Groth16 keypair for the latest revision of the shard circuit; with
+/// `sha256_commitment`, for its dual-commitment variant, which also proves a SHA-256 commitment
+/// to the same records (at a cost of roughly 30k constraints per 64 bytes of encoding).
 ///
-/// ORDERING MUST MATCH the circuit's `new_input` allocation order.
-pub fn shard_public_inputs_to_field_elems(commitment: Fr, stats: &ShardStats) -> Vec<Fr> {
-    let mut v = Vec::with_capacity(1 + 2 * NUM_BUCKETS);
-    v.push(commitment);
-    for i in 0..NUM_BUCKETS {
-        v.push(Fr::from(stats.sum_glucose_by_bucket[i]));
-    }
-    for i in 0..NUM_BUCKETS {
-        v.push(Fr::from(stats.count_by_bucket[i]));
+/// For a fixed `N`, field set, bucket layout and variant, this must be run once.
+pub fn setup_keys<const N: usize>(
+    rng: &mut impl RngCore,
+    field_set: FieldSet,
+    buckets: &AgeBuckets,
+    sha256_commitment: bool,
+) -> Result<(ProvingKey<Bn254>, VerifyingKey<Bn254>), ZkError> {
+    // Use an empty witness; constraints only depend on N, the field set, buckets and variant.
+    let dummy_records = vec![Record::default(); N];
+    let master_salt = Fr::from(0u64);
+    let (commitment, mut stats) = compute_shard_commitment_and_stats::<N>(&dummy_records, field_set, buckets, Some(master_salt))?;
+    if sha256_commitment {
+        stats.sha256_commitment = Some(self::sha256_commitment(&dummy_records, field_set, master_salt)?);
     }
-    v
-}
-
-/// Generate a Groth16 keypair for the shard circuit.
-///
-/// For a fixed `N`, this must be run once.
-pub fn setup_keys<const N: usize>(rng: &mut impl RngCore) -> Result<(ProvingKey<Bn254>, VerifyingKey<Bn254>), ZkError> {
-    // Use an empty witness; constraints only depend on N.
-    let dummy_records = vec![Record { age: 0, blood_glucose_mg_dl: 0 }; N];
-    let (commitment, stats) = compute_shard_commitment_and_stats::<N>(&dummy_records)?;
 
     let circuit = HealthShardCircuit::<N> {
+        field_set,
+        buckets: buckets.clone(),
         records: dummy_records,
         public_shard_commitment: commitment,
         public_sum_glucose_by_bucket: stats.sum_glucose_by_bucket,
         public_count_by_bucket: stats.count_by_bucket,
+        public_extra_sums_by_bucket: stats.extra_sums_by_bucket,
+        public_sum_glucose_sq_by_bucket: stats.sum_glucose_sq_by_bucket,
+        public_glucose_histogram_by_bucket: stats.glucose_histogram_by_bucket,
+        master_salt,
+        public_salt_commitment: stats.salt_commitment,
+        public_glucose_bounds: stats.glucose_bounds,
+        public_sha256_commitment: stats.sha256_commitment,
+        public_ranges: None,
     };
 
     let pk = Groth16::<Bn254>::generate_random_parameters_with_reduction(circuit, rng)
@@ -92,49 +160,225 @@ pub fn setup_keys<const N: usize>(rng: &mut impl RngCore) -> Result<(ProvingKey<
     Ok((pk, vk))
 }
 
-/// Prove a shard's commitment and aggregate outputs.
+/// Prove a shard's commitment and aggregate outputs; returns (proof, commitment, stats, master
+/// salt).
+///
+/// Keys set up for an older circuit revision prove that revision; the returned stats then carry
+/// only the outputs it proves. Salted revisions use `master_salt`, or a fresh one drawn from `rng`,
+/// and return it: whoever holds it can recompute the commitment from the records. Unsalted ones
+/// ignore it and return `None`. Dual-commitment keys also prove the SHA-256 commitment, returned in
+/// the stats. The keys must have been set up for `buckets`.
 pub fn prove_shard<const N: usize>(
     rng: &mut impl RngCore,
     pk: &ProvingKey<Bn254>,
     records: Vec<Record>,
-) -> Result<(Proof<Bn254>, Fr, ShardStats), ZkError> {
+    field_set: FieldSet,
+    buckets: &AgeBuckets,
+    master_salt: Option<Fr>,
+) -> Result<(Proof<Bn254>, Fr, ShardStats, Option<Fr>), ZkError> {
     if records.len() != N {
         return Err(ZkError::InvalidShardSize { expected: N, got: records.len() });
     }
 
-    let (commitment, stats) = compute_shard_commitment_and_stats::<N>(&records)?;
+    let revision = vk_revision(&pk.vk, field_set, buckets.num_buckets());
+    let master_salt = revision.proves_salt().then(|| master_salt.unwrap_or_else(|| Fr::rand(rng)));
+    let (commitment, mut stats) = compute_shard_commitment_and_stats::<N>(&records, field_set, buckets, master_salt)?;
+    stats.restrict_to(revision);
+    if let Some((min, max)) = stats.glucose_bounds {
+        // The proof would not verify; say which record is at fault instead.
+        if let Some((index, r)) = records.iter().enumerate().find(|(_, r)| r.blood_glucose_mg_dl < min || r.blood_glucose_mg_dl > max) {
+            return Err(ZkError::GlucoseOutOfBounds { index, glucose: r.blood_glucose_mg_dl, min, max });
+        }
+    }
+    if let Some(master_salt) = master_salt.filter(|_| vk_sha256_commitment(&pk.vk, field_set, buckets.num_buckets())) {
+        stats.sha256_commitment = Some(sha256_commitment(&records, field_set, master_salt)?);
+    }
 
     let circuit = HealthShardCircuit::<N> {
+        field_set,
+        buckets: buckets.clone(),
         records,
         public_shard_commitment: commitment,
-        public_sum_glucose_by_bucket: stats.sum_glucose_by_bucket,
-        public_count_by_bucket: stats.count_by_bucket,
+        public_sum_glucose_by_bucket: stats.sum_glucose_by_bucket.clone(),
+        public_count_by_bucket: stats.count_by_bucket.clone(),
+        public_extra_sums_by_bucket: stats.extra_sums_by_bucket.clone(),
+        public_sum_glucose_sq_by_bucket: stats.sum_glucose_sq_by_bucket.clone(),
+        public_glucose_histogram_by_bucket: stats.glucose_histogram_by_bucket.clone(),
+        master_salt: master_salt.unwrap_or_default(),
+        public_salt_commitment: stats.salt_commitment,
+        public_glucose_bounds: stats.glucose_bounds,
+        public_sha256_commitment: stats.sha256_commitment,
+        public_ranges: None,
     };
 
     let proof = Groth16::<Bn254>::create_random_proof_with_reduction(circuit, pk, rng)
         .map_err(|e| ZkError::Ark(format!("{e}")))?;
 
-    Ok((proof, commitment, stats))
+    Ok((proof, commitment, stats, master_salt))
+}
+
+/// The salted shard circuit over `records` on the scalar field `F`, and its commitment and stats
+/// (whose salt commitment, on `F`, is left unset).
+///
+/// Other curves stay at v4, without glucose bounds, so their keys and the proofs of earlier
+/// migrations stay valid.
+fn latest_circuit_on<const N: usize, F: PrimeField + Absorb>(
+    records: Vec<Record>,
+    field_set: FieldSet,
+    buckets: &AgeBuckets,
+    master_salt: F,
+) -> Result<(HealthShardCircuit<N, F>, F, ShardStats), ZkError> {
+    let (commitment, mut stats) = compute_shard_commitment_and_stats_on::<N, F>(&records, field_set, buckets, Some(master_salt))?;
+    stats.restrict_to(CircuitRevision::V4);
+
+    let circuit = HealthShardCircuit::<N, F> {
+        field_set,
+        buckets: buckets.clone(),
+        records,
+        public_shard_commitment: commitment,
+        public_sum_glucose_by_bucket: stats.sum_glucose_by_bucket.clone(),
+        public_count_by_bucket: stats.count_by_bucket.clone(),
+        public_extra_sums_by_bucket: stats.extra_sums_by_bucket.clone(),
+        public_sum_glucose_sq_by_bucket: stats.sum_glucose_sq_by_bucket.clone(),
+        public_glucose_histogram_by_bucket: stats.glucose_histogram_by_bucket.clone(),
+        master_salt,
+        public_salt_commitment: Some(salt_commitment_on(master_salt)),
+        public_glucose_bounds: None,
+        public_sha256_commitment: None,
+        public_ranges: None,
+    };
+    Ok((circuit, commitment, stats))
+}
+
+/// Generate a Groth16 keypair for the shard circuit on curve `E` (v4, without the dual-commitment
+/// variant; see `latest_circuit_on`).
+pub fn setup_keys_on<const N: usize, E: Pairing>(
+    rng: &mut impl RngCore,
+    field_set: FieldSet,
+    buckets: &AgeBuckets,
+) -> Result<(ProvingKey<E>, VerifyingKey<E>), ZkError>
+where
+    E::ScalarField: Absorb,
+{
+    let dummy_records = vec![Record::default(); N];
+    let (circuit, _, _) = latest_circuit_on::<N, E::ScalarField>(dummy_records, field_set, buckets, E::ScalarField::from(0u64))?;
+
+    let pk = Groth16::<E>::generate_random_parameters_with_reduction(circuit, rng)
+        .map_err(|e| ZkError::Ark(format!("{e}")))?;
+
+    let vk = pk.vk.clone();
+    Ok((pk, vk))
+}
+
+/// Proof, commitment, stats and master salt of a shard proven on curve `E`.
+pub type ShardProofOn<E> = (Proof<E>, <E as Pairing>::ScalarField, ShardStats, <E as Pairing>::ScalarField);
+
+/// Prove a shard on curve `E` with keys from `setup_keys_on`; returns (proof, commitment, stats,
+/// master salt), all but the stats on `E`'s scalar field. The stats carry no salt commitment:
+/// verify with `salt_commitment_on(master_salt)` (see `verify_shard_proof_on`).
+///
+/// `master_salt` works as in `prove_shard`; these keys are always salted.
+pub fn prove_shard_on<const N: usize, E: Pairing>(
+    rng: &mut impl RngCore,
+    pk: &ProvingKey<E>,
+    records: Vec<Record>,
+    field_set: FieldSet,
+    buckets: &AgeBuckets,
+    master_salt: Option<E::ScalarField>,
+) -> Result<ShardProofOn<E>, ZkError>
+where
+    E::ScalarField: Absorb,
+{
+    if records.len() != N {
+        return Err(ZkError::InvalidShardSize { expected: N, got: records.len() });
+    }
+
+    let master_salt = master_salt.unwrap_or_else(|| E::ScalarField::rand(rng));
+    let (circuit, commitment, stats) = latest_circuit_on::<N, E::ScalarField>(records, field_set, buckets, master_salt)?;
+
+    let proof = Groth16::<E>::create_random_proof_with_reduction(circuit, pk, rng)
+        .map_err(|e| ZkError::Ark(format!("{e}")))?;
+
+    Ok((proof, commitment, stats, master_salt))
+}
+
+/// The range-released circuit over `records` with bounds of `widths`, and its commitment and
+/// bounds.
+fn range_circuit<const N: usize>(
+    records: Vec<Record>,
+    field_set: FieldSet,
+    buckets: &AgeBuckets,
+    widths: RangeWidths,
+    master_salt: Fr,
+) -> Result<(HealthShardCircuit<N>, Fr, ShardRanges), ZkError> {
+    let (commitment, stats) = compute_shard_commitment_and_stats::<N>(&records, field_set, buckets, Some(master_salt))?;
+    let ranges = ShardRanges::around(&stats, widths);
+
+    let circuit = HealthShardCircuit::<N> {
+        field_set,
+        buckets: buckets.clone(),
+        records,
+        public_shard_commitment: commitment,
+        public_sum_glucose_by_bucket: stats.sum_glucose_by_bucket,
+        public_count_by_bucket: stats.count_by_bucket,
+        public_extra_sums_by_bucket: stats.extra_sums_by_bucket,
+        public_sum_glucose_sq_by_bucket: None,
+        public_glucose_histogram_by_bucket: None,
+        master_salt,
+        public_salt_commitment: stats.salt_commitment,
+        public_glucose_bounds: None,
+        public_sha256_commitment: None,
+        public_ranges: Some(ranges.clone()),
+    };
+    Ok((circuit, commitment, ranges))
 }
 
-/// Verify a shard proof.
-pub fn verify_shard_proof(
-    vk: &VerifyingKey<Bn254>,
-    proof: &Proof<Bn254>,
-    commitment: Fr,
-    stats: &ShardStats,
-) -> Result<(), ZkError> {
-    let public_inputs = shard_public_inputs_to_field_elems(commitment, stats);
-    let ok = Groth16::<Bn254>::verify_proof(vk, proof, &public_inputs)
+/// Generate a Groth16 keypair for the range-released shard circuit.
+///
+/// The bounds are public inputs, so one keypair serves every choice of `RangeWidths`.
+pub fn setup_range_keys<const N: usize>(
+    rng: &mut impl RngCore,
+    field_set: FieldSet,
+    buckets: &AgeBuckets,
+) -> Result<(ProvingKey<Bn254>, VerifyingKey<Bn254>), ZkError> {
+    let widths = RangeWidths { sum: 1, count: 1 };
+    let (circuit, _, _) = range_circuit::<N>(vec![Record::default(); N], field_set, buckets, widths, Fr::from(0u64))?;
+
+    let pk = Groth16::<Bn254>::generate_random_parameters_with_reduction(circuit, rng)
         .map_err(|e| ZkError::Ark(format!("{e}")))?;
-    if !ok {
-        return Err(ZkError::VerificationFailed);
+
+    let vk = pk.vk.clone();
+    Ok((pk, vk))
+}
+
+/// Prove that a shard's per-bucket sums and counts lie within the bounds of `widths` around them,
+/// without revealing the exact values; returns (proof, commitment, bounds, master salt).
+///
+/// `master_salt` works as in `prove_shard`; this circuit is always salted.
+pub fn prove_shard_ranges<const N: usize>(
+    rng: &mut impl RngCore,
+    pk: &ProvingKey<Bn254>,
+    records: Vec<Record>,
+    field_set: FieldSet,
+    buckets: &AgeBuckets,
+    widths: RangeWidths,
+    master_salt: Option<Fr>,
+) -> Result<(Proof<Bn254>, Fr, ShardRanges, Fr), ZkError> {
+    if records.len() != N {
+        return Err(ZkError::InvalidShardSize { expected: N, got: records.len() });
     }
-    Ok(())
+
+    let master_salt = master_salt.unwrap_or_else(|| Fr::rand(rng));
+    let (circuit, commitment, ranges) = range_circuit::<N>(records, field_set, buckets, widths, master_salt)?;
+
+    let proof = Groth16::<Bn254>::create_random_proof_with_reduction(circuit, pk, rng)
+        .map_err(|e| ZkError::Ark(format!("{e}")))?;
+
+    Ok((proof, commitment, ranges, master_salt))
 }
 
 /// Serialize a proving key to bytes.
-pub fn serialize_pk(pk: &ProvingKey<Bn254>) -> Result<Vec<u8>, ZkError> {
+pub fn serialize_pk<E: Pairing>(pk: &ProvingKey<E>) -> Result<Vec<u8>, ZkError> {
     let mut out = Vec::new();
     pk.serialize_compressed(&mut out)
         .map_err(|e| ZkError::Serialization(format!("{e}")))?;
@@ -142,23 +386,23 @@ pub fn serialize_pk(pk: &ProvingKey<Bn254>) -> Result<Vec<u8>, ZkError> {
 }
 
 pub fn deserialize_pk(bytes: &[u8]) -> Result<ProvingKey<Bn254>, ZkError> {
-    ProvingKey::<Bn254>::deserialize_compressed(bytes)
+    deserialize_pk_on::<Bn254>(bytes)
+}
+
+/// `deserialize_pk` for keys on any curve.
+pub fn deserialize_pk_on<E: Pairing>(bytes: &[u8]) -> Result<ProvingKey<E>, ZkError> {
+    ProvingKey::<E>::deserialize_compressed(bytes)
         .map_err(|e| ZkError::Serialization(format!("{e}")))
 }
 
-pub fn serialize_vk(vk: &VerifyingKey<Bn254>) -> Result<Vec<u8>, ZkError> {
+pub fn serialize_vk<E: Pairing>(vk: &VerifyingKey<E>) -> Result<Vec<u8>, ZkError> {
     let mut out = Vec::new();
     vk.serialize_compressed(&mut out)
         .map_err(|e| ZkError::Serialization(format!("{e}")))?;
     Ok(out)
 }
 
-pub fn deserialize_vk(bytes: &[u8]) -> Result<VerifyingKey<Bn254>, ZkError> {
-    VerifyingKey::<Bn254>::deserialize_compressed(bytes)
-        .map_err(|e| ZkError::Serialization(format!("{e}")))
-}
-
-pub fn serialize_proof(proof: &Proof<Bn254>) -> Result<Vec<u8>, ZkError> {
+pub fn serialize_proof<E: Pairing>(proof: &Proof<E>) -> Result<Vec<u8>, ZkError> {
     let mut out = Vec::new();
     proof
         .serialize_compressed(&mut out)
@@ -166,9 +410,213 @@ pub fn serialize_proof(proof: &Proof<Bn254>) -> Result<Vec<u8>, ZkError> {
     Ok(out)
 }
 
-pub fn deserialize_proof(bytes: &[u8]) -> Result<Proof<Bn254>, ZkError> {
-    Proof::<Bn254>::deserialize_compressed(bytes)
-        .map_err(|e| ZkError::Serialization(format!("{e}")))
+/// An affine G1 point as snarkjs writes it: decimal projective coordinates `[x, y, z]`.
+fn snarkjs_g1(p: &G1Affine) -> serde_json::Value {
+    match p.xy() {
+        Some((x, y)) => serde_json::json!([decimal(x), decimal(y), "1"]),
+        None => serde_json::json!(["0", "1", "0"]),
+    }
+}
+
+/// An affine G2 point as snarkjs writes it: each coordinate as `[c0, c1]`.
+fn snarkjs_g2(p: &G2Affine) -> serde_json::Value {
+    match p.xy() {
+        Some((x, y)) => serde_json::json!([[decimal(x.c0), decimal(x.c1)], [decimal(y.c0), decimal(y.c1)], ["1", "0"]]),
+        None => serde_json::json!([["0", "0"], ["1", "0"], ["0", "0"]]),
+    }
+}
+
+fn decimal<F: PrimeField>(x: F) -> String {
+    x.into_bigint().to_string()
+}
+
+/// A BN254 verifying key as snarkjs' `verification_key.json`, for `snarkjs groth16 verify`.
+/// `vk_alphabeta_12` is left out: snarkjs doesn't need it to verify.
+pub fn to_snarkjs_vk_json(vk: &VerifyingKey<Bn254>) -> serde_json::Value {
+    serde_json::json!({
+        "protocol": "groth16",
+        "curve": "bn128",
+        "nPublic": vk.gamma_abc_g1.len().saturating_sub(1),
+        "vk_alpha_1": snarkjs_g1(&vk.alpha_g1),
+        "vk_beta_2": snarkjs_g2(&vk.beta_g2),
+        "vk_gamma_2": snarkjs_g2(&vk.gamma_g2),
+        "vk_delta_2": snarkjs_g2(&vk.delta_g2),
+        "IC": vk.gamma_abc_g1.iter().map(snarkjs_g1).collect::<Vec<_>>(),
+    })
+}
+
+/// A BN254 proof as snarkjs' `proof.json`.
+pub fn to_snarkjs_proof_json(proof: &Proof<Bn254>) -> serde_json::Value {
+    serde_json::json!({
+        "pi_a": snarkjs_g1(&proof.a),
+        "pi_b": snarkjs_g2(&proof.b),
+        "pi_c": snarkjs_g1(&proof.c),
+        "protocol": "groth16",
+        "curve": "bn128",
+    })
+}
+
+/// Public inputs as snarkjs' `public.json`: decimal strings, in circuit order.
+pub fn to_snarkjs_public_signals(inputs: &[Fr]) -> Vec<String> {
+    inputs.iter().copied().map(decimal).collect()
+}
+
+/// Names of the shard circuit's public inputs, in the order `shard_public_inputs_on` lays them out.
+pub fn shard_public_input_names(revision: CircuitRevision, field_set: FieldSet, num_buckets: usize, sha256_commitment: bool) -> Vec<String> {
+    let per_bucket = |name: &str| (0..num_buckets).map(|b| format!("{name}[{b}]")).collect::<Vec<_>>();
+    let mut names = vec!["shard_commitment".to_string()];
+    names.extend(per_bucket("sum_glucose_by_bucket"));
+    names.extend(per_bucket("count_by_bucket"));
+    for measurement in &field_set.measurements()[1..] {
+        names.extend(per_bucket(&format!("sum_{}_by_bucket", measurement.name())));
+    }
+    if revision.proves_sum_sq() {
+        names.extend(per_bucket("sum_glucose_sq_by_bucket"));
+    }
+    if revision.proves_histogram() {
+        for b in 0..num_buckets {
+            names.extend((0..NUM_GLUCOSE_RANGES).map(|r| format!("glucose_histogram_by_bucket[{b}][{r}]")));
+        }
+    }
+    if revision.proves_salt() {
+        names.push("salt_commitment".to_string());
+    }
+    if revision.proves_glucose_bounds() {
+        names.push("glucose_bounds (min + 2^16 * max)".to_string());
+    }
+    if sha256_commitment {
+        names.push("sha256_commitment[0..16] (big-endian)".to_string());
+        names.push("sha256_commitment[16..32] (big-endian)".to_string());
+    }
+    names
+}
+
+/// A Solidity contract verifying BN254 Groth16 proofs of `vk` with the EIP-196/197 precompiles.
+///
+/// `verifyProof(a, b, c, input)` takes the proof points as `snarkjs zkey export soliditycalldata`
+/// gives them (`b`'s coordinates as `[c1, c0]`, the precompiles' order) and the public inputs in
+/// circuit order; `input_names` label them in the contract's comments. `circuit_id` names the
+/// circuit in the header.
+pub fn solidity_verifier(vk: &VerifyingKey<Bn254>, circuit_id: &str, input_names: &[String]) -> String {
+    use std::fmt::Write;
+
+    let g1 = |name: &str, p: &G1Affine| {
+        let (x, y) = p.xy().map(|(x, y)| (decimal(x), decimal(y))).unwrap_or(("0".to_string(), "0".to_string()));
+        format!("    uint256 constant {name}_X = {x};\n    uint256 constant {name}_Y = {y};\n")
+    };
+    let g2 = |name: &str, p: &G2Affine| {
+        let (x, y) = p.xy().unwrap_or_default();
+        format!(
+            "    uint256 constant {name}_X1 = {};\n    uint256 constant {name}_X0 = {};\n    uint256 constant {name}_Y1 = {};\n    uint256 constant {name}_Y0 = {};\n",
+            decimal(x.c1),
+            decimal(x.c0),
+            decimal(y.c1),
+            decimal(y.c0)
+        )
+    };
+    let n_public = vk.gamma_abc_g1.len().saturating_sub(1);
+
+    let mut out = String::new();
+    let _ = writeln!(out, "// SPDX-License-Identifier: MIT");
+    let _ = writeln!(out, "pragma solidity ^0.8.19;");
+    let _ = writeln!(out);
+    let _ = writeln!(out, "/// Groth16 verifier for shard proofs of circuit `{circuit_id}` (BN254).");
+    let _ = writeln!(out, "///");
+    let _ = writeln!(out, "/// Public inputs, in order:");
+    for i in 0..n_public {
+        let name = input_names.get(i).map_or("", String::as_str);
+        let _ = writeln!(out, "///   input[{i}]: {name}");
+    }
+    let _ = writeln!(out, "contract ShardVerifier {{");
+    let _ = writeln!(out, "    // Scalar field and base field moduli.");
+    let _ = writeln!(out, "    uint256 constant R = 21888242871839275222246405745257275088548364400416034343698204186575808495617;");
+    let _ = writeln!(out, "    uint256 constant Q = 21888242871839275222246405745257275088696311157297823662689037894645226208583;");
+    let _ = writeln!(out);
+    let _ = writeln!(out, "    uint256 constant N_PUBLIC = {n_public};");
+    let _ = writeln!(out);
+    out.push_str(&g1("ALPHA", &vk.alpha_g1));
+    out.push_str(&g2("BETA", &vk.beta_g2));
+    out.push_str(&g2("GAMMA", &vk.gamma_g2));
+    out.push_str(&g2("DELTA", &vk.delta_g2));
+    for (i, p) in vk.gamma_abc_g1.iter().enumerate() {
+        out.push_str(&g1(&format!("IC{i}"), p));
+    }
+    out.push_str(
+        r#"
+    /// acc += s * (x, y)
+    function _mulAdd(uint256[2] memory acc, uint256 x, uint256 y, uint256 s) private view {
+        require(s < R, "input not in the scalar field");
+        uint256[3] memory mulIn;
+        mulIn[0] = x;
+        mulIn[1] = y;
+        mulIn[2] = s;
+        uint256[4] memory addIn;
+        bool ok;
+        assembly {
+            ok := staticcall(gas(), 0x07, mulIn, 0x60, add(addIn, 0x40), 0x40)
+        }
+        require(ok, "ec mul failed");
+        addIn[0] = acc[0];
+        addIn[1] = acc[1];
+        assembly {
+            ok := staticcall(gas(), 0x06, addIn, 0x80, acc, 0x40)
+        }
+        require(ok, "ec add failed");
+    }
+
+    function verifyProof(
+        uint256[2] calldata a,
+        uint256[2][2] calldata b,
+        uint256[2] calldata c,
+        uint256[N_PUBLIC] calldata input
+    ) external view returns (bool) {
+        uint256[2] memory vkX;
+        vkX[0] = IC0_X;
+        vkX[1] = IC0_Y;
+"#,
+    );
+    for i in 0..n_public {
+        let _ = writeln!(out, "        _mulAdd(vkX, IC{}_X, IC{}_Y, input[{i}]);", i + 1, i + 1);
+    }
+    out.push_str(
+        r#"
+        // e(-a, b) * e(alpha, beta) * e(vkX, gamma) * e(c, delta) == 1
+        uint256[24] memory p;
+        p[0] = a[0];
+        p[1] = (Q - (a[1] % Q)) % Q;
+        p[2] = b[0][0];
+        p[3] = b[0][1];
+        p[4] = b[1][0];
+        p[5] = b[1][1];
+        p[6] = ALPHA_X;
+        p[7] = ALPHA_Y;
+        p[8] = BETA_X1;
+        p[9] = BETA_X0;
+        p[10] = BETA_Y1;
+        p[11] = BETA_Y0;
+        p[12] = vkX[0];
+        p[13] = vkX[1];
+        p[14] = GAMMA_X1;
+        p[15] = GAMMA_X0;
+        p[16] = GAMMA_Y1;
+        p[17] = GAMMA_Y0;
+        p[18] = c[0];
+        p[19] = c[1];
+        p[20] = DELTA_X1;
+        p[21] = DELTA_X0;
+        p[22] = DELTA_Y1;
+        p[23] = DELTA_Y0;
+        uint256[1] memory out;
+        bool ok;
+        assembly {
+            ok := staticcall(gas(), 0x08, p, 0x300, out, 0x20)
+        }
+        return ok && out[0] == 1;
+    }
+}
+"#,
+    );
+    out
 }
 
 /// Helper used by the backend for its default shard size.
@@ -178,7 +626,13 @@ pub type DefaultCircuit = HealthShardCircuit<DEFAULT_SHARD_SIZE>;
 pub fn shard_public_inputs_json(commitment: Fr, stats: &ShardStats) -> ShardPublicInputs {
     ShardPublicInputs {
         shard_commitment: crate::types::FrHex::from_fr(&commitment),
-        sum_glucose_by_bucket: stats.sum_glucose_by_bucket,
-        count_by_bucket: stats.count_by_bucket,
+        sum_glucose_by_bucket: stats.sum_glucose_by_bucket.clone(),
+        count_by_bucket: stats.count_by_bucket.clone(),
+        extra_sums_by_bucket: stats.extra_sums_by_bucket.clone(),
+        sum_glucose_sq_by_bucket: stats.sum_glucose_sq_by_bucket.clone(),
+        glucose_histogram_by_bucket: stats.glucose_histogram_by_bucket.clone(),
+        salt_commitment: stats.salt_commitment.as_ref().map(crate::types::FrHex::from_fr),
+        sha256_commitment: stats.sha256_commitment,
+        glucose_bounds: stats.glucose_bounds,
     }
 }
@@ -1,11 +1,28 @@
 //! ZK layer for the Privacy-Preserving Health-Data Ledger.
 //!
 //! This crate contains:
+//! - The canonical encoding of a record as field elements and bytes, shared by the circuit and the
+//!   host-side commitments.
 //! - A SNARK circuit that proves shard-level aggregate statistics were computed from committed data.
-//! - Prover + verifier orchestration.
+//! - Prover + verifier orchestration, and a registry of supported shard sizes.
+//! - Phase-2 trusted setup ceremony tooling for shard keys.
 //! - Serialization helpers for transporting proofs and public inputs.
+//! - A dataset aggregate circuit binding the dataset commitment to all shards' public inputs and
+//!   their summed totals.
+//! - A query circuit binding one released sum and count to the dataset commitment.
 
+pub mod aggregate;
+pub mod canonical_encoding;
+pub mod ceremony;
 pub mod constants;
 pub mod circuit;
 pub mod groth16;
+pub mod query;
+pub mod registry;
 pub mod types;
+
+// Wire decoding lives in the verify-only crate, shared with `ledger-verify` and the WASM build.
+pub use zk_proofs_verifier::verification;
+
+/// Crate version, recorded in dataset manifests.
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
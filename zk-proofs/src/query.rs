@@ -0,0 +1,182 @@
+//! Query proofs: one Groth16 proof binding a released (sum, count) to the dataset commitment.
+//!
+//! Built like the dataset aggregate circuit (`aggregate`), over the shards' public inputs as
+//! private witnesses, it proves:
+//! 1) The public dataset commitment is the Poseidon chain over the shard commitments, and a public
+//!    `shard_inputs_root` the Merkle root over the shards' hashed inputs (shared with `aggregate`).
+//! 2) The public `sum` and `count` are the totals over all shards of the inputs at the public
+//!    positions `sum_index` and `count_index` (`query_input_indices`), selected in-circuit with
+//!    one-hot witnesses, so one key pair serves every bucket and measurement.
+//!
+//! As with the aggregate, the shard proofs are verified outside the circuit, against the inputs
+//! the root commits to. Keys are set up per `AggregateShape`.
+
+use crate::aggregate::{alloc_shard_inputs, enforce_chain_and_root, shard_inputs_and_statement, AggregateShape};
+use crate::groth16::ZkError;
+use ark_bn254::{Bn254, Fr};
+use ark_ff::{PrimeField, Zero};
+use ark_groth16::{Groth16, Proof, ProvingKey, VerifyingKey};
+use ark_r1cs_std::alloc::AllocVar;
+use ark_r1cs_std::boolean::Boolean;
+use ark_r1cs_std::eq::EqGadget;
+use ark_r1cs_std::fields::fp::FpVar;
+use ark_relations::r1cs::{ConstraintSynthesizer, ConstraintSystemRef, SynthesisError};
+use rand::RngCore;
+use zk_proofs_verifier::types::{QueryStatement, ShardStats};
+
+pub use zk_proofs_verifier::verify::{query_input_indices, verify_query_proof};
+
+/// Circuit proving a query's sum and count over private shard inputs.
+#[derive(Clone, Debug)]
+pub struct QueryCircuit {
+    pub shape: AggregateShape,
+
+    /// Private: every shard's public-input vector, in shard order.
+    pub shard_inputs: Vec<Vec<Fr>>,
+
+    /// Public outputs, in `query_public_inputs_to_field_elems` order.
+    pub public_dataset_commitment: Fr,
+    pub public_shard_inputs_root: Fr,
+    pub public_sum_index: usize,
+    pub public_count_index: usize,
+    pub public_sum: Fr,
+    pub public_count: Fr,
+}
+
+impl ConstraintSynthesizer<Fr> for QueryCircuit {
+    fn generate_constraints(self, cs: ConstraintSystemRef<Fr>) -> Result<(), SynthesisError> {
+        let shape = self.shape;
+        if self.shard_inputs.len() != shape.num_shards || self.shard_inputs.iter().any(|x| x.len() != shape.input_len) {
+            return Err(SynthesisError::Unsatisfiable);
+        }
+
+        // Public inputs (ORDER MATTERS).
+        let dataset_commitment = FpVar::<Fr>::new_input(cs.clone(), || Ok(self.public_dataset_commitment))?;
+        let root = FpVar::<Fr>::new_input(cs.clone(), || Ok(self.public_shard_inputs_root))?;
+        let sum_index = FpVar::<Fr>::new_input(cs.clone(), || Ok(Fr::from(self.public_sum_index as u64)))?;
+        let count_index = FpVar::<Fr>::new_input(cs.clone(), || Ok(Fr::from(self.public_count_index as u64)))?;
+        let sum = FpVar::<Fr>::new_input(cs.clone(), || Ok(self.public_sum))?;
+        let count = FpVar::<Fr>::new_input(cs.clone(), || Ok(self.public_count))?;
+
+        let shards = alloc_shard_inputs(cs.clone(), &self.shard_inputs)?;
+
+        // 1) Commitment chain and shard inputs root.
+        enforce_chain_and_root(cs.clone(), &shards, &dataset_commitment, &root)?;
+
+        // 2) Totals of every summed position (linear, no constraints), then the selected ones.
+        let totals: Vec<FpVar<Fr>> = (1..=shape.num_summed)
+            .map(|i| shards.iter().map(|x| x[i].clone()).sum())
+            .collect();
+        enforce_selected(cs.clone(), &totals, self.public_sum_index, &sum_index, &sum)?;
+        enforce_selected(cs, &totals, self.public_count_index, &count_index, &count)?;
+
+        Ok(())
+    }
+}
+
+/// Enforce `value == totals[index - 1]` (totals start at input position 1) with a one-hot
+/// selector: boolean witnesses summing to one, whose weighted positions sum to `index`.
+fn enforce_selected(
+    cs: ConstraintSystemRef<Fr>,
+    totals: &[FpVar<Fr>],
+    index_value: usize,
+    index: &FpVar<Fr>,
+    value: &FpVar<Fr>,
+) -> Result<(), SynthesisError> {
+    let bits = (1..=totals.len())
+        .map(|i| Boolean::new_witness(cs.clone(), || Ok(i == index_value)))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut ones = FpVar::<Fr>::Constant(Fr::zero());
+    let mut position = FpVar::<Fr>::Constant(Fr::zero());
+    let mut selected = FpVar::<Fr>::Constant(Fr::zero());
+    for (i, (bit, total)) in bits.iter().zip(totals).enumerate() {
+        let bit = FpVar::from(bit.clone());
+        ones += &bit;
+        position += &bit * Fr::from(1 + i as u64);
+        selected += &bit * total;
+    }
+    ones.enforce_equal(&FpVar::Constant(Fr::from(1u64)))?;
+    position.enforce_equal(index)?;
+    selected.enforce_equal(value)
+}
+
+/// Generate a Groth16 keypair for the query circuit of `shape`.
+pub fn setup_query_keys(
+    rng: &mut impl RngCore,
+    shape: AggregateShape,
+) -> Result<(ProvingKey<Bn254>, VerifyingKey<Bn254>), ZkError> {
+    // Constraints only depend on the shape, so zero inputs do (the statement needn't hold).
+    let circuit = QueryCircuit {
+        shape,
+        shard_inputs: vec![vec![Fr::zero(); shape.input_len]; shape.num_shards],
+        public_dataset_commitment: Fr::zero(),
+        public_shard_inputs_root: Fr::zero(),
+        public_sum_index: 1,
+        public_count_index: 1,
+        public_sum: Fr::zero(),
+        public_count: Fr::zero(),
+    };
+
+    let pk = Groth16::<Bn254>::generate_random_parameters_with_reduction(circuit, rng)
+        .map_err(|e| ZkError::Ark(format!("{e}")))?;
+
+    let vk = pk.vk.clone();
+    Ok((pk, vk))
+}
+
+/// Prove the totals at `sum_index` and `count_index` (see `query_input_indices`) over `shards`
+/// (commitment and stats of each, in shard order) with keys for their `AggregateShape`; returns
+/// the proof and its public statement.
+pub fn prove_query(
+    rng: &mut impl RngCore,
+    pk: &ProvingKey<Bn254>,
+    shards: &[(Fr, ShardStats)],
+    sum_index: usize,
+    count_index: usize,
+) -> Result<(Proof<Bn254>, QueryStatement), ZkError> {
+    let Some((_, first)) = shards.first() else {
+        return Err(ZkError::InvalidAggregate("no shards".to_string()));
+    };
+    let shape = AggregateShape::of(shards.len(), first);
+    if shards.iter().any(|(_, stats)| AggregateShape::of(shards.len(), stats) != shape) {
+        return Err(ZkError::InvalidAggregate("shards prove different outputs".to_string()));
+    }
+    if [sum_index, count_index].iter().any(|i| !(1..=shape.num_summed).contains(i)) {
+        return Err(ZkError::InvalidAggregate("query position outside the shards' aggregates".to_string()));
+    }
+
+    let (shard_inputs, dataset_commitment, shard_inputs_root) = shard_inputs_and_statement(shards);
+    // Totals of `u64` aggregates; released values must fit `u64` too.
+    let total = |i: usize| -> Result<u64, ZkError> {
+        let limbs = shard_inputs.iter().map(|x| x[i]).sum::<Fr>().into_bigint().0;
+        if limbs[1..].iter().any(|l| *l != 0) {
+            return Err(ZkError::InvalidAggregate("total overflows u64".to_string()));
+        }
+        Ok(limbs[0])
+    };
+    let statement = QueryStatement {
+        dataset_commitment,
+        shard_inputs_root,
+        sum_index,
+        count_index,
+        sum: total(sum_index)?,
+        count: total(count_index)?,
+    };
+
+    let circuit = QueryCircuit {
+        shape,
+        shard_inputs,
+        public_dataset_commitment: statement.dataset_commitment,
+        public_shard_inputs_root: statement.shard_inputs_root,
+        public_sum_index: sum_index,
+        public_count_index: count_index,
+        public_sum: Fr::from(statement.sum),
+        public_count: Fr::from(statement.count),
+    };
+
+    let proof = Groth16::<Bn254>::create_random_proof_with_reduction(circuit, pk, rng)
+        .map_err(|e| ZkError::Ark(format!("{e}")))?;
+
+    Ok((proof, statement))
+}
@@ -0,0 +1,131 @@
+//! Circuit registry: the shard sizes this build ships circuits for.
+//!
+//! The shard circuit is generic over a const `N`, so every supported size is monomorphized here
+//! and selected at runtime. Each size needs its own Groth16 setup.
+
+use crate::groth16::{prove_shard, prove_shard_on, prove_shard_ranges, setup_keys, setup_keys_on, setup_range_keys, ShardProofOn, ZkError};
+use crate::types::{AgeBuckets, FieldSet, RangeWidths, Record, ShardRanges, ShardStats};
+use ark_bn254::{Bn254, Fr};
+use ark_crypto_primitives::sponge::Absorb;
+use ark_ec::pairing::Pairing;
+use ark_groth16::{Proof, ProvingKey, VerifyingKey};
+use rand::RngCore;
+
+/// Shard sizes with a compiled circuit. Keep in sync with `dispatch!` below.
+pub const SUPPORTED_SHARD_SIZES: [usize; 3] = [100, 1000, 5000];
+
+pub fn is_supported(shard_size: usize) -> bool {
+    SUPPORTED_SHARD_SIZES.contains(&shard_size)
+}
+
+macro_rules! dispatch {
+    ($shard_size:expr, $f:ident ( $($arg:expr),* )) => {
+        match $shard_size {
+            100 => $f::<100>($($arg),*),
+            1000 => $f::<1000>($($arg),*),
+            5000 => $f::<5000>($($arg),*),
+            other => Err(ZkError::UnsupportedShardSize(other)),
+        }
+    };
+    ($shard_size:expr, $f:ident::<$e:ty> ( $($arg:expr),* )) => {
+        match $shard_size {
+            100 => $f::<100, $e>($($arg),*),
+            1000 => $f::<1000, $e>($($arg),*),
+            5000 => $f::<5000, $e>($($arg),*),
+            other => Err(ZkError::UnsupportedShardSize(other)),
+        }
+    };
+}
+
+/// `setup_keys` for a runtime shard size.
+pub fn setup_keys_for(
+    shard_size: usize,
+    field_set: FieldSet,
+    buckets: &AgeBuckets,
+    sha256_commitment: bool,
+    rng: &mut impl RngCore,
+) -> Result<(ProvingKey<Bn254>, VerifyingKey<Bn254>), ZkError> {
+    dispatch!(shard_size, setup_keys(rng, field_set, buckets, sha256_commitment))
+}
+
+/// `prove_shard` for a runtime shard size.
+pub fn prove_shard_for(
+    shard_size: usize,
+    field_set: FieldSet,
+    buckets: &AgeBuckets,
+    rng: &mut impl RngCore,
+    pk: &ProvingKey<Bn254>,
+    records: Vec<Record>,
+    master_salt: Option<Fr>,
+) -> Result<(Proof<Bn254>, Fr, ShardStats, Option<Fr>), ZkError> {
+    dispatch!(shard_size, prove_shard(rng, pk, records, field_set, buckets, master_salt))
+}
+
+/// `setup_range_keys` for a runtime shard size.
+pub fn setup_range_keys_for(
+    shard_size: usize,
+    field_set: FieldSet,
+    buckets: &AgeBuckets,
+    rng: &mut impl RngCore,
+) -> Result<(ProvingKey<Bn254>, VerifyingKey<Bn254>), ZkError> {
+    dispatch!(shard_size, setup_range_keys(rng, field_set, buckets))
+}
+
+/// `prove_shard_ranges` for a runtime shard size, that of `records`.
+pub fn prove_shard_ranges_for(
+    field_set: FieldSet,
+    buckets: &AgeBuckets,
+    rng: &mut impl RngCore,
+    pk: &ProvingKey<Bn254>,
+    records: Vec<Record>,
+    widths: RangeWidths,
+    master_salt: Option<Fr>,
+) -> Result<(Proof<Bn254>, Fr, ShardRanges, Fr), ZkError> {
+    dispatch!(records.len(), prove_shard_ranges(rng, pk, records, field_set, buckets, widths, master_salt))
+}
+
+/// `setup_keys_on` for a runtime shard size.
+pub fn setup_keys_on_for<E: Pairing>(
+    shard_size: usize,
+    field_set: FieldSet,
+    buckets: &AgeBuckets,
+    rng: &mut impl RngCore,
+) -> Result<(ProvingKey<E>, VerifyingKey<E>), ZkError>
+where
+    E::ScalarField: Absorb,
+{
+    dispatch!(shard_size, setup_keys_on::<E>(rng, field_set, buckets))
+}
+
+/// `prove_shard_on` for a runtime shard size.
+pub fn prove_shard_on_for<E: Pairing>(
+    shard_size: usize,
+    field_set: FieldSet,
+    buckets: &AgeBuckets,
+    rng: &mut impl RngCore,
+    pk: &ProvingKey<E>,
+    records: Vec<Record>,
+    master_salt: Option<E::ScalarField>,
+) -> Result<ShardProofOn<E>, ZkError>
+where
+    E::ScalarField: Absorb,
+{
+    dispatch!(shard_size, prove_shard_on::<E>(rng, pk, records, field_set, buckets, master_salt))
+}
+
+/// Size metrics of a compiled circuit, read off its proving key.
+#[derive(Debug, Clone, Copy)]
+pub struct CircuitMetrics {
+    /// Evaluation domain size (constraints + public inputs, rounded up to a power of two).
+    pub domain_size: usize,
+    /// Instance + witness variables.
+    pub num_variables: usize,
+}
+
+pub fn circuit_metrics<E: Pairing>(pk: &ProvingKey<E>) -> CircuitMetrics {
+    CircuitMetrics {
+        // The libsnark reduction has one H query per domain point but the last.
+        domain_size: pk.h_query.len() + 1,
+        num_variables: pk.a_query.len(),
+    }
+}